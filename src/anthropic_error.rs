@@ -0,0 +1,258 @@
+//! Errors of the Anthropic API, explained.
+//!
+//! A failed Messages API call answers with an HTTP status and a body like
+//!
+//! ```json
+//! {"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}
+//! ```
+//!
+//! [`ApiError`] reads both and says what to do about it: set a valid key,
+//! retry later, pick one of the available models, or rephrase the request.
+//! Bodies that aren't Anthropic errors (e.g. from a proxy) fall back to the
+//! HTTP status.
+
+use crate::http_client::HttpResponse;
+use serde_json::Value;
+use std::fmt;
+
+/// Characters of a body that isn't an Anthropic error included in the message.
+const MAX_RAW_BODY: usize = 200;
+
+/// What went wrong, from the error's `type` or the HTTP status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The API key is missing or invalid (401).
+    Authentication,
+    /// The API key can't use the resource (403).
+    Permission,
+    /// The model or endpoint doesn't exist (404).
+    NotFound,
+    /// Too many requests or tokens (429).
+    RateLimit,
+    /// The API is temporarily overloaded (529).
+    Overloaded,
+    /// The request is too large (413).
+    RequestTooLarge,
+    /// The request or its answer was blocked by the content policy.
+    ContentPolicy,
+    /// The account has no credit left.
+    Billing,
+    /// Anything else wrong with the request (400).
+    InvalidRequest,
+    /// An internal error of the API (500).
+    Server,
+    /// Some other error type or status.
+    Other(String),
+}
+
+impl ErrorKind {
+    fn from_type(kind: &str, message: &str) -> Self {
+        let lower = message.to_lowercase();
+        match kind {
+            "authentication_error" => ErrorKind::Authentication,
+            "permission_error" => ErrorKind::Permission,
+            "not_found_error" => ErrorKind::NotFound,
+            "rate_limit_error" => ErrorKind::RateLimit,
+            "overloaded_error" => ErrorKind::Overloaded,
+            "request_too_large" => ErrorKind::RequestTooLarge,
+            "api_error" => ErrorKind::Server,
+            "billing_error" => ErrorKind::Billing,
+            "invalid_request_error" if lower.contains("content filtering") || lower.contains("usage policy") => {
+                ErrorKind::ContentPolicy
+            }
+            "invalid_request_error" if lower.contains("credit balance") => ErrorKind::Billing,
+            "invalid_request_error" => ErrorKind::InvalidRequest,
+            other => ErrorKind::Other(other.to_string()),
+        }
+    }
+
+    fn from_status(status: u16) -> Self {
+        match status {
+            400 => ErrorKind::InvalidRequest,
+            401 => ErrorKind::Authentication,
+            403 => ErrorKind::Permission,
+            404 => ErrorKind::NotFound,
+            413 => ErrorKind::RequestTooLarge,
+            429 => ErrorKind::RateLimit,
+            529 => ErrorKind::Overloaded,
+            500..=599 => ErrorKind::Server,
+            status => ErrorKind::Other(format!("HTTP {}", status)),
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ErrorKind::RateLimit | ErrorKind::Overloaded | ErrorKind::Server)
+    }
+}
+
+/// A failed Anthropic API call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiError {
+    /// The HTTP status, if the API answered.
+    pub status: Option<u16>,
+    /// What went wrong.
+    pub kind: ErrorKind,
+    /// The API's own message.
+    pub message: String,
+    /// The model that was asked for.
+    pub model: String,
+    /// Models the API key can use, listed when the model wasn't found.
+    pub available_models: Vec<String>,
+}
+
+impl ApiError {
+    /// The error a non-success `response` to a request for `model` stands for.
+    pub fn from_response(response: &HttpResponse, model: &str) -> Self {
+        let parsed: Option<Value> = serde_json::from_str(&response.body).ok();
+        let error = parsed.as_ref().and_then(|body| body.get("error"));
+        let kind = error.and_then(|e| e.get("type")).and_then(Value::as_str);
+        let message = error.and_then(|e| e.get("message")).and_then(Value::as_str);
+
+        let (kind, message) = match (kind, message) {
+            (Some(kind), message) => {
+                let message = message.unwrap_or_default().to_string();
+                (ErrorKind::from_type(kind, &message), message)
+            }
+            (None, _) => (ErrorKind::from_status(response.status), excerpt(&response.body)),
+        };
+        Self {
+            status: Some(response.status),
+            kind,
+            message,
+            model: model.to_string(),
+            available_models: Vec::new(),
+        }
+    }
+
+    /// The error for a successful answer the model refused to give, if
+    /// `response` is one.
+    pub fn refusal(response: &Value) -> Option<Self> {
+        (response.get("stop_reason").and_then(Value::as_str) == Some("refusal")).then(|| Self {
+            status: None,
+            kind: ErrorKind::ContentPolicy,
+            message: "The model declined to answer".to_string(),
+            model: response.get("model").and_then(Value::as_str).unwrap_or_default().to_string(),
+            available_models: Vec::new(),
+        })
+    }
+
+    /// Whether the missing resource is the model, so listing the available
+    /// ones helps.
+    pub fn is_unknown_model(&self) -> bool {
+        self.kind == ErrorKind::NotFound && (self.message.is_empty() || self.message.contains("model"))
+    }
+
+    /// What the user can do about the error.
+    pub fn guidance(&self) -> String {
+        match &self.kind {
+            ErrorKind::Authentication => "Your Anthropic API key was rejected. Set a valid one with \
+                `ergo --set-api-key <KEY>` or `export ANTHROPIC_API_KEY=<KEY>` \
+                (keys are at https://console.anthropic.com/settings/keys)."
+                .to_string(),
+            ErrorKind::Permission => {
+                format!("Your API key can't use {}. Check its workspace and permissions in the Anthropic console.", self.model)
+            }
+            ErrorKind::NotFound if self.available_models.is_empty() => {
+                format!("Model '{}' doesn't exist or your API key can't use it.", self.model)
+            }
+            ErrorKind::NotFound => format!(
+                "Model '{}' doesn't exist or your API key can't use it. Models it can use:\n{}",
+                self.model,
+                self.available_models.iter().map(|m| format!("   • {}", m)).collect::<Vec<_>>().join("\n")
+            ),
+            ErrorKind::RateLimit => {
+                "You hit your API rate limit. Wait a minute and try again, or raise the limit in the Anthropic console."
+                    .to_string()
+            }
+            ErrorKind::Overloaded => {
+                "Anthropic's API is overloaded right now. This is temporary: wait a few seconds and try again.".to_string()
+            }
+            ErrorKind::RequestTooLarge => "The request is too large. Shorten the intent or the output it includes.".to_string(),
+            ErrorKind::ContentPolicy => {
+                "The request was blocked by the content policy. Rephrase the intent, describing the task itself.".to_string()
+            }
+            ErrorKind::Billing => {
+                "Your Anthropic account is out of credit. Add some in the console's billing settings.".to_string()
+            }
+            ErrorKind::InvalidRequest => {
+                "The API rejected the request. If it keeps happening, run with --debug-llm and report it.".to_string()
+            }
+            ErrorKind::Server => "Anthropic's API had an internal error. Try again in a moment.".to_string(),
+            ErrorKind::Other(_) => "Try again; if it keeps failing, run with --debug-llm to see the request.".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Anthropic API error")?;
+        if let Some(status) = self.status {
+            write!(f, " (HTTP {})", status)?;
+        }
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        write!(f, "\n💡 {}", self.guidance())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// The start of a body, on one line.
+fn excerpt(body: &str) -> String {
+    let line = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(MAX_RAW_BODY) {
+        Some((index, _)) => format!("{}…", &line[..index]),
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn error(status: u16, kind: &str, message: &str) -> ApiError {
+        let body = json!({"type": "error", "error": {"type": kind, "message": message}});
+        ApiError::from_response(&HttpResponse::new(status, &body.to_string()), "claude-x")
+    }
+
+    #[test]
+    fn test_from_response_reads_type_and_message() {
+        let invalid_key = error(401, "authentication_error", "invalid x-api-key");
+        assert_eq!(invalid_key.kind, ErrorKind::Authentication);
+        assert!(invalid_key.to_string().starts_with("Anthropic API error (HTTP 401): invalid x-api-key\n💡 Your Anthropic API key"));
+
+        assert!(error(529, "overloaded_error", "Overloaded").kind.is_transient());
+        assert_eq!(
+            error(400, "invalid_request_error", "Output blocked by content filtering policy").kind,
+            ErrorKind::ContentPolicy
+        );
+        assert_eq!(
+            error(400, "invalid_request_error", "Your credit balance is too low").kind,
+            ErrorKind::Billing
+        );
+        assert!(error(404, "not_found_error", "model: claude-x").is_unknown_model());
+    }
+
+    #[test]
+    fn test_from_response_falls_back_to_status() {
+        let gateway = ApiError::from_response(&HttpResponse::new(502, "<html>\n  Bad   Gateway\n</html>"), "claude-x");
+
+        assert_eq!(gateway.kind, ErrorKind::Server);
+        assert_eq!(gateway.message, "<html> Bad Gateway </html>");
+    }
+
+    #[test]
+    fn test_guidance_lists_available_models() {
+        let mut unknown = error(404, "not_found_error", "model: claude-x");
+        unknown.available_models = vec!["claude-a".to_string(), "claude-b".to_string()];
+
+        assert!(unknown.guidance().ends_with("Models it can use:\n   • claude-a\n   • claude-b"));
+        assert_eq!(
+            ApiError::refusal(&json!({"stop_reason": "refusal", "content": []})).map(|e| e.kind),
+            Some(ErrorKind::ContentPolicy)
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Cooperative cancellation on Ctrl-C.
+//!
+//! ergo traps SIGINT instead of dying on it, so in-flight work can be wound
+//! down cleanly: pending API requests are dropped, the generated command's
+//! process group is killed, and temporary files are removed before exiting.
+//! A second Ctrl-C exits immediately.
+//!
+//! Generation requests are also abandoned when they exceed the configured
+//! `generator.timeout`, with [`GenerationTimedOut`].
+
+use anyhow::Result;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// Exit code used when ergo is interrupted (128 + SIGINT).
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Error returned when an operation was cancelled by the user.
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Error returned when a generation request took longer than allowed.
+#[derive(Debug)]
+pub struct GenerationTimedOut {
+    /// The timeout, in seconds.
+    pub seconds: u64,
+    /// Whether retries were already made.
+    pub retried: bool,
+}
+
+impl std::fmt::Display for GenerationTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.retried {
+            write!(f, "Generation timed out after {}s, even after retrying", self.seconds)
+        } else {
+            write!(f, "Generation timed out after {}s; retry with --retry", self.seconds)
+        }
+    }
+}
+
+impl std::error::Error for GenerationTimedOut {}
+
+/// A shared flag signalling that the current operation should stop.
+///
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Cancellation {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks the token as cancelled and wakes everything waiting on it.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Returns true once [`Self::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Completes when the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Runs `future` to completion unless the token is cancelled first.
+    ///
+    /// On cancellation the future is dropped, which aborts any request it
+    /// has in flight, and [`Interrupted`] is returned.
+    pub async fn run<T>(&self, future: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::select! {
+            result = future => result,
+            _ = self.cancelled() => Err(Interrupted.into()),
+        }
+    }
+
+    /// Cancels this token on the first Ctrl-C and exits on the second.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn cancel_on_ctrl_c(&self) {
+        let token = self.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            warn!("Interrupted, cancelling");
+            eprintln!("\n🛑 Interrupted, cleaning up (press Ctrl-C again to exit now)");
+            token.cancel();
+
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_token_is_not_cancelled() {
+        assert!(!Cancellation::new().is_cancelled());
+    }
+
+    #[test]
+    fn test_cancel_is_shared_between_clones() {
+        let token = Cancellation::new();
+        let clone = token.clone();
+
+        clone.cancel();
+
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_wakes_waiters() {
+        let token = Cancellation::new();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+
+        token.cancel();
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), waiter)
+            .await
+            .expect("waiter was not woken")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_returns_interrupted_when_cancelled() {
+        let token = Cancellation::new();
+        token.cancel();
+
+        let result: Result<()> = token.run(std::future::pending()).await;
+
+        assert!(result.unwrap_err().is::<Interrupted>());
+    }
+
+    #[tokio::test]
+    async fn test_run_returns_the_future_result() {
+        let token = Cancellation::new();
+
+        assert_eq!(token.run(async { Ok(7) }).await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_returns_immediately_when_already_cancelled() {
+        let token = Cancellation::new();
+        token.cancel();
+
+        token.cancelled().await;
+    }
+}
@@ -0,0 +1,221 @@
+//! Bounded capture of command output.
+//!
+//! Generated commands may print arbitrarily large output. Everything is
+//! passed through to the terminal, but only the last `limit` bytes are kept
+//! for the execution context, so a command that dumps gigabytes can't exhaust
+//! ergo's memory.
+//!
+//! [`PrefixedLines`] relays the output of concurrent commands line by line,
+//! labelled with the command that produced it.
+
+use crate::output_processing::MAX_PROCESSED_BYTES;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Default number of bytes kept per stream (64 KiB).
+pub const DEFAULT_CAPTURE_LIMIT: usize = 64 * 1024;
+
+/// A ring buffer that keeps the last `limit` bytes written to it.
+#[derive(Debug)]
+pub struct CaptureBuffer {
+    limit: usize,
+    buf: VecDeque<u8>,
+    total: u64,
+}
+
+impl CaptureBuffer {
+    /// Creates a buffer that keeps at most `limit` bytes.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            buf: VecDeque::with_capacity(limit.min(8192)),
+            total: 0,
+        }
+    }
+
+    /// Total number of bytes written, including dropped ones.
+    pub fn total_bytes(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns true if older output was dropped.
+    pub fn is_truncated(&self) -> bool {
+        self.total > self.buf.len() as u64
+    }
+
+    /// Returns true if nothing was written.
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Returns the kept output, prefixed with a marker if it was truncated.
+    pub fn contents(&self) -> String {
+        let bytes: Vec<u8> = self.buf.iter().copied().collect();
+        let text = String::from_utf8_lossy(&bytes);
+        if self.is_truncated() {
+            format!(
+                "[... {} earlier bytes truncated ...]\n{}",
+                self.total - self.buf.len() as u64,
+                text
+            )
+        } else {
+            text.into_owned()
+        }
+    }
+}
+
+impl Write for CaptureBuffer {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.total += data.len() as u64;
+
+        let keep = &data[data.len().saturating_sub(self.limit)..];
+        let overflow = (self.buf.len() + keep.len()).saturating_sub(self.limit);
+        self.buf.drain(..overflow.min(self.buf.len()));
+        self.buf.extend(keep);
+
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes complete lines to a shared sink, each prefixed with a label.
+///
+/// Partial lines are held back until their newline arrives (or the writer is
+/// dropped), so lines from concurrent commands never mix. A line longer than
+/// the limit is written in pieces of that size rather than held in memory.
+pub struct PrefixedLines<'a, W: Write> {
+    prefix: String,
+    sink: &'a Mutex<W>,
+    pending: Vec<u8>,
+    limit: usize,
+}
+
+impl<'a, W: Write> PrefixedLines<'a, W> {
+    /// Creates a writer that labels each line with `[label] `.
+    pub fn new(label: &str, sink: &'a Mutex<W>) -> Self {
+        Self::with_limit(label, sink, MAX_PROCESSED_BYTES)
+    }
+
+    /// Creates a writer that holds back at most `limit` bytes of a line.
+    pub fn with_limit(label: &str, sink: &'a Mutex<W>, limit: usize) -> Self {
+        Self {
+            prefix: format!("[{}] ", label),
+            sink,
+            pending: Vec::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    fn emit(&self, line: &[u8]) -> std::io::Result<()> {
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        sink.write_all(self.prefix.as_bytes())?;
+        sink.write_all(line)?;
+        sink.flush()
+    }
+}
+
+impl<W: Write> Write for PrefixedLines<'_, W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(data);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            self.emit(&line)?;
+        }
+        while self.pending.len() >= self.limit {
+            let mut piece: Vec<u8> = self.pending.drain(..self.limit).collect();
+            piece.push(b'\n');
+            self.emit(&piece)?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.sink.lock().unwrap_or_else(|e| e.into_inner()).flush()
+    }
+}
+
+impl<W: Write> Drop for PrefixedLines<'_, W> {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            let mut line = std::mem::take(&mut self.pending);
+            line.push(b'\n');
+            let _ = self.emit(&line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_output_is_kept_whole() {
+        let mut capture = CaptureBuffer::new(16);
+        write!(capture, "hello").unwrap();
+
+        assert!(!capture.is_truncated());
+        assert_eq!(capture.contents(), "hello");
+    }
+
+    #[test]
+    fn test_keeps_only_the_tail() {
+        let mut capture = CaptureBuffer::new(8);
+        for chunk in ["0123", "4567", "89ab"] {
+            capture.write_all(chunk.as_bytes()).unwrap();
+        }
+
+        assert_eq!(capture.total_bytes(), 12);
+        assert!(capture.is_truncated());
+        assert_eq!(capture.contents(), "[... 4 earlier bytes truncated ...]\n456789ab");
+    }
+
+    #[test]
+    fn test_single_write_larger_than_limit() {
+        let mut capture = CaptureBuffer::new(4);
+        capture.write_all(b"abcdefghij").unwrap();
+
+        assert!(capture.contents().ends_with("\nghij"));
+    }
+
+    #[test]
+    fn test_prefixed_lines_hold_back_partial_lines() {
+        let sink = Mutex::new(Vec::new());
+        {
+            let mut a = PrefixedLines::new("a", &sink);
+            let mut b = PrefixedLines::new("b", &sink);
+            a.write_all(b"one ").unwrap();
+            b.write_all(b"two\n").unwrap();
+            a.write_all(b"three\nfour").unwrap();
+        }
+
+        let output = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+        assert_eq!(output, "[b] two\n[a] one three\n[a] four\n");
+    }
+
+    #[test]
+    fn test_prefixed_lines_split_lines_longer_than_limit() {
+        let sink = Mutex::new(Vec::new());
+        {
+            let mut a = PrefixedLines::with_limit("a", &sink, 4);
+            a.write_all(b"abc").unwrap();
+            a.write_all(b"defghij").unwrap();
+            assert_eq!(a.pending, b"ij");
+            a.write_all(b"\nok\n").unwrap();
+        }
+
+        let output = String::from_utf8(sink.into_inner().unwrap()).unwrap();
+        assert_eq!(output, "[a] abcd\n[a] efgh\n[a] ij\n[a] ok\n");
+    }
+
+    #[test]
+    fn test_empty() {
+        let capture = CaptureBuffer::new(4);
+
+        assert!(capture.is_empty());
+        assert_eq!(capture.contents(), "");
+    }
+}
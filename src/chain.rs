@@ -0,0 +1,200 @@
+//! Command chains: several intents run one after the other, with branches on
+//! success and failure.
+//!
+//! A chain is built from the command line (`ergo build --then deploy
+//! --on-failure notify`) or loaded from a pipeline file:
+//!
+//! ```toml
+//! [[steps]]
+//! run = "build --release"
+//!
+//! [[steps]]
+//! run = "deploy @prod"
+//!
+//! [[steps]]
+//! run = "notify deploy-failed"
+//! when = "failure"
+//! ```
+//!
+//! Each step runs depending on how the previous step that ran went, like
+//! `build && deploy || notify` in a shell.
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::path::Path;
+
+/// When a chain step runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepCondition {
+    /// Only if the previous step succeeded (`--then`).
+    #[default]
+    Success,
+    /// Only if the previous step failed (`--on-failure`).
+    Failure,
+    /// Regardless of the previous step (`--finally`).
+    Always,
+}
+
+impl StepCondition {
+    /// Returns whether a step with this condition runs, given whether the
+    /// last step that ran succeeded (`None` for the first step).
+    pub fn should_run(self, previous: Option<bool>) -> bool {
+        match self {
+            StepCondition::Success => previous.unwrap_or(true),
+            StepCondition::Failure => previous == Some(false),
+            StepCondition::Always => true,
+        }
+    }
+}
+
+/// One step of a chain: an intent and when to run it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainStep {
+    /// Command name followed by its arguments.
+    pub intent: Vec<String>,
+    /// When the step runs.
+    pub when: StepCondition,
+}
+
+impl ChainStep {
+    /// Creates a step from a command line such as `"deploy @prod"`.
+    pub fn parse(line: &str, when: StepCondition) -> Result<Self> {
+        let intent: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        if intent.is_empty() {
+            return Err(anyhow!("Chain steps need a command"));
+        }
+        Ok(Self { intent, when })
+    }
+
+    /// Returns the step's command line.
+    pub fn command_line(&self) -> String {
+        self.intent.join(" ")
+    }
+}
+
+/// A sequence of steps run one after the other.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Chain {
+    /// The steps, in order.
+    pub steps: Vec<ChainStep>,
+}
+
+/// A step as written in a pipeline file.
+#[derive(Deserialize)]
+struct StepSpec {
+    run: String,
+    #[serde(default)]
+    when: StepCondition,
+}
+
+#[derive(Deserialize)]
+struct PipelineFile {
+    steps: Vec<StepSpec>,
+}
+
+impl Chain {
+    /// Parses a TOML pipeline file (see the module documentation).
+    pub fn parse_pipeline(content: &str) -> Result<Self> {
+        let file: PipelineFile = toml::from_str(content).context("Invalid pipeline file")?;
+        let steps = file
+            .steps
+            .iter()
+            .map(|spec| ChainStep::parse(&spec.run, spec.when))
+            .collect::<Result<Vec<_>>>()?;
+        if steps.is_empty() {
+            return Err(anyhow!("Pipeline file has no steps"));
+        }
+        Ok(Self { steps })
+    }
+
+    /// Loads a TOML pipeline file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pipeline file {}", path.display()))?;
+        Self::parse_pipeline(&content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns which steps run when each step that runs succeeds or fails as
+    /// given by `results`, in order.
+    fn simulate(chain: &Chain, mut results: impl Iterator<Item = bool>) -> Vec<String> {
+        let mut previous = None;
+        let mut ran = Vec::new();
+        for step in &chain.steps {
+            if step.when.should_run(previous) {
+                ran.push(step.command_line());
+                previous = results.next();
+            }
+        }
+        ran
+    }
+
+    fn chain() -> Chain {
+        Chain::parse_pipeline(
+            r#"
+            [[steps]]
+            run = "build --release"
+
+            [[steps]]
+            run = "deploy @prod"
+
+            [[steps]]
+            run = "notify failed"
+            when = "failure"
+
+            [[steps]]
+            run = "cleanup"
+            when = "always"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_pipeline() {
+        let chain = chain();
+
+        assert_eq!(chain.steps.len(), 4);
+        assert_eq!(chain.steps[0].intent, vec!["build", "--release"]);
+        assert_eq!(chain.steps[1].when, StepCondition::Success);
+        assert_eq!(chain.steps[2].when, StepCondition::Failure);
+    }
+
+    #[test]
+    fn test_parse_pipeline_rejects_empty_steps() {
+        assert!(Chain::parse_pipeline("steps = []").is_err());
+        assert!(Chain::parse_pipeline("[[steps]]\nrun = \"  \"").is_err());
+        assert!(Chain::parse_pipeline("[[steps]]\nrun = \"x\"\nwhen = \"maybe\"").is_err());
+    }
+
+    #[test]
+    fn test_chain_runs_success_path() {
+        assert_eq!(
+            simulate(&chain(), [true, true, true].into_iter()),
+            vec!["build --release", "deploy @prod", "cleanup"]
+        );
+    }
+
+    #[test]
+    fn test_chain_branches_on_failure() {
+        assert_eq!(
+            simulate(&chain(), [false, true, true].into_iter()),
+            vec!["build --release", "notify failed", "cleanup"]
+        );
+        assert_eq!(
+            simulate(&chain(), [true, false, true, true].into_iter()),
+            vec!["build --release", "deploy @prod", "notify failed", "cleanup"]
+        );
+    }
+
+    #[test]
+    fn test_failure_step_does_not_start_a_chain() {
+        assert!(!StepCondition::Failure.should_run(None));
+        assert!(StepCondition::Success.should_run(None));
+    }
+}
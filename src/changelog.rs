@@ -0,0 +1,176 @@
+//! Changelogs of a command's revisions, for `ergo --changelog`.
+//!
+//! Every `--nope` or `--regenerate` archives the version it replaces (see
+//! [`crate::command_cache::CommandRevision`]). A [`Changelog`] puts those
+//! revisions back together, newest first: when each one was generated, the
+//! feedback that led to it, how its permissions changed and the diff of its
+//! script against the revision before. It tells what changed when a shared
+//! command suddenly behaves differently; `--summarize` has the model sum it
+//! up in a few lines.
+
+use crate::command_cache::{CommandCache, FeedbackTurn, RevisionSnapshot};
+use crate::providers::utc_date;
+use crate::script_diff::{self, PermissionChange};
+use anyhow::Result;
+use std::fmt;
+
+/// One revision of a command and how it differs from the one before.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangelogEntry {
+    /// The revision number.
+    pub revision: u32,
+    /// Unix timestamp when the revision was created.
+    pub created_at: u64,
+    /// Description of the command at this revision.
+    pub description: String,
+    /// The `--nope` feedback on the previous revision that led to this one.
+    pub feedback: Vec<FeedbackTurn>,
+    /// Permissions added or removed since the previous revision.
+    pub permission_changes: Vec<PermissionChange>,
+    /// Unified diff of the script against the previous revision; empty for
+    /// the first revision or an unchanged script.
+    pub diff: String,
+}
+
+/// The revisions of a command, newest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Changelog {
+    /// The command's name.
+    pub command: String,
+    /// The revision in use.
+    pub current: u32,
+    /// One entry per revision, newest first.
+    pub entries: Vec<ChangelogEntry>,
+}
+
+impl Changelog {
+    /// Builds the changelog of `name` from its revisions and feedback.
+    ///
+    /// Returns `None` if the command isn't cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a revision's script can't be read.
+    pub fn build(cache: &CommandCache, name: &str) -> Result<Option<Self>> {
+        let Some(current) = cache.current_revision(name) else {
+            return Ok(None);
+        };
+        let feedback = cache.feedback_history(name);
+
+        let mut entries = Vec::new();
+        let mut previous: Option<RevisionSnapshot> = None;
+        for revision in 1..=current {
+            let snapshot = cache.get_revision(name, revision)?;
+            let (permission_changes, diff) = match &previous {
+                Some(old) => (
+                    script_diff::permission_diff(&old.permissions, &snapshot.permissions),
+                    script_diff::unified_diff(
+                        &old.script,
+                        &snapshot.script,
+                        &format!("{}@{}", name, old.revision),
+                        &format!("{}@{}", name, revision),
+                    ),
+                ),
+                None => (Vec::new(), String::new()),
+            };
+            entries.push(ChangelogEntry {
+                revision,
+                created_at: snapshot.created_at,
+                description: snapshot.description.clone(),
+                feedback: feedback.iter().filter(|turn| turn.revision + 1 == revision).cloned().collect(),
+                permission_changes,
+                diff,
+            });
+            previous = Some(snapshot);
+        }
+        entries.reverse();
+
+        Ok(Some(Self {
+            command: name.to_string(),
+            current,
+            entries,
+        }))
+    }
+}
+
+impl fmt::Display for Changelog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "📜 Changelog of '{}' ({} revision{})",
+            self.command,
+            self.current,
+            if self.current == 1 { "" } else { "s" }
+        )?;
+        for entry in &self.entries {
+            let (year, month, day) = utc_date(entry.created_at);
+            write!(f, "\n\n{}@{} · {:04}-{:02}-{:02}", self.command, entry.revision, year, month, day)?;
+            if entry.revision == self.current {
+                write!(f, " (current)")?;
+            }
+            write!(f, "\n   📝 {}", entry.description)?;
+            if entry.revision == 1 {
+                write!(f, "\n   ✨ First generated")?;
+            } else if entry.feedback.is_empty() {
+                write!(f, "\n   🔄 Regenerated without feedback")?;
+            }
+            for turn in &entry.feedback {
+                match turn.feedback.trim() {
+                    "" => write!(f, "\n   💬 Regenerated from the error of the previous revision")?,
+                    feedback => write!(f, "\n   💬 {}", feedback)?,
+                }
+                if let Some(error) = turn.stderr.as_deref().and_then(|e| e.lines().find(|l| !l.trim().is_empty())) {
+                    write!(f, "\n      error: {}", error.trim())?;
+                }
+            }
+            for line in script_diff::format_permission_diff(&entry.permission_changes).lines() {
+                write!(f, "\n   🔑 {}", line)?;
+            }
+            if entry.revision > 1 && entry.diff.is_empty() {
+                write!(f, "\n   📄 Script unchanged")?;
+            }
+            for line in entry.diff.lines() {
+                write!(f, "\n   │ {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FixedTime, MockPathResolver, command};
+
+    #[tokio::test]
+    async fn test_changelog_pairs_revisions_with_their_feedback() {
+        let mut cache = CommandCache::with_providers(Box::new(MockPathResolver::new().unwrap()), Box::new(FixedTime(0)))
+            .await
+            .unwrap();
+        cache.store_command("greet", &command("greet", &[]), "console.log('hi')\n").await.unwrap();
+        let turn = FeedbackTurn {
+            revision: 1,
+            feedback: "greet the user by name".to_string(),
+            stderr: None,
+            created_at: 0,
+        };
+        cache.record_feedback("greet", turn).await.unwrap();
+        cache
+            .store_command("greet", &command("greet", &["--allow-env=USER"]), "console.log(Deno.env.get('USER'))\n")
+            .await
+            .unwrap();
+
+        let changelog = Changelog::build(&cache, "greet").unwrap().unwrap();
+
+        assert_eq!(changelog.current, 2);
+        assert_eq!(changelog.entries.iter().map(|e| e.revision).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(changelog.entries[0].feedback[0].feedback, "greet the user by name");
+        assert!(changelog.entries[1].feedback.is_empty());
+        let text = changelog.to_string();
+        assert!(text.starts_with("📜 Changelog of 'greet' (2 revisions)\n\ngreet@2 · 1970-01-01 (current)\n"));
+        assert!(text.contains("   💬 greet the user by name\n   🔑 + --allow-env=USER (Needed by the test)\n"));
+        assert!(text.contains("   │ -console.log('hi')\n   │ +console.log(Deno.env.get('USER'))"));
+        assert!(text.ends_with("greet@1 · 1970-01-01\n   📝 Test command 'greet'\n   ✨ First generated"));
+        assert!(Changelog::build(&cache, "missing").unwrap().is_none());
+    }
+}
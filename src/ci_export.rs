@@ -0,0 +1,228 @@
+//! GitHub Actions steps for cached commands, for `ergo ci export`.
+//!
+//! A command proven locally can run in CI without ergo or a model: the
+//! exported steps install Deno at the version the command was rated with
+//! (see [`crate::environment`]) and run its script with exactly the
+//! permissions it was granted.
+//!
+//! The script is either run from the repository, when the command lives in
+//! a bioma inside it, or inlined into the step:
+//!
+//! ```yaml
+//! - name: Set up Deno
+//!   uses: denoland/setup-deno@v2
+//!   with:
+//!     deno-version: v1.46.3
+//! - name: "ergo: csv2json"
+//!   shell: bash
+//!   run: deno run --allow-read=./data .abiogenesis/biomas/csv2json.ts data/in.csv
+//! ```
+
+use crate::executor::shell_quote;
+use crate::llm_generator::GeneratedCommand;
+use crate::permissions::PermissionKind;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// The action installing Deno.
+pub const SETUP_DENO_ACTION: &str = "denoland/setup-deno@v2";
+
+/// Deno version installed when the command's proven version is unknown.
+pub const FALLBACK_DENO_VERSION: &str = "v2.x";
+
+/// Environment variables the runner provides, never mapped to secrets.
+const RUNNER_VARIABLES: &[&str] = &["HOME", "PATH", "USER", "PWD", "TMPDIR", "SHELL", "LANG", "CI"];
+
+/// Where the exported step gets the script from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptSource {
+    /// The script file, relative to the repository root.
+    Repository(PathBuf),
+    /// The script itself, written to a temporary file by the step.
+    Inline(String),
+}
+
+/// Returns the root of the git repository containing `dir`, if any.
+pub fn repository_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Returns `script` relative to `repository`, if it's inside it.
+pub fn repository_path(repository: &Path, script: &Path) -> Option<PathBuf> {
+    script.strip_prefix(repository).ok().map(Path::to_path_buf)
+}
+
+/// Renders the steps running `command` with `args` in a GitHub Actions job.
+///
+/// # Arguments
+///
+/// * `command` - The command, with the permissions of the script to run
+/// * `source` - Where the step gets the script from
+/// * `deno_version` - The Deno version the command was proven with, e.g. `1.46.3`
+/// * `args` - Arguments passed to the command
+pub fn render_steps(command: &GeneratedCommand, source: &ScriptSource, deno_version: Option<&str>, args: &[String]) -> String {
+    let deno_version = match deno_version {
+        Some(version) => format!("v{}", version.trim_start_matches('v')),
+        None => FALLBACK_DENO_VERSION.to_string(),
+    };
+
+    let mut deno_command = vec!["deno".to_string(), "run".to_string()];
+    deno_command.extend(command.permissions.iter().map(|p| shell_quote(&p.permission)));
+    deno_command.push(match source {
+        ScriptSource::Repository(path) => shell_quote(&path.to_string_lossy()),
+        ScriptSource::Inline(_) => format!("\"$RUNNER_TEMP/{}\"", script_file_name(command)),
+    });
+    deno_command.extend(args.iter().map(|arg| shell_quote(arg)));
+    let deno_command = deno_command.join(" ");
+
+    let mut yaml = String::new();
+    let _ = writeln!(yaml, "# {}", command.description.lines().next().unwrap_or_default());
+    let _ = writeln!(yaml, "- name: Set up Deno");
+    let _ = writeln!(yaml, "  uses: {}", SETUP_DENO_ACTION);
+    let _ = writeln!(yaml, "  with:");
+    let _ = writeln!(yaml, "    deno-version: {}", deno_version);
+    let _ = writeln!(yaml, "- name: {}", yaml_string(&format!("ergo: {}", command.name)));
+    let _ = writeln!(yaml, "  shell: bash");
+
+    let variables = secret_variables(command);
+    if !variables.is_empty() {
+        let _ = writeln!(yaml, "  env:");
+        for variable in variables {
+            let _ = writeln!(yaml, "    {}: ${{{{ secrets.{} }}}}", variable, variable);
+        }
+    }
+
+    match source {
+        ScriptSource::Repository(_) => {
+            let _ = writeln!(yaml, "  run: {}", yaml_string(&deno_command));
+        }
+        ScriptSource::Inline(script) => {
+            let delimiter = heredoc_delimiter(script);
+            let _ = writeln!(yaml, "  run: |");
+            let _ = writeln!(
+                yaml,
+                "    cat > \"$RUNNER_TEMP/{}\" <<'{}'",
+                script_file_name(command),
+                delimiter
+            );
+            for line in script.lines() {
+                if line.is_empty() {
+                    yaml.push('\n');
+                } else {
+                    let _ = writeln!(yaml, "    {}", line);
+                }
+            }
+            let _ = writeln!(yaml, "    {}", delimiter);
+            let _ = writeln!(yaml, "    {}", deno_command);
+        }
+    }
+    yaml
+}
+
+/// The file name an inlined script is written to.
+fn script_file_name(command: &GeneratedCommand) -> String {
+    Path::new(&command.script_file)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("{}.ts", command.name))
+}
+
+/// Environment variables the command may read by name, which the job has
+/// to provide.
+fn secret_variables(command: &GeneratedCommand) -> Vec<String> {
+    command
+        .permissions
+        .iter()
+        .filter_map(|p| p.parsed().ok())
+        .filter(|p| p.kind == PermissionKind::Env)
+        .flat_map(|p| p.scopes.unwrap_or_default())
+        .filter(|name| {
+            !RUNNER_VARIABLES.contains(&name.as_str())
+                && !name.starts_with("GITHUB_")
+                && !name.starts_with("RUNNER_")
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .collect()
+}
+
+/// A heredoc delimiter that doesn't occur as a line of `script`.
+fn heredoc_delimiter(script: &str) -> String {
+    let mut delimiter = "ERGO_SCRIPT".to_string();
+    while script.lines().any(|line| line.trim() == delimiter) {
+        delimiter.push('_');
+    }
+    delimiter
+}
+
+/// Quotes `text` as a YAML string when it could be misread.
+fn yaml_string(text: &str) -> String {
+    let plain = text
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || " -_./=,@%+()".contains(c))
+        && !text.starts_with(['-', ' '])
+        && !text.ends_with(' ');
+    if plain {
+        text.to_string()
+    } else {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::command;
+
+    #[test]
+    fn test_render_steps_runs_the_repository_script() {
+        let mut csv2json = command("csv2json", &["--allow-read=./data", "--allow-env=API_TOKEN,HOME"]);
+        csv2json.description = "Convert CSV files to JSON".to_string();
+        let source = ScriptSource::Repository(PathBuf::from(".abiogenesis/biomas/csv2json.ts"));
+
+        let yaml = render_steps(&csv2json, &source, Some("1.46.3"), &["data/in file.csv".to_string()]);
+
+        assert_eq!(
+            yaml,
+            "# Convert CSV files to JSON\n\
+             - name: Set up Deno\n\
+             \x20 uses: denoland/setup-deno@v2\n\
+             \x20 with:\n\
+             \x20   deno-version: v1.46.3\n\
+             - name: \"ergo: csv2json\"\n\
+             \x20 shell: bash\n\
+             \x20 env:\n\
+             \x20   API_TOKEN: ${{ secrets.API_TOKEN }}\n\
+             \x20 run: \"deno run --allow-read=./data --allow-env=API_TOKEN,HOME .abiogenesis/biomas/csv2json.ts 'data/in file.csv'\"\n"
+        );
+    }
+
+    #[test]
+    fn test_render_steps_inlines_the_script() {
+        let hello = command("hello", &[]);
+        let script = "console.log(`\n\nERGO_SCRIPT\n`);";
+
+        let yaml = render_steps(&hello, &ScriptSource::Inline(script.to_string()), None, &[]);
+
+        assert!(yaml.contains(&format!("deno-version: {}", FALLBACK_DENO_VERSION)));
+        assert!(yaml.contains("    cat > \"$RUNNER_TEMP/hello.ts\" <<'ERGO_SCRIPT_'\n    console.log(`\n\n    ERGO_SCRIPT\n"));
+        assert!(yaml.ends_with("    ERGO_SCRIPT_\n    deno run \"$RUNNER_TEMP/hello.ts\"\n"));
+    }
+
+    #[test]
+    fn test_repository_root_and_path() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path().join("repo");
+        let bioma = repo.join("tools/.abiogenesis/biomas");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(&bioma).unwrap();
+
+        assert_eq!(repository_root(&bioma), Some(repo.clone()));
+        assert_eq!(repository_root(temp_dir.path()), None);
+        assert_eq!(
+            repository_path(&repo, &bioma.join("hello.ts")),
+            Some(PathBuf::from("tools/.abiogenesis/biomas/hello.ts"))
+        );
+    }
+}
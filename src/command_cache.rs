@@ -6,8 +6,11 @@
 //!
 //! # Cache Structure
 //!
-//! Commands are stored in `.abiogenesis/biomas/` directories:
+//! Commands are stored in `.abiogenesis/biomas/` directories (both names are
+//! configurable, see [`CacheConfig`]):
 //! - `commands.json` - Command metadata and permission decisions
+//! - `usage.jsonl` - One line per run, appended without touching
+//!   `commands.json` and folded into it the next time metadata is written
 //! - `*.ts` - Generated TypeScript script files
 //!
 //! # Hierarchy Resolution
@@ -16,15 +19,28 @@
 //! 1. Current directory's `.abiogenesis/biomas/`
 //! 2. Parent directories' `.abiogenesis/biomas/`
 //! 3. Home directory's `~/.abiogenesis/biomas/`
-
-use crate::llm_generator::{GeneratedCommand, PermissionRequest};
+//!
+//! The upward search stops early at a directory containing an
+//! `.abiogenesisignore` file, at a repository root if
+//! [`CacheConfig::stop_at_repo_root`] is set, and before any directory
+//! matching [`CacheConfig::stop_at`], so slow (e.g. network-mounted) parents
+//! aren't scanned. It also stays on the starting filesystem and within
+//! [`CacheConfig::max_search_depth`] parents.
+
+use crate::environment::EnvironmentSnapshot;
+use crate::execution_context::script_hash;
+use crate::llm_generator::{GeneratedCommand, PermissionRequest, PlatformVariant};
+use crate::output_processing::OutputProcessor;
 use crate::providers::{SystemTimeProvider, TimeProvider};
+use crate::semantic_search::{Embedding, SimilarCommand};
+use crate::trust::ExpiredGrants;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
-use tracing::{debug, info};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use tracing::{debug, info, warn};
 
 // =============================================================================
 // Traits for Dependency Injection
@@ -47,49 +63,256 @@ pub trait CachePathResolver: Send + Sync {
     ///
     /// Returns the script content if found, None otherwise.
     fn find_script(&self, script_file: &str) -> Result<Option<String>>;
+
+    /// Finds the cache directory a command is stored in.
+    ///
+    /// Returns None if the command isn't found or the resolver can't tell.
+    fn find_command_dir(&self, _name: &str) -> Result<Option<PathBuf>> {
+        Ok(None)
+    }
 }
 
 // =============================================================================
 // Default Implementations
 // =============================================================================
 
+/// Directory layout of the cache (the `[cache]` table in `config.toml`).
+///
+/// Caches live in `<dir_name>/<bioma_dir>` of every project directory and of
+/// the home directory. The config file itself always stays in `~/.abiogenesis`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Name of the dotfolder marking a project, e.g. `.abiogenesis`.
+    /// Overridden by `ERGO_CACHE_DIR_NAME`.
+    #[serde(default = "default_dir_name")]
+    pub dir_name: String,
+    /// Path of the cache inside the dotfolder, e.g. `biomas`, or `.` for
+    /// the dotfolder itself. Overridden by `ERGO_CACHE_BIOMA_DIR`.
+    #[serde(default = "default_bioma_dir")]
+    pub bioma_dir: String,
+    /// Directories the upward search stops before, e.g. `/mnt/*` or `~`.
+    /// `*` matches within one path component.
+    #[serde(default)]
+    pub stop_at: Vec<String>,
+    /// Whether the upward search stops at the first directory containing `.git`.
+    #[serde(default)]
+    pub stop_at_repo_root: bool,
+    /// Whether the upward search stops at mount points instead of crossing
+    /// into the parent filesystem.
+    #[serde(default = "default_stay_on_filesystem")]
+    pub stay_on_filesystem: bool,
+    /// How many parents of the current directory are searched at most.
+    #[serde(default)]
+    pub max_search_depth: Option<usize>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            dir_name: default_dir_name(),
+            bioma_dir: default_bioma_dir(),
+            stop_at: Vec::new(),
+            stop_at_repo_root: false,
+            stay_on_filesystem: default_stay_on_filesystem(),
+            max_search_depth: None,
+        }
+    }
+}
+
+fn default_stay_on_filesystem() -> bool {
+    true
+}
+
+fn default_dir_name() -> String {
+    ".abiogenesis".to_string()
+}
+
+fn default_bioma_dir() -> String {
+    "biomas".to_string()
+}
+
+impl CacheConfig {
+    /// Checks that `dir_name` is a single directory name and `bioma_dir` a
+    /// relative path that stays inside it.
+    pub fn validate(&self) -> Result<()> {
+        let mut dir_name = Path::new(&self.dir_name).components();
+        if !matches!((dir_name.next(), dir_name.next()), (Some(Component::Normal(_)), None)) {
+            anyhow::bail!("cache.dir_name must be a single directory name, got '{}'", self.dir_name);
+        }
+        let bioma_dir = Path::new(&self.bioma_dir);
+        if self.bioma_dir.is_empty()
+            || !bioma_dir.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            anyhow::bail!("cache.bioma_dir must be a relative path inside cache.dir_name, got '{}'", self.bioma_dir);
+        }
+        Ok(())
+    }
+
+    /// The cache directory of `base`, e.g. `<base>/.abiogenesis/biomas`.
+    pub fn cache_dir(&self, base: &Path) -> PathBuf {
+        let mut dir = base.join(&self.dir_name);
+        dir.extend(Path::new(&self.bioma_dir).components().filter(|c| matches!(c, Component::Normal(_))));
+        dir
+    }
+
+    /// The directory a cache directory belongs to, or `None` if `cache_dir`
+    /// doesn't follow this layout.
+    pub fn base_dir(&self, cache_dir: &Path) -> Option<PathBuf> {
+        let suffix = self.cache_dir(Path::new(""));
+        if !cache_dir.ends_with(&suffix) {
+            return None;
+        }
+        let mut base = cache_dir;
+        for _ in suffix.components() {
+            base = base.parent()?;
+        }
+        Some(base.to_path_buf())
+    }
+
+    /// Name of the file that makes a directory the last one searched, e.g.
+    /// `.abiogenesisignore`.
+    pub fn ignore_file_name(&self) -> String {
+        format!("{}ignore", self.dir_name)
+    }
+
+    /// Whether the upward search stops before looking at `dir`.
+    fn is_excluded(&self, dir: &Path, home: Option<&Path>) -> bool {
+        self.stop_at.iter().any(|pattern| {
+            let pattern = match (pattern.strip_prefix('~'), home) {
+                (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+                    home.join(rest.trim_start_matches('/'))
+                }
+                _ => PathBuf::from(pattern),
+            };
+            path_matches(dir, &pattern)
+        })
+    }
+
+    /// Whether the upward search stops after looking at `dir`.
+    fn is_boundary(&self, dir: &Path) -> bool {
+        dir.join(self.ignore_file_name()).exists() || (self.stop_at_repo_root && dir.join(".git").exists())
+    }
+}
+
+/// The ID of the device (filesystem) holding `path`.
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+/// The ID of the device (filesystem) holding `path`.
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Whether `path` matches `pattern` component by component.
+fn path_matches(path: &Path, pattern: &Path) -> bool {
+    let (mut path, mut pattern) = (path.components(), pattern.components());
+    loop {
+        match (path.next(), pattern.next()) {
+            (None, None) => return true,
+            (Some(name), Some(glob))
+                if name_matches(&name.as_os_str().to_string_lossy(), &glob.as_os_str().to_string_lossy()) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Whether `name` matches `glob`, where `*` matches any text.
+fn name_matches(name: &str, glob: &str) -> bool {
+    let parts: Vec<&str> = glob.split('*').collect();
+    let [first, middle @ .., last] = parts.as_slice() else {
+        return name == glob;
+    };
+    if name.len() < first.len() + last.len() || !name.starts_with(first) || !name.ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
 /// Default path resolver that searches the actual filesystem hierarchy.
-pub struct HierarchyPathResolver;
+pub struct HierarchyPathResolver {
+    layout: CacheConfig,
+}
 
 impl HierarchyPathResolver {
-    /// Creates a new hierarchy path resolver.
+    /// Creates a hierarchy path resolver with the configured layout.
     pub fn new() -> Self {
-        Self
+        let layout = crate::config::Config::load().map(|config| config.cache).unwrap_or_default();
+        Self::with_layout(layout)
+    }
+
+    /// Creates a hierarchy path resolver with the given layout, falling back
+    /// to the default layout if it's invalid.
+    pub fn with_layout(layout: CacheConfig) -> Self {
+        let layout = match layout.validate() {
+            Ok(()) => layout,
+            Err(e) => {
+                warn!("Ignoring the cache layout: {}", e);
+                CacheConfig::default()
+            }
+        };
+        Self { layout }
     }
 
     /// Gets all cache directories, from closest to home.
-    fn get_cache_dirs(&self) -> Result<Vec<PathBuf>> {
+    pub fn get_cache_dirs(&self) -> Result<Vec<PathBuf>> {
+        Ok(self.search(&std::env::current_dir()?, dirs::home_dir().as_deref()))
+    }
+
+    /// Gets the cache directories from `start` upward, then `home`'s.
+    fn search(&self, start: &Path, home: Option<&Path>) -> Vec<PathBuf> {
         let mut dirs = Vec::new();
-        let mut current_dir = std::env::current_dir()?;
+        let mut current_dir = start;
+        let device = device_id(start);
 
         // Search upward from current directory
-        loop {
-            let abiogenesis_dir = current_dir.join(".abiogenesis");
-            if abiogenesis_dir.exists() && abiogenesis_dir.is_dir() {
-                let cache_dir = abiogenesis_dir.join("biomas");
-                dirs.push(cache_dir);
+        for depth in 0.. {
+            if self.layout.is_excluded(current_dir, home) {
+                debug!("Stopping the cache search before {:?}", current_dir);
+                break;
+            }
+            if current_dir.join(&self.layout.dir_name).is_dir() {
+                dirs.push(self.layout.cache_dir(current_dir));
+            }
+            if self.layout.is_boundary(current_dir) {
+                debug!("Stopping the cache search at {:?}", current_dir);
+                break;
+            }
+
+            if self.layout.max_search_depth.is_some_and(|max| depth >= max) {
+                debug!("Stopping the cache search at {:?}: max_search_depth reached", current_dir);
+                break;
             }
 
-            match current_dir.parent() {
-                Some(parent) => current_dir = parent.to_path_buf(),
-                None => break,
+            let Some(parent) = current_dir.parent() else {
+                break;
+            };
+            if self.layout.stay_on_filesystem && device.is_some() && device_id(parent) != device {
+                debug!("Stopping the cache search at {:?}: mount point", current_dir);
+                break;
             }
+            current_dir = parent;
         }
 
         // Add home directory as fallback
-        if let Some(home) = dirs::home_dir() {
-            let home_cache = home.join(".abiogenesis").join("biomas");
+        if let Some(home) = home {
+            let home_cache = self.layout.cache_dir(home);
             if !dirs.contains(&home_cache) {
                 dirs.push(home_cache);
             }
         }
 
-        Ok(dirs)
+        dirs
     }
 }
 
@@ -110,16 +333,25 @@ impl CachePathResolver for HierarchyPathResolver {
     fn find_command(&self, name: &str) -> Result<Option<GeneratedCommand>> {
         for cache_dir in self.get_cache_dirs()? {
             let cache_file = cache_dir.join("commands.json");
-            if cache_file.exists() {
-                if let Ok(content) = fs::read_to_string(&cache_file) {
-                    if let Ok(cache) = serde_json::from_str::<HashMap<String, CacheEntry>>(&content)
-                    {
-                        if let Some(entry) = cache.get(name) {
-                            debug!("Found command '{}' in cache at {:?}", name, cache_dir);
-                            return Ok(Some(entry.command.clone()));
-                        }
-                    }
-                }
+            if cache_file.exists()
+                && let Ok(content) = fs::read_to_string(&cache_file)
+                && let Ok((cache, _)) = parse_entries(&content)
+                && let Some(entry) = cache.get(name)
+            {
+                debug!("Found command '{}' in cache at {:?}", name, cache_dir);
+                return Ok(Some(entry.command.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    fn find_command_dir(&self, name: &str) -> Result<Option<PathBuf>> {
+        for cache_dir in self.get_cache_dirs()? {
+            if let Ok(content) = fs::read_to_string(cache_dir.join("commands.json"))
+                && let Ok((cache, _)) = parse_entries(&content)
+                && cache.contains_key(name)
+            {
+                return Ok(Some(cache_dir));
             }
         }
         Ok(None)
@@ -150,6 +382,38 @@ pub enum PermissionConsent {
     AcceptForever,
     /// User explicitly denied execution.
     Denied,
+    /// Run without asking again from the same shell session, for
+    /// [`SESSION_CONSENT_SECS`] at most.
+    AcceptForSession,
+}
+
+/// Environment variable naming the shell session consent is granted for;
+/// the parent process (the shell ergo runs from) when unset.
+pub const SESSION_ENV: &str = "ERGO_SESSION";
+
+/// How long an [`PermissionConsent::AcceptForSession`] grant lasts.
+pub const SESSION_CONSENT_SECS: u64 = 8 * 60 * 60;
+
+/// Identifies the shell session ergo runs from: [`SESSION_ENV`] if set,
+/// the parent process otherwise. `None` where neither is available.
+pub fn shell_session(env: impl Fn(&str) -> Option<String>) -> Option<String> {
+    if let Some(session) = env(SESSION_ENV).filter(|session| !session.is_empty()) {
+        return Some(session);
+    }
+    #[cfg(unix)]
+    return Some(format!("ppid:{}", std::os::unix::process::parent_id()));
+    #[cfg(not(unix))]
+    None
+}
+
+/// The shell session an [`PermissionConsent::AcceptForSession`] decision
+/// was granted for.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SessionGrant {
+    /// The session, as given by [`shell_session`].
+    pub id: String,
+    /// Unix timestamp after which the grant no longer applies.
+    pub expires_at: u64,
 }
 
 /// A user's permission decision for a command.
@@ -161,16 +425,358 @@ pub struct PermissionDecision {
     pub consent: PermissionConsent,
     /// Unix timestamp when the decision was made.
     pub decided_at: u64,
+    /// Hash of the scripts the decision was made for, set by
+    /// [`CommandCache::set_permission_decision`]. The decision no longer
+    /// applies once any of them changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script_hash: Option<String>,
+    /// The session an [`PermissionConsent::AcceptForSession`] decision
+    /// applies to, set by [`CommandCache::set_permission_decision`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionGrant>,
+}
+
+/// A previous version of a command, archived when it was replaced.
+///
+/// Revisions are numbered from 1 (the first generated version). The current
+/// version of a command is always `revisions.len() + 1`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandRevision {
+    /// The revision number.
+    pub revision: u32,
+    /// Description of the command at this revision.
+    pub description: String,
+    /// Permissions declared at this revision.
+    pub permissions: Vec<PermissionRequest>,
+    /// Path to the archived script (relative to the biomas directory).
+    pub script_file: String,
+    /// Unix timestamp when this revision was created.
+    pub created_at: u64,
+}
+
+/// One `--nope` round of a command's feedback transcript.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FeedbackTurn {
+    /// The revision the feedback was about.
+    pub revision: u32,
+    /// What the user asked to change (may be empty).
+    pub feedback: String,
+    /// Standard error of the execution the feedback was about, if any.
+    pub stderr: Option<String>,
+    /// Unix timestamp when the feedback was given.
+    pub created_at: u64,
+}
+
+/// A thumbs-up on a revision of a command, given with `--yep`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Rating {
+    /// The revision that worked.
+    pub revision: u32,
+    /// What the user liked about it, if they said.
+    pub comment: Option<String>,
+    /// Whether the revision is pinned, blocking regeneration until unpinned.
+    pub pinned: bool,
+    /// The environment of the run that was rated, if known.
+    #[serde(default)]
+    pub environment: Option<EnvironmentSnapshot>,
+    /// Unix timestamp when the rating was given.
+    pub rated_at: u64,
+}
+
+/// A fully materialized revision of a command, including its script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RevisionSnapshot {
+    /// The revision number.
+    pub revision: u32,
+    /// Description of the command at this revision.
+    pub description: String,
+    /// Permissions declared at this revision.
+    pub permissions: Vec<PermissionRequest>,
+    /// The script source at this revision.
+    pub script: String,
+    /// Unix timestamp when this revision was created.
+    pub created_at: u64,
+}
+
+/// Who generated a command, recorded so shared biomas show where each
+/// command came from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Owner {
+    /// User name of whoever generated the command.
+    pub created_by: Option<String>,
+    /// Host the command was generated on.
+    pub host: Option<String>,
+}
+
+impl Owner {
+    /// The current user and host, as far as they can be determined.
+    pub fn current() -> Self {
+        Self {
+            created_by: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok()
+                .filter(|user| !user.is_empty()),
+            host: host_name(),
+        }
+    }
+}
+
+impl std::fmt::Display for Owner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.created_by.as_deref().unwrap_or("unknown user"))?;
+        if let Some(host) = &self.host {
+            write!(f, " on {}", host)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the machine's host name.
+#[cfg(unix)]
+fn host_name() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer is valid for its whole length, and gethostname
+    // writes at most that many bytes
+    if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+        return None;
+    }
+    let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    let name = String::from_utf8_lossy(&buffer[..end]).into_owned();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Returns the machine's host name.
+#[cfg(not(unix))]
+fn host_name() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok().filter(|name| !name.is_empty())
+}
+
+/// Longest name a command can have.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Checks that `name` can name a command, and so its script files.
+///
+/// Names are ASCII letters, digits, `-` and `_`, starting with a letter or
+/// digit, at most [`MAX_NAME_LEN`] long: nothing that could reach outside
+/// the bioma (`/`, `..`) or look like another name (Unicode look-alikes,
+/// invisible characters).
+///
+/// # Errors
+///
+/// Returns an error saying what's wrong with `name`.
+pub fn validate_command_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        anyhow::bail!("A command name can't be empty");
+    }
+    if name.len() > MAX_NAME_LEN {
+        anyhow::bail!("The command name {:?} is longer than {} characters", name, MAX_NAME_LEN);
+    }
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if !name.starts_with(|c: char| c.is_ascii_alphanumeric()) || !name.chars().all(allowed) {
+        anyhow::bail!(
+            "{:?} isn't a valid command name: use letters, digits, '-' and '_', starting with a letter or digit",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Turns a name the model suggested into kebab-case: `Git Status`,
+/// `git_status` and `gitStatus` all become `git-status`.
+///
+/// Separators, punctuation and whitespace become single dashes, so a name
+/// like `../../bin/sh` can't reach outside the bioma.
+///
+/// # Errors
+///
+/// Returns an error if the name has other non-ASCII characters, which
+/// could make it look like another, or if nothing valid is left of it.
+pub fn normalize_command_name(suggested: &str) -> Result<String> {
+    let mut name = String::new();
+    let mut after_lowercase = false;
+    for c in suggested.trim().chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && after_lowercase {
+                name.push('-');
+            }
+            name.push(c.to_ascii_lowercase());
+            after_lowercase = c.is_ascii_lowercase() || c.is_ascii_digit();
+        } else if c.is_ascii() || c.is_whitespace() {
+            if !name.is_empty() && !name.ends_with('-') {
+                name.push('-');
+            }
+            after_lowercase = false;
+        } else {
+            anyhow::bail!("The suggested command name {:?} has the non-ASCII character {:?}", suggested, c);
+        }
+    }
+    let name = name.trim_end_matches('-');
+    validate_command_name(name)?;
+    Ok(name.to_string())
+}
+
+/// Checks the integrity of the cache in `cache_dir`.
+///
+/// Returns the script files `commands.json` references that are missing,
+/// including platform variants and archived revisions. A directory without
+/// a `commands.json` is an empty cache.
+///
+/// # Errors
+///
+/// Returns an error if `commands.json` can't be read or doesn't parse.
+pub fn missing_scripts(cache_dir: &Path) -> Result<Vec<String>> {
+    let content = match fs::read_to_string(cache_dir.join("commands.json")) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let (cache, _) = parse_entries(&content)?;
+    let mut missing: Vec<String> = cache
+        .values()
+        .flat_map(|entry| {
+            std::iter::once(&entry.command.script_file)
+                .chain(entry.command.variants.values().map(|variant| &variant.script_file))
+                .chain(entry.revisions.iter().map(|revision| &revision.script_file))
+        })
+        .filter(|script_file| !cache_dir.join(script_file).exists())
+        .cloned()
+        .collect();
+    missing.sort();
+    Ok(missing)
+}
+
+/// File runs are appended to, next to `commands.json`.
+const USAGE_LOG: &str = "usage.jsonl";
+
+/// Directory (inside the bioma) keeping a copy of each script as ergo wrote
+/// it, to show how it was changed on disk.
+const PRISTINE_DIR: &str = ".pristine";
+
+/// How often and how recently a command has been run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CommandUsage {
+    /// Number of runs.
+    pub count: u32,
+    /// Timestamp of the last run (or of creation, if never run).
+    pub last_used: u64,
+}
+
+impl CommandUsage {
+    fn record(&mut self, at: u64) {
+        self.count += 1;
+        self.last_used = self.last_used.max(at);
+    }
+}
+
+/// One line of the usage log.
+#[derive(Debug, Serialize, Deserialize)]
+struct UsageRecord {
+    name: String,
+    at: u64,
+}
+
+/// Tallies the usage log at `path`, skipping malformed (e.g. torn) lines.
+///
+/// A missing log has no runs.
+fn read_usage_log(path: &Path) -> Result<HashMap<String, CommandUsage>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut tally: HashMap<String, CommandUsage> = HashMap::new();
+    for record in content.lines().filter_map(|line| serde_json::from_str::<UsageRecord>(line).ok()) {
+        tally.entry(record.name).or_default().record(record.at);
+    }
+    Ok(tally)
 }
 
 /// Internal cache entry storing command metadata and usage statistics.
+///
+/// Like [`GeneratedCommand`], every field but the command defaults and
+/// unknown fields are kept in `extra`.
 #[derive(Debug, Serialize, Deserialize)]
 struct CacheEntry {
     command: GeneratedCommand,
+    #[serde(default)]
     created_at: u64,
+    #[serde(default)]
     usage_count: u32,
+    #[serde(default)]
     last_used: u64,
+    #[serde(default)]
     permission_decision: Option<PermissionDecision>,
+    /// Previous versions of the command, oldest first.
+    #[serde(default)]
+    revisions: Vec<CommandRevision>,
+    /// Output processors overriding the global `output.processors` setting.
+    #[serde(default)]
+    output_processors: Option<Vec<OutputProcessor>>,
+    /// Saved argument lists, invoked as `ergo <command> @<preset>`.
+    #[serde(default)]
+    presets: BTreeMap<String, Vec<String>>,
+    /// Feedback given with `--nope`, oldest first.
+    #[serde(default)]
+    feedback_history: Vec<FeedbackTurn>,
+    /// The latest `--yep` rating.
+    #[serde(default)]
+    rating: Option<Rating>,
+    /// Who generated the current version.
+    #[serde(default)]
+    owner: Option<Owner>,
+    /// Free-form note set with `--note`, e.g. why the command exists.
+    #[serde(default)]
+    note: Option<String>,
+    /// The request the command was generated from, e.g. `weather London`
+    /// or `show today's date`.
+    #[serde(default)]
+    intent: Option<String>,
+    /// Embedding of the name and description, for semantic lookup.
+    #[serde(default)]
+    embedding: Option<Embedding>,
+    /// Hash of each script file as ergo wrote it, to notice edits on disk.
+    #[serde(default)]
+    script_hashes: BTreeMap<String, String>,
+    /// The URL the command was imported from with `--import-url`.
+    #[serde(default)]
+    imported_from: Option<String>,
+    /// Fields written by a newer version of ergo, kept as they are.
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// The entries of a `commands.json`, and those this version can't read.
+///
+/// An entry written by a newer ergo may not parse here, e.g. if it holds a
+/// consent choice added since. It is skipped rather than failing the whole
+/// file, and kept as it is so that saving the cache doesn't drop it.
+fn parse_entries(content: &str) -> Result<(HashMap<String, CacheEntry>, BTreeMap<String, serde_json::Value>)> {
+    let raw: BTreeMap<String, serde_json::Value> = serde_json::from_str(content)?;
+    let mut entries = HashMap::new();
+    let mut unreadable = BTreeMap::new();
+    for (name, value) in raw {
+        match CacheEntry::deserialize(&value) {
+            Ok(entry) => {
+                entries.insert(name, entry);
+            }
+            Err(e) => {
+                warn!("Skipping command '{}', written by a newer ergo: {}", name, e);
+                unreadable.insert(name, value);
+            }
+        }
+    }
+    Ok((entries, unreadable))
+}
+
+/// A script of a cached command that changed on disk since ergo wrote it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModifiedScript {
+    /// The script file, relative to the bioma.
+    pub script_file: String,
+    /// The script as ergo wrote it, if its copy is still there.
+    pub original: Option<String>,
+    /// The script on disk now.
+    pub current: String,
 }
 
 // =============================================================================
@@ -200,10 +806,23 @@ pub struct CommandCache {
     write_cache_dir: PathBuf,
     /// In-memory cache for the write directory.
     write_cache: HashMap<String, CacheEntry>,
+    /// Entries of `commands.json` written by a newer ergo that don't parse,
+    /// saved back as they are.
+    unreadable: BTreeMap<String, serde_json::Value>,
+    /// Runs in the usage log not yet folded into `write_cache`.
+    pending_usage: HashMap<String, CommandUsage>,
     /// Path resolver for cache operations.
     path_resolver: Box<dyn CachePathResolver>,
     /// Time provider for timestamps.
     time_provider: Box<dyn TimeProvider>,
+    /// The shell session consent for the session is granted for.
+    session: Option<String>,
+    /// How long AcceptForever decisions last without the command being run,
+    /// in seconds, and what they become then.
+    grant_expiry: Option<(u64, ExpiredGrants)>,
+    /// Files written in memory instead of the write directory, by path
+    /// relative to it; `None` while the cache is saved to disk.
+    memory: Option<HashMap<String, String>>,
 }
 
 impl CommandCache {
@@ -211,29 +830,60 @@ impl CommandCache {
     ///
     /// The cache directory is determined by searching upward from the current
     /// directory for a `.abiogenesis` folder, falling back to the home directory.
+    ///
+    /// In [`crate::ephemeral`] mode nothing is written: see [`Self::ephemeral`].
     pub async fn new() -> Result<Self> {
-        Self::with_providers(
+        Self::open(
             Box::new(HierarchyPathResolver::new()),
             Box::new(SystemTimeProvider),
+            crate::ephemeral::is_enabled(),
         )
         .await
     }
 
     /// Creates a command cache with custom providers (for testing).
+    ///
+    /// If the write directory can't be created (e.g. a read-only home), the
+    /// cache warns and keeps its changes in memory.
     pub async fn with_providers(
         path_resolver: Box<dyn CachePathResolver>,
         time_provider: Box<dyn TimeProvider>,
+    ) -> Result<Self> {
+        Self::open(path_resolver, time_provider, false).await
+    }
+
+    /// Creates a command cache that reads the commands already on disk but
+    /// keeps every change in memory, for the lifetime of the process.
+    pub async fn ephemeral(
+        path_resolver: Box<dyn CachePathResolver>,
+        time_provider: Box<dyn TimeProvider>,
+    ) -> Result<Self> {
+        Self::open(path_resolver, time_provider, true).await
+    }
+
+    async fn open(
+        path_resolver: Box<dyn CachePathResolver>,
+        time_provider: Box<dyn TimeProvider>,
+        ephemeral: bool,
     ) -> Result<Self> {
         let write_cache_dir = path_resolver.get_write_dir()?;
-        fs::create_dir_all(&write_cache_dir)?;
+        let mut memory = ephemeral.then(HashMap::new);
+        if !ephemeral && let Err(e) = fs::create_dir_all(&write_cache_dir) {
+            crate::ephemeral::warn_unsaved(&format!("the command cache at {}", write_cache_dir.display()), &e);
+            memory = Some(HashMap::new());
+        }
 
         let cache_file = write_cache_dir.join("commands.json");
-        let write_cache = if cache_file.exists() {
+        let (write_cache, unreadable) = if cache_file.exists() {
             let content = fs::read_to_string(&cache_file)?;
-            serde_json::from_str(&content).unwrap_or_default()
+            parse_entries(&content).unwrap_or_default()
         } else {
-            HashMap::new()
+            Default::default()
         };
+        let pending_usage = read_usage_log(&write_cache_dir.join(USAGE_LOG)).unwrap_or_else(|e| {
+            warn!("Failed to read the usage log: {}", e);
+            HashMap::new()
+        });
 
         info!(
             "Write cache initialized at {:?} with {} entries",
@@ -244,11 +894,96 @@ impl CommandCache {
         Ok(Self {
             write_cache_dir,
             write_cache,
+            unreadable,
+            pending_usage,
             path_resolver,
             time_provider,
+            session: shell_session(|name| std::env::var(name).ok()),
+            grant_expiry: None,
+            memory,
         })
     }
 
+    /// Whether changes are kept in memory rather than saved to disk.
+    pub fn is_in_memory(&self) -> bool {
+        self.memory.is_some()
+    }
+
+    /// Reads `file` of the write directory, looking in memory first.
+    ///
+    /// Returns `None` if there's no such file.
+    fn read_file(&self, file: &str) -> Result<Option<String>> {
+        if let Some(content) = self.memory.as_ref().and_then(|files| files.get(file)) {
+            return Ok(Some(content.clone()));
+        }
+        match fs::read_to_string(self.write_cache_dir.join(file)) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes `file` of the write directory, or keeps it in memory if the
+    /// cache is there or the write fails.
+    fn write_file(&mut self, file: &str, content: &str) {
+        if self.memory.is_none() {
+            let path = self.write_cache_dir.join(file);
+            let written = match path.parent() {
+                Some(parent) => fs::create_dir_all(parent).and_then(|()| fs::write(&path, content)),
+                None => fs::write(&path, content),
+            };
+            match written {
+                Ok(()) => return,
+                Err(e) => self.fall_back_to_memory(&e),
+            }
+        }
+        if let Some(files) = &mut self.memory {
+            files.insert(file.to_string(), content.to_string());
+        }
+    }
+
+    /// Removes `path` of the write directory, a file or a whole directory.
+    fn remove_path(&mut self, path: &str) {
+        if self.memory.is_none() {
+            let full_path = self.write_cache_dir.join(path);
+            let removed = if full_path.is_dir() {
+                fs::remove_dir_all(&full_path)
+            } else if full_path.exists() {
+                fs::remove_file(&full_path)
+            } else {
+                Ok(())
+            };
+            match removed {
+                Ok(()) => return,
+                Err(e) => self.fall_back_to_memory(&e),
+            }
+        }
+        if let Some(files) = &mut self.memory {
+            let dir = format!("{}/", path);
+            files.retain(|file, _| file != path && !file.starts_with(&dir));
+        }
+    }
+
+    /// Keeps the changes from now on in memory, after `error` writing to disk.
+    fn fall_back_to_memory(&mut self, error: &dyn std::fmt::Display) {
+        crate::ephemeral::warn_unsaved(&format!("the command cache at {}", self.write_cache_dir.display()), error);
+        self.memory.get_or_insert_with(HashMap::new);
+    }
+
+    /// Sets the shell session consent for the session is granted for.
+    pub fn set_session(&mut self, session: Option<String>) {
+        self.session = session;
+    }
+
+    /// Makes AcceptForever decisions expire once their command hasn't been
+    /// run for `days` (0 for never), becoming what `expired` says.
+    ///
+    /// Expiry is checked when a decision is looked up: nothing is rewritten
+    /// until the user decides again.
+    pub fn set_grant_expiry(&mut self, days: u64, expired: ExpiredGrants) {
+        self.grant_expiry = (days > 0).then_some((days * 24 * 60 * 60, expired));
+    }
+
     /// Retrieves a command by name from the cache.
     ///
     /// Searches the in-memory cache first, then uses the path resolver.
@@ -273,9 +1008,8 @@ impl CommandCache {
     /// Searches the write cache directory first, then uses the path resolver.
     pub fn get_script_content(&self, command: &GeneratedCommand) -> Result<String> {
         // First try the write cache directory
-        let script_path = self.write_cache_dir.join(&command.script_file);
-        if script_path.exists() {
-            return Ok(fs::read_to_string(&script_path)?);
+        if let Some(content) = self.read_file(&command.script_file)? {
+            return Ok(content);
         }
 
         // Then use the path resolver
@@ -289,6 +1023,21 @@ impl CommandCache {
         ))
     }
 
+    /// Retrieves the test script for a command, if one exists.
+    ///
+    /// Test scripts live next to the command script as `<name>.test.ts` and are
+    /// written by users (never generated). They are used to guard
+    /// regenerations against breaking behavior that already works.
+    pub fn get_test_script(&self, name: &str) -> Result<Option<String>> {
+        let test_file = format!("{}.test.ts", name);
+
+        if let Some(content) = self.read_file(&test_file)? {
+            return Ok(Some(content));
+        }
+
+        self.path_resolver.find_script(&test_file)
+    }
+
     /// Stores a new command in the cache.
     ///
     /// # Arguments
@@ -296,25 +1045,52 @@ impl CommandCache {
     /// * `name` - The command name (used for lookup)
     /// * `command` - The command metadata
     /// * `script_content` - The TypeScript source code
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` isn't a valid command name (see
+    /// [`validate_command_name`]), or if the cache can't be written.
     pub async fn store_command(
         &mut self,
         name: &str,
         command: &GeneratedCommand,
         script_content: &str,
     ) -> Result<()> {
+        validate_command_name(name)?;
         let now = self.time_provider.now();
 
+        // Archive the version being replaced, if any
+        let revisions = self.archive_revision(name)?;
+        let output_processors = self.write_cache.get(name).and_then(|e| e.output_processors.clone());
+        let presets = self.write_cache.get(name).map(|e| e.presets.clone()).unwrap_or_default();
+        let feedback_history = self
+            .write_cache
+            .get(name)
+            .map(|e| e.feedback_history.clone())
+            .unwrap_or_default();
+        let rating = self.write_cache.get(name).and_then(|e| e.rating.clone());
+        let note = self.write_cache.get(name).and_then(|e| e.note.clone());
+        let intent = self.write_cache.get(name).and_then(|e| e.intent.clone());
+        let extra = self.write_cache.get(name).map(|e| e.extra.clone()).unwrap_or_default();
+        let mut script_hashes = self.write_cache.get(name).map(|e| e.script_hashes.clone()).unwrap_or_default();
+        // Fold earlier runs into the entry being replaced, so the new one starts at zero
+        self.fold_usage_log()?;
+        // Regenerating the main script leaves the platform variants alone
+        let variants = match self.write_cache.get(name) {
+            Some(previous) if command.variants.is_empty() => previous.command.variants.clone(),
+            _ => command.variants.clone(),
+        };
+
         // Write the script file
         let script_filename = format!("{}.ts", name);
-        let script_path = self.write_cache_dir.join(&script_filename);
-        fs::write(&script_path, script_content)?;
+        self.write_file(&script_filename, script_content);
+        script_hashes.insert(script_filename.clone(), self.keep_pristine(&script_filename, script_content));
 
         // Create command entry with script file reference
         let command_with_file = GeneratedCommand {
-            name: command.name.clone(),
-            description: command.description.clone(),
             script_file: script_filename.clone(),
-            permissions: command.permissions.clone(),
+            variants,
+            ..command.clone()
         };
 
         let entry = CacheEntry {
@@ -323,8 +1099,21 @@ impl CommandCache {
             usage_count: 0,
             last_used: now,
             permission_decision: None,
+            revisions,
+            output_processors,
+            presets,
+            feedback_history,
+            rating,
+            owner: Some(Owner::current()),
+            note,
+            intent,
+            embedding: Some(Embedding::of_command(command)),
+            script_hashes,
+            imported_from: None,
+            extra,
         };
 
+        self.unreadable.remove(name);
         self.write_cache.insert(name.to_string(), entry);
         self.persist_write_cache().await?;
 
@@ -335,64 +1124,658 @@ impl CommandCache {
         Ok(())
     }
 
-    /// Updates the usage statistics for a command.
-    pub async fn update_usage(&mut self, name: &str) -> Result<()> {
-        if let Some(entry) = self.write_cache.get_mut(name) {
-            let now = self.time_provider.now();
-            entry.usage_count += 1;
-            entry.last_used = now;
-            self.persist_write_cache().await?;
-            debug!("Updated usage for command '{}'", name);
+    /// Stores a platform-specific version of a command's script as
+    /// `<name>.<os>.ts`, replacing any earlier variant for that platform.
+    ///
+    /// The main script and the other variants are left alone. The permission
+    /// decision is reset, since the variant may need other permissions.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the command is not in the cache.
+    pub async fn store_variant(
+        &mut self,
+        name: &str,
+        os: &str,
+        permissions: &[PermissionRequest],
+        script_content: &str,
+    ) -> Result<bool> {
+        if !self.write_cache.contains_key(name) {
+            return Ok(false);
         }
-        Ok(())
+        if os.is_empty() || !os.chars().all(|c| c.is_ascii_alphanumeric()) {
+            anyhow::bail!("{:?} isn't a platform name", os);
+        }
+
+        let script_file = format!("{}.{}.ts", name, os);
+        self.write_file(&script_file, script_content);
+        let hash = self.keep_pristine(&script_file, script_content);
+        let Some(entry) = self.write_cache.get_mut(name) else {
+            return Ok(false);
+        };
+        entry.script_hashes.insert(script_file.clone(), hash);
+        entry.command.variants.insert(
+            os.to_string(),
+            PlatformVariant {
+                script_file: script_file.clone(),
+                permissions: permissions.to_vec(),
+            },
+        );
+        entry.permission_decision = None;
+        self.persist_write_cache().await?;
+
+        info!("Stored {} variant of command '{}' as '{}'", os, name, script_file);
+        Ok(true)
     }
 
-    /// Persists the in-memory cache to disk.
-    async fn persist_write_cache(&self) -> Result<()> {
-        let cache_file = self.write_cache_dir.join("commands.json");
-        let content = serde_json::to_string_pretty(&self.write_cache)?;
-        fs::write(cache_file, content)?;
-        Ok(())
+    /// Keeps a copy of `script` as ergo wrote it to `script_file` and
+    /// returns its hash.
+    fn keep_pristine(&mut self, script_file: &str, script: &str) -> String {
+        self.write_file(&format!("{}/{}", PRISTINE_DIR, script_file), script);
+        script_hash(script)
     }
 
-    /// Lists all cached command names.
-    #[allow(dead_code)]
-    pub async fn list_cached_commands(&self) -> Vec<String> {
-        self.write_cache.keys().cloned().collect()
+    /// Returns the scripts of a command that were changed on disk (edited by
+    /// hand, or by a sync tool) since ergo wrote them.
+    ///
+    /// Scripts stored before ergo recorded their hashes are never reported.
+    pub fn modified_scripts(&self, name: &str) -> Vec<ModifiedScript> {
+        let Some(entry) = self.write_cache.get(name) else {
+            return Vec::new();
+        };
+        entry
+            .script_hashes
+            .iter()
+            .filter_map(|(script_file, hash)| {
+                let current = self.read_file(script_file).ok()??;
+                (script_hash(&current) != *hash).then(|| ModifiedScript {
+                    script_file: script_file.clone(),
+                    original: self.read_file(&format!("{}/{}", PRISTINE_DIR, script_file)).ok().flatten(),
+                    current,
+                })
+            })
+            .collect()
     }
 
-    /// Stores a permission decision for a command.
-    pub async fn set_permission_decision(
-        &mut self,
-        name: &str,
-        decision: PermissionDecision,
-    ) -> Result<()> {
+    /// Takes the scripts of a command as they are on disk, so their changes
+    /// are no longer reported by [`Self::modified_scripts`].
+    pub async fn accept_modified_scripts(&mut self, name: &str) -> Result<()> {
+        let mut hashes = Vec::new();
+        for script in self.modified_scripts(name) {
+            hashes.push((script.script_file.clone(), self.keep_pristine(&script.script_file, &script.current)));
+        }
+        if hashes.is_empty() {
+            return Ok(());
+        }
         if let Some(entry) = self.write_cache.get_mut(name) {
-            entry.permission_decision = Some(decision);
+            entry.script_hashes.extend(hashes);
             self.persist_write_cache().await?;
-            info!("Updated permission decision for command '{}'", name);
+            info!("Accepted the changes made on disk to command '{}'", name);
         }
         Ok(())
     }
 
-    /// Retrieves the permission decision for a command.
-    pub fn get_permission_decision(&self, name: &str) -> Option<&PermissionDecision> {
-        self.write_cache.get(name)?.permission_decision.as_ref()
+    /// Copies the current script of command `name` into the revisions
+    /// directory and returns its revision list extended with it (empty if
+    /// the command isn't cached).
+    fn archive_revision(&mut self, name: &str) -> Result<Vec<CommandRevision>> {
+        let Some(entry) = self.write_cache.get(name) else {
+            return Ok(Vec::new());
+        };
+        let mut revisions = entry.revisions.clone();
+        let revision = revisions.len() as u32 + 1;
+
+        let Ok(Some(script)) = self.read_file(&entry.command.script_file) else {
+            debug!("No script to archive for command '{}'", name);
+            return Ok(revisions);
+        };
+
+        let script_file = format!("revisions/{}/{}.ts", name, revision);
+        revisions.push(CommandRevision {
+            revision,
+            description: entry.command.description.clone(),
+            permissions: entry.command.permissions.clone(),
+            script_file: script_file.clone(),
+            created_at: entry.created_at,
+        });
+        self.write_file(&script_file, &script);
+        debug!("Archived revision {} of command '{}'", revision, name);
+        Ok(revisions)
     }
 
-    /// Checks if permission consent is needed for a command.
+    /// Returns the archived revisions of a command, oldest first.
     ///
-    /// Returns true if:
-    /// - No decision has been made yet
-    /// - The previous decision was AcceptOnce
+    /// The current version is not included; its number is
+    /// [`Self::current_revision`].
+    pub fn list_revisions(&self, name: &str) -> &[CommandRevision] {
+        self.write_cache
+            .get(name)
+            .map(|e| e.revisions.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Returns the revision number of the current version of a command.
+    pub fn current_revision(&self, name: &str) -> Option<u32> {
+        self.write_cache
+            .get(name)
+            .map(|e| e.revisions.len() as u32 + 1)
+    }
+
+    /// Loads a specific revision of a command, including its script.
+    ///
+    /// Revision numbers start at 1; the highest number is the current version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command or revision doesn't exist, or the
+    /// script file cannot be read.
+    pub fn get_revision(&self, name: &str, revision: u32) -> Result<RevisionSnapshot> {
+        let entry = self
+            .write_cache
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Command '{}' not found in cache", name))?;
+
+        let current = entry.revisions.len() as u32 + 1;
+        if revision == current {
+            return Ok(RevisionSnapshot {
+                revision,
+                description: entry.command.description.clone(),
+                permissions: entry.command.permissions.clone(),
+                script: self.get_script_content(&entry.command)?,
+                created_at: entry.created_at,
+            });
+        }
+
+        let archived = entry
+            .revisions
+            .iter()
+            .find(|r| r.revision == revision)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Command '{}' has no revision {} (current is {})",
+                    name,
+                    revision,
+                    current
+                )
+            })?;
+
+        Ok(RevisionSnapshot {
+            revision,
+            description: archived.description.clone(),
+            permissions: archived.permissions.clone(),
+            script: self
+                .read_file(&archived.script_file)?
+                .ok_or_else(|| anyhow::anyhow!("Script file '{}' not found", archived.script_file))?,
+            created_at: archived.created_at,
+        })
+    }
+
+    /// Records a run of a command.
+    ///
+    /// The run is appended to the usage log as a single line rather than
+    /// rewriting `commands.json`, so concurrent invocations neither contend
+    /// on the metadata file nor lose each other's updates.
+    pub async fn update_usage(&mut self, name: &str) -> Result<()> {
+        if self.write_cache.contains_key(name) {
+            let at = self.time_provider.now();
+            let mut line = serde_json::to_string(&UsageRecord {
+                name: name.to_string(),
+                at,
+            })?;
+            line.push('\n');
+            if self.memory.is_none()
+                && let Err(e) = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.write_cache_dir.join(USAGE_LOG))
+                    .and_then(|mut log| log.write_all(line.as_bytes()))
+            {
+                self.fall_back_to_memory(&e);
+            }
+            self.pending_usage.entry(name.to_string()).or_default().record(at);
+            debug!("Updated usage for command '{}'", name);
+        }
+        Ok(())
+    }
+
+    /// Returns how often and how recently a command has been run, merging
+    /// `commands.json` with the usage log.
+    pub fn usage(&self, name: &str) -> Option<CommandUsage> {
+        let entry = self.write_cache.get(name)?;
+        let mut usage = CommandUsage {
+            count: entry.usage_count,
+            last_used: entry.last_used,
+        };
+        if let Some(pending) = self.pending_usage.get(name) {
+            usage.count += pending.count;
+            usage.last_used = usage.last_used.max(pending.last_used);
+        }
+        Some(usage)
+    }
+
+    /// Where this process moves the usage log while folding it.
+    fn folding_log_path(&self) -> PathBuf {
+        self.write_cache_dir
+            .join(format!("usage.{}.folding.jsonl", std::process::id()))
+    }
+
+    /// Moves the runs in the usage log into the cache entries.
+    ///
+    /// The log is renamed out of the way first, so runs appended meanwhile
+    /// land in a fresh log instead of being lost. It is deleted once
+    /// `commands.json` has been written. In memory, the runs this process
+    /// knows of are folded instead.
+    fn fold_usage_log(&mut self) -> Result<()> {
+        let folding = self.folding_log_path();
+        let mut renamed = false;
+        if self.memory.is_none() {
+            match fs::rename(self.write_cache_dir.join(USAGE_LOG), &folding) {
+                Ok(()) => renamed = true,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => self.fall_back_to_memory(&e),
+            }
+        }
+        let runs = match self.memory {
+            Some(_) => std::mem::take(&mut self.pending_usage),
+            None => {
+                // Everything this process appended is in the renamed log now
+                self.pending_usage.clear();
+                // A log folded earlier, and not yet deleted, is in the entries already
+                if renamed { read_usage_log(&folding)? } else { HashMap::new() }
+            }
+        };
+        for (name, usage) in runs {
+            if let Some(entry) = self.write_cache.get_mut(&name) {
+                entry.usage_count += usage.count;
+                entry.last_used = entry.last_used.max(usage.last_used);
+            }
+        }
+        Ok(())
+    }
+
+    /// Persists the in-memory cache to disk, folding in the usage log.
+    ///
+    /// If `commands.json` can't be written, the cache stays in memory.
+    async fn persist_write_cache(&mut self) -> Result<()> {
+        self.fold_usage_log()?;
+        if self.memory.is_some() {
+            return Ok(());
+        }
+        let cache_file = self.write_cache_dir.join("commands.json");
+        let mut entries = self.unreadable.clone();
+        for (name, entry) in &self.write_cache {
+            entries.insert(name.clone(), serde_json::to_value(entry)?);
+        }
+        let content = serde_json::to_string_pretty(&entries)?;
+        if let Err(e) = fs::write(cache_file, content) {
+            self.fall_back_to_memory(&e);
+            return Ok(());
+        }
+        let folding = self.folding_log_path();
+        if folding.exists() {
+            fs::remove_file(folding)?;
+        }
+        Ok(())
+    }
+
+    /// Lists all cached command names.
+    #[allow(dead_code)]
+    pub async fn list_cached_commands(&self) -> Vec<String> {
+        self.write_cache.keys().cloned().collect()
+    }
+
+    /// Finds the cached command most similar to a natural language `intent`,
+    /// if any is at least `min_similarity` similar (see [`crate::semantic_search`]).
+    ///
+    /// Entries embedded by an older model are embedded again on the fly.
+    pub fn find_similar(&self, intent: &str, min_similarity: f32) -> Option<SimilarCommand> {
+        let query = Embedding::of(intent);
+        self.write_cache
+            .iter()
+            .map(|(name, entry)| {
+                let similarity = match &entry.embedding {
+                    Some(embedding) if embedding.is_current() => query.similarity(embedding),
+                    _ => query.similarity(&Embedding::of_command(&entry.command)),
+                };
+                SimilarCommand {
+                    name: name.clone(),
+                    description: entry.command.description.clone(),
+                    similarity,
+                }
+            })
+            .filter(|similar| similar.similarity >= min_similarity)
+            .max_by(|a, b| a.similarity.total_cmp(&b.similarity).then_with(|| b.name.cmp(&a.name)))
+    }
+
+    /// Stores a permission decision for a command, bound to the current
+    /// version of its scripts and, when accepted for the session, to the
+    /// current shell session.
+    pub async fn set_permission_decision(
+        &mut self,
+        name: &str,
+        decision: PermissionDecision,
+    ) -> Result<()> {
+        let Some(script_hash) = self.write_cache.get(name).map(|entry| self.script_version(entry)) else {
+            return Ok(());
+        };
+        let session = match (&decision.consent, &self.session) {
+            (PermissionConsent::AcceptForSession, Some(id)) => Some(SessionGrant {
+                id: id.clone(),
+                expires_at: self.time_provider.now() + SESSION_CONSENT_SECS,
+            }),
+            _ => None,
+        };
+        if let Some(entry) = self.write_cache.get_mut(name) {
+            entry.permission_decision = Some(PermissionDecision {
+                script_hash,
+                session,
+                ..decision
+            });
+            self.persist_write_cache().await?;
+            info!("Updated permission decision for command '{}'", name);
+        }
+        Ok(())
+    }
+
+    /// Retrieves the permission decision for a command.
+    ///
+    /// Returns `None` if the decision was made for another version of the
+    /// command's scripts (or before decisions recorded one): whatever the
+    /// user agreed to, they haven't seen the script that would run now.
+    ///
+    /// A revoked expired decision (see [`Self::set_grant_expiry`]) is `None`
+    /// too.
+    pub fn get_permission_decision(&self, name: &str) -> Option<&PermissionDecision> {
+        self.current_decision(name, self.write_cache.get(name)?)
+    }
+
+    /// The decision of `entry`, if it was made for its current scripts and
+    /// hasn't been revoked for going unused.
+    fn current_decision<'a>(&self, name: &str, entry: &'a CacheEntry) -> Option<&'a PermissionDecision> {
+        let decision = entry.permission_decision.as_ref()?;
+        let revoked = self.grant_expired(name, decision) && matches!(self.grant_expiry, Some((_, ExpiredGrants::Revoke)));
+        (decision.script_hash.is_some() && decision.script_hash == self.script_version(entry) && !revoked)
+            .then_some(decision)
+    }
+
+    /// Whether `decision` allowed `name` forever, but the command hasn't been
+    /// run since for longer than the grant expiry.
+    fn grant_expired(&self, name: &str, decision: &PermissionDecision) -> bool {
+        let Some((max_idle, _)) = self.grant_expiry else {
+            return false;
+        };
+        let last_used = self.usage(name).map_or(0, |usage| usage.last_used);
+        decision.consent == PermissionConsent::AcceptForever
+            && self.time_provider.now().saturating_sub(last_used.max(decision.decided_at)) > max_idle
+    }
+
+    /// Hash of the scripts of `entry`: the main script and every platform
+    /// variant. `None` if the main script can't be read.
+    fn script_version(&self, entry: &CacheEntry) -> Option<String> {
+        let mut scripts = self.read_file(&entry.command.script_file).ok()??;
+        for (os, variant) in &entry.command.variants {
+            let script = self.read_file(&variant.script_file).ok().flatten().unwrap_or_default();
+            scripts.push_str(&format!("\n// {} variant\n{}", os, script));
+        }
+        Some(script_hash(&scripts))
+    }
+
+    /// Returns the output processors set for a command, if it overrides the
+    /// global setting.
+    pub fn get_output_processors(&self, name: &str) -> Option<&[OutputProcessor]> {
+        self.write_cache.get(name)?.output_processors.as_deref()
+    }
+
+    /// Sets a command's output processors; `None` reverts to the global setting.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the command is not in the cache.
+    pub async fn set_output_processors(&mut self, name: &str, processors: Option<Vec<OutputProcessor>>) -> Result<bool> {
+        let Some(entry) = self.write_cache.get_mut(name) else {
+            return Ok(false);
+        };
+        entry.output_processors = processors;
+        self.persist_write_cache().await?;
+        info!("Updated output processors for command '{}'", name);
+        Ok(true)
+    }
+
+    /// Returns the arguments saved under `preset` for a command.
+    pub fn get_preset(&self, name: &str, preset: &str) -> Option<&[String]> {
+        self.write_cache.get(name)?.presets.get(preset).map(Vec::as_slice)
+    }
+
+    /// Returns the names of a command's presets, sorted.
+    pub fn preset_names(&self, name: &str) -> Vec<&str> {
+        self.write_cache
+            .get(name)
+            .map(|e| e.presets.keys().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Saves `args` as a preset of a command, replacing any preset with the
+    /// same name. Empty `args` remove the preset.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the command is not in the cache.
+    pub async fn save_preset(&mut self, name: &str, preset: &str, args: Vec<String>) -> Result<bool> {
+        let Some(entry) = self.write_cache.get_mut(name) else {
+            return Ok(false);
+        };
+        if args.is_empty() {
+            entry.presets.remove(preset);
+        } else {
+            entry.presets.insert(preset.to_string(), args);
+        }
+        self.persist_write_cache().await?;
+        info!("Updated preset '{}' of command '{}'", preset, name);
+        Ok(true)
+    }
+
+    /// Returns the cache directory (bioma) a command is stored in.
+    pub fn command_dir(&self, name: &str) -> Option<PathBuf> {
+        if self.write_cache.contains_key(name) {
+            return Some(self.write_cache_dir.clone());
+        }
+        self.path_resolver.find_command_dir(name).ok().flatten()
+    }
+
+    /// Returns who generated the current version of a command, if recorded.
+    pub fn get_owner(&self, name: &str) -> Option<&Owner> {
+        self.write_cache.get(name)?.owner.as_ref()
+    }
+
+    /// Returns the note attached to a command.
+    pub fn get_note(&self, name: &str) -> Option<&str> {
+        self.write_cache.get(name)?.note.as_deref()
+    }
+
+    /// Attaches a note to a command, replacing any earlier note. `None` (or
+    /// a blank note) removes it. Notes are kept when the command is regenerated.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the command is not in the cache.
+    pub async fn set_note(&mut self, name: &str, note: Option<String>) -> Result<bool> {
+        let Some(entry) = self.write_cache.get_mut(name) else {
+            return Ok(false);
+        };
+        entry.note = note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+        self.persist_write_cache().await?;
+        info!("Updated note of command '{}'", name);
+        Ok(true)
+    }
+
+    /// Returns the URL a command was imported from, if it was.
+    pub fn imported_from(&self, name: &str) -> Option<&str> {
+        self.write_cache.get(name)?.imported_from.as_deref()
+    }
+
+    /// Records that a command was imported from `url`. Until its permissions
+    /// are accepted forever, every run shows its script and asks for consent.
+    /// Storing a new version of the command forgets where it came from.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the command is not in the cache.
+    pub async fn set_imported_from(&mut self, name: &str, url: &str) -> Result<bool> {
+        let Some(entry) = self.write_cache.get_mut(name) else {
+            return Ok(false);
+        };
+        entry.imported_from = Some(url.to_string());
+        self.persist_write_cache().await?;
+        info!("Recorded that command '{}' was imported from {}", name, url);
+        Ok(true)
+    }
+
+    /// Returns the request a command was generated from: the recorded
+    /// intent, or the specification it was generated from with `--intent-file`.
+    pub fn get_intent(&self, name: &str) -> Option<&str> {
+        let entry = self.write_cache.get(name)?;
+        entry.intent.as_deref().or(entry.command.spec.as_deref())
+    }
+
+    /// Records the request a command was generated from. It is kept when
+    /// the command is regenerated.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the command is not in the cache.
+    pub async fn set_intent(&mut self, name: &str, intent: &str) -> Result<bool> {
+        let Some(entry) = self.write_cache.get_mut(name) else {
+            return Ok(false);
+        };
+        entry.intent = Some(intent.trim().to_string()).filter(|i| !i.is_empty());
+        self.persist_write_cache().await?;
+        info!("Recorded intent of command '{}'", name);
+        Ok(true)
+    }
+
+    /// Returns the feedback given on a command with `--nope`, oldest first.
+    pub fn feedback_history(&self, name: &str) -> &[FeedbackTurn] {
+        self.write_cache
+            .get(name)
+            .map(|e| e.feedback_history.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Appends a round of feedback to a command's transcript.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the command is not in the cache.
+    pub async fn record_feedback(&mut self, name: &str, turn: FeedbackTurn) -> Result<bool> {
+        let Some(entry) = self.write_cache.get_mut(name) else {
+            return Ok(false);
+        };
+        entry.feedback_history.push(turn);
+        self.persist_write_cache().await?;
+        info!("Recorded feedback for command '{}'", name);
+        Ok(true)
+    }
+
+    /// Rates the current revision of a command as good, replacing any earlier
+    /// rating. `environment` is the snapshot of the run being rated, later
+    /// compared against when the command runs (see [`crate::environment`]).
+    ///
+    /// # Returns
+    ///
+    /// The new rating, or `None` if the command is not in the cache.
+    pub async fn rate_command(
+        &mut self,
+        name: &str,
+        comment: Option<String>,
+        pin: bool,
+        environment: Option<EnvironmentSnapshot>,
+    ) -> Result<Option<Rating>> {
+        let rated_at = self.time_provider.now();
+        let Some(entry) = self.write_cache.get_mut(name) else {
+            return Ok(None);
+        };
+        let rating = Rating {
+            revision: entry.revisions.len() as u32 + 1,
+            comment,
+            pinned: pin,
+            rated_at,
+            environment,
+        };
+        entry.rating = Some(rating.clone());
+        self.persist_write_cache().await?;
+        info!("Rated revision {} of command '{}' (pinned: {})", rating.revision, name, pin);
+        Ok(Some(rating))
+    }
+
+    /// Returns the latest rating of a command.
+    pub fn get_rating(&self, name: &str) -> Option<&Rating> {
+        self.write_cache.get(name)?.rating.as_ref()
+    }
+
+    /// Returns the revision a command is pinned at, if any.
+    pub fn pinned_revision(&self, name: &str) -> Option<u32> {
+        self.get_rating(name).filter(|r| r.pinned).map(|r| r.revision)
+    }
+
+    /// Unpins a command, keeping its rating.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the command is not pinned.
+    pub async fn unpin(&mut self, name: &str) -> Result<bool> {
+        let Some(rating) = self.write_cache.get_mut(name).and_then(|e| e.rating.as_mut()) else {
+            return Ok(false);
+        };
+        if !rating.pinned {
+            return Ok(false);
+        }
+        rating.pinned = false;
+        self.persist_write_cache().await?;
+        info!("Unpinned command '{}'", name);
+        Ok(true)
+    }
+
+    /// Whether a command may be regenerated automatically.
+    ///
+    /// Automatic regeneration must leave alone pinned commands and commands
+    /// whose current revision was rated good.
+    pub fn allows_auto_heal(&self, name: &str) -> bool {
+        match self.get_rating(name) {
+            Some(rating) => !rating.pinned && Some(rating.revision) != self.current_revision(name),
+            None => true,
+        }
+    }
+
+    /// Returns the raw cache entry for a command as JSON.
+    ///
+    /// Used by diagnostics to capture the full metadata (usage statistics and
+    /// permission decision included) without exposing the internal entry type.
+    pub fn get_entry_json(&self, name: &str) -> Option<serde_json::Value> {
+        let entry = self.write_cache.get(name)?;
+        serde_json::to_value(entry).ok()
+    }
+
+    /// Checks if permission consent is needed for a command.
+    ///
+    /// Returns true if:
+    /// - No decision has been made yet, or it was made for another version
+    ///   of the command's scripts
+    /// - The previous decision was AcceptOnce
     /// - The previous decision was Denied (user might change their mind)
+    /// - The previous decision was AcceptForSession, for another shell
+    ///   session or long enough ago to have expired
+    /// - The previous decision was AcceptForever, but the command hasn't
+    ///   been run for longer than the grant expiry (see [`Self::set_grant_expiry`])
     pub fn needs_permission_consent(&self, name: &str) -> bool {
         match self.get_permission_decision(name) {
             None => true,
             Some(decision) => match decision.consent {
                 PermissionConsent::AcceptOnce => true,
-                PermissionConsent::AcceptForever => false,
+                PermissionConsent::AcceptForever => self.grant_expired(name, decision),
                 PermissionConsent::Denied => true,
+                PermissionConsent::AcceptForSession => !decision.session.as_ref().is_some_and(|grant| {
+                    Some(&grant.id) == self.session.as_ref() && self.time_provider.now() < grant.expires_at
+                }),
             },
         }
     }
@@ -400,10 +1783,12 @@ impl CommandCache {
     /// Removes a command and its script file from the cache.
     pub async fn remove_command(&mut self, name: &str) -> Result<bool> {
         if let Some(entry) = self.write_cache.remove(name) {
-            let script_path = self.write_cache_dir.join(&entry.command.script_file);
-            if script_path.exists() {
-                fs::remove_file(script_path)?;
+            let variant_files = entry.command.variants.values().map(|v| &v.script_file);
+            for script_file in std::iter::once(&entry.command.script_file).chain(variant_files) {
+                self.remove_path(script_file);
+                self.remove_path(&format!("{}/{}", PRISTINE_DIR, script_file));
             }
+            self.remove_path(&format!("revisions/{}", name));
             self.persist_write_cache().await?;
             info!("Removed command '{}' and its script file", name);
             Ok(true)
@@ -414,14 +1799,20 @@ impl CommandCache {
 
     /// Clears all commands from the cache.
     pub async fn clear_cache(&mut self) -> Result<()> {
-        for entry in self.write_cache.values() {
-            let script_path = self.write_cache_dir.join(&entry.command.script_file);
-            if script_path.exists() {
-                fs::remove_file(script_path).ok();
-            }
+        let script_files: Vec<String> = self
+            .write_cache
+            .values()
+            .flat_map(|entry| {
+                let variant_files = entry.command.variants.values().map(|v| v.script_file.clone());
+                std::iter::once(entry.command.script_file.clone()).chain(variant_files)
+            })
+            .collect();
+        for path in script_files.iter().map(String::as_str).chain(["revisions", PRISTINE_DIR]) {
+            self.remove_path(path);
         }
 
         self.write_cache.clear();
+        self.unreadable.clear();
         self.persist_write_cache().await?;
         info!("Cache cleared");
         Ok(())
@@ -437,7 +1828,7 @@ impl CommandCache {
                 (
                     name.clone(),
                     &entry.command,
-                    entry.permission_decision.as_ref(),
+                    self.current_decision(name, entry),
                 )
             })
             .collect()
@@ -447,13 +1838,18 @@ impl CommandCache {
     #[allow(dead_code)]
     pub async fn get_stats(&self) -> Result<String> {
         let total_commands = self.write_cache.len();
-        let total_usage: u32 = self.write_cache.values().map(|e| e.usage_count).sum();
+        let total_usage: u32 = self
+            .write_cache
+            .keys()
+            .filter_map(|name| self.usage(name))
+            .map(|u| u.count)
+            .sum();
         let accepted_forever = self
             .write_cache
-            .values()
-            .filter(|e| {
+            .iter()
+            .filter(|(name, e)| {
                 matches!(
-                    e.permission_decision.as_ref().map(|d| &d.consent),
+                    self.current_decision(name, e).map(|d| &d.consent),
                     Some(PermissionConsent::AcceptForever)
                 )
             })
@@ -543,6 +1939,16 @@ mod tests {
             description: format!("Test command: {}", name),
             script_file: format!("{}.ts", name),
             permissions: vec![],
+            sensitive_output: false,
+            output_format: crate::output_processing::OutputFormat::Text,
+            examples: vec![],
+            assumptions: vec![],
+            confidence: None,
+            platforms: vec![],
+            variants: BTreeMap::new(),
+            provenance: None,
+            spec: None,
+            extra: BTreeMap::new(),
         }
     }
 
@@ -638,10 +2044,64 @@ mod tests {
         cache.update_usage("hello").await.unwrap();
         cache.update_usage("hello").await.unwrap();
 
-        // Verify usage count is stored (check via the cache file)
+        let usage = cache.usage("hello").unwrap();
+        assert_eq!(usage, CommandUsage { count: 2, last_used: 1000 });
+        // Runs are appended to the usage log, leaving commands.json alone
         let cache_file = temp_dir.path().join("commands.json");
         let content = fs::read_to_string(&cache_file).unwrap();
+        assert!(content.contains("\"usage_count\": 0"));
+        let log = fs::read_to_string(temp_dir.path().join(USAGE_LOG)).unwrap();
+        assert_eq!(log.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_caches_keep_each_others_usage() {
+        let temp_dir = TempDir::new().unwrap();
+        let open = |time| {
+            CommandCache::with_providers(
+                Box::new(MockPathResolver::new(temp_dir.path().to_path_buf())),
+                Box::new(MockTimeProvider::new(time)),
+            )
+        };
+        let mut setup = open(1000).await.unwrap();
+        setup
+            .store_command("hello", &test_command("hello"), "console.log('Hello');")
+            .await
+            .unwrap();
+
+        let mut first = open(2000).await.unwrap();
+        let mut second = open(3000).await.unwrap();
+        first.update_usage("hello").await.unwrap();
+        second.update_usage("hello").await.unwrap();
+        second.update_usage("hello").await.unwrap();
+
+        let reopened = open(4000).await.unwrap();
+        assert_eq!(reopened.usage("hello").unwrap(), CommandUsage { count: 3, last_used: 3000 });
+    }
+
+    #[tokio::test]
+    async fn test_writing_metadata_folds_usage_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+        let cmd = test_command("hello");
+        cache.store_command("hello", &cmd, "console.log('Hello');").await.unwrap();
+        cache.update_usage("hello").await.unwrap();
+        cache.update_usage("hello").await.unwrap();
+
+        cache.save_preset("hello", "loud", vec!["--loud".to_string()]).await.unwrap();
+
+        let content = fs::read_to_string(temp_dir.path().join("commands.json")).unwrap();
         assert!(content.contains("\"usage_count\": 2"));
+        assert!(!temp_dir.path().join(USAGE_LOG).exists());
+        assert_eq!(cache.usage("hello").unwrap().count, 2);
+
+        // Regenerating a command starts its count over
+        cache.store_command("hello", &cmd, "console.log('Hi');").await.unwrap();
+        assert_eq!(cache.usage("hello").unwrap().count, 0);
     }
 
     #[tokio::test]
@@ -753,6 +2213,8 @@ mod tests {
             permissions: vec![],
             consent: PermissionConsent::AcceptOnce,
             decided_at: 1000,
+            script_hash: None,
+            session: None,
         };
         cache
             .set_permission_decision("hello", decision)
@@ -782,6 +2244,8 @@ mod tests {
             permissions: vec![],
             consent: PermissionConsent::AcceptForever,
             decided_at: 1000,
+            script_hash: None,
+            session: None,
         };
         cache
             .set_permission_decision("hello", decision)
@@ -792,7 +2256,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_needs_permission_consent_denied() {
+    async fn test_permission_decision_is_bound_to_the_script() {
         let temp_dir = TempDir::new().unwrap();
         let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
         let time = MockTimeProvider::new(1000);
@@ -806,23 +2270,35 @@ mod tests {
             .store_command("hello", &cmd, "console.log('Hello');")
             .await
             .unwrap();
-
-        let decision = PermissionDecision {
+        let forever = PermissionDecision {
             permissions: vec![],
-            consent: PermissionConsent::Denied,
+            consent: PermissionConsent::AcceptForever,
             decided_at: 1000,
+            script_hash: None,
+            session: None,
         };
-        cache
-            .set_permission_decision("hello", decision)
-            .await
-            .unwrap();
+        cache.set_permission_decision("hello", forever.clone()).await.unwrap();
+        assert!(cache.get_permission_decision("hello").unwrap().script_hash.is_some());
 
-        // Denied commands should ask again
+        // Editing the script by hand invalidates the decision
+        fs::write(temp_dir.path().join("hello.ts"), "Deno.removeSync('/');").unwrap();
+        assert!(cache.needs_permission_consent("hello"));
+        assert!(cache.list_commands().await[0].2.is_none());
+
+        // So does a new platform variant
+        cache.set_permission_decision("hello", forever.clone()).await.unwrap();
+        assert!(!cache.needs_permission_consent("hello"));
+        cache.store_variant("hello", "windows", &[], "console.log('Hi');").await.unwrap();
+        assert!(cache.needs_permission_consent("hello"));
+
+        // Decisions that predate script hashes must be made again
+        cache.set_permission_decision("hello", forever).await.unwrap();
+        cache.write_cache.get_mut("hello").unwrap().permission_decision.as_mut().unwrap().script_hash = None;
         assert!(cache.needs_permission_consent("hello"));
     }
 
     #[tokio::test]
-    async fn test_list_commands() {
+    async fn test_session_consent_is_limited_to_the_session() {
         let temp_dir = TempDir::new().unwrap();
         let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
         let time = MockTimeProvider::new(1000);
@@ -830,24 +2306,841 @@ mod tests {
         let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
             .await
             .unwrap();
+        cache.set_session(Some("shell-1".to_string()));
 
+        let cmd = test_command("hello");
         cache
-            .store_command("cmd1", &test_command("cmd1"), "script1")
+            .store_command("hello", &cmd, "console.log('Hello');")
             .await
             .unwrap();
-        cache
-            .store_command("cmd2", &test_command("cmd2"), "script2")
+        let decision = PermissionDecision {
+            permissions: vec![],
+            consent: PermissionConsent::AcceptForSession,
+            decided_at: 1000,
+            script_hash: None,
+            session: None,
+        };
+        cache.set_permission_decision("hello", decision).await.unwrap();
+
+        assert_eq!(
+            cache.get_permission_decision("hello").unwrap().session,
+            Some(SessionGrant {
+                id: "shell-1".to_string(),
+                expires_at: 1000 + SESSION_CONSENT_SECS,
+            })
+        );
+        assert!(!cache.needs_permission_consent("hello"));
+
+        cache.set_session(Some("shell-2".to_string()));
+        assert!(cache.needs_permission_consent("hello"));
+
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let expired = MockTimeProvider::new(1000 + SESSION_CONSENT_SECS);
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(expired))
             .await
             .unwrap();
+        cache.set_session(Some("shell-1".to_string()));
+        assert!(cache.needs_permission_consent("hello"));
+        assert_eq!(shell_session(|name| (name == SESSION_ENV).then(|| "tmux-3".to_string())).as_deref(), Some("tmux-3"));
+    }
 
-        let commands = cache.list_commands().await;
-        assert_eq!(commands.len(), 2);
+    #[tokio::test]
+    async fn test_names_reaching_outside_the_bioma_are_refused() {
+        let home = TempDir::new().unwrap();
+        let bioma = home.path().join("bioma");
+        let mut cache = CommandCache::with_providers(Box::new(MockPathResolver::new(bioma.clone())), Box::new(MockTimeProvider::new(1000)))
+            .await
+            .unwrap();
+
+        let long = "a".repeat(MAX_NAME_LEN + 1);
+        for name in ["../escape", "..", "a/b", "/tmp/evil", "a\\b", "", ".hidden", "-rf", "cd\u{202e}", "ｃｏｎｆｉｇ", "zero\u{200b}width", &long] {
+            assert!(cache.store_command(name, &test_command(name), "console.log('x')").await.is_err(), "{:?} was stored", name);
+        }
+        cache.store_command("git_tidy-2", &test_command("git_tidy-2"), "console.log('x')").await.unwrap();
+        assert!(cache.store_variant("git_tidy-2", "../../escape", &[], "console.log('x')").await.is_err());
+
+        assert_eq!(cache.list_cached_commands().await, vec!["git_tidy-2"]);
+        let outside: Vec<_> = fs::read_dir(home.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(outside, vec!["bioma"]);
+    }
+
+    #[test]
+    fn test_suggested_names_are_normalized_to_kebab_case() {
+        assert_eq!(normalize_command_name("Git Status").unwrap(), "git-status");
+        assert_eq!(normalize_command_name("gitStatus").unwrap(), "git-status");
+        assert_eq!(normalize_command_name("  git__status!  ").unwrap(), "git-status");
+        assert_eq!(normalize_command_name("../../bin/sh").unwrap(), "bin-sh");
+        assert_eq!(normalize_command_name("ip2Location").unwrap(), "ip2-location");
+        assert!(normalize_command_name("cаt").is_err()); // Cyrillic 'а'
+        assert!(normalize_command_name("rm\u{200b}").is_err());
+        assert!(normalize_command_name("../..").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unused_forever_grants_expire() {
+        let temp_dir = TempDir::new().unwrap();
+        let day = 24 * 60 * 60;
+        let mut cache = CommandCache::with_providers(
+            Box::new(MockPathResolver::new(temp_dir.path().to_path_buf())),
+            Box::new(MockTimeProvider::new(1000)),
+        )
+        .await
+        .unwrap();
+        cache.store_command("hello", &test_command("hello"), "console.log('Hello');").await.unwrap();
+        let decision = PermissionDecision {
+            permissions: vec![],
+            consent: PermissionConsent::AcceptForever,
+            decided_at: 1000,
+            script_hash: None,
+            session: None,
+        };
+        cache.set_permission_decision("hello", decision).await.unwrap();
+        cache.update_usage("hello").await.unwrap();
+
+        // A month later, the grant still applies unless it expires in less
+        let reopen = |expiry_days, expired| {
+            let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+            async move {
+                let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(MockTimeProvider::new(1000 + 30 * day)))
+                    .await
+                    .unwrap();
+                cache.set_grant_expiry(expiry_days, expired);
+                cache
+            }
+        };
+        let cache = reopen(0, ExpiredGrants::AskOnce).await;
+        assert!(!cache.needs_permission_consent("hello"));
+        let cache = reopen(60, ExpiredGrants::AskOnce).await;
+        assert!(!cache.needs_permission_consent("hello"));
+
+        let cache = reopen(20, ExpiredGrants::AskOnce).await;
+        assert!(cache.needs_permission_consent("hello"));
+        assert!(cache.get_permission_decision("hello").is_some());
+
+        let cache = reopen(20, ExpiredGrants::Revoke).await;
+        assert!(cache.needs_permission_consent("hello"));
+        assert!(cache.get_permission_decision("hello").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_modified_scripts_are_reported_until_accepted() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        let cmd = test_command("hello");
+        cache
+            .store_command("hello", &cmd, "console.log('Hello');")
+            .await
+            .unwrap();
+        assert!(cache.modified_scripts("hello").is_empty());
+
+        fs::write(temp_dir.path().join("hello.ts"), "console.log('Pwned');").unwrap();
+        assert_eq!(
+            cache.modified_scripts("hello"),
+            vec![ModifiedScript {
+                script_file: "hello.ts".to_string(),
+                original: Some("console.log('Hello');".to_string()),
+                current: "console.log('Pwned');".to_string(),
+            }]
+        );
+
+        cache.accept_modified_scripts("hello").await.unwrap();
+        assert!(cache.modified_scripts("hello").is_empty());
+
+        cache.remove_command("hello").await.unwrap();
+        assert!(!temp_dir.path().join(PRISTINE_DIR).join("hello.ts").exists());
+    }
+
+    #[tokio::test]
+    async fn test_needs_permission_consent_denied() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        let cmd = test_command("hello");
+        cache
+            .store_command("hello", &cmd, "console.log('Hello');")
+            .await
+            .unwrap();
+
+        let decision = PermissionDecision {
+            permissions: vec![],
+            consent: PermissionConsent::Denied,
+            decided_at: 1000,
+            script_hash: None,
+            session: None,
+        };
+        cache
+            .set_permission_decision("hello", decision)
+            .await
+            .unwrap();
+
+        // Denied commands should ask again
+        assert!(cache.needs_permission_consent("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_list_commands() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("cmd1", &test_command("cmd1"), "script1")
+            .await
+            .unwrap();
+        cache
+            .store_command("cmd2", &test_command("cmd2"), "script2")
+            .await
+            .unwrap();
+
+        let commands = cache.list_commands().await;
+        assert_eq!(commands.len(), 2);
 
         let names: Vec<_> = commands.iter().map(|(n, _, _)| n.as_str()).collect();
         assert!(names.contains(&"cmd1"));
         assert!(names.contains(&"cmd2"));
     }
 
+    #[tokio::test]
+    async fn test_get_test_script() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        assert!(cache.get_test_script("hello").unwrap().is_none());
+
+        fs::write(temp_dir.path().join("hello.test.ts"), "Deno.test('x', () => {});").unwrap();
+        assert_eq!(
+            cache.get_test_script("hello").unwrap(),
+            Some("Deno.test('x', () => {});".to_string())
+        );
+    }
+
+    // =========================================================================
+    // Output processor tests
+    // =========================================================================
+
+    #[tokio::test]
+    async fn test_output_processors_survive_regeneration() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("weather", &test_command("weather"), "v1")
+            .await
+            .unwrap();
+        assert!(cache.get_output_processors("weather").is_none());
+
+        let set = cache
+            .set_output_processors("weather", Some(vec![OutputProcessor::Json]))
+            .await
+            .unwrap();
+        cache
+            .store_command("weather", &test_command("weather"), "v2")
+            .await
+            .unwrap();
+
+        assert!(set);
+        assert_eq!(cache.get_output_processors("weather"), Some(&[OutputProcessor::Json][..]));
+        assert!(!cache.set_output_processors("missing", None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_presets_are_saved_and_removed() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("deploy", &test_command("deploy"), "v1")
+            .await
+            .unwrap();
+        let args = vec!["prod".to_string(), "--region".to_string(), "eu-west-1".to_string()];
+        assert!(cache.save_preset("deploy", "prod", args.clone()).await.unwrap());
+        assert!(cache.save_preset("deploy", "dev", vec!["dev".to_string()]).await.unwrap());
+        cache
+            .store_command("deploy", &test_command("deploy"), "v2")
+            .await
+            .unwrap();
+
+        assert_eq!(cache.get_preset("deploy", "prod"), Some(&args[..]));
+        assert_eq!(cache.preset_names("deploy"), vec!["dev", "prod"]);
+
+        assert!(cache.save_preset("deploy", "dev", vec![]).await.unwrap());
+        assert_eq!(cache.preset_names("deploy"), vec!["prod"]);
+        assert!(!cache.save_preset("missing", "prod", args).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_notes_and_owner_are_recorded() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("deploy", &test_command("deploy"), "v1")
+            .await
+            .unwrap();
+        assert_eq!(cache.get_owner("deploy"), Some(&Owner::current()));
+        assert_eq!(cache.get_note("deploy"), None);
+
+        assert!(cache.set_note("deploy", Some(" Ships the docs site ".to_string())).await.unwrap());
+        cache
+            .store_command("deploy", &test_command("deploy"), "v2")
+            .await
+            .unwrap();
+        assert_eq!(cache.get_note("deploy"), Some("Ships the docs site"));
+
+        assert!(cache.set_note("deploy", Some("  ".to_string())).await.unwrap());
+        assert_eq!(cache.get_note("deploy"), None);
+        assert!(!cache.set_note("missing", None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_intent_is_kept_across_regenerations() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(MockTimeProvider::new(1000)))
+            .await
+            .unwrap();
+
+        cache.store_command("weather", &test_command("weather"), "v1").await.unwrap();
+        assert_eq!(cache.get_intent("weather"), None);
+        assert!(cache.set_intent("weather", "weather London").await.unwrap());
+        cache.store_command("weather", &test_command("weather"), "v2").await.unwrap();
+        assert_eq!(cache.get_intent("weather"), Some("weather London"));
+        assert!(!cache.set_intent("missing", "anything").await.unwrap());
+
+        let spec = GeneratedCommand {
+            spec: Some("# Report\nSummarize the week".to_string()),
+            ..test_command("report")
+        };
+        cache.store_command("report", &spec, "v1").await.unwrap();
+        assert_eq!(cache.get_intent("report"), Some("# Report\nSummarize the week"));
+    }
+
+    #[test]
+    fn test_owner_display() {
+        let owner = Owner {
+            created_by: Some("ada".to_string()),
+            host: Some("build-box".to_string()),
+        };
+
+        assert_eq!(owner.to_string(), "ada on build-box");
+        assert_eq!(Owner::default().to_string(), "unknown user");
+    }
+
+    #[tokio::test]
+    async fn test_feedback_history_survives_regeneration() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("password", &test_command("password"), "v1")
+            .await
+            .unwrap();
+        let turn = FeedbackTurn {
+            revision: 1,
+            feedback: "no ambiguous characters".to_string(),
+            stderr: None,
+            created_at: 1000,
+        };
+        assert!(cache.record_feedback("password", turn.clone()).await.unwrap());
+        cache
+            .store_command("password", &test_command("password"), "v2")
+            .await
+            .unwrap();
+
+        assert_eq!(cache.feedback_history("password").to_vec(), vec![turn.clone()]);
+        assert!(cache.feedback_history("missing").is_empty());
+        assert!(!cache.record_feedback("missing", turn).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_platform_variants_survive_regeneration_and_removal() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("open-url", &test_command("open-url"), "mac version")
+            .await
+            .unwrap();
+        assert!(cache.store_variant("open-url", "linux", &[], "linux version").await.unwrap());
+        assert!(!cache.store_variant("missing", "linux", &[], "x").await.unwrap());
+
+        let command = cache.get_command("open-url").await.unwrap().unwrap();
+        assert_eq!(cache.get_script_content(&command).unwrap(), "mac version");
+        assert_eq!(cache.get_script_content(&command.for_platform("linux")).unwrap(), "linux version");
+
+        // Regenerating the main script keeps the variant
+        cache
+            .store_command("open-url", &test_command("open-url"), "mac version 2")
+            .await
+            .unwrap();
+        let command = cache.get_command("open-url").await.unwrap().unwrap();
+        assert_eq!(command.variants["linux"].script_file, "open-url.linux.ts");
+
+        assert!(cache.remove_command("open-url").await.unwrap());
+        assert!(!temp_dir.path().join("open-url.linux.ts").exists());
+    }
+
+    #[tokio::test]
+    async fn test_rating_excludes_current_revision_from_auto_heal() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("weather", &test_command("weather"), "v1")
+            .await
+            .unwrap();
+        assert!(cache.allows_auto_heal("weather"));
+
+        let rating = cache
+            .rate_command("weather", Some("perfect".to_string()), false, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((rating.revision, rating.rated_at), (1, 1000));
+        assert!(!cache.allows_auto_heal("weather"));
+
+        // A new revision hasn't been rated yet
+        cache
+            .store_command("weather", &test_command("weather"), "v2")
+            .await
+            .unwrap();
+        assert_eq!(cache.get_rating("weather"), Some(&rating));
+        assert!(cache.allows_auto_heal("weather"));
+        assert!(cache.rate_command("missing", None, false, None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pin_and_unpin() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("weather", &test_command("weather"), "v1")
+            .await
+            .unwrap();
+        cache.rate_command("weather", None, true, None).await.unwrap();
+
+        assert_eq!(cache.pinned_revision("weather"), Some(1));
+        assert!(!cache.allows_auto_heal("weather"));
+
+        assert!(cache.unpin("weather").await.unwrap());
+        assert_eq!(cache.pinned_revision("weather"), None);
+        assert!(cache.get_rating("weather").is_some());
+        assert!(!cache.unpin("weather").await.unwrap());
+    }
+
+    // =========================================================================
+    // Revision history tests
+    // =========================================================================
+
+    #[tokio::test]
+    async fn test_new_command_has_no_revisions() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("hello", &test_command("hello"), "v1")
+            .await
+            .unwrap();
+
+        assert!(cache.list_revisions("hello").is_empty());
+        assert_eq!(cache.current_revision("hello"), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_store_command_archives_previous_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("hello", &test_command("hello"), "v1")
+            .await
+            .unwrap();
+        cache
+            .store_command("hello", &test_command("hello"), "v2")
+            .await
+            .unwrap();
+        cache
+            .store_command("hello", &test_command("hello"), "v3")
+            .await
+            .unwrap();
+
+        assert_eq!(cache.list_revisions("hello").len(), 2);
+        assert_eq!(cache.current_revision("hello"), Some(3));
+        assert_eq!(cache.get_revision("hello", 1).unwrap().script, "v1");
+        assert_eq!(cache.get_revision("hello", 2).unwrap().script, "v2");
+        assert_eq!(cache.get_revision("hello", 3).unwrap().script, "v3");
+    }
+
+    #[tokio::test]
+    async fn test_get_revision_unknown_revision_errors() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("hello", &test_command("hello"), "v1")
+            .await
+            .unwrap();
+
+        assert!(cache.get_revision("hello", 5).is_err());
+        assert!(cache.get_revision("missing", 1).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remove_command_removes_revisions() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+
+        cache
+            .store_command("hello", &test_command("hello"), "v1")
+            .await
+            .unwrap();
+        cache
+            .store_command("hello", &test_command("hello"), "v2")
+            .await
+            .unwrap();
+
+        cache.remove_command("hello").await.unwrap();
+
+        assert!(!temp_dir.path().join("revisions").join("hello").exists());
+    }
+
+    #[tokio::test]
+    async fn test_ephemeral_cache_writes_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut disk = CommandCache::with_providers(
+            Box::new(MockPathResolver::new(temp_dir.path().to_path_buf())),
+            Box::new(MockTimeProvider::new(1000)),
+        )
+        .await
+        .unwrap();
+        disk.store_command("old", &test_command("old"), "v1").await.unwrap();
+        let before = fs::read_to_string(temp_dir.path().join("commands.json")).unwrap();
+
+        let mut cache = CommandCache::ephemeral(
+            Box::new(MockPathResolver::new(temp_dir.path().to_path_buf())),
+            Box::new(MockTimeProvider::new(2000)),
+        )
+        .await
+        .unwrap();
+        cache.update_usage("old").await.unwrap();
+        cache.store_command("old", &test_command("old"), "v2").await.unwrap();
+        cache.store_command("hello", &test_command("hello"), "v1").await.unwrap();
+
+        assert!(cache.is_in_memory());
+        assert_eq!(cache.get_script_content(&test_command("old")).unwrap(), "v2");
+        assert_eq!(cache.get_revision("old", 1).unwrap().script, "v1");
+        assert!(cache.modified_scripts("hello").is_empty());
+        assert_eq!(fs::read_to_string(temp_dir.path().join("commands.json")).unwrap(), before);
+        assert_eq!(fs::read_to_string(temp_dir.path().join("old.ts")).unwrap(), "v1");
+        assert!(!temp_dir.path().join("hello.ts").exists());
+        assert!(!temp_dir.path().join(USAGE_LOG).exists());
+
+        cache.remove_command("hello").await.unwrap();
+        assert!(cache.get_script_content(&test_command("hello")).is_err());
+    }
+
+    /// `commands.json` as written by each schema version of ergo, oldest
+    /// first, then by a version newer than this one.
+    const SCHEMA_FIXTURES: [(&str, &str); 4] = [
+        ("v1", include_str!("../tests/fixtures/commands_json/v1.json")),
+        ("v2", include_str!("../tests/fixtures/commands_json/v2.json")),
+        ("v3", include_str!("../tests/fixtures/commands_json/v3.json")),
+        ("newer", include_str!("../tests/fixtures/commands_json/newer.json")),
+    ];
+
+    async fn cache_from_fixture(fixture: &str) -> (TempDir, CommandCache) {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("commands.json"), fixture).unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let cache = CommandCache::with_providers(Box::new(resolver), Box::new(MockTimeProvider::new(1900000000)))
+            .await
+            .unwrap();
+        (temp_dir, cache)
+    }
+
+    #[tokio::test]
+    async fn test_reads_commands_json_of_every_schema_version() {
+        for (version, fixture) in SCHEMA_FIXTURES {
+            let (_temp_dir, cache) = cache_from_fixture(fixture).await;
+
+            let hello = cache.get_command("hello").await.unwrap().unwrap_or_else(|| panic!("{}: no hello", version));
+            assert_eq!(hello.description, "Says hello", "{}", version);
+            assert_eq!(cache.usage("hello").unwrap().count, 3, "{}", version);
+        }
+
+        let (_temp_dir, v2) = cache_from_fixture(SCHEMA_FIXTURES[1].1).await;
+        assert_eq!(v2.current_revision("hello"), Some(2));
+        assert_eq!(v2.get_preset("hello", "formal"), Some(&["--formal".to_string()][..]));
+        assert_eq!(v2.get_note("hello"), Some("Used in the onboarding docs"));
+        // Decided before decisions were bound to the script, so asked again
+        assert!(v2.get_permission_decision("hello").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_saving_keeps_what_a_newer_ergo_wrote() {
+        let (temp_dir, mut cache) = cache_from_fixture(SCHEMA_FIXTURES[3].1).await;
+
+        assert!(cache.get_command("deploy").await.unwrap().is_none());
+        assert!(cache.set_note("hello", Some("still works".to_string())).await.unwrap());
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(temp_dir.path().join("commands.json")).unwrap()).unwrap();
+        let newer: serde_json::Value = serde_json::from_str(SCHEMA_FIXTURES[3].1).unwrap();
+        assert_eq!(saved["deploy"], newer["deploy"]);
+        assert_eq!(saved["hello"]["command"]["tags"], serde_json::json!(["greeting", "demo"]));
+        assert_eq!(saved["hello"]["command"]["input_schema"], newer["hello"]["command"]["input_schema"]);
+        assert_eq!(saved["hello"]["schedule"], newer["hello"]["schedule"]);
+        assert_eq!(saved["hello"]["note"], "still works");
+
+        cache.store_command("deploy", &test_command("deploy"), "v1").await.unwrap();
+        assert!(cache.get_command("deploy").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unwritable_write_dir_falls_back_to_memory() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("home"), "not a directory").unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().join("home").join(".abiogenesis"));
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(MockTimeProvider::new(1000)))
+            .await
+            .unwrap();
+        cache.store_command("hello", &test_command("hello"), "v1").await.unwrap();
+        cache.update_usage("hello").await.unwrap();
+
+        assert!(cache.is_in_memory());
+        assert_eq!(cache.get_script_content(&test_command("hello")).unwrap(), "v1");
+        assert_eq!(cache.usage("hello").unwrap().count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_missing_scripts_lists_dangling_references() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+        cache.store_command("hello", &test_command("hello"), "console.log('Hello');").await.unwrap();
+        cache.store_command("bye", &test_command("bye"), "console.log('Bye');").await.unwrap();
+        assert!(missing_scripts(temp_dir.path()).unwrap().is_empty());
+
+        fs::remove_file(temp_dir.path().join("bye.ts")).unwrap();
+        assert_eq!(missing_scripts(temp_dir.path()).unwrap(), vec!["bye.ts"]);
+
+        fs::write(temp_dir.path().join("commands.json"), "{not json").unwrap();
+        assert!(missing_scripts(temp_dir.path()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_find_similar_picks_the_closest_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let resolver = MockPathResolver::new(temp_dir.path().to_path_buf());
+        let time = MockTimeProvider::new(1000);
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(time))
+            .await
+            .unwrap();
+        let command = |name: &str, description: &str| GeneratedCommand {
+            description: description.to_string(),
+            ..test_command(name)
+        };
+        cache
+            .store_command("format-json", &command("format-json", "Formats JSON input with indentation"), "")
+            .await
+            .unwrap();
+        cache
+            .store_command("weather", &command("weather", "Shows the current weather for a city"), "")
+            .await
+            .unwrap();
+
+        let similar = cache.find_similar("pretty print json", 0.3).unwrap();
+        assert_eq!(similar.name, "format-json");
+        assert!(cache.find_similar("list open ports", 0.3).is_none());
+
+        // Entries cached before embeddings existed are still found
+        cache.write_cache.get_mut("weather").unwrap().embedding = None;
+        assert_eq!(cache.find_similar("what's the weather like", 0.3).unwrap().name, "weather");
+    }
+
+    #[test]
+    fn test_cache_layout_paths() {
+        let layout = CacheConfig {
+            dir_name: ".acme".to_string(),
+            bioma_dir: "ergo/commands".to_string(),
+            ..CacheConfig::default()
+        };
+
+        assert_eq!(layout.cache_dir(Path::new("/work/app")), PathBuf::from("/work/app/.acme/ergo/commands"));
+        assert_eq!(layout.base_dir(Path::new("/work/app/.acme/ergo/commands")), Some(PathBuf::from("/work/app")));
+        assert_eq!(layout.base_dir(Path::new("/work/app/.abiogenesis/biomas")), None);
+
+        let flat = CacheConfig {
+            bioma_dir: ".".to_string(),
+            ..CacheConfig::default()
+        };
+        assert_eq!(flat.cache_dir(Path::new("/work/app")), PathBuf::from("/work/app/.abiogenesis"));
+        assert_eq!(flat.base_dir(Path::new("/work/app/.abiogenesis")), Some(PathBuf::from("/work/app")));
+    }
+
+    #[test]
+    fn test_cache_layout_validation() {
+        let layout = |dir_name: &str, bioma_dir: &str| CacheConfig {
+            dir_name: dir_name.to_string(),
+            bioma_dir: bioma_dir.to_string(),
+            ..CacheConfig::default()
+        };
+
+        assert!(CacheConfig::default().validate().is_ok());
+        assert!(layout(".acme", "ergo/commands").validate().is_ok());
+        assert!(layout(".acme/nested", "biomas").validate().is_err());
+        assert!(layout("..", "biomas").validate().is_err());
+        assert!(layout(".acme", "../outside").validate().is_err());
+        assert!(layout(".acme", "/etc").validate().is_err());
+        assert!(layout(".acme", "").validate().is_err());
+    }
+
+    #[test]
+    fn test_search_stops_at_boundaries() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let home = root.join("home");
+        let repo = root.join("mnt/nfs/repo");
+        let start = repo.join("src/app");
+        for dir in [root, &root.join("mnt/nfs"), &repo, &start] {
+            fs::create_dir_all(dir.join(".abiogenesis")).unwrap();
+        }
+        let resolver = |layout: CacheConfig| HierarchyPathResolver::with_layout(layout).search(&start, Some(&home));
+        let bioma = |dir: &Path| dir.join(".abiogenesis").join("biomas");
+
+        let all = resolver(CacheConfig::default());
+        assert_eq!(&all[..4], &[bioma(&start), bioma(&repo), bioma(&root.join("mnt/nfs")), bioma(root)]);
+
+        fs::create_dir(repo.join(".git")).unwrap();
+        let repo_only = resolver(CacheConfig {
+            stop_at_repo_root: true,
+            ..CacheConfig::default()
+        });
+        assert_eq!(repo_only, vec![bioma(&start), bioma(&repo), bioma(&home)]);
+
+        let mount = resolver(CacheConfig {
+            stop_at: vec![format!("{}/mnt/*", root.display())],
+            ..CacheConfig::default()
+        });
+        assert_eq!(mount, vec![bioma(&start), bioma(&repo), bioma(&home)]);
+
+        fs::write(start.join(".abiogenesisignore"), "").unwrap();
+        assert_eq!(resolver(CacheConfig::default()), vec![bioma(&start), bioma(&home)]);
+    }
+
+    #[test]
+    fn test_search_respects_max_depth() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let start = root.join("a/b");
+        for dir in [root, &root.join("a"), &start] {
+            fs::create_dir_all(dir.join(".abiogenesis")).unwrap();
+        }
+        let bioma = |dir: &Path| dir.join(".abiogenesis").join("biomas");
+        let search = |max_search_depth| {
+            HierarchyPathResolver::with_layout(CacheConfig {
+                max_search_depth,
+                ..CacheConfig::default()
+            })
+            .search(&start, None)
+        };
+
+        assert_eq!(search(Some(0)), vec![bioma(&start)]);
+        assert_eq!(search(Some(1)), vec![bioma(&start), bioma(&root.join("a"))]);
+        assert_eq!(&search(None)[..3], &[bioma(&start), bioma(&root.join("a")), bioma(root)]);
+    }
+
+    #[test]
+    fn test_name_matches() {
+        assert!(name_matches("nfs", "*"));
+        assert!(name_matches("nfs-share", "nfs*"));
+        assert!(name_matches("my-nfs-share", "*nfs*"));
+        assert!(name_matches("a", "a"));
+        assert!(!name_matches("ab", "a"));
+        assert!(!name_matches("aba", "ab*ba"));
+    }
+
     // =========================================================================
     // Time provider tests
     // =========================================================================
@@ -26,21 +26,169 @@
 //! router will generate a command based on this description and suggest a name.
 
 use crate::{
-    command_cache::{CommandCache, PermissionConsent},
+    cancellation::{Cancellation, GenerationTimedOut},
+    chain::Chain,
+    command_cache::{self, CacheConfig, CommandCache, FeedbackTurn, PermissionConsent},
+    deno_deps::{self, DepsConfig},
+    error::ErgoError,
     execution_context::ExecutionContext,
-    executor::Executor,
-    llm_generator::{CommandGenerator, LlmGenerator},
+    executor::{BatchJob, ExecutionResult, Executor},
+    json_filter::JsonFilter,
+    llm_generator::{CommandGenerator, FeedbackRequest, GeneratedCommand, GenerationResult, PermissionRequest, PlanRequest},
     permission_ui::PermissionUI,
+    progress::{ConsoleProgress, ProgressSink, RouterEvent},
+    project_scope,
+    reserved,
+    security_review::{self, SafetyConfig},
+    semantic_search::SemanticSearchConfig,
+    providers::{SystemTimeProvider, TimeProvider},
+    script_diff,
+    secrets::SecretDelivery,
+    trust::{TrustConfig, TrustLevel},
+    validation::{self, FailureAction, SafetyValidator, ValidationConfig, ValidationPipeline},
+    workflow::{self, Workflow},
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{info, warn};
-use which::which;
+
+/// What happened when an intent was processed.
+#[derive(Debug)]
+pub enum IntentOutcome {
+    /// The command exists in PATH and was run directly.
+    SystemExecuted {
+        /// Name of the system command.
+        command_name: String,
+    },
+    /// A previously generated command was run from the cache.
+    CacheHit {
+        /// The cached command.
+        command: GeneratedCommand,
+        /// Result of running it.
+        result: ExecutionResult,
+    },
+    /// A new command was generated and run.
+    Generated {
+        /// The generated command.
+        command: GeneratedCommand,
+        /// Result of running it.
+        result: ExecutionResult,
+    },
+    /// A command was regenerated from corrective feedback and run.
+    Regenerated {
+        /// The regenerated command.
+        command: GeneratedCommand,
+        /// Result of running it.
+        result: ExecutionResult,
+    },
+    /// The user declined the command's permissions, so it was not run.
+    PermissionDenied {
+        /// The declined command.
+        command: GeneratedCommand,
+    },
+}
+
+impl IntentOutcome {
+    /// Returns the result of the generated command that ran, if any.
+    pub fn execution(&self) -> Option<&ExecutionResult> {
+        match self {
+            IntentOutcome::CacheHit { result, .. }
+            | IntentOutcome::Generated { result, .. }
+            | IntentOutcome::Regenerated { result, .. } => Some(result),
+            IntentOutcome::SystemExecuted { .. } | IntentOutcome::PermissionDenied { .. } => None,
+        }
+    }
+
+    /// Returns whether the intent ran and succeeded.
+    pub fn succeeded(&self) -> bool {
+        match self {
+            IntentOutcome::SystemExecuted { .. } => true,
+            IntentOutcome::PermissionDenied { .. } => false,
+            _ => self.execution().is_some_and(|result| result.success),
+        }
+    }
+}
+
+/// Outcome of [`CommandRouter::prepare_intent`].
+#[derive(Debug)]
+pub enum PrepareOutcome {
+    /// The command was generated and cached.
+    Generated {
+        /// The generated command.
+        command: GeneratedCommand,
+    },
+    /// The command was already cached, so nothing was generated.
+    AlreadyCached {
+        /// The cached command.
+        command: GeneratedCommand,
+    },
+}
+
+/// Outcome of one step of a chain run by [`CommandRouter::process_chain`].
+#[derive(Debug)]
+pub struct ChainOutcome {
+    /// The step's command line.
+    pub command_line: String,
+    /// What happened, or `None` if the step was skipped.
+    pub outcome: Option<Result<IntentOutcome, ErgoError>>,
+}
+
+impl ChainOutcome {
+    /// Returns whether the step ran and succeeded.
+    pub fn succeeded(&self) -> bool {
+        matches!(&self.outcome, Some(Ok(outcome)) if outcome.succeeded())
+    }
+}
+
+/// Outcome of one intent in [`CommandRouter::process_many`].
+#[derive(Debug)]
+pub struct BatchOutcome {
+    /// The command name of the intent.
+    pub command_name: String,
+    /// What happened, or why the intent couldn't be run.
+    pub outcome: Result<IntentOutcome, ErgoError>,
+}
+
+/// Where the description of a conversational intent came from.
+#[derive(Clone, Copy)]
+enum Origin<'a> {
+    /// The user's request, recorded as the command's intent.
+    Intent(&'a str),
+    /// A specification, kept as the command's spec.
+    Spec,
+}
+
+/// Default number of commands [`CommandRouter::process_many`] runs at once.
+pub const DEFAULT_PARALLELISM: usize = 4;
+
+/// How many times the model is asked to rename a command it gave a reserved
+/// name (see [`reserved`]).
+const MAX_RENAMES: usize = 2;
+
+/// Refuses to generate a command the user named with an invalid or
+/// reserved name.
+fn check_command_name(command_name: &str) -> Result<(), ErgoError> {
+    command_cache::validate_command_name(command_name).map_err(ErgoError::Other)?;
+    match reserved::reason(command_name) {
+        Some(reason) => Err(ErgoError::Other(anyhow!(
+            "'{}' is {}, so it can't be a command's name; pick another one",
+            command_name,
+            reason
+        ))),
+        None => Ok(()),
+    }
+}
 
 /// Routes user intents to appropriate command handlers.
 ///
 /// The router is the main orchestrator that coordinates between:
 /// - Command cache for persistent storage
-/// - LLM generator for creating new commands
+/// - Command generator (Claude or a plugin) for creating new commands
 /// - Executor for running commands
 /// - Permission UI for user consent
 ///
@@ -60,10 +208,23 @@ use which::which;
 /// ```
 pub struct CommandRouter {
     cache: CommandCache,
-    generator: LlmGenerator,
+    generator: Box<dyn CommandGenerator>,
     executor: Executor,
     permission_ui: PermissionUI,
-    verbose: bool,
+    cancellation: Cancellation,
+    deps: DepsConfig,
+    safety: SafetyConfig,
+    safety_override: bool,
+    cache_layout: CacheConfig,
+    trust: TrustConfig,
+    generation_timeout: Duration,
+    generation_retries: u32,
+    backend: String,
+    model_chosen: bool,
+    plan_first: bool,
+    semantic_search: SemanticSearchConfig,
+    validation: ValidationPipeline,
+    progress: Arc<dyn ProgressSink>,
 }
 
 impl CommandRouter {
@@ -78,17 +239,178 @@ impl CommandRouter {
     ///
     /// # Errors
     ///
-    /// Returns an error if the command cache cannot be initialized.
-    pub async fn new(verbose: bool) -> Result<Self> {
+    /// Returns [`ErgoError::Config`] if the configuration can't be loaded, or
+    /// [`ErgoError::Cache`] if the command cache cannot be initialized.
+    pub async fn new(verbose: bool) -> Result<Self, ErgoError> {
+        let config = crate::config::Config::load().map_err(ErgoError::Config)?;
+        let mut cache = CommandCache::new().await.map_err(ErgoError::Cache)?;
+        cache.set_grant_expiry(config.trust.grant_expiry_days, config.trust.expired_grants);
+
         Ok(Self {
-            cache: CommandCache::new().await?,
-            generator: LlmGenerator::new(),
-            executor: Executor::new(verbose),
-            permission_ui: PermissionUI::new(verbose),
-            verbose,
+            cache,
+            generator: crate::fixtures::select_generator(
+                |name| std::env::var(name).ok(),
+                || crate::plugins::select_generator(&config.generator, &crate::plugins::plugins_dir()?),
+            )
+            .map_err(ErgoError::Config)?,
+            executor: Executor::new(verbose)
+                .with_sandbox(config.sandbox)
+                .with_tty_passthrough(config.execution.tty.passthrough(
+                    std::io::stdin().is_terminal() && std::io::stdout().is_terminal(),
+                ))
+                .with_capture_limit(config.execution.capture_limit)
+                .with_output_processors(config.output.processors)
+                .with_context_saving(!crate::ephemeral::is_enabled()),
+            permission_ui: PermissionUI::new(verbose).with_consent_provider(
+                crate::permission_ui::select_consent_provider(|name| std::env::var(name).ok())
+                    .map_err(ErgoError::Config)?,
+            ),
+            cancellation: Cancellation::new(),
+            deps: config.deps,
+            validation: ValidationPipeline::from_config(&config.validation, &config.safety),
+            safety: config.safety,
+            safety_override: false,
+            cache_layout: config.cache,
+            trust: config.trust,
+            generation_timeout: Duration::from_secs(config.generator.timeout),
+            generation_retries: 0,
+            backend: config.generator.backend,
+            model_chosen: false,
+            plan_first: false,
+            semantic_search: config.semantic_search,
+            progress: Arc::new(ConsoleProgress::new(verbose)),
         })
     }
 
+    /// Creates a router from ready-made parts, without reading the
+    /// configuration (see [`crate::testing::TestRouter`] for tests).
+    pub fn from_parts(
+        cache: CommandCache,
+        generator: Box<dyn CommandGenerator>,
+        executor: Executor,
+        permission_ui: PermissionUI,
+        verbose: bool,
+    ) -> Self {
+        Self {
+            cache,
+            generator,
+            executor,
+            permission_ui,
+            cancellation: Cancellation::new(),
+            deps: DepsConfig::default(),
+            safety: SafetyConfig::default(),
+            safety_override: false,
+            cache_layout: CacheConfig::default(),
+            trust: TrustConfig::default(),
+            generation_timeout: Duration::from_secs(crate::plugins::DEFAULT_GENERATION_TIMEOUT_SECS),
+            generation_retries: 0,
+            backend: crate::plugins::BUILTIN_BACKEND.to_string(),
+            model_chosen: false,
+            plan_first: false,
+            semantic_search: SemanticSearchConfig::default(),
+            validation: ValidationPipeline::new(&ValidationConfig::default()),
+            progress: Arc::new(ConsoleProgress::new(verbose)),
+        }
+    }
+
+    /// Sends progress events to `progress` instead of printing them in
+    /// verbose mode (see [`crate::progress`]).
+    pub fn with_progress(mut self, progress: impl ProgressSink + 'static) -> Self {
+        self.progress = Arc::new(progress);
+        self
+    }
+
+    /// Abandons generation and stops running commands when `cancellation` fires.
+    ///
+    /// Cancelled operations return [`ErgoError::Interrupted`].
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.executor = self.executor.with_cancellation(cancellation.clone());
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Enables or disables staged writes for generated commands.
+    ///
+    /// Overrides the `sandbox.stage_writes` setting (see [`crate::staging`]).
+    pub fn set_staged_writes(&mut self, enabled: bool) {
+        self.executor.set_staged_writes(enabled);
+    }
+
+    /// Runs generated commands in a temporary workspace holding only their
+    /// inputs.
+    ///
+    /// Overrides the `sandbox.workspace` setting (see [`crate::workspace`]).
+    pub fn set_workspace(&mut self, enabled: bool) {
+        self.executor.set_workspace(enabled);
+    }
+
+    /// Chooses how the output of commands that print secrets is delivered
+    /// (see [`crate::secrets`]).
+    pub fn set_secret_delivery(&mut self, delivery: SecretDelivery) {
+        self.executor.set_secret_delivery(delivery);
+    }
+
+    /// Abandons generation requests that take longer than `timeout`.
+    ///
+    /// Overrides the `generator.timeout` setting.
+    pub fn set_generation_timeout(&mut self, timeout: Duration) {
+        self.generation_timeout = timeout;
+    }
+
+    /// Retries generation requests that time out up to `retries` times, as
+    /// `--retry` does.
+    pub fn set_generation_retries(&mut self, retries: u32) {
+        self.generation_retries = retries;
+    }
+
+    /// Generates and regenerates commands with `model` instead of the
+    /// configured one, as `--model` does. The choice is recorded in the
+    /// commands' provenance, so `--nope` keeps using it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErgoError::Config`] if the generator backend can't choose models.
+    pub fn set_model(&mut self, model: &str) -> Result<(), ErgoError> {
+        self.generator.set_model(model).map_err(ErgoError::Config)?;
+        self.model_chosen = true;
+        Ok(())
+    }
+
+    /// Has the generator plan new commands before writing them, as `--plan`
+    /// does: the plan is shown for approval or changes, and the command is
+    /// generated from the approved plan.
+    pub fn set_plan_first(&mut self, enabled: bool) {
+        self.plan_first = enabled;
+    }
+
+    /// Runs commands even if their scripts match catastrophic patterns, as
+    /// `--i-know-what-im-doing` does (see [`security_review::blocked_patterns`]).
+    pub fn set_safety_override(&mut self, enabled: bool) {
+        self.safety_override = enabled;
+    }
+
+    /// Validates generated commands with `validation` before caching them
+    /// (see [`crate::validation`]).
+    ///
+    /// Overrides the `[validation]` settings.
+    pub fn set_validation(&mut self, validation: ValidationPipeline) {
+        self.validation = validation;
+    }
+
+    /// Sets which biomas are trusted (see [`crate::trust`]).
+    ///
+    /// Overrides the `[trust]` settings, including when unused grants expire.
+    pub fn set_trust(&mut self, trust: TrustConfig) {
+        self.cache.set_grant_expiry(trust.grant_expiry_days, trust.expired_grants);
+        self.trust = trust;
+    }
+
+    /// Filters the JSON output of generated commands, as `--jq` does
+    /// (see [`crate::json_filter`]).
+    pub fn set_json_filter(&mut self, filter: Option<JsonFilter>) {
+        self.executor.set_json_filter(filter);
+    }
+
     /// Processes a user intent and executes the appropriate command.
     ///
     /// This is the main entry point for command execution. The router determines
@@ -103,17 +425,32 @@ impl CommandRouter {
     ///
     /// * `intent_args` - The command name and arguments, or a natural language description
     ///
+    /// # Returns
+    ///
+    /// An [`IntentOutcome`] describing which path was taken and, for generated
+    /// commands, the [`ExecutionResult`]. Nothing is printed about the outcome;
+    /// presenting it is up to the caller.
+    ///
     /// # Errors
     ///
-    /// Returns an error if:
-    /// - Command generation fails
-    /// - Command execution fails
-    /// - Cache operations fail
-    pub async fn process_intent(&mut self, intent_args: Vec<String>) -> Result<()> {
+    /// Returns an [`ErgoError`] telling what failed:
+    /// - [`ErgoError::Generation`] if command generation fails
+    /// - [`ErgoError::Execution`] if a system command can't be run
+    /// - [`ErgoError::Cache`] if cache operations fail
+    /// - [`ErgoError::PermissionDenied`] if the script matches catastrophic patterns
+    /// - [`ErgoError::Interrupted`] if the user presses Ctrl-C
+    pub async fn process_intent(&mut self, intent_args: Vec<String>) -> Result<IntentOutcome, ErgoError> {
         // Conversational mode: single argument with spaces = natural language
         if intent_args.len() == 1 && intent_args[0].contains(' ') {
             info!("Detected conversational mode: {}", intent_args[0]);
-            return self.process_conversational_intent(&intent_args[0]).await;
+            // Most natural language intents need the model; connect while searching the cache
+            self.generator.warm_up();
+            if let Some(outcome) = self.run_similar_command(&intent_args[0]).await? {
+                return Ok(outcome);
+            }
+            return Ok(self
+                .process_conversational_intent(&intent_args[0], Origin::Intent(&intent_args[0]))
+                .await?);
         }
 
         let command_name = &intent_args[0];
@@ -122,56 +459,692 @@ impl CommandRouter {
         info!("Processing intent: {} with args: {:?}", command_name, args);
 
         // Check if command exists in system PATH
-        if which(command_name).is_ok() {
+        if self.executor.program_exists(command_name) {
             info!("Command '{}' found in system PATH, executing directly", command_name);
-            return self.executor.execute_system_command(&intent_args).await;
+            self.progress.emit(&RouterEvent::Executing {
+                command_name: command_name.clone(),
+                args: args.to_vec(),
+                system: true,
+            });
+            self.executor
+                .execute_system_command(&intent_args)
+                .await
+                .map_err(ErgoError::Execution)?;
+            return Ok(IntentOutcome::SystemExecuted {
+                command_name: command_name.clone(),
+            });
         }
 
         // Check if command exists in our cache
-        if let Some(cached_command) = self.cache.get_command(command_name).await? {
+        if let Some(cached_command) = self.host_command(command_name).await? {
             info!("Command '{}' found in cache, checking permissions", command_name);
-            return self
-                .execute_with_permissions(command_name, &cached_command, args)
-                .await;
+            self.progress.emit(&RouterEvent::CacheHit {
+                command_name: command_name.clone(),
+            });
+            let args = self.expand_presets(command_name, args)?;
+            return Ok(match self.execute_with_permissions(command_name, &cached_command, &args).await? {
+                Some(result) => IntentOutcome::CacheHit {
+                    command: cached_command,
+                    result,
+                },
+                None => IntentOutcome::PermissionDenied { command: cached_command },
+            });
         }
 
         // Generate new command using LLM
-        if self.verbose {
-            println!("⚡ Command '{}' not found, generating with AI...", command_name);
-        }
+        check_command_name(command_name)?;
+        self.progress.emit(&RouterEvent::GenerationStarted {
+            command_name: Some(command_name.clone()),
+            request: intent_args.join(" "),
+        });
         warn!("Command '{}' not found, generating with AI", command_name);
-        let generation_result = self.generator.generate_command(command_name, args).await?;
+        let mut generation_result = self
+            .generate_planned(PlanRequest::Named { name: command_name, args })
+            .await?;
+        self.pin_imports(&mut generation_result);
+        self.validate_generation(command_name, &mut generation_result).await?;
 
         // Cache the generated command and its script
         self.cache
             .store_command(command_name, &generation_result.command, &generation_result.script_content)
+            .await.map_err(ErgoError::Cache)?;
+        self.cache.set_intent(command_name, &intent_args.join(" ")).await.map_err(ErgoError::Cache)?;
+
+        self.show_assumptions(command_name, &generation_result.command);
+        let executed = self
+            .execute_with_permissions(command_name, &generation_result.command, args)
             .await?;
+        Ok(self.generated_outcome(generation_result, executed))
+    }
+
+    /// Runs several cached commands concurrently.
+    ///
+    /// Each intent is a command name followed by its arguments. Only commands
+    /// that have already been generated can run this way; permissions are
+    /// confirmed for all of them first, one at a time, and the approved
+    /// commands then run with at most `max_parallel` at once. Output is
+    /// prefixed with the command name (see [`Executor::execute_many`]).
+    ///
+    /// # Returns
+    ///
+    /// One [`BatchOutcome`] per intent, in the same order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a consent prompt or cache update fails.
+    pub async fn process_many(&mut self, intents: Vec<Vec<String>>, max_parallel: usize) -> Result<Vec<BatchOutcome>, ErgoError> {
+        let mut outcomes: Vec<Option<Result<IntentOutcome, ErgoError>>> = Vec::with_capacity(intents.len());
+        let mut jobs = Vec::new();
+        let mut job_slots = Vec::new();
+
+        for (slot, intent) in intents.iter().enumerate() {
+            let Some(command_name) = intent.first() else {
+                outcomes.push(Some(Err(anyhow!("Empty intent").into())));
+                continue;
+            };
+            let Some(command) = self.host_command(command_name).await? else {
+                outcomes.push(Some(Err(anyhow!(
+                    "'{}' hasn't been generated yet; run `ergo {}` once first",
+                    command_name,
+                    command_name
+                )
+                .into())));
+                continue;
+            };
+            self.progress.emit(&RouterEvent::CacheHit {
+                command_name: command_name.clone(),
+            });
+            let args = match self.expand_presets(command_name, &intent[1..]) {
+                Ok(args) => args,
+                Err(e) => {
+                    outcomes.push(Some(Err(e.into())));
+                    continue;
+                }
+            };
+
+            self.warn_if_other_platform(command_name, &command);
+            if let Err(e) = self.enforce_safety_rails(command_name, &command) {
+                outcomes.push(Some(Err(e.into())));
+                continue;
+            }
+            let approved = match self.check_and_request_permissions(command_name, &command).await? {
+                Some(decision) => decision.consent != PermissionConsent::Denied,
+                None => false,
+            };
+            if !approved {
+                outcomes.push(Some(Ok(IntentOutcome::PermissionDenied { command })));
+                continue;
+            }
+
+            match self.cache.get_script_content(&command) {
+                Ok(script) => {
+                    self.cache.update_usage(command_name).await.map_err(ErgoError::Cache)?;
+                    jobs.push(BatchJob {
+                        command,
+                        script,
+                        args,
+                    });
+                    job_slots.push(slot);
+                    outcomes.push(None);
+                }
+                Err(e) => outcomes.push(Some(Err(ErgoError::Cache(e)))),
+            }
+        }
 
-        self.execute_with_permissions(command_name, &generation_result.command, args)
-            .await
+        for job in &jobs {
+            self.progress.emit(&RouterEvent::Executing {
+                command_name: job.command.name.clone(),
+                args: job.args.clone(),
+                system: false,
+            });
+        }
+        let results = self.executor.execute_many(&jobs, max_parallel);
+        for ((slot, job), result) in job_slots.into_iter().zip(jobs).zip(results) {
+            self.progress.emit(&RouterEvent::Finished {
+                command_name: job.command.name.clone(),
+                result: result.clone(),
+            });
+            outcomes[slot] = Some(Ok(IntentOutcome::CacheHit {
+                command: job.command,
+                result,
+            }));
+        }
+
+        Ok(intents
+            .into_iter()
+            .zip(outcomes)
+            .map(|(intent, outcome)| BatchOutcome {
+                command_name: intent.first().cloned().unwrap_or_default(),
+                outcome: outcome.expect("every intent has an outcome"),
+            })
+            .collect())
+    }
+
+    /// Runs the steps of a chain one after the other (see [`crate::chain`]).
+    ///
+    /// Each step is processed like a single intent, so its execution context
+    /// is saved and `--nope` applies to the last step that ran. A step that
+    /// fails to run (e.g. a failing system command) counts as failed.
+    ///
+    /// # Returns
+    ///
+    /// One [`ChainOutcome`] per step, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErgoError::Interrupted`] if the user
+    /// pressed Ctrl-C while a command was being generated. Steps after an
+    /// interrupted command are skipped.
+    pub async fn process_chain(&mut self, chain: &Chain) -> Result<Vec<ChainOutcome>, ErgoError> {
+        let mut outcomes = Vec::with_capacity(chain.steps.len());
+        let mut previous = None;
+
+        for step in &chain.steps {
+            let command_line = step.command_line();
+            if !step.when.should_run(previous) || self.cancellation.is_cancelled() {
+                info!("Skipping chain step '{}'", command_line);
+                outcomes.push(ChainOutcome {
+                    command_line,
+                    outcome: None,
+                });
+                continue;
+            }
+
+            self.progress.emit(&RouterEvent::StepStarted {
+                command_line: command_line.clone(),
+            });
+            let outcome = match self.process_intent(step.intent.clone()).await {
+                Err(ErgoError::Interrupted) => return Err(ErgoError::Interrupted),
+                outcome => outcome,
+            };
+            let step_outcome = ChainOutcome {
+                command_line,
+                outcome: Some(outcome),
+            };
+            previous = Some(step_outcome.succeeded());
+            outcomes.push(step_outcome);
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Runs a workflow (see [`crate::workflow`]).
+    ///
+    /// 1. Commands that aren't cached are generated from their step's description
+    /// 2. The permissions of every command still needing consent are reviewed
+    ///    in a single prompt
+    /// 3. The steps run in order, with `${steps.<id>.output}` replaced by the
+    ///    output of earlier steps
+    ///
+    /// Steps are never attached to the terminal, so their output can be passed
+    /// on.
+    ///
+    /// # Returns
+    ///
+    /// One [`ChainOutcome`] per step, or `None` if the user denied the
+    /// permissions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a missing command has no description, generation
+    /// fails, or the user presses Ctrl-C while a command is being generated.
+    pub async fn process_workflow(&mut self, workflow: &Workflow) -> Result<Option<Vec<ChainOutcome>>, ErgoError> {
+        // Every distinct command, in order of first use
+        let mut commands: Vec<GeneratedCommand> = Vec::new();
+        for step in &workflow.steps {
+            if commands.iter().any(|c| c.name == step.command) {
+                continue;
+            }
+            let command = match self.host_command(&step.command).await? {
+                Some(command) => {
+                    self.progress.emit(&RouterEvent::CacheHit {
+                        command_name: command.name.clone(),
+                    });
+                    command
+                }
+                None => {
+                    check_command_name(&step.command)?;
+                    self.generate_workflow_command(&step.command, step.description.as_deref()).await?
+                }
+            };
+            commands.push(command);
+        }
+        for command in &commands {
+            self.enforce_safety_rails(&command.name, command)?;
+        }
+
+        // Commands that need a closer look are asked about one by one, the
+        // rest all at once
+        let mut pending: Vec<&GeneratedCommand> = Vec::new();
+        for command in &commands {
+            if self.needs_closer_review(&command.name) {
+                let approved = self
+                    .check_and_request_permissions(&command.name, command)
+                    .await?
+                    .is_some_and(|decision| decision.consent != PermissionConsent::Denied);
+                if !approved {
+                    info!("Permissions denied for workflow '{}'", workflow.name);
+                    return Ok(None);
+                }
+            } else if self.cache.needs_permission_consent(&command.name) {
+                self.warn_if_outside_project(&command.name, command);
+                pending.push(command);
+            }
+        }
+        if !pending.is_empty() {
+            let listing: Vec<(&str, &str, &[PermissionRequest])> = pending
+                .iter()
+                .map(|c| (c.name.as_str(), c.description.as_str(), c.permissions.as_slice()))
+                .collect();
+            for command in &pending {
+                self.progress.emit(&RouterEvent::ConsentRequested {
+                    command_name: command.name.clone(),
+                });
+            }
+            let consent = self.permission_ui.prompt_for_workflow_consent(&workflow.name, &listing)?;
+            for command in &pending {
+                let decision = self
+                    .permission_ui
+                    .create_permission_decision(command.permissions.clone(), consent.clone());
+                self.cache.set_permission_decision(&command.name, decision).await.map_err(ErgoError::Cache)?;
+            }
+            if consent == PermissionConsent::Denied {
+                info!("Permissions denied for workflow '{}'", workflow.name);
+                return Ok(None);
+            }
+        }
+
+        self.executor.set_tty_passthrough(false);
+        let mut outputs: HashMap<String, String> = HashMap::new();
+        let mut outcomes = Vec::with_capacity(workflow.steps.len());
+        let mut previous = None;
+
+        for step in &workflow.steps {
+            let planned = std::iter::once(&step.command).chain(&step.args).cloned().collect::<Vec<_>>().join(" ");
+            if !step.when.should_run(previous) || self.cancellation.is_cancelled() {
+                info!("Skipping workflow step '{}'", step.id);
+                outcomes.push(ChainOutcome {
+                    command_line: planned,
+                    outcome: None,
+                });
+                continue;
+            }
+
+            let args = step
+                .args
+                .iter()
+                .map(|arg| workflow::interpolate(arg, &outputs))
+                .collect::<Result<Vec<_>>>()
+                .and_then(|args| self.expand_presets(&step.command, &args));
+            let args = match args {
+                Ok(args) => args,
+                Err(e) => {
+                    outcomes.push(ChainOutcome {
+                        command_line: planned,
+                        outcome: Some(Err(e.into())),
+                    });
+                    previous = Some(false);
+                    continue;
+                }
+            };
+
+            let command = commands
+                .iter()
+                .find(|c| c.name == step.command)
+                .expect("every step's command was resolved above")
+                .clone();
+            let command_line = std::iter::once(step.command.clone()).chain(args.iter().cloned()).collect::<Vec<_>>().join(" ");
+            self.progress.emit(&RouterEvent::StepStarted {
+                command_line: command_line.clone(),
+            });
+            self.warn_if_other_platform(&command.name, &command);
+            self.permission_ui
+                .show_running_with_permissions(&command.name, &command.permissions);
+            self.cache.update_usage(&command.name).await.map_err(ErgoError::Cache)?;
+            self.progress.emit(&RouterEvent::Executing {
+                command_name: command.name.clone(),
+                args: args.clone(),
+                system: false,
+            });
+            let result = self
+                .executor
+                .execute_generated_command_with_context(&command, &self.cache, &args)
+                .await;
+            self.progress.emit(&RouterEvent::Finished {
+                command_name: command.name.clone(),
+                result: result.clone(),
+            });
+
+            if result.success
+                && let Some(stdout) = &result.stdout
+            {
+                outputs.insert(step.id.clone(), stdout.clone());
+            }
+            previous = Some(result.success);
+            outcomes.push(ChainOutcome {
+                command_line,
+                outcome: Some(Ok(IntentOutcome::CacheHit { command, result })),
+            });
+        }
+
+        Ok(Some(outcomes))
+    }
+
+    /// Generates and caches a command a workflow needs under `name`.
+    async fn generate_workflow_command(&mut self, name: &str, description: Option<&str>) -> Result<GeneratedCommand> {
+        let description = description.ok_or_else(|| {
+            anyhow!(
+                "'{}' hasn't been generated yet and its workflow step has no description to generate it from",
+                name
+            )
+        })?;
+        self.progress.emit(&RouterEvent::WorkflowGeneration {
+            command_name: name.to_string(),
+            description: description.to_string(),
+        });
+        warn!("Command '{}' not found, generating it for a workflow", name);
+
+        let request = format!("Create a command named '{}' that does this: {}", name, description);
+        let mut generation_result = self
+            .generate(|| self.generator.generate_command_from_description(&request))
+            .await?;
+        self.pin_imports(&mut generation_result);
+        self.validate_generation(name, &mut generation_result).await?;
+        let command = GeneratedCommand {
+            name: name.to_string(),
+            ..generation_result.command
+        };
+        self.show_assumptions(name, &command);
+        self.cache
+            .store_command(name, &command, &generation_result.script_content)
+            .await.map_err(ErgoError::Cache)?;
+        self.cache.set_intent(name, description).await.map_err(ErgoError::Cache)?;
+        Ok(self.host_command(name).await?.unwrap_or(command))
+    }
+
+    /// Runs the validation pipeline over a fresh generation before it's
+    /// cached, asking the model to repair it or refusing to cache it when it
+    /// fails (see [`crate::validation`]).
+    async fn validate_generation(&self, command_name: &str, generation_result: &mut GenerationResult) -> Result<()> {
+        // The safety rails are overridden for running, so don't enforce them here either
+        let skip: &[&str] = if self.safety_override { &[SafetyValidator::NAME] } else { &[] };
+        let mut issues = self.validation.validate(generation_result, skip);
+        let mut repairs = 0;
+        while !issues.is_empty() {
+            if self.validation.on_failure() == FailureAction::Block || repairs == self.validation.max_repairs() {
+                warn!("Generated '{}' fails validation with {} issue(s)", command_name, issues.len());
+                let attempts = match repairs {
+                    0 => String::new(),
+                    n => format!(" after {} repair attempt(s)", n),
+                };
+                return Err(ErgoError::Generation(anyhow!(
+                    "🛑 Not caching '{}', it fails validation{}:\n{}",
+                    command_name,
+                    attempts,
+                    validation::format_issues(&issues)
+                ))
+                .into());
+            }
+            repairs += 1;
+            info!("Repairing '{}' ({} issue(s)), attempt {}", command_name, issues.len(), repairs);
+            self.progress.emit(&RouterEvent::RepairStarted {
+                command_name: command_name.to_string(),
+                attempt: repairs,
+                max_attempts: self.validation.max_repairs(),
+                issues: validation::format_issues(&issues),
+            });
+
+            let feedback = validation::repair_feedback(&issues);
+            let request = FeedbackRequest {
+                command_name,
+                original_script: &generation_result.script_content,
+                stderr: None,
+                exit_status: None,
+                user_feedback: &feedback,
+                test_script: None,
+                history: &[],
+                intent: None,
+            };
+            let mut repaired = self
+                .generate(|| self.generator.regenerate_command_with_feedback(&request))
+                .await?;
+            self.pin_imports(&mut repaired);
+            *generation_result = repaired;
+            issues = self.validation.validate(generation_result, skip);
+        }
+        if repairs > 0 {
+            self.progress.emit(&RouterEvent::Repaired {
+                command_name: command_name.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Generates the command for `request`, planning it first and asking
+    /// the user to approve the plan with `--plan`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if generation fails or the user cancels the plan.
+    async fn generate_planned(&self, request: PlanRequest<'_>) -> Result<GenerationResult> {
+        if !self.plan_first {
+            return match request {
+                PlanRequest::Named { name, args } => self.generate(|| self.generator.generate_command(name, args)).await,
+                PlanRequest::Described(description) => {
+                    self.generate(|| self.generator.generate_command_from_description(description))
+                        .await
+                }
+            };
+        }
+
+        self.progress.emit(&RouterEvent::PlanningStarted {
+            request: request.to_string(),
+        });
+        let mut plan = self.generate(|| self.generator.plan_command(request)).await?;
+        if !self.permission_ui.review_plan(&request.to_string(), &mut plan)? {
+            return Err(anyhow!("Cancelled: the plan for '{}' wasn't approved, so nothing was generated", request));
+        }
+        info!("Generating '{}' from its approved plan", request);
+        self.generate(|| self.generator.generate_from_plan(request, &plan)).await
+    }
+
+    /// Runs a generation request, abandoning it on Ctrl-C or after the
+    /// generation timeout, and retrying timeouts as configured.
+    async fn generate<T, F, Fut>(&self, request: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let seconds = self.generation_timeout.as_secs();
+        for attempt in 0..=self.generation_retries {
+            if attempt > 0 {
+                self.progress.emit(&RouterEvent::GenerationRetried {
+                    timeout_secs: seconds,
+                    attempt,
+                    retries: self.generation_retries,
+                });
+            }
+            let attempt_result = self
+                .cancellation
+                .run(async {
+                    let attempt = tokio::time::timeout(self.generation_timeout, request()).await.ok();
+                    attempt.transpose().map_err(|e| ErgoError::Generation(e).into())
+                })
+                .await?;
+            if let Some(result) = attempt_result {
+                return Ok(result);
+            }
+            warn!("Generation timed out after {}s", seconds);
+        }
+        Err(ErgoError::Generation(
+            GenerationTimedOut {
+                seconds,
+                retried: self.generation_retries > 0,
+            }
+            .into(),
+        )
+        .into())
+    }
+
+    /// Pins the unversioned std imports of a freshly generated script to
+    /// `deps.std_version` (see [`crate::deno_deps`]).
+    fn pin_imports(&self, generation: &mut GenerationResult) {
+        match deno_deps::pin_std_imports(&generation.script_content, &self.deps.std_version) {
+            Ok((_, 0)) => {}
+            Ok((script, count)) => {
+                info!("Pinned {} std import(s) to std@{}", count, self.deps.std_version);
+                self.progress.emit(&RouterEvent::ImportsPinned {
+                    count,
+                    std_version: self.deps.std_version.clone(),
+                });
+                generation.script_content = script;
+            }
+            Err(e) => warn!("Leaving std imports unpinned: {}", e),
+        }
+    }
+
+    /// Loads a cached command as it runs on this platform (see
+    /// [`GeneratedCommand::for_platform`]).
+    async fn host_command(&self, name: &str) -> Result<Option<GeneratedCommand>> {
+        let command = self.cache.get_command(name).await.map_err(ErgoError::Cache)?;
+        Ok(command.map(|c| c.for_platform(std::env::consts::OS)))
+    }
+
+    /// Replaces `@preset` arguments with the arguments saved under that
+    /// preset (see [`CommandCache::save_preset`]). `@@name` passes a literal
+    /// `@name` through.
+    fn expand_presets(&self, command_name: &str, args: &[String]) -> Result<Vec<String>> {
+        let mut expanded = Vec::with_capacity(args.len());
+        for arg in args {
+            match arg.strip_prefix('@') {
+                Some(literal) if literal.starts_with('@') => expanded.push(literal.to_string()),
+                Some(preset) if !preset.is_empty() => {
+                    let saved = self.cache.get_preset(command_name, preset).ok_or_else(|| {
+                        let names = self.cache.preset_names(command_name);
+                        anyhow!(
+                            "Unknown preset '@{}' for '{}' (saved presets: {})",
+                            preset,
+                            command_name,
+                            if names.is_empty() { "none".to_string() } else { names.join(", ") }
+                        )
+                    })?;
+                    info!("Expanded preset '@{}' to {:?}", preset, saved);
+                    expanded.extend_from_slice(saved);
+                }
+                _ => expanded.push(arg.clone()),
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Generates and runs a command for `intent` that works on the output of
+    /// the last execution, as `--with-last` does.
+    pub async fn process_with_last(&mut self, intent: &str) -> Result<IntentOutcome, ErgoError> {
+        let last = ExecutionContext::load()?
+            .ok_or_else(|| anyhow!("No previous execution found. Run a command first, then use --with-last."))?;
+        let request = last.follow_up_request(intent)?;
+        self.progress.emit(&RouterEvent::UsingLastOutput {
+            command_name: last.command_name.clone(),
+        });
+        let origin = format!("{} (with the output of '{}')", intent, last.command_name);
+        Ok(self.process_conversational_intent(&request, Origin::Intent(&origin)).await?)
+    }
+
+    /// Generates and caches the command for an intent without running it,
+    /// as `--flush-queue` does for queued intents (see [`crate::intent_queue`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the intent names a system command or generation fails.
+    pub async fn prepare_intent(&mut self, intent_args: &[String]) -> Result<PrepareOutcome, ErgoError> {
+        let command_name = intent_args.first().ok_or_else(|| anyhow!("Empty intent"))?;
+        let mut generation_result = if intent_args.len() == 1 && command_name.contains(' ') {
+            let generated = self
+                .generate(|| self.generator.generate_command_from_description(command_name))
+                .await?;
+            self.choose_name(command_name, generated).await?
+        } else {
+            if self.executor.program_exists(command_name) {
+                return Err(anyhow!("'{}' is a system command; there's nothing to generate", command_name).into());
+            }
+            if let Some(command) = self.host_command(command_name).await? {
+                return Ok(PrepareOutcome::AlreadyCached { command });
+            }
+            check_command_name(command_name)?;
+            self.generate(|| self.generator.generate_command(command_name, &intent_args[1..]))
+                .await?
+        };
+        self.pin_imports(&mut generation_result);
+        let name = generation_result.command.name.clone();
+        self.validate_generation(&name, &mut generation_result).await?;
+
+        info!("Generated command '{}' without running it", name);
+        self.cache
+            .store_command(&name, &generation_result.command, &generation_result.script_content)
+            .await.map_err(ErgoError::Cache)?;
+        self.cache.set_intent(&name, &intent_args.join(" ")).await.map_err(ErgoError::Cache)?;
+        Ok(PrepareOutcome::Generated {
+            command: generation_result.command,
+        })
+    }
+
+    /// Offers to run a cached command similar to a natural language intent
+    /// instead of generating one (see [`crate::semantic_search`]).
+    ///
+    /// Returns `None` if no cached command is similar enough or the user
+    /// wants a new one.
+    async fn run_similar_command(&mut self, description: &str) -> Result<Option<IntentOutcome>> {
+        if !self.semantic_search.enabled {
+            return Ok(None);
+        }
+        let Some(similar) = self.cache.find_similar(description, self.semantic_search.min_similarity) else {
+            return Ok(None);
+        };
+        info!("'{}' is {:.2} similar to '{}'", similar.name, similar.similarity, description);
+        if !self.permission_ui.offer_similar_command(&similar)? {
+            return Ok(None);
+        }
+        let Some(command) = self.host_command(&similar.name).await? else {
+            return Ok(None);
+        };
+        Ok(Some(match self.execute_with_permissions(&similar.name, &command, &[]).await? {
+            Some(result) => IntentOutcome::CacheHit { command, result },
+            None => IntentOutcome::PermissionDenied { command },
+        }))
+    }
+
+    /// Generates and runs a command from a detailed specification, such as
+    /// a Markdown file, as `--intent-file` does. The specification is kept
+    /// with the command (see [`GeneratedCommand::spec`]).
+    pub async fn process_spec(&mut self, spec: &str) -> Result<IntentOutcome, ErgoError> {
+        self.generator.warm_up();
+        Ok(self.process_conversational_intent(spec, Origin::Spec).await?)
     }
 
     /// Processes a natural language description to generate and execute a command.
     ///
     /// This handles "conversational mode" where the user provides a description
     /// instead of a command name. The LLM will suggest both the command name
-    /// and implementation.
-    async fn process_conversational_intent(&mut self, description: &str) -> Result<()> {
+    /// and implementation. `origin` records where the description came from
+    /// with the command.
+    async fn process_conversational_intent(&mut self, description: &str, origin: Origin<'_>) -> Result<IntentOutcome> {
         info!("Processing conversational intent: {}", description);
-        if self.verbose {
-            println!("💭 Understanding your request: {}", description);
-        }
+        self.progress.emit(&RouterEvent::GenerationStarted {
+            command_name: None,
+            request: description.to_string(),
+        });
 
         // Generate command from natural language description
-        let generation_result = self
-            .generator
-            .generate_command_from_description(description)
-            .await?;
-
-        if self.verbose {
-            println!("🎯 Generated command: {}", generation_result.command.name);
-            println!("📝 Description: {}", generation_result.command.description);
+        let generated = self.generate_planned(PlanRequest::Described(description)).await?;
+        let mut generation_result = self.choose_name(description, generated).await?;
+        if let Origin::Spec = origin {
+            generation_result.command.spec = Some(description.to_string());
         }
+        self.pin_imports(&mut generation_result);
+        let name = generation_result.command.name.clone();
+        self.validate_generation(&name, &mut generation_result).await?;
+
+        info!("Generated command '{}' from description", generation_result.command.name);
 
         // Cache the generated command and its script
         self.cache
@@ -180,10 +1153,88 @@ impl CommandRouter {
                 &generation_result.command,
                 &generation_result.script_content,
             )
+            .await.map_err(ErgoError::Cache)?;
+        if let Origin::Intent(intent) = origin {
+            self.cache.set_intent(&generation_result.command.name, intent).await.map_err(ErgoError::Cache)?;
+        }
+
+        self.show_assumptions(&generation_result.command.name, &generation_result.command);
+        let executed = self
+            .execute_with_permissions(&generation_result.command.name, &generation_result.command, &[])
             .await?;
+        Ok(self.generated_outcome(generation_result, executed))
+    }
+
+    /// Settles the name of the command generated from `description`: the
+    /// name the model chose, in kebab-case (see
+    /// [`command_cache::normalize_command_name`]), asking it for another one
+    /// while that name is reserved (see [`reserved`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErgoError::Generation`] if the name can't be normalized, or
+    /// if the model still chooses a reserved name after [`MAX_RENAMES`]
+    /// requests.
+    async fn choose_name(&self, description: &str, mut generation: GenerationResult) -> Result<GenerationResult> {
+        let mut refused = Vec::new();
+        loop {
+            let name = command_cache::normalize_command_name(&generation.command.name).map_err(ErgoError::Generation)?;
+            if name != generation.command.name {
+                info!("Renaming the suggested '{}' to '{}'", generation.command.name, name);
+                generation.command.script_file = format!("{}.ts", name);
+                generation.command.name = name;
+            }
+            let Some(reason) = reserved::reason(&generation.command.name) else {
+                break;
+            };
+            let name = generation.command.name.clone();
+            if refused.len() == MAX_RENAMES {
+                return Err(ErgoError::Generation(anyhow!(
+                    "🛑 Not caching '{}': it's {}, and the model kept choosing reserved names",
+                    name,
+                    reason
+                ))
+                .into());
+            }
+            warn!("The model named a command '{}', which is {}", name, reason);
+            self.progress.emit(&RouterEvent::NameRefused {
+                name: name.clone(),
+                reason: reason.to_string(),
+            });
+            refused.push((name, reason));
+            let request = reserved::rename_request(description, &refused);
+            generation = self
+                .generate(|| self.generator.generate_command_from_description(&request))
+                .await?;
+        }
+        Ok(generation)
+    }
+
+    /// Shows what the model assumed while generating a command, before
+    /// asking for consent to run it.
+    fn show_assumptions(&self, command_name: &str, command: &GeneratedCommand) {
+        self.permission_ui
+            .show_assumptions(command_name, &command.assumptions, command.confidence);
+    }
 
-        self.execute_with_permissions(&generation_result.command.name, &generation_result.command, &[])
-            .await
+    /// Builds the outcome for a freshly generated command.
+    ///
+    /// A denied command becomes the last execution, so `--nope` can correct
+    /// its assumptions before it ever runs.
+    fn generated_outcome(&self, generation: GenerationResult, executed: Option<ExecutionResult>) -> IntentOutcome {
+        let command = generation.command;
+        match executed {
+            Some(result) => IntentOutcome::Generated { command, result },
+            None => {
+                let context = ExecutionContext::new(&command.name, &generation.script_content, None, false);
+                if self.executor.saves_context()
+                    && let Err(e) = context.save()
+                {
+                    crate::ephemeral::warn_unsaved("the execution context", &e);
+                }
+                IntentOutcome::PermissionDenied { command }
+            }
+        }
     }
 
     /// Processes corrective feedback loop to regenerate a command.
@@ -195,63 +1246,171 @@ impl CommandRouter {
     ///
     /// * `feedback` - User feedback about what went wrong (empty string uses stderr only)
     ///
+    /// # Returns
+    ///
+    /// [`IntentOutcome::Regenerated`] or [`IntentOutcome::PermissionDenied`].
+    ///
     /// # Errors
     ///
     /// Returns an error if:
     /// - No previous execution context exists
-    /// - Command regeneration fails
+    /// - The command is pinned
+    /// - Command regeneration fails, or the new version fails the command's tests
     /// - Cache operations fail
-    pub async fn process_corrective_feedback(&mut self, feedback: &str) -> Result<()> {
+    pub async fn process_corrective_feedback(&mut self, feedback: &str) -> Result<IntentOutcome, ErgoError> {
         // Load the last execution context
-        let context = match ExecutionContext::load()? {
-            Some(ctx) => ctx,
-            None => {
-                eprintln!("No previous command execution found. Run a command first, then use --nope.");
-                return Ok(());
-            }
-        };
+        let context = ExecutionContext::load()?
+            .ok_or_else(|| anyhow!("No previous command execution found. Run a command first, then use --nope."))?;
 
-        if self.verbose {
-            println!("🔄 Regenerating command '{}'...", context.command_name);
-            if !feedback.is_empty() {
-                println!("💭 Feedback: {}", feedback);
-            } else if context.stderr.is_some() {
-                println!("💭 Using stderr from last execution as context");
-            }
+        if let Some(revision) = self.cache.pinned_revision(&context.command_name) {
+            return Err(anyhow!(
+                "📌 '{}' is pinned at revision {}. Run `ergo --unpin {}` to regenerate it.",
+                context.command_name,
+                revision,
+                context.command_name
+            )
+            .into());
         }
 
+        // On a platform the main script doesn't serve, only that platform's variant is regenerated
+        let os = std::env::consts::OS;
+        let cached = self.cache.get_command(&context.command_name).await.map_err(ErgoError::Cache)?;
+        let as_variant = cached.as_ref().is_some_and(|c| c.regenerates_as_variant(os));
+        let spec = cached.as_ref().and_then(|c| c.spec.clone());
+
+        // A command generated with a chosen model is regenerated with it too
+        if !self.model_chosen
+            && let Some(model) = cached
+                .as_ref()
+                .and_then(|c| c.provenance.as_ref())
+                .and_then(|p| p.chosen_model(&self.backend))
+            && let Err(e) = self.generator.set_model(model)
+        {
+            warn!("Regenerating '{}' with the configured model: {}", context.command_name, e);
+        }
+
+        self.progress.emit(&RouterEvent::RegenerationStarted {
+            command_name: context.command_name.clone(),
+            variant: as_variant.then(|| os.to_string()),
+            feedback: feedback.to_string(),
+            with_stderr: context.stderr.is_some(),
+        });
+
         info!(
             "Regenerating command '{}' with feedback: {}",
             context.command_name, feedback
         );
 
+        let test_script = self.cache.get_test_script(&context.command_name)?;
+
         // Regenerate the command with feedback
-        let generation_result = self
-            .generator
-            .regenerate_command_with_feedback(
-                &context.command_name,
-                &context.script_content,
-                context.stderr.as_deref(),
-                feedback,
-            )
+        let exit_status = context.exit_summary();
+        let request = FeedbackRequest {
+            command_name: &context.command_name,
+            original_script: &context.script_content,
+            stderr: context.stderr.as_deref(),
+            exit_status: exit_status.as_deref(),
+            user_feedback: feedback,
+            test_script: test_script.as_deref(),
+            history: self.cache.feedback_history(&context.command_name),
+            intent: self.cache.get_intent(&context.command_name),
+        };
+        let mut generation_result = self
+            .generate(|| self.generator.regenerate_command_with_feedback(&request))
             .await?;
+        generation_result.command.spec = spec;
+        self.pin_imports(&mut generation_result);
+        self.validate_generation(&context.command_name, &mut generation_result).await?;
+
+        self.progress.emit(&RouterEvent::Regenerated {
+            command_name: context.command_name.clone(),
+            description: generation_result.command.description.clone(),
+        });
+
+        // Show what the model changed before running the new version
+        let old_permissions = cached
+            .map(|c| c.for_platform(os).permissions)
+            .unwrap_or_default();
+        let old_revision = self.cache.current_revision(&context.command_name).unwrap_or(1);
+        let (old_label, new_label, kept) = if as_variant {
+            (
+                format!("{}.{}", context.command_name, os),
+                format!("{}.{} (regenerated)", context.command_name, os),
+                format!("the {} variant", os),
+            )
+        } else {
+            (
+                format!("{}@{}", context.command_name, old_revision),
+                format!("{}@{}", context.command_name, old_revision + 1),
+                format!("revision {}", old_revision),
+            )
+        };
+        let _ = script_diff::write_revision_diff(
+            &mut std::io::stdout(),
+            (&context.script_content, &old_permissions),
+            (&generation_result.script_content, &generation_result.command.permissions),
+            &old_label,
+            &new_label,
+        );
 
-        if self.verbose {
-            println!("✨ Command regenerated successfully!");
-            println!("📝 New description: {}", generation_result.command.description);
+        // Guardrail: the new version must keep passing the command's tests
+        if let Some(tests) = &test_script {
+            let outcome = self
+                .executor
+                .run_command_tests(tests, &generation_result.script_content)?;
+            if !outcome.passed {
+                warn!(
+                    "Regenerated '{}' fails its tests; keeping {}",
+                    context.command_name, kept
+                );
+                return Err(ErgoError::Generation(anyhow!(
+                    "🛑 The regenerated '{}' fails its tests. Keeping the previous version ({}).\n{}",
+                    context.command_name,
+                    kept,
+                    outcome.output
+                )));
+            }
+            self.progress.emit(&RouterEvent::TestsPassed {
+                command_name: context.command_name.clone(),
+            });
         }
 
         // Update the command in cache
-        self.cache
-            .store_command(
-                &context.command_name,
-                &generation_result.command,
-                &generation_result.script_content,
-            )
-            .await?;
+        if as_variant {
+            self.cache
+                .store_variant(
+                    &context.command_name,
+                    os,
+                    &generation_result.command.permissions,
+                    &generation_result.script_content,
+                )
+                .await?;
+        } else {
+            self.cache
+                .store_command(
+                    &context.command_name,
+                    &generation_result.command,
+                    &generation_result.script_content,
+                )
+                .await.map_err(ErgoError::Cache)?;
+        }
+        let turn = FeedbackTurn {
+            revision: old_revision,
+            feedback: feedback.to_string(),
+            stderr: context.stderr.clone(),
+            created_at: SystemTimeProvider.now(),
+        };
+        self.cache.record_feedback(&context.command_name, turn).await.map_err(ErgoError::Cache)?;
 
-        self.execute_with_permissions(&context.command_name, &generation_result.command, &[])
-            .await
+        self.show_assumptions(&context.command_name, &generation_result.command);
+        let command = self
+            .host_command(&context.command_name)
+            .await?
+            .unwrap_or(generation_result.command);
+        Ok(match self.execute_with_permissions(&context.command_name, &command, &[]).await? {
+            Some(result) => IntentOutcome::Regenerated { command, result },
+            None => IntentOutcome::PermissionDenied { command },
+        })
     }
 
     /// Checks permissions and executes a generated command if approved.
@@ -259,7 +1418,7 @@ impl CommandRouter {
     /// This is the common workflow for executing any generated command:
     /// 1. Check/request permission consent from the user
     /// 2. If approved, show permissions and execute the command
-    /// 3. If denied, show denial message
+    /// 3. If denied, return `None` without running it
     ///
     /// # Arguments
     ///
@@ -271,31 +1430,173 @@ impl CommandRouter {
         command_name: &str,
         command: &crate::llm_generator::GeneratedCommand,
         args: &[String],
-    ) -> Result<()> {
+    ) -> Result<Option<ExecutionResult>> {
+        self.warn_if_other_platform(command_name, command);
+        self.enforce_safety_rails(command_name, command)?;
+        self.warn_if_environment_changed(command_name, command);
         if let Some(decision) = self.check_and_request_permissions(command_name, command).await? {
             match decision.consent {
-                PermissionConsent::AcceptOnce | PermissionConsent::AcceptForever => {
+                PermissionConsent::AcceptOnce | PermissionConsent::AcceptForever | PermissionConsent::AcceptForSession => {
                     self.permission_ui
                         .show_running_with_permissions(command_name, &command.permissions);
-                    self.cache.update_usage(command_name).await?;
-                    let _result = self
+                    self.cache.update_usage(command_name).await.map_err(ErgoError::Cache)?;
+                    self.progress.emit(&RouterEvent::Executing {
+                        command_name: command_name.to_string(),
+                        args: args.to_vec(),
+                        system: false,
+                    });
+                    let result = self
                         .executor
                         .execute_generated_command_with_context(command, &self.cache, args)
                         .await;
+                    self.progress.emit(&RouterEvent::Finished {
+                        command_name: command_name.to_string(),
+                        result: result.clone(),
+                    });
+                    return Ok(Some(result));
                 }
                 PermissionConsent::Denied => {
-                    self.permission_ui.show_permission_denied(command_name);
+                    info!("Permission denied for '{}'", command_name);
                 }
             }
         }
-        Ok(())
+        Ok(None)
+    }
+
+    /// Warns when a command was generated for other operating systems, e.g.
+    /// when a command directory is shared between machines.
+    fn warn_if_other_platform(&self, command_name: &str, command: &GeneratedCommand) {
+        let os = std::env::consts::OS;
+        if command.supports_platform(os) {
+            return;
+        }
+        warn!("'{}' was generated for {:?}, running on {}", command_name, command.platforms, os);
+        self.progress.emit(&RouterEvent::OtherPlatform {
+            command_name: command_name.to_string(),
+            platforms: command.platforms.clone(),
+            os: os.to_string(),
+        });
+    }
+
+    /// Warns when a command validated with `--yep` runs in a materially
+    /// different environment than the rated run (see [`crate::environment`]).
+    fn warn_if_environment_changed(&self, command_name: &str, command: &GeneratedCommand) {
+        let Some(rating) = self.cache.get_rating(command_name) else {
+            return;
+        };
+        // Only the rated revision was validated; a newer one never was
+        if Some(rating.revision) != self.cache.current_revision(command_name) {
+            return;
+        }
+        let Some(validated) = &rating.environment else {
+            return;
+        };
+        let Ok(script) = self.cache.get_script_content(&command.for_platform(std::env::consts::OS)) else {
+            return;
+        };
+
+        let differences = self.executor.environment(&script).differences(validated);
+        if differences.is_empty() {
+            return;
+        }
+        warn!("'{}' runs in a different environment than validated: {:?}", command_name, differences);
+        self.progress.emit(&RouterEvent::EnvironmentChanged {
+            command_name: command_name.to_string(),
+            differences,
+        });
+    }
+
+    /// Refuses to run a command whose script matches catastrophic patterns,
+    /// whatever was consented to, unless overridden.
+    fn enforce_safety_rails(&self, command_name: &str, command: &GeneratedCommand) -> Result<()> {
+        let script = match self.cache.get_script_content(command) {
+            Ok(script) => script,
+            Err(e) => {
+                // Running it fails the same way, with a clearer error
+                warn!("Couldn't read the script of '{}' to check it: {}", command_name, e);
+                return Ok(());
+            }
+        };
+        let blocked = security_review::blocked_patterns(&script, &command.permissions, &self.safety);
+        if blocked.is_empty() {
+            return Ok(());
+        }
+
+        let patterns: Vec<String> = blocked
+            .iter()
+            .map(|finding| format!("line {}: {}", finding.line.unwrap_or_default(), finding.message))
+            .collect();
+        if self.safety_override {
+            warn!("Running '{}' despite {} blocked pattern(s)", command_name, blocked.len());
+            self.progress.emit(&RouterEvent::SafetyOverridden {
+                command_name: command_name.to_string(),
+                patterns,
+            });
+            return Ok(());
+        }
+        let listing = patterns.iter().map(|pattern| format!("   • {}", pattern)).collect::<Vec<_>>().join("\n");
+        Err(ErgoError::PermissionDenied(anyhow!(
+            "🛑 Refusing to run '{}', its script matches catastrophic patterns:\n{}\n   Rerun with --i-know-what-im-doing if you're sure.",
+            command_name,
+            listing
+        ))
+        .into())
+    }
+
+    /// Warns before consent when a command from a project's bioma reads or
+    /// writes outside that project (see [`crate::project_scope`]).
+    fn warn_if_outside_project(&self, command_name: &str, command: &GeneratedCommand) {
+        let home = dirs::home_dir();
+        let Some(root) = self
+            .cache
+            .command_dir(command_name)
+            .and_then(|dir| project_scope::project_root(&dir, home.as_deref(), &self.cache_layout))
+        else {
+            return;
+        };
+        let script = match self.cache.get_script_content(command) {
+            Ok(script) => script,
+            Err(e) => {
+                warn!("Couldn't read the script of '{}' to check its paths: {}", command_name, e);
+                String::new()
+            }
+        };
+        let cwd = std::env::current_dir().unwrap_or_else(|_| root.clone());
+        let escapes = project_scope::find_escapes(&root, &cwd, home.as_deref(), &command.permissions, &script);
+        if !escapes.is_empty() {
+            warn!("'{}' reaches outside its project: {:?}", command_name, escapes);
+        }
+        self.permission_ui.show_outside_project(command_name, &root, &escapes);
+    }
+
+    /// The bioma a command is stored in, if it is untrusted.
+    fn untrusted_bioma(&self, command_name: &str) -> Option<PathBuf> {
+        let bioma = self.cache.command_dir(command_name)?;
+        let home = dirs::home_dir();
+        (self.trust.level(&bioma, &self.cache_layout, home.as_deref()) == TrustLevel::Untrusted).then_some(bioma)
+    }
+
+    /// Whether consent for a command must show more than its permissions: its
+    /// script when it comes from an untrusted bioma or was imported, or the
+    /// changes to a script edited on disk.
+    fn needs_closer_review(&self, command_name: &str) -> bool {
+        self.untrusted_bioma(command_name).is_some()
+            || (self.cache.needs_permission_consent(command_name)
+                && (self.cache.imported_from(command_name).is_some()
+                    || !self.cache.modified_scripts(command_name).is_empty()))
     }
 
     /// Checks and requests permission consent for a command.
     ///
     /// If the user has previously granted "AcceptForever" consent, returns the
     /// stored decision. Otherwise, prompts the user for consent and stores
-    /// their decision.
+    /// their decision. Commands of untrusted biomas (see [`crate::trust`])
+    /// always show their script and ask, whatever was decided before, and so
+    /// do imported commands until they're allowed forever; a script changed
+    /// on disk shows the changes and asks, since no decision was made for it.
+    ///
+    /// Every run of a cached command, workflow steps included, goes through
+    /// here for these checks.
     ///
     /// # Returns
     ///
@@ -306,8 +1607,12 @@ impl CommandRouter {
         command_name: &str,
         command: &crate::llm_generator::GeneratedCommand,
     ) -> Result<Option<crate::command_cache::PermissionDecision>> {
+        let untrusted = self.untrusted_bioma(command_name);
+        let modified = self.cache.modified_scripts(command_name);
+        let imported = self.cache.imported_from(command_name).map(str::to_string);
+
         // Check if we need to ask for consent
-        if !self.cache.needs_permission_consent(command_name) {
+        if untrusted.is_none() && !self.cache.needs_permission_consent(command_name) {
             // Permission already granted forever, return existing decision
             if let Some(decision) = self.cache.get_permission_decision(command_name) {
                 return Ok(Some(decision.clone()));
@@ -315,11 +1620,47 @@ impl CommandRouter {
         }
 
         // Ask user for consent
-        let consent = self.permission_ui.prompt_for_consent(
-            command_name,
-            &command.description,
-            &command.permissions,
-        )?;
+        self.progress.emit(&RouterEvent::ConsentRequested {
+            command_name: command_name.to_string(),
+        });
+        self.warn_if_outside_project(command_name, command);
+        let consent = match &untrusted {
+            Some(bioma) => {
+                let script = self.cache.get_script_content(&command.for_platform(std::env::consts::OS))?;
+                info!("'{}' comes from the untrusted bioma {:?}", command_name, bioma);
+                self.permission_ui.prompt_for_untrusted_consent(command, bioma, &script)?
+            }
+            None if let Some(source) = &imported => {
+                let script = self.cache.get_script_content(&command.for_platform(std::env::consts::OS))?;
+                info!("'{}' was imported from {} and isn't allowed forever yet", command_name, source);
+                self.permission_ui.prompt_for_imported_consent(command, source, &script)?
+            }
+            None if !modified.is_empty() => {
+                warn!("The script of '{}' was changed on disk", command_name);
+                let diff = modified
+                    .iter()
+                    .map(|script| match &script.original {
+                        Some(original) => script_diff::unified_diff(
+                            original,
+                            &script.current,
+                            &format!("{} (as written by ergo)", script.script_file),
+                            &format!("{} (on disk)", script.script_file),
+                        ),
+                        None => format!("{}: changed, but its original copy is gone, so there's no diff\n", script.script_file),
+                    })
+                    .collect::<String>();
+                self.permission_ui.prompt_for_modified_script_consent(command, &diff)?
+            }
+            None => self.permission_ui.prompt_for_consent(
+                command_name,
+                &command.description,
+                &command.permissions,
+                &command.examples,
+            )?,
+        };
+        if !modified.is_empty() && consent != PermissionConsent::Denied {
+            self.cache.accept_modified_scripts(command_name).await.map_err(ErgoError::Cache)?;
+        }
 
         // Create and store decision
         let decision = self
@@ -1,3 +1,19 @@
+use crate::command_cache::CacheConfig;
+use crate::deno_deps::DepsConfig;
+use crate::security_review::SafetyConfig;
+use crate::semantic_search::SemanticSearchConfig;
+use crate::retention::HistoryConfig;
+use crate::preload::PreloadConfig;
+use crate::starter::StarterConfig;
+use crate::executor::ExecutionConfig;
+use crate::http_client::HttpConfig;
+use crate::output_processing::OutputConfig;
+use crate::notify::NotifyConfig;
+use crate::plugins::GeneratorConfig;
+use crate::sandbox::SandboxConfig;
+use crate::telemetry::TelemetryConfig;
+use crate::trust::TrustConfig;
+use crate::validation::ValidationConfig;
 use anyhow::{anyhow, Result};
 use dirs::home_dir;
 use serde::{Deserialize, Serialize};
@@ -63,6 +79,110 @@ pub struct Config {
     /// - Environment variable: `ANTHROPIC_API_KEY`
     #[serde(default)]
     pub anthropic_api_key: Option<String>,
+
+    /// OS-level sandbox wrapper around the Deno process.
+    ///
+    /// Set via the `[sandbox]` table, e.g. `ergo config set sandbox.mode auto`.
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+
+    /// How generated commands are executed.
+    ///
+    /// Set via the `[execution]` table, e.g. `ergo config set execution.tty never`
+    /// or `ergo config set execution.capture_limit 1048576`.
+    #[serde(default)]
+    pub execution: ExecutionConfig,
+
+    /// How generated commands' output is post-processed.
+    ///
+    /// Set via the `[output]` table, e.g. `ergo config set output.processors json,color`.
+    #[serde(default)]
+    pub output: OutputConfig,
+
+    /// Which backend generates commands: the built-in Claude backend or a plugin.
+    ///
+    /// Set via the `[generator]` table, e.g. `ergo config set generator.backend gemini`.
+    #[serde(default)]
+    pub generator: GeneratorConfig,
+
+    /// Opt-in anonymous usage statistics.
+    ///
+    /// Set via the `[telemetry]` table, e.g. `ergo --telemetry on` or
+    /// `ergo config set telemetry.endpoint https://...`.
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+
+    /// Versions imports of generated scripts are pinned to.
+    ///
+    /// Set via the `[deps]` table, e.g. `ergo config set deps.std_version 0.224.0`.
+    #[serde(default)]
+    pub deps: DepsConfig,
+
+    /// Catastrophic patterns that block commands regardless of consent.
+    ///
+    /// Set via the `[safety]` table, e.g. `ergo config set safety.deny_patterns "git push*--force"`.
+    #[serde(default)]
+    pub safety: SafetyConfig,
+
+    /// Names of the project dotfolder and the cache directory inside it.
+    ///
+    /// Set via the `[cache]` table, e.g. `ergo config set cache.dir_name .acme`,
+    /// or the `ERGO_CACHE_DIR_NAME` and `ERGO_CACHE_BIOMA_DIR` environment variables.
+    #[serde(default)]
+    pub cache: CacheConfig,
+
+    /// Whether natural language intents first look for a similar cached command.
+    ///
+    /// Set via the `[semantic_search]` table, e.g.
+    /// `ergo config set semantic_search.min_similarity 0.4`.
+    #[serde(default)]
+    pub semantic_search: SemanticSearchConfig,
+
+    /// Checks generated commands pass before they're cached.
+    ///
+    /// Set via the `[validation]` table, e.g. `ergo config set validation.on_failure block`.
+    #[serde(default)]
+    pub validation: ValidationConfig,
+
+    /// Webhook notified of command results.
+    ///
+    /// Set via the `[notify]` table, e.g.
+    /// `ergo config set notify.webhook https://hooks.slack.com/services/...`.
+    #[serde(default)]
+    pub notify: NotifyConfig,
+
+    /// Timeouts and connection pool limits of HTTP requests.
+    ///
+    /// Set via the `[http]` table, e.g. `ergo config set http.timeout_secs 600`.
+    #[serde(default)]
+    pub http: HttpConfig,
+
+    /// Biomas whose commands always show their script and ask for consent.
+    ///
+    /// Set via the `[trust]` table, e.g. `ergo --distrust-bioma ~/src/shared`
+    /// or `ergo config set trust.default untrusted`.
+    #[serde(default)]
+    pub trust: TrustConfig,
+
+    /// Whether the starter commands are installed on the first run.
+    ///
+    /// Set via the `[starter]` table, e.g. `ergo config set starter.install false`.
+    #[serde(default)]
+    pub starter: StarterConfig,
+
+    /// How much of the latency log, the usage ledger and the `--debug-llm`
+    /// captures is kept.
+    ///
+    /// Set via the `[history]` table, e.g. `ergo config set history.max_age_days 90`.
+    #[serde(default)]
+    pub history: HistoryConfig,
+
+    /// How many of the most-used commands are preloaded, and whether
+    /// long-running processes do it at startup.
+    ///
+    /// Set via the `[preload]` table, e.g. `ergo config set preload.max_commands 20`.
+    #[serde(default)]
+    pub preload: PreloadConfig,
 }
 
 /// Handles loading, saving, and managing configuration files.
@@ -152,6 +272,12 @@ impl ConfigLoader {
         if let Ok(api_key) = std::env::var("ANTHROPIC_API_KEY") {
             config.anthropic_api_key = Some(api_key);
         }
+        if let Ok(dir_name) = std::env::var("ERGO_CACHE_DIR_NAME") {
+            config.cache.dir_name = dir_name;
+        }
+        if let Ok(bioma_dir) = std::env::var("ERGO_CACHE_BIOMA_DIR") {
+            config.cache.bioma_dir = bioma_dir;
+        }
 
         Ok(config)
     }
@@ -195,6 +321,8 @@ impl ConfigLoader {
 
     /// Sets the API key and saves the configuration.
     ///
+    /// This is sugar over [`Self::set_value`] for the `anthropic_api_key` key.
+    ///
     /// # Arguments
     ///
     /// * `config` - The configuration to update
@@ -204,12 +332,140 @@ impl ConfigLoader {
     ///
     /// Returns an error if saving fails.
     pub fn set_api_key(&self, config: &mut Config, api_key: String) -> Result<()> {
+        self.set_value("anthropic_api_key", &api_key)?;
         config.anthropic_api_key = Some(api_key);
-        self.save(config)?;
         info!("API key saved to config file");
         Ok(())
     }
 
+    // =========================================================================
+    // Key/value access to the config file
+    // =========================================================================
+
+    /// Returns the value stored under `key` in the config file.
+    ///
+    /// Keys use dotted notation for nested tables (e.g., `logging.level`).
+    /// Only the config file is consulted; environment overrides are ignored.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the key is not set (or the config file doesn't exist).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config file cannot be read or parsed.
+    pub fn get_value(&self, key: &str) -> Result<Option<toml::Value>> {
+        let key = canonical_key(key);
+        let table = self.load_table()?;
+        Ok(lookup_key(&table, key).cloned())
+    }
+
+    /// Sets `key` to `raw_value` in the config file.
+    ///
+    /// The raw string is parsed according to the type the key expects: each
+    /// candidate interpretation (boolean, integer, float, string) is tried in
+    /// order and the first one that produces a valid [`Config`] is kept. This
+    /// means `ergo config set some_flag true` stores a boolean while
+    /// `ergo config set anthropic_api_key 1234` still stores a string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The key is not a known configuration key
+    /// - The value cannot be interpreted as the type the key expects
+    /// - The config file cannot be read or written
+    pub fn set_value(&self, key: &str, raw_value: &str) -> Result<()> {
+        let key = canonical_key(key);
+        validate_key(key)?;
+        let table = self.load_table()?;
+
+        for candidate in parse_value_candidates(raw_value) {
+            let mut updated = table.clone();
+            insert_key(&mut updated, key, candidate.clone())?;
+
+            let Ok(config) = toml::Value::Table(updated.clone()).try_into::<Config>() else {
+                continue;
+            };
+
+            // Keys the Config struct doesn't know about are silently dropped
+            // during deserialization; round-trip to detect them.
+            let roundtrip = toml::Value::try_from(&config)?;
+            let Some(stored) = roundtrip.as_table().and_then(|t| lookup_key(t, key)) else {
+                return Err(anyhow!("Unknown configuration key: '{}'", key));
+            };
+            // Read as something else, e.g. as part of a flattened table
+            if !same_value(stored, &candidate) {
+                continue;
+            }
+
+            self.save_table(&updated)?;
+            info!("Set config key '{}'", key);
+            return Ok(());
+        }
+
+        Err(anyhow!(
+            "Invalid value '{}' for configuration key '{}'",
+            raw_value,
+            key
+        ))
+    }
+
+    /// Removes `key` from the config file.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the key was present and removed, `false` otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config file cannot be read or written.
+    pub fn unset_value(&self, key: &str) -> Result<bool> {
+        let key = canonical_key(key);
+        validate_key(key)?;
+        let mut table = self.load_table()?;
+
+        let (parent_path, leaf) = match key.rsplit_once('.') {
+            Some((parent, leaf)) => (Some(parent), leaf),
+            None => (None, key),
+        };
+
+        let parent = match parent_path {
+            Some(path) => match lookup_key_mut(&mut table, path) {
+                Some(toml::Value::Table(t)) => t,
+                _ => return Ok(false),
+            },
+            None => &mut table,
+        };
+
+        if parent.remove(leaf).is_none() {
+            return Ok(false);
+        }
+
+        self.save_table(&table)?;
+        info!("Unset config key '{}'", key);
+        Ok(true)
+    }
+
+    /// Reads the raw TOML table from the config file (empty if missing).
+    fn load_table(&self) -> Result<toml::Table> {
+        let config_path = self.get_config_path()?;
+        if !config_path.exists() {
+            return Ok(toml::Table::new());
+        }
+        let content = fs::read_to_string(&config_path)?;
+        Ok(content.parse::<toml::Table>()?)
+    }
+
+    /// Writes a raw TOML table to the config file.
+    fn save_table(&self, table: &toml::Table) -> Result<()> {
+        let config_path = self.get_config_path()?;
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&config_path, toml::to_string_pretty(table)?)?;
+        Ok(())
+    }
+
     /// Displays configuration information to stdout.
     ///
     /// This is a convenience wrapper around [`Self::show_config_info_with_io`].
@@ -255,6 +511,7 @@ impl ConfigLoader {
         )?;
 
         writeln!(output, "\nTo set API key:")?;
+        writeln!(output, "  ergo config set anthropic_api_key <your-key>")?;
         writeln!(output, "  ergo --set-api-key <your-key>")?;
         writeln!(output, "\nOr set environment variable:")?;
         writeln!(output, "  export ANTHROPIC_API_KEY=<your-key>")?;
@@ -263,6 +520,105 @@ impl ConfigLoader {
     }
 }
 
+// =============================================================================
+// Key path helpers
+// =============================================================================
+
+/// Other names accepted for configuration keys.
+const KEY_ALIASES: &[(&str, &str)] = &[("generator.provider", "generator.backend")];
+
+/// Resolves an alias to the key it stands for.
+fn canonical_key(key: &str) -> &str {
+    KEY_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == key)
+        .map_or(key, |(_, canonical)| canonical)
+}
+
+/// Ensures a key is non-empty and has no empty dotted segments.
+fn validate_key(key: &str) -> Result<()> {
+    if key.is_empty() || key.split('.').any(|segment| segment.trim().is_empty()) {
+        return Err(anyhow!("Invalid configuration key: '{}'", key));
+    }
+    Ok(())
+}
+
+/// Returns the possible typed interpretations of a raw CLI value, most
+/// specific first.
+fn parse_value_candidates(raw: &str) -> Vec<toml::Value> {
+    let mut candidates = Vec::new();
+    if let Ok(b) = raw.parse::<bool>() {
+        candidates.push(toml::Value::Boolean(b));
+    }
+    if let Ok(i) = raw.parse::<i64>() {
+        candidates.push(toml::Value::Integer(i));
+    }
+    if let Ok(f) = raw.parse::<f64>() {
+        candidates.push(toml::Value::Float(f));
+    }
+    candidates.push(toml::Value::String(raw.to_string()));
+    // Comma-separated lists, e.g. `json,pager`
+    candidates.push(toml::Value::Array(
+        raw.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| toml::Value::String(s.to_string()))
+            .collect(),
+    ));
+    candidates
+}
+
+/// Whether `stored` is `candidate` as the config keeps it: numbers may come
+/// back as floats of the key's precision, e.g. `1` as `1.0` or `0.3` as the
+/// nearest `f32`.
+fn same_value(stored: &toml::Value, candidate: &toml::Value) -> bool {
+    let number = |value: &toml::Value| match value {
+        toml::Value::Integer(i) => Some(*i as f64),
+        toml::Value::Float(f) => Some(*f),
+        _ => None,
+    };
+    match (number(stored), number(candidate)) {
+        (Some(stored), Some(candidate)) => stored == candidate || stored as f32 == candidate as f32,
+        _ => stored == candidate,
+    }
+}
+
+/// Looks up a dotted key in a TOML table.
+fn lookup_key<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let mut segments = key.split('.');
+    let mut current = table.get(segments.next()?)?;
+    for segment in segments {
+        current = current.as_table()?.get(segment)?;
+    }
+    Some(current)
+}
+
+/// Looks up a dotted key in a TOML table, returning a mutable reference.
+fn lookup_key_mut<'a>(table: &'a mut toml::Table, key: &str) -> Option<&'a mut toml::Value> {
+    let mut segments = key.split('.');
+    let mut current = table.get_mut(segments.next()?)?;
+    for segment in segments {
+        current = current.as_table_mut()?.get_mut(segment)?;
+    }
+    Some(current)
+}
+
+/// Inserts a value at a dotted key, creating intermediate tables as needed.
+fn insert_key(table: &mut toml::Table, key: &str, value: toml::Value) -> Result<()> {
+    let mut segments: Vec<&str> = key.split('.').collect();
+    let leaf = segments.pop().ok_or_else(|| anyhow!("Empty configuration key"))?;
+
+    let mut current = table;
+    for segment in segments {
+        current = current
+            .entry(segment)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("Configuration key '{}' is not a table", segment))?;
+    }
+    current.insert(leaf.to_string(), value);
+    Ok(())
+}
 
 impl Config {
     // =========================================================================
@@ -276,7 +632,8 @@ impl Config {
     ///
     /// # Configuration Precedence
     ///
-    /// 1. `ANTHROPIC_API_KEY` environment variable (highest priority)
+    /// 1. `ANTHROPIC_API_KEY`, `ERGO_CACHE_DIR_NAME` and `ERGO_CACHE_BIOMA_DIR`
+    ///    environment variables (highest priority)
     /// 2. Config file (`~/.abiogenesis/config.toml`)
     /// 3. Default values (lowest priority)
     ///
@@ -326,6 +683,8 @@ impl Config {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::output_processing::OutputProcessor;
+    use crate::sandbox::SandboxMode;
     use std::sync::Mutex;
     use tempfile::TempDir;
 
@@ -371,6 +730,7 @@ mod tests {
     fn test_get_api_key_returns_value_when_set() {
         let config = Config {
             anthropic_api_key: Some("test-key".to_string()),
+            ..Default::default()
         };
         assert_eq!(config.get_api_key(), Some(&"test-key".to_string()));
     }
@@ -383,6 +743,7 @@ mod tests {
     fn test_config_serializes_to_toml() {
         let config = Config {
             anthropic_api_key: Some("sk-ant-test123".to_string()),
+            ..Default::default()
         };
 
         let toml_str = toml::to_string(&config).unwrap();
@@ -411,6 +772,7 @@ mod tests {
     fn test_config_roundtrip_serialization() {
         let original = Config {
             anthropic_api_key: Some("roundtrip-key".to_string()),
+            ..Default::default()
         };
 
         let toml_str = toml::to_string(&original).unwrap();
@@ -430,6 +792,7 @@ mod tests {
 
         let config = Config {
             anthropic_api_key: Some("save-test-key".to_string()),
+            ..Default::default()
         };
 
         loader.save(&config).unwrap();
@@ -627,6 +990,175 @@ mod tests {
         assert!(output_str.contains("API Key: Not set"));
     }
 
+    // =========================================================================
+    // get_value / set_value / unset_value tests
+    // =========================================================================
+
+    #[test]
+    fn test_set_value_writes_string_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        loader.set_value("anthropic_api_key", "sk-ant-set").unwrap();
+
+        let loaded = loader.load_from_file().unwrap();
+        assert_eq!(loaded.anthropic_api_key, Some("sk-ant-set".to_string()));
+    }
+
+    #[test]
+    fn test_set_value_keeps_numeric_looking_strings_as_strings() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        loader.set_value("anthropic_api_key", "12345").unwrap();
+
+        let value = loader.get_value("anthropic_api_key").unwrap();
+        assert_eq!(value, Some(toml::Value::String("12345".to_string())));
+    }
+
+    #[test]
+    fn test_set_value_rejects_unknown_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        let result = loader.set_value("no_such_key", "value");
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Unknown configuration key"));
+        assert!(!temp_dir.path().join("config.toml").exists());
+    }
+
+    #[test]
+    fn test_generator_provider_is_an_alias_of_backend() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+        fs::write(temp_dir.path().join("config.toml"), "[generator]\nprovider = \"bedrock\"\n").unwrap();
+        assert_eq!(loader.load_from_file().unwrap().generator.backend, "bedrock");
+
+        fs::remove_file(temp_dir.path().join("config.toml")).unwrap();
+        loader.set_value("generator.provider", "gemini").unwrap();
+
+        assert_eq!(loader.load_from_file().unwrap().generator.backend, "gemini");
+        assert_eq!(
+            loader.get_value("generator.provider").unwrap(),
+            Some(toml::Value::String("gemini".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_set_value_accepts_numbers_stored_as_floats() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        loader.set_value("semantic_search.min_similarity", "0.3").unwrap();
+        assert_eq!(loader.load_from_file().unwrap().semantic_search.min_similarity, 0.3);
+
+        loader.set_value("semantic_search.min_similarity", "1").unwrap();
+        assert_eq!(loader.load_from_file().unwrap().semantic_search.min_similarity, 1.0);
+    }
+
+    #[test]
+    fn test_set_value_rejects_malformed_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        assert!(loader.set_value("", "value").is_err());
+        assert!(loader.set_value("a..b", "value").is_err());
+    }
+
+    #[test]
+    fn test_set_value_writes_nested_sandbox_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        loader.set_value("sandbox.mode", "bubblewrap").unwrap();
+        loader.set_value("sandbox.required", "true").unwrap();
+
+        let loaded = loader.load_from_file().unwrap();
+        assert_eq!(loaded.sandbox.mode, SandboxMode::Bubblewrap);
+        assert!(loaded.sandbox.required);
+        assert!(loader.set_value("sandbox.mode", "chroot").is_err());
+    }
+
+    #[test]
+    fn test_set_value_parses_comma_separated_lists() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        loader.set_value("output.processors", "json, pager").unwrap();
+
+        let loaded = loader.load_from_file().unwrap();
+        assert_eq!(loaded.output.processors, vec![OutputProcessor::Json, OutputProcessor::Pager]);
+        assert!(loader.set_value("output.processors", "json,xml").is_err());
+    }
+
+    #[test]
+    fn test_get_value_returns_none_when_missing() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        assert!(loader.get_value("anthropic_api_key").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unset_value_removes_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        loader.set_value("anthropic_api_key", "to-remove").unwrap();
+        assert!(loader.unset_value("anthropic_api_key").unwrap());
+
+        assert!(loader.get_value("anthropic_api_key").unwrap().is_none());
+        assert!(!loader.unset_value("anthropic_api_key").unwrap());
+    }
+
+    #[test]
+    fn test_set_value_preserves_other_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = ConfigLoader::with_provider(Box::new(TempPathProvider::new(&temp_dir)));
+
+        let config_path = temp_dir.path().join("config.toml");
+        fs::write(&config_path, "# comment\nanthropic_api_key = \"old\"\n").unwrap();
+
+        loader.set_value("anthropic_api_key", "new").unwrap();
+
+        let loaded = loader.load_from_file().unwrap();
+        assert_eq!(loaded.anthropic_api_key, Some("new".to_string()));
+    }
+
+    #[test]
+    fn test_parse_value_candidates_orders_types() {
+        let candidates = parse_value_candidates("true");
+        assert_eq!(candidates[0], toml::Value::Boolean(true));
+        assert_eq!(candidates[candidates.len() - 2], toml::Value::String("true".to_string()));
+        assert_eq!(
+            candidates.last(),
+            Some(&toml::Value::Array(vec![toml::Value::String("true".to_string())]))
+        );
+
+        let candidates = parse_value_candidates("42");
+        assert_eq!(candidates[0], toml::Value::Integer(42));
+        assert_eq!(candidates[1], toml::Value::Float(42.0));
+
+        let candidates = parse_value_candidates("hello");
+        assert_eq!(
+            candidates,
+            vec![
+                toml::Value::String("hello".to_string()),
+                toml::Value::Array(vec![toml::Value::String("hello".to_string())]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_and_lookup_nested_key() {
+        let mut table = toml::Table::new();
+        insert_key(&mut table, "a.b.c", toml::Value::Integer(1)).unwrap();
+
+        assert_eq!(lookup_key(&table, "a.b.c"), Some(&toml::Value::Integer(1)));
+        assert!(lookup_key(&table, "a.b.missing").is_none());
+    }
+
     // =========================================================================
     // HomePathProvider tests
     // =========================================================================
@@ -0,0 +1,158 @@
+//! Version pinning of Deno standard library imports in generated scripts.
+//!
+//! Models often import `https://deno.land/std/...` without a version, which
+//! resolves to whatever std is current and breaks when it changes. Before a
+//! generated script is cached, [`pin_std_imports`] rewrites those imports to
+//! `https://deno.land/std@<deps.std_version>/...`. Imports that already name a
+//! version are left alone; `ergo --bump-deps <command>` moves them to the
+//! configured version deliberately with [`bump_std_imports`].
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+/// Base URL of the Deno standard library.
+pub const STD_URL: &str = "https://deno.land/std";
+
+/// The last std release published on deno.land.
+pub const DEFAULT_STD_VERSION: &str = "0.224.0";
+
+/// Dependency settings (the `[deps]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepsConfig {
+    /// The std version imports are pinned to, e.g. `0.224.0`.
+    #[serde(default = "default_std_version")]
+    pub std_version: String,
+    /// OSV-compatible `querybatch` endpoint `ergo --deps` checks npm
+    /// packages against (see [`crate::dep_audit`]).
+    #[serde(default = "default_advisory_url")]
+    pub advisory_url: String,
+}
+
+impl Default for DepsConfig {
+    fn default() -> Self {
+        Self {
+            std_version: default_std_version(),
+            advisory_url: default_advisory_url(),
+        }
+    }
+}
+
+fn default_std_version() -> String {
+    DEFAULT_STD_VERSION.to_string()
+}
+
+fn default_advisory_url() -> String {
+    crate::dep_audit::OSV_QUERY_BATCH_URL.to_string()
+}
+
+/// Pins unversioned std imports of `script` to `version`.
+///
+/// # Returns
+///
+/// The rewritten script and the number of imports that were pinned.
+///
+/// # Errors
+///
+/// Returns an error if `version` isn't a version number like `0.224.0`.
+pub fn pin_std_imports(script: &str, version: &str) -> Result<(String, usize)> {
+    rewrite_std_imports(script, version, false)
+}
+
+/// Moves every std import of `script`, pinned or not, to `version`.
+///
+/// # Returns
+///
+/// The rewritten script and the number of imports that changed.
+///
+/// # Errors
+///
+/// Returns an error if `version` isn't a version number like `0.224.0`.
+pub fn bump_std_imports(script: &str, version: &str) -> Result<(String, usize)> {
+    rewrite_std_imports(script, version, true)
+}
+
+fn rewrite_std_imports(script: &str, version: &str, bump: bool) -> Result<(String, usize)> {
+    if version.is_empty() || !version.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return Err(anyhow!(
+            "Invalid std version '{}': expected a version like {}",
+            version,
+            DEFAULT_STD_VERSION
+        ));
+    }
+
+    let mut rewritten = String::with_capacity(script.len());
+    let mut count = 0;
+    let mut rest = script;
+    while let Some(start) = rest.find(STD_URL) {
+        let (before, url) = rest.split_at(start);
+        rewritten.push_str(before);
+        rewritten.push_str(STD_URL);
+        let after = &url[STD_URL.len()..];
+
+        rest = if after.starts_with('/') {
+            rewritten.push('@');
+            rewritten.push_str(version);
+            count += 1;
+            after
+        } else if let Some(pinned) = after.strip_prefix('@') {
+            let end = pinned.find('/').unwrap_or(pinned.len());
+            let current = &pinned[..end];
+            rewritten.push('@');
+            if bump && current != version {
+                rewritten.push_str(version);
+                count += 1;
+            } else {
+                rewritten.push_str(current);
+            }
+            &pinned[end..]
+        } else {
+            // Some other URL that merely starts like std's
+            after
+        };
+    }
+    rewritten.push_str(rest);
+
+    Ok((rewritten, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#"import { parse } from "https://deno.land/std/flags/mod.ts";
+import { join } from "https://deno.land/std@0.190.0/path/mod.ts";
+import { exists } from "https://deno.land/std/fs/exists.ts";
+import lib from "https://deno.land/stdlib/mod.ts";
+"#;
+
+    #[test]
+    fn test_pin_std_imports_pins_only_unversioned_imports() {
+        let (pinned, count) = pin_std_imports(SCRIPT, "0.224.0").unwrap();
+
+        assert_eq!(count, 2);
+        assert!(pinned.contains(r#""https://deno.land/std@0.224.0/flags/mod.ts""#));
+        assert!(pinned.contains(r#""https://deno.land/std@0.190.0/path/mod.ts""#));
+        assert!(pinned.contains(r#""https://deno.land/std@0.224.0/fs/exists.ts""#));
+        assert!(pinned.contains(r#""https://deno.land/stdlib/mod.ts""#));
+        assert_eq!(pin_std_imports(&pinned, "0.224.0").unwrap(), (pinned.clone(), 0));
+    }
+
+    #[test]
+    fn test_bump_std_imports_moves_every_import() {
+        let (pinned, _) = pin_std_imports(SCRIPT, "0.200.0").unwrap();
+
+        let (bumped, count) = bump_std_imports(&pinned, "0.224.0").unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(bumped.matches("https://deno.land/std@0.224.0/").count(), 3);
+        assert!(bumped.ends_with("import lib from \"https://deno.land/stdlib/mod.ts\";\n"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_version() {
+        let err = pin_std_imports(SCRIPT, "latest").unwrap_err();
+
+        assert!(err.to_string().contains("Invalid std version 'latest'"));
+        assert!(bump_std_imports(SCRIPT, "").is_err());
+    }
+}
@@ -0,0 +1,382 @@
+//! Dependency inventory and advisory checks for cached scripts.
+//!
+//! `ergo --deps [command]` lists the remote imports of cached scripts
+//! (`npm:`, `jsr:` and `https://` specifiers) and flags the ones worth a look:
+//!
+//! - **vulnerable**: npm packages (imported with `npm:` or through esm.sh,
+//!   unpkg or jsDelivr) with known advisories in the advisory database,
+//!   [OSV](https://osv.dev) by default (`deps.advisory_url`)
+//! - **outdated**: std imports older than `deps.std_version`
+//!   (see [`crate::deno_deps`])
+//! - **unpinned**: package imports without an exact version
+
+use crate::deno_deps::STD_URL;
+use crate::http_client::HttpClient;
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+use std::fmt;
+use tracing::info;
+
+/// OSV's batch query endpoint, the default advisory source.
+pub const OSV_QUERY_BATCH_URL: &str = "https://api.osv.dev/v1/querybatch";
+
+/// CDNs that serve npm packages at `<cdn>/<package>@<version>/...`.
+const NPM_CDNS: [&str; 3] = ["https://esm.sh/", "https://unpkg.com/", "https://cdn.jsdelivr.net/npm/"];
+
+/// Where an import comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registry {
+    /// An npm package, via `npm:` or an npm CDN.
+    Npm,
+    /// A JSR package (`jsr:`).
+    Jsr,
+    /// The Deno standard library on deno.land.
+    DenoStd,
+    /// A third-party module on deno.land/x.
+    DenoX,
+    /// Any other URL.
+    Url,
+}
+
+/// A remote import of a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// The specifier as written, e.g. `npm:lodash@4.17.21`.
+    pub specifier: String,
+    /// Where it comes from.
+    pub registry: Registry,
+    /// The package name, e.g. `lodash`, `@std/path` or `x/oak`.
+    pub name: String,
+    /// The requested version or range, if any.
+    pub version: Option<String>,
+}
+
+impl Import {
+    /// Parses a remote specifier; returns `None` for local paths.
+    pub fn parse(specifier: &str) -> Option<Self> {
+        let (registry, name, version) = if let Some(path) = specifier.strip_prefix("npm:") {
+            let (name, version) = split_package(path);
+            (Registry::Npm, name, version)
+        } else if let Some(path) = specifier.strip_prefix("jsr:") {
+            let (name, version) = split_package(path);
+            (Registry::Jsr, name, version)
+        } else if let Some(rest) = specifier.strip_prefix(STD_URL)
+            && (rest.starts_with('/') || rest.starts_with('@'))
+        {
+            let version = rest
+                .strip_prefix('@')
+                .map(|v| v.split('/').next().unwrap_or_default().to_string());
+            (Registry::DenoStd, "std".to_string(), version)
+        } else if let Some(path) = specifier.strip_prefix("https://deno.land/x/") {
+            let (name, version) = split_package(path);
+            (Registry::DenoX, format!("x/{}", name), version)
+        } else if let Some(path) = NPM_CDNS.iter().find_map(|cdn| specifier.strip_prefix(cdn)) {
+            let (name, version) = split_package(path);
+            (Registry::Npm, name, version)
+        } else if specifier.starts_with("https://") || specifier.starts_with("http://") {
+            (Registry::Url, specifier.to_string(), None)
+        } else {
+            return None;
+        };
+
+        Some(Self {
+            specifier: specifier.to_string(),
+            registry,
+            name,
+            version,
+        })
+    }
+
+    /// The version, if it names one exact release rather than a range.
+    pub fn exact_version(&self) -> Option<&str> {
+        let version = self.version.as_deref()?.trim_start_matches('v');
+        let exact = version.starts_with(|c: char| c.is_ascii_digit())
+            && version
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '+');
+        exact.then_some(version)
+    }
+}
+
+/// Splits `@scope/name@1.2.3/sub/path` into `("@scope/name", Some("1.2.3"))`.
+fn split_package(path: &str) -> (String, Option<String>) {
+    let (scope, rest) = match path.strip_prefix('@').and_then(|p| p.split_once('/')) {
+        Some((scope, rest)) => (Some(scope), rest),
+        None => (None, path),
+    };
+    let segment = rest.split('/').next().unwrap_or_default();
+    let (name, version) = match segment.split_once('@') {
+        Some((name, version)) => (name, Some(version.to_string())),
+        None => (segment, None),
+    };
+    match scope {
+        Some(scope) => (format!("@{}/{}", scope, name), version),
+        None => (name.to_string(), version),
+    }
+}
+
+/// Finds the remote imports of a script, in order of first appearance.
+///
+/// Every string literal holding a remote specifier counts, which covers
+/// static imports, `export ... from` and dynamic `import()`.
+pub fn find_imports(script: &str) -> Vec<Import> {
+    let mut imports: Vec<Import> = Vec::new();
+    let mut chars = script.char_indices();
+    while let Some((start, c)) = chars.next() {
+        if !matches!(c, '"' | '\'' | '`') {
+            continue;
+        }
+        let Some((end, _)) = chars.by_ref().find(|&(_, d)| d == c || d == '\n') else {
+            break;
+        };
+        if let Some(import) = Import::parse(&script[start + 1..end])
+            && !imports.iter().any(|i| i.specifier == import.specifier)
+        {
+            imports.push(import);
+        }
+    }
+    imports
+}
+
+/// Something worth a look about an import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The package has known advisories (their IDs).
+    Vulnerable(Vec<String>),
+    /// A std import older than the configured version.
+    Outdated { configured: String },
+    /// A package import without an exact version.
+    Unpinned,
+}
+
+/// An import with what was found about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditedImport {
+    pub import: Import,
+    pub issues: Vec<Issue>,
+}
+
+/// The imports of one command, its platform variants included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandInventory {
+    pub command: String,
+    pub imports: Vec<AuditedImport>,
+}
+
+impl CommandInventory {
+    /// Lists the imports of a command's scripts and flags outdated and
+    /// unpinned ones. Advisories are added by [`check_advisories`].
+    pub fn audit(command: &str, scripts: &[String], std_version: &str) -> Self {
+        let mut imports: Vec<AuditedImport> = Vec::new();
+        for import in scripts.iter().flat_map(|script| find_imports(script)) {
+            if imports.iter().any(|a| a.import.specifier == import.specifier) {
+                continue;
+            }
+            let mut issues = Vec::new();
+            match import.registry {
+                Registry::Url => {}
+                _ if import.exact_version().is_none() => issues.push(Issue::Unpinned),
+                Registry::DenoStd
+                    if import
+                        .exact_version()
+                        .is_some_and(|v| version_key(v) < version_key(std_version)) =>
+                {
+                    issues.push(Issue::Outdated {
+                        configured: std_version.to_string(),
+                    })
+                }
+                _ => {}
+            }
+            imports.push(AuditedImport { import, issues });
+        }
+        Self {
+            command: command.to_string(),
+            imports,
+        }
+    }
+
+    /// Whether any import has an issue.
+    pub fn has_issues(&self) -> bool {
+        self.imports.iter().any(|a| !a.issues.is_empty())
+    }
+}
+
+/// Numeric parts of a version, for ordering (`0.190.0` < `0.224.0`).
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .split(['.', '-', '+'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+impl fmt::Display for CommandInventory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.imports.len();
+        write!(f, "📦 {} ({} import{})", self.command, count, if count == 1 { "" } else { "s" })?;
+        for audited in &self.imports {
+            write!(f, "\n   ")?;
+            let specifier = &audited.import.specifier;
+            match audited.issues.first() {
+                None => write!(f, "✅ {}", specifier)?,
+                Some(Issue::Vulnerable(ids)) => write!(f, "🚨 {} — vulnerable: {}", specifier, ids.join(", "))?,
+                Some(Issue::Outdated { configured }) => write!(
+                    f,
+                    "⬆️  {} — outdated, deps.std_version is {} (ergo --bump-deps {})",
+                    specifier, configured, self.command
+                )?,
+                Some(Issue::Unpinned) => write!(f, "⚠️  {} — unpinned", specifier)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Looks up the npm imports with exact versions in the advisory database at
+/// `url` (an OSV-compatible `querybatch` endpoint) and flags the vulnerable
+/// ones.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or the answer isn't understood;
+/// the inventories are left unchanged then.
+pub async fn check_advisories<H: HttpClient>(client: &H, url: &str, inventories: &mut [CommandInventory]) -> Result<()> {
+    let mut packages: Vec<(String, String)> = Vec::new();
+    for audited in inventories.iter().flat_map(|inv| &inv.imports) {
+        let import = &audited.import;
+        if import.registry == Registry::Npm
+            && let Some(version) = import.exact_version()
+        {
+            let package = (import.name.clone(), version.to_string());
+            if !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+    }
+    if packages.is_empty() {
+        return Ok(());
+    }
+
+    let queries: Vec<Value> = packages
+        .iter()
+        .map(|(name, version)| json!({"package": {"name": name, "ecosystem": "npm"}, "version": version}))
+        .collect();
+    let response = client.post_json(url, &[("content-type", "application/json")], &json!({"queries": queries})).await?;
+    let response: Value = serde_json::from_str(&response)
+        .map_err(|_| anyhow!("Unexpected answer from the advisory database: {}", response))?;
+    let results = response
+        .get("results")
+        .and_then(Value::as_array)
+        .filter(|results| results.len() == packages.len())
+        .ok_or_else(|| anyhow!("Unexpected answer from the advisory database: {}", response))?;
+
+    for (package, result) in packages.iter().zip(results) {
+        let ids: Vec<String> = result
+            .get("vulns")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|vuln| vuln.get("id").and_then(Value::as_str).map(str::to_string))
+            .collect();
+        if ids.is_empty() {
+            continue;
+        }
+        info!("{}@{} has advisories: {:?}", package.0, package.1, ids);
+        for audited in inventories.iter_mut().flat_map(|inv| &mut inv.imports) {
+            let import = &audited.import;
+            if import.registry == Registry::Npm
+                && import.name == package.0
+                && import.exact_version() == Some(package.1.as_str())
+            {
+                audited.issues.insert(0, Issue::Vulnerable(ids.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockHttpClient;
+
+    const SCRIPT: &str = r#"import { join } from "https://deno.land/std@0.190.0/path/mod.ts";
+import { Application } from 'https://deno.land/x/oak/mod.ts';
+import _ from "npm:lodash@4.17.20";
+import chalk from "npm:chalk@^5";
+import { z } from "jsr:@std/path@1.0.8/join";
+import React from "https://esm.sh/react@18.2.0";
+import "./local.ts";
+const config = await import(`https://example.com/config.js`);
+console.log("not an import: https");
+"#;
+
+    #[test]
+    fn test_find_imports_parses_registries_and_versions() {
+        let imports = find_imports(SCRIPT);
+
+        let summary: Vec<(Registry, &str, Option<&str>)> = imports
+            .iter()
+            .map(|i| (i.registry, i.name.as_str(), i.version.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Registry::DenoStd, "std", Some("0.190.0")),
+                (Registry::DenoX, "x/oak", None),
+                (Registry::Npm, "lodash", Some("4.17.20")),
+                (Registry::Npm, "chalk", Some("^5")),
+                (Registry::Jsr, "@std/path", Some("1.0.8")),
+                (Registry::Npm, "react", Some("18.2.0")),
+                (Registry::Url, "https://example.com/config.js", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_audit_flags_outdated_and_unpinned_imports() {
+        let inventory = CommandInventory::audit("weather", &[SCRIPT.to_string()], "0.224.0");
+
+        let issues: Vec<&[Issue]> = inventory.imports.iter().map(|a| a.issues.as_slice()).collect();
+        assert_eq!(
+            issues[0],
+            &[Issue::Outdated {
+                configured: "0.224.0".to_string()
+            }]
+        );
+        assert_eq!(issues[1], &[Issue::Unpinned]);
+        assert!(issues[2].is_empty());
+        assert_eq!(issues[3], &[Issue::Unpinned]);
+        assert!(issues[6].is_empty());
+        assert!(inventory.has_issues());
+        assert!(!CommandInventory::audit("hello", &["console.log('hi')".to_string()], "0.224.0").has_issues());
+    }
+
+    #[tokio::test]
+    async fn test_check_advisories_flags_vulnerable_npm_packages() {
+        let client = MockHttpClient::new().on(OSV_QUERY_BATCH_URL, r#"{"results": [{"vulns": [{"id": "GHSA-35jh-r3h4-6jhm"}]}, {}]}"#);
+        let mut inventories = vec![CommandInventory::audit("weather", &[SCRIPT.to_string()], "0.224.0")];
+
+        check_advisories(&client, OSV_QUERY_BATCH_URL, &mut inventories).await.unwrap();
+
+        let queries = &client.requests()[0].1["queries"];
+        assert_eq!(queries.as_array().unwrap().len(), 2);
+        assert_eq!(queries[0], json!({"package": {"name": "lodash", "ecosystem": "npm"}, "version": "4.17.20"}));
+        assert_eq!(inventories[0].imports[2].issues, vec![Issue::Vulnerable(vec!["GHSA-35jh-r3h4-6jhm".to_string()])]);
+        assert!(inventories[0].imports[5].issues.is_empty());
+
+        let report = inventories[0].to_string();
+        assert!(report.starts_with("📦 weather (7 imports)"));
+        assert!(report.contains("🚨 npm:lodash@4.17.20 — vulnerable: GHSA-35jh-r3h4-6jhm"));
+        assert!(report.contains("(ergo --bump-deps weather)"));
+    }
+
+    #[tokio::test]
+    async fn test_check_advisories_rejects_unexpected_answers() {
+        let client = MockHttpClient::new().with_response(r#"{"results": []}"#);
+        let mut inventories = vec![CommandInventory::audit("weather", &[SCRIPT.to_string()], "0.224.0")];
+        let before = inventories.clone();
+
+        assert!(check_advisories(&client, OSV_QUERY_BATCH_URL, &mut inventories).await.is_err());
+        assert_eq!(inventories, before);
+    }
+}
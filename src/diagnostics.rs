@@ -0,0 +1,441 @@
+//! Bug report bundle generation.
+//!
+//! `ergo --report-bug` collects everything a maintainer usually asks for when
+//! triaging an issue into a single `.tar.gz` file:
+//!
+//! - `version.txt` - ergo version, platform and Deno version
+//! - `ergo.log` - the most recent log lines
+//! - `last_execution.json` - the last execution context (for `--nope`)
+//! - `cache_entry.json` / `script.ts` - the cache entry of the last command
+//! - `config.toml` - the configuration with secrets redacted
+//!
+//! Every text file is passed through [`redact_secrets`] before it is added to
+//! the bundle so API keys never leave the machine.
+
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Number of log lines included in the bundle.
+pub const LOG_TAIL_LINES: usize = 200;
+
+/// Placeholder written in place of redacted values.
+const REDACTED: &str = "[REDACTED]";
+
+/// Config keys whose values are always redacted.
+const SECRET_KEY_MARKERS: &[&str] = &["key", "token", "secret", "password"];
+
+/// Token prefixes that identify API credentials inside free text.
+const SECRET_PREFIXES: &[&str] = &[
+    "sk-ant-",
+    // Google API keys and OAuth access tokens (Gemini backend)
+    "AIza",
+    "ya29.",
+];
+
+/// A sanitized bug report ready to be written as a tarball.
+#[derive(Debug, Default)]
+pub struct BugReport {
+    files: Vec<(String, String)>,
+}
+
+impl BugReport {
+    /// Collects a bug report from the given config directory.
+    ///
+    /// Missing pieces (no log yet, no previous execution, ...) are skipped and
+    /// noted in `version.txt` instead of failing the whole report.
+    ///
+    /// # Arguments
+    ///
+    /// * `config_dir` - The ergo configuration directory (`~/.abiogenesis`)
+    /// * `cache_entry` - The cache entry and script of the last executed command, if found
+    /// * `deno_version` - Output of `deno --version`, if available
+    pub fn collect(
+        config_dir: &Path,
+        cache_entry: Option<(serde_json::Value, Option<String>)>,
+        deno_version: Option<String>,
+    ) -> Result<Self> {
+        let mut report = Self::default();
+        let mut notes = Vec::new();
+
+        let secrets = known_secrets(config_dir);
+        let redact = |text: &str| {
+            let mut redacted = redact_secrets(text);
+            for secret in &secrets {
+                redacted = redacted.replace(secret.as_str(), REDACTED);
+            }
+            redacted
+        };
+
+        match fs::read_to_string(config_dir.join("ergo.log")) {
+            Ok(log) => report.add("ergo.log", redact(&tail_lines(&log, LOG_TAIL_LINES))),
+            Err(_) => notes.push("log file not found".to_string()),
+        }
+
+        match fs::read_to_string(config_dir.join("last_execution.json")) {
+            Ok(context) => report.add("last_execution.json", redact(&context)),
+            Err(_) => notes.push("no previous execution context".to_string()),
+        }
+
+        match cache_entry {
+            Some((entry, script)) => {
+                report.add(
+                    "cache_entry.json",
+                    redact(&serde_json::to_string_pretty(&entry)?),
+                );
+                if let Some(script) = script {
+                    report.add("script.ts", redact(&script));
+                }
+            }
+            None => notes.push("no cache entry for the last command".to_string()),
+        }
+
+        match fs::read_to_string(config_dir.join("config.toml")) {
+            Ok(content) => match redact_config(&content) {
+                Ok(redacted) => report.add("config.toml", redact(&redacted)),
+                // A broken config is often the bug being reported
+                Err(e) => {
+                    notes.push(format!("config.toml doesn't parse, redacted line by line: {}", e));
+                    report.add("config.toml", redact(&redact_config_lines(&content)));
+                }
+            },
+            Err(_) => notes.push("no config file".to_string()),
+        }
+
+        let mut version = version_info(deno_version.as_deref());
+        if !notes.is_empty() {
+            version.push_str("\nNotes:\n");
+            for note in notes {
+                version.push_str(&format!("- {}\n", note));
+            }
+        }
+        report.files.insert(0, ("version.txt".to_string(), version));
+
+        Ok(report)
+    }
+
+    /// Adds a file to the report.
+    fn add(&mut self, name: &str, content: String) {
+        self.files.push((name.to_string(), content));
+    }
+
+    /// Returns the names of the files in the report.
+    pub fn file_names(&self) -> Vec<&str> {
+        self.files.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Returns the content of a file in the report.
+    pub fn file(&self, name: &str) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, content)| content.as_str())
+    }
+
+    /// Writes the report as a gzip-compressed tarball.
+    ///
+    /// All files are placed under a single `ergo-bug-report/` directory.
+    pub fn write_tarball(&self, path: &Path) -> Result<()> {
+        let file = fs::File::create(path)?;
+        let encoder = GzEncoder::new(file, Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+
+        for (name, content) in &self.files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(
+                &mut header,
+                format!("ergo-bug-report/{}", name),
+                content.as_bytes(),
+            )?;
+        }
+
+        builder.into_inner()?.finish()?;
+        info!("Wrote bug report to {}", path.display());
+        Ok(())
+    }
+}
+
+/// Returns the default file name for a report created at `timestamp`.
+pub fn default_report_path(timestamp: u64) -> PathBuf {
+    PathBuf::from(format!("ergo-bug-report-{}.tar.gz", timestamp))
+}
+
+/// Describes the ergo build and host platform.
+fn version_info(deno_version: Option<&str>) -> String {
+    format!(
+        "ergo {}\nplatform: {}-{}\ndeno: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        deno_version
+            .map(|v| v.lines().next().unwrap_or("").trim().to_string())
+            .unwrap_or_else(|| "not found".to_string()),
+    )
+}
+
+/// Returns the last `n` lines of `text`.
+pub fn tail_lines(text: &str, n: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let start = lines.len().saturating_sub(n);
+    let mut tail = lines[start..].join("\n");
+    if !tail.is_empty() {
+        tail.push('\n');
+    }
+    tail
+}
+
+/// Replaces credential-looking tokens in free text with a placeholder.
+///
+/// Tokens are detected by well-known prefixes (e.g., `sk-ant-`) and run until
+/// the next whitespace or quote character.
+pub fn redact_secrets(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some((index, prefix)) = SECRET_PREFIXES
+        .iter()
+        .filter_map(|p| rest.find(p).map(|i| (i, *p)))
+        .min_by_key(|(i, _)| *i)
+    {
+        result.push_str(&rest[..index]);
+        result.push_str(REDACTED);
+        let after = &rest[index + prefix.len()..];
+        let end = after
+            .find(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+            .unwrap_or(after.len());
+        rest = &after[end..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Redacts the values of secret-looking keys in a TOML config file.
+///
+/// Keys are matched case-insensitively against [`SECRET_KEY_MARKERS`] at any
+/// nesting level.
+pub fn redact_config(content: &str) -> Result<String> {
+    let mut table: toml::Table = content.parse()?;
+    redact_table(&mut table);
+    Ok(toml::to_string_pretty(&table)?)
+}
+
+/// Redacts the values of secret-looking keys in a config file that isn't
+/// valid TOML, one `key = value` line at a time.
+pub fn redact_config_lines(content: &str) -> String {
+    let mut redacted = String::with_capacity(content.len());
+    for line in content.lines() {
+        match secret_assignment(line) {
+            Some((key, _)) => redacted.push_str(&format!("{}= \"{}\"", key, REDACTED)),
+            None => redacted.push_str(line),
+        }
+        redacted.push('\n');
+    }
+    redacted
+}
+
+/// Splits a `key = value` line whose key looks secret into the key (up to
+/// the `=`) and the unquoted value.
+fn secret_assignment(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once('=')?;
+    let lowercase = key.to_lowercase();
+    SECRET_KEY_MARKERS
+        .iter()
+        .any(|m| lowercase.contains(m))
+        .then(|| (key, value.trim().trim_matches(|c| c == '"' || c == '\'')))
+}
+
+fn redact_table(table: &mut toml::Table) {
+    for (key, value) in table.iter_mut() {
+        let lowercase = key.to_lowercase();
+        if let toml::Value::Table(nested) = value {
+            redact_table(nested);
+        } else if SECRET_KEY_MARKERS.iter().any(|m| lowercase.contains(m)) {
+            *value = toml::Value::String(REDACTED.to_string());
+        }
+    }
+}
+
+/// Collects secret values from the config file and environment so they can
+/// be scrubbed from any text, regardless of their format.
+fn known_secrets(config_dir: &Path) -> Vec<String> {
+    fn collect(table: &toml::Table, secrets: &mut Vec<String>) {
+        for (key, value) in table {
+            let lowercase = key.to_lowercase();
+            match value {
+                toml::Value::Table(nested) => collect(nested, secrets),
+                toml::Value::String(s)
+                    if !s.is_empty() && SECRET_KEY_MARKERS.iter().any(|m| lowercase.contains(m)) =>
+                {
+                    secrets.push(s.clone())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut secrets = Vec::new();
+    if let Ok(content) = fs::read_to_string(config_dir.join("config.toml")) {
+        match content.parse::<toml::Table>() {
+            Ok(table) => collect(&table, &mut secrets),
+            Err(_) => secrets.extend(
+                content
+                    .lines()
+                    .filter_map(secret_assignment)
+                    .map(|(_, value)| value.to_string())
+                    .filter(|value| !value.is_empty()),
+            ),
+        }
+    }
+    if let Ok(key) = std::env::var("ANTHROPIC_API_KEY")
+        && !key.is_empty()
+    {
+        secrets.push(key);
+    }
+    secrets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_redact_secrets_replaces_api_keys() {
+        let text = "using key sk-ant-abc123XYZ for request";
+        assert_eq!(redact_secrets(text), "using key [REDACTED] for request");
+    }
+
+    #[test]
+    fn test_redact_secrets_replaces_google_credentials() {
+        let text = "x-goog-api-key: AIzaSyA1b2 authorization: Bearer ya29.a0Af";
+        assert_eq!(redact_secrets(text), "x-goog-api-key: [REDACTED] authorization: Bearer [REDACTED]");
+    }
+
+    #[test]
+    fn test_redact_secrets_stops_at_quotes() {
+        let text = r#"{"x-api-key": "sk-ant-secret"}"#;
+        assert_eq!(redact_secrets(text), r#"{"x-api-key": "[REDACTED]"}"#);
+    }
+
+    #[test]
+    fn test_redact_secrets_leaves_plain_text_untouched() {
+        let text = "nothing to see here";
+        assert_eq!(redact_secrets(text), text);
+    }
+
+    #[test]
+    fn test_redact_config_hides_secret_keys() {
+        let content = "anthropic_api_key = \"my-key\"\n[webhook]\ntoken = \"t\"\nurl = \"https://example.com\"\n";
+        let redacted = redact_config(content).unwrap();
+
+        assert!(!redacted.contains("my-key"));
+        assert!(!redacted.contains("\"t\""));
+        assert!(redacted.contains("https://example.com"));
+        assert!(redacted.contains(REDACTED));
+    }
+
+    #[test]
+    fn test_tail_lines_returns_last_lines() {
+        let text = "1\n2\n3\n4\n";
+        assert_eq!(tail_lines(text, 2), "3\n4\n");
+        assert_eq!(tail_lines(text, 10), "1\n2\n3\n4\n");
+        assert_eq!(tail_lines("", 5), "");
+    }
+
+    #[test]
+    fn test_collect_redacts_configured_key_everywhere() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("config.toml"),
+            "anthropic_api_key = \"plainsecret\"\n",
+        )
+        .unwrap();
+        fs::write(
+            temp_dir.path().join("ergo.log"),
+            "INFO request with plainsecret\n",
+        )
+        .unwrap();
+
+        let report = BugReport::collect(temp_dir.path(), None, None).unwrap();
+
+        assert!(!report.file("ergo.log").unwrap().contains("plainsecret"));
+        assert!(!report.file("config.toml").unwrap().contains("plainsecret"));
+    }
+
+    #[test]
+    fn test_collect_redacts_a_malformed_config_line_by_line() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("config.toml"),
+            "anthropic_api_key = \"plainsecret\"\n[generator\nmodel = \"opus\"\n",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("ergo.log"), "INFO request with plainsecret\n").unwrap();
+
+        let report = BugReport::collect(temp_dir.path(), None, None).unwrap();
+
+        let config = report.file("config.toml").unwrap();
+        assert_eq!(config, "anthropic_api_key = \"[REDACTED]\"\n[generator\nmodel = \"opus\"\n");
+        assert!(!report.file("ergo.log").unwrap().contains("plainsecret"));
+        assert!(report.file("version.txt").unwrap().contains("config.toml doesn't parse"));
+    }
+
+    #[test]
+    fn test_collect_notes_missing_pieces() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let report = BugReport::collect(temp_dir.path(), None, Some("deno 2.0.0\nv8".to_string())).unwrap();
+
+        assert_eq!(report.file_names(), vec!["version.txt"]);
+        let version = report.file("version.txt").unwrap();
+        assert!(version.contains("deno 2.0.0"));
+        assert!(version.contains("no previous execution context"));
+    }
+
+    #[test]
+    fn test_collect_includes_cache_entry_and_script() {
+        let temp_dir = TempDir::new().unwrap();
+        let entry = serde_json::json!({"command": {"name": "hello"}});
+
+        let report = BugReport::collect(
+            temp_dir.path(),
+            Some((entry, Some("console.log('hi');".to_string()))),
+            None,
+        )
+        .unwrap();
+
+        assert!(report.file("cache_entry.json").unwrap().contains("hello"));
+        assert_eq!(report.file("script.ts"), Some("console.log('hi');"));
+    }
+
+    #[test]
+    fn test_write_tarball_contains_all_files() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("ergo.log"), "line\n").unwrap();
+        let report = BugReport::collect(temp_dir.path(), None, None).unwrap();
+
+        let tarball = temp_dir.path().join("report.tar.gz");
+        report.write_tarball(&tarball).unwrap();
+
+        let mut archive = tar::Archive::new(GzDecoder::new(fs::File::open(&tarball).unwrap()));
+        let mut names = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            names.push(entry.path().unwrap().to_string_lossy().to_string());
+        }
+
+        assert!(names.contains(&"ergo-bug-report/version.txt".to_string()));
+        assert!(names.contains(&"ergo-bug-report/ergo.log".to_string()));
+    }
+}
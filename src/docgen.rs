@@ -0,0 +1,219 @@
+//! Man pages for cached commands.
+//!
+//! Renders a command's cache metadata (description, usage examples,
+//! permissions, presets, note and author) as a roff page and installs it in the user's man
+//! directory, so `man <command>` documents `ergo <command>`.
+
+use crate::command_cache::Owner;
+use crate::llm_generator::GeneratedCommand;
+use crate::providers::utc_date;
+use anyhow::{Context, Result, anyhow};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Returns the directory user man pages for section 1 are installed in:
+/// `$XDG_DATA_HOME/man/man1`, or `~/.local/share/man/man1`.
+pub fn user_man_dir() -> Result<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("share")))
+        .ok_or_else(|| anyhow!("Could not determine the home directory"))?;
+    Ok(data_dir.join("man").join("man1"))
+}
+
+/// Renders a roff man page for a cached command.
+///
+/// # Arguments
+///
+/// * `command` - The command's cache metadata
+/// * `presets` - The command's saved presets and their arguments
+/// * `owner` - Who generated the command, if recorded
+/// * `note` - The command's note, if any
+/// * `timestamp` - Unix timestamp shown as the page's date
+pub fn render_man_page(
+    command: &GeneratedCommand,
+    presets: &[(&str, &[String])],
+    owner: Option<&Owner>,
+    note: Option<&str>,
+    timestamp: u64,
+) -> String {
+    let mut page = String::new();
+    let name = escape(&command.name);
+
+    let _ = writeln!(page, ".TH \"{}\" 1 \"{}\" \"ergo\" \"Generated Commands\"", name.to_uppercase(), date(timestamp));
+    let _ = writeln!(page, ".SH NAME");
+    let _ = writeln!(page, "{} \\- {}", name, escape(&command.description));
+    let _ = writeln!(page, ".SH SYNOPSIS");
+    let _ = writeln!(page, ".B ergo {}", name);
+    let _ = writeln!(page, "[\\fIARGS\\fR...]");
+    let _ = writeln!(page, ".SH DESCRIPTION");
+    let _ = writeln!(page, "{}", escape(&command.description));
+    let _ = writeln!(page, ".PP");
+    let _ = writeln!(
+        page,
+        "This command was generated by ergo and runs in the Deno sandbox with the permissions listed below."
+    );
+
+    if !command.examples.is_empty() {
+        let _ = writeln!(page, ".SH EXAMPLES");
+        for example in &command.examples {
+            let _ = writeln!(page, ".TP");
+            let _ = writeln!(page, ".B {}", escape(&example.usage));
+            let _ = writeln!(page, "{}", escape(&example.explanation));
+        }
+    }
+
+    let _ = writeln!(page, ".SH PERMISSIONS");
+    if command.permissions.is_empty() {
+        let _ = writeln!(page, "None.");
+    }
+    for permission in &command.permissions {
+        let _ = writeln!(page, ".TP");
+        let _ = writeln!(page, ".B {}", escape(&permission.permission));
+        let _ = writeln!(page, "{}", escape(&permission.reason));
+    }
+
+    if !presets.is_empty() {
+        let _ = writeln!(page, ".SH PRESETS");
+        for (preset, args) in presets {
+            let _ = writeln!(page, ".TP");
+            let _ = writeln!(page, ".B ergo {} @{}", name, escape(preset));
+            let _ = writeln!(page, "{}", escape(&args.join(" ")));
+        }
+    }
+
+    if let Some(note) = note {
+        let _ = writeln!(page, ".SH NOTES");
+        let _ = writeln!(page, "{}", escape(note));
+    }
+
+    if let Some(owner) = owner {
+        let _ = writeln!(page, ".SH AUTHOR");
+        let _ = writeln!(page, "Generated by {}.", escape(&owner.to_string()));
+    }
+
+    let _ = writeln!(page, ".SH SEE ALSO");
+    let _ = writeln!(page, ".BR ergo (1)");
+    page
+}
+
+/// Writes a man page for `command_name` into `dir`, creating it if needed.
+///
+/// # Returns
+///
+/// The path of the installed page.
+pub fn install_man_page(dir: &Path, command_name: &str, page: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("{}.1", command_name));
+    fs::write(&path, page).with_context(|| format!("Failed to write {}", path.display()))?;
+    info!("Installed man page for '{}' at {:?}", command_name, path);
+    Ok(path)
+}
+
+/// Removes the man page installed for `command_name`, if any.
+///
+/// # Returns
+///
+/// `true` if a page was removed.
+pub fn remove_man_page(dir: &Path, command_name: &str) -> Result<bool> {
+    let path = dir.join(format!("{}.1", command_name));
+    if !path.exists() {
+        return Ok(false);
+    }
+    fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(true)
+}
+
+/// Escapes text for roff: backslashes, hyphens, and leading control characters.
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-").replace('\n', " ");
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD` (UTC).
+fn date(timestamp: u64) -> String {
+    let (year, month, day) = utc_date(timestamp);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_generator::{PermissionRequest, UsageExample};
+    use tempfile::TempDir;
+
+    fn weather() -> GeneratedCommand {
+        GeneratedCommand {
+            name: "weather".to_string(),
+            description: "Shows the weather for a city".to_string(),
+            script_file: "weather.ts".to_string(),
+            permissions: vec![PermissionRequest {
+                permission: "--allow-net=wttr.in".to_string(),
+                reason: "Fetch the forecast".to_string(),
+            }],
+            sensitive_output: false,
+            output_format: crate::output_processing::OutputFormat::Text,
+            examples: vec![UsageExample {
+                usage: "ergo weather --days 3 London".to_string(),
+                explanation: "Three-day forecast for London".to_string(),
+            }],
+            assumptions: vec![],
+            confidence: None,
+            platforms: vec![],
+            variants: Default::default(),
+            provenance: None,
+            spec: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_render_man_page_sections() {
+        let args = vec!["Paris".to_string()];
+        let owner = Owner {
+            created_by: Some("ada".to_string()),
+            host: Some("build-box".to_string()),
+        };
+        let page = render_man_page(&weather(), &[("paris", &args)], Some(&owner), Some("For the trip"), 0);
+
+        assert!(page.starts_with(".TH \"WEATHER\" 1 \"1970-01-01\""));
+        assert!(page.contains(".SH NAME\nweather \\- Shows the weather for a city\n"));
+        assert!(page.contains(".B ergo weather \\-\\-days 3 London\nThree\\-day forecast for London\n"));
+        assert!(page.contains(".B \\-\\-allow\\-net=wttr.in\nFetch the forecast\n"));
+        assert!(page.contains(".B ergo weather @paris\nParis\n"));
+        assert!(page.contains(".SH NOTES\nFor the trip\n"));
+        assert!(page.contains(".SH AUTHOR\nGenerated by ada on build\\-box.\n"));
+    }
+
+    #[test]
+    fn test_escape_guards_control_lines() {
+        assert_eq!(escape(".hidden"), "\\&.hidden");
+        assert_eq!(escape("a\\b"), "a\\eb");
+    }
+
+    #[test]
+    fn test_date_formats_utc_days() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_767_225_599), "2025-12-31");
+    }
+
+    #[test]
+    fn test_install_and_remove_man_page() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("man1");
+
+        let path = install_man_page(&dir, "weather", ".TH test").unwrap();
+
+        assert_eq!(path, dir.join("weather.1"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), ".TH test");
+        assert!(remove_man_page(&dir, "weather").unwrap());
+        assert!(!remove_man_page(&dir, "weather").unwrap());
+    }
+}
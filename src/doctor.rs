@@ -0,0 +1,420 @@
+//! Environment diagnostics for `ergo doctor`.
+//!
+//! Runs a series of independent checks and reports each as passed, warned,
+//! failed or skipped, with a hint on how to fix anything that isn't right:
+//!
+//! - Deno is installed and recent enough for generated scripts
+//! - the API key is accepted by the Anthropic API
+//! - the config file parses
+//! - every bioma's `commands.json` parses and its scripts exist
+//! - every bioma directory is writable
+//! - the system clock agrees with the API server's
+
+use crate::command_cache;
+use crate::config::ConfigLoader;
+use crate::environment;
+use crate::executor::ProcessRunner;
+use crate::http_client::HttpClient;
+use crate::llm_generator::ANTHROPIC_MODEL;
+use crate::plugins::BUILTIN_BACKEND;
+use serde_json::{Value, json};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Oldest Deno release generated scripts work with (`Deno.Command` is stable since 1.31).
+pub const MIN_DENO_VERSION: (u32, u32) = (1, 31);
+
+/// Clock skew beyond which requests fail (AWS SigV4 rejects signatures older than this).
+pub const MAX_CLOCK_SKEW_SECS: u64 = 5 * 60;
+
+/// Server whose clock and key validation the checks use.
+const ANTHROPIC_API: &str = "https://api.anthropic.com";
+
+/// The outcome of a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+    /// The check doesn't apply, e.g. the API key check with another backend.
+    Skip,
+}
+
+/// The result of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked, e.g. `Deno`.
+    pub name: String,
+    pub status: Status,
+    /// What was found.
+    pub detail: String,
+    /// How to fix it, for warnings and failures.
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(name, Status::Pass, detail, None)
+    }
+
+    fn skip(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(name, Status::Skip, detail, None)
+    }
+
+    fn warn(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self::new(name, Status::Warn, detail, Some(hint.into()))
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self::new(name, Status::Fail, detail, Some(hint.into()))
+    }
+
+    fn new(name: impl Into<String>, status: Status, detail: impl Into<String>, hint: Option<String>) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+            hint,
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let icon = match self.status {
+            Status::Pass => "✅",
+            Status::Warn => "⚠️ ",
+            Status::Fail => "❌",
+            Status::Skip => "⏭️ ",
+        };
+        write!(f, "{} {}: {}", icon, self.name, self.detail)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n   💡 {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs every check.
+///
+/// # Arguments
+///
+/// * `runner` - Runs `deno --version`
+/// * `client` - Reaches the Anthropic API
+/// * `loader` - Loads the config file
+/// * `cache_dirs` - The bioma directories, nearest first
+/// * `now` - The current Unix timestamp
+pub async fn diagnose<P: ProcessRunner + ?Sized>(
+    runner: &P,
+    client: &dyn HttpClient,
+    loader: &ConfigLoader,
+    cache_dirs: &[PathBuf],
+    now: u64,
+) -> Vec<Check> {
+    let mut checks = vec![check_deno(runner), check_config(loader)];
+    checks.push(check_api_key(client, loader).await);
+    for dir in cache_dirs {
+        checks.push(check_cache(dir));
+        checks.push(check_writable(dir));
+    }
+    let server_date = client.server_date(ANTHROPIC_API).await.ok().flatten();
+    checks.push(check_clock(now, server_date.as_deref()));
+    checks
+}
+
+/// Checks that Deno is installed and at least [`MIN_DENO_VERSION`].
+pub fn check_deno<P: ProcessRunner + ?Sized>(runner: &P) -> Check {
+    let Some(version) = environment::deno_version(runner) else {
+        return Check::fail(
+            "Deno",
+            "not found on PATH",
+            "Install Deno from https://deno.com and make sure `deno` is on your PATH",
+        );
+    };
+    let mut numbers = version.split('.').map(|n| n.parse::<u32>().unwrap_or(0));
+    let found = (numbers.next().unwrap_or(0), numbers.next().unwrap_or(0));
+    if found < MIN_DENO_VERSION {
+        return Check::fail(
+            "Deno",
+            format!(
+                "{} is older than the supported {}.{}",
+                version, MIN_DENO_VERSION.0, MIN_DENO_VERSION.1
+            ),
+            "Upgrade with `deno upgrade`",
+        );
+    }
+    Check::pass("Deno", version)
+}
+
+/// Checks that the config file, if any, parses.
+pub fn check_config(loader: &ConfigLoader) -> Check {
+    let path = match loader.get_config_path() {
+        Ok(path) => path,
+        Err(e) => return Check::fail("Config", e.to_string(), "Set HOME to your home directory"),
+    };
+    if !path.exists() {
+        return Check::pass("Config", format!("{} not found, using defaults", path.display()));
+    }
+    match loader.load_from_file() {
+        Ok(_) => Check::pass("Config", path.display().to_string()),
+        Err(e) => Check::fail(
+            "Config",
+            format!("{} doesn't parse: {}", path.display(), e.to_string().trim()),
+            "Fix the file by hand, or remove the broken key with `ergo config unset <key>`",
+        ),
+    }
+}
+
+/// Checks that the Anthropic API accepts the configured key.
+///
+/// Counting tokens validates the key without generating anything.
+pub async fn check_api_key(client: &dyn HttpClient, loader: &ConfigLoader) -> Check {
+    let config = match loader.load() {
+        Ok(config) => config,
+        Err(e) => return Check::skip("API key", format!("config unavailable: {}", e)),
+    };
+    if config.generator.backend != BUILTIN_BACKEND {
+        return Check::skip(
+            "API key",
+            format!("not used by the `{}` backend", config.generator.backend),
+        );
+    }
+    let Some(api_key) = config.get_api_key() else {
+        return Check::fail(
+            "API key",
+            "not set",
+            "Run `ergo --set-api-key <KEY>` or set ANTHROPIC_API_KEY",
+        );
+    };
+
+    let headers = [
+        ("x-api-key", api_key.as_str()),
+        ("content-type", "application/json"),
+        ("anthropic-version", "2023-06-01"),
+    ];
+    let body = json!({
+        "model": ANTHROPIC_MODEL,
+        "messages": [{"role": "user", "content": "ping"}]
+    });
+    let url = format!("{}/v1/messages/count_tokens", ANTHROPIC_API);
+    let response = match client.post_json(&url, &headers, &body).await {
+        Ok(response) => response,
+        Err(e) => {
+            return Check::warn(
+                "API key",
+                format!("couldn't reach the Anthropic API: {}", e),
+                "Check your network connection and proxy settings",
+            );
+        }
+    };
+
+    let response: Value = serde_json::from_str(&response).unwrap_or(Value::Null);
+    if response.get("input_tokens").is_some() {
+        return Check::pass("API key", "accepted by the Anthropic API");
+    }
+    let error = &response["error"];
+    let message = error["message"].as_str().unwrap_or("unexpected response");
+    match error["type"].as_str() {
+        Some("authentication_error") | Some("permission_error") => Check::fail(
+            "API key",
+            format!("rejected: {}", message),
+            "Create a new key in the Anthropic console and save it with `ergo --set-api-key <KEY>`",
+        ),
+        _ => Check::warn(
+            "API key",
+            format!("couldn't be verified: {}", message),
+            "Try again later; the API may be overloaded",
+        ),
+    }
+}
+
+/// Checks that a bioma's `commands.json` parses and its scripts exist.
+pub fn check_cache(dir: &Path) -> Check {
+    let name = format!("Cache {}", dir.display());
+    if !dir.exists() {
+        return Check::skip(name, "not created yet");
+    }
+    match command_cache::missing_scripts(dir) {
+        Ok(missing) if missing.is_empty() => Check::pass(name, "intact"),
+        Ok(missing) => Check::fail(
+            name,
+            format!("missing scripts: {}", missing.join(", ")),
+            "Regenerate the affected commands with `ergo --nope`, or remove them with `ergo --remove <name>`",
+        ),
+        Err(e) => Check::fail(
+            name,
+            format!("commands.json doesn't parse: {}", e),
+            "Restore commands.json from a backup, or move it aside to start an empty cache",
+        ),
+    }
+}
+
+/// Checks that a bioma directory is writable by creating and removing a file.
+pub fn check_writable(dir: &Path) -> Check {
+    let name = format!("Write access {}", dir.display());
+    if !dir.exists() {
+        return Check::skip(name, "not created yet");
+    }
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            Check::pass(name, "writable")
+        }
+        Err(e) => Check::fail(
+            name,
+            format!("not writable: {}", e),
+            format!("Fix the permissions, e.g. `chmod u+rwx {}`", dir.display()),
+        ),
+    }
+}
+
+/// Checks the system clock against a server's `Date` header.
+pub fn check_clock(now: u64, server_date: Option<&str>) -> Check {
+    let Some(server_now) = server_date.and_then(parse_http_date) else {
+        return Check::skip("Clock", "couldn't read the server time");
+    };
+    let skew = now.abs_diff(server_now);
+    if skew > MAX_CLOCK_SKEW_SECS {
+        let direction = if now > server_now { "ahead of" } else { "behind" };
+        return Check::fail(
+            "Clock",
+            format!("{}s {} the API server", skew, direction),
+            "Synchronize your clock, e.g. enable NTP; signed requests fail with a skewed clock",
+        );
+    }
+    Check::pass("Clock", format!("within {}s of the API server", skew))
+}
+
+/// Parses an HTTP date (`Sun, 06 Nov 1994 08:49:37 GMT`) into a Unix timestamp.
+fn parse_http_date(date: &str) -> Option<u64> {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let mut parts = date.split_whitespace().skip(1);
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|m| *m == month_name)? as u64 + 1;
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|n| n.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+
+    // Days since the epoch of a proleptic Gregorian date (years starting in March)
+    let (y, m) = if month <= 2 { (year.checked_sub(1)?, month + 9) } else { (year, month - 3) };
+    let era_day = 365 * y + y / 4 - y / 100 + y / 400 + (153 * m + 2) / 5 + day - 1;
+    let days = era_day.checked_sub(719_468)?;
+    Some(days * 86_400 + hours * 3_600 + minutes * 60 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigPathProvider;
+    use crate::testing::{MockHttpClient, MockProcessRunner};
+    use tempfile::TempDir;
+
+    struct TempPathProvider(PathBuf);
+
+    impl ConfigPathProvider for TempPathProvider {
+        fn get_base_dir(&self) -> anyhow::Result<PathBuf> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn loader(dir: &Path, config: &str) -> ConfigLoader {
+        fs::write(dir.join("config.toml"), config).unwrap();
+        ConfigLoader::with_provider(Box::new(TempPathProvider(dir.to_path_buf())))
+    }
+
+    #[test]
+    fn test_check_deno_versions() {
+        let deno = |output: &str| MockProcessRunner::new().with_output("deno", output, 0);
+
+        assert_eq!(check_deno(&deno("deno 1.46.3 (stable)\n")).status, Status::Pass);
+        assert_eq!(check_deno(&deno("deno 2.0.0 (stable)\n")).status, Status::Pass);
+        assert_eq!(check_deno(&deno("deno 1.30.3 (stable)\n")).status, Status::Fail);
+        assert_eq!(check_deno(&MockProcessRunner::new().with_output("deno", "", 127)).status, Status::Fail);
+    }
+
+    #[test]
+    fn test_check_config_reports_parse_errors() {
+        let temp_dir = TempDir::new().unwrap();
+
+        assert_eq!(check_config(&loader(temp_dir.path(), "[sandbox]\nmode = \"auto\"\n")).status, Status::Pass);
+
+        let check = check_config(&loader(temp_dir.path(), "anthropic_api_key = \n"));
+        assert_eq!(check.status, Status::Fail);
+        assert!(check.detail.contains("doesn't parse"));
+    }
+
+    #[tokio::test]
+    async fn test_check_api_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = loader(temp_dir.path(), "anthropic_api_key = \"sk-test\"\n");
+        let rejected = r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#;
+
+        let client = MockHttpClient::new().with_response(r#"{"input_tokens": 8}"#);
+        assert_eq!(check_api_key(&client, &loader).await.status, Status::Pass);
+        assert!(client.requests()[0].0.ends_with("/v1/messages/count_tokens"));
+
+        let check = check_api_key(&MockHttpClient::new().with_response(rejected), &loader).await;
+        assert_eq!(check.status, Status::Fail);
+        assert_eq!(check.detail, "rejected: invalid x-api-key");
+
+        let check = check_api_key(&MockHttpClient::new().with_error("dns error"), &loader).await;
+        assert_eq!(check.status, Status::Warn);
+    }
+
+    #[tokio::test]
+    async fn test_check_api_key_skips_other_backends() {
+        let temp_dir = TempDir::new().unwrap();
+        let loader = loader(temp_dir.path(), "[generator]\nbackend = \"gemini\"\n");
+
+        let check = check_api_key(&MockHttpClient::new(), &loader).await;
+
+        assert_eq!(check.status, Status::Skip);
+    }
+
+    #[test]
+    fn test_check_cache_and_write_access() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("missing");
+
+        assert_eq!(check_cache(temp_dir.path()).status, Status::Pass);
+        assert_eq!(check_writable(temp_dir.path()).status, Status::Pass);
+        assert_eq!(check_cache(&missing).status, Status::Skip);
+        assert_eq!(check_writable(&missing).status, Status::Skip);
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+        fs::write(temp_dir.path().join("commands.json"), "[").unwrap();
+        assert_eq!(check_cache(temp_dir.path()).status, Status::Fail);
+    }
+
+    #[test]
+    fn test_check_clock() {
+        let server = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+        assert_eq!(check_clock(784_111_777, Some(server)).status, Status::Pass);
+        assert_eq!(check_clock(784_111_777 + 60, Some(server)).status, Status::Pass);
+
+        let check = check_clock(784_111_777 - 3_600, Some(server));
+        assert_eq!(check.status, Status::Fail);
+        assert_eq!(check.detail, "3600s behind the API server");
+
+        assert_eq!(check_clock(784_111_777, None).status, Status::Skip);
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784_111_777));
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(parse_http_date("Thu, 29 Feb 2024 12:00:00 GMT"), Some(1_709_208_000));
+        assert_eq!(parse_http_date("yesterday"), None);
+    }
+
+    #[test]
+    fn test_display_includes_hint() {
+        let check = Check::fail("Deno", "not found on PATH", "Install Deno");
+
+        assert_eq!(check.to_string(), "❌ Deno: not found on PATH\n   💡 Install Deno");
+    }
+}
@@ -0,0 +1,133 @@
+//! Snapshots of the environment a command runs in.
+//!
+//! Every execution records the Deno version, ergo version, platform and
+//! script hash it ran with (see [`crate::execution_context`]). Rating a
+//! command with `--yep` keeps the snapshot of the run that was rated, and
+//! running the command again under a materially different environment
+//! prints a warning, which helps with "it worked yesterday".
+
+use crate::executor::ProcessRunner;
+use serde::{Deserialize, Serialize};
+
+/// The environment of one execution.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvironmentSnapshot {
+    /// Deno version, e.g. `1.46.3`, if Deno could be queried.
+    pub deno_version: Option<String>,
+    /// ergo version, e.g. `0.1.0`.
+    pub ergo_version: String,
+    /// Operating system and architecture, e.g. `linux-x86_64`.
+    pub platform: String,
+    /// SHA-256 of the executed script.
+    pub script_hash: String,
+}
+
+impl EnvironmentSnapshot {
+    /// Captures the current environment for a script with `script_hash`.
+    pub fn capture(deno_version: Option<String>, script_hash: &str) -> Self {
+        Self {
+            deno_version,
+            ergo_version: env!("CARGO_PKG_VERSION").to_string(),
+            platform: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+            script_hash: script_hash.to_string(),
+        }
+    }
+
+    /// Lists how this environment materially differs from `validated`.
+    ///
+    /// Versions differ materially when their major or minor number changes;
+    /// an unknown Deno version on either side isn't compared.
+    pub fn differences(&self, validated: &EnvironmentSnapshot) -> Vec<String> {
+        let mut differences = Vec::new();
+        if let (Some(now), Some(then)) = (&self.deno_version, &validated.deno_version)
+            && minor_version(now) != minor_version(then)
+        {
+            differences.push(format!("Deno {} (validated with {})", now, then));
+        }
+        if minor_version(&self.ergo_version) != minor_version(&validated.ergo_version) {
+            differences.push(format!("ergo {} (validated with {})", self.ergo_version, validated.ergo_version));
+        }
+        if self.platform != validated.platform {
+            differences.push(format!("{} (validated on {})", self.platform, validated.platform));
+        }
+        if self.script_hash != validated.script_hash {
+            differences.push("the script changed since it was validated".to_string());
+        }
+        differences
+    }
+}
+
+/// Asks Deno for its version, e.g. `1.46.3`.
+pub fn deno_version<P: ProcessRunner + ?Sized>(runner: &P) -> Option<String> {
+    let output = runner.run("deno", &["--version"]).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // "deno 1.46.3 (stable, release, x86_64-unknown-linux-gnu)"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next()?.split_whitespace().nth(1).map(str::to_string)
+}
+
+/// The `major.minor` part of a version.
+fn minor_version(version: &str) -> &str {
+    match version.match_indices('.').nth(1) {
+        Some((index, _)) => &version[..index],
+        None => version,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(deno: Option<&str>, ergo: &str, platform: &str, hash: &str) -> EnvironmentSnapshot {
+        EnvironmentSnapshot {
+            deno_version: deno.map(str::to_string),
+            ergo_version: ergo.to_string(),
+            platform: platform.to_string(),
+            script_hash: hash.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_patch_releases_are_not_material() {
+        let validated = snapshot(Some("1.46.0"), "0.1.0", "linux-x86_64", "abc");
+
+        assert!(snapshot(Some("1.46.3"), "0.1.2", "linux-x86_64", "abc").differences(&validated).is_empty());
+        assert!(snapshot(None, "0.1.0", "linux-x86_64", "abc").differences(&validated).is_empty());
+    }
+
+    #[test]
+    fn test_material_differences_are_listed() {
+        let validated = snapshot(Some("1.46.0"), "0.1.0", "linux-x86_64", "abc");
+        let now = snapshot(Some("2.0.1"), "0.2.0", "macos-aarch64", "def");
+
+        assert_eq!(
+            now.differences(&validated),
+            vec![
+                "Deno 2.0.1 (validated with 1.46.0)",
+                "ergo 0.2.0 (validated with 0.1.0)",
+                "macos-aarch64 (validated on linux-x86_64)",
+                "the script changed since it was validated",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deno_version_reads_the_first_line() {
+        let runner = crate::testing::MockProcessRunner::new().with_output(
+            "deno",
+            "deno 1.46.3 (stable, release, x86_64-unknown-linux-gnu)\nv8 12.9.202.2\n",
+            0,
+        );
+
+        assert_eq!(deno_version(&runner).as_deref(), Some("1.46.3"));
+    }
+
+    #[test]
+    fn test_minor_version() {
+        assert_eq!(minor_version("1.46.3"), "1.46");
+        assert_eq!(minor_version("2.0"), "2.0");
+        assert_eq!(minor_version("canary"), "canary");
+    }
+}
@@ -0,0 +1,65 @@
+//! Running without writing to disk.
+//!
+//! In containers the home directory is often read-only. ergo then carries on
+//! without its files: the command cache keeps its changes in memory, and the
+//! log, the execution context and the statistics are skipped, with a single
+//! warning saying so. `ergo --ephemeral` (or `ERGO_EPHEMERAL=1`) asks for
+//! that up front: cached commands are still read, but whatever is generated,
+//! decided or recorded lives in memory and is gone when the process exits.
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Environment variable turning ephemeral mode on.
+pub const EPHEMERAL_ENV: &str = "ERGO_EPHEMERAL";
+
+/// Whether ephemeral mode is on for this process.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a failed write was already reported to the user.
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps everything this process would write to disk in memory.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether ephemeral mode is on.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether `env` asks for ephemeral mode: [`EPHEMERAL_ENV`] set to anything
+/// but empty, `0` or `false`.
+pub fn requested(env: impl Fn(&str) -> Option<String>) -> bool {
+    env(EPHEMERAL_ENV).is_some_and(|value| !matches!(value.trim(), "" | "0" | "false"))
+}
+
+/// Tells the user that `what` couldn't be saved, the first time something
+/// can't be; later failures of the same process are only logged.
+pub fn warn_unsaved(what: &str, error: &dyn Display) {
+    tracing::warn!("Couldn't save {}: {}", what, error);
+    if !is_enabled() && !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "⚠️  Couldn't save {} ({}); carrying on without it. Use --ephemeral to keep everything in memory",
+            what, error
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requested_from_env() {
+        let env = |value: &'static str| move |name: &str| (name == EPHEMERAL_ENV).then(|| value.to_string());
+
+        assert!(requested(env("1")));
+        assert!(requested(env("yes")));
+        assert!(!requested(env("0")));
+        assert!(!requested(env("false")));
+        assert!(!requested(env("")));
+        assert!(!requested(|_: &str| None));
+    }
+}
@@ -0,0 +1,136 @@
+//! Error categories of the library API.
+//!
+//! ergo uses `anyhow` internally. [`CommandRouter`](crate::command_router::CommandRouter)
+//! returns an [`ErgoError`] instead, so embedders can tell a broken
+//! configuration from a failed generation without matching on messages:
+//!
+//! ```ignore
+//! match router.process_intent(intent).await {
+//!     Ok(outcome) => report(outcome),
+//!     Err(ErgoError::Interrupted) => std::process::exit(130),
+//!     Err(ErgoError::Generation(e)) => eprintln!("The model couldn't write it: {e}"),
+//!     Err(e) => return Err(e.into()),
+//! }
+//! ```
+//!
+//! A failure is tagged with its category where it happens, by wrapping it in
+//! an [`ErgoError`] that travels inside `anyhow` until it reaches the API.
+//! Untagged failures become [`ErgoError::Other`], except the typed errors
+//! this crate raises: [`Interrupted`], [`GenerationTimedOut`] and [`ApiError`].
+
+use crate::anthropic_error::ApiError;
+use crate::cancellation::{GenerationTimedOut, Interrupted};
+use std::fmt;
+
+/// What kind of failure stopped an operation.
+///
+/// Each variant but [`ErgoError::Interrupted`] keeps the underlying error,
+/// with its whole chain of causes. A user declining a command's permissions
+/// isn't an error: it's reported as
+/// [`IntentOutcome::PermissionDenied`](crate::command_router::IntentOutcome::PermissionDenied).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ErgoError {
+    /// The configuration couldn't be read, or holds an invalid value.
+    Config(anyhow::Error),
+    /// The command cache couldn't be read or written.
+    Cache(anyhow::Error),
+    /// No usable command was generated: the model's API failed or timed out,
+    /// or the script it wrote fails validation.
+    Generation(anyhow::Error),
+    /// A command couldn't be run.
+    Execution(anyhow::Error),
+    /// A command was refused, whatever was consented to, such as a script
+    /// matching catastrophic patterns.
+    PermissionDenied(anyhow::Error),
+    /// The user pressed Ctrl-C.
+    Interrupted,
+    /// Anything else, such as an empty intent or an unknown preset.
+    Other(anyhow::Error),
+}
+
+impl ErgoError {
+    /// The underlying error, unless the operation was interrupted.
+    pub fn inner(&self) -> Option<&anyhow::Error> {
+        match self {
+            ErgoError::Config(e)
+            | ErgoError::Cache(e)
+            | ErgoError::Generation(e)
+            | ErgoError::Execution(e)
+            | ErgoError::PermissionDenied(e)
+            | ErgoError::Other(e) => Some(e),
+            ErgoError::Interrupted => None,
+        }
+    }
+}
+
+impl fmt::Display for ErgoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.inner() {
+            Some(e) => write!(f, "{}", e),
+            None => write!(f, "{}", Interrupted),
+        }
+    }
+}
+
+impl std::error::Error for ErgoError {
+    // The wrapper shows the underlying error's message, so its causes come next
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner().and_then(|e| e.source())
+    }
+}
+
+impl From<anyhow::Error> for ErgoError {
+    fn from(error: anyhow::Error) -> Self {
+        // Tagged where it happened, with nothing added on top
+        if error.chain().next().is_some_and(|outermost| outermost.is::<ErgoError>()) {
+            return error.downcast().expect("the outermost error is an ErgoError");
+        }
+        match error.downcast_ref::<ErgoError>() {
+            Some(ErgoError::Config(_)) => ErgoError::Config(error),
+            Some(ErgoError::Cache(_)) => ErgoError::Cache(error),
+            Some(ErgoError::Generation(_)) => ErgoError::Generation(error),
+            Some(ErgoError::Execution(_)) => ErgoError::Execution(error),
+            Some(ErgoError::PermissionDenied(_)) => ErgoError::PermissionDenied(error),
+            Some(ErgoError::Interrupted) => ErgoError::Interrupted,
+            Some(ErgoError::Other(_)) => ErgoError::Other(error),
+            None if error.is::<Interrupted>() => ErgoError::Interrupted,
+            None if error.is::<GenerationTimedOut>() || error.is::<ApiError>() => ErgoError::Generation(error),
+            None => ErgoError::Other(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, anyhow};
+
+    #[test]
+    fn test_tagged_errors_keep_their_category_and_message() {
+        let tagged: anyhow::Error = ErgoError::Cache(anyhow!("disk full").context("Failed to write commands.json")).into();
+        let error = ErgoError::from(tagged);
+        assert!(matches!(error, ErgoError::Cache(_)));
+        assert_eq!(error.to_string(), "Failed to write commands.json");
+        assert_eq!(format!("{:#}", anyhow::Error::from(error)), "Failed to write commands.json: disk full");
+
+        let wrapped = Err::<(), _>(ErgoError::Config(anyhow!("bad TOML")))
+            .context("Loading ~/.abiogenesis/config.toml")
+            .unwrap_err();
+        let error = ErgoError::from(wrapped);
+        assert!(matches!(error, ErgoError::Config(_)));
+        assert_eq!(error.to_string(), "Loading ~/.abiogenesis/config.toml");
+    }
+
+    #[test]
+    fn test_untagged_errors_are_categorized_by_type() {
+        assert!(matches!(ErgoError::from(anyhow::Error::from(Interrupted)), ErgoError::Interrupted));
+        let timed_out = GenerationTimedOut {
+            seconds: 60,
+            retried: false,
+        };
+        assert!(matches!(ErgoError::from(anyhow::Error::from(timed_out)), ErgoError::Generation(_)));
+        assert!(matches!(ErgoError::from(anyhow!("Empty intent")), ErgoError::Other(_)));
+        assert_eq!(ErgoError::Interrupted.to_string(), "Interrupted");
+    }
+}
@@ -1,13 +1,25 @@
 //! Execution context tracking for the feedback loop.
 //!
 //! This module tracks the last executed command and its output, enabling
-//! the `--nope` feedback feature for refining generated commands.
+//! the `--nope` feedback feature for refining generated commands, and
+//! `--with-last`, which hands the last output to the next generation.
 
-use anyhow::Result;
+use crate::environment::EnvironmentSnapshot;
+use crate::executor::ExecutionResult;
+use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fs;
 use std::path::PathBuf;
 
+/// Returns the hex-encoded SHA-256 of a script.
+pub fn script_hash(script: &str) -> String {
+    format!("{:x}", Sha256::digest(script.as_bytes()))
+}
+
+/// How much of the last output `--with-last` puts in the request.
+pub const MAX_FOLLOW_UP_OUTPUT: usize = 16 * 1024;
+
 /// Context from the last command execution.
 ///
 /// Stores information needed to regenerate a command with feedback.
@@ -21,6 +33,36 @@ pub struct ExecutionContext {
     pub stderr: Option<String>,
     /// Whether the command succeeded.
     pub success: bool,
+    /// Exit code of the command, if it ran and exited normally.
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// Signal that killed the command, if one did (Unix only).
+    #[serde(default)]
+    pub signal: Option<i32>,
+    /// Execution time in milliseconds.
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// Standard output (if any), truncated to the capture limit.
+    #[serde(default)]
+    pub stdout: Option<String>,
+    /// Arguments passed to the command.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Directory the command ran in.
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// SHA-256 of the executed script.
+    #[serde(default)]
+    pub script_hash: Option<String>,
+    /// Whether the user interrupted the command with Ctrl-C.
+    #[serde(default)]
+    pub interrupted: bool,
+    /// Whether the command printed secrets; none of its output is kept.
+    #[serde(default)]
+    pub sensitive: bool,
+    /// The environment the command ran in.
+    #[serde(default)]
+    pub environment: Option<EnvironmentSnapshot>,
 }
 
 impl ExecutionContext {
@@ -31,7 +73,115 @@ impl ExecutionContext {
             script_content: script_content.to_string(),
             stderr,
             success,
+            exit_code: None,
+            signal: None,
+            duration_ms: 0,
+            stdout: None,
+            args: Vec::new(),
+            cwd: None,
+            script_hash: None,
+            interrupted: false,
+            sensitive: false,
+            environment: None,
+        }
+    }
+
+    /// Creates an execution context from the result of running a command.
+    pub fn from_result(command_name: &str, script_content: &str, result: &ExecutionResult) -> Self {
+        Self {
+            exit_code: result.exit_code,
+            signal: result.signal,
+            duration_ms: result.duration.as_millis() as u64,
+            stdout: result.stdout.clone().filter(|_| !result.sensitive),
+            args: result.args.clone(),
+            cwd: Some(result.cwd.clone()),
+            script_hash: (!result.script_hash.is_empty()).then(|| result.script_hash.clone()),
+            interrupted: result.interrupted,
+            sensitive: result.sensitive,
+            ..Self::new(
+                command_name,
+                script_content,
+                result.stderr.clone().filter(|_| !result.sensitive),
+                result.success,
+            )
+        }
+    }
+
+    /// How the command ended, for the model repairing it, e.g. `exited
+    /// with code 127 (command not found) after 0.2s`.
+    ///
+    /// `None` for successful runs and for contexts that recorded neither an
+    /// exit code nor a signal.
+    pub fn exit_summary(&self) -> Option<String> {
+        if self.success {
+            return None;
         }
+        let ending = if self.interrupted {
+            "was interrupted with Ctrl-C".to_string()
+        } else if let Some(signal) = self.signal {
+            match signal_name(signal) {
+                Some(name) => format!("was killed by signal {} ({})", signal, name),
+                None => format!("was killed by signal {}", signal),
+            }
+        } else {
+            let code = self.exit_code?;
+            match code {
+                126 => format!("exited with code {} (found but not executable)", code),
+                127 => format!("exited with code {} (command not found)", code),
+                _ => format!("exited with code {}", code),
+            }
+        };
+        Some(match self.duration_ms {
+            0 => ending,
+            ms => format!("{} after {:.1}s", ending, ms as f64 / 1000.0),
+        })
+    }
+
+    /// Builds a generation request for `intent` that works on this
+    /// execution's output, e.g. "now chart these numbers".
+    ///
+    /// The output is embedded in the request, keeping its first
+    /// [`MAX_FOLLOW_UP_OUTPUT`] bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command printed secrets or nothing at all.
+    pub fn follow_up_request(&self, intent: &str) -> Result<String> {
+        if self.sensitive {
+            return Err(anyhow!(
+                "The output of '{}' contains secrets, so it wasn't kept",
+                self.command_name
+            ));
+        }
+        let output = self
+            .stdout
+            .as_deref()
+            .filter(|s| !s.trim().is_empty())
+            .ok_or_else(|| anyhow!("'{}' didn't print anything to work on", self.command_name))?;
+
+        let mut end = output.len().min(MAX_FOLLOW_UP_OUTPUT);
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        let truncated = if end < output.len() {
+            format!("\n(output truncated to its first {} bytes)", end)
+        } else {
+            String::new()
+        };
+
+        let command_line = std::iter::once(self.command_name.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(format!(
+            "{}\n\nWork on the output of the previous command (`{}`), shown below. \
+             It won't be available when the script runs, so embed the data the script needs in it.\n\n\
+             ```\n{}\n```{}",
+            intent,
+            command_line,
+            output[..end].trim_end(),
+            truncated
+        ))
     }
 
     /// Returns the path to the context file.
@@ -69,9 +219,149 @@ impl ExecutionContext {
     }
 }
 
+/// The name of a common Unix signal.
+fn signal_name(signal: i32) -> Option<&'static str> {
+    Some(match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        6 => "SIGABRT",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        15 => "SIGTERM",
+        _ => return None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_execution_context_from_result() {
+        let result = ExecutionResult {
+            success: false,
+            exit_code: Some(2),
+            signal: None,
+            duration: Duration::from_millis(1500),
+            stdout: Some("partial\n".to_string()),
+            stderr: Some("boom\n".to_string()),
+            args: vec!["--fast".to_string()],
+            cwd: PathBuf::from("/work"),
+            script_hash: script_hash("console.log(1);"),
+            interrupted: true,
+            sensitive: false,
+            error: None,
+        };
+
+        let context = ExecutionContext::from_result("build", "console.log(1);", &result);
+
+        assert_eq!(context.command_name, "build");
+        assert_eq!(context.exit_code, Some(2));
+        assert_eq!(context.duration_ms, 1500);
+        assert_eq!(context.stdout.as_deref(), Some("partial\n"));
+        assert_eq!(context.stderr.as_deref(), Some("boom\n"));
+        assert_eq!(context.args, vec!["--fast"]);
+        assert_eq!(context.cwd, Some(PathBuf::from("/work")));
+        assert_eq!(context.script_hash.as_deref().map(str::len), Some(64));
+        assert!(context.interrupted);
+    }
+
+    #[test]
+    fn test_exit_summary_tells_how_the_command_ended() {
+        let mut context = ExecutionContext::new("build", "", None, false);
+        assert_eq!(context.exit_summary(), None);
+
+        context.exit_code = Some(127);
+        context.duration_ms = 230;
+        assert_eq!(context.exit_summary().as_deref(), Some("exited with code 127 (command not found) after 0.2s"));
+
+        context.exit_code = None;
+        context.signal = Some(9);
+        assert_eq!(context.exit_summary().as_deref(), Some("was killed by signal 9 (SIGKILL) after 0.2s"));
+
+        context.success = true;
+        assert_eq!(context.exit_summary(), None);
+    }
+
+    #[test]
+    fn test_follow_up_request_embeds_last_output() {
+        let mut context = ExecutionContext::new("sales", "", None, true);
+        context.args = vec!["2024".to_string()];
+        context.stdout = Some("jan 10\nfeb 12\n".to_string());
+
+        let request = context.follow_up_request("now chart these numbers").unwrap();
+
+        assert!(request.starts_with("now chart these numbers\n\n"));
+        assert!(request.contains("(`sales 2024`)"));
+        assert!(request.ends_with("```\njan 10\nfeb 12\n```"));
+    }
+
+    #[test]
+    fn test_follow_up_request_truncates_long_output() {
+        let mut context = ExecutionContext::new("dump", "", None, true);
+        context.stdout = Some("é".repeat(MAX_FOLLOW_UP_OUTPUT));
+
+        let request = context.follow_up_request("summarize").unwrap();
+
+        assert!(request.ends_with(&format!("(output truncated to its first {} bytes)", MAX_FOLLOW_UP_OUTPUT)));
+    }
+
+    #[test]
+    fn test_follow_up_request_needs_kept_output() {
+        let mut context = ExecutionContext::new("password", "", None, true);
+        assert!(context.follow_up_request("x").unwrap_err().to_string().contains("didn't print"));
+
+        context.sensitive = true;
+        assert!(context.follow_up_request("x").unwrap_err().to_string().contains("secrets"));
+    }
+
+    #[test]
+    fn test_execution_context_drops_sensitive_output() {
+        let result = ExecutionResult {
+            success: true,
+            exit_code: Some(0),
+            signal: None,
+            duration: Duration::ZERO,
+            stdout: Some("hunter2\n".to_string()),
+            stderr: Some("hunter2\n".to_string()),
+            args: vec![],
+            cwd: PathBuf::from("/work"),
+            script_hash: String::new(),
+            interrupted: false,
+            sensitive: true,
+            error: None,
+        };
+
+        let context = ExecutionContext::from_result("password", "x", &result);
+
+        assert!(context.sensitive);
+        assert!(context.stdout.is_none());
+        assert!(context.stderr.is_none());
+    }
+
+    #[test]
+    fn test_execution_context_loads_older_format() {
+        let json = r#"{"command_name":"hello","script_content":"x","stderr":null,"success":true}"#;
+
+        let context: ExecutionContext = serde_json::from_str(json).unwrap();
+
+        assert_eq!(context.command_name, "hello");
+        assert!(context.exit_code.is_none());
+        assert!(context.signal.is_none());
+        assert!(context.args.is_empty());
+    }
+
+    #[test]
+    fn test_script_hash_is_stable_sha256() {
+        assert_eq!(
+            script_hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_ne!(script_hash("a"), script_hash("b"));
+    }
 
     #[test]
     fn test_execution_context_serialization() {
@@ -7,21 +7,145 @@
 //! All generated commands are executed through Deno's sandboxed runtime with
 //! explicit permission grants for security.
 
+use crate::cancellation::{Cancellation, Interrupted};
+use crate::capture::{CaptureBuffer, DEFAULT_CAPTURE_LIMIT, PrefixedLines};
 use crate::command_cache::CommandCache;
-use crate::execution_context::ExecutionContext;
+use crate::environment::{self, EnvironmentSnapshot};
+use crate::execution_context::{self, ExecutionContext};
 use crate::llm_generator::GeneratedCommand;
+use crate::json_filter::JsonFilter;
+use crate::output_processing::{OutputFormat, OutputProcessor, ProcessingWriter, Terminal};
+use crate::permissions::Permission;
+use crate::permissions::PermissionKind;
+use crate::sandbox::{self, SandboxConfig, SandboxMode, SandboxPolicy};
+use crate::secrets::{self, Clipboard, SecretDelivery};
+use crate::staging::{ChangeApprover, StagingArea, StdinApprover};
+use crate::workspace::Workspace;
 use anyhow::{anyhow, Result};
 use io_tee::TeeWriter;
-use std::process::{Command, Output};
-use tracing::{error, info};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::io::{IsTerminal, Write};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
 
 /// Result of executing a generated command.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ExecutionResult {
     /// Whether the command succeeded.
     pub success: bool,
-    /// Standard error output (if any).
+    /// Exit code of the command, if it ran and exited normally.
+    pub exit_code: Option<i32>,
+    /// Signal that killed the command, if one did (Unix only).
+    pub signal: Option<i32>,
+    /// Wall-clock time spent executing.
+    pub duration: Duration,
+    /// Standard output (if any), truncated to the capture limit.
+    pub stdout: Option<String>,
+    /// Standard error output (if any), truncated to the capture limit.
     pub stderr: Option<String>,
+    /// Arguments passed to the command.
+    pub args: Vec<String>,
+    /// Directory the command ran in.
+    pub cwd: PathBuf,
+    /// SHA-256 of the executed script (empty if it couldn't be read).
+    pub script_hash: String,
+    /// Whether the user interrupted the command with Ctrl-C.
+    pub interrupted: bool,
+    /// Whether the output is a secret (see [`crate::secrets`]).
+    pub sensitive: bool,
+    /// Why the command couldn't be started, if it couldn't (e.g. its script
+    /// is missing or Deno isn't installed).
+    pub error: Option<String>,
+}
+
+/// Whether generated commands are attached to the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtyMode {
+    /// Pass the terminal through when stdin and stdout are both terminals.
+    #[default]
+    Auto,
+    /// Always pass the terminal through.
+    Always,
+    /// Always capture output.
+    Never,
+}
+
+impl TtyMode {
+    /// Decides whether to pass the terminal through.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_terminal` - Whether stdin and stdout are both terminals
+    pub fn passthrough(&self, is_terminal: bool) -> bool {
+        match self {
+            TtyMode::Auto => is_terminal,
+            TtyMode::Always => true,
+            TtyMode::Never => false,
+        }
+    }
+}
+
+/// Execution settings (the `[execution]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionConfig {
+    /// Whether generated commands are attached to the terminal.
+    #[serde(default)]
+    pub tty: TtyMode,
+    /// Maximum bytes of stdout and of stderr kept for the execution context.
+    /// Output beyond this still reaches the terminal; only the tail is kept.
+    #[serde(default = "default_capture_limit")]
+    pub capture_limit: usize,
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            tty: TtyMode::default(),
+            capture_limit: DEFAULT_CAPTURE_LIMIT,
+        }
+    }
+}
+
+fn default_capture_limit() -> usize {
+    DEFAULT_CAPTURE_LIMIT
+}
+
+/// A cached command queued for [`Executor::execute_many`].
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    /// The command to run.
+    pub command: GeneratedCommand,
+    /// The command's script content.
+    pub script: String,
+    /// Arguments to pass to the script.
+    pub args: Vec<String>,
+}
+
+/// Per-run settings and results threaded through a single execution.
+#[derive(Debug, Default)]
+struct RunState {
+    /// Attach the command to the terminal instead of capturing its output.
+    passthrough: bool,
+    /// The output may hold secrets, so a passthrough run isn't recorded.
+    sensitive: bool,
+    /// Exit code of the command, once it has run.
+    exit_code: Option<i32>,
+    /// Signal that killed the command, if one did.
+    signal: Option<i32>,
+}
+
+/// Result of running a command's test script against a candidate script.
+#[derive(Debug)]
+pub struct TestRunResult {
+    /// Whether all tests passed.
+    pub passed: bool,
+    /// Combined stdout and stderr of the test run.
+    pub output: String,
 }
 
 // =============================================================================
@@ -35,10 +159,76 @@ pub trait ProcessRunner: Send + Sync {
     /// Executes a command and returns its output.
     fn run(&self, program: &str, args: &[&str]) -> Result<Output>;
 
+    /// Executes a command, streaming its stdout and stderr into the given
+    /// writers as they are produced instead of buffering them in memory.
+    fn run_streaming(
+        &self,
+        dir: Option<&Path>,
+        program: &str,
+        args: &[&str],
+        stdout: &mut (dyn Write + Send),
+        stderr: &mut (dyn Write + Send),
+    ) -> Result<ExitStatus>;
+
+    /// Executes a command attached to the current terminal (stdio inherited).
+    fn run_interactive(&self, dir: Option<&Path>, program: &str, args: &[&str]) -> Result<ExitStatus>;
+
+    /// Executes a command attached to the terminal, feeding `input` to its stdin.
+    fn run_with_input(&self, program: &str, args: &[&str], input: &[u8]) -> Result<ExitStatus>;
+
     /// Checks if a program exists in PATH.
     fn program_exists(&self, program: &str) -> bool;
 }
 
+/// Lets a shared runner be injected where a concrete one is expected.
+impl ProcessRunner for Arc<dyn ProcessRunner> {
+    fn run(&self, program: &str, args: &[&str]) -> Result<Output> {
+        (**self).run(program, args)
+    }
+
+    fn run_streaming(
+        &self,
+        dir: Option<&Path>,
+        program: &str,
+        args: &[&str],
+        stdout: &mut (dyn Write + Send),
+        stderr: &mut (dyn Write + Send),
+    ) -> Result<ExitStatus> {
+        (**self).run_streaming(dir, program, args, stdout, stderr)
+    }
+
+    fn run_interactive(&self, dir: Option<&Path>, program: &str, args: &[&str]) -> Result<ExitStatus> {
+        (**self).run_interactive(dir, program, args)
+    }
+
+    fn run_with_input(&self, program: &str, args: &[&str], input: &[u8]) -> Result<ExitStatus> {
+        (**self).run_with_input(program, args, input)
+    }
+
+    fn program_exists(&self, program: &str) -> bool {
+        (**self).program_exists(program)
+    }
+}
+
+/// Arguments for running `program` as `user` through non-interactive sudo.
+///
+/// `sudo -n` fails instead of prompting when a password would be required.
+fn sudo_args<'a>(user: &'a str, program: &'a str, args: &[&'a str]) -> Vec<&'a str> {
+    let mut sudo_args = vec!["-n", "-u", user, "--", program];
+    sudo_args.extend_from_slice(args);
+    sudo_args
+}
+
+/// Command that grants `user` alone read access to `path` through an ACL
+/// entry (`chmod +a` on macOS, `setfacl` elsewhere).
+fn acl_read_args(user: &str, path: &str) -> (&'static str, Vec<String>) {
+    if cfg!(target_os = "macos") {
+        ("chmod", vec!["+a".to_string(), format!("user:{} allow read", user), path.to_string()])
+    } else {
+        ("setfacl", vec!["-m".to_string(), format!("u:{}:r", user), path.to_string()])
+    }
+}
+
 /// Trait for retrieving script content.
 ///
 /// This abstraction decouples the executor from the cache implementation.
@@ -51,8 +241,82 @@ pub trait ScriptProvider {
 // Default Implementations
 // =============================================================================
 
+/// How long a cancelled command gets to exit after SIGINT before it is killed.
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
 /// Default process runner using std::process::Command.
-pub struct SystemProcessRunner;
+///
+/// Streamed commands run in their own process group. When the runner's
+/// [`Cancellation`] fires, the whole group is sent SIGINT and, if it is still
+/// running after [`CANCEL_GRACE_PERIOD`], SIGKILL.
+#[derive(Debug, Clone, Default)]
+pub struct SystemProcessRunner {
+    cancellation: Cancellation,
+}
+
+impl SystemProcessRunner {
+    /// Creates a runner whose streamed commands are killed when `cancellation` fires.
+    pub fn with_cancellation(cancellation: Cancellation) -> Self {
+        Self { cancellation }
+    }
+
+    /// Waits for `child`, stopping its process group if cancelled meanwhile.
+    fn wait_cancellable(&self, child: &mut Child) -> Result<ExitStatus> {
+        let mut interrupted_at: Option<Instant> = None;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                if interrupted_at.is_some() {
+                    // Don't leave anything it started behind, holding our pipes open
+                    signal_process_group(child, true);
+                }
+                return Ok(status);
+            }
+            if self.cancellation.is_cancelled() {
+                match interrupted_at {
+                    None => {
+                        info!("Cancelling child process group {}", child.id());
+                        signal_process_group(child, false);
+                        interrupted_at = Some(Instant::now());
+                    }
+                    Some(at) if at.elapsed() >= CANCEL_GRACE_PERIOD => {
+                        warn!("Child process group {} ignored SIGINT, killing it", child.id());
+                        signal_process_group(child, true);
+                    }
+                    Some(_) => {}
+                }
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+/// Sends SIGINT (or SIGKILL if `kill`) to the child's process group.
+#[cfg(unix)]
+fn signal_process_group(child: &mut Child, kill: bool) {
+    let signal = if kill { libc::SIGKILL } else { libc::SIGINT };
+    // SAFETY: kill(2) has no memory safety requirements; a negative pid
+    // addresses the process group the child leads.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn signal_process_group(child: &mut Child, _kill: bool) {
+    let _ = child.kill();
+}
+
+/// The signal that killed a process, if one did.
+#[cfg(unix)]
+fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
 
 impl ProcessRunner for SystemProcessRunner {
     fn run(&self, program: &str, args: &[&str]) -> Result<Output> {
@@ -61,6 +325,69 @@ impl ProcessRunner for SystemProcessRunner {
         Ok(cmd.output()?)
     }
 
+    fn run_streaming(
+        &self,
+        dir: Option<&Path>,
+        program: &str,
+        args: &[&str],
+        stdout: &mut (dyn Write + Send),
+        stderr: &mut (dyn Write + Send),
+    ) -> Result<ExitStatus> {
+        // Piped input is the command's to read (`cat data.json | ergo ...`);
+        // a terminal isn't, since outside the foreground process group
+        // reading it would stop the command
+        let stdin = if std::io::stdin().is_terminal() { Stdio::null() } else { Stdio::inherit() };
+        let mut cmd = Command::new(program);
+        cmd.args(args)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = dir {
+            cmd.current_dir(dir);
+        }
+        #[cfg(unix)]
+        {
+            // Own process group, so cancelling reaches everything it spawned
+            use std::os::unix::process::CommandExt;
+            cmd.process_group(0);
+        }
+        let mut child = cmd.spawn()?;
+        let mut child_stdout = child.stdout.take().expect("stdout is piped");
+        let mut child_stderr = child.stderr.take().expect("stderr is piped");
+
+        // Drain both pipes concurrently so neither can fill up and block the child
+        std::thread::scope(|scope| -> Result<ExitStatus> {
+            let stdout_copy = scope.spawn(move || std::io::copy(&mut child_stdout, stdout));
+            let stderr_copy = scope.spawn(move || std::io::copy(&mut child_stderr, stderr));
+            let status = self.wait_cancellable(&mut child)?;
+            stdout_copy.join().expect("stdout copy thread panicked")?;
+            stderr_copy.join().expect("stderr copy thread panicked")?;
+            Ok(status)
+        })
+    }
+
+    fn run_interactive(&self, dir: Option<&Path>, program: &str, args: &[&str]) -> Result<ExitStatus> {
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        if let Some(dir) = dir {
+            cmd.current_dir(dir);
+        }
+        Ok(cmd.status()?)
+    }
+
+    fn run_with_input(&self, program: &str, args: &[&str], input: &[u8]) -> Result<ExitStatus> {
+        let mut child = Command::new(program).args(args).stdin(Stdio::piped()).spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // A pager that quits early closes its stdin; that's not an error
+        if let Err(e) = stdin.write_all(input)
+            && e.kind() != std::io::ErrorKind::BrokenPipe
+        {
+            return Err(e.into());
+        }
+        drop(stdin);
+        Ok(child.wait()?)
+    }
+
     fn program_exists(&self, program: &str) -> bool {
         which::which(program).is_ok()
     }
@@ -73,6 +400,60 @@ impl ScriptProvider for CommandCache {
     }
 }
 
+/// Number of scrollback lines kept from a passthrough run.
+const SCROLLBACK_LINES: usize = 100;
+
+/// Arguments for `script(1)` recording `program args` into `log_path`.
+///
+/// Returns `None` on platforms without a known `script` syntax.
+fn script_args(os: &str, log_path: &Path, program: &str, args: &[&str]) -> Option<Vec<String>> {
+    let log = log_path.to_string_lossy().to_string();
+    match os {
+        // util-linux: the command is a single shell string; -e returns its exit code
+        "linux" => {
+            let command_line = std::iter::once(program)
+                .chain(args.iter().copied())
+                .map(shell_quote)
+                .collect::<Vec<_>>()
+                .join(" ");
+            Some(vec!["-q".to_string(), "-e".to_string(), "-c".to_string(), command_line, log])
+        }
+        // BSD script: the command follows the log file
+        "macos" | "freebsd" => {
+            let mut script_args = vec!["-q".to_string(), log, program.to_string()];
+            script_args.extend(args.iter().map(|a| a.to_string()));
+            Some(script_args)
+        }
+        _ => None,
+    }
+}
+
+/// Quotes an argument for a POSIX shell.
+pub fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c)) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Strips `script(1)` headers and carriage returns and keeps the tail.
+fn clean_recording(recording: &str) -> String {
+    let lines: Vec<&str> = recording
+        .lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .filter(|l| !l.starts_with("Script started") && !l.starts_with("Script done"))
+        .collect();
+    crate::diagnostics::tail_lines(&lines.join("\n"), SCROLLBACK_LINES)
+}
+
+/// Best-effort name of the current user, for error hints.
+fn whoami() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "<your-user>".to_string())
+}
+
 // =============================================================================
 // Executor Implementation
 // =============================================================================
@@ -96,6 +477,19 @@ impl ScriptProvider for CommandCache {
 /// ```
 pub struct Executor {
     verbose: bool,
+    sandbox: SandboxConfig,
+    change_approver: Box<dyn ChangeApprover>,
+    working_dir: Option<PathBuf>,
+    tty_passthrough: bool,
+    capture_limit: usize,
+    output_processors: Vec<OutputProcessor>,
+    json_filter: Option<JsonFilter>,
+    secret_delivery: SecretDelivery,
+    last_scrollback: Mutex<Option<String>>,
+    cancellation: Cancellation,
+    process_runner: Option<Arc<dyn ProcessRunner>>,
+    save_context: bool,
+    deno_version: OnceLock<Option<String>>,
 }
 
 impl Executor {
@@ -105,7 +499,148 @@ impl Executor {
     ///
     /// * `verbose` - If true, prints additional output during execution
     pub fn new(verbose: bool) -> Self {
-        Self { verbose }
+        Self {
+            verbose,
+            sandbox: SandboxConfig::default(),
+            change_approver: Box::new(StdinApprover),
+            working_dir: None,
+            tty_passthrough: false,
+            capture_limit: DEFAULT_CAPTURE_LIMIT,
+            output_processors: Vec::new(),
+            json_filter: None,
+            secret_delivery: SecretDelivery::default(),
+            last_scrollback: Mutex::new(None),
+            cancellation: Cancellation::default(),
+            process_runner: None,
+            save_context: true,
+            deno_version: OnceLock::new(),
+        }
+    }
+
+    /// Runs generated commands inside an OS-level sandbox wrapper.
+    ///
+    /// See [`crate::sandbox`] for the available wrappers and what they restrict.
+    pub fn with_sandbox(mut self, sandbox: SandboxConfig) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Enables or disables staged writes (see [`crate::staging`]).
+    pub fn set_staged_writes(&mut self, enabled: bool) {
+        self.sandbox.stage_writes = enabled;
+    }
+
+    /// Enables or disables running commands in a temporary workspace (see
+    /// [`crate::workspace`]).
+    pub fn set_workspace(&mut self, enabled: bool) {
+        self.sandbox.workspace = enabled;
+    }
+
+    /// Runs generated commands in `dir` instead of the process's current directory.
+    pub fn with_working_dir(mut self, dir: PathBuf) -> Self {
+        self.working_dir = Some(dir);
+        self
+    }
+
+    /// Attaches generated commands to the terminal instead of capturing
+    /// their output, so interactive scripts work.
+    pub fn with_tty_passthrough(mut self, enabled: bool) -> Self {
+        self.tty_passthrough = enabled;
+        self
+    }
+
+    /// Enables or disables terminal passthrough (see [`Self::with_tty_passthrough`]).
+    pub fn set_tty_passthrough(&mut self, enabled: bool) {
+        self.tty_passthrough = enabled;
+    }
+
+    /// Limits how many bytes of each output stream are kept for the
+    /// execution context (see [`crate::capture`]).
+    pub fn with_capture_limit(mut self, limit: usize) -> Self {
+        self.capture_limit = limit;
+        self
+    }
+
+    /// Post-processes the output of commands that don't set their own
+    /// processors (see [`crate::output_processing`]).
+    pub fn with_output_processors(mut self, processors: Vec<OutputProcessor>) -> Self {
+        self.output_processors = processors;
+        self
+    }
+
+    /// Filters the JSON output of the next commands with `filter`, as `--jq` does.
+    pub fn set_json_filter(&mut self, filter: Option<JsonFilter>) {
+        self.json_filter = filter;
+    }
+
+    /// Chooses how the output of commands that print secrets is delivered
+    /// (see [`crate::secrets`]).
+    pub fn set_secret_delivery(&mut self, delivery: SecretDelivery) {
+        self.secret_delivery = delivery;
+    }
+
+    /// Stops running commands when `cancellation` fires (see [`crate::cancellation`]).
+    pub fn with_cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Returns the directory generated commands run in.
+    fn run_dir(&self) -> PathBuf {
+        self.working_dir
+            .clone()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default()
+    }
+
+    /// Runs every process through `runner` instead of spawning real ones
+    /// (for testing).
+    pub fn with_process_runner(mut self, runner: Arc<dyn ProcessRunner>) -> Self {
+        self.process_runner = Some(runner);
+        self
+    }
+
+    /// Enables or disables saving the last execution for `--nope`
+    /// (see [`crate::execution_context`]). Enabled by default.
+    pub fn with_context_saving(mut self, enabled: bool) -> Self {
+        self.save_context = enabled;
+        self
+    }
+
+    /// Captures the environment `script` runs in (see [`crate::environment`]).
+    ///
+    /// Deno is asked for its version once per executor.
+    pub fn environment(&self, script: &str) -> EnvironmentSnapshot {
+        let deno_version = self
+            .deno_version
+            .get_or_init(|| environment::deno_version(&self.system_runner()))
+            .clone();
+        EnvironmentSnapshot::capture(deno_version, &execution_context::script_hash(script))
+    }
+
+    /// Whether the last execution is saved for `--nope`.
+    pub fn saves_context(&self) -> bool {
+        self.save_context
+    }
+
+    /// Checks if a program exists in PATH, using the injected runner if any.
+    pub fn program_exists(&self, program: &str) -> bool {
+        self.system_runner().program_exists(program)
+    }
+
+    /// Returns the injected process runner, or one tied to this executor's
+    /// cancellation.
+    fn system_runner(&self) -> Arc<dyn ProcessRunner> {
+        match &self.process_runner {
+            Some(runner) => runner.clone(),
+            None => Arc::new(SystemProcessRunner::with_cancellation(self.cancellation.clone())),
+        }
+    }
+
+    /// Uses a custom approver for staged changes (for testing).
+    pub fn with_change_approver(mut self, approver: Box<dyn ChangeApprover>) -> Self {
+        self.change_approver = approver;
+        self
     }
 
     /// Executes a system command directly.
@@ -123,7 +658,7 @@ impl Executor {
     /// - The command fails to execute
     /// - The command exits with a non-zero status
     pub async fn execute_system_command(&self, args: &[String]) -> Result<()> {
-        self.execute_system_command_with_runner(args, &SystemProcessRunner, &mut std::io::stdout(), &mut std::io::stderr())
+        self.execute_system_command_with_runner(args, &self.system_runner(), &mut std::io::stdout(), &mut std::io::stderr())
     }
 
     /// Executes a system command with injected dependencies (for testing).
@@ -164,62 +699,141 @@ impl Executor {
     ///
     /// # Returns
     ///
-    /// Returns `ExecutionResult` with success status and stderr output.
+    /// Returns `ExecutionResult` with the exit status, timing, and captured output.
     pub async fn execute_generated_command_with_context(
         &self,
         command: &GeneratedCommand,
         cache: &CommandCache,
         args: &[String],
     ) -> ExecutionResult {
-        let mut stdout_buf = Vec::new();
-        let mut stderr_buf = Vec::new();
+        let mut stdout_buf = CaptureBuffer::new(self.capture_limit);
+        let mut stderr_buf = CaptureBuffer::new(self.capture_limit);
+        let started = Instant::now();
+        let cwd = self.run_dir();
 
+        // Run the host platform's variant of the script, if it has one
+        let command = &command.for_platform(std::env::consts::OS);
         let script_content = match cache.get_script_content(command) {
             Ok(content) => content,
             Err(e) => {
-                eprintln!("Error: {}", e);
                 return ExecutionResult {
                     success: false,
+                    exit_code: None,
+                    signal: None,
+                    duration: started.elapsed(),
+                    stdout: None,
                     stderr: Some(e.to_string()),
+                    args: args.to_vec(),
+                    cwd,
+                    script_hash: String::new(),
+                    interrupted: false,
+                    sensitive: false,
+                    error: Some(e.to_string()),
                 };
             }
         };
 
-        // Use TeeWriter to write to both real stdout/stderr AND capture buffers
-        let mut stdout_tee = TeeWriter::new(std::io::stdout(), &mut stdout_buf);
+        // Secrets are kept off the terminal unless asked for; piped output
+        // is left alone so `ergo password | ...` keeps working
+        let sensitive = secrets::is_sensitive(command);
+        let terminal = Terminal::detect();
+        let hidden = sensitive
+            && match self.secret_delivery {
+                SecretDelivery::Show => false,
+                SecretDelivery::Mask => terminal.is_terminal,
+                SecretDelivery::Clipboard => true,
+            };
+
+        // Output processors and JSON handling need the whole output, so such
+        // commands don't get the terminal
+        let processors = cache
+            .get_output_processors(&command.name)
+            .map_or_else(|| self.output_processors.clone(), <[_]>::to_vec);
+        let json = command.output_format == OutputFormat::Json || self.json_filter.is_some();
+        let processed = (!processors.is_empty() || json) && !hidden;
+        let mut processing = processed.then(|| {
+            ProcessingWriter::new(processors, std::io::stdout())
+                .with_format(command.output_format, self.json_filter.clone())
+        });
+        let terminal_stdout: Box<dyn std::io::Write + Send + '_> = match processing.as_mut() {
+            _ if hidden => Box::new(std::io::sink()),
+            Some(processing) => Box::new(processing),
+            None => Box::new(std::io::stdout()),
+        };
+
+        // Use TeeWriter to write everything to real stdout/stderr while the
+        // capture buffers keep only the tail
+        let mut stdout_tee = TeeWriter::new(terminal_stdout, &mut stdout_buf);
         let mut stderr_tee = TeeWriter::new(std::io::stderr(), &mut stderr_buf);
 
-        let result = self.execute_generated_command_with_deps(
+        let mut run = RunState {
+            passthrough: self.tty_passthrough && !processed && !hidden,
+            sensitive,
+            ..Default::default()
+        };
+        let runner = self.system_runner();
+        let result = self.execute_script(
             command,
-            cache,
+            &script_content,
             args,
-            &SystemProcessRunner,
+            &runner,
             &mut stdout_tee,
             &mut stderr_tee,
+            &mut run,
         );
+        drop(stdout_tee);
+        if let Some(processing) = processing
+            && let Err(e) = processing.finish(terminal, &runner)
+        {
+            // Keep the problem in the captured stderr so `--nope` can fix it
+            let _ = writeln!(stderr_tee, "⚠️  {}: {}", command.name, e);
+        }
+        if hidden && !stdout_buf.is_empty() {
+            self.deliver_secret(&stdout_buf.contents(), &runner);
+        }
 
         let success = result.is_ok();
+        let error = result
+            .err()
+            .filter(|_| run.exit_code.is_none() && run.signal.is_none() && !self.cancellation.is_cancelled())
+            .map(|e| e.to_string());
         let stderr_str = if stderr_buf.is_empty() {
-            None
+            // In passthrough mode output isn't captured; use the recorded scrollback
+            self.take_scrollback()
         } else {
-            Some(String::from_utf8_lossy(&stderr_buf).to_string())
+            if stderr_buf.is_truncated() {
+                info!("Captured stderr truncated to its last {} bytes", self.capture_limit);
+            }
+            Some(stderr_buf.contents())
         };
 
-        // Save execution context for --nope feedback
-        let context = ExecutionContext::new(
-            &command.name,
-            &script_content,
-            stderr_str.clone(),
-            success,
-        );
-        if let Err(e) = context.save() {
-            error!("Failed to save execution context: {}", e);
-        }
-
-        ExecutionResult {
+        let execution = ExecutionResult {
             success,
+            exit_code: run.exit_code,
+            signal: run.signal,
+            duration: started.elapsed(),
+            stdout: (!stdout_buf.is_empty()).then(|| stdout_buf.contents()),
             stderr: stderr_str,
+            args: args.to_vec(),
+            cwd,
+            script_hash: execution_context::script_hash(&script_content),
+            interrupted: self.cancellation.is_cancelled(),
+            sensitive,
+            error,
+        };
+
+        // Save execution context for --nope feedback
+        let context = ExecutionContext {
+            environment: Some(self.environment(&script_content)),
+            ..ExecutionContext::from_result(&command.name, &script_content, &execution)
+        };
+        if self.save_context
+            && let Err(e) = context.save()
+        {
+            crate::ephemeral::warn_unsaved("the execution context", &e);
         }
+
+        execution
     }
 
     /// Executes a generated command with injected dependencies (for testing).
@@ -235,46 +849,255 @@ impl Executor {
     where
         S: ScriptProvider,
         P: ProcessRunner,
-        W1: std::io::Write,
-        W2: std::io::Write,
+        W1: std::io::Write + Send,
+        W2: std::io::Write + Send,
+    {
+        let script_content = script_provider.get_script(command)?;
+        let mut run = RunState {
+            passthrough: self.tty_passthrough,
+            sensitive: secrets::is_sensitive(command),
+            ..Default::default()
+        };
+
+        self.execute_script(command, &script_content, args, runner, stdout, stderr, &mut run)
+    }
+
+    /// Runs several generated commands concurrently.
+    ///
+    /// At most `max_parallel` commands run at once. Their output is relayed
+    /// line by line, prefixed with the command name. Commands never get the
+    /// terminal in this mode, and no execution context is saved.
+    ///
+    /// # Returns
+    ///
+    /// One `ExecutionResult` per job, in the same order as `jobs`.
+    pub fn execute_many(&self, jobs: &[BatchJob], max_parallel: usize) -> Vec<ExecutionResult> {
+        let stdout = Mutex::new(std::io::stdout());
+        let stderr = Mutex::new(std::io::stderr());
+        self.execute_many_with_deps(jobs, max_parallel, &self.system_runner(), &stdout, &stderr)
+    }
+
+    /// Runs several generated commands concurrently with injected dependencies (for testing).
+    pub fn execute_many_with_deps<P, W1, W2>(
+        &self,
+        jobs: &[BatchJob],
+        max_parallel: usize,
+        runner: &P,
+        stdout: &Mutex<W1>,
+        stderr: &Mutex<W2>,
+    ) -> Vec<ExecutionResult>
+    where
+        P: ProcessRunner,
+        W1: std::io::Write + Send,
+        W2: std::io::Write + Send,
+    {
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<ExecutionResult>>> = jobs.iter().map(|_| Mutex::new(None)).collect();
+        let workers = max_parallel.clamp(1, jobs.len().max(1));
+        info!("Running {} commands with up to {} at a time", jobs.len(), workers);
+
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(job) = jobs.get(index) else {
+                            break;
+                        };
+                        let result = self.execute_batch_job(job, runner, stdout, stderr);
+                        *results[index].lock().unwrap() = Some(result);
+                    }
+                });
+            }
+        });
+
+        results
+            .into_iter()
+            .map(|r| r.into_inner().unwrap().expect("every job was run"))
+            .collect()
+    }
+
+    /// Runs one command of a batch, relaying its output with a prefix.
+    fn execute_batch_job<P, W1, W2>(
+        &self,
+        job: &BatchJob,
+        runner: &P,
+        stdout: &Mutex<W1>,
+        stderr: &Mutex<W2>,
+    ) -> ExecutionResult
+    where
+        P: ProcessRunner,
+        W1: std::io::Write + Send,
+        W2: std::io::Write + Send,
+    {
+        let started = Instant::now();
+        let mut stdout_buf = CaptureBuffer::new(self.capture_limit);
+        let mut stderr_buf = CaptureBuffer::new(self.capture_limit);
+        let mut run = RunState::default();
+        let success;
+        let mut error = None;
+        let sensitive = secrets::is_sensitive(&job.command);
+        let hidden = sensitive && self.secret_delivery != SecretDelivery::Show;
+
+        {
+            let mut stdout_lines = PrefixedLines::new(&job.command.name, stdout);
+            let mut stderr_lines = PrefixedLines::new(&job.command.name, stderr);
+            // Interleaved output is no place for secrets, even when piped
+            let mut hidden_sink = std::io::sink();
+            let stdout_target: &mut (dyn std::io::Write + Send) =
+                if hidden { &mut hidden_sink } else { &mut stdout_lines };
+            let mut stdout_tee = TeeWriter::new(stdout_target, &mut stdout_buf);
+            let mut stderr_tee = TeeWriter::new(&mut stderr_lines, &mut stderr_buf);
+
+            let writes = job
+                .command
+                .permissions
+                .iter()
+                .any(|p| p.parsed().is_ok_and(|p| p.kind == PermissionKind::Write));
+            let result = if self.cancellation.is_cancelled() {
+                Err(Interrupted.into())
+            } else if self.sandbox.stage_writes && writes {
+                Err(anyhow!("Staged writes need approval one command at a time; run '{}' on its own", job.command.name))
+            } else if self.sandbox.workspace {
+                Err(anyhow!("Workspace outputs need approval one command at a time; run '{}' on its own", job.command.name))
+            } else {
+                self.execute_script(&job.command, &job.script, &job.args, runner, &mut stdout_tee, &mut stderr_tee, &mut run)
+            };
+
+            success = result.is_ok();
+            if let Err(e) = result {
+                let _ = match run.exit_code {
+                    Some(code) if !e.is::<Interrupted>() => writeln!(stderr_tee, "❌ Exited with code {}", code),
+                    _ => writeln!(stderr_tee, "❌ {}", e),
+                };
+                if run.exit_code.is_none() && run.signal.is_none() && !e.is::<Interrupted>() {
+                    error = Some(e.to_string());
+                }
+            }
+            if hidden && !stdout_buf.is_empty() {
+                let _ = writeln!(stdout_lines, "{}", secrets::hidden_notice(&stdout_buf.contents()));
+            }
+        }
+
+        ExecutionResult {
+            success,
+            exit_code: run.exit_code,
+            signal: run.signal,
+            duration: started.elapsed(),
+            stdout: (!stdout_buf.is_empty()).then(|| stdout_buf.contents()),
+            stderr: (!stderr_buf.is_empty()).then(|| stderr_buf.contents()),
+            args: job.args.clone(),
+            cwd: self.run_dir(),
+            script_hash: execution_context::script_hash(&job.script),
+            interrupted: self.cancellation.is_cancelled(),
+            sensitive,
+            error,
+        }
+    }
+
+    /// Hands hidden output to the user: copies it to the clipboard for
+    /// `--clipboard`, otherwise shows a notice and offers to copy it.
+    fn deliver_secret<P: ProcessRunner>(&self, secret: &str, runner: &P) {
+        let secret = secret.trim_end_matches(['\r', '\n']);
+        let clipboard = Clipboard::detect_system(runner);
+        let copy = match self.secret_delivery {
+            SecretDelivery::Clipboard => true,
+            _ => {
+                println!("{}", secrets::hidden_notice(secret));
+                clipboard.is_some()
+                    && std::io::stdin().is_terminal()
+                    && secrets::offer_clipboard_with_io(&mut std::io::stdin().lock(), &mut std::io::stdout())
+                        .unwrap_or(false)
+            }
+        };
+        if !copy {
+            return;
+        }
+
+        match clipboard {
+            Some(clipboard) => {
+                if let Err(e) = clipboard.copy_temporarily(runner, secret, &self.cancellation, &mut std::io::stdout()) {
+                    eprintln!("❌ Failed to use the clipboard: {}", e);
+                }
+            }
+            None => eprintln!("❌ No clipboard tool found (pbcopy, wl-copy, xclip or xsel); use --show to print the output"),
+        }
+    }
+
+    /// Validates a command's permissions and runs its script.
+    #[allow(clippy::too_many_arguments)]
+    fn execute_script<P, W1, W2>(
+        &self,
+        command: &GeneratedCommand,
+        script_content: &str,
+        args: &[String],
+        runner: &P,
+        stdout: &mut W1,
+        stderr: &mut W2,
+        run: &mut RunState,
+    ) -> Result<()>
+    where
+        P: ProcessRunner,
+        W1: std::io::Write + Send,
+        W2: std::io::Write + Send,
     {
         info!("Executing generated command: {} - {}", command.name, command.description);
 
+        let permissions = Self::validated_permissions(command)?;
+
         if self.verbose {
             writeln!(stdout, "🤖 Executing generated command: {}", command.description)?;
 
-            if !command.permissions.is_empty() {
-                let permission_strings: Vec<String> = command.permissions
-                    .iter()
-                    .map(|p| p.permission.clone())
-                    .collect();
+            if !permissions.is_empty() {
+                let permission_strings: Vec<String> = permissions.iter().map(|p| p.to_flag()).collect();
                 writeln!(stdout, "🔒 Deno permissions required: {}", permission_strings.join(" "))?;
             }
         }
 
-        let script_content = script_provider.get_script(command)?;
-        let permission_strings: Vec<String> = command.permissions
-            .iter()
-            .map(|p| p.permission.clone())
-            .collect();
+        self.execute_deno_script_with_deps(script_content, &permissions, args, runner, stdout, stderr, run)
+    }
 
-        self.execute_deno_script_with_deps(&script_content, &permission_strings, args, runner, stdout, stderr)
+    /// Validates a command's permissions before they are passed to Deno.
+    ///
+    /// Permission strings are passed to `deno run` as arguments, so anything
+    /// other than a known permission flag (e.g. `--allow-all`, `--unstable`,
+    /// or a second flag hidden after a space) is refused rather than passed
+    /// through.
+    fn validated_permissions(command: &GeneratedCommand) -> Result<Vec<Permission>> {
+        command
+            .permissions
+            .iter()
+            .map(|p| {
+                p.parsed().map_err(|e| {
+                    error!("Refusing to run '{}': {}", command.name, e);
+                    anyhow!(
+                        "Refusing to run '{}': '{}' is not an allowed Deno permission ({}). \
+                         Regenerate the command with --nope or remove it with --remove-command.",
+                        command.name,
+                        p.permission,
+                        e
+                    )
+                })
+            })
+            .collect()
     }
 
     /// Executes a Deno script with injected dependencies (for testing).
+    #[allow(clippy::too_many_arguments)]
     fn execute_deno_script_with_deps<P, W1, W2>(
         &self,
         script: &str,
-        permissions: &[String],
+        permissions: &[Permission],
         args: &[String],
         runner: &P,
         stdout: &mut W1,
         stderr: &mut W2,
+        run: &mut RunState,
     ) -> Result<()>
     where
         P: ProcessRunner,
-        W1: std::io::Write,
-        W2: std::io::Write,
+        W1: std::io::Write + Send,
+        W2: std::io::Write + Send,
     {
         if !runner.program_exists("deno") {
             return Err(anyhow!(
@@ -282,34 +1105,436 @@ impl Executor {
             ));
         }
 
-        // Create a temporary file for the script
+        if let Some(user) = &self.sandbox.run_as
+            && !runner.program_exists("sudo")
+        {
+            return Err(anyhow!(
+                "sandbox.run_as is set to '{}' but sudo is not installed. \
+                 Install sudo or clear the setting with `ergo config unset sandbox.run_as`.",
+                user
+            ));
+        }
+
+        let wrapper = self
+            .sandbox
+            .resolve(std::env::consts::OS, |program| runner.program_exists(program))?;
+        if wrapper.is_none() && self.sandbox.mode != SandboxMode::Off {
+            warn!("No sandbox wrapper available; running Deno without one");
+            if self.verbose {
+                writeln!(stdout, "⚠️  Sandbox wrapper not installed, relying on Deno's permissions only")?;
+            }
+        }
+
         let temp_dir = std::env::temp_dir();
-        let script_path = temp_dir.join(format!("ergo_script_{}.ts", std::process::id()));
 
-        std::fs::write(&script_path, script)?;
+        // Run in a temporary workspace, or stage writes into a copy of the
+        // working directory, if requested
+        let cwd = match &self.working_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::current_dir()?,
+        };
+        let workspace = if self.sandbox.workspace {
+            let workspace = Workspace::create(&cwd, &temp_dir, args)?;
+            if self.verbose {
+                writeln!(
+                    stdout,
+                    "📂 Running in a temporary workspace with {} input(s) from {}",
+                    workspace.inputs().len(),
+                    cwd.display()
+                )?;
+            }
+            Some(workspace)
+        } else {
+            None
+        };
+        let staging = if workspace.is_none()
+            && self.sandbox.stage_writes
+            && permissions.iter().any(|p| p.kind == PermissionKind::Write)
+        {
+            if self.verbose {
+                writeln!(stdout, "📂 Staging writes in a copy of {}", cwd.display())?;
+            }
+            Some(StagingArea::create(&cwd, &temp_dir)?)
+        } else {
+            None
+        };
+        let permissions = match (&staging, &workspace) {
+            (Some(area), _) => {
+                Self::redirected_permissions(permissions, &cwd, area.staged_dir(), |p| area.map_path(p), "staging")?
+            }
+            (None, Some(workspace)) => {
+                Self::redirected_permissions(permissions, &cwd, workspace.dir(), |p| workspace.map_path(p), "--workspace")?
+            }
+            (None, None) => permissions.to_vec(),
+        };
+        let run_dir = match (&staging, &workspace) {
+            (Some(area), _) => area.staged_dir(),
+            (None, Some(workspace)) => workspace.dir(),
+            (None, None) => cwd.as_path(),
+        };
 
-        // Build deno arguments
-        let script_path_str = script_path.to_string_lossy();
-        let mut deno_args: Vec<&str> = vec!["run"];
-        for perm in permissions {
-            deno_args.push(perm.as_str());
+        // A fresh file only we can read (0600, unpredictable name), removed
+        // when dropped, even if something below fails or panics
+        let mut script_file = tempfile::Builder::new()
+            .prefix("ergo_script_")
+            .suffix(".ts")
+            .tempfile_in(&temp_dir)?;
+        script_file.write_all(script.as_bytes())?;
+        script_file.flush()?;
+        let script_path = script_file.path().to_path_buf();
+        #[cfg(unix)]
+        if let Some(user) = &self.sandbox.run_as {
+            // The other user must be able to read the script, and nobody else
+            Self::share_script(runner, user, &script_path)?;
         }
-        deno_args.push(&script_path_str);
-        for arg in args {
-            deno_args.push(arg.as_str());
+
+        // Build deno command line
+        let mut deno_command: Vec<String> = vec!["deno".to_string(), "run".to_string()];
+        deno_command.extend(permissions.iter().map(|p| p.to_flag()));
+        deno_command.push(script_path.to_string_lossy().to_string());
+        deno_command.extend(args.iter().cloned());
+
+        let (program, full_args) = match wrapper {
+            Some(wrapper) => {
+                let policy = SandboxPolicy::from_permissions(&permissions, run_dir, sandbox::deno_dir().as_deref())
+                    .with_readable(&script_path);
+                info!("Sandboxing Deno with {}", wrapper.program());
+                (wrapper.program(), wrapper.wrap_args(&deno_command, &policy))
+            }
+            None => ("deno", deno_command[1..].to_vec()),
+        };
+        let full_args: Vec<&str> = full_args.iter().map(|a| a.as_str()).collect();
+
+        let dir = (staging.is_some() || workspace.is_some() || self.working_dir.is_some()).then_some(run_dir);
+        let invocation: (&str, Vec<&str>) = match &self.sandbox.run_as {
+            Some(user) => {
+                info!("Running Deno as user '{}'", user);
+                ("sudo", sudo_args(user, program, &full_args))
+            }
+            None => (program, full_args.clone()),
+        };
+
+        if run.passthrough {
+            let result = self.run_passthrough(runner, dir, invocation.0, &invocation.1, &temp_dir, run);
+            drop(script_file);
+            let result = self.finish_workspace(workspace, result, stdout, stderr);
+            return self.finish_staging(staging, result, stdout, stderr);
         }
 
-        let output = runner.run("deno", &deno_args);
+        // Keep a short stderr tail to recognize sudo's own failures
+        let mut stderr_tail = CaptureBuffer::new(4096);
+        let status = runner.run_streaming(
+            dir,
+            invocation.0,
+            &invocation.1,
+            stdout,
+            &mut TeeWriter::new(&mut *stderr, &mut stderr_tail),
+        );
 
         // Clean up temporary file
-        let _ = std::fs::remove_file(&script_path);
+        drop(script_file);
 
-        let output = output?;
-        Self::handle_output(&output, stdout, stderr)?;
+        let status = status?;
+        run.exit_code = status.code();
+        run.signal = exit_signal(&status);
+        if let Some(user) = &self.sandbox.run_as {
+            Self::check_user_switch(user, &status, &stderr_tail.contents())?;
+        }
+        let result = if self.cancellation.is_cancelled() {
+            info!("Command interrupted");
+            Err(Interrupted.into())
+        } else if status.success() {
+            Ok(())
+        } else {
+            error!("Command failed with status: {}", status);
+            Err(anyhow!("Command execution failed"))
+        };
+
+        let result = self.finish_workspace(workspace, result, stdout, stderr);
+        self.finish_staging(staging, result, stdout, stderr)
+    }
+
+    /// Offers staged changes for approval once the command has finished.
+    fn finish_staging<W1, W2>(
+        &self,
+        staging: Option<StagingArea>,
+        result: Result<()>,
+        stdout: &mut W1,
+        stderr: &mut W2,
+    ) -> Result<()>
+    where
+        W1: std::io::Write,
+        W2: std::io::Write,
+    {
+        if let Some(area) = staging {
+            if result.is_err() {
+                writeln!(stderr, "📂 The command failed; its staged file changes were discarded")?;
+            } else {
+                let manifest = area.manifest()?;
+                if self.change_approver.approve(&manifest, stdout)? {
+                    area.apply(&manifest)?;
+                    writeln!(stdout, "✅ Changes applied")?;
+                } else if !manifest.is_empty() {
+                    writeln!(stdout, "🗑️  Changes discarded")?;
+                }
+            }
+        }
 
+        result
+    }
+
+    /// Offers the files a command produced in its workspace for copying back
+    /// once the command has finished.
+    fn finish_workspace<W1, W2>(
+        &self,
+        workspace: Option<Workspace>,
+        result: Result<()>,
+        stdout: &mut W1,
+        stderr: &mut W2,
+    ) -> Result<()>
+    where
+        W1: std::io::Write,
+        W2: std::io::Write,
+    {
+        if let Some(workspace) = workspace {
+            if result.is_err() {
+                writeln!(stderr, "📂 The command failed; its workspace was discarded")?;
+            } else {
+                let outputs = workspace.outputs()?;
+                if self.change_approver.approve(&outputs, stdout)? {
+                    workspace.copy_back(&outputs)?;
+                    writeln!(stdout, "✅ Outputs copied to the current directory")?;
+                } else if !outputs.is_empty() {
+                    writeln!(stdout, "🗑️  Outputs discarded")?;
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Runs a command attached to the terminal.
+    ///
+    /// The child inherits stdin/stdout/stderr so prompts and progress bars
+    /// work. When `script(1)` is available the session is recorded through it,
+    /// and the tail of the recording is kept as scrollback for the execution
+    /// context (see [`Self::take_scrollback`]), unless the output is
+    /// sensitive.
+    fn run_passthrough<P: ProcessRunner>(
+        &self,
+        runner: &P,
+        dir: Option<&Path>,
+        program: &str,
+        args: &[&str],
+        temp_dir: &Path,
+        run: &mut RunState,
+    ) -> Result<()> {
+        // A fresh log only we can read (0600, unpredictable name), since it
+        // holds everything the command showed; removed when dropped
+        let log_file = if !run.sensitive && runner.program_exists("script") {
+            Some(
+                tempfile::Builder::new()
+                    .prefix("ergo_tty_")
+                    .suffix(".log")
+                    .tempfile_in(temp_dir)?,
+            )
+        } else {
+            None
+        };
+        let recorder = log_file
+            .as_ref()
+            .and_then(|log| script_args(std::env::consts::OS, log.path(), program, args));
+
+        info!("Running with terminal passthrough (recording: {})", recorder.is_some());
+        let status = match &recorder {
+            Some(script_args) => {
+                let script_args: Vec<&str> = script_args.iter().map(|a| a.as_str()).collect();
+                runner.run_interactive(dir, "script", &script_args)?
+            }
+            None => runner.run_interactive(dir, program, args)?,
+        };
+        run.exit_code = status.code();
+        run.signal = exit_signal(&status);
+
+        if let (Some(log), Some(_)) = (&log_file, &recorder) {
+            let recording = std::fs::read(log.path()).unwrap_or_default();
+            let scrollback = clean_recording(&String::from_utf8_lossy(&recording));
+            *self.last_scrollback.lock().unwrap() = (!scrollback.is_empty()).then_some(scrollback);
+        }
+
+        // The child shares our terminal, so Ctrl-C already reached it directly
+        if self.cancellation.is_cancelled() {
+            Err(Interrupted.into())
+        } else if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("Command execution failed"))
+        }
+    }
+
+    /// Returns the scrollback recorded by the last passthrough run, if any.
+    pub fn take_scrollback(&self) -> Option<String> {
+        self.last_scrollback.lock().unwrap().take()
+    }
+
+    /// Rewrites write permissions to point into `dir`, the staging copy or
+    /// workspace the command runs in, using `map_path` to map paths inside
+    /// the working directory.
+    ///
+    /// An unrestricted write grant becomes a grant for `dir` only. Write
+    /// scopes outside the working directory can't be redirected.
+    fn redirected_permissions(
+        permissions: &[Permission],
+        cwd: &Path,
+        dir: &Path,
+        map_path: impl Fn(&Path) -> Option<PathBuf>,
+        mode: &str,
+    ) -> Result<Vec<Permission>> {
+        permissions
+            .iter()
+            .map(|perm| {
+                if perm.kind != PermissionKind::Write {
+                    return Ok(perm.clone());
+                }
+                let Some(scopes) = &perm.scopes else {
+                    let staged = dir.to_string_lossy().to_string();
+                    return Ok(Permission::scoped(PermissionKind::Write, vec![staged]));
+                };
+                let staged = scopes
+                    .iter()
+                    .map(|scope| {
+                        map_path(&cwd.join(scope))
+                            .map(|p| p.to_string_lossy().to_string())
+                            .ok_or_else(|| {
+                                anyhow!(
+                                    "Cannot redirect writes to '{}' because it is outside the current directory. \
+                                     Run the command from a directory that contains it, or without {}.",
+                                    scope,
+                                    mode
+                                )
+                            })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Permission::scoped(PermissionKind::Write, staged))
+            })
+            .collect()
+    }
+
+    /// Lets `user` read the script through an ACL entry, keeping it private
+    /// to everyone else.
+    fn share_script(runner: &impl ProcessRunner, user: &str, script_path: &Path) -> Result<()> {
+        let (program, args) = acl_read_args(user, &script_path.to_string_lossy());
+        if !runner.program_exists(program) {
+            return Err(anyhow!(
+                "sandbox.run_as needs `{}` to let '{}' read the script. \
+                 Install it (e.g. the `acl` package) or clear the setting with `ergo config unset sandbox.run_as`.",
+                program,
+                user
+            ));
+        }
+        let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+        let output = runner.run(program, &args)?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Could not let user '{}' read the script ({}). \
+                 The temporary directory must support ACLs.",
+                user,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
         Ok(())
     }
 
+    /// Turns sudo's own failures into actionable errors.
+    ///
+    /// Errors from the command itself are reported by the caller.
+    fn check_user_switch(user: &str, status: &ExitStatus, stderr: &str) -> Result<()> {
+        if status.success() {
+            return Ok(());
+        }
+
+        let Some(sudo_error) = stderr.lines().find(|l| l.starts_with("sudo:")) else {
+            return Ok(());
+        };
+
+        error!("Could not switch to user '{}': {}", user, sudo_error);
+        let hint = if sudo_error.contains("unknown user") {
+            format!("Create the user first, e.g. `sudo useradd --system --no-create-home {}`.", user)
+        } else {
+            format!(
+                "Allow passwordless sudo for it, e.g. add `{} ALL=({}) NOPASSWD: ALL` via `visudo`.",
+                whoami(),
+                user
+            )
+        };
+        Err(anyhow!(
+            "Could not run the command as user '{}' ({}). {}",
+            user,
+            sudo_error.trim_start_matches("sudo:").trim(),
+            hint
+        ))
+    }
+
+    /// Runs a command's test script against a candidate script.
+    ///
+    /// Both files are written to a private temporary directory and executed
+    /// with `deno test`. The candidate script's path is passed to the tests
+    /// as `Deno.args[0]`; tests may read it and spawn `deno` to run it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Deno is not installed or the temporary files
+    /// cannot be written. Failing tests are reported via [`TestRunResult`].
+    pub fn run_command_tests(&self, test_script: &str, candidate_script: &str) -> Result<TestRunResult> {
+        self.run_command_tests_with_runner(test_script, candidate_script, &self.system_runner())
+    }
+
+    /// Runs a command's test script with an injected process runner (for testing).
+    pub fn run_command_tests_with_runner<P: ProcessRunner>(
+        &self,
+        test_script: &str,
+        candidate_script: &str,
+        runner: &P,
+    ) -> Result<TestRunResult> {
+        if !runner.program_exists("deno") {
+            return Err(anyhow!(
+                "Deno is not installed. Please install Deno to run command tests."
+            ));
+        }
+
+        let test_dir = tempfile::Builder::new().prefix("ergo_tests_").tempdir()?;
+        let script_path = test_dir.path().join("script.ts");
+        let test_path = test_dir.path().join("script.test.ts");
+        std::fs::write(&script_path, candidate_script)?;
+        std::fs::write(&test_path, test_script)?;
+
+        let allow_read = format!("--allow-read={}", test_dir.path().to_string_lossy());
+        let test_path_str = test_path.to_string_lossy();
+        let script_path_str = script_path.to_string_lossy();
+        let args = [
+            "test",
+            allow_read.as_str(),
+            "--allow-run=deno",
+            &test_path_str,
+            "--",
+            &script_path_str,
+        ];
+
+        info!("Running command tests: deno {:?}", args);
+        let output = runner.run("deno", &args);
+
+        drop(test_dir);
+
+        let output = output?;
+        let mut combined = String::from_utf8_lossy(&output.stdout).to_string();
+        combined.push_str(&String::from_utf8_lossy(&output.stderr));
+
+        Ok(TestRunResult {
+            passed: output.status.success(),
+            output: combined,
+        })
+    }
+
     /// Handles command output, writing to stdout/stderr and checking status.
     fn handle_output<W1: std::io::Write, W2: std::io::Write>(
         output: &Output,
@@ -349,6 +1574,12 @@ mod tests {
     struct MockProcessRunner {
         output: Output,
         program_exists: bool,
+        calls: std::sync::Mutex<Vec<Vec<String>>>,
+        missing: Vec<&'static str>,
+        /// Programs that succeed whatever `output` says.
+        succeeding: Vec<&'static str>,
+        dir: std::sync::Mutex<Option<std::path::PathBuf>>,
+        side_effect: Option<fn(&Path)>,
     }
 
     impl MockProcessRunner {
@@ -360,6 +1591,11 @@ mod tests {
                     stderr: vec![],
                 },
                 program_exists: true,
+                calls: Default::default(),
+                missing: Vec::new(),
+                succeeding: Vec::new(),
+                dir: Default::default(),
+                side_effect: None,
             }
         }
 
@@ -371,6 +1607,11 @@ mod tests {
                     stderr: stderr.as_bytes().to_vec(),
                 },
                 program_exists: true,
+                calls: Default::default(),
+                missing: Vec::new(),
+                succeeding: Vec::new(),
+                dir: Default::default(),
+                side_effect: None,
             }
         }
 
@@ -382,17 +1623,59 @@ mod tests {
                     stderr: vec![],
                 },
                 program_exists: false,
+                calls: Default::default(),
+                missing: Vec::new(),
+                succeeding: Vec::new(),
+                dir: Default::default(),
+                side_effect: None,
             }
         }
     }
 
     impl ProcessRunner for MockProcessRunner {
-        fn run(&self, _program: &str, _args: &[&str]) -> Result<Output> {
+        fn run(&self, program: &str, args: &[&str]) -> Result<Output> {
+            let mut call = vec![program.to_string()];
+            call.extend(args.iter().map(|a| a.to_string()));
+            self.calls.lock().unwrap().push(call);
+            if self.succeeding.contains(&program) {
+                return Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stdout: vec![],
+                    stderr: vec![],
+                });
+            }
             Ok(self.output.clone())
         }
 
-        fn program_exists(&self, _program: &str) -> bool {
-            self.program_exists
+        fn run_streaming(
+            &self,
+            dir: Option<&Path>,
+            program: &str,
+            args: &[&str],
+            stdout: &mut (dyn Write + Send),
+            stderr: &mut (dyn Write + Send),
+        ) -> Result<ExitStatus> {
+            *self.dir.lock().unwrap() = dir.map(Path::to_path_buf);
+            if let (Some(effect), Some(dir)) = (self.side_effect, dir) {
+                effect(dir);
+            }
+            let output = self.run(program, args)?;
+            stdout.write_all(&output.stdout)?;
+            stderr.write_all(&output.stderr)?;
+            Ok(output.status)
+        }
+
+        fn run_interactive(&self, dir: Option<&Path>, program: &str, args: &[&str]) -> Result<ExitStatus> {
+            *self.dir.lock().unwrap() = dir.map(Path::to_path_buf);
+            self.run(program, args).map(|output| output.status)
+        }
+
+        fn run_with_input(&self, program: &str, args: &[&str], _input: &[u8]) -> Result<ExitStatus> {
+            self.run(program, args).map(|output| output.status)
+        }
+
+        fn program_exists(&self, program: &str) -> bool {
+            self.program_exists && !self.missing.contains(&program)
         }
     }
 
@@ -428,6 +1711,16 @@ mod tests {
                     reason: reason.to_string(),
                 })
                 .collect(),
+            sensitive_output: false,
+            output_format: crate::output_processing::OutputFormat::Text,
+            examples: vec![],
+            assumptions: vec![],
+            confidence: None,
+            platforms: vec![],
+            variants: Default::default(),
+            provenance: None,
+            spec: None,
+            extra: Default::default(),
         }
     }
 
@@ -539,6 +1832,105 @@ mod tests {
         assert_eq!(String::from_utf8_lossy(&stdout), "Hello\n");
     }
 
+    #[test]
+    fn test_execute_generated_command_script_is_private_and_removed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        /// Records the script Deno is given while it runs.
+        struct ScriptInspector {
+            inner: MockProcessRunner,
+            seen: std::sync::Mutex<Vec<(std::path::PathBuf, u32, String)>>,
+        }
+
+        impl ProcessRunner for ScriptInspector {
+            fn run(&self, program: &str, args: &[&str]) -> Result<Output> {
+                self.inner.run(program, args)
+            }
+
+            fn run_streaming(
+                &self,
+                dir: Option<&Path>,
+                program: &str,
+                args: &[&str],
+                stdout: &mut (dyn Write + Send),
+                stderr: &mut (dyn Write + Send),
+            ) -> Result<ExitStatus> {
+                let script = std::path::PathBuf::from(args.iter().find(|arg| arg.ends_with(".ts")).unwrap());
+                let mode = std::fs::metadata(&script)?.permissions().mode() & 0o777;
+                self.seen.lock().unwrap().push((script.clone(), mode, std::fs::read_to_string(&script)?));
+                self.inner.run_streaming(dir, program, args, stdout, stderr)
+            }
+
+            fn run_interactive(&self, dir: Option<&Path>, program: &str, args: &[&str]) -> Result<ExitStatus> {
+                self.inner.run_interactive(dir, program, args)
+            }
+
+            fn run_with_input(&self, program: &str, args: &[&str], input: &[u8]) -> Result<ExitStatus> {
+                self.inner.run_with_input(program, args, input)
+            }
+
+            fn program_exists(&self, program: &str) -> bool {
+                self.inner.program_exists(program)
+            }
+        }
+
+        let executor = Executor::new(false);
+        let command = test_command("hello", vec![]);
+        let runner = ScriptInspector {
+            inner: MockProcessRunner::success(""),
+            seen: Default::default(),
+        };
+        for _ in 0..2 {
+            executor
+                .execute_generated_command_with_deps(
+                    &command,
+                    &MockScriptProvider::new("console.log('Hello');"),
+                    &[],
+                    &runner,
+                    &mut Vec::new(),
+                    &mut Vec::new(),
+                )
+                .unwrap();
+        }
+
+        let seen = runner.seen.lock().unwrap();
+        assert_eq!(seen[0].1, 0o600);
+        assert_eq!(seen[0].2, "console.log('Hello');");
+        assert_ne!(seen[0].0, seen[1].0);
+        assert!(seen.iter().all(|(script, _, _)| !script.exists()));
+    }
+
+    #[test]
+    fn test_temporary_paths_never_have_fixed_names() {
+        // Names built from the pid or a counter can be guessed and claimed
+        // first by another user; every temporary path must come from tempfile
+        let fixed_names = [
+            concat!("temp_dir", "().join("),
+            concat!("join(format!(", "\"ergo_"),
+            concat!("join(format!(", "\"ergo-"),
+        ];
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for entry in std::fs::read_dir(&src).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "rs") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            for name in fixed_names {
+                assert!(!source.contains(name), "{} builds a fixed temporary path ({})", path.display(), name);
+            }
+        }
+
+        let real = tempfile::TempDir::new().unwrap();
+        let base = tempfile::TempDir::new().unwrap();
+        let first = StagingArea::create(real.path(), base.path()).unwrap();
+        let second = StagingArea::create(real.path(), base.path()).unwrap();
+        assert_ne!(first.staged_dir(), second.staged_dir());
+        let first = Workspace::create(real.path(), base.path(), &[]).unwrap();
+        let second = Workspace::create(real.path(), base.path(), &[]).unwrap();
+        assert_ne!(first.dir(), second.dir());
+    }
+
     #[test]
     fn test_execute_generated_command_verbose_shows_description() {
         let executor = Executor::new(true);
@@ -591,6 +1983,438 @@ mod tests {
         assert!(output.contains("--allow-read"));
     }
 
+    #[test]
+    fn test_execute_generated_command_passes_canonical_permission_flags() {
+        let executor = Executor::new(false);
+        let command = test_command("fetch", vec![("--allow-net=a.com, b.com", "Network access")]);
+        let script_provider = MockScriptProvider::new("");
+        let runner = MockProcessRunner::success("");
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        executor
+            .execute_generated_command_with_deps(&command, &script_provider, &[], &runner, &mut stdout, &mut stderr)
+            .unwrap();
+
+        let calls = runner.calls.lock().unwrap();
+        assert_eq!(calls[0][..3], ["deno", "run", "--allow-net=a.com,b.com"]);
+    }
+
+    #[test]
+    fn test_execute_generated_command_runs_inside_sandbox_wrapper() {
+        let executor = Executor::new(false).with_sandbox(SandboxConfig {
+            mode: SandboxMode::Firejail,
+            required: true,
+            ..Default::default()
+        });
+        let command = test_command("hello", vec![]);
+        let script_provider = MockScriptProvider::new("console.log('Hello');");
+        let runner = MockProcessRunner::success("Hello\n");
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        executor
+            .execute_generated_command_with_deps(&command, &script_provider, &[], &runner, &mut stdout, &mut stderr)
+            .unwrap();
+
+        let calls = runner.calls.lock().unwrap();
+        assert_eq!(calls[0][0], "firejail");
+        assert!(calls[0].contains(&"--net=none".to_string()));
+        let separator = calls[0].iter().position(|a| a == "--").unwrap();
+        assert_eq!(calls[0][separator + 1..separator + 3], ["deno", "run"]);
+    }
+
+    #[test]
+    fn test_execute_generated_command_falls_back_without_wrapper() {
+        let executor = Executor::new(false).with_sandbox(SandboxConfig {
+            mode: SandboxMode::Firejail,
+            required: false,
+            ..Default::default()
+        });
+        let command = test_command("hello", vec![]);
+        let script_provider = MockScriptProvider::new("");
+        let mut runner = MockProcessRunner::success("");
+        runner.missing = vec!["firejail"];
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        executor
+            .execute_generated_command_with_deps(&command, &script_provider, &[], &runner, &mut stdout, &mut stderr)
+            .unwrap();
+
+        assert_eq!(runner.calls.lock().unwrap()[0][0], "deno");
+    }
+
+    #[test]
+    fn test_execute_generated_command_runs_as_configured_user() {
+        let executor = Executor::new(false).with_sandbox(SandboxConfig {
+            run_as: Some("ergo-sandbox".to_string()),
+            ..Default::default()
+        });
+        let command = test_command("hello", vec![("--allow-read", "Read")]);
+        let script_provider = MockScriptProvider::new("");
+        let runner = MockProcessRunner::success("");
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        executor
+            .execute_generated_command_with_deps(&command, &script_provider, &[], &runner, &mut stdout, &mut stderr)
+            .unwrap();
+
+        let calls = runner.calls.lock().unwrap();
+        let script = calls[1].iter().find(|a| a.contains("ergo_script_")).unwrap();
+        let (program, args) = acl_read_args("ergo-sandbox", script);
+        assert_eq!(calls[0][0], program);
+        assert_eq!(calls[0][1..], args[..]);
+        assert_eq!(calls[1][..7], ["sudo", "-n", "-u", "ergo-sandbox", "--", "deno", "run"]);
+        assert_eq!(calls[1][7], "--allow-read");
+    }
+
+    #[test]
+    fn test_execute_generated_command_run_as_without_sudo() {
+        let executor = Executor::new(false).with_sandbox(SandboxConfig {
+            run_as: Some("ergo-sandbox".to_string()),
+            ..Default::default()
+        });
+        let command = test_command("hello", vec![]);
+        let script_provider = MockScriptProvider::new("");
+        let mut runner = MockProcessRunner::success("");
+        runner.missing = vec!["sudo"];
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        let result = executor.execute_generated_command_with_deps(
+            &command,
+            &script_provider,
+            &[],
+            &runner,
+            &mut stdout,
+            &mut stderr,
+        );
+
+        assert!(result.unwrap_err().to_string().contains("sudo is not installed"));
+    }
+
+    #[test]
+    fn test_execute_generated_command_run_as_needs_acls() {
+        let executor = Executor::new(false).with_sandbox(SandboxConfig {
+            run_as: Some("ergo-sandbox".to_string()),
+            ..Default::default()
+        });
+        let command = test_command("hello", vec![]);
+        let script_provider = MockScriptProvider::new("");
+
+        let mut runner = MockProcessRunner::success("");
+        runner.missing = vec![acl_read_args("ergo-sandbox", "").0];
+        let result = executor.execute_generated_command_with_deps(
+            &command,
+            &script_provider,
+            &[],
+            &runner,
+            &mut Vec::new(),
+            &mut Vec::new(),
+        );
+        assert!(result.unwrap_err().to_string().contains("to let 'ergo-sandbox' read the script"));
+
+        let runner = MockProcessRunner::failure("Operation not supported\n");
+        let result = executor.execute_generated_command_with_deps(
+            &command,
+            &script_provider,
+            &[],
+            &runner,
+            &mut Vec::new(),
+            &mut Vec::new(),
+        );
+        assert!(result.unwrap_err().to_string().contains("(Operation not supported)"));
+        assert_eq!(runner.calls.lock().unwrap().len(), 1, "the command must not run");
+    }
+
+    #[test]
+    fn test_execute_generated_command_run_as_reports_sudo_failures() {
+        let executor = Executor::new(false).with_sandbox(SandboxConfig {
+            run_as: Some("ergo-sandbox".to_string()),
+            ..Default::default()
+        });
+        let command = test_command("hello", vec![]);
+        let script_provider = MockScriptProvider::new("");
+        let mut runner = MockProcessRunner::failure("sudo: a password is required\n");
+        runner.succeeding = vec![acl_read_args("ergo-sandbox", "").0];
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        let result = executor.execute_generated_command_with_deps(
+            &command,
+            &script_provider,
+            &[],
+            &runner,
+            &mut stdout,
+            &mut stderr,
+        );
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("as user 'ergo-sandbox'"));
+        assert!(err.contains("a password is required"));
+        assert!(err.contains("NOPASSWD"));
+    }
+
+    // =========================================================================
+    // Terminal passthrough tests
+    // =========================================================================
+
+    #[test]
+    fn test_tty_mode_passthrough() {
+        assert!(TtyMode::Auto.passthrough(true));
+        assert!(!TtyMode::Auto.passthrough(false));
+        assert!(TtyMode::Always.passthrough(false));
+        assert!(!TtyMode::Never.passthrough(true));
+    }
+
+    #[test]
+    fn test_passthrough_records_with_script_and_reports_failure() {
+        let executor = Executor::new(false).with_tty_passthrough(true);
+        let command = test_command("ask", vec![]);
+        let script_provider = MockScriptProvider::new("prompt('name?');");
+        let runner = MockProcessRunner::failure("ignored\n");
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        let result = executor.execute_generated_command_with_deps(
+            &command,
+            &script_provider,
+            &["x y".to_string()],
+            &runner,
+            &mut stdout,
+            &mut stderr,
+        );
+
+        assert!(result.is_err());
+        // Output goes straight to the terminal, nothing is captured
+        assert!(stdout.is_empty() && stderr.is_empty());
+        let calls = runner.calls.lock().unwrap();
+        assert_eq!(calls[0][0], "script");
+        if std::env::consts::OS == "linux" {
+            assert_eq!(calls[0][1..4], ["-q", "-e", "-c"]);
+            assert!(calls[0][4].starts_with("deno run "));
+            assert!(calls[0][4].ends_with(" 'x y'"));
+        }
+    }
+
+    #[test]
+    fn test_passthrough_of_sensitive_output_is_not_recorded() {
+        let executor = Executor::new(false).with_tty_passthrough(true);
+        let mut command = test_command("password", vec![]);
+        command.sensitive_output = true;
+        let runner = MockProcessRunner::success("");
+
+        executor
+            .execute_generated_command_with_deps(
+                &command,
+                &MockScriptProvider::new(""),
+                &[],
+                &runner,
+                &mut Vec::new(),
+                &mut Vec::new(),
+            )
+            .unwrap();
+
+        assert_eq!(runner.calls.lock().unwrap()[0][..2], ["deno", "run"]);
+        assert!(executor.take_scrollback().is_none());
+    }
+
+    #[test]
+    fn test_passthrough_without_script_runs_directly() {
+        let executor = Executor::new(false).with_tty_passthrough(true);
+        let command = test_command("ask", vec![]);
+        let script_provider = MockScriptProvider::new("");
+        let mut runner = MockProcessRunner::success("");
+        runner.missing = vec!["script"];
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        executor
+            .execute_generated_command_with_deps(&command, &script_provider, &[], &runner, &mut stdout, &mut stderr)
+            .unwrap();
+
+        assert_eq!(runner.calls.lock().unwrap()[0][..2], ["deno", "run"]);
+        assert!(executor.take_scrollback().is_none());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("--allow-read=./a"), "--allow-read=./a");
+        assert_eq!(shell_quote("two words"), "'two words'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_clean_recording_strips_headers_and_keeps_tail() {
+        let mut recording = String::from("Script started on 2024-01-01\r\n");
+        for i in 0..150 {
+            recording.push_str(&format!("line {}\r\n", i));
+        }
+        recording.push_str("Script done on 2024-01-01\n");
+
+        let cleaned = clean_recording(&recording);
+
+        assert_eq!(cleaned.lines().count(), SCROLLBACK_LINES);
+        assert!(cleaned.starts_with("line 50\n"));
+        assert!(cleaned.ends_with("line 149\n"));
+        assert!(!cleaned.contains('\r'));
+    }
+
+    struct FixedApprover(bool);
+
+    impl ChangeApprover for FixedApprover {
+        fn approve(&self, _manifest: &crate::staging::ChangeManifest, _output: &mut dyn std::io::Write) -> Result<bool> {
+            Ok(self.0)
+        }
+    }
+
+    fn run_staged(approve: bool, permission: &str) -> (tempfile::TempDir, Result<()>, String) {
+        let work = tempfile::TempDir::new().unwrap();
+        std::fs::write(work.path().join("existing.txt"), "old").unwrap();
+        let mut executor = Executor::new(false)
+            .with_working_dir(work.path().to_path_buf())
+            .with_change_approver(Box::new(FixedApprover(approve)));
+        executor.set_staged_writes(true);
+        let command = test_command("tidy", vec![(permission, "Write files")]);
+        let script_provider = MockScriptProvider::new("");
+        let mut runner = MockProcessRunner::success("");
+        runner.side_effect = Some(|dir: &Path| {
+            std::fs::write(dir.join("created.txt"), "new").unwrap();
+            std::fs::remove_file(dir.join("existing.txt")).unwrap();
+        });
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        let result = executor.execute_generated_command_with_deps(
+            &command,
+            &script_provider,
+            &[],
+            &runner,
+            &mut stdout,
+            &mut stderr,
+        );
+
+        let calls = runner.calls.lock().unwrap();
+        let flags = calls.first().map(|c| c.join(" ")).unwrap_or_default();
+        (work, result, flags)
+    }
+
+    #[test]
+    fn test_staged_writes_applied_when_approved() {
+        let (work, result, flags) = run_staged(true, "--allow-write");
+
+        result.unwrap();
+        assert!(flags.contains("--allow-write=") && flags.contains("ergo_stage_"));
+        assert!(work.path().join("created.txt").exists());
+        assert!(!work.path().join("existing.txt").exists());
+    }
+
+    #[test]
+    fn test_staged_writes_discarded_when_rejected() {
+        let (work, result, _) = run_staged(false, "--allow-write=.");
+
+        result.unwrap();
+        assert!(!work.path().join("created.txt").exists());
+        assert!(work.path().join("existing.txt").exists());
+    }
+
+    #[test]
+    fn test_staged_writes_refuse_scopes_outside_working_dir() {
+        let (_work, result, flags) = run_staged(true, "--allow-write=/etc");
+
+        assert!(result.unwrap_err().to_string().contains("outside the current directory"));
+        assert!(flags.is_empty());
+    }
+
+    #[test]
+    fn test_workspace_runs_with_inputs_only_and_copies_outputs_back() {
+        let work = tempfile::TempDir::new().unwrap();
+        std::fs::write(work.path().join("input.csv"), "a,b").unwrap();
+        std::fs::write(work.path().join("unrelated.txt"), "keep").unwrap();
+        let mut executor = Executor::new(false)
+            .with_working_dir(work.path().to_path_buf())
+            .with_change_approver(Box::new(FixedApprover(true)));
+        executor.set_workspace(true);
+        let command = test_command("summarize", vec![("--allow-write", "Write the report")]);
+        let mut runner = MockProcessRunner::success("");
+        runner.side_effect = Some(|dir: &Path| {
+            assert!(dir.join("input.csv").exists());
+            assert!(!dir.join("unrelated.txt").exists());
+            std::fs::write(dir.join("report.txt"), "2 columns").unwrap();
+        });
+
+        let result = executor.execute_generated_command_with_deps(
+            &command,
+            &MockScriptProvider::new(""),
+            &["input.csv".to_string()],
+            &runner,
+            &mut Vec::new(),
+            &mut Vec::new(),
+        );
+
+        result.unwrap();
+        let flags = runner.calls.lock().unwrap()[0].join(" ");
+        assert!(flags.contains("--allow-write=") && flags.contains("ergo_workspace_"));
+        assert_eq!(std::fs::read_to_string(work.path().join("report.txt")).unwrap(), "2 columns");
+        assert_eq!(std::fs::read_to_string(work.path().join("input.csv")).unwrap(), "a,b");
+    }
+
+    #[test]
+    fn test_execute_generated_command_required_sandbox_missing() {
+        let executor = Executor::new(false).with_sandbox(SandboxConfig {
+            mode: SandboxMode::Firejail,
+            required: true,
+            ..Default::default()
+        });
+        let command = test_command("hello", vec![]);
+        let script_provider = MockScriptProvider::new("");
+        let mut runner = MockProcessRunner::success("");
+        runner.missing = vec!["firejail"];
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+
+        let result = executor.execute_generated_command_with_deps(
+            &command,
+            &script_provider,
+            &[],
+            &runner,
+            &mut stdout,
+            &mut stderr,
+        );
+
+        assert!(result.unwrap_err().to_string().contains("sandbox wrapper is required"));
+        assert!(runner.calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_execute_generated_command_refuses_smuggled_flags() {
+        let executor = Executor::new(false);
+        let script_provider = MockScriptProvider::new("");
+
+        for flag in ["--allow-all", "-A", "--unstable", "--allow-read --allow-all", "--allow-read=--allow-all"] {
+            let command = test_command("sneaky", vec![("--allow-read", "Read"), (flag, "Innocent")]);
+            let runner = MockProcessRunner::success("");
+            let mut stdout = Vec::new();
+            let mut stderr = Vec::new();
+
+            let result = executor.execute_generated_command_with_deps(
+                &command,
+                &script_provider,
+                &[],
+                &runner,
+                &mut stdout,
+                &mut stderr,
+            );
+
+            let err = result.unwrap_err().to_string();
+            assert!(err.contains("not an allowed Deno permission"), "{}", flag);
+            assert!(runner.calls.lock().unwrap().is_empty(), "deno must not run for {}", flag);
+        }
+    }
+
     #[test]
     fn test_execute_generated_command_non_verbose_no_extra_output() {
         let executor = Executor::new(false);
@@ -636,6 +2460,255 @@ mod tests {
         assert_eq!(String::from_utf8_lossy(&stderr), "Error: Oops\n");
     }
 
+    // =========================================================================
+    // Batch execution tests
+    // =========================================================================
+
+    fn batch_job(name: &str, permissions: Vec<(&str, &str)>) -> BatchJob {
+        BatchJob {
+            command: test_command(name, permissions),
+            script: format!("console.log('{}');", name),
+            args: vec![],
+        }
+    }
+
+    #[test]
+    fn test_execute_many_prefixes_output_and_keeps_job_order() {
+        let executor = Executor::new(false);
+        let runner = MockProcessRunner::success("ok\n");
+        let jobs = vec![batch_job("first", vec![]), batch_job("second", vec![]), batch_job("third", vec![])];
+        let stdout = Mutex::new(Vec::new());
+        let stderr = Mutex::new(Vec::new());
+
+        let results = executor.execute_many_with_deps(&jobs, 2, &runner, &stdout, &stderr);
+
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r.success && r.exit_code == Some(0)));
+        assert_eq!(results[1].script_hash, execution_context::script_hash("console.log('second');"));
+        let output = String::from_utf8(stdout.into_inner().unwrap()).unwrap();
+        for name in ["first", "second", "third"] {
+            assert!(output.contains(&format!("[{}] ok\n", name)));
+        }
+        assert_eq!(runner.calls.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_execute_many_reports_failures_per_command() {
+        let executor = Executor::new(false);
+        let runner = MockProcessRunner::failure("boom\n");
+        let jobs = vec![batch_job("flaky", vec![])];
+        let stdout = Mutex::new(Vec::new());
+        let stderr = Mutex::new(Vec::new());
+
+        let results = executor.execute_many_with_deps(&jobs, 4, &runner, &stdout, &stderr);
+
+        assert!(!results[0].success);
+        assert_eq!(results[0].exit_code, Some(1));
+        let errors = String::from_utf8(stderr.into_inner().unwrap()).unwrap();
+        assert_eq!(errors, "[flaky] boom\n[flaky] ❌ Exited with code 1\n");
+    }
+
+    #[test]
+    fn test_execute_many_refuses_staged_writes() {
+        let sandbox = SandboxConfig {
+            stage_writes: true,
+            ..Default::default()
+        };
+        let executor = Executor::new(false).with_sandbox(sandbox);
+        let runner = MockProcessRunner::success("");
+        let jobs = vec![batch_job("writer", vec![("--allow-write", "Save files")])];
+        let stdout = Mutex::new(Vec::new());
+        let stderr = Mutex::new(Vec::new());
+
+        let results = executor.execute_many_with_deps(&jobs, 1, &runner, &stdout, &stderr);
+
+        assert!(!results[0].success);
+        assert!(runner.calls.lock().unwrap().is_empty());
+        assert!(results[0].stderr.as_deref().unwrap().contains("one command at a time"));
+    }
+
+    #[test]
+    fn test_execute_many_hides_sensitive_output() {
+        let executor = Executor::new(false);
+        let runner = MockProcessRunner::success("hunter2\n");
+        let mut job = batch_job("password", vec![]);
+        job.command.sensitive_output = true;
+        let stdout = Mutex::new(Vec::new());
+        let stderr = Mutex::new(Vec::new());
+
+        let results = executor.execute_many_with_deps(&[job], 1, &runner, &stdout, &stderr);
+
+        assert!(results[0].sensitive);
+        let output = String::from_utf8(stdout.into_inner().unwrap()).unwrap();
+        assert!(!output.contains("hunter2"));
+        assert!(output.starts_with("[password] 🔒 Sensitive output hidden (1 line)"));
+    }
+
+    #[test]
+    fn test_system_runner_streams_large_output_from_both_pipes() {
+        // More than a pipe buffer on each stream; reading them one after the
+        // other would deadlock
+        let mut stdout = CaptureBuffer::new(16);
+        let mut stderr = CaptureBuffer::new(16);
+
+        let status = SystemProcessRunner::default()
+            .run_streaming(
+                None,
+                "sh",
+                &["-c", "head -c 200000 /dev/zero >&2; head -c 200000 /dev/zero; printf end; exit 3"],
+                &mut stdout,
+                &mut stderr,
+            )
+            .unwrap();
+
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(stdout.total_bytes(), 200_003);
+        assert_eq!(stderr.total_bytes(), 200_000);
+        assert!(stdout.contents().ends_with("end"));
+    }
+
+    /// Runs `f` with ergo's stdin replaced by a pipe holding `input`, as in
+    /// `printf input | ergo ...`.
+    #[cfg(unix)]
+    fn with_piped_stdin<T>(input: &[u8], f: impl FnOnce() -> T) -> T {
+        use std::os::fd::FromRawFd;
+        static STDIN: Mutex<()> = Mutex::new(());
+        let _guard = STDIN.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut fds = [0; 2];
+        // SAFETY: pipe(2), dup(2) and dup2(2) only touch the descriptors
+        // passed in, which are owned here
+        let saved = unsafe {
+            assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+            let saved = libc::dup(0);
+            libc::dup2(fds[0], 0);
+            libc::close(fds[0]);
+            saved
+        };
+        // SAFETY: the write end was just created and nothing else owns it
+        let mut writer = unsafe { std::fs::File::from_raw_fd(fds[1]) };
+        writer.write_all(input).unwrap();
+        drop(writer);
+
+        let result = f();
+        // SAFETY: as above
+        unsafe {
+            libc::dup2(saved, 0);
+            libc::close(saved);
+        }
+        result
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_system_runner_passes_piped_stdin_to_the_command() {
+        let mut stdout = Vec::new();
+
+        let status = with_piped_stdin(b"piped data", || {
+            SystemProcessRunner::default().run_streaming(None, "cat", &[], &mut stdout, &mut Vec::new())
+        });
+
+        assert!(status.unwrap().success());
+        assert_eq!(stdout, b"piped data");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_json_format_starter_reads_piped_input() {
+        let runner = SystemProcessRunner::default();
+        if !runner.program_exists("deno") {
+            eprintln!("Deno is not installed; skipping");
+            return;
+        }
+        let starter = crate::starter::STARTER_COMMANDS
+            .iter()
+            .find(|starter| starter.name == "json-format")
+            .unwrap();
+        let executor = Executor::new(false);
+        let mut stdout = Vec::new();
+
+        with_piped_stdin(br#"{ "a": [1, 2] }"#, || {
+            executor.execute_generated_command_with_deps(
+                &starter.command(),
+                &MockScriptProvider::new(starter.script),
+                &["--compact".to_string()],
+                &runner,
+                &mut stdout,
+                &mut Vec::new(),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(String::from_utf8(stdout).unwrap(), "{\"a\":[1,2]}\n");
+    }
+
+    #[test]
+    fn test_system_runner_cancellation_kills_the_process_group() {
+        let cancellation = Cancellation::new();
+        let runner = SystemProcessRunner::with_cancellation(cancellation.clone());
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            cancellation.cancel();
+        });
+        let started = Instant::now();
+
+        // The background sleep ignores SIGINT and keeps the pipes open until
+        // the group is killed
+        let status = runner
+            .run_streaming(None, "sh", &["-c", "sleep 30 & sleep 30"], &mut Vec::new(), &mut Vec::new())
+            .unwrap();
+
+        canceller.join().unwrap();
+        assert!(!status.success());
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    // =========================================================================
+    // Command test runner tests
+    // =========================================================================
+
+    #[test]
+    fn test_run_command_tests_passing() {
+        let executor = Executor::new(false);
+        let runner = MockProcessRunner::success("ok | 1 passed\n");
+
+        let result = executor
+            .run_command_tests_with_runner("Deno.test('x', () => {});", "console.log(1);", &runner)
+            .unwrap();
+
+        assert!(result.passed);
+        assert!(result.output.contains("1 passed"));
+    }
+
+    #[test]
+    fn test_run_command_tests_failing() {
+        let executor = Executor::new(false);
+        let runner = MockProcessRunner::failure("FAILED | 1 failed\n");
+
+        let result = executor
+            .run_command_tests_with_runner("Deno.test('x', () => {});", "console.log(1);", &runner)
+            .unwrap();
+
+        assert!(!result.passed);
+        assert!(result.output.contains("1 failed"));
+    }
+
+    #[test]
+    fn test_exit_signal() {
+        assert_eq!(exit_signal(&ExitStatus::from_raw(9)), Some(9));
+        assert_eq!(exit_signal(&ExitStatus::from_raw(127 << 8)), None);
+    }
+
+    #[test]
+    fn test_run_command_tests_deno_missing() {
+        let executor = Executor::new(false);
+        let runner = MockProcessRunner::missing_program();
+
+        let result = executor.run_command_tests_with_runner("", "", &runner);
+
+        assert!(result.is_err());
+    }
+
     // =========================================================================
     // handle_output tests
     // =========================================================================
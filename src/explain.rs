@@ -0,0 +1,124 @@
+//! Rendering of script explanations for `ergo --explain`.
+//!
+//! The explanation itself comes from the LLM (see
+//! [`LlmGenerator::explain_script`](crate::llm_generator::LlmGenerator::explain_script));
+//! this module lays it out next to the script source so each line can be read
+//! together with what it does.
+
+use crate::llm_generator::ScriptExplanation;
+
+/// Writes an annotated listing of `script` followed by a summary of its
+/// external effects.
+///
+/// Lines without an explanation are still printed so the listing reads as the
+/// full script. Explanations referring to lines outside the script are ignored.
+pub fn write_explanation<W: std::io::Write>(
+    output: &mut W,
+    command_name: &str,
+    script: &str,
+    explanation: &ScriptExplanation,
+) -> std::io::Result<()> {
+    writeln!(output, "🔍 {}: {}", command_name, explanation.summary)?;
+    writeln!(output)?;
+
+    for (index, line) in script.lines().enumerate() {
+        let number = index + 1;
+        writeln!(output, "{:>4} │ {}", number, line)?;
+        for annotation in explanation.lines.iter().filter(|l| l.line == number) {
+            writeln!(output, "     └─ {}", annotation.explanation)?;
+        }
+    }
+
+    writeln!(output)?;
+    let effects = &explanation.effects;
+    if effects.is_empty() {
+        writeln!(output, "🌐 External effects: none")?;
+        return Ok(());
+    }
+
+    writeln!(output, "🌐 External effects:")?;
+    write_effect_list(output, "📁 Files", &effects.files)?;
+    write_effect_list(output, "🌍 Hosts", &effects.hosts)?;
+    write_effect_list(output, "⚙️  Other", &effects.other)?;
+
+    Ok(())
+}
+
+fn write_effect_list<W: std::io::Write>(output: &mut W, label: &str, items: &[String]) -> std::io::Result<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+    writeln!(output, "   {}:", label)?;
+    for item in items {
+        writeln!(output, "      - {}", item)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_generator::{ExternalEffects, LineExplanation};
+
+    fn render(script: &str, explanation: &ScriptExplanation) -> String {
+        let mut output = Vec::new();
+        write_explanation(&mut output, "weather", script, explanation).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_annotations_follow_their_line() {
+        let explanation = ScriptExplanation {
+            summary: "Shows the weather".to_string(),
+            lines: vec![LineExplanation {
+                line: 2,
+                explanation: "Downloads the forecast".to_string(),
+            }],
+            effects: ExternalEffects::default(),
+        };
+
+        let output = render("const city = Deno.args[0];\nawait fetch(url);\n", &explanation);
+
+        let fetch = output.find("await fetch(url);").unwrap();
+        let note = output.find("Downloads the forecast").unwrap();
+        assert!(output.contains("Shows the weather"));
+        assert!(output.contains("   1 │ const city"));
+        assert!(note > fetch);
+    }
+
+    #[test]
+    fn test_out_of_range_annotations_are_ignored() {
+        let explanation = ScriptExplanation {
+            summary: String::new(),
+            lines: vec![LineExplanation {
+                line: 42,
+                explanation: "Phantom line".to_string(),
+            }],
+            effects: ExternalEffects::default(),
+        };
+
+        let output = render("console.log(1);\n", &explanation);
+
+        assert!(!output.contains("Phantom line"));
+        assert!(output.contains("External effects: none"));
+    }
+
+    #[test]
+    fn test_effects_are_listed_by_kind() {
+        let explanation = ScriptExplanation {
+            summary: String::new(),
+            lines: Vec::new(),
+            effects: ExternalEffects {
+                files: vec!["./cache.json".to_string()],
+                hosts: vec!["wttr.in".to_string()],
+                other: Vec::new(),
+            },
+        };
+
+        let output = render("", &explanation);
+
+        assert!(output.contains("Files:\n      - ./cache.json"));
+        assert!(output.contains("Hosts:\n      - wttr.in"));
+        assert!(!output.contains("Other"));
+    }
+}
@@ -0,0 +1,362 @@
+//! Fixture-driven generation for deterministic, offline end-to-end tests.
+//!
+//! Two environment variables switch the generator away from the configured
+//! backend:
+//!
+//! - `ABIOGENESIS_MOCK_FIXTURES=dir` answers every request from a JSON
+//!   fixture in `dir`, without any API call ([`FixtureGenerator`])
+//! - `ABIOGENESIS_RECORD_FIXTURES=dir` uses the configured backend and saves
+//!   each answer as a fixture in `dir`, ready for replay
+//!   ([`RecordingGenerator`])
+//!
+//! Fixtures hold the same command JSON generator plugins answer with (see
+//! [`crate::plugins`]) and are named after the request:
+//!
+//! | Request                         | Fixture                          |
+//! |---------------------------------|----------------------------------|
+//! | `ergo weather London`           | `generate-weather.json`          |
+//! | `ergo "show today's date"`      | `describe-show-today-s-date.json` |
+//! | `ergo --nope weather ...`       | `regenerate-weather.json`        |
+//!
+//! With `--plan`, the fixture of the request answers both the plan (its
+//! description and permissions) and the generation that follows it.
+
+use crate::llm_generator::{self, CommandGenerator, FeedbackRequest, GenerationPlan, GenerationResult, PlanRequest};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde_json::json;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Replays fixtures from this directory instead of calling a backend.
+pub const MOCK_FIXTURES_ENV: &str = "ABIOGENESIS_MOCK_FIXTURES";
+
+/// Records the backend's answers as fixtures in this directory.
+pub const RECORD_FIXTURES_ENV: &str = "ABIOGENESIS_RECORD_FIXTURES";
+
+/// Longest description slug used in a fixture name.
+const MAX_SLUG_CHARS: usize = 60;
+
+/// A generation request, as far as fixtures are concerned.
+#[derive(Clone, Copy)]
+enum Request<'a> {
+    Generate(&'a str),
+    Describe(&'a str),
+    Regenerate(&'a str),
+}
+
+impl<'a> Request<'a> {
+    /// The request a command generated from a plan for `request` answers.
+    fn planned(request: PlanRequest<'a>) -> Self {
+        match request {
+            PlanRequest::Named { name, .. } => Request::Generate(name),
+            PlanRequest::Described(description) => Request::Describe(description),
+        }
+    }
+
+    /// File name of the fixture answering this request.
+    fn file_name(&self) -> String {
+        match self {
+            Request::Generate(name) => format!("generate-{}.json", name),
+            Request::Describe(description) => format!("describe-{}.json", slug(description)),
+            Request::Regenerate(name) => format!("regenerate-{}.json", name),
+        }
+    }
+}
+
+impl fmt::Display for Request<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Generate(name) => write!(f, "command '{}'", name),
+            Request::Describe(description) => write!(f, "description '{}'", description),
+            Request::Regenerate(name) => write!(f, "regenerating '{}'", name),
+        }
+    }
+}
+
+/// Lowercases `text` and joins its alphanumeric runs with dashes.
+fn slug(text: &str) -> String {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let slug = words.join("-");
+    match slug.char_indices().nth(MAX_SLUG_CHARS) {
+        Some((end, _)) => slug[..end].trim_end_matches('-').to_string(),
+        None => slug,
+    }
+}
+
+/// Picks the generator: fixture replay or recording if `env` asks for it,
+/// otherwise the one `configured` creates.
+///
+/// Replay takes precedence, and never creates the configured generator, so
+/// it needs no API key or plugin.
+pub fn select_generator(
+    env: impl Fn(&str) -> Option<String>,
+    configured: impl FnOnce() -> Result<Box<dyn CommandGenerator>>,
+) -> Result<Box<dyn CommandGenerator>> {
+    let dir = |name| env(name).filter(|dir: &String| !dir.is_empty()).map(PathBuf::from);
+    if let Some(dir) = dir(MOCK_FIXTURES_ENV) {
+        info!("Replaying generator fixtures from {:?}", dir);
+        return Ok(Box::new(FixtureGenerator::new(dir)));
+    }
+    if let Some(dir) = dir(RECORD_FIXTURES_ENV) {
+        info!("Recording generator fixtures to {:?}", dir);
+        return Ok(Box::new(RecordingGenerator::new(configured()?, dir)));
+    }
+    configured()
+}
+
+/// A [`CommandGenerator`] that answers from fixtures in a directory.
+pub struct FixtureGenerator {
+    dir: PathBuf,
+}
+
+impl FixtureGenerator {
+    /// Creates a generator that reads fixtures from `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn load(&self, request: Request<'_>) -> Result<GenerationResult> {
+        let path = self.dir.join(request.file_name());
+        let content = fs::read_to_string(&path).with_context(|| {
+            format!(
+                "No fixture for {} at {:?} (record one with {}={})",
+                request,
+                path,
+                RECORD_FIXTURES_ENV,
+                self.dir.display()
+            )
+        })?;
+        llm_generator::parse_command_json(&content)
+    }
+}
+
+#[async_trait]
+impl CommandGenerator for FixtureGenerator {
+    async fn generate_command(&self, command_name: &str, _args: &[String]) -> Result<GenerationResult> {
+        let mut result = self.load(Request::Generate(command_name))?;
+        rename(&mut result, command_name);
+        Ok(result)
+    }
+
+    async fn generate_command_from_description(&self, description: &str) -> Result<GenerationResult> {
+        self.load(Request::Describe(description))
+    }
+
+    async fn regenerate_command_with_feedback(&self, request: &FeedbackRequest<'_>) -> Result<GenerationResult> {
+        let mut result = self.load(Request::Regenerate(request.command_name))?;
+        rename(&mut result, request.command_name);
+        Ok(result)
+    }
+
+    async fn plan_command(&self, request: PlanRequest<'_>) -> Result<GenerationPlan> {
+        let result = self.load(Request::planned(request))?;
+        Ok(GenerationPlan {
+            summary: result.command.description,
+            permissions: result.command.permissions,
+            ..GenerationPlan::default()
+        })
+    }
+
+    async fn generate_from_plan(&self, request: PlanRequest<'_>, _plan: &GenerationPlan) -> Result<GenerationResult> {
+        let mut result = self.load(Request::planned(request))?;
+        if let Some(name) = request.command_name() {
+            rename(&mut result, name);
+        }
+        Ok(result)
+    }
+
+    fn set_model(&mut self, _model: &str) -> Result<()> {
+        // Fixtures are replayed whatever the model
+        Ok(())
+    }
+}
+
+/// Keeps the name asked for, whatever the fixture says.
+fn rename(result: &mut GenerationResult, command_name: &str) {
+    result.command.name = command_name.to_string();
+    result.command.script_file = format!("{}.ts", command_name);
+}
+
+/// A [`CommandGenerator`] that passes requests to another generator and
+/// saves its answers as fixtures for [`FixtureGenerator`].
+pub struct RecordingGenerator {
+    inner: Box<dyn CommandGenerator>,
+    dir: PathBuf,
+}
+
+impl RecordingGenerator {
+    /// Creates a generator that records `inner`'s answers in `dir`.
+    pub fn new(inner: Box<dyn CommandGenerator>, dir: impl Into<PathBuf>) -> Self {
+        Self { inner, dir: dir.into() }
+    }
+
+    fn record(&self, request: Request<'_>, result: Result<GenerationResult>) -> Result<GenerationResult> {
+        let result = result?;
+        let path = self.dir.join(request.file_name());
+        write_fixture(&path, &result).with_context(|| format!("Failed to record fixture {:?}", path))?;
+        info!("Recorded fixture {:?}", path);
+        Ok(result)
+    }
+}
+
+/// Saves `result` at `path` in the command JSON format.
+fn write_fixture(path: &Path, result: &GenerationResult) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let command = &result.command;
+    let fixture = json!({
+        "name": command.name,
+        "description": command.description,
+        "script": result.script_content,
+        "permissions": command.permissions,
+        "sensitive_output": command.sensitive_output,
+        "output_format": command.output_format,
+        "examples": command.examples,
+        "assumptions": command.assumptions,
+        "confidence": command.confidence,
+        "platforms": command.platforms,
+    });
+    fs::write(path, serde_json::to_string_pretty(&fixture)? + "\n")?;
+    Ok(())
+}
+
+#[async_trait]
+impl CommandGenerator for RecordingGenerator {
+    async fn generate_command(&self, command_name: &str, args: &[String]) -> Result<GenerationResult> {
+        let result = self.inner.generate_command(command_name, args).await;
+        self.record(Request::Generate(command_name), result)
+    }
+
+    async fn generate_command_from_description(&self, description: &str) -> Result<GenerationResult> {
+        let result = self.inner.generate_command_from_description(description).await;
+        self.record(Request::Describe(description), result)
+    }
+
+    async fn regenerate_command_with_feedback(&self, request: &FeedbackRequest<'_>) -> Result<GenerationResult> {
+        let result = self.inner.regenerate_command_with_feedback(request).await;
+        self.record(Request::Regenerate(request.command_name), result)
+    }
+
+    async fn plan_command(&self, request: PlanRequest<'_>) -> Result<GenerationPlan> {
+        self.inner.plan_command(request).await
+    }
+
+    async fn generate_from_plan(&self, request: PlanRequest<'_>, plan: &GenerationPlan) -> Result<GenerationResult> {
+        let result = self.inner.generate_from_plan(request, plan).await;
+        self.record(Request::planned(request), result)
+    }
+
+    fn set_model(&mut self, model: &str) -> Result<()> {
+        self.inner.set_model(model)
+    }
+
+    fn warm_up(&self) {
+        self.inner.warm_up();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use tempfile::TempDir;
+
+    const COMMAND_JSON: &str = r#"{"name":"suggested","description":"Says hi","script":"console.log('hi')","permissions":[{"permission":"--allow-env","reason":"Read HOME"}],"assumptions":["HOME is set"],"confidence":"high"}"#;
+
+    fn feedback(command_name: &str) -> FeedbackRequest<'_> {
+        FeedbackRequest {
+            command_name,
+            original_script: "console.log('hi')",
+            stderr: None,
+            exit_status: None,
+            user_feedback: "louder",
+            test_script: None,
+            history: &[],
+            intent: None,
+        }
+    }
+
+    #[test]
+    fn test_fixture_names_follow_the_request() {
+        assert_eq!(Request::Generate("weather").file_name(), "generate-weather.json");
+        assert_eq!(
+            Request::Describe("Show today's  DATE!").file_name(),
+            "describe-show-today-s-date.json"
+        );
+        assert_eq!(Request::Regenerate("weather").file_name(), "regenerate-weather.json");
+        assert_eq!(slug(&"word ".repeat(30)).chars().count(), 59);
+    }
+
+    #[tokio::test]
+    async fn test_fixture_generator_replays_fixtures() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("generate-hello.json"), COMMAND_JSON).unwrap();
+        fs::write(temp_dir.path().join("describe-say-hi.json"), COMMAND_JSON).unwrap();
+        let generator = FixtureGenerator::new(temp_dir.path());
+
+        let generated = generator.generate_command("hello", &[]).await.unwrap();
+        let described = generator.generate_command_from_description("say hi").await.unwrap();
+
+        assert_eq!(generated.command.name, "hello");
+        assert_eq!(generated.command.script_file, "hello.ts");
+        assert_eq!(generated.script_content, "console.log('hi')");
+        assert_eq!(generated.command.permissions[0].permission, "--allow-env");
+        assert_eq!(described.command.name, "suggested");
+    }
+
+    #[tokio::test]
+    async fn test_missing_fixture_names_the_expected_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let generator = FixtureGenerator::new(temp_dir.path());
+
+        let err = generator
+            .regenerate_command_with_feedback(&feedback("weather"))
+            .await
+            .err()
+            .unwrap();
+
+        let message = err.to_string();
+        assert!(message.contains("regenerate-weather.json"));
+        assert!(message.contains(RECORD_FIXTURES_ENV));
+    }
+
+    #[tokio::test]
+    async fn test_recording_generator_saves_replayable_fixtures() {
+        let source = TempDir::new().unwrap();
+        fs::write(source.path().join("generate-hello.json"), COMMAND_JSON).unwrap();
+        fs::write(source.path().join("regenerate-hello.json"), COMMAND_JSON).unwrap();
+        let recorded = TempDir::new().unwrap();
+        let recorder = RecordingGenerator::new(Box::new(FixtureGenerator::new(source.path())), recorded.path());
+
+        let original = recorder.generate_command("hello", &[]).await.unwrap();
+        recorder.regenerate_command_with_feedback(&feedback("hello")).await.unwrap();
+        assert!(recorder.generate_command("missing", &[]).await.is_err());
+
+        let replayed = FixtureGenerator::new(recorded.path()).generate_command("hello", &[]).await.unwrap();
+        assert_eq!(replayed.command, original.command);
+        assert_eq!(replayed.script_content, original.script_content);
+        assert!(recorded.path().join("regenerate-hello.json").exists());
+        assert!(!recorded.path().join("generate-missing.json").exists());
+    }
+
+    #[test]
+    fn test_select_generator_prefers_replay() {
+        let env = |name: &str| (name == MOCK_FIXTURES_ENV).then(|| "fixtures".to_string());
+        let selected = select_generator(env, || Err(anyhow!("no backend configured")));
+        assert!(selected.is_ok());
+
+        let env = |name: &str| (name == RECORD_FIXTURES_ENV).then(|| "fixtures".to_string());
+        let err = select_generator(env, || Err(anyhow!("no backend configured"))).err().unwrap();
+        assert_eq!(err.to_string(), "no backend configured");
+
+        assert!(select_generator(|_| Some(String::new()), || Err(anyhow!("configured"))).is_err());
+    }
+}
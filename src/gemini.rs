@@ -0,0 +1,506 @@
+//! Google Gemini models, through AI Studio or Vertex AI.
+//!
+//! Select Gemini with `generator.backend = "gemini"`:
+//!
+//! ```toml
+//! [generator]
+//! backend = "gemini"
+//!
+//! [generator.gemini]
+//! api = "ai-studio"          # ai-studio | vertex
+//! model = "gemini-1.5-flash"
+//! api_key = "..."            # AI Studio; default: GEMINI_API_KEY or GOOGLE_API_KEY
+//! project = "my-project"     # Vertex AI; default: GOOGLE_CLOUD_PROJECT
+//! location = "us-central1"   # Vertex AI
+//! ```
+//!
+//! Vertex AI requests are authorized with the access token of the active
+//! `gcloud` login (`gcloud auth print-access-token`).
+//!
+//! Gemini constrains its output with a response schema rather than prompt
+//! instructions alone, so command generation sends [`command_schema`], and
+//! every request asks for JSON. Responses blocked by Gemini's safety filters
+//! are reported as errors naming the reason instead of failing to parse.
+
+use crate::executor::ProcessRunner;
+use crate::llm_generator::{Message, Role};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fmt;
+use tracing::info;
+
+/// Name of the Gemini backend in `generator.backend`.
+pub const GEMINI_BACKEND: &str = "gemini";
+
+/// Default Gemini model.
+pub const DEFAULT_GEMINI_MODEL: &str = "gemini-1.5-flash";
+
+/// Default Vertex AI location.
+pub const DEFAULT_VERTEX_LOCATION: &str = "us-central1";
+
+/// Which Google API serves the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GeminiApi {
+    /// Google AI Studio (`generativelanguage.googleapis.com`), with an API key.
+    #[default]
+    AiStudio,
+    /// Vertex AI, with a `gcloud` access token.
+    Vertex,
+}
+
+impl fmt::Display for GeminiApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeminiApi::AiStudio => write!(f, "AI Studio"),
+            GeminiApi::Vertex => write!(f, "Vertex AI"),
+        }
+    }
+}
+
+/// Gemini settings (the `[generator.gemini]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeminiConfig {
+    /// Which API serves the model.
+    #[serde(default)]
+    pub api: GeminiApi,
+    /// Gemini model name.
+    #[serde(default = "default_model")]
+    pub model: String,
+    /// AI Studio API key; falls back to `GEMINI_API_KEY` and `GOOGLE_API_KEY`.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Vertex AI project; falls back to `GOOGLE_CLOUD_PROJECT`.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Vertex AI location.
+    #[serde(default = "default_location")]
+    pub location: String,
+}
+
+impl Default for GeminiConfig {
+    fn default() -> Self {
+        Self {
+            api: GeminiApi::default(),
+            model: default_model(),
+            api_key: None,
+            project: None,
+            location: default_location(),
+        }
+    }
+}
+
+fn default_model() -> String {
+    DEFAULT_GEMINI_MODEL.to_string()
+}
+
+fn default_location() -> String {
+    DEFAULT_VERTEX_LOCATION.to_string()
+}
+
+/// A resolved Gemini model: its URL and authorization header.
+#[derive(Clone, PartialEq)]
+pub struct GeminiTarget {
+    /// Which API serves the model.
+    pub api: GeminiApi,
+    /// Gemini model name.
+    pub model: String,
+    /// URL of the `generateContent` call.
+    pub url: String,
+    auth_header: (&'static str, String),
+}
+
+impl fmt::Debug for GeminiTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never log the API key or token
+        f.debug_struct("GeminiTarget")
+            .field("api", &self.api)
+            .field("model", &self.model)
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GeminiTarget {
+    /// Resolves the URL and credentials for `config`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[generator.gemini]` settings
+    /// * `env` - Looks up environment variables
+    /// * `runner` - Runs `gcloud` for Vertex AI access tokens
+    pub fn resolve<P: ProcessRunner>(
+        config: &GeminiConfig,
+        env: impl Fn(&str) -> Option<String>,
+        runner: &P,
+    ) -> Result<Self> {
+        let target = match config.api {
+            GeminiApi::AiStudio => {
+                let api_key = config
+                    .api_key
+                    .clone()
+                    .or_else(|| env("GEMINI_API_KEY"))
+                    .or_else(|| env("GOOGLE_API_KEY"))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "No Gemini API key found. Set it with:\n\
+                            \n\
+                               ergo config set generator.gemini.api_key YOUR_KEY\n\
+                            \n\
+                            or export GEMINI_API_KEY."
+                        )
+                    })?;
+                Self {
+                    api: config.api,
+                    model: config.model.clone(),
+                    url: format!(
+                        "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
+                        config.model
+                    ),
+                    auth_header: ("x-goog-api-key", api_key),
+                }
+            }
+            GeminiApi::Vertex => {
+                let project = config
+                    .project
+                    .clone()
+                    .or_else(|| env("GOOGLE_CLOUD_PROJECT"))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "No Vertex AI project configured. Set it with:\n\
+                            \n\
+                               ergo config set generator.gemini.project YOUR_PROJECT\n\
+                            \n\
+                            or export GOOGLE_CLOUD_PROJECT."
+                        )
+                    })?;
+                let output = runner
+                    .run("gcloud", &["auth", "print-access-token"])
+                    .map_err(|e| anyhow!("Failed to run gcloud for a Vertex AI access token: {}", e))?;
+                let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if !output.status.success() || token.is_empty() {
+                    return Err(anyhow!(
+                        "Could not get a Vertex AI access token: {}\nLog in with 'gcloud auth login'.",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+                Self {
+                    api: config.api,
+                    model: config.model.clone(),
+                    url: format!(
+                        "https://{location}-aiplatform.googleapis.com/v1/projects/{project}/locations/{location}/publishers/google/models/{model}:generateContent",
+                        location = config.location,
+                        project = project,
+                        model = config.model
+                    ),
+                    auth_header: ("authorization", format!("Bearer {}", token)),
+                }
+            }
+        };
+
+        info!("Using Gemini model '{}' via {}", target.model, target.api);
+        Ok(target)
+    }
+
+    /// URL listing the models the credentials can use, where the API has one
+    /// (AI Studio; Vertex AI lists models in the Cloud console).
+    pub fn models_url(&self) -> Option<String> {
+        match self.api {
+            GeminiApi::AiStudio => Some("https://generativelanguage.googleapis.com/v1beta/models?pageSize=1000".to_string()),
+            GeminiApi::Vertex => None,
+        }
+    }
+
+    /// Headers for a `generateContent` request.
+    pub fn headers(&self) -> [(&str, &str); 2] {
+        [
+            (self.auth_header.0, self.auth_header.1.as_str()),
+            ("content-type", "application/json"),
+        ]
+    }
+}
+
+/// Builds a `generateContent` request body.
+///
+/// # Arguments
+///
+/// * `system` - Instructions, sent as the system instruction
+/// * `messages` - The conversation; assistant turns use Gemini's `model` role
+/// * `max_tokens` - Maximum output tokens
+/// * `schema` - Response schema the output must follow, if any
+pub fn request_body(system: &str, messages: &[Message], max_tokens: u32, schema: Option<Value>) -> Value {
+    let mut generation_config = json!({
+        "maxOutputTokens": max_tokens,
+        "responseMimeType": "application/json"
+    });
+    if let Some(schema) = schema {
+        generation_config["responseSchema"] = schema;
+    }
+    let contents: Vec<Value> = messages
+        .iter()
+        .map(|m| {
+            let role = match m.role {
+                Role::User => "user",
+                Role::Assistant => "model",
+            };
+            json!({"role": role, "parts": [{"text": m.content}]})
+        })
+        .collect();
+    json!({
+        "systemInstruction": {"parts": [{"text": system}]},
+        "contents": contents,
+        "generationConfig": generation_config
+    })
+}
+
+/// Response schema of a generated command, in Gemini's OpenAPI subset.
+///
+/// Mirrors the JSON the Claude prompt asks for: `name`, `description`,
+/// `script`, `permissions`, `sensitive_output`, `output_format`, `examples`, `assumptions`,
+/// `confidence` and `platforms`.
+pub fn command_schema() -> Value {
+    let string = json!({"type": "STRING"});
+    json!({
+        "type": "OBJECT",
+        "properties": {
+            "name": string,
+            "description": string,
+            "script": string,
+            "permissions": {
+                "type": "ARRAY",
+                "items": {
+                    "type": "OBJECT",
+                    "properties": {"permission": string, "reason": string},
+                    "required": ["permission", "reason"]
+                }
+            },
+            "sensitive_output": {"type": "BOOLEAN"},
+            "output_format": {"type": "STRING", "enum": ["text", "json"]},
+            "examples": {
+                "type": "ARRAY",
+                "items": {
+                    "type": "OBJECT",
+                    "properties": {"usage": string, "explanation": string},
+                    "required": ["usage", "explanation"]
+                }
+            },
+            "assumptions": {"type": "ARRAY", "items": string},
+            "confidence": {"type": "STRING", "enum": ["low", "medium", "high"]},
+            "platforms": {
+                "type": "ARRAY",
+                "items": {"type": "STRING", "enum": ["linux", "macos", "windows"]}
+            }
+        },
+        "required": ["name", "description", "script", "permissions"],
+        "propertyOrdering": ["name", "description", "script", "permissions", "sensitive_output", "output_format", "examples", "assumptions", "confidence", "platforms"]
+    })
+}
+
+/// Extracts the generated text from a `generateContent` response.
+///
+/// # Errors
+///
+/// Returns an error if the API reported one, if the prompt or the answer was
+/// blocked by a safety filter (naming the reason and flagged categories), or
+/// if the response has no text.
+pub fn extract_text(response_text: &str) -> Result<String> {
+    let response: Value = serde_json::from_str(response_text)
+        .map_err(|_| anyhow!("Failed to parse Gemini response as JSON: {}", response_text))?;
+
+    if let Some(message) = response.pointer("/error/message").and_then(Value::as_str) {
+        return Err(anyhow!("Gemini API error: {}", message));
+    }
+
+    if let Some(reason) = response.pointer("/promptFeedback/blockReason").and_then(Value::as_str) {
+        return Err(blocked_error(
+            "the request",
+            reason,
+            response.pointer("/promptFeedback/safetyRatings"),
+        ));
+    }
+
+    let candidate = response
+        .pointer("/candidates/0")
+        .ok_or_else(|| anyhow!("Gemini returned no candidates: {}", response_text))?;
+    let text: String = candidate
+        .pointer("/content/parts")
+        .and_then(Value::as_array)
+        .map(|parts| parts.iter().filter_map(|p| p.get("text").and_then(Value::as_str)).collect())
+        .unwrap_or_default();
+
+    let finish_reason = candidate.get("finishReason").and_then(Value::as_str).unwrap_or("");
+    if text.is_empty() {
+        return Err(match finish_reason {
+            "" | "STOP" => anyhow!("Failed to extract content from Gemini response"),
+            reason => blocked_error("the answer", reason, candidate.get("safetyRatings")),
+        });
+    }
+    if finish_reason == "MAX_TOKENS" {
+        return Err(anyhow!("Gemini's answer was cut off at the output token limit"));
+    }
+
+    info!("Extracted content from Gemini: {}", text);
+    Ok(text)
+}
+
+/// Describes a response Gemini refused to give.
+fn blocked_error(what: &str, reason: &str, ratings: Option<&Value>) -> anyhow::Error {
+    let categories: Vec<&str> = ratings
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|rating| {
+            rating.get("blocked").and_then(Value::as_bool) == Some(true)
+                || matches!(rating.get("probability").and_then(Value::as_str), Some("HIGH" | "MEDIUM"))
+        })
+        .filter_map(|rating| rating.get("category").and_then(Value::as_str))
+        .map(|category| category.trim_start_matches("HARM_CATEGORY_"))
+        .collect();
+
+    let mut message = format!("Gemini blocked {} (reason: {})", what, reason);
+    if !categories.is_empty() {
+        message.push_str(&format!(", flagged: {}", categories.join(", ")));
+    }
+    message.push_str(". Try rephrasing the intent.");
+    anyhow!(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::Path;
+    use std::process::{ExitStatus, Output};
+
+    /// Answers `gcloud auth print-access-token` with a fixed token.
+    struct GcloudRunner {
+        token: &'static str,
+    }
+
+    impl ProcessRunner for GcloudRunner {
+        fn run(&self, program: &str, args: &[&str]) -> Result<Output> {
+            assert_eq!((program, args), ("gcloud", &["auth", "print-access-token"][..]));
+            Ok(Output {
+                status: ExitStatus::from_raw(if self.token.is_empty() { 1 << 8 } else { 0 }),
+                stdout: format!("{}\n", self.token).into_bytes(),
+                stderr: b"not logged in".to_vec(),
+            })
+        }
+
+        fn run_streaming(
+            &self,
+            _dir: Option<&Path>,
+            _program: &str,
+            _args: &[&str],
+            _stdout: &mut (dyn std::io::Write + Send),
+            _stderr: &mut (dyn std::io::Write + Send),
+        ) -> Result<ExitStatus> {
+            unimplemented!()
+        }
+
+        fn run_interactive(&self, _dir: Option<&Path>, _program: &str, _args: &[&str]) -> Result<ExitStatus> {
+            unimplemented!()
+        }
+
+        fn run_with_input(&self, _program: &str, _args: &[&str], _input: &[u8]) -> Result<ExitStatus> {
+            unimplemented!()
+        }
+
+        fn program_exists(&self, _program: &str) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_resolve_ai_studio_uses_env_key() {
+        let env = |name: &str| (name == "GEMINI_API_KEY").then(|| "AIzaTest".to_string());
+
+        let target = GeminiTarget::resolve(&GeminiConfig::default(), env, &GcloudRunner { token: "" }).unwrap();
+
+        assert_eq!(
+            target.url,
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-flash:generateContent"
+        );
+        assert_eq!(target.headers()[0], ("x-goog-api-key", "AIzaTest"));
+        assert!(!format!("{:?}", target).contains("AIzaTest"));
+    }
+
+    #[test]
+    fn test_resolve_vertex_uses_gcloud_token() {
+        let config = GeminiConfig {
+            api: GeminiApi::Vertex,
+            project: Some("proj".to_string()),
+            ..Default::default()
+        };
+
+        let target = GeminiTarget::resolve(&config, |_| None, &GcloudRunner { token: "ya29.token" }).unwrap();
+
+        assert_eq!(
+            target.url,
+            "https://us-central1-aiplatform.googleapis.com/v1/projects/proj/locations/us-central1/publishers/google/models/gemini-1.5-flash:generateContent"
+        );
+        assert_eq!(target.headers()[0], ("authorization", "Bearer ya29.token"));
+
+        let err = GeminiTarget::resolve(&config, |_| None, &GcloudRunner { token: "" }).unwrap_err();
+        assert!(err.to_string().contains("gcloud auth login"));
+    }
+
+    #[test]
+    fn test_request_body_sets_schema() {
+        let messages = [
+            Message {
+                role: Role::User,
+                content: "hi".to_string(),
+            },
+            Message {
+                role: Role::Assistant,
+                content: "hello".to_string(),
+            },
+        ];
+        let body = request_body("be brief", &messages, 1500, Some(command_schema()));
+
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "be brief");
+        assert_eq!(body["contents"][1]["role"], "model");
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "hi");
+        assert_eq!(body["generationConfig"]["responseMimeType"], "application/json");
+        assert_eq!(body["generationConfig"]["responseSchema"]["required"][2], "script");
+        assert!(request_body("be brief", &messages, 1500, None)["generationConfig"].get("responseSchema").is_none());
+    }
+
+    #[test]
+    fn test_extract_text_joins_parts() {
+        let response = r#"{"candidates": [{"content": {"parts": [{"text": "{\"a\":"}, {"text": "1}"}]}, "finishReason": "STOP"}]}"#;
+
+        assert_eq!(extract_text(response).unwrap(), r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn test_extract_text_reports_blocked_prompt() {
+        let response = r#"{"promptFeedback": {"blockReason": "SAFETY", "safetyRatings": [
+            {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "HIGH"},
+            {"category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE"}]}}"#;
+
+        let err = extract_text(response).unwrap_err().to_string();
+
+        assert!(err.contains("blocked the request (reason: SAFETY), flagged: DANGEROUS_CONTENT."));
+        assert!(!err.contains("HARASSMENT"));
+    }
+
+    #[test]
+    fn test_extract_text_reports_blocked_answer() {
+        let response = r#"{"candidates": [{"finishReason": "SAFETY", "safetyRatings": [
+            {"category": "HARM_CATEGORY_HATE_SPEECH", "probability": "LOW", "blocked": true}]}]}"#;
+
+        let err = extract_text(response).unwrap_err().to_string();
+
+        assert!(err.contains("blocked the answer (reason: SAFETY), flagged: HATE_SPEECH"));
+    }
+
+    #[test]
+    fn test_extract_text_reports_api_errors() {
+        let response = r#"{"error": {"code": 400, "message": "API key not valid"}}"#;
+
+        assert!(extract_text(response).unwrap_err().to_string().contains("API key not valid"));
+    }
+}
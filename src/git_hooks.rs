@@ -0,0 +1,196 @@
+//! Git hooks running cached commands, for `ergo hook install`.
+//!
+//! `ergo hook install pre-commit lint-staged check-todos` writes a hook
+//! script that runs each command through ergo, in order, and stops the
+//! commit as soon as one exits with a non-zero status. Commands run with the
+//! permissions the user already granted forever; the hook never prompts.
+//!
+//! With `--framework`, the commands become local hooks of the
+//! [pre-commit](https://pre-commit.com) framework instead, in
+//! `.pre-commit-config.yaml`.
+
+use crate::executor::{ProcessRunner, shell_quote};
+use anyhow::{Result, anyhow};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Marks hook scripts written by ergo, which it may overwrite.
+pub const MANAGED_MARKER: &str = "# Managed by ergo";
+
+/// The pre-commit framework's configuration file, at the repository root.
+pub const FRAMEWORK_CONFIG_FILE: &str = ".pre-commit-config.yaml";
+
+/// What installing a hook did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookInstall {
+    /// The hook script was written (or rewritten) at this path.
+    Written(PathBuf),
+    /// A hook ergo didn't write exists at this path; the lines to add to it
+    /// by hand are in `lines`.
+    Foreign {
+        /// The existing hook.
+        path: PathBuf,
+        /// The commands, as shell lines.
+        lines: String,
+    },
+}
+
+/// Asks git where the `hook` script of the current repository goes, which
+/// honors `core.hooksPath` and worktrees.
+///
+/// # Errors
+///
+/// Returns an error outside a git repository.
+pub fn hook_path<P: ProcessRunner + ?Sized>(runner: &P, hook: &str) -> Result<PathBuf> {
+    git_path(runner, &["rev-parse", "--git-path", &format!("hooks/{}", hook)])
+}
+
+/// Asks git for the root of the current repository.
+///
+/// # Errors
+///
+/// Returns an error outside a git repository.
+pub fn repository_root<P: ProcessRunner + ?Sized>(runner: &P) -> Result<PathBuf> {
+    git_path(runner, &["rev-parse", "--show-toplevel"])
+}
+
+fn git_path<P: ProcessRunner + ?Sized>(runner: &P, args: &[&str]) -> Result<PathBuf> {
+    let output = runner.run("git", args)?;
+    if !output.status.success() {
+        return Err(anyhow!("Not inside a git repository"));
+    }
+    Ok(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// The shell lines running `commands` through ergo, without a terminal so
+/// nothing can prompt.
+pub fn command_lines(commands: &[String]) -> String {
+    commands
+        .iter()
+        .map(|command| format!("ergo {} </dev/null\n", shell_quote(command)))
+        .collect()
+}
+
+/// Renders a `hook` script running `commands` in order and failing on the
+/// first one that fails.
+pub fn render_hook(hook: &str, commands: &[String]) -> String {
+    let mut script = String::new();
+    let _ = writeln!(script, "#!/bin/sh");
+    let _ = writeln!(script, "{}: `ergo hook install {}` rewrites this file.", MANAGED_MARKER, hook);
+    let _ = writeln!(script, "# Runs cached ergo commands with their saved permissions; a failing");
+    let _ = writeln!(script, "# command stops the commit (skip them with `git commit --no-verify`).");
+    let _ = writeln!(script, "if ! command -v ergo >/dev/null 2>&1; then");
+    let _ = writeln!(script, "  echo \"{}: ergo is not installed\" >&2", hook);
+    let _ = writeln!(script, "  exit 1");
+    let _ = writeln!(script, "fi");
+    let _ = writeln!(script, "set -e");
+    script.push_str(&command_lines(commands));
+    script
+}
+
+/// Writes the `hook` script to `path`, unless a hook ergo didn't write is
+/// already there.
+pub fn install_hook(path: &Path, hook: &str, commands: &[String]) -> Result<HookInstall> {
+    if let Ok(existing) = fs::read_to_string(path)
+        && !existing.contains(MANAGED_MARKER)
+    {
+        return Ok(HookInstall::Foreign {
+            path: path.to_path_buf(),
+            lines: command_lines(commands),
+        });
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, render_hook(hook, commands))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    info!("Installed {} hook at '{}' running {:?}", hook, path.display(), commands);
+    Ok(HookInstall::Written(path.to_path_buf()))
+}
+
+/// Renders the local pre-commit framework hooks running `commands`, as
+/// entries of a `repos:` list.
+pub fn render_framework_repo(commands: &[String]) -> String {
+    let mut yaml = String::new();
+    let _ = writeln!(yaml, "  - repo: local");
+    let _ = writeln!(yaml, "    hooks:");
+    for command in commands {
+        let _ = writeln!(yaml, "      - id: ergo-{}", command);
+        let _ = writeln!(yaml, "        name: \"ergo: {}\"", command);
+        let _ = writeln!(yaml, "        entry: ergo {}", shell_quote(command));
+        let _ = writeln!(yaml, "        language: system");
+        let _ = writeln!(yaml, "        pass_filenames: false");
+        let _ = writeln!(yaml, "        always_run: true");
+    }
+    yaml
+}
+
+/// Renders a complete pre-commit framework configuration running `commands`.
+pub fn render_framework_config(commands: &[String]) -> String {
+    format!("repos:\n{}", render_framework_repo(commands))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn commands() -> Vec<String> {
+        vec!["lint-staged".to_string(), "check-todos".to_string()]
+    }
+
+    #[test]
+    fn test_install_hook_writes_and_rewrites_managed_hooks() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("hooks/pre-commit");
+
+        let first = install_hook(&path, "pre-commit", &commands()).unwrap();
+        let second = install_hook(&path, "pre-commit", &commands()[..1]).unwrap();
+
+        assert_eq!(first, HookInstall::Written(path.clone()));
+        assert_eq!(second, HookInstall::Written(path.clone()));
+        let script = fs::read_to_string(&path).unwrap();
+        assert!(script.starts_with("#!/bin/sh\n# Managed by ergo"));
+        assert!(script.ends_with("set -e\nergo lint-staged </dev/null\n"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn test_install_hook_leaves_foreign_hooks_alone() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("pre-commit");
+        fs::write(&path, "#!/bin/sh\nnpm test\n").unwrap();
+
+        let outcome = install_hook(&path, "pre-commit", &commands()).unwrap();
+
+        assert_eq!(
+            outcome,
+            HookInstall::Foreign {
+                path: path.clone(),
+                lines: "ergo lint-staged </dev/null\nergo check-todos </dev/null\n".to_string(),
+            }
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "#!/bin/sh\nnpm test\n");
+    }
+
+    #[test]
+    fn test_render_framework_config() {
+        let config = render_framework_config(&commands()[..1]);
+
+        assert_eq!(
+            config,
+            "repos:\n  - repo: local\n    hooks:\n      - id: ergo-lint-staged\n        name: \"ergo: lint-staged\"\n        entry: ergo lint-staged\n        language: system\n        pass_filenames: false\n        always_run: true\n"
+        );
+    }
+}
@@ -2,10 +2,276 @@
 //!
 //! This module provides a trait-based abstraction over HTTP clients, enabling
 //! dependency injection and easy mocking in tests.
+//!
+//! Every [`ReqwestHttpClient`] in a process shares one connection pool, so
+//! the model calls of a batch, chain or workflow reuse the same TLS (and,
+//! where the server supports it, HTTP/2) connection instead of handshaking
+//! for each one. Timeouts and pool limits come from the `[http]` table of
+//! the config (see [`HttpConfig`]). It needs the `claude` feature; without
+//! it, embedders bring their own [`HttpClient`].
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use async_trait::async_trait;
+#[cfg(feature = "claude")]
 use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "claude")]
+use std::sync::OnceLock;
+use std::time::Duration;
+#[cfg(feature = "claude")]
+use std::time::Instant;
+#[cfg(feature = "claude")]
+use tracing::{debug, warn};
+
+/// HTTP connection settings (the `[http]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Seconds to wait for a connection to be established.
+    pub connect_timeout_secs: u64,
+    /// Seconds a whole request may take; 0 for no limit.
+    pub timeout_secs: u64,
+    /// Seconds an idle connection is kept open for reuse.
+    pub pool_idle_timeout_secs: u64,
+    /// Most idle connections kept open per host.
+    pub pool_max_idle_per_host: usize,
+    /// Seconds between keep-alive pings on idle HTTP/2 and TCP connections;
+    /// 0 to disable them.
+    pub keep_alive_secs: u64,
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_timeout_secs() -> u64 {
+    300
+}
+
+fn default_pool_idle_timeout_secs() -> u64 {
+    90
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    8
+}
+
+fn default_keep_alive_secs() -> u64 {
+    30
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: default_connect_timeout_secs(),
+            timeout_secs: default_timeout_secs(),
+            pool_idle_timeout_secs: default_pool_idle_timeout_secs(),
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            keep_alive_secs: default_keep_alive_secs(),
+        }
+    }
+}
+
+#[cfg(feature = "claude")]
+impl HttpConfig {
+    /// Builds a reqwest client with these settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the TLS backend can't be initialized.
+    pub fn build_client(&self) -> Result<Client> {
+        let mut builder = Client::builder()
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs))
+            .pool_idle_timeout(Duration::from_secs(self.pool_idle_timeout_secs))
+            .pool_max_idle_per_host(self.pool_max_idle_per_host);
+        if self.timeout_secs > 0 {
+            builder = builder.timeout(Duration::from_secs(self.timeout_secs));
+        }
+        if self.keep_alive_secs > 0 {
+            let interval = Duration::from_secs(self.keep_alive_secs);
+            builder = builder
+                .tcp_keepalive(interval)
+                .http2_keep_alive_interval(interval)
+                .http2_keep_alive_while_idle(true);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// HTTP request method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// `GET`
+    Get,
+    /// `HEAD`
+    Head,
+    /// `POST`
+    Post,
+    /// `PUT`
+    Put,
+    /// `DELETE`
+    Delete,
+}
+
+impl Method {
+    /// The method's name, e.g. `GET`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// An HTTP request, built with [`HttpRequest::get`], [`HttpRequest::post_json`]
+/// and friends.
+///
+/// # Example
+///
+/// ```ignore
+/// let request = HttpRequest::get("https://api.github.com/repos/o/r/releases/latest")
+///     .header("accept", "application/vnd.github+json")
+///     .timeout(Duration::from_secs(5));
+/// let release: Release = client.send(&request).await?.error_for_status()?.json()?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    /// The method.
+    pub method: Method,
+    /// The URL.
+    pub url: String,
+    /// Headers, in order.
+    pub headers: Vec<(String, String)>,
+    /// A JSON body, if any.
+    pub body: Option<serde_json::Value>,
+    /// How long the request may take, overriding `http.timeout_secs`.
+    pub timeout: Option<Duration>,
+}
+
+impl HttpRequest {
+    /// A request with no headers, body or timeout.
+    pub fn new(method: Method, url: &str) -> Self {
+        Self {
+            method,
+            url: url.to_string(),
+            headers: Vec::new(),
+            body: None,
+            timeout: None,
+        }
+    }
+
+    /// A `GET` request.
+    pub fn get(url: &str) -> Self {
+        Self::new(Method::Get, url)
+    }
+
+    /// A `HEAD` request.
+    pub fn head(url: &str) -> Self {
+        Self::new(Method::Head, url)
+    }
+
+    /// A `POST` request with a JSON body.
+    pub fn post_json(url: &str, body: serde_json::Value) -> Self {
+        Self {
+            body: Some(body),
+            ..Self::new(Method::Post, url)
+        }
+    }
+
+    /// Adds a header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds headers.
+    pub fn headers(mut self, headers: &[(&str, &str)]) -> Self {
+        self.headers.extend(headers.iter().map(|(name, value)| (name.to_string(), value.to_string())));
+        self
+    }
+
+    /// Limits how long the request may take.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The first value of the header `name`, ignoring case.
+    pub fn header_value(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An HTTP response, whatever its status.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HttpResponse {
+    /// The status code, e.g. `200`.
+    pub status: u16,
+    /// Headers, with lowercase names.
+    pub headers: Vec<(String, String)>,
+    /// The body (empty for `HEAD` requests).
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// A response with `status` and `body` and no headers.
+    pub fn new(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    /// Adds a header.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_ascii_lowercase(), value.to_string()));
+        self
+    }
+
+    /// Whether the status is 2xx.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The first value of the header `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the response if its status is 2xx, or an error with the status
+    /// and the start of the body.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for any other status.
+    pub fn error_for_status(self) -> Result<Self> {
+        if self.is_success() {
+            return Ok(self);
+        }
+        let excerpt: String = self.body.chars().take(200).collect();
+        Err(anyhow!("HTTP {}: {}", self.status, excerpt.trim()))
+    }
+
+    /// Parses the body as JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the body isn't JSON of type `T`.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+}
 
 /// Trait for HTTP communication with external APIs.
 ///
@@ -47,30 +313,101 @@ pub trait HttpClient: Send + Sync {
         headers: &[(&str, &str)],
         body: &serde_json::Value,
     ) -> Result<String>;
+
+    /// Sends any request and returns the response, whatever its status (see
+    /// [`HttpResponse::error_for_status`]).
+    ///
+    /// Clients that only support [`HttpClient::post_json`] fail.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request can't be sent or times out.
+    async fn send(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        Err(anyhow!("This HTTP client can't send {} requests", request.method.as_str()))
+    }
+
+    /// Sends a GET request and returns the response, whatever its status.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request can't be sent.
+    async fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<HttpResponse> {
+        self.send(&HttpRequest::get(url).headers(headers)).await
+    }
+
+    /// Downloads `url` and returns its body as bytes, e.g. an archive.
+    ///
+    /// Clients that only read text bodies return the text's bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the status isn't 2xx.
+    async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        Ok(self.get(url, &[]).await?.error_for_status()?.body.into_bytes())
+    }
+
+    /// Sends a HEAD request and returns the server's `Date` header, if any.
+    ///
+    /// Used to detect clock skew. Clients that can't tell return `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails.
+    async fn server_date(&self, _url: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Starts connecting to `url`'s server in the background, so a request
+    /// sent shortly after finds the connection ready.
+    ///
+    /// Clients without a connection pool do nothing.
+    fn warm_up(&self, _url: &str) {}
 }
 
 /// HTTP client implementation using reqwest.
 ///
 /// This is the default production implementation that makes real HTTP requests.
+#[cfg(feature = "claude")]
 pub struct ReqwestHttpClient {
     client: Client,
 }
 
+#[cfg(feature = "claude")]
 impl ReqwestHttpClient {
-    /// Creates a new HTTP client with default configuration.
+    /// Creates an HTTP client sharing the process-wide connection pool,
+    /// configured from the `[http]` config table.
     pub fn new() -> Self {
-        Self {
-            client: Client::new(),
-        }
+        static SHARED: OnceLock<Client> = OnceLock::new();
+        let client = SHARED.get_or_init(|| {
+            let config = crate::config::Config::load().map(|config| config.http).unwrap_or_default();
+            config.build_client().unwrap_or_else(|e| {
+                warn!("Invalid HTTP settings, using the defaults: {}", e);
+                Client::new()
+            })
+        });
+        Self { client: client.clone() }
+    }
+
+    /// Creates an HTTP client with its own connection pool and `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the TLS backend can't be initialized.
+    pub fn with_config(config: &HttpConfig) -> Result<Self> {
+        Ok(Self {
+            client: config.build_client()?,
+        })
     }
 }
 
+#[cfg(feature = "claude")]
 impl Default for ReqwestHttpClient {
     fn default() -> Self {
         Self::new()
     }
 }
 
+#[cfg(feature = "claude")]
 #[async_trait]
 impl HttpClient for ReqwestHttpClient {
     async fn post_json(
@@ -85,48 +422,135 @@ impl HttpClient for ReqwestHttpClient {
             request = request.header(*key, *value);
         }
 
+        let started = Instant::now();
         let response = request.json(body).send().await?;
-        Ok(response.text().await?)
+        let headers_ms = started.elapsed().as_millis() as u64;
+        let status = response.status().as_u16();
+        let text = response.text().await?;
+        debug!(
+            host = url_host(url),
+            status,
+            headers_ms,
+            duration_ms = started.elapsed().as_millis() as u64,
+            response_bytes = text.len(),
+            "HTTP POST"
+        );
+        Ok(text)
+    }
+
+    async fn send(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let method = match request.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Head => reqwest::Method::HEAD,
+            Method::Post => reqwest::Method::POST,
+            Method::Put => reqwest::Method::PUT,
+            Method::Delete => reqwest::Method::DELETE,
+        };
+        let mut builder = self.client.request(method, &request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.json(body);
+        }
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        let started = Instant::now();
+        let response = builder.send().await?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.text().await?;
+        debug!(
+            method = request.method.as_str(),
+            host = url_host(&request.url),
+            status,
+            duration_ms = started.elapsed().as_millis() as u64,
+            response_bytes = body.len(),
+            "HTTP request"
+        );
+        Ok(HttpResponse { status, headers, body })
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        let started = Instant::now();
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let excerpt: String = response.text().await.unwrap_or_default().chars().take(200).collect();
+            return Err(anyhow!("HTTP {}: {}", status.as_u16(), excerpt.trim()));
+        }
+        let body = response.bytes().await?.to_vec();
+        debug!(
+            host = url_host(url),
+            status = status.as_u16(),
+            duration_ms = started.elapsed().as_millis() as u64,
+            response_bytes = body.len(),
+            "HTTP download"
+        );
+        Ok(body)
+    }
+
+    async fn server_date(&self, url: &str) -> Result<Option<String>> {
+        let response = self.send(&HttpRequest::head(url)).await?;
+        Ok(response.header("date").map(str::to_string))
+    }
+
+    fn warm_up(&self, url: &str) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let (client, url) = (self.client.clone(), url.to_string());
+        runtime.spawn(async move {
+            let started = Instant::now();
+            match client.head(&url).send().await {
+                Ok(_) => debug!(host = url_host(&url), duration_ms = started.elapsed().as_millis() as u64, "Warmed up connection"),
+                Err(e) => debug!("Failed to warm up connection to {}: {}", url_host(&url), e),
+            }
+        });
     }
 }
 
+/// The host of `url`, logged instead of the full URL, which may carry keys.
+#[cfg(feature = "claude")]
+fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?']).next().unwrap_or(rest)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::Mutex;
+    use crate::testing::MockHttpClient;
 
-    /// Mock HTTP client for testing.
-    ///
-    /// Returns a predetermined response without making network requests.
-    pub struct MockHttpClient {
-        response: Mutex<String>,
-    }
+    #[tokio::test]
+    async fn test_get_goes_through_send() {
+        let client = MockHttpClient::new().with_status(503, "busy");
 
-    impl MockHttpClient {
-        /// Creates a mock client that always returns the given response.
-        pub fn new(response: &str) -> Self {
-            Self {
-                response: Mutex::new(response.to_string()),
-            }
-        }
-    }
+        let busy = client.get("https://api.example.com/v1/models", &[("accept", "application/json")]).await.unwrap();
 
-    #[async_trait]
-    impl HttpClient for MockHttpClient {
-        async fn post_json(
-            &self,
-            _url: &str,
-            _headers: &[(&str, &str)],
-            _body: &serde_json::Value,
-        ) -> Result<String> {
-            Ok(self.response.lock().unwrap().clone())
-        }
+        assert_eq!(busy.status, 503);
+        assert_eq!(busy.error_for_status().unwrap_err().to_string(), "HTTP 503: busy");
+        let sent = client.sent();
+        assert_eq!(sent[0].method, Method::Get);
+        assert_eq!(sent[0].header_value("ACCEPT"), Some("application/json"));
     }
 
     #[test]
-    fn test_mock_http_client_returns_response() {
-        let client = MockHttpClient::new("test response");
-        let response = client.response.lock().unwrap().clone();
-        assert_eq!(response, "test response");
+    fn test_http_config_fills_in_defaults() {
+        let config: HttpConfig = toml::from_str("timeout_secs = 0\nkeep_alive_secs = 0").unwrap();
+
+        assert_eq!(config.timeout_secs, 0);
+        assert_eq!(config.connect_timeout_secs, 10);
+        assert_eq!(config.pool_max_idle_per_host, 8);
+        #[cfg(feature = "claude")]
+        assert!(ReqwestHttpClient::with_config(&config).is_ok());
+        #[cfg(feature = "claude")]
+        assert_eq!(url_host("https://generativelanguage.googleapis.com/v1beta/models/x?key=secret"), "generativelanguage.googleapis.com");
     }
 }
\ No newline at end of file
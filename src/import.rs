@@ -0,0 +1,343 @@
+//! Commands imported from a URL, for `ergo --import-url`.
+//!
+//! A team can publish its commands as a tarball of a bioma (`bioma.tar.gz`,
+//! holding a `commands.json` and the scripts it names) or a single command as
+//! a JSON file:
+//!
+//! ```json
+//! {"command": {"name": "greet", "description": "...", "permissions": []}, "script": "console.log('hi')"}
+//! ```
+//!
+//! The download is checked against its SHA-256 checksum, given with
+//! `--sha256` or published next to it as `<url>.sha256` (the output of
+//! `sha256sum`). Imported commands keep neither the publisher's permission
+//! decisions nor their usage: every run shows the script and asks for
+//! consent until its permissions are allowed forever.
+
+use crate::http_client::HttpClient;
+use crate::llm_generator::GeneratedCommand;
+use anyhow::{Context, Result, anyhow, bail};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tracing::warn;
+
+/// Largest download accepted, and largest file read from an archive.
+pub const MAX_IMPORT_BYTES: usize = 16 * 1024 * 1024;
+
+/// How a download was checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// It matched the checksum given with `--sha256`.
+    Given,
+    /// It matched the checksum published at `<url>.sha256`.
+    Published,
+    /// No checksum was given or published; holds the download's SHA-256.
+    Unverified(String),
+}
+
+/// A command read from a download, with its scripts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedCommand {
+    /// The command; its variants are those whose script was found.
+    pub command: GeneratedCommand,
+    /// The main script.
+    pub script: String,
+    /// The script of each platform variant, by OS.
+    pub variant_scripts: BTreeMap<String, String>,
+}
+
+/// The commands of a download.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bundle {
+    /// The commands that can be imported.
+    pub commands: Vec<ImportedCommand>,
+    /// Why each entry that can't be imported was left out.
+    pub skipped: Vec<String>,
+}
+
+/// A single exported command.
+#[derive(Deserialize)]
+struct CommandFile {
+    command: GeneratedCommand,
+    script: String,
+}
+
+/// Downloads `url` with `client` and checks it against `sha256`, or else
+/// against the checksum published at `<url>.sha256`, if there's one.
+///
+/// # Errors
+///
+/// Returns an error if the download fails or is too large, or if it doesn't
+/// match the checksum.
+pub async fn download(client: &dyn HttpClient, url: &str, sha256: Option<&str>) -> Result<(Vec<u8>, Verification)> {
+    let bytes = client.download(url).await.with_context(|| format!("Couldn't download {}", url))?;
+    if bytes.len() > MAX_IMPORT_BYTES {
+        bail!("{} is {} bytes; imports are limited to {} bytes", url, bytes.len(), MAX_IMPORT_BYTES);
+    }
+    if let Some(expected) = sha256 {
+        verify_checksum(&bytes, expected)?;
+        return Ok((bytes, Verification::Given));
+    }
+    match client.download(&format!("{}.sha256", url)).await {
+        Ok(published) => {
+            verify_checksum(&bytes, &String::from_utf8_lossy(&published))
+                .with_context(|| format!("{} doesn't match the checksum published at {}.sha256", url, url))?;
+            Ok((bytes, Verification::Published))
+        }
+        Err(e) => {
+            warn!("No checksum published for {}: {}", url, e);
+            let digest = sha256_hex(&bytes);
+            Ok((bytes, Verification::Unverified(digest)))
+        }
+    }
+}
+
+/// The SHA-256 of `bytes`, in lowercase hex.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Checks `bytes` against `checksum`: a SHA-256 in hex, optionally followed
+/// by a file name as `sha256sum` prints it.
+///
+/// # Errors
+///
+/// Returns an error if `checksum` isn't a SHA-256 or doesn't match.
+pub fn verify_checksum(bytes: &[u8], checksum: &str) -> Result<()> {
+    let expected = checksum.split_whitespace().next().unwrap_or_default().to_lowercase();
+    if expected.len() != 64 || !expected.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("'{}' isn't a SHA-256 checksum", checksum.trim());
+    }
+    let actual = sha256_hex(bytes);
+    if actual != expected {
+        bail!("Checksum mismatch: expected {}, downloaded {}", expected, actual);
+    }
+    Ok(())
+}
+
+/// Reads the commands of a download: a gzipped tarball of a bioma, or a
+/// single command file.
+///
+/// # Errors
+///
+/// Returns an error if the download is neither.
+pub fn parse_bundle(bytes: &[u8]) -> Result<Bundle> {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        parse_archive(bytes)
+    } else {
+        let file: CommandFile = serde_json::from_slice(bytes)
+            .context("Not a bioma tarball (.tar.gz) nor an exported command file (JSON with `command` and `script`)")?;
+        let mut command = file.command;
+        check_name(&command.name)?;
+        command.script_file = format!("{}.ts", command.name);
+        command.variants.clear();
+        Ok(Bundle {
+            commands: vec![ImportedCommand {
+                command,
+                script: file.script,
+                variant_scripts: BTreeMap::new(),
+            }],
+            skipped: Vec::new(),
+        })
+    }
+}
+
+/// Reads the commands of a gzipped tarball holding a `commands.json`.
+fn parse_archive(bytes: &[u8]) -> Result<Bundle> {
+    let mut files = HashMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(bytes));
+    for entry in archive.entries().context("Invalid tarball")? {
+        let entry = entry.context("Invalid tarball")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let mut content = String::new();
+        entry
+            .take(MAX_IMPORT_BYTES as u64)
+            .read_to_string(&mut content)
+            .with_context(|| format!("{} in the tarball isn't text", path.display()))?;
+        files.insert(normalize(&path), content);
+    }
+
+    let index = files
+        .keys()
+        .filter(|path| path.file_name().is_some_and(|name| name == "commands.json"))
+        .min_by_key(|path| path.components().count())
+        .cloned()
+        .ok_or_else(|| anyhow!("The tarball has no commands.json"))?;
+    let dir = index.parent().unwrap_or(Path::new("")).to_path_buf();
+    let entries: BTreeMap<String, serde_json::Value> =
+        serde_json::from_str(&files[&index]).context("The tarball's commands.json is invalid")?;
+
+    let mut bundle = Bundle::default();
+    for (name, entry) in entries {
+        match read_entry(&name, entry, &dir, &files) {
+            Ok(command) => bundle.commands.push(command),
+            Err(e) => {
+                warn!("Not importing '{}': {}", name, e);
+                bundle.skipped.push(format!("{}: {}", name, e));
+            }
+        }
+    }
+    Ok(bundle)
+}
+
+/// Reads the command `name` of a `commands.json` with its scripts.
+fn read_entry(name: &str, entry: serde_json::Value, dir: &Path, files: &HashMap<PathBuf, String>) -> Result<ImportedCommand> {
+    check_name(name)?;
+    let command = entry.get("command").cloned().ok_or_else(|| anyhow!("no command"))?;
+    let mut command: GeneratedCommand = serde_json::from_value(command).context("unreadable command")?;
+    let script = script_at(dir, &command.script_file, files)?;
+
+    let mut variant_scripts = BTreeMap::new();
+    command.variants.retain(|os, variant| match script_at(dir, &variant.script_file, files) {
+        Ok(script) => {
+            variant_scripts.insert(os.clone(), script);
+            true
+        }
+        Err(e) => {
+            warn!("Not importing the {} variant of '{}': {}", os, name, e);
+            false
+        }
+    });
+    command.name = name.to_string();
+    command.script_file = format!("{}.ts", name);
+    Ok(ImportedCommand {
+        command,
+        script,
+        variant_scripts,
+    })
+}
+
+/// The content of the script `file`, next to `commands.json` in `dir`.
+fn script_at(dir: &Path, file: &str, files: &HashMap<PathBuf, String>) -> Result<String> {
+    let mut components = Path::new(file).components();
+    if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+        bail!("script '{}' isn't next to commands.json", file);
+    }
+    files.get(&dir.join(file)).cloned().ok_or_else(|| anyhow!("script '{}' is missing", file))
+}
+
+/// `path` without `.` components, as tarballs made with `tar -C dir .` have.
+fn normalize(path: &Path) -> PathBuf {
+    path.components().filter(|c| !matches!(c, Component::CurDir)).collect()
+}
+
+/// Checks that `name` can be a command name and a file name.
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        bail!("'{}' isn't a valid command name", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockHttpClient, command};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use serde_json::json;
+
+    fn tarball(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_parse_archive_reads_commands_and_their_scripts() {
+        let mut greet = command("greet", &["--allow-env=USER"]);
+        greet.variants.insert(
+            "macos".to_string(),
+            crate::llm_generator::PlatformVariant {
+                script_file: "greet.macos.ts".to_string(),
+                permissions: Vec::new(),
+            },
+        );
+        greet.variants.insert(
+            "windows".to_string(),
+            crate::llm_generator::PlatformVariant {
+                script_file: "greet.windows.ts".to_string(),
+                permissions: Vec::new(),
+            },
+        );
+        let mut escape = command("escape", &[]);
+        escape.script_file = "../escape.ts".to_string();
+        let index = json!({
+            "greet": {"command": greet, "permission_decision": {"consent": "AcceptForever"}},
+            "escape": {"command": escape},
+            "lost": {"command": command("lost", &[])},
+        });
+        let bytes = tarball(&[
+            ("./bioma/commands.json", &index.to_string()),
+            ("./bioma/greet.ts", "console.log('hi')"),
+            ("./bioma/greet.macos.ts", "console.log('hi from macos')"),
+        ]);
+
+        let bundle = parse_bundle(&bytes).unwrap();
+
+        assert_eq!(bundle.commands.len(), 1);
+        let imported = &bundle.commands[0];
+        assert_eq!(imported.command.name, "greet");
+        assert_eq!(imported.command.permissions[0].permission, "--allow-env=USER");
+        assert_eq!(imported.script, "console.log('hi')");
+        assert_eq!(imported.command.variants.keys().collect::<Vec<_>>(), vec!["macos"]);
+        assert_eq!(imported.variant_scripts["macos"], "console.log('hi from macos')");
+        assert_eq!(
+            bundle.skipped,
+            vec![
+                "escape: script '../escape.ts' isn't next to commands.json",
+                "lost: script 'lost.ts' is missing"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_command_file() {
+        let file = json!({"command": command("greet", &[]), "script": "console.log('hi')"});
+        let bundle = parse_bundle(file.to_string().as_bytes()).unwrap();
+        assert_eq!(bundle.commands[0].command.script_file, "greet.ts");
+        assert_eq!(bundle.commands[0].script, "console.log('hi')");
+
+        let file = json!({"command": command("../greet", &[]), "script": ""});
+        assert!(parse_bundle(file.to_string().as_bytes()).unwrap_err().to_string().contains("isn't a valid command name"));
+        assert!(parse_bundle(b"<html>Not found</html>").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_checks_checksums() {
+        let body = r#"{"command": {}}"#;
+        let digest = sha256_hex(body.as_bytes());
+        let url = "https://example.com/greet.json";
+
+        let published = MockHttpClient::new()
+            .on("greet.json.sha256", &format!("{}  greet.json\n", digest))
+            .on(url, body);
+        assert_eq!(download(&published, url, None).await.unwrap().1, Verification::Published);
+
+        let unpublished = MockHttpClient::new()
+            .on_response("greet.json.sha256", crate::http_client::HttpResponse::new(404, ""))
+            .on(url, body);
+        assert_eq!(download(&unpublished, url, None).await.unwrap().1, Verification::Unverified(digest.clone()));
+
+        let tampered = MockHttpClient::new().on(url, "{}");
+        let err = download(&tampered, url, Some(&digest.to_uppercase())).await.unwrap_err();
+        assert!(err.to_string().starts_with("Checksum mismatch"));
+        assert!(verify_checksum(body.as_bytes(), "not-a-checksum").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! Intents queued while offline.
+//!
+//! `ergo --queue "<intent>"` appends the intent, with the directory it was
+//! queued from, to `~/.abiogenesis/queue.jsonl` without contacting the model.
+//! `ergo --flush-queue` later generates every queued command from the
+//! directory it was queued in (so project commands land in the project's
+//! bioma) and keeps only the intents that failed.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// An intent waiting to be generated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedIntent {
+    /// The intent, as it would be passed to `ergo`.
+    pub intent: Vec<String>,
+    /// The directory it was queued from.
+    pub cwd: PathBuf,
+    /// When it was queued (Unix timestamp).
+    pub queued_at: u64,
+}
+
+impl QueuedIntent {
+    /// The intent as a single line, e.g. `weather London`.
+    pub fn display(&self) -> String {
+        self.intent.join(" ")
+    }
+}
+
+/// Returns the path of the queue file.
+pub fn default_path() -> Result<PathBuf> {
+    Ok(crate::config::Config::get_config_dir()?.join("queue.jsonl"))
+}
+
+/// Appends `intent` to the queue at `path`.
+pub fn push(path: &Path, intent: &QueuedIntent) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(intent)?)?;
+    Ok(())
+}
+
+/// Reads the queue at `path`, oldest first, skipping malformed lines.
+///
+/// A missing queue is empty.
+pub fn read(path: &Path) -> Result<Vec<QueuedIntent>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+/// Replaces the queue at `path` with `intents`, removing the file when none
+/// are left.
+pub fn replace(path: &Path, intents: &[QueuedIntent]) -> Result<()> {
+    if intents.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    }
+    let mut content = String::new();
+    for intent in intents {
+        content.push_str(&serde_json::to_string(intent)?);
+        content.push('\n');
+    }
+    let temp_path = path.with_extension("jsonl.tmp");
+    fs::write(&temp_path, content)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn queued(intent: &str, queued_at: u64) -> QueuedIntent {
+        QueuedIntent {
+            intent: vec![intent.to_string()],
+            cwd: PathBuf::from("/work/app"),
+            queued_at,
+        }
+    }
+
+    #[test]
+    fn test_push_and_read_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("nested").join("queue.jsonl");
+
+        assert!(read(&path).unwrap().is_empty());
+        push(&path, &queued("show disk usage", 1)).unwrap();
+        push(&path, &queued("list open ports", 2)).unwrap();
+
+        assert_eq!(read(&path).unwrap(), vec![queued("show disk usage", 1), queued("list open ports", 2)]);
+    }
+
+    #[test]
+    fn test_replace_keeps_remaining_and_removes_empty_queue() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("queue.jsonl");
+        push(&path, &queued("show disk usage", 1)).unwrap();
+        push(&path, &queued("list open ports", 2)).unwrap();
+
+        replace(&path, &[queued("list open ports", 2)]).unwrap();
+        assert_eq!(read(&path).unwrap(), vec![queued("list open ports", 2)]);
+
+        replace(&path, &[]).unwrap();
+        assert!(!path.exists());
+        replace(&path, &[]).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+//! `--jq`-style filters for commands that output JSON.
+//!
+//! Supports the path subset of jq, which covers most day-to-day use:
+//!
+//! - `.` the whole document
+//! - `.name`, `.["odd key"]` an object field (`null` if missing)
+//! - `.[2]`, `.[-1]` an array element (`null` if out of range)
+//! - `.[]` every element of an array (or value of an object)
+//!
+//! Steps chain, as in `.items[].name` or `.data.users[0].email`.
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// One step of a filter path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Field(String),
+    Index(i64),
+    Iterate,
+}
+
+/// A parsed filter such as `.items[].name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonFilter {
+    expr: String,
+    steps: Vec<Step>,
+}
+
+impl JsonFilter {
+    /// Applies the filter to `value`, returning every result in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when indexing or iterating something that isn't an
+    /// object or array, e.g. `.name` on a number.
+    pub fn apply(&self, value: &Value) -> Result<Vec<Value>> {
+        let mut current = vec![value.clone()];
+        for step in &self.steps {
+            let mut next = Vec::new();
+            for value in current {
+                match (step, value) {
+                    (Step::Field(_) | Step::Index(_), Value::Null) => next.push(Value::Null),
+                    (Step::Field(name), Value::Object(mut map)) => {
+                        next.push(map.remove(name).unwrap_or(Value::Null));
+                    }
+                    (Step::Index(index), Value::Array(mut items)) => {
+                        let len = items.len() as i64;
+                        let index = if *index < 0 { len + index } else { *index };
+                        let item = (0..len).contains(&index).then(|| items.swap_remove(index as usize));
+                        next.push(item.unwrap_or(Value::Null));
+                    }
+                    (Step::Iterate, Value::Array(items)) => next.extend(items),
+                    (Step::Iterate, Value::Object(map)) => next.extend(map.into_iter().map(|(_, v)| v)),
+                    (step, value) => {
+                        return Err(anyhow!(
+                            "{}: can't apply {} to {}",
+                            self.expr,
+                            step,
+                            type_name(&value)
+                        ));
+                    }
+                }
+            }
+            current = next;
+        }
+        Ok(current)
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Field(name) => write!(f, ".{}", name),
+            Step::Index(index) => write!(f, "[{}]", index),
+            Step::Iterate => write!(f, "[]"),
+        }
+    }
+}
+
+/// The jq name of a value's type.
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+impl fmt::Display for JsonFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+impl FromStr for JsonFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let expr = s.trim();
+        let invalid = |why: &str| anyhow!("Invalid filter '{}': {}", expr, why);
+        let mut rest = expr.strip_prefix('.').ok_or_else(|| invalid("it must start with '.'"))?;
+        let mut steps = Vec::new();
+
+        // The first step follows the leading dot; later fields need their own
+        let mut first = true;
+        while !rest.is_empty() {
+            if !first && !rest.starts_with('[') {
+                rest = rest.strip_prefix('.').ok_or_else(|| invalid("expected '.' or '['"))?;
+            }
+            first = false;
+
+            if let Some(inner) = rest.strip_prefix('[') {
+                let (inside, after) = inner.split_once(']').ok_or_else(|| invalid("unclosed '['"))?;
+                let inside = inside.trim();
+                steps.push(if inside.is_empty() {
+                    Step::Iterate
+                } else if let Some(key) = inside.strip_prefix('"').and_then(|k| k.strip_suffix('"')) {
+                    Step::Field(key.to_string())
+                } else {
+                    Step::Index(inside.parse().map_err(|_| invalid("expected a number, a \"key\" or nothing in []"))?)
+                });
+                rest = after;
+                continue;
+            }
+
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(invalid("expected a field name"));
+            }
+            steps.push(Step::Field(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+
+        Ok(Self {
+            expr: expr.to_string(),
+            steps,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn apply(expr: &str, value: &Value) -> Vec<Value> {
+        expr.parse::<JsonFilter>().unwrap().apply(value).unwrap()
+    }
+
+    fn apply_err(expr: &str, value: &Value) -> String {
+        expr.parse::<JsonFilter>().unwrap().apply(value).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_paths_select_fields_and_elements() {
+        let doc = json!({"items": [{"name": "a", "tags": ["x"]}, {"name": "b"}], "odd key": 1});
+
+        assert_eq!(apply(".", &doc), vec![doc.clone()]);
+        assert_eq!(apply(".items[].name", &doc), vec![json!("a"), json!("b")]);
+        assert_eq!(apply(".items[0].tags[-1]", &doc), vec![json!("x")]);
+        assert_eq!(apply(".[\"odd key\"]", &doc), vec![json!(1)]);
+        assert_eq!(apply(".items[1].tags", &doc), vec![Value::Null]);
+        assert_eq!(apply(".items[5]", &doc), vec![Value::Null]);
+        assert_eq!(apply(".[]", &json!([1, 2])), vec![json!(1), json!(2)]);
+    }
+
+    #[test]
+    fn test_rejects_invalid_filters() {
+        for expr in ["items", ".items[", ".items[x]", ".items..name", ".a b"] {
+            assert!(expr.parse::<JsonFilter>().is_err(), "{} should be rejected", expr);
+        }
+    }
+
+    #[test]
+    fn test_applying_to_the_wrong_type_fails() {
+        let err = apply_err(".count.value", &json!({"count": 3}));
+
+        assert_eq!(err, ".count.value: can't apply .value to a number");
+    }
+}
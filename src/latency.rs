@@ -0,0 +1,303 @@
+//! Latency samples of model API calls and local command runs.
+//!
+//! Every model API call and every generated command run appends one line to
+//! `~/.abiogenesis/latency.jsonl` with how long it took; API calls also
+//! record the request and response sizes and the output tokens.
+//! `ergo --usage --latency` turns the samples into histograms, which tell
+//! whether slowness comes from the model, the network or local execution.
+//!
+//! A model call's time is split by fitting `latency = overhead + per_token ×
+//! output_tokens` over the API samples: the per-token part is the model
+//! generating its answer, the fixed overhead is the network, TLS and queueing.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Upper bounds of the histogram buckets, in milliseconds.
+const BUCKETS_MS: &[u64] = &[100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000];
+
+/// Width of the longest histogram bar.
+const BAR_WIDTH: usize = 30;
+
+/// API samples needed before the overhead is estimated.
+const MIN_FIT_SAMPLES: usize = 3;
+
+/// What a sample timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleKind {
+    /// A model API call.
+    Api,
+    /// A generated command run.
+    Execution,
+}
+
+/// One line of the latency log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencySample {
+    /// When the sample was taken (Unix timestamp).
+    pub timestamp: u64,
+    /// What was timed.
+    pub kind: SampleKind,
+    /// The model called, or the command run.
+    pub name: String,
+    /// Wall-clock time, in milliseconds.
+    pub duration_ms: u64,
+    /// Bytes of the request body (API calls only).
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub request_bytes: u64,
+    /// Bytes of the response body (API calls only).
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub response_bytes: u64,
+    /// Tokens the model answered with (API calls only).
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub output_tokens: u64,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// Returns the path of the latency log.
+pub fn default_path() -> Result<PathBuf> {
+    Ok(crate::config::Config::get_config_dir()?.join("latency.jsonl"))
+}
+
+/// Appends `sample` to the log at `path`.
+pub fn append(path: &Path, sample: &LatencySample) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(sample)?)?;
+    Ok(())
+}
+
+/// Reads every sample of the log at `path`, skipping malformed lines.
+///
+/// A missing log has no samples.
+pub fn read(path: &Path) -> Result<Vec<LatencySample>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+/// Distribution of the durations of one kind of sample.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyStats {
+    /// Durations in milliseconds, sorted.
+    pub durations: Vec<u64>,
+    /// Samples per bucket of [`BUCKETS_MS`], plus one for longer ones.
+    pub histogram: Vec<usize>,
+}
+
+impl LatencyStats {
+    /// Collects the durations of `samples`.
+    pub fn from_samples<'a>(samples: impl IntoIterator<Item = &'a LatencySample>) -> Self {
+        let mut durations: Vec<u64> = samples.into_iter().map(|sample| sample.duration_ms).collect();
+        durations.sort_unstable();
+        let mut histogram = vec![0; BUCKETS_MS.len() + 1];
+        for duration in &durations {
+            let bucket = BUCKETS_MS.iter().position(|&bound| *duration < bound).unwrap_or(BUCKETS_MS.len());
+            histogram[bucket] += 1;
+        }
+        Self { durations, histogram }
+    }
+
+    /// The duration `percent`% of samples don't exceed (nearest rank).
+    pub fn percentile(&self, percent: usize) -> Option<u64> {
+        let rank = (self.durations.len() * percent).div_ceil(100).max(1);
+        self.durations.get(rank - 1).copied()
+    }
+
+    fn fmt_section(&self, f: &mut fmt::Formatter<'_>, title: &str) -> fmt::Result {
+        write!(f, "{} ({} sample{}):", title, self.durations.len(), if self.durations.len() == 1 { "" } else { "s" })?;
+        let (Some(p50), Some(p90), Some(max)) = (self.percentile(50), self.percentile(90), self.durations.last()) else {
+            return Ok(());
+        };
+        write!(f, "\n   p50 {}  p90 {}  max {}", seconds(p50), seconds(p90), seconds(*max))?;
+        let most = self.histogram.iter().copied().max().unwrap_or(0).max(1);
+        for (index, count) in self.histogram.iter().enumerate() {
+            let label = match BUCKETS_MS.get(index) {
+                Some(&bound) => format!("< {}", seconds(bound)),
+                None => format!("≥ {}", seconds(BUCKETS_MS[BUCKETS_MS.len() - 1])),
+            };
+            let bar = "█".repeat((count * BAR_WIDTH).div_ceil(most));
+            write!(f, "\n   {:>7} │{} {}", label, bar, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats milliseconds for display, e.g. `250ms` or `2.5s`.
+fn seconds(ms: u64) -> String {
+    if ms < 1_000 {
+        format!("{}ms", ms)
+    } else {
+        format!("{:.1}s", ms as f64 / 1_000.0)
+    }
+}
+
+/// Latency histograms of model calls and local runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyReport {
+    /// Model API calls.
+    pub api: LatencyStats,
+    /// Generated command runs.
+    pub execution: LatencyStats,
+    /// Average request and response body sizes of API calls, in bytes.
+    pub api_bytes: Option<(u64, u64)>,
+    /// Fixed overhead per API call and time per output token, in
+    /// milliseconds, when there are enough samples to tell.
+    pub overhead: Option<(f64, f64)>,
+}
+
+impl LatencyReport {
+    /// Summarizes `samples`.
+    pub fn from_samples(samples: &[LatencySample]) -> Self {
+        let api: Vec<&LatencySample> = samples.iter().filter(|s| s.kind == SampleKind::Api).collect();
+        let api_bytes = (!api.is_empty()).then(|| {
+            let count = api.len() as u64;
+            (
+                api.iter().map(|s| s.request_bytes).sum::<u64>() / count,
+                api.iter().map(|s| s.response_bytes).sum::<u64>() / count,
+            )
+        });
+        Self {
+            api: LatencyStats::from_samples(api.iter().copied()),
+            execution: LatencyStats::from_samples(samples.iter().filter(|s| s.kind == SampleKind::Execution)),
+            api_bytes,
+            overhead: fit_overhead(&api),
+        }
+    }
+
+    /// One line saying where most of the waiting goes.
+    pub fn verdict(&self) -> Option<String> {
+        let api = self.api.percentile(50)?;
+        if let Some(execution) = self.execution.percentile(50)
+            && execution > api
+        {
+            return Some("Local execution takes longer than model calls; the commands themselves are slow".to_string());
+        }
+        match self.overhead {
+            Some((overhead, _)) if overhead * 2.0 > api as f64 => {
+                Some("Most of a model call is fixed overhead: check your network, proxy or VPN".to_string())
+            }
+            Some(_) => Some("Most of a model call is the model generating its answer".to_string()),
+            None => None,
+        }
+    }
+}
+
+/// Least-squares fit of `duration = overhead + per_token × output_tokens`.
+fn fit_overhead(samples: &[&LatencySample]) -> Option<(f64, f64)> {
+    let points: Vec<(f64, f64)> = samples
+        .iter()
+        .filter(|s| s.output_tokens > 0)
+        .map(|s| (s.output_tokens as f64, s.duration_ms as f64))
+        .collect();
+    if points.len() < MIN_FIT_SAMPLES {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    if variance == 0.0 {
+        return None;
+    }
+    let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let per_token = (covariance / variance).max(0.0);
+    let overhead = (mean_y - per_token * mean_x).max(0.0);
+    Some((overhead, per_token))
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.api.fmt_section(f, "⏱️  Model API calls")?;
+        if let Some((request, response)) = self.api_bytes {
+            write!(f, "\n   avg request {:.1} KiB, response {:.1} KiB", request as f64 / 1024.0, response as f64 / 1024.0)?;
+        }
+        if let Some((overhead, per_token)) = self.overhead {
+            write!(
+                f,
+                "\n   🧮 ~{} fixed per call (network, queueing) + {:.1}ms per output token (model)",
+                seconds(overhead.round() as u64),
+                per_token
+            )?;
+        }
+        write!(f, "\n\n")?;
+        self.execution.fmt_section(f, "⚙️  Local execution")?;
+        if let Some(verdict) = self.verdict() {
+            write!(f, "\n\n💡 {}", verdict)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sample(kind: SampleKind, duration_ms: u64, output_tokens: u64) -> LatencySample {
+        LatencySample {
+            timestamp: 0,
+            kind,
+            name: "x".to_string(),
+            duration_ms,
+            request_bytes: 2048,
+            response_bytes: 1024,
+            output_tokens,
+        }
+    }
+
+    #[test]
+    fn test_append_and_read_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("latency.jsonl");
+        let run = LatencySample {
+            request_bytes: 0,
+            response_bytes: 0,
+            ..sample(SampleKind::Execution, 40, 0)
+        };
+
+        append(&path, &sample(SampleKind::Api, 900, 120)).unwrap();
+        append(&path, &run).unwrap();
+        fs::write(&path, format!("{}torn line\n", fs::read_to_string(&path).unwrap())).unwrap();
+
+        assert_eq!(read(&path).unwrap(), vec![sample(SampleKind::Api, 900, 120), run]);
+        assert!(fs::read_to_string(&path).unwrap().contains(r#"{"timestamp":0,"kind":"execution","name":"x","duration_ms":40}"#));
+        assert!(read(&temp_dir.path().join("missing.jsonl")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_report_fits_overhead_and_buckets() {
+        // 800ms fixed + 10ms per token
+        let samples = vec![
+            sample(SampleKind::Api, 1_800, 100),
+            sample(SampleKind::Api, 2_800, 200),
+            sample(SampleKind::Api, 4_800, 400),
+            sample(SampleKind::Execution, 50, 0),
+        ];
+
+        let report = LatencyReport::from_samples(&samples);
+
+        let (overhead, per_token) = report.overhead.unwrap();
+        assert!((overhead - 800.0).abs() < 1e-6);
+        assert!((per_token - 10.0).abs() < 1e-6);
+        assert_eq!(report.api.percentile(50), Some(2_800));
+        assert_eq!(report.api.histogram, vec![0, 0, 0, 0, 1, 2, 0, 0, 0]);
+        assert_eq!(report.execution.histogram[0], 1);
+        assert_eq!(report.api_bytes, Some((2048, 1024)));
+        assert_eq!(report.verdict().unwrap(), "Most of a model call is the model generating its answer");
+        assert!(report.to_string().contains("~800ms fixed per call (network, queueing) + 10.0ms per output token (model)"));
+    }
+}
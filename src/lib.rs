@@ -13,14 +13,70 @@
 //!
 //! The library is organized into several modules:
 //!
+//! - [`anthropic_error`] - Anthropic API errors turned into user guidance
+//! - [`bedrock`] - Anthropic models on AWS Bedrock with SigV4 signing
+//! - [`capture`] - Bounded capture of command output
+//! - [`chain`] - Sequential command chains with success and failure branches
+//! - [`changelog`] - Changelogs of a command's revisions, for `--changelog`
+//! - [`ci_export`] - GitHub Actions steps for running cached commands in CI
 //! - [`config`] - Configuration management (API keys, paths)
+//! - [`cancellation`] - Ctrl-C handling and cooperative cancellation
 //! - [`command_cache`] - Persistent command storage
 //! - [`command_router`] - Routes intents to appropriate handlers
+//! - [`dep_audit`] - Dependency inventory and advisory checks for cached scripts
+//! - [`deno_deps`] - Version pinning of Deno std imports in generated scripts
+//! - [`docgen`] - Man pages generated from cached command metadata
+//! - [`doctor`] - Environment diagnostics for `ergo doctor`
+//! - [`error`] - Error categories of the library API ([`error::ErgoError`])
+//! - [`environment`] - Environment snapshots of executions, for reproducibility
+//! - [`ephemeral`] - Running without writing to disk (`--ephemeral`, read-only homes)
+//! - [`diagnostics`] - Sanitized bug report bundles
 //! - [`executor`] - Runs system and generated commands
 //! - [`execution_context`] - Tracks last execution for corrective feedback
+//! - [`explain`] - Annotated script listings for `--explain`
+//! - [`fixtures`] - Fixture replay and recording of generator answers for tests
+//! - [`git_hooks`] - Git hooks running cached commands
+//! - [`import`] - Commands imported from a URL, for `--import-url`
+//! - [`intent_queue`] - Intents queued while offline, generated by `--flush-queue`
+//! - [`json_filter`] - jq-style path filters for JSON output
+//! - [`latency`] - Latency samples of model calls and command runs (`--usage --latency`)
+//! - [`llm_debug`] - Capture of raw model API exchanges (`--debug-llm`)
 //! - [`llm_generator`] - AI-powered command generation
+//! - [`logging`] - Log file and stderr tracing setup
+//! - [`models`] - Models a generator backend offers, for `ergo --models`
+//! - [`notify`] - Webhook notifications of command results
+//! - [`output_processing`] - JSON formatting, colors, and paging of command output
+//! - [`permission_sim`] - Dry runs with permissions denied, for `--simulate`
+//! - [`permission_summary`] - Permissions held across the whole cache, by risk tier
 //! - [`permission_ui`] - User consent dialogs
+//! - [`permissions`] - Typed Deno permissions with scopes
+//! - [`plugins`] - Generator plugins discovered in `~/.abiogenesis/plugins`
+//! - [`preload`] - Preloading of the most-used commands' imports
+//! - [`project_scope`] - Detection of project commands reaching outside the project
+//! - [`progress`] - Progress events of the router, for frontends rendering their own progress
 //! - [`providers`] - Shared dependency injection traits
+//! - [`raycast_export`] - Raycast script commands running cached commands
+//! - [`regenerate_filter`] - Selection of cached commands for `--regenerate-all`
+//! - [`reserved`] - Names generated commands can't take, such as ergo subcommands and shell builtins
+//! - [`retention`] - Pruning of the history logs and `--debug-llm` captures to configured limits
+//! - [`sandbox`] - Optional OS-level sandbox wrappers around Deno
+//! - [`script_diff`] - Diffs between command revisions
+//! - [`secrets`] - Masking and clipboard delivery of sensitive output
+//! - [`security_review`] - Static and model-based script security reviews
+//! - [`semantic_search`] - Local embeddings to find cached commands matching an intent
+//! - [`snapshot`] - Snapshots of a whole bioma, for `ergo bioma snapshot` and `restore`
+//! - [`staging`] - Staged filesystem writes with a changes manifest
+//! - [`starter`] - Starter commands embedded in the binary, installed on the first run
+//! - [`suggest`] - Project detection and pick-lists for command suggestions
+//! - [`testing`] - Mocks and a router builder for tests (`testing` feature)
+//! - [`telemetry`] - Opt-in anonymous usage statistics
+//! - [`trust`] - Trust levels of biomas, and untrusted ones always asking for consent
+//! - [`usage_ledger`] - Token usage ledger and prompt cache savings
+//! - [`validation`] - Validators generated commands pass before they're cached
+//! - [`vscode_tasks`] - VS Code tasks for project commands
+//! - [`workflow`] - Declarative multi-step workflow files
+//! - [`workspace`] - Temporary workspaces holding only a command's inputs
+//! - [`gemini`] - Google Gemini models via AI Studio or Vertex AI
 //! - [`http_client`] - HTTP client abstraction
 //!
 //! # Example
@@ -33,7 +89,10 @@
 //!     let mut router = CommandRouter::new(false).await?;
 //!
 //!     // Generate and execute a command
-//!     router.process_intent(vec!["hello".to_string()]).await?;
+//!     let outcome = router.process_intent(vec!["hello".to_string()]).await?;
+//!     if let Some(result) = outcome.execution() {
+//!         println!("exit code: {:?}, took {:?}", result.exit_code, result.duration);
+//!     }
 //!
 //!     // If the command didn't work as expected, provide corrective feedback
 //!     // to regenerate it with improvements
@@ -43,6 +102,16 @@
 //! }
 //! ```
 //!
+//! # Features
+//!
+//! - `cli` (default) - the `ergo` binary, with argument parsing and [`logging`]
+//! - `claude` (default, implied by `cli`) - the built-in model backends over
+//!   reqwest ([`http_client::ReqwestHttpClient`])
+//! - `testing` - mocks and a router builder for tests
+//!
+//! Embedders of the routing and caching logic alone can use
+//! `default-features = false`.
+//!
 //! # Corrective Feedback
 //!
 //! When a generated command doesn't meet expectations, use the `--nope` flag
@@ -63,12 +132,70 @@
 //! the implementation based on your feedback and any error output from the
 //! previous execution.
 
+pub mod anthropic_error;
+pub mod bedrock;
+pub mod cancellation;
+pub mod capture;
+pub mod chain;
+pub mod changelog;
+pub mod ci_export;
 pub mod command_cache;
 pub mod command_router;
 pub mod config;
+pub mod dep_audit;
+pub mod deno_deps;
+pub mod diagnostics;
+pub mod docgen;
+pub mod doctor;
+pub mod environment;
+pub mod error;
+pub mod ephemeral;
 pub mod execution_context;
 pub mod executor;
+pub mod explain;
+pub mod fixtures;
+pub mod gemini;
+pub mod git_hooks;
 pub mod http_client;
+pub mod import;
+pub mod intent_queue;
+pub mod json_filter;
+pub mod latency;
+pub mod llm_debug;
 pub mod llm_generator;
+#[cfg(feature = "cli")]
+pub mod logging;
+pub mod models;
+pub mod notify;
+pub mod output_processing;
+pub mod permission_sim;
+pub mod permission_summary;
 pub mod permission_ui;
-pub mod providers;
\ No newline at end of file
+pub mod permissions;
+pub mod plugins;
+pub mod preload;
+pub mod project_scope;
+pub mod progress;
+pub mod providers;
+pub mod raycast_export;
+pub mod regenerate_filter;
+pub mod reserved;
+pub mod retention;
+pub mod sandbox;
+pub mod secrets;
+pub mod script_diff;
+pub mod security_review;
+pub mod semantic_search;
+pub mod snapshot;
+pub mod staging;
+pub mod starter;
+pub mod suggest;
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod trust;
+pub mod usage_ledger;
+pub mod validation;
+pub mod vscode_tasks;
+pub mod workflow;
+pub mod workspace;
@@ -0,0 +1,186 @@
+//! Capture of raw model API exchanges for debugging prompts.
+//!
+//! With `ergo --debug-llm`, every model API call is written to its own file
+//! under `~/.abiogenesis/llm_debug/`, named after the time of the call: the
+//! URL, headers and exact request payload, and the raw response (or the
+//! error). `ergo --last-llm` prints the most recent exchange.
+//!
+//! Secrets never reach the files: header values that carry credentials are
+//! replaced, and credential-looking tokens anywhere in the exchange are
+//! redacted with [`crate::diagnostics::redact_secrets`].
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Placeholder for redacted header values.
+const REDACTED: &str = "[REDACTED]";
+
+/// Header names (lowercase) containing these carry credentials.
+const SECRET_HEADER_MARKERS: &[&str] = &["authorization", "key", "token", "secret"];
+
+/// Where exchanges are captured, once capture is enabled for this process.
+static CAPTURE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// One model API call.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// When the call was made (Unix timestamp in milliseconds).
+    pub timestamp_ms: u64,
+    /// The endpoint called.
+    pub url: String,
+    /// Request headers, with credentials redacted.
+    pub headers: Vec<(String, String)>,
+    /// The exact request payload.
+    pub request: Value,
+    /// The raw response: JSON if it parses, text otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    /// Why the call failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Exchange {
+    /// Describes a call from its parts, redacting credential headers.
+    pub fn new(url: &str, headers: &[(&str, &str)], request: &Value, response: &Result<String>) -> Self {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| {
+                let lowercase = name.to_lowercase();
+                let value = if SECRET_HEADER_MARKERS.iter().any(|m| lowercase.contains(m)) {
+                    REDACTED
+                } else {
+                    value
+                };
+                (name.to_string(), value.to_string())
+            })
+            .collect();
+        let (response, error) = match response {
+            Ok(text) => (
+                Some(serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.clone()))),
+                None,
+            ),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Self {
+            timestamp_ms: now_ms(),
+            url: url.to_string(),
+            headers,
+            request: request.clone(),
+            response,
+            error,
+        }
+    }
+}
+
+/// Returns the default capture directory.
+pub fn default_dir() -> Result<PathBuf> {
+    Ok(crate::config::Config::get_config_dir()?.join("llm_debug"))
+}
+
+/// Captures every later model API call of this process to `dir`.
+pub fn enable(dir: PathBuf) {
+    let _ = CAPTURE_DIR.set(dir);
+}
+
+/// The capture directory, if capture is enabled.
+pub fn capture_dir() -> Option<&'static Path> {
+    CAPTURE_DIR.get().map(PathBuf::as_path)
+}
+
+/// Writes `exchange` to a new file in `dir`, with secrets redacted, and
+/// returns its path.
+pub fn record(dir: &Path, exchange: &Exchange) -> Result<PathBuf> {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+    fs::create_dir_all(dir)?;
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    // Zero-padded so names sort by time
+    let path = dir.join(format!("{:015}-{:04}.json", exchange.timestamp_ms, sequence % 10_000));
+    let content = crate::diagnostics::redact_secrets(&serde_json::to_string_pretty(exchange)?);
+    fs::write(&path, content)?;
+    Ok(path)
+}
+
+/// Reads the most recent exchange captured in `dir`, if any.
+pub fn latest(dir: &Path) -> Result<Option<(PathBuf, Exchange)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let newest = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .max();
+    let Some(path) = newest else {
+        return Ok(None);
+    };
+    let exchange = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    Ok(Some((path, exchange)))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_exchange_redacts_credential_headers() {
+        let headers = [
+            ("x-api-key", "sk-ant-secret"),
+            ("Authorization", "AWS4-HMAC-SHA256 Credential=AKIA..."),
+            ("content-type", "application/json"),
+        ];
+
+        let exchange = Exchange::new("https://api.example.com", &headers, &json!({}), &Ok("plain".to_string()));
+
+        assert_eq!(exchange.headers[0].1, REDACTED);
+        assert_eq!(exchange.headers[1].1, REDACTED);
+        assert_eq!(exchange.headers[2].1, "application/json");
+        assert_eq!(exchange.response, Some(json!("plain")));
+    }
+
+    #[test]
+    fn test_record_redacts_secrets_and_latest_reads_newest() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("llm_debug");
+        assert!(latest(&dir).unwrap().is_none());
+
+        let mut first = Exchange::new(
+            "https://api.example.com",
+            &[],
+            &json!({"messages": [{"role": "user", "content": "use key sk-ant-abc123 please"}]}),
+            &Ok(r#"{"content": [{"text": "ok"}]}"#.to_string()),
+        );
+        first.timestamp_ms = 1_000;
+        let mut second = Exchange::new("https://api.example.com", &[], &json!({}), &Err(anyhow!("connection reset")));
+        second.timestamp_ms = 2_000;
+
+        let first_path = record(&dir, &first).unwrap();
+        record(&dir, &second).unwrap();
+
+        let saved = fs::read_to_string(&first_path).unwrap();
+        assert!(!saved.contains("sk-ant-abc123"));
+        assert!(saved.contains("[REDACTED]"));
+        let (_, newest) = latest(&dir).unwrap().unwrap();
+        assert_eq!(newest.timestamp_ms, 2_000);
+        assert_eq!(newest.error.as_deref(), Some("connection reset"));
+        assert_eq!(newest.response, None);
+    }
+}
@@ -4,12 +4,31 @@
 //! requests a command that doesn't exist, the LLM generates a Deno/TypeScript
 //! script that implements the requested functionality.
 
-use crate::http_client::{HttpClient, ReqwestHttpClient};
+use crate::anthropic_error::ApiError;
+use crate::bedrock::{BEDROCK_ANTHROPIC_VERSION, BEDROCK_BACKEND, BedrockTarget};
+use crate::command_cache::FeedbackTurn;
+use crate::executor::SystemProcessRunner;
+use crate::gemini::{self, GEMINI_BACKEND, GeminiTarget};
+#[cfg(feature = "claude")]
+use crate::http_client::ReqwestHttpClient;
+use crate::http_client::{HttpClient, HttpRequest, HttpResponse};
+use crate::latency::{self, LatencySample, SampleKind};
+use crate::llm_debug;
+use crate::models::{self, ModelList};
+use crate::output_processing::OutputFormat;
+use crate::permissions::Permission;
+use crate::plugins::BUILTIN_BACKEND;
+use crate::providers::{SystemTimeProvider, TimeProvider};
+use crate::retention;
+use crate::security_review::ModelReview;
+use crate::usage_ledger::{self, LedgerEntry, TokenUsage};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tracing::info;
+use std::collections::BTreeMap;
+use std::fmt;
+use tracing::{info, warn};
 
 // =============================================================================
 // Prompt Building
@@ -32,7 +51,18 @@ mod prompt_sections {
       "permission": "--allow-read",
       "reason": "Read files from the current directory"
     }
-  ]
+  ],
+  "sensitive_output": false,
+  "output_format": "text",
+  "examples": [
+    {
+      "usage": "ergo suggested-command-name --flag value",
+      "explanation": "What this invocation does"
+    }
+  ],
+  "assumptions": ["Input files are UTF-8 text"],
+  "confidence": "high",
+  "platforms": ["linux", "macos", "windows"]
 }"#;
 
     /// Rules for using Deno APIs.
@@ -42,14 +72,101 @@ mod prompt_sections {
     /// Rules for requesting minimal permissions.
     pub const PERMISSION_RULES: &str =
         "- Use MINIMAL permissions (empty [] preferred)\n\
-         - Valid permission values: --allow-read, --allow-write, --allow-net, --allow-env, --allow-run\n\
+         - Valid permission values: --allow-read, --allow-write, --allow-net, --allow-env, --allow-run, --allow-sys\n\
+         - Prefer scoped permissions when the targets are known, e.g. --allow-read=./data or --allow-net=api.example.com\n\
          - For each permission, provide a clear reason why it's needed in user-friendly language";
 
+    /// Rules for flagging secrets in the output.
+    pub const SENSITIVE_OUTPUT_RULES: &str =
+        "- Set \"sensitive_output\" to true if the command prints secrets such as passwords, tokens, or keys";
+
+    /// Rules for declaring JSON output.
+    pub const OUTPUT_FORMAT_RULES: &str =
+        "- Set \"output_format\" to \"json\" if the command prints a single JSON document and nothing else \
+         on stdout (send progress and errors to stderr); otherwise use \"text\"";
+
+    /// Rules for usage examples.
+    pub const EXAMPLE_RULES: &str =
+        "- Include 2-3 \"examples\" of realistic invocations, each starting with 'ergo <command name>', \
+         covering the main ways to use the arguments";
+
+    /// Rules for reporting assumptions and confidence.
+    pub const ASSUMPTION_RULES: &str =
+        "- List in \"assumptions\" what you assumed but weren't told: installed tools, file formats and \
+         encodings, platform, argument meaning. Keep each one short; use an empty list if there are none\n\
+         - Set \"confidence\" to low, medium or high: how sure you are the command does what was asked";
+
+    /// Rules for writing scripts that work on the host platform.
+    pub const PLATFORM_RULES: &str =
+        "- The script must work on the host platform: use paths, tools and APIs available there, and prefer \
+         portable Deno APIs (Deno.build.os, Deno.env, Deno.Command) over OS-specific shell commands\n\
+         - Set \"platforms\" to the operating systems the script works on, from: linux, macos, windows";
+
     /// Rules for code quality.
     pub const QUALITY_RULES: &str =
         "- Create real, working functionality - no placeholder code\n\
          - Include try/catch for error handling";
 
+    /// The expected JSON schema for script explanations.
+    pub const EXPLANATION_SCHEMA: &str = r#"RESPOND WITH EXACTLY THIS FORMAT (with your values):
+{
+  "summary": "One or two sentences describing what the command does",
+  "lines": [
+    { "line": 1, "explanation": "What this line does" }
+  ],
+  "effects": {
+    "files": ["./notes.txt (read)"],
+    "hosts": ["api.example.com"],
+    "other": ["Reads the HOME environment variable"]
+  }
+}"#;
+
+    /// The expected JSON schema for failure diagnoses.
+    pub const DIAGNOSIS_SCHEMA: &str = r#"RESPOND WITH EXACTLY THIS FORMAT (with your values):
+{
+  "explanation": "Why the command failed, in one to three sentences",
+  "fixed_command": "the corrected command line, or null",
+  "ergo_intent": "a description of an ergo command that does what the user wanted, or null"
+}"#;
+
+    /// The expected JSON schema for command suggestions.
+    pub const SUGGESTION_SCHEMA: &str = r#"RESPOND WITH EXACTLY THIS FORMAT (with your values):
+{
+  "suggestions": [
+    { "intent": "One-sentence description of the command to generate", "reason": "Why it helps in this project" }
+  ]
+}"#;
+
+    /// The expected JSON schema for generation plans.
+    pub const PLAN_SCHEMA: &str = r#"RESPOND WITH EXACTLY THIS FORMAT (with your values):
+{
+  "summary": "What the command will do, in one sentence",
+  "steps": ["Read the file given as the first argument", "Print the lines that match"],
+  "permissions": [
+    {
+      "permission": "--allow-read",
+      "reason": "Read the file given as the first argument"
+    }
+  ],
+  "edge_cases": ["The file doesn't exist", "The file is empty"]
+}"#;
+
+    /// Instructions for the security audit prompt.
+    pub const AUDIT_INSTRUCTIONS: &str =
+        "You are a security auditor reviewing a Deno/TypeScript command before a user runs it on their machine. \
+         Look for risky patterns: data exfiltration, destructive file operations, arbitrary code execution, \
+         subprocess spawning, credential or environment access, unvalidated input used in paths or commands, \
+         obfuscated code, and permissions broader than the script needs.";
+
+    /// The expected JSON schema for security reviews.
+    pub const REVIEW_SCHEMA: &str = r#"RESPOND WITH EXACTLY THIS FORMAT (with your values):
+{
+  "summary": "Overall assessment in one or two sentences",
+  "findings": [
+    { "severity": "high", "line": 12, "message": "What is risky and why" }
+  ]
+}"#;
+
     /// Final reminder to output only JSON.
     pub const JSON_ONLY_REMINDER: &str =
         "- CRITICAL: RESPOND ONLY WITH THE JSON OBJECT ABOVE - NO OTHER TEXT";
@@ -115,6 +232,102 @@ impl PromptBuilder {
     }
 }
 
+/// Author of a message in a prompt's conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// The user, i.e. ergo speaking on the user's behalf.
+    User,
+    /// The model, e.g. a script it generated earlier.
+    Assistant,
+}
+
+impl Role {
+    /// The role name used by the Anthropic Messages API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+/// One turn of a prompt's conversation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    /// Who wrote the message.
+    pub role: Role,
+    /// The message text.
+    pub content: String,
+}
+
+/// A prompt split into the system instructions and the conversation.
+///
+/// The system prompt holds the task, response schema and rules; the messages
+/// hold only what the user asked for and, for refinements, the earlier turns.
+/// When `cache_system` is set the system prompt is identical across calls and
+/// Anthropic's prompt cache can serve it (see [`crate::usage_ledger`]).
+#[derive(Debug, Clone, PartialEq)]
+struct Prompt {
+    /// Instructions sent in the API's `system` field.
+    system: String,
+    /// The conversation, starting and ending with a user message.
+    messages: Vec<Message>,
+    /// Whether to mark the system prompt for caching.
+    cache_system: bool,
+    /// Longest answer the model may give, in tokens.
+    max_tokens: u32,
+}
+
+impl Prompt {
+    fn new(system: String) -> Self {
+        Self {
+            system,
+            messages: Vec::new(),
+            cache_system: false,
+            max_tokens: MAX_TOKENS,
+        }
+    }
+
+    fn user(mut self, content: String) -> Self {
+        self.messages.push(Message {
+            role: Role::User,
+            content,
+        });
+        self
+    }
+
+    fn assistant(mut self, content: String) -> Self {
+        self.messages.push(Message {
+            role: Role::Assistant,
+            content,
+        });
+        self
+    }
+
+    fn cached(mut self) -> Self {
+        self.cache_system = true;
+        self
+    }
+
+    /// The messages in the Anthropic Messages API format.
+    fn messages_json(&self) -> serde_json::Value {
+        self.messages
+            .iter()
+            .map(|m| json!({"role": m.role.as_str(), "content": m.content}))
+            .collect()
+    }
+
+    /// The whole prompt as one text.
+    #[cfg(test)]
+    fn joined(&self) -> String {
+        std::iter::once(self.system.as_str())
+            .chain(self.messages.iter().map(|m| m.content.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
 // =============================================================================
 // Data Types
 // =============================================================================
@@ -142,10 +355,26 @@ pub struct PermissionRequest {
     pub reason: String,
 }
 
+impl PermissionRequest {
+    /// Parses the permission flag into a typed [`Permission`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the flag is not a known Deno permission or has an
+    /// invalid scope.
+    pub fn parsed(&self) -> Result<Permission> {
+        self.permission.parse()
+    }
+}
+
 /// A command generated by the LLM.
 ///
 /// Contains metadata about the command and a reference to its script file.
 /// The actual script content is stored separately in the cache.
+///
+/// Every field added after the first release is `#[serde(default)]`, so
+/// older `commands.json` files keep loading; fields this version doesn't
+/// know are kept in [`Self::extra`], so newer ones survive being saved.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct GeneratedCommand {
     /// The command name (used for invocation and caching).
@@ -155,9 +384,398 @@ pub struct GeneratedCommand {
     /// Path to the script file (relative to biomas directory).
     pub script_file: String,
     /// List of Deno permissions required by this command.
+    #[serde(default)]
+    pub permissions: Vec<PermissionRequest>,
+    /// Whether the command prints secrets (passwords, tokens, keys).
+    ///
+    /// Sensitive output is masked on the terminal and never persisted (see
+    /// [`crate::secrets`]).
+    #[serde(default)]
+    pub sensitive_output: bool,
+    /// What the command prints on stdout; JSON output is validated,
+    /// pretty-printed and filterable with `--jq`.
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    /// Usage examples suggested by the model when generating the command.
+    #[serde(default)]
+    pub examples: Vec<UsageExample>,
+    /// Assumptions the model made about the user's environment or intent,
+    /// e.g. "git is installed".
+    #[serde(default)]
+    pub assumptions: Vec<String>,
+    /// How sure the model is that the script does what was asked.
+    #[serde(default)]
+    pub confidence: Option<Confidence>,
+    /// Operating systems the script works on (as in [`std::env::consts::OS`]);
+    /// empty if the model didn't say.
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    /// Platform-specific versions of the script, by OS. They take the place
+    /// of the main script on their platform.
+    #[serde(default)]
+    pub variants: BTreeMap<String, PlatformVariant>,
+    /// How the main script was generated; `None` for commands generated by
+    /// plugins or before provenance was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// The specification the command was generated from with
+    /// `--intent-file`, kept as written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spec: Option<String>,
+    /// Fields written by a newer version of ergo, kept as they are.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// A platform-specific version of a command's script, e.g. `hello.macos.ts`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PlatformVariant {
+    /// Path to the script file (relative to the biomas directory).
+    pub script_file: String,
+    /// Deno permissions the variant requires.
+    pub permissions: Vec<PermissionRequest>,
+}
+
+impl GeneratedCommand {
+    /// Returns the command as it runs on `os`: the variant for that
+    /// platform if there is one, the command itself otherwise.
+    pub fn for_platform(&self, os: &str) -> GeneratedCommand {
+        match self.variants.get(os) {
+            Some(variant) => GeneratedCommand {
+                script_file: variant.script_file.clone(),
+                permissions: variant.permissions.clone(),
+                platforms: vec![os.to_string()],
+                ..self.clone()
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// Whether `--nope` on `os` should regenerate a variant for that
+    /// platform rather than the main script: the platform already has a
+    /// variant, or the main script doesn't work there.
+    pub fn regenerates_as_variant(&self, os: &str) -> bool {
+        self.variants.contains_key(os) || !self.supports_platform(os)
+    }
+
+    /// Whether the script works on `os` (as in [`std::env::consts::OS`]).
+    ///
+    /// Commands that don't list their platforms are assumed to be portable.
+    pub fn supports_platform(&self, os: &str) -> bool {
+        self.platforms.is_empty() || self.platforms.iter().any(|p| p == os)
+    }
+}
+
+/// How a command was generated: the backend, model and settings it was
+/// generated with, and the exact prompt.
+///
+/// Recorded with every command generated by a built-in backend, so
+/// `ergo --regenerate` can send the same prompt again.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Provenance {
+    /// The backend: `claude`, `bedrock` or `gemini`.
+    pub backend: String,
+    /// The model ID, e.g. [`ANTHROPIC_MODEL`].
+    pub model: String,
+    /// Longest answer the model could give, in tokens.
+    pub max_tokens: u32,
+    /// Operating system the prompt targeted (as in [`std::env::consts::OS`]).
+    pub os: String,
+    /// Architecture the prompt targeted (as in [`std::env::consts::ARCH`]).
+    pub arch: String,
+    /// The system prompt.
+    pub system: String,
+    /// The conversation, ending with the user's request.
+    pub messages: Vec<Message>,
+    /// When the command was generated (Unix timestamp).
+    pub generated_at: u64,
+    /// Whether the model was chosen with `--model` rather than configured;
+    /// regenerations keep a chosen model.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub model_chosen: bool,
+}
+
+impl Provenance {
+    /// The model chosen with `--model` for this command, if it was and
+    /// `backend` is the one that generated it.
+    pub fn chosen_model(&self, backend: &str) -> Option<&str> {
+        (self.model_chosen && self.backend == backend).then_some(self.model.as_str())
+    }
+
+    /// The recorded prompt, retargeted at `os` and `arch` if they differ
+    /// from the recorded platform.
+    fn prompt_for(&self, os: &str, arch: &str) -> Prompt {
+        let (recorded, host) = (host_platform(&self.os, &self.arch), host_platform(os, arch));
+        let retarget = |text: &str| text.replace(&recorded, &host);
+        Prompt {
+            system: retarget(&self.system),
+            messages: self
+                .messages
+                .iter()
+                .map(|m| Message {
+                    role: m.role,
+                    content: retarget(&m.content),
+                })
+                .collect(),
+            cache_system: false,
+            max_tokens: self.max_tokens,
+        }
+    }
+}
+
+/// The model's confidence that a generated command does what was asked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Confidence::Low => write!(f, "low"),
+            Confidence::Medium => write!(f, "medium"),
+            Confidence::High => write!(f, "high"),
+        }
+    }
+}
+
+/// An example invocation of a generated command.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UsageExample {
+    /// The full command line, e.g. `ergo weather London`.
+    pub usage: String,
+    /// What the invocation does.
+    pub explanation: String,
+}
+
+impl fmt::Display for UsageExample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}  # {}", self.usage, self.explanation)
+    }
+}
+
+/// Everything the model needs to regenerate a command with feedback.
+///
+/// Used by the `--nope` feedback loop. Only `command_name`, `original_script`
+/// and `user_feedback` are required; the remaining fields add context to the
+/// regeneration prompt when available.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeedbackRequest<'a> {
+    /// The name of the command to regenerate.
+    pub command_name: &'a str,
+    /// The original script that was executed.
+    pub original_script: &'a str,
+    /// Standard error output from the failed execution (if any).
+    pub stderr: Option<&'a str>,
+    /// How the failed execution ended, e.g. `exited with code 127 (command
+    /// not found) after 0.2s` (see [`crate::execution_context::ExecutionContext::exit_summary`]).
+    pub exit_status: Option<&'a str>,
+    /// User's feedback about what went wrong (may be empty).
+    pub user_feedback: &'a str,
+    /// The command's test script, which the new version must keep passing.
+    pub test_script: Option<&'a str>,
+    /// Feedback from earlier `--nope` rounds, oldest first.
+    pub history: &'a [FeedbackTurn],
+    /// The request the command was originally generated from, if recorded.
+    pub intent: Option<&'a str>,
+}
+
+/// Plain-English explanation of a single script line.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LineExplanation {
+    /// 1-based line number in the script.
+    pub line: usize,
+    /// What the line does.
+    pub explanation: String,
+}
+
+/// Effects a script has outside of its own process.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ExternalEffects {
+    /// Files or directories read or written.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Hosts contacted over the network.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Anything else (environment variables, subprocesses, ...).
+    #[serde(default)]
+    pub other: Vec<String>,
+}
+
+impl ExternalEffects {
+    /// Returns true if the script has no external effects.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.hosts.is_empty() && self.other.is_empty()
+    }
+}
+
+/// Explanation of a cached script, produced by `ergo --explain`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScriptExplanation {
+    /// One or two sentence summary of the whole script.
+    pub summary: String,
+    /// Per-line explanations. Trivial lines (braces, blank lines) may be omitted.
+    #[serde(default)]
+    pub lines: Vec<LineExplanation>,
+    /// Files touched, hosts contacted, and other side effects.
+    #[serde(default)]
+    pub effects: ExternalEffects,
+}
+
+/// Diagnosis of a failed command line, produced by `ergo --why`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FailureDiagnosis {
+    /// What went wrong, in plain English.
+    pub explanation: String,
+    /// The original command line, corrected, if a fix is enough.
+    #[serde(default)]
+    pub fixed_command: Option<String>,
+    /// Description of an ergo command that accomplishes the user's intent,
+    /// for when no existing tool does.
+    #[serde(default)]
+    pub ergo_intent: Option<String>,
+}
+
+/// A command worth generating for a project, proposed by `ergo suggest`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandSuggestion {
+    /// Natural language description to generate the command from.
+    pub intent: String,
+    /// Why the command is useful in this project.
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// What a command is generated for: a name with its arguments, as in
+/// `ergo weather London`, or a natural language description.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlanRequest<'a> {
+    /// A command the user named, keeping that name.
+    Named {
+        /// The command's name.
+        name: &'a str,
+        /// Arguments the command should handle.
+        args: &'a [String],
+    },
+    /// A natural language description; the backend chooses the name.
+    Described(&'a str),
+}
+
+impl PlanRequest<'_> {
+    /// The name the generated command must have, if the user chose it.
+    pub fn command_name(&self) -> Option<&str> {
+        match self {
+            PlanRequest::Named { name, .. } => Some(name),
+            PlanRequest::Described(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for PlanRequest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanRequest::Named { name, args: [] } => write!(f, "{}", name),
+            PlanRequest::Named { name, args } => write!(f, "{} {}", name, args.join(" ")),
+            PlanRequest::Described(description) => write!(f, "{}", description),
+        }
+    }
+}
+
+/// How a command will be implemented, produced before its script with
+/// `ergo --plan` so the user can approve or correct it first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct GenerationPlan {
+    /// What the command will do, in one sentence.
+    pub summary: String,
+    /// The steps the script will take, in order.
+    #[serde(default)]
+    pub steps: Vec<String>,
+    /// The Deno permissions the script will need.
+    #[serde(default)]
     pub permissions: Vec<PermissionRequest>,
+    /// Unusual inputs and failures the script will handle.
+    #[serde(default)]
+    pub edge_cases: Vec<String>,
+    /// Changes the user asked for while reviewing the plan, which take
+    /// precedence over the rest of it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<String>,
+}
+
+impl fmt::Display for GenerationPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Summary: {}", self.summary)?;
+        if !self.steps.is_empty() {
+            write!(f, "\nSteps:")?;
+            for (index, step) in self.steps.iter().enumerate() {
+                write!(f, "\n  {}. {}", index + 1, step)?;
+            }
+        }
+        write!(f, "\nPermissions:")?;
+        if self.permissions.is_empty() {
+            write!(f, " none")?;
+        }
+        for permission in &self.permissions {
+            write!(f, "\n  - {}: {}", permission.permission, permission.reason)?;
+        }
+        if !self.edge_cases.is_empty() {
+            write!(f, "\nEdge cases:")?;
+            for edge_case in &self.edge_cases {
+                write!(f, "\n  - {}", edge_case)?;
+            }
+        }
+        if !self.changes.is_empty() {
+            write!(f, "\nChanges requested by the user:")?;
+            for change in &self.changes {
+                write!(f, "\n  - {}", change)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describes the platform generated commands will run on, e.g.
+/// `HOST PLATFORM: macos (aarch64)`.
+///
+/// `os` and `arch` are as in [`std::env::consts`].
+fn host_platform(os: &str, arch: &str) -> String {
+    format!("HOST PLATFORM: {} ({})", os, arch)
 }
 
+/// Only the most recent rounds of earlier feedback are sent for regeneration.
+const MAX_FEEDBACK_HISTORY_TURNS: usize = 5;
+
+/// Earlier feedback and error output are cut to this many characters each.
+const MAX_FEEDBACK_TURN_CHARS: usize = 500;
+
+/// Only the end of the error output is sent for diagnosis (in characters).
+const MAX_DIAGNOSIS_OUTPUT_CHARS: usize = 8 * 1024;
+
+/// Only the newest part of a changelog is sent for summarizing (in characters).
+const MAX_CHANGELOG_CHARS: usize = 16 * 1024;
+
+/// Longest answer the model may give by default, in tokens.
+const MAX_TOKENS: u32 = 1500;
+
+/// At most this many command suggestions are kept.
+const MAX_SUGGESTIONS: usize = 8;
+
+/// Model used with the Anthropic API.
+pub const ANTHROPIC_MODEL: &str = "claude-3-haiku-20240307";
+
+/// Anthropic API version sent with every request.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// The Anthropic Messages API.
+const ANTHROPIC_MESSAGES_URL: &str = "https://api.anthropic.com/v1/messages";
+
+/// The Anthropic API listing the models an API key can use.
+const ANTHROPIC_MODELS_URL: &str = "https://api.anthropic.com/v1/models";
+
 /// Result of generating a command, including the script content.
 ///
 /// This struct bundles the command metadata with the actual script source code
@@ -177,7 +795,7 @@ pub struct GenerationResult {
 /// Trait for command generation backends.
 ///
 /// This trait abstracts command generation, allowing different implementations
-/// (e.g., real LLM API, mock for testing).
+/// (e.g., real LLM API, generator plugins, mock for testing).
 #[async_trait]
 pub trait CommandGenerator: Send + Sync {
     /// Generates a command with the given name and arguments.
@@ -191,20 +809,125 @@ pub trait CommandGenerator: Send + Sync {
     ///
     /// A `GenerationResult` containing the command metadata and script content.
     async fn generate_command(&self, command_name: &str, args: &[String]) -> Result<GenerationResult>;
+
+    /// Generates a command from a natural language description, letting the
+    /// backend choose its name.
+    async fn generate_command_from_description(&self, description: &str) -> Result<GenerationResult>;
+
+    /// Regenerates a command from the user's corrective feedback, keeping its name.
+    async fn regenerate_command_with_feedback(&self, request: &FeedbackRequest<'_>) -> Result<GenerationResult>;
+
+    /// Generates and regenerates with `model` instead of the configured one,
+    /// as `ergo --model` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend can't choose models, which is the default.
+    fn set_model(&mut self, model: &str) -> Result<()> {
+        Err(anyhow!("This generator backend can't generate with model '{}'", model))
+    }
+
+    /// Plans how to implement `request` without writing the script yet, as
+    /// `ergo --plan` does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend can't plan, which is the default.
+    async fn plan_command(&self, request: PlanRequest<'_>) -> Result<GenerationPlan> {
+        Err(anyhow!("This generator backend can't plan '{}' before generating it", request))
+    }
+
+    /// Generates the command for `request` following the approved `plan`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backend can't plan, which is the default.
+    async fn generate_from_plan(&self, request: PlanRequest<'_>, _plan: &GenerationPlan) -> Result<GenerationResult> {
+        Err(anyhow!("This generator backend can't generate '{}' from a plan", request))
+    }
+
+    /// Prepares for a generation that is likely to follow, e.g. by opening
+    /// the connection to the model API. Does nothing by default.
+    fn warm_up(&self) {}
 }
 
 // =============================================================================
 // LLM Generator Implementation
 // =============================================================================
 
+/// A model's answer to a prompt.
+struct Reply {
+    /// The answer's text, or why it couldn't be read.
+    content: Result<String>,
+    /// Tokens the call used, if the response reported them.
+    usage: Option<TokenUsage>,
+}
+
+/// Where prompts are sent.
+enum ApiEndpoint {
+    /// The Anthropic API, authenticated with an API key.
+    Anthropic { api_key: String, model: String },
+    /// An Anthropic model on AWS Bedrock.
+    Bedrock(BedrockTarget),
+    /// A Google Gemini model.
+    Gemini(GeminiTarget),
+}
+
+impl fmt::Display for ApiEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiEndpoint::Anthropic { .. } => write!(f, "Claude API"),
+            ApiEndpoint::Bedrock(target) => write!(f, "Bedrock model {} in {}", target.model, target.region),
+            ApiEndpoint::Gemini(target) => write!(f, "Gemini model {} via {}", target.model, target.api),
+        }
+    }
+}
+
+impl ApiEndpoint {
+    /// The backend name, as in `generator.backend`.
+    fn backend(&self) -> &'static str {
+        match self {
+            ApiEndpoint::Anthropic { .. } => BUILTIN_BACKEND,
+            ApiEndpoint::Bedrock(_) => BEDROCK_BACKEND,
+            ApiEndpoint::Gemini(_) => GEMINI_BACKEND,
+        }
+    }
+
+    /// The scheme and host prompts are sent to.
+    fn base_url(&self) -> String {
+        let url = match self {
+            ApiEndpoint::Anthropic { .. } => return "https://api.anthropic.com".to_string(),
+            ApiEndpoint::Bedrock(target) => target.invoke_url(),
+            ApiEndpoint::Gemini(target) => target.url.clone(),
+        };
+        // Strip the path (and, for Gemini, the query string with its key)
+        match url.split_once("://") {
+            Some((scheme, rest)) => format!("{}://{}", scheme, rest.split('/').next().unwrap_or(rest)),
+            None => url,
+        }
+    }
+
+    /// The model prompts are sent to.
+    fn model(&self) -> &str {
+        match self {
+            ApiEndpoint::Anthropic { model, .. } => model,
+            ApiEndpoint::Bedrock(target) => &target.model,
+            ApiEndpoint::Gemini(target) => &target.model,
+        }
+    }
+}
+
 /// LLM-based command generator using Claude API.
 ///
 /// Uses the Anthropic Claude API to generate Deno/TypeScript commands based on
 /// natural language descriptions or command names.
-pub struct LlmGenerator<H: HttpClient = ReqwestHttpClient> {
+pub struct LlmGenerator<H: HttpClient> {
     http_client: H,
+    /// Model used instead of the configured one, as chosen with `--model`.
+    model: Option<String>,
 }
 
+#[cfg(feature = "claude")]
 impl LlmGenerator<ReqwestHttpClient> {
     /// Creates a new LlmGenerator with the default HTTP client.
     pub fn new() -> Self {
@@ -212,6 +935,7 @@ impl LlmGenerator<ReqwestHttpClient> {
     }
 }
 
+#[cfg(feature = "claude")]
 impl Default for LlmGenerator<ReqwestHttpClient> {
     fn default() -> Self {
         Self::new()
@@ -223,7 +947,13 @@ impl<H: HttpClient> LlmGenerator<H> {
     ///
     /// This is primarily useful for testing with mock HTTP responses.
     pub fn with_http_client(http_client: H) -> Self {
-        Self { http_client }
+        Self { http_client, model: None }
+    }
+
+    /// Uses `model` instead of the configured backend's model.
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
     }
 
     /// Generates a command from a natural language description.
@@ -238,21 +968,17 @@ impl<H: HttpClient> LlmGenerator<H> {
     /// # Errors
     ///
     /// Returns an error if:
-    /// - No API key is configured
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
     /// - The API request fails
     /// - The response cannot be parsed
     pub async fn generate_command_from_description(&self, description: &str) -> Result<GenerationResult> {
         info!("Generating command from description: {}", description);
 
         let config = crate::config::Config::load()?;
-
-        if let Some(api_key) = config.get_api_key() {
-            info!("Using Claude API for conversational command generation");
-            let prompt = self.build_unified_prompt(description, None);
-            self.call_claude_api_with_prompt(&prompt, api_key).await
-        } else {
-            Err(Self::api_key_missing_error())
-        }
+        let endpoint = self.endpoint(&config)?;
+        info!("Using {} for conversational command generation", endpoint);
+        let prompt = self.build_unified_prompt(description, None);
+        self.call_claude_api_with_prompt(&prompt, &endpoint, None, false).await
     }
 
     /// Regenerates a command with user feedback.
@@ -262,208 +988,1134 @@ impl<H: HttpClient> LlmGenerator<H> {
     ///
     /// # Arguments
     ///
-    /// * `command_name` - The name of the command to regenerate
-    /// * `original_script` - The original script that was executed
-    /// * `stderr` - Standard error output from the failed execution (if any)
-    /// * `user_feedback` - User's feedback about what went wrong
+    /// * `request` - The command, its original script, and the feedback context
     ///
     /// # Errors
     ///
     /// Returns an error if:
-    /// - No API key is configured
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
     /// - The API request fails
     /// - The response cannot be parsed
     pub async fn regenerate_command_with_feedback(
         &self,
-        command_name: &str,
-        original_script: &str,
-        stderr: Option<&str>,
-        user_feedback: &str,
+        request: &FeedbackRequest<'_>,
     ) -> Result<GenerationResult> {
-        info!("Regenerating command '{}' with feedback: {}", command_name, user_feedback);
+        info!(
+            "Regenerating command '{}' with feedback: {}",
+            request.command_name, request.user_feedback
+        );
 
         let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        info!("Using {} for command regeneration", endpoint);
+        let prompt = self.build_feedback_prompt(request);
+        // Keep the original command name
+        self.call_claude_api_with_prompt(&prompt, &endpoint, Some(request.command_name), true)
+            .await
+    }
 
-        if let Some(api_key) = config.get_api_key() {
-            info!("Using Claude API for command regeneration");
-            let prompt = self.build_feedback_prompt(command_name, original_script, stderr, user_feedback);
-            let mut result = self.call_claude_api_with_prompt(&prompt, api_key).await?;
-            // Keep the original command name
-            result.command.name = command_name.to_string();
-            result.command.script_file = format!("{}.ts", command_name);
-            Ok(result)
-        } else {
-            Err(Self::api_key_missing_error())
-        }
+    /// Explains what a cached script does, line by line.
+    ///
+    /// Used by `ergo --explain` to review a command before trusting it, for
+    /// example one imported from someone else's bioma.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The API request fails
+    /// - The response cannot be parsed
+    pub async fn explain_script(&self, command_name: &str, script: &str) -> Result<ScriptExplanation> {
+        info!("Explaining command '{}'", command_name);
+
+        let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        let prompt = self.build_explain_prompt(command_name, script);
+        let content = self.send_prompt(&prompt, &endpoint, None).await?;
+        Self::parse_explanation(&content)
     }
 
-    // -------------------------------------------------------------------------
-    // Internal helpers
-    // -------------------------------------------------------------------------
+    /// Asks the model why a shell command line failed.
+    ///
+    /// Used by `ergo --why`. Credentials in the error output are redacted
+    /// before it is sent, and only its last few kilobytes are included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The API request fails
+    /// - The response cannot be parsed
+    pub async fn diagnose_failure(&self, command_line: &str, error_output: &str) -> Result<FailureDiagnosis> {
+        info!("Diagnosing failure of: {}", command_line);
 
-    async fn generate_command_impl(&self, command_name: &str, args: &[String]) -> Result<GenerationResult> {
         let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        let prompt = self.build_diagnosis_prompt(command_line, error_output);
+        let content = self.send_prompt(&prompt, &endpoint, None).await?;
+        Self::parse_diagnosis(&content)
+    }
 
-        if let Some(api_key) = config.get_api_key() {
-            info!("Using Claude API for command generation");
-            let prompt = self.build_unified_prompt(command_name, Some(args));
-            let mut result = self.call_claude_api_with_prompt(&prompt, api_key).await?;
-            // Override Claude's suggested name with the user's specified name
-            result.command.name = command_name.to_string();
-            result.command.script_file = format!("{}.ts", command_name);
-            Ok(result)
+    /// Sends the prompt recorded in `provenance` again.
+    ///
+    /// Used by `ergo --regenerate`. With `same_model`, the prompt goes to the
+    /// recorded backend and model with the recorded settings; otherwise to the
+    /// configured backend with the current defaults. The prompt is retargeted
+    /// at the host platform if it was generated for another one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The recorded backend is not built in (with `same_model`)
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The API request fails
+    /// - The response cannot be parsed
+    pub async fn regenerate_from_provenance(
+        &self,
+        command_name: &str,
+        provenance: &Provenance,
+        same_model: bool,
+    ) -> Result<GenerationResult> {
+        let config = crate::config::Config::load()?;
+        let mut prompt = provenance.prompt_for(std::env::consts::OS, std::env::consts::ARCH);
+        let endpoint = if same_model {
+            Self::recorded_endpoint(&config, provenance)?
         } else {
-            Err(Self::api_key_missing_error())
-        }
+            prompt.max_tokens = MAX_TOKENS;
+            self.endpoint(&config)?
+        };
+        info!("Regenerating '{}' from provenance with {}", command_name, endpoint);
+        self.call_claude_api_with_prompt(&prompt, &endpoint, Some(command_name), true)
+            .await
     }
 
-    fn api_key_missing_error() -> anyhow::Error {
-        anyhow!(
-            "No Anthropic API key found. Please set it using one of these methods:\n\
-            \n\
-            1. Set API key in config:\n\
-               ergo --set-api-key sk-ant-your-key-here\n\
-            \n\
-            2. Set environment variable:\n\
-               export ANTHROPIC_API_KEY=sk-ant-your-key-here\n\
-            \n\
-            3. Check current config:\n\
-               ergo --config\n\
-            \n\
-            Get your API key from: https://console.anthropic.com"
+    /// Sends the request recorded in `provenance` with the current prompt
+    /// template to the configured backend and model.
+    ///
+    /// Used by `ergo --regenerate-all` after a model or prompt upgrade. A
+    /// command refined with `--nope` has a whole conversation recorded,
+    /// which is replayed as it is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The API request fails
+    /// - The response cannot be parsed
+    pub async fn regenerate_with_current_prompt(
+        &self,
+        command_name: &str,
+        provenance: &Provenance,
+    ) -> Result<GenerationResult> {
+        let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        let prompt = self.current_prompt_for(provenance);
+        info!("Regenerating '{}' with the current prompt and {}", command_name, endpoint);
+        self.call_claude_api_with_prompt(&prompt, &endpoint, Some(command_name), true)
+            .await
+    }
+
+    /// The prompt `provenance` would be sent with today: a single request
+    /// gets the current system prompt, a conversation keeps the recorded one.
+    fn current_prompt_for(&self, provenance: &Provenance) -> Prompt {
+        let prompt = provenance.prompt_for(std::env::consts::OS, std::env::consts::ARCH);
+        match prompt.messages.as_slice() {
+            [request] if request.role == Role::User => self.build_unified_prompt(&request.content, None),
+            _ => Prompt {
+                max_tokens: MAX_TOKENS,
+                ..prompt
+            },
+        }
+    }
+
+    /// Asks the model for useful commands to generate for a project.
+    ///
+    /// Used by `ergo suggest`. `project_summary` comes from
+    /// [`crate::suggest::ProjectContext::summary`]; commands named in
+    /// `existing` are already cached and shouldn't be suggested again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The API request fails
+    /// - The response cannot be parsed
+    pub async fn suggest_commands(&self, project_summary: &str, existing: &[String]) -> Result<Vec<CommandSuggestion>> {
+        info!("Suggesting commands for the current project");
+
+        let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        let prompt = self.build_suggestion_prompt(project_summary, existing);
+        let content = self.send_prompt(&prompt, &endpoint, None).await?;
+        Self::parse_suggestions(&content)
+    }
+
+    /// Asks the model to audit a cached script for risky patterns.
+    ///
+    /// Used by `ergo --review`, which combines the result with the static
+    /// analyzer's findings (see [`crate::security_review`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The API request fails
+    /// - The response cannot be parsed
+    pub async fn review_script(
+        &self,
+        command_name: &str,
+        script: &str,
+        permissions: &[PermissionRequest],
+    ) -> Result<ModelReview> {
+        info!("Reviewing command '{}'", command_name);
+
+        let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        let prompt = self.build_review_prompt(command_name, script, permissions);
+        let content = self.send_prompt(&prompt, &endpoint, None).await?;
+        Self::parse_review(&content)
+    }
+
+    /// Asks the model to sum up how a command changed across its revisions.
+    ///
+    /// Used by `ergo --changelog --summarize`. `changelog` is the rendered
+    /// [`crate::changelog::Changelog`], newest revision first; only its
+    /// first few kilobytes are sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The API request fails
+    pub async fn summarize_changelog(&self, command_name: &str, changelog: &str) -> Result<String> {
+        info!("Summarizing the changelog of '{}'", command_name);
+
+        let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        let prompt = self.build_changelog_prompt(command_name, changelog);
+        let content = self.send_prompt(&prompt, &endpoint, None).await?;
+        Ok(content.trim().to_string())
+    }
+
+    /// Asks the model how it would implement `request`, without the script.
+    ///
+    /// The first half of `ergo --plan`; [`Self::generate_from_plan`] is the
+    /// second.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The API request fails
+    /// - The response cannot be parsed
+    pub async fn plan_command(&self, request: PlanRequest<'_>) -> Result<GenerationPlan> {
+        info!("Planning command for: {}", request);
+
+        let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        let prompt = self.build_plan_prompt(request);
+        let content = self.send_prompt(&prompt, &endpoint, None).await?;
+        Self::parse_plan(&content)
+    }
+
+    /// Generates the command for `request` following the approved `plan`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The API request fails
+    /// - The response cannot be parsed
+    pub async fn generate_from_plan(&self, request: PlanRequest<'_>, plan: &GenerationPlan) -> Result<GenerationResult> {
+        info!("Generating command for '{}' from its plan", request);
+
+        let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        let prompt = self.build_planned_prompt(request, plan);
+        self.call_claude_api_with_prompt(&prompt, &endpoint, request.command_name(), false)
+            .await
+    }
+
+    /// Lists the models the configured backend offers, marking the one in use.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No API key (or, for Bedrock, no AWS credentials) is configured
+    /// - The backend's API doesn't list models (Gemini on Vertex AI)
+    /// - The API request fails
+    pub async fn list_models(&self) -> Result<ModelList> {
+        let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        self.list_models_for(&endpoint).await
+    }
+
+    // -------------------------------------------------------------------------
+    // Internal helpers
+    // -------------------------------------------------------------------------
+
+    async fn generate_command_impl(&self, command_name: &str, args: &[String]) -> Result<GenerationResult> {
+        let config = crate::config::Config::load()?;
+        let endpoint = self.endpoint(&config)?;
+        info!("Using {} for command generation", endpoint);
+        let prompt = self.build_unified_prompt(command_name, Some(args));
+        // Override Claude's suggested name with the user's specified name
+        self.call_claude_api_with_prompt(&prompt, &endpoint, Some(command_name), false)
+            .await
+    }
+
+    /// The configured endpoint, with the model chosen with `--model`, if any.
+    fn endpoint(&self, config: &crate::config::Config) -> Result<ApiEndpoint> {
+        let Some(model) = &self.model else {
+            return Self::api_endpoint(config);
+        };
+        let mut config = config.clone();
+        config.generator.bedrock.model = model.clone();
+        config.generator.gemini.model = model.clone();
+        Ok(match Self::api_endpoint(&config)? {
+            ApiEndpoint::Anthropic { api_key, .. } => ApiEndpoint::Anthropic {
+                api_key,
+                model: model.clone(),
+            },
+            endpoint => endpoint,
+        })
+    }
+
+    /// Chooses where prompts go: Bedrock or Gemini if `generator.backend`
+    /// names them, the Anthropic API otherwise.
+    fn api_endpoint(config: &crate::config::Config) -> Result<ApiEndpoint> {
+        if config.generator.backend == BEDROCK_BACKEND {
+            return Ok(ApiEndpoint::Bedrock(BedrockTarget::resolve(&config.generator.bedrock)?));
+        }
+        if config.generator.backend == GEMINI_BACKEND {
+            return Ok(ApiEndpoint::Gemini(GeminiTarget::resolve(
+                &config.generator.gemini,
+                |name| std::env::var(name).ok(),
+                &SystemProcessRunner::default(),
+            )?));
+        }
+        match config.get_api_key() {
+            Some(api_key) => Ok(ApiEndpoint::Anthropic {
+                api_key: api_key.clone(),
+                model: ANTHROPIC_MODEL.to_string(),
+            }),
+            None => Err(Self::api_key_missing_error()),
+        }
+    }
+
+    /// Chooses the backend and model recorded in `provenance`, keeping the
+    /// credentials and region from `config`.
+    fn recorded_endpoint(config: &crate::config::Config, provenance: &Provenance) -> Result<ApiEndpoint> {
+        let mut config = config.clone();
+        match provenance.backend.as_str() {
+            BUILTIN_BACKEND => {}
+            BEDROCK_BACKEND => config.generator.bedrock.model = provenance.model.clone(),
+            GEMINI_BACKEND => config.generator.gemini.model = provenance.model.clone(),
+            other => return Err(anyhow!("Commands generated by '{}' can't be regenerated with the same model", other)),
+        }
+        config.generator.backend = provenance.backend.clone();
+        Ok(match Self::api_endpoint(&config)? {
+            ApiEndpoint::Anthropic { api_key, .. } => ApiEndpoint::Anthropic {
+                api_key,
+                model: provenance.model.clone(),
+            },
+            endpoint => endpoint,
+        })
+    }
+
+    fn api_key_missing_error() -> anyhow::Error {
+        anyhow!(
+            "No Anthropic API key found. Please set it using one of these methods:\n\
+            \n\
+            1. Set API key in config:\n\
+               ergo --set-api-key sk-ant-your-key-here\n\
+            \n\
+            2. Set environment variable:\n\
+               export ANTHROPIC_API_KEY=sk-ant-your-key-here\n\
+            \n\
+            3. Check current config:\n\
+               ergo --config\n\
+            \n\
+            Get your API key from: https://console.anthropic.com"
         )
     }
 
-    fn build_unified_prompt(&self, request: &str, args: Option<&[String]>) -> String {
+    /// Builds the generation prompt. The system prompt doesn't depend on the
+    /// request, so Anthropic can serve it from its prompt cache.
+    fn build_unified_prompt(&self, request: &str, args: Option<&[String]>) -> Prompt {
         use prompt_sections::*;
 
-        let request_description = if let Some(args) = args {
-            format!("Create a command named '{}' that handles arguments {:?}", request, args)
-        } else {
-            request.to_string()
-        };
+        let request_description = Self::request_description(match args {
+            Some(args) => PlanRequest::Named { name: request, args },
+            None => PlanRequest::Described(request),
+        });
 
-        PromptBuilder::new()
+        let system = PromptBuilder::new()
             .section(JSON_PREAMBLE)
-            .context("Based on this request", &request_description)
-            .section("Create a Deno/TypeScript command and suggest a short, descriptive command name.")
+            .section("Create a Deno/TypeScript command for the user's request and suggest a short, descriptive command name.")
+            .section(&host_platform(std::env::consts::OS, std::env::consts::ARCH))
             .section(RESPONSE_SCHEMA)
             .rules(&[
                 "- Choose a clear, short command name (2-3 words max, kebab-case)",
                 QUALITY_RULES,
                 DENO_RULES,
+                PLATFORM_RULES,
                 PERMISSION_RULES,
+                SENSITIVE_OUTPUT_RULES,
+                OUTPUT_FORMAT_RULES,
+                EXAMPLE_RULES,
+                ASSUMPTION_RULES,
                 JSON_ONLY_REMINDER,
             ])
-            .build()
+            .build();
+
+        Prompt::new(system).cached().user(request_description)
     }
 
-    fn build_feedback_prompt(
-        &self,
-        command_name: &str,
-        original_script: &str,
-        stderr: Option<&str>,
-        user_feedback: &str,
-    ) -> String {
+    /// Builds the refinement prompt as a conversation: the original request,
+    /// the script the model answered with, and the user's feedback on it.
+    fn build_feedback_prompt(&self, request: &FeedbackRequest<'_>) -> Prompt {
         use prompt_sections::*;
 
-        let keep_name_rule = format!("- Keep the same command name: '{}'", command_name);
-
-        PromptBuilder::new()
+        let system = PromptBuilder::new()
             .section(JSON_PREAMBLE)
-            .section(&format!(
-                "I need you to improve an existing command called '{}' based on user feedback.",
-                command_name
-            ))
-            .code_block("ORIGINAL SCRIPT", original_script)
-            .optional_code_block("ERROR OUTPUT FROM EXECUTION", stderr)
-            .context("USER FEEDBACK", user_feedback)
-            .section("Please create an improved version that addresses the feedback.")
+            .section("Improve the Deno/TypeScript command you generated earlier in this conversation based on the user's feedback.")
+            .section(&host_platform(std::env::consts::OS, std::env::consts::ARCH))
             .section(RESPONSE_SCHEMA)
             .rules(&[
-                &keep_name_rule,
                 "- Address the user's feedback in your improved implementation",
                 QUALITY_RULES,
                 DENO_RULES,
+                PLATFORM_RULES,
+                PERMISSION_RULES,
+                SENSITIVE_OUTPUT_RULES,
+                OUTPUT_FORMAT_RULES,
+                EXAMPLE_RULES,
+                ASSUMPTION_RULES,
+                JSON_ONLY_REMINDER,
+            ])
+            .build();
+
+        let original = PromptBuilder::new()
+            .code_block("ORIGINAL SCRIPT", request.original_script)
+            .build();
+
+        let mut feedback = PromptBuilder::new();
+        if let Some(exit_status) = request.exit_status {
+            feedback = feedback.section(&format!("EXECUTION RESULT: the script {}", exit_status));
+        }
+        let mut feedback = feedback.optional_code_block("ERROR OUTPUT FROM EXECUTION", request.stderr);
+        if let Some(tests) = request.test_script {
+            feedback = feedback.code_block(
+                "EXISTING TESTS (run with `deno test`, the script path is passed as Deno.args[0])",
+                tests,
+            );
+        }
+        let history = Self::format_feedback_history(request.history);
+        if !history.is_empty() {
+            feedback = feedback.code_block("EARLIER FEEDBACK ON THIS COMMAND (oldest first)", &history);
+        }
+        let mut rules = vec![format!("- Keep the same command name: '{}'", request.command_name)];
+        if request.test_script.is_some() {
+            rules.push("- The improved script MUST keep passing the existing tests shown above".to_string());
+        }
+        if !history.is_empty() {
+            rules.push("- Keep honoring the earlier feedback; don't reintroduce behavior it rejected".to_string());
+        }
+        let rules: Vec<&str> = rules.iter().map(String::as_str).collect();
+        let feedback = feedback
+            .context("USER FEEDBACK", request.user_feedback)
+            .section("Please create an improved version that addresses the feedback.")
+            .rules(&rules)
+            .build();
+
+        let original_request = match request.intent {
+            Some(intent) => format!("Create a command named '{}' for this request:\n{}", request.command_name, intent),
+            None => format!("Create a command named '{}'.", request.command_name),
+        };
+        Prompt::new(system)
+            .user(original_request)
+            .assistant(original)
+            .user(feedback)
+    }
+
+    /// Formats the most recent feedback rounds, one per paragraph, with long
+    /// feedback and error output cut short.
+    fn format_feedback_history(history: &[FeedbackTurn]) -> String {
+        let truncate = |text: &str| -> String {
+            if text.chars().count() > MAX_FEEDBACK_TURN_CHARS {
+                format!("{}…", text.chars().take(MAX_FEEDBACK_TURN_CHARS).collect::<String>())
+            } else {
+                text.to_string()
+            }
+        };
+        let skip = history.len().saturating_sub(MAX_FEEDBACK_HISTORY_TURNS);
+        history[skip..]
+            .iter()
+            .map(|turn| {
+                let feedback = if turn.feedback.is_empty() {
+                    "(no feedback text, fix the error)".to_string()
+                } else {
+                    truncate(&turn.feedback)
+                };
+                let mut entry = format!("Revision {}: {}", turn.revision, feedback);
+                if let Some(stderr) = &turn.stderr {
+                    entry.push_str(&format!("\n  error: {}", truncate(stderr.trim())));
+                }
+                entry
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn build_explain_prompt(&self, command_name: &str, script: &str) -> Prompt {
+        use prompt_sections::*;
+
+        let numbered: String = script
+            .lines()
+            .enumerate()
+            .map(|(i, line)| format!("{:>4} | {}\n", i + 1, line))
+            .collect();
+
+        let system = PromptBuilder::new()
+            .section(JSON_PREAMBLE)
+            .section(
+                "Explain in plain English what the user's Deno/TypeScript command does. \
+                 The script is shown with line numbers.",
+            )
+            .section(EXPLANATION_SCHEMA)
+            .rules(&[
+                "- Explain every meaningful line; skip blank lines and lone braces",
+                "- Use the line numbers shown in the script",
+                "- List every file, directory and host the script may touch, even if it only happens conditionally",
+                "- Put environment variables, subprocesses and other side effects in \"other\"",
+                "- Write for someone who can't read TypeScript",
+                JSON_ONLY_REMINDER,
+            ])
+            .build();
+
+        Prompt::new(system).user(
+            PromptBuilder::new()
+                .section(&format!("Command name: '{}'", command_name))
+                .code_block("SCRIPT", numbered.trim_end())
+                .build(),
+        )
+    }
+
+    fn build_diagnosis_prompt(&self, command_line: &str, error_output: &str) -> Prompt {
+        use prompt_sections::*;
+
+        let redacted = crate::diagnostics::redact_secrets(error_output);
+        let skip = redacted.chars().count().saturating_sub(MAX_DIAGNOSIS_OUTPUT_CHARS);
+        let tail: String = redacted.chars().skip(skip).collect();
+
+        let system = PromptBuilder::new()
+            .section(JSON_PREAMBLE)
+            .section("The user's shell command failed. Explain why, then suggest how to get what the user wanted.")
+            .section(DIAGNOSIS_SCHEMA)
+            .rules(&[
+                "- Set \"fixed_command\" when correcting the command line (flags, typos, quoting, missing tools) is enough",
+                "- Otherwise, set \"ergo_intent\" to a one-sentence description of a Deno command that would do what the user wanted",
+                "- Set the other field to null; never suggest destructive commands the user didn't ask for",
+                "- Write the explanation for someone who doesn't know the tool",
+                JSON_ONLY_REMINDER,
+            ])
+            .build();
+
+        Prompt::new(system).user(
+            PromptBuilder::new()
+                .code_block("COMMAND LINE", command_line)
+                .code_block("ERROR OUTPUT", tail.trim_end())
+                .build(),
+        )
+    }
+
+    fn build_suggestion_prompt(&self, project_summary: &str, existing: &[String]) -> Prompt {
+        use prompt_sections::*;
+
+        let system = PromptBuilder::new()
+            .section(JSON_PREAMBLE)
+            .section(
+                "Suggest small command line tools that would save a developer time in the project described below. \
+                 Each tool will be generated as a Deno/TypeScript script.",
+            )
+            .section(SUGGESTION_SCHEMA)
+            .rules(&[
+                &format!("- Suggest between 3 and {} commands, most useful first", MAX_SUGGESTIONS),
+                "- Don't duplicate the project's existing scripts or the commands already generated; build on them instead",
+                "- Make each intent specific enough to generate the command from it alone",
+                "- Never suggest destructive commands",
+                JSON_ONLY_REMINDER,
+            ])
+            .build();
+
+        let existing = if existing.is_empty() {
+            "(none)".to_string()
+        } else {
+            existing.join(", ")
+        };
+        Prompt::new(system).user(
+            PromptBuilder::new()
+                .optional_code_block("PROJECT", Some(project_summary))
+                .section(&format!("Commands already generated: {}", existing))
+                .build(),
+        )
+    }
+
+    fn build_changelog_prompt(&self, command_name: &str, changelog: &str) -> Prompt {
+        let head: String = changelog.chars().take(MAX_CHANGELOG_CHARS).collect();
+
+        let system = PromptBuilder::new()
+            .section(
+                "Summarize how a command line tool changed across its revisions, for a user whose copy \
+                 suddenly behaves differently. The changelog lists each revision newest first, with the \
+                 feedback that led to it, its permission changes and a diff of its Deno/TypeScript script.",
+            )
+            .rules(&[
+                "- Answer in plain text: at most 6 lines, each starting with \"- \", newest changes first",
+                "- Describe behavior the user would notice (output, arguments, files, network), not code",
+                "- Mention permission changes and anything that could break existing uses",
+            ])
+            .build();
+
+        Prompt::new(system).user(
+            PromptBuilder::new()
+                .section(&format!("Command name: '{}'", command_name))
+                .code_block("CHANGELOG", head.trim_end())
+                .build(),
+        )
+    }
+
+    fn build_plan_prompt(&self, request: PlanRequest<'_>) -> Prompt {
+        use prompt_sections::*;
+
+        let system = PromptBuilder::new()
+            .section(JSON_PREAMBLE)
+            .section(
+                "Plan a Deno/TypeScript command for the user's request. Don't write the script yet: \
+                 the user reviews the plan first.",
+            )
+            .section(&host_platform(std::env::consts::OS, std::env::consts::ARCH))
+            .section(PLAN_SCHEMA)
+            .rules(&[
+                "- List the steps the script will take, in order, each in one short sentence",
                 PERMISSION_RULES,
+                "- List in \"edge_cases\" the unusual inputs and failures the script should handle",
                 JSON_ONLY_REMINDER,
             ])
-            .build()
+            .build();
+
+        Prompt::new(system).user(Self::request_description(request))
+    }
+
+    /// Builds the generation prompt for `request` with the approved `plan`
+    /// after the request.
+    fn build_planned_prompt(&self, request: PlanRequest<'_>, plan: &GenerationPlan) -> Prompt {
+        let mut prompt = match request {
+            PlanRequest::Named { name, args } => self.build_unified_prompt(name, Some(args)),
+            PlanRequest::Described(description) => self.build_unified_prompt(description, None),
+        };
+        if let Some(message) = prompt.messages.last_mut() {
+            message.content = PromptBuilder::new()
+                .section(&message.content)
+                .code_block("APPROVED PLAN (follow it; the user's changes take precedence)", &plan.to_string())
+                .build();
+        }
+        prompt
+    }
+
+    /// The user message asking for `request`, as in [`Self::build_unified_prompt`].
+    fn request_description(request: PlanRequest<'_>) -> String {
+        match request {
+            PlanRequest::Named { name, args } => {
+                format!("Create a command named '{}' that handles arguments {:?}", name, args)
+            }
+            PlanRequest::Described(description) => description.to_string(),
+        }
+    }
+
+    fn build_review_prompt(&self, command_name: &str, script: &str, permissions: &[PermissionRequest]) -> Prompt {
+        use prompt_sections::*;
+
+        let numbered: String = script
+            .lines()
+            .enumerate()
+            .map(|(i, line)| format!("{:>4} | {}\n", i + 1, line))
+            .collect();
+        let declared = if permissions.is_empty() {
+            "(none)".to_string()
+        } else {
+            permissions
+                .iter()
+                .map(|p| format!("{} ({})", p.permission, p.reason))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let system = PromptBuilder::new()
+            .section(JSON_PREAMBLE)
+            .section(AUDIT_INSTRUCTIONS)
+            .section(REVIEW_SCHEMA)
+            .rules(&[
+                "- severity must be one of: low, medium, high",
+                "- Use the line numbers shown in the script; omit \"line\" for findings about the script as a whole",
+                "- Report only real risks; return an empty findings list for a harmless script",
+                JSON_ONLY_REMINDER,
+            ])
+            .build();
+
+        Prompt::new(system).user(
+            PromptBuilder::new()
+                .section(&format!("Command name: '{}'", command_name))
+                .code_block("SCRIPT (with line numbers)", numbered.trim_end())
+                .optional_code_block("DECLARED DENO PERMISSIONS", Some(&declared))
+                .build(),
+        )
+    }
+
+    /// Sends a generation prompt and parses the command it answers with,
+    /// naming it `command_name` if given.
+    ///
+    /// The call's tokens are attributed to the command in the usage ledger,
+    /// even if its answer can't be parsed.
+    async fn call_claude_api_with_prompt(
+        &self,
+        prompt: &Prompt,
+        endpoint: &ApiEndpoint,
+        command_name: Option<&str>,
+        regeneration: bool,
+    ) -> Result<GenerationResult> {
+        let reply = self
+            .send_prompt_unrecorded(prompt, endpoint, Some(gemini::command_schema()))
+            .await?;
+        let result = reply.content.and_then(|content| parse_command_json(&content));
+        let name = command_name.or_else(|| result.as_ref().ok().map(|r| r.command.name.as_str()));
+        Self::record_usage(endpoint.model(), reply.usage, name, regeneration);
+
+        let mut result = result?;
+        if let Some(name) = command_name {
+            result.command.name = name.to_string();
+            result.command.script_file = format!("{}.ts", name);
+        }
+        result.command.provenance = Some(Provenance {
+            backend: endpoint.backend().to_string(),
+            model: endpoint.model().to_string(),
+            max_tokens: prompt.max_tokens,
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            system: prompt.system.clone(),
+            messages: prompt.messages.clone(),
+            generated_at: SystemTimeProvider.now(),
+            model_chosen: self.model.is_some(),
+        });
+        Ok(result)
+    }
+
+    /// Sends `prompt` and returns the model's text answer, recording its
+    /// token usage in the [`crate::usage_ledger`].
+    ///
+    /// `schema` constrains the answer on backends with structured output (Gemini).
+    async fn send_prompt(&self, prompt: &Prompt, endpoint: &ApiEndpoint, schema: Option<serde_json::Value>) -> Result<String> {
+        let reply = self.send_prompt_unrecorded(prompt, endpoint, schema).await?;
+        Self::record_usage(endpoint.model(), reply.usage, None, false);
+        reply.content
+    }
+
+    /// Sends `prompt` and returns the model's reply without recording its
+    /// token usage, for callers that attribute it to a command.
+    async fn send_prompt_unrecorded(
+        &self,
+        prompt: &Prompt,
+        endpoint: &ApiEndpoint,
+        schema: Option<serde_json::Value>,
+    ) -> Result<Reply> {
+        let messages = prompt.messages_json();
+
+        let response_text = match endpoint {
+            ApiEndpoint::Anthropic { api_key, model } => {
+                let mut request_body = json!({
+                    "model": model,
+                    "max_tokens": prompt.max_tokens,
+                    "messages": messages
+                });
+                request_body["system"] = if prompt.cache_system {
+                    // Cache the invariant instructions; only the messages change between calls
+                    json!([
+                        {
+                            "type": "text",
+                            "text": prompt.system,
+                            "cache_control": {"type": "ephemeral"}
+                        }
+                    ])
+                } else {
+                    json!(prompt.system)
+                };
+
+                let headers = [
+                    ("x-api-key", api_key.as_str()),
+                    ("content-type", "application/json"),
+                    ("anthropic-version", ANTHROPIC_VERSION),
+                ];
+
+                let response = self.post(model, ANTHROPIC_MESSAGES_URL, &headers, &request_body).await?;
+                if !response.is_success() {
+                    return Err(self.anthropic_error(&response, model, &headers).await.into());
+                }
+                response.body
+            }
+            ApiEndpoint::Bedrock(target) => {
+                // Bedrock takes the model from the URL and the version from the body
+                let request_body = json!({
+                    "anthropic_version": BEDROCK_ANTHROPIC_VERSION,
+                    "max_tokens": prompt.max_tokens,
+                    "system": prompt.system,
+                    "messages": messages
+                });
+
+                // The signature covers the exact body bytes the HTTP client sends
+                let signed = target.signed_headers(&serde_json::to_vec(&request_body)?, SystemTimeProvider.now());
+                let headers: Vec<(&str, &str)> = signed.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+
+                self.post(endpoint.model(), &target.invoke_url(), &headers, &request_body).await?.body
+            }
+            ApiEndpoint::Gemini(target) => {
+                let request_body = gemini::request_body(&prompt.system, &prompt.messages, prompt.max_tokens, schema);
+                let response_text = self.post(endpoint.model(), &target.url, &target.headers(), &request_body).await?.body;
+
+                info!("Gemini API response: {}", response_text);
+                return Ok(Reply {
+                    usage: Self::parse_usage(&response_text, TokenUsage::from_gemini),
+                    content: gemini::extract_text(&response_text),
+                });
+            }
+        };
+
+        info!("Claude API response: {}", response_text);
+        Ok(Reply {
+            usage: Self::parse_usage(&response_text, TokenUsage::from_anthropic),
+            content: Self::extract_content(&response_text),
+        })
+    }
+
+    /// Posts a request to `model`'s API, recording its latency (see
+    /// [`crate::latency`]) and capturing the exchange when `--debug-llm` is
+    /// on (see [`crate::llm_debug`]).
+    async fn post(&self, model: &str, url: &str, headers: &[(&str, &str)], body: &serde_json::Value) -> Result<HttpResponse> {
+        let started = std::time::Instant::now();
+        let request = HttpRequest::post_json(url, body.clone()).headers(headers);
+        let response = self.http_client.send(&request).await;
+        if let Ok(response) = &response {
+            Self::record_latency(model, started.elapsed(), body, &response.body);
+        }
+        if let Some(dir) = llm_debug::capture_dir() {
+            let captured = response.as_ref().map(|r| r.body.clone()).map_err(|e| anyhow!("{}", e));
+            match llm_debug::record(dir, &llm_debug::Exchange::new(url, headers, body, &captured)) {
+                Ok(path) => {
+                    info!("Captured LLM exchange in {}", path.display());
+                    retention::prune(dir);
+                }
+                Err(e) => warn!("Failed to capture LLM exchange: {}", e),
+            }
+        }
+        response
+    }
+
+    /// The error of a failed Anthropic call, listing the models the API key
+    /// can use when the model wasn't found.
+    async fn anthropic_error(&self, response: &HttpResponse, model: &str, headers: &[(&str, &str)]) -> ApiError {
+        let mut error = ApiError::from_response(response, model);
+        if error.is_unknown_model() {
+            match self.anthropic_models(headers).await {
+                Ok(models) => error.available_models = models,
+                Err(e) => warn!("Failed to list Anthropic models: {}", e),
+            }
+        }
+        warn!(status = response.status, kind = ?error.kind, "Anthropic API error: {}", error.message);
+        error
+    }
+
+    /// The IDs of the models the API key in `headers` can use.
+    async fn anthropic_models(&self, headers: &[(&str, &str)]) -> Result<Vec<String>> {
+        let response = self.http_client.get(ANTHROPIC_MODELS_URL, headers).await?.error_for_status()?;
+        Ok(models::parse_anthropic(&response.json()?))
+    }
+
+    /// Lists the models `endpoint`'s credentials can use, if its API lists them.
+    async fn list_models_for(&self, endpoint: &ApiEndpoint) -> Result<ModelList> {
+        let (models, setting) = match endpoint {
+            ApiEndpoint::Anthropic { api_key, model } => {
+                let headers = [("x-api-key", api_key.as_str()), ("anthropic-version", ANTHROPIC_VERSION)];
+                let response = self.http_client.get(ANTHROPIC_MODELS_URL, &headers).await?;
+                if !response.is_success() {
+                    return Err(ApiError::from_response(&response, model).into());
+                }
+                (models::parse_anthropic(&response.json()?), None)
+            }
+            ApiEndpoint::Bedrock(target) => {
+                let signed = target.signed_models_headers(SystemTimeProvider.now());
+                let headers: Vec<(&str, &str)> = signed.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+                let response = self.http_client.get(&target.models_url(), &headers).await?.error_for_status()?;
+                (models::parse_bedrock(&response.json()?), Some("generator.bedrock.model"))
+            }
+            ApiEndpoint::Gemini(target) => {
+                let url = target
+                    .models_url()
+                    .ok_or_else(|| anyhow!("{} doesn't list models; see the Model Garden in the Cloud console", target.api))?;
+                let [auth, _] = target.headers();
+                let response = self.http_client.get(&url, &[auth]).await?.error_for_status()?;
+                (models::parse_gemini(&response.json()?), Some("generator.gemini.model"))
+            }
+        };
+        Ok(ModelList {
+            backend: endpoint.backend().to_string(),
+            selected: endpoint.model().to_string(),
+            models,
+            setting,
+        })
+    }
+
+    /// Appends a successful call's latency to the latency log. Failures are
+    /// only logged.
+    fn record_latency(model: &str, elapsed: std::time::Duration, body: &serde_json::Value, response_text: &str) {
+        let output_tokens = serde_json::from_str(response_text)
+            .ok()
+            .and_then(|response| TokenUsage::from_anthropic(&response).or_else(|| TokenUsage::from_gemini(&response)))
+            .map_or(0, |usage| usage.output_tokens);
+        let sample = LatencySample {
+            timestamp: SystemTimeProvider.now(),
+            kind: SampleKind::Api,
+            name: model.to_string(),
+            duration_ms: elapsed.as_millis() as u64,
+            request_bytes: body.to_string().len() as u64,
+            response_bytes: response_text.len() as u64,
+            output_tokens,
+        };
+        info!(
+            model,
+            duration_ms = sample.duration_ms,
+            request_bytes = sample.request_bytes,
+            response_bytes = sample.response_bytes,
+            output_tokens,
+            "Model API call"
+        );
+        if !crate::ephemeral::is_enabled()
+            && let Err(e) = latency::default_path().and_then(|path| {
+                latency::append(&path, &sample)?;
+                retention::prune(&path);
+                Ok(())
+            })
+        {
+            warn!("Failed to record API latency: {}", e);
+        }
+    }
+
+    /// Reads the token usage reported in a response, if any.
+    fn parse_usage(response_text: &str, usage: fn(&serde_json::Value) -> Option<TokenUsage>) -> Option<TokenUsage> {
+        serde_json::from_str(response_text).ok().as_ref().and_then(usage)
+    }
+
+    /// Appends a call's token usage to the ledger, attributed to `command`
+    /// if the call generated or regenerated one. Failures are only logged.
+    fn record_usage(model: &str, usage: Option<TokenUsage>, command: Option<&str>, regeneration: bool) {
+        let Some(usage) = usage else {
+            return;
+        };
+        info!("Token usage: {:?}", usage);
+        let entry = LedgerEntry {
+            timestamp: SystemTimeProvider.now(),
+            model: model.to_string(),
+            command: command.map(str::to_string),
+            regeneration,
+            usage,
+        };
+        if !crate::ephemeral::is_enabled()
+            && let Err(e) = usage_ledger::default_path().and_then(|path| {
+                usage_ledger::append(&path, &entry)?;
+                retention::prune(&path);
+                Ok(())
+            })
+        {
+            warn!("Failed to record token usage: {}", e);
+        }
+    }
+
+    /// Extracts the text content from a Claude API response.
+    fn extract_content(response_text: &str) -> Result<String> {
+        // Parse the outer Claude API response
+        let api_response: serde_json::Value = serde_json::from_str(response_text)
+            .map_err(|_| anyhow!("Failed to parse Claude response as JSON: {}", response_text))?;
+        if let Some(refusal) = ApiError::refusal(&api_response) {
+            return Err(refusal.into());
+        }
+
+        // Extract the text content from Claude's response
+        let content = api_response
+            .get("content")
+            .and_then(|c| c.as_array())
+            .and_then(|arr| arr.first())
+            .and_then(|item| item.get("text"))
+            .and_then(|text| text.as_str())
+            .ok_or_else(|| anyhow!("Failed to extract content from Claude response"))?;
+
+        info!("Extracted content from Claude: {}", content);
+
+        Ok(content.to_string())
+    }
+
+    /// Parses the Claude API response to a security review request.
+    #[cfg(test)]
+    fn parse_review_response(response_text: &str) -> Result<ModelReview> {
+        Self::parse_review(&Self::extract_content(response_text)?)
+    }
+
+    /// Parses the model's answer to a security review request.
+    fn parse_review(content: &str) -> Result<ModelReview> {
+        serde_json::from_str(content)
+            .map_err(|e| anyhow!("Failed to parse security review JSON: {}. Content: {}", e, content))
+    }
+
+    /// Parses the Claude API response to a failure diagnosis request.
+    #[cfg(test)]
+    fn parse_diagnosis_response(response_text: &str) -> Result<FailureDiagnosis> {
+        Self::parse_diagnosis(&Self::extract_content(response_text)?)
+    }
+
+    /// Parses the model's answer to a failure diagnosis request.
+    fn parse_diagnosis(content: &str) -> Result<FailureDiagnosis> {
+        let mut diagnosis: FailureDiagnosis = serde_json::from_str(content)
+            .map_err(|e| anyhow!("Failed to parse diagnosis JSON: {}. Content: {}", e, content))?;
+        // Treat blank suggestions as missing
+        diagnosis.fixed_command = diagnosis.fixed_command.filter(|c| !c.trim().is_empty());
+        diagnosis.ergo_intent = diagnosis.ergo_intent.filter(|i| !i.trim().is_empty());
+        Ok(diagnosis)
+    }
+
+    /// Parses the Claude API response to a suggestion request.
+    #[cfg(test)]
+    fn parse_suggestions_response(response_text: &str) -> Result<Vec<CommandSuggestion>> {
+        Self::parse_suggestions(&Self::extract_content(response_text)?)
     }
 
-    async fn call_claude_api_with_prompt(&self, prompt: &str, api_key: &str) -> Result<GenerationResult> {
-        let request_body = json!({
-            "model": "claude-3-haiku-20240307",
-            "max_tokens": 1500,
-            "messages": [
-                {
-                    "role": "user",
-                    "content": prompt
-                }
-            ]
-        });
+    /// Parses the model's answer to a suggestion request, dropping blank
+    /// intents and keeping at most [`MAX_SUGGESTIONS`].
+    fn parse_suggestions(content: &str) -> Result<Vec<CommandSuggestion>> {
+        #[derive(Deserialize)]
+        struct Suggestions {
+            suggestions: Vec<CommandSuggestion>,
+        }
 
-        let headers = [
-            ("x-api-key", api_key),
-            ("content-type", "application/json"),
-            ("anthropic-version", "2023-06-01"),
-        ];
+        let parsed: Suggestions = serde_json::from_str(content)
+            .map_err(|e| anyhow!("Failed to parse suggestions JSON: {}. Content: {}", e, content))?;
+        Ok(parsed
+            .suggestions
+            .into_iter()
+            .filter(|s| !s.intent.trim().is_empty())
+            .take(MAX_SUGGESTIONS)
+            .collect())
+    }
 
-        let response_text = self
-            .http_client
-            .post_json("https://api.anthropic.com/v1/messages", &headers, &request_body)
-            .await?;
+    /// Parses the model's answer to a plan request.
+    fn parse_plan(content: &str) -> Result<GenerationPlan> {
+        let plan: GenerationPlan = serde_json::from_str(content)
+            .map_err(|e| anyhow!("Failed to parse plan JSON: {}. Content: {}", e, content))?;
+        if plan.summary.trim().is_empty() && plan.steps.is_empty() {
+            return Err(anyhow!("The model answered with an empty plan"));
+        }
+        for permission in &plan.permissions {
+            permission.parsed()?;
+        }
+        Ok(plan)
+    }
 
-        info!("Claude API response: {}", response_text);
+    /// Parses the Claude API response to an explanation request.
+    #[cfg(test)]
+    fn parse_explanation_response(response_text: &str) -> Result<ScriptExplanation> {
+        Self::parse_explanation(&Self::extract_content(response_text)?)
+    }
 
-        Self::parse_claude_response(&response_text)
+    /// Parses the model's answer to an explanation request.
+    fn parse_explanation(content: &str) -> Result<ScriptExplanation> {
+        serde_json::from_str(content)
+            .map_err(|e| anyhow!("Failed to parse explanation JSON: {}. Content: {}", e, content))
     }
 
     /// Parses the Claude API response and extracts the generated command.
     ///
     /// This is separated from the HTTP call to enable unit testing of the
     /// parsing logic without making actual API requests.
+    #[cfg(test)]
     fn parse_claude_response(response_text: &str) -> Result<GenerationResult> {
-        // Internal struct for deserializing Claude's JSON response
-        #[derive(Debug, Deserialize)]
-        struct ClaudeCommandResponse {
-            name: String,
-            description: String,
-            script: String,
-            permissions: Vec<PermissionRequest>,
-        }
-
-        // Parse the outer Claude API response
-        let api_response: serde_json::Value = serde_json::from_str(response_text)
-            .map_err(|_| anyhow!("Failed to parse Claude response as JSON: {}", response_text))?;
-
-        // Extract the text content from Claude's response
-        let content = api_response
-            .get("content")
-            .and_then(|c| c.as_array())
-            .and_then(|arr| arr.first())
-            .and_then(|item| item.get("text"))
-            .and_then(|text| text.as_str())
-            .ok_or_else(|| anyhow!("Failed to extract content from Claude response"))?;
-
-        info!("Extracted content from Claude: {}", content);
-
-        // Parse the generated command JSON
-        let command_response: ClaudeCommandResponse = serde_json::from_str(content)
-            .map_err(|e| anyhow!("Failed to parse generated command JSON: {}. Content: {}", e, content))?;
+        let content = Self::extract_content(response_text)?;
+        parse_command_json(&content)
+    }
+}
 
-        info!("Successfully parsed Claude-generated command");
+/// Parses a generated command (name, description, script, permissions,
+/// sensitive_output, output_format and examples) from JSON, validating its permissions.
+///
+/// Shared by the Claude backend and generator plugins.
+pub fn parse_command_json(content: &str) -> Result<GenerationResult> {
+    // Internal struct for deserializing the generated command
+    #[derive(Debug, Deserialize)]
+    struct CommandResponse {
+        name: String,
+        description: String,
+        script: String,
+        permissions: Vec<PermissionRequest>,
+        #[serde(default)]
+        sensitive_output: bool,
+        #[serde(default)]
+        output_format: OutputFormat,
+        #[serde(default)]
+        examples: Vec<UsageExample>,
+        #[serde(default)]
+        assumptions: Vec<String>,
+        #[serde(default)]
+        confidence: Option<Confidence>,
+        #[serde(default)]
+        platforms: Vec<String>,
+    }
 
-        Ok(GenerationResult {
-            command: GeneratedCommand {
-                name: command_response.name.clone(),
-                description: command_response.description,
-                script_file: format!("{}.ts", command_response.name),
-                permissions: command_response.permissions,
-            },
-            script_content: command_response.script,
+    // Parse the generated command JSON
+    let command_response: CommandResponse = serde_json::from_str(content)
+        .map_err(|e| anyhow!("Failed to parse generated command JSON: {}. Content: {}", e, content))?;
+
+    // Reject unknown permissions and normalize the rest to canonical flags
+    let permissions = command_response
+        .permissions
+        .into_iter()
+        .map(|p| {
+            let parsed = p
+                .parsed()
+                .map_err(|e| anyhow!("Generated command requests an invalid permission: {}", e))?;
+            Ok(PermissionRequest {
+                permission: parsed.to_flag(),
+                reason: p.reason,
+            })
         })
-    }
+        .collect::<Result<Vec<_>>>()?;
+
+    info!("Successfully parsed generated command");
+
+    Ok(GenerationResult {
+        command: GeneratedCommand {
+            name: command_response.name.clone(),
+            description: command_response.description,
+            script_file: format!("{}.ts", command_response.name),
+            permissions,
+            sensitive_output: command_response.sensitive_output,
+            output_format: command_response.output_format,
+            examples: command_response.examples,
+            assumptions: command_response.assumptions,
+            confidence: command_response.confidence,
+            platforms: command_response.platforms,
+            variants: BTreeMap::new(),
+            provenance: None,
+            spec: None,
+            extra: BTreeMap::new(),
+        },
+        script_content: command_response.script,
+    })
 }
 
 #[async_trait]
@@ -472,11 +2124,46 @@ impl<H: HttpClient> CommandGenerator for LlmGenerator<H> {
         info!("Generating command for: {} with args: {:?}", command_name, args);
         self.generate_command_impl(command_name, args).await
     }
+
+    async fn generate_command_from_description(&self, description: &str) -> Result<GenerationResult> {
+        LlmGenerator::generate_command_from_description(self, description).await
+    }
+
+    async fn regenerate_command_with_feedback(&self, request: &FeedbackRequest<'_>) -> Result<GenerationResult> {
+        LlmGenerator::regenerate_command_with_feedback(self, request).await
+    }
+
+    async fn plan_command(&self, request: PlanRequest<'_>) -> Result<GenerationPlan> {
+        LlmGenerator::plan_command(self, request).await
+    }
+
+    async fn generate_from_plan(&self, request: PlanRequest<'_>, plan: &GenerationPlan) -> Result<GenerationResult> {
+        LlmGenerator::generate_from_plan(self, request, plan).await
+    }
+
+    fn set_model(&mut self, model: &str) -> Result<()> {
+        self.model = Some(model.to_string());
+        Ok(())
+    }
+
+    fn warm_up(&self) {
+        let Ok(config) = crate::config::Config::load() else {
+            return;
+        };
+        // Resolving Gemini credentials may run gcloud, which costs more than it saves
+        if config.generator.backend == GEMINI_BACKEND {
+            return;
+        }
+        if let Ok(endpoint) = self.endpoint(&config) {
+            self.http_client.warm_up(&endpoint.base_url());
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::MockHttpClient;
 
     // =========================================================================
     // Data type deserialization tests
@@ -525,6 +2212,400 @@ mod tests {
         assert_eq!(cmd.permissions[1].permission, "--allow-read");
     }
 
+    // =========================================================================
+    // Explanation tests
+    // =========================================================================
+
+    fn claude_response(text: &str) -> String {
+        json!({ "content": [{ "type": "text", "text": text }] }).to_string()
+    }
+
+    #[test]
+    fn test_parse_explanation_response() {
+        let response = claude_response(
+            r#"{
+                "summary": "Fetches the weather",
+                "lines": [{"line": 2, "explanation": "Calls the API"}],
+                "effects": {"hosts": ["wttr.in"]}
+            }"#,
+        );
+
+        let explanation = LlmGenerator::<MockHttpClient>::parse_explanation_response(&response).unwrap();
+
+        assert_eq!(explanation.summary, "Fetches the weather");
+        assert_eq!(explanation.lines[0].line, 2);
+        assert_eq!(explanation.effects.hosts, vec!["wttr.in"]);
+        assert!(explanation.effects.files.is_empty());
+    }
+
+    #[test]
+    fn test_parse_explanation_response_invalid_json() {
+        let response = claude_response("This script prints hello");
+
+        let result = LlmGenerator::<MockHttpClient>::parse_explanation_response(&response);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("explanation JSON"));
+    }
+
+    #[test]
+    fn test_parse_diagnosis_response() {
+        let response = claude_response(
+            r#"{
+                "explanation": "tar needs -f before the archive name",
+                "fixed_command": "tar -xzf backup.tgz",
+                "ergo_intent": ""
+            }"#,
+        );
+
+        let diagnosis = LlmGenerator::<MockHttpClient>::parse_diagnosis_response(&response).unwrap();
+
+        assert_eq!(diagnosis.fixed_command.as_deref(), Some("tar -xzf backup.tgz"));
+        assert!(diagnosis.ergo_intent.is_none());
+    }
+
+    #[test]
+    fn test_build_diagnosis_prompt_redacts_and_truncates_output() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let output = format!("{}\nerror: bad key sk-ant-abc123", "x".repeat(MAX_DIAGNOSIS_OUTPUT_CHARS));
+
+        let prompt = generator.build_diagnosis_prompt("deploy --key sk", &output);
+
+        assert!(prompt.joined().contains("deploy --key sk"));
+        assert!(prompt.joined().contains("error: bad key"));
+        assert!(!prompt.joined().contains("sk-ant-abc123"));
+        assert!(!prompt.joined().contains(&"x".repeat(MAX_DIAGNOSIS_OUTPUT_CHARS)));
+    }
+
+    #[test]
+    fn test_parse_suggestions_response() {
+        let suggestions: Vec<String> = (0..10)
+            .map(|i| format!(r#"{{"intent": "Command {}", "reason": "Useful"}}"#, i))
+            .collect();
+        let response = claude_response(&format!(
+            r#"{{"suggestions": [{{"intent": " ", "reason": "blank"}}, {}]}}"#,
+            suggestions.join(", ")
+        ));
+
+        let parsed = LlmGenerator::<MockHttpClient>::parse_suggestions_response(&response).unwrap();
+
+        assert_eq!(parsed.len(), MAX_SUGGESTIONS);
+        assert_eq!(parsed[0].intent, "Command 0");
+        assert!(LlmGenerator::<MockHttpClient>::parse_suggestions_response(&claude_response("Try a linter")).is_err());
+    }
+
+    #[test]
+    fn test_build_suggestion_prompt_includes_project_and_existing_commands() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+
+        let prompt = generator.build_suggestion_prompt("Languages: Rust", &["loc".to_string(), "todo-list".to_string()]);
+
+        assert!(prompt.joined().contains("Languages: Rust"));
+        assert!(prompt.joined().contains("Commands already generated: loc, todo-list"));
+        assert!(prompt.joined().contains("\"suggestions\""));
+    }
+
+    #[tokio::test]
+    async fn test_generation_records_provenance() {
+        let client = crate::testing::MockHttpClient::new().with_response(&claude_response(
+            r#"{"name": "count-lines", "description": "Counts lines", "script": "console.log(1)", "permissions": []}"#,
+        ));
+        let generator = LlmGenerator::with_http_client(client.clone());
+        let endpoint = ApiEndpoint::Anthropic {
+            api_key: "sk-ant-test".to_string(),
+            model: "claude-test-model".to_string(),
+        };
+        let prompt = generator.build_unified_prompt("count lines", None);
+
+        let result = generator
+            .call_claude_api_with_prompt(&prompt, &endpoint, None, false)
+            .await
+            .unwrap();
+
+        let provenance = result.command.provenance.unwrap();
+        assert_eq!(provenance.backend, BUILTIN_BACKEND);
+        assert_eq!(provenance.model, "claude-test-model");
+        assert_eq!(provenance.max_tokens, MAX_TOKENS);
+        assert_eq!(provenance.system, prompt.system);
+        assert_eq!(provenance.messages, prompt.messages);
+        assert_eq!(client.requests()[0].1["model"], "claude-test-model");
+        let sent = client.sent_to("api.anthropic.com");
+        assert_eq!(sent[0].header_value("x-api-key"), Some("sk-ant-test"));
+        assert_eq!(sent[0].header_value("anthropic-version"), Some("2023-06-01"));
+    }
+
+    #[tokio::test]
+    async fn test_generation_surfaces_request_errors() {
+        let client = crate::testing::MockHttpClient::new().on_error("api.anthropic.com", "connection reset");
+        let generator = LlmGenerator::with_http_client(client.clone());
+        let endpoint = ApiEndpoint::Anthropic {
+            api_key: "sk-ant-test".to_string(),
+            model: "claude-test-model".to_string(),
+        };
+        let prompt = generator.build_unified_prompt("count lines", None);
+
+        let result = generator.call_claude_api_with_prompt(&prompt, &endpoint, None, false).await;
+
+        assert!(format!("{:#}", result.unwrap_err()).contains("connection reset"));
+        assert_eq!(client.pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_list_models_marks_selected_model() {
+        let client = crate::testing::MockHttpClient::new()
+            .on("/v1/models", r#"{"data": [{"id": "claude-test-model"}, {"id": "claude-other"}]}"#);
+        let generator = LlmGenerator::with_http_client(client.clone());
+        let endpoint = ApiEndpoint::Anthropic {
+            api_key: "sk-ant-test".to_string(),
+            model: "claude-test-model".to_string(),
+        };
+
+        let list = generator.list_models_for(&endpoint).await.unwrap();
+
+        assert_eq!(list.backend, BUILTIN_BACKEND);
+        assert_eq!(list.models, vec!["claude-test-model", "claude-other"]);
+        assert!(list.to_string().contains(" ▶ claude-test-model\n   claude-other"));
+        let sent = client.sent();
+        assert_eq!(sent[0].method, crate::http_client::Method::Get);
+        assert_eq!(sent[0].header_value("anthropic-version"), Some(ANTHROPIC_VERSION));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_model_error_lists_available_models() {
+        let not_found = r#"{"type": "error", "error": {"type": "not_found_error", "message": "model: claude-gone"}}"#;
+        let client = crate::testing::MockHttpClient::new()
+            .on_response("/v1/messages", HttpResponse::new(404, not_found))
+            .on("/v1/models", r#"{"data": [{"id": "claude-a"}, {"id": "claude-b"}], "has_more": false}"#);
+        let generator = LlmGenerator::with_http_client(client.clone());
+        let endpoint = ApiEndpoint::Anthropic {
+            api_key: "sk-ant-test".to_string(),
+            model: "claude-gone".to_string(),
+        };
+        let prompt = generator.build_unified_prompt("count lines", None);
+
+        let error = generator
+            .call_claude_api_with_prompt(&prompt, &endpoint, None, false)
+            .await
+            .unwrap_err();
+
+        let error = error.downcast::<ApiError>().unwrap();
+        assert_eq!(error.status, Some(404));
+        assert_eq!(error.available_models, vec!["claude-a", "claude-b"]);
+        assert_eq!(client.sent_to("/v1/models")[0].header_value("x-api-key"), Some("sk-ant-test"));
+    }
+
+    #[test]
+    fn test_provenance_prompt_is_retargeted_at_host_platform() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let prompt = generator.build_unified_prompt("open a url", None);
+        let provenance = Provenance {
+            backend: BUILTIN_BACKEND.to_string(),
+            model: ANTHROPIC_MODEL.to_string(),
+            max_tokens: 900,
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            system: prompt.system.replace(
+                &host_platform(std::env::consts::OS, std::env::consts::ARCH),
+                &host_platform("linux", "x86_64"),
+            ),
+            messages: prompt.messages.clone(),
+            generated_at: 0,
+            model_chosen: false,
+        };
+
+        let same = provenance.prompt_for("linux", "x86_64");
+        assert_eq!(same.system, provenance.system);
+        assert_eq!(same.max_tokens, 900);
+
+        let retargeted = provenance.prompt_for("macos", "aarch64");
+        assert!(retargeted.system.contains("HOST PLATFORM: macos (aarch64)"));
+        assert!(!retargeted.system.contains("HOST PLATFORM: linux"));
+        assert_eq!(retargeted.messages, prompt.messages);
+    }
+
+    #[test]
+    fn test_current_prompt_replaces_outdated_system_prompt() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let current = generator.build_unified_prompt("open a url", None);
+        let mut provenance = Provenance {
+            backend: BUILTIN_BACKEND.to_string(),
+            model: "claude-older-model".to_string(),
+            max_tokens: 900,
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            system: "An older prompt".to_string(),
+            messages: current.messages.clone(),
+            generated_at: 0,
+            model_chosen: false,
+        };
+
+        let prompt = generator.current_prompt_for(&provenance);
+        assert_eq!(prompt.system, current.system);
+        assert_eq!(prompt.messages, current.messages);
+        assert_eq!(prompt.max_tokens, MAX_TOKENS);
+
+        // A --nope conversation only makes sense with the prompt it was held with
+        provenance.messages.push(Message {
+            role: Role::Assistant,
+            content: "{}".to_string(),
+        });
+        let replayed = generator.current_prompt_for(&provenance);
+        assert_eq!(replayed.system, "An older prompt");
+        assert_eq!(replayed.messages.len(), 2);
+        assert_eq!(replayed.max_tokens, MAX_TOKENS);
+    }
+
+    #[test]
+    fn test_recorded_endpoint_uses_recorded_model() {
+        let config = crate::config::Config {
+            anthropic_api_key: Some("sk-ant-test".to_string()),
+            ..Default::default()
+        };
+        let provenance = |backend: &str| Provenance {
+            backend: backend.to_string(),
+            model: "claude-older-model".to_string(),
+            max_tokens: MAX_TOKENS,
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            system: String::new(),
+            messages: Vec::new(),
+            generated_at: 0,
+            model_chosen: true,
+        };
+
+        let endpoint = LlmGenerator::<MockHttpClient>::recorded_endpoint(&config, &provenance(BUILTIN_BACKEND)).unwrap();
+        assert_eq!(endpoint.model(), "claude-older-model");
+        assert_eq!(provenance(BUILTIN_BACKEND).chosen_model(BUILTIN_BACKEND), Some("claude-older-model"));
+        assert_eq!(provenance(BUILTIN_BACKEND).chosen_model(BEDROCK_BACKEND), None);
+        assert_eq!(endpoint.backend(), BUILTIN_BACKEND);
+
+        let err = LlmGenerator::<MockHttpClient>::recorded_endpoint(&config, &provenance("gateway")).err().unwrap();
+        assert!(err.to_string().contains("'gateway'"));
+    }
+
+    #[test]
+    fn test_chosen_model_overrides_configured_model() {
+        let mut config = crate::config::Config {
+            anthropic_api_key: Some("sk-ant-test".to_string()),
+            ..Default::default()
+        };
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new()).with_model("claude-big-model");
+
+        assert_eq!(LlmGenerator::with_http_client(MockHttpClient::new()).endpoint(&config).unwrap().model(), ANTHROPIC_MODEL);
+        assert_eq!(generator.endpoint(&config).unwrap().model(), "claude-big-model");
+
+        config.generator.backend = GEMINI_BACKEND.to_string();
+        config.generator.gemini.api_key = Some("gemini-key".to_string());
+        let ApiEndpoint::Gemini(target) = generator.endpoint(&config).unwrap() else {
+            panic!("expected the Gemini endpoint");
+        };
+        assert!(target.url.contains("/models/claude-big-model:generateContent"));
+    }
+
+    #[test]
+    fn test_build_explain_prompt_numbers_lines() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+
+        let prompt = generator.build_explain_prompt("greet", "const a = 1;\nconsole.log(a);");
+
+        assert!(prompt.joined().contains("'greet'"));
+        assert!(prompt.joined().contains("   1 | const a = 1;"));
+        assert!(prompt.joined().contains("   2 | console.log(a);"));
+        assert!(prompt.joined().contains("\"effects\""));
+    }
+
+    #[test]
+    fn test_build_unified_prompt_keeps_instructions_invariant() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+
+        let weather = generator.build_unified_prompt("weather", Some(&["London".to_string()]));
+        let date = generator.build_unified_prompt("show today's date", None);
+
+        assert_eq!(weather.system, date.system);
+        assert!(weather.cache_system);
+        assert!(!weather.system.contains("weather"));
+        assert!(weather.system.contains("RULES:"));
+        assert_eq!(
+            date.messages,
+            vec![Message {
+                role: Role::User,
+                content: "show today's date".to_string(),
+            }]
+        );
+        assert!(weather.messages[0].content.starts_with("Create a command named 'weather'"));
+    }
+
+    #[test]
+    fn test_build_changelog_prompt_keeps_newest_revisions() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let changelog = format!("greet@3 newest\n{}greet@1 oldest", "x".repeat(MAX_CHANGELOG_CHARS));
+
+        let prompt = generator.build_changelog_prompt("greet", &changelog);
+
+        assert!(prompt.joined().contains("Command name: 'greet'"));
+        assert!(prompt.joined().contains("greet@3 newest"));
+        assert!(!prompt.joined().contains("greet@1 oldest"));
+    }
+
+    #[test]
+    fn test_plan_prompts_and_parsing() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let args = ["London".to_string()];
+        let request = PlanRequest::Named { name: "weather", args: &args };
+
+        let plan_prompt = generator.build_plan_prompt(request);
+        assert!(plan_prompt.system.contains("\"edge_cases\""));
+        assert!(plan_prompt.messages[0].content.starts_with("Create a command named 'weather'"));
+
+        let mut plan = LlmGenerator::<MockHttpClient>::parse_plan(
+            r#"{"summary": "Show the weather", "steps": ["Fetch the forecast"], "permissions": [{"permission": "--allow-net=wttr.in", "reason": "Fetch the forecast"}]}"#,
+        )
+        .unwrap();
+        assert!(plan.edge_cases.is_empty());
+        plan.changes.push("Use Celsius".to_string());
+
+        let prompt = generator.build_planned_prompt(request, &plan);
+        assert_eq!(prompt.system, generator.build_unified_prompt("weather", Some(&args)).system);
+        let message = &prompt.messages[0].content;
+        assert!(message.starts_with("Create a command named 'weather' that handles arguments [\"London\"]"));
+        assert!(message.contains("  - --allow-net=wttr.in: Fetch the forecast"));
+        assert!(message.contains("Changes requested by the user:\n  - Use Celsius"));
+
+        assert!(LlmGenerator::<MockHttpClient>::parse_plan(r#"{"summary": " "}"#).is_err());
+        assert!(LlmGenerator::<MockHttpClient>::parse_plan(
+            r#"{"summary": "x", "permissions": [{"permission": "--allow-all", "reason": "y"}]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_review_response() {
+        let response = claude_response(
+            r#"{"summary": "Deletes files", "findings": [{"severity": "high", "line": 3, "message": "Removes $HOME"}]}"#,
+        );
+
+        let review = LlmGenerator::<MockHttpClient>::parse_review_response(&response).unwrap();
+
+        assert_eq!(review.summary, "Deletes files");
+        assert_eq!(review.findings.len(), 1);
+        assert_eq!(review.findings[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_build_review_prompt_includes_permissions() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let permissions = [PermissionRequest {
+            permission: "--allow-net".to_string(),
+            reason: "Fetch data".to_string(),
+        }];
+
+        let prompt = generator.build_review_prompt("fetcher", "await fetch(url);", &permissions);
+
+        assert!(prompt.joined().contains("security auditor"));
+        assert!(prompt.joined().contains("   1 | await fetch(url);"));
+        assert!(prompt.joined().contains("--allow-net (Fetch data)"));
+    }
+
     // =========================================================================
     // Claude API response parsing tests
     // =========================================================================
@@ -540,7 +2621,7 @@ mod tests {
             ]
         }"#;
 
-        let result = LlmGenerator::<ReqwestHttpClient>::parse_claude_response(response).unwrap();
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(response).unwrap();
 
         assert_eq!(result.command.name, "hello");
         assert_eq!(result.command.description, "Greets the user");
@@ -549,6 +2630,113 @@ mod tests {
         assert!(result.command.permissions.is_empty());
     }
 
+    #[test]
+    fn test_parse_claude_response_with_examples() {
+        let response = claude_response(
+            r#"{"name": "weather", "description": "d", "script": "s", "permissions": [],
+                "examples": [{"usage": "ergo weather Paris", "explanation": "Weather in Paris"}]}"#,
+        );
+
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(&response).unwrap();
+
+        assert_eq!(result.command.examples.len(), 1);
+        assert_eq!(result.command.examples[0].usage, "ergo weather Paris");
+        assert_eq!(result.command.examples[0].to_string(), "ergo weather Paris  # Weather in Paris");
+    }
+
+    #[test]
+    fn test_generation_prompts_name_host_platform() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let host = host_platform(std::env::consts::OS, std::env::consts::ARCH);
+
+        assert!(generator.build_unified_prompt("weather", None).system.contains(&host));
+        let feedback = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "weather",
+            original_script: "console.log('sunny');",
+            user_feedback: "use celsius",
+            ..Default::default()
+        });
+        assert!(feedback.system.contains(&host));
+        assert_eq!(host_platform("macos", "aarch64"), "HOST PLATFORM: macos (aarch64)");
+    }
+
+    #[test]
+    fn test_supports_platform() {
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(&claude_response(
+            r#"{"name": "open-url", "description": "d", "script": "s", "permissions": [], "platforms": ["macos"]}"#,
+        ))
+        .unwrap();
+
+        assert!(result.command.supports_platform("macos"));
+        assert!(!result.command.supports_platform("linux"));
+        let portable = GeneratedCommand {
+            platforms: vec![],
+            ..result.command
+        };
+        assert!(portable.supports_platform("windows"));
+    }
+
+    #[test]
+    fn test_for_platform_selects_variant() {
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(&claude_response(
+            r#"{"name": "open-url", "description": "d", "script": "s", "permissions": [], "platforms": ["macos"]}"#,
+        ))
+        .unwrap();
+        let mut command = result.command;
+        command.variants.insert(
+            "linux".to_string(),
+            PlatformVariant {
+                script_file: "open-url.linux.ts".to_string(),
+                permissions: vec![PermissionRequest {
+                    permission: "--allow-run=xdg-open".to_string(),
+                    reason: "Open the browser".to_string(),
+                }],
+            },
+        );
+
+        let linux = command.for_platform("linux");
+        assert_eq!(linux.script_file, "open-url.linux.ts");
+        assert_eq!(linux.permissions.len(), 1);
+        assert_eq!(linux.platforms, vec!["linux"]);
+        assert_eq!(linux.for_platform("linux"), linux);
+        assert_eq!(command.for_platform("macos"), command);
+
+        assert!(command.regenerates_as_variant("linux"));
+        assert!(command.regenerates_as_variant("windows"));
+        assert!(!command.regenerates_as_variant("macos"));
+    }
+
+    #[test]
+    fn test_parse_claude_response_with_assumptions() {
+        let response = claude_response(
+            r#"{"name": "changelog", "description": "d", "script": "s", "permissions": [],
+                "assumptions": ["git is installed"], "confidence": "medium"}"#,
+        );
+
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(&response).unwrap();
+
+        assert_eq!(result.command.assumptions, vec!["git is installed"]);
+        assert_eq!(result.command.confidence, Some(Confidence::Medium));
+
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(&claude_response(
+            r#"{"name": "hello", "description": "d", "script": "s", "permissions": []}"#,
+        ))
+        .unwrap();
+        assert!(result.command.assumptions.is_empty());
+        assert_eq!(result.command.confidence, None);
+    }
+
+    #[test]
+    fn test_parse_claude_response_with_output_format() {
+        let response = claude_response(
+            r#"{"name": "pods", "description": "d", "script": "s", "permissions": [], "output_format": "json"}"#,
+        );
+
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(&response).unwrap();
+
+        assert_eq!(result.command.output_format, OutputFormat::Json);
+    }
+
     #[test]
     fn test_parse_claude_response_with_permissions() {
         let response = r#"{
@@ -560,7 +2748,7 @@ mod tests {
             ]
         }"#;
 
-        let result = LlmGenerator::<ReqwestHttpClient>::parse_claude_response(response).unwrap();
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(response).unwrap();
 
         assert_eq!(result.command.name, "fetch-data");
         assert_eq!(result.command.permissions.len(), 1);
@@ -568,11 +2756,33 @@ mod tests {
         assert_eq!(result.command.permissions[0].reason, "Network access");
     }
 
+    #[test]
+    fn test_parse_claude_response_normalizes_scoped_permissions() {
+        let response = claude_response(
+            r#"{"name": "n", "description": "d", "script": "s", "permissions": [{"permission": " --allow-read=./a, ./b ", "reason": "r"}]}"#,
+        );
+
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(&response).unwrap();
+
+        assert_eq!(result.command.permissions[0].permission, "--allow-read=./a,./b");
+    }
+
+    #[test]
+    fn test_parse_claude_response_rejects_unknown_permissions() {
+        let response = claude_response(
+            r#"{"name": "n", "description": "d", "script": "s", "permissions": [{"permission": "--allow-all", "reason": "r"}]}"#,
+        );
+
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(&response);
+
+        assert!(result.unwrap_err().to_string().contains("invalid permission"));
+    }
+
     #[test]
     fn test_parse_claude_response_invalid_json() {
         let response = "not valid json";
 
-        let result = LlmGenerator::<ReqwestHttpClient>::parse_claude_response(response);
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(response);
         assert!(result.is_err());
     }
 
@@ -580,7 +2790,7 @@ mod tests {
     fn test_parse_claude_response_missing_content() {
         let response = r#"{"error": "something went wrong"}"#;
 
-        let result = LlmGenerator::<ReqwestHttpClient>::parse_claude_response(response);
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(response);
         assert!(result.is_err());
     }
 
@@ -595,7 +2805,7 @@ mod tests {
             ]
         }"#;
 
-        let result = LlmGenerator::<ReqwestHttpClient>::parse_claude_response(response);
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(response);
         assert!(result.is_err());
     }
 
@@ -603,7 +2813,7 @@ mod tests {
     fn test_parse_claude_response_empty_content_array() {
         let response = r#"{"content": []}"#;
 
-        let result = LlmGenerator::<ReqwestHttpClient>::parse_claude_response(response);
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(response);
         assert!(result.is_err());
     }
 
@@ -617,7 +2827,7 @@ mod tests {
             ]
         }"#;
 
-        let result = LlmGenerator::<ReqwestHttpClient>::parse_claude_response(response);
+        let result = LlmGenerator::<MockHttpClient>::parse_claude_response(response);
         assert!(result.is_err());
     }
 
@@ -627,142 +2837,301 @@ mod tests {
 
     #[test]
     fn test_build_feedback_prompt_includes_command_name() {
-        let generator = LlmGenerator::new();
-        let prompt = generator.build_feedback_prompt(
-            "password",
-            "console.log('abc');",
-            None,
-            "make it longer",
-        );
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "password",
+            original_script: "console.log('abc');",
+            stderr: None,
+            exit_status: None,
+            user_feedback: "make it longer",
+            test_script: None,
+            history: &[],
+            intent: None,
+        });
+
+        assert!(prompt.joined().contains("password"));
+        assert!(prompt.joined().contains("Keep the same command name: 'password'"));
+    }
+
+    #[test]
+    fn test_build_feedback_prompt_includes_exit_status() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "lint",
+            original_script: "new Deno.Command('eslint').outputSync();",
+            stderr: Some("NotFound: Failed to spawn 'eslint'"),
+            exit_status: Some("exited with code 127 (command not found) after 0.2s"),
+            user_feedback: "",
+            ..Default::default()
+        });
+
+        let last = &prompt.messages[2].content;
+        assert!(last.starts_with(
+            "EXECUTION RESULT: the script exited with code 127 (command not found) after 0.2s\n\n\nERROR OUTPUT FROM EXECUTION:"
+        ));
+    }
+
+    #[test]
+    fn test_build_feedback_prompt_includes_truncated_history() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let history: Vec<FeedbackTurn> = (1..=7)
+            .map(|revision| FeedbackTurn {
+                revision,
+                feedback: format!("round {} {}", revision, "x".repeat(MAX_FEEDBACK_TURN_CHARS)),
+                stderr: (revision == 7).then(|| "Uncaught TypeError".to_string()),
+                created_at: 0,
+            })
+            .collect();
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "password",
+            original_script: "console.log('abc');",
+            stderr: None,
+            exit_status: None,
+            user_feedback: "make it longer",
+            test_script: None,
+            history: &history,
+            intent: None,
+        });
+
+        let last = &prompt.messages[2].content;
+        assert!(last.contains("EARLIER FEEDBACK ON THIS COMMAND"));
+        assert!(!last.contains("round 2 "));
+        assert!(last.contains("Revision 3: round 3 "));
+        assert!(last.contains("Revision 7: round 7 "));
+        assert!(last.contains("x…"));
+        assert!(last.contains("error: Uncaught TypeError"));
+        assert!(last.contains("don't reintroduce behavior it rejected"));
+    }
+
+    #[test]
+    fn test_build_feedback_prompt_omits_empty_history() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "password",
+            original_script: "console.log('abc');",
+            user_feedback: "make it longer",
+            ..Default::default()
+        });
 
-        assert!(prompt.contains("password"));
-        assert!(prompt.contains("Keep the same command name: 'password'"));
+        assert!(!prompt.joined().contains("EARLIER FEEDBACK"));
+    }
+
+    #[test]
+    fn test_build_feedback_prompt_models_conversation() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "password",
+            original_script: "console.log('abc');",
+            stderr: None,
+            exit_status: None,
+            user_feedback: "make it longer",
+            test_script: None,
+            history: &[],
+            intent: Some("generate a random password"),
+        });
+
+        let roles: Vec<Role> = prompt.messages.iter().map(|m| m.role).collect();
+        assert_eq!(roles, vec![Role::User, Role::Assistant, Role::User]);
+        assert_eq!(
+            prompt.messages[0].content,
+            "Create a command named 'password' for this request:\ngenerate a random password"
+        );
+        assert!(prompt.messages[1].content.contains("console.log('abc');"));
+        assert!(prompt.messages[2].content.contains("make it longer"));
+        assert!(!prompt.system.contains("'password'"));
+        assert!(!prompt.system.contains("make it longer"));
     }
 
     #[test]
     fn test_build_feedback_prompt_includes_original_script() {
-        let generator = LlmGenerator::new();
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
         let original_script = "const pw = Math.random().toString(36).slice(2, 8);";
-        let prompt = generator.build_feedback_prompt(
-            "password",
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "password",
             original_script,
-            None,
-            "make it longer",
-        );
+            stderr: None,
+            exit_status: None,
+            user_feedback: "make it longer",
+            test_script: None,
+            history: &[],
+            intent: None,
+        });
 
-        assert!(prompt.contains(original_script));
-        assert!(prompt.contains("ORIGINAL SCRIPT:"));
+        assert!(prompt.joined().contains(original_script));
+        assert!(prompt.joined().contains("ORIGINAL SCRIPT:"));
     }
 
     #[test]
     fn test_build_feedback_prompt_includes_user_feedback() {
-        let generator = LlmGenerator::new();
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
         let feedback = "password must be at least 15 characters with symbols";
-        let prompt = generator.build_feedback_prompt(
-            "password",
-            "console.log('short');",
-            None,
-            feedback,
-        );
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "password",
+            original_script: "console.log('short');",
+            stderr: None,
+            exit_status: None,
+            user_feedback: feedback,
+            test_script: None,
+            history: &[],
+            intent: None,
+        });
 
-        assert!(prompt.contains(feedback));
-        assert!(prompt.contains("USER FEEDBACK:"));
+        assert!(prompt.joined().contains(feedback));
+        assert!(prompt.joined().contains("USER FEEDBACK:"));
     }
 
     #[test]
     fn test_build_feedback_prompt_includes_stderr_when_present() {
-        let generator = LlmGenerator::new();
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
         let stderr = "Error: password too short";
-        let prompt = generator.build_feedback_prompt(
-            "password",
-            "console.log('abc');",
-            Some(stderr),
-            "make it longer",
-        );
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "password",
+            original_script: "console.log('abc');",
+            stderr: Some(stderr),
+            exit_status: None,
+            user_feedback: "make it longer",
+            test_script: None,
+            history: &[],
+            intent: None,
+        });
 
-        assert!(prompt.contains(stderr));
-        assert!(prompt.contains("ERROR OUTPUT FROM EXECUTION:"));
+        assert!(prompt.joined().contains(stderr));
+        assert!(prompt.joined().contains("ERROR OUTPUT FROM EXECUTION:"));
     }
 
     #[test]
     fn test_build_feedback_prompt_omits_error_section_when_no_stderr() {
-        let generator = LlmGenerator::new();
-        let prompt = generator.build_feedback_prompt(
-            "hello",
-            "console.log('Hello');",
-            None,
-            "add a greeting parameter",
-        );
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "hello",
+            original_script: "console.log('Hello');",
+            stderr: None,
+            exit_status: None,
+            user_feedback: "add a greeting parameter",
+            test_script: None,
+            history: &[],
+            intent: None,
+        });
 
-        assert!(!prompt.contains("ERROR OUTPUT FROM EXECUTION:"));
+        assert!(!prompt.joined().contains("ERROR OUTPUT FROM EXECUTION:"));
     }
 
     #[test]
     fn test_build_feedback_prompt_requires_json_response() {
-        let generator = LlmGenerator::new();
-        let prompt = generator.build_feedback_prompt(
-            "test",
-            "console.log('test');",
-            None,
-            "improve it",
-        );
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "test",
+            original_script: "console.log('test');",
+            stderr: None,
+            exit_status: None,
+            user_feedback: "improve it",
+            test_script: None,
+            history: &[],
+            intent: None,
+        });
 
-        assert!(prompt.contains("EXACTLY a JSON object"));
-        assert!(prompt.contains("\"name\":"));
-        assert!(prompt.contains("\"description\":"));
-        assert!(prompt.contains("\"script\":"));
-        assert!(prompt.contains("\"permissions\":"));
+        assert!(prompt.joined().contains("EXACTLY a JSON object"));
+        assert!(prompt.joined().contains("\"name\":"));
+        assert!(prompt.joined().contains("\"description\":"));
+        assert!(prompt.joined().contains("\"script\":"));
+        assert!(prompt.joined().contains("\"permissions\":"));
     }
 
     #[test]
     fn test_build_feedback_prompt_mentions_deno_rules() {
-        let generator = LlmGenerator::new();
-        let prompt = generator.build_feedback_prompt(
-            "test",
-            "console.log('test');",
-            None,
-            "improve it",
-        );
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "test",
+            original_script: "console.log('test');",
+            stderr: None,
+            exit_status: None,
+            user_feedback: "improve it",
+            test_script: None,
+            history: &[],
+            intent: None,
+        });
 
-        assert!(prompt.contains("Deno APIs"));
-        assert!(prompt.contains("Deno.args"));
-        assert!(prompt.contains("--allow-read"));
-        assert!(prompt.contains("--allow-net"));
+        assert!(prompt.joined().contains("Deno APIs"));
+        assert!(prompt.joined().contains("Deno.args"));
+        assert!(prompt.joined().contains("--allow-read"));
+        assert!(prompt.joined().contains("--allow-net"));
     }
 
     #[test]
     fn test_build_feedback_prompt_with_multiline_script() {
-        let generator = LlmGenerator::new();
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
         let script = r#"
 function generatePassword() {
     return "abc123";
 }
 console.log(generatePassword());
 "#;
-        let prompt = generator.build_feedback_prompt(
-            "password",
-            script,
-            None,
-            "add symbols",
-        );
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "password",
+            original_script: script,
+            stderr: None,
+            exit_status: None,
+            user_feedback: "add symbols",
+            test_script: None,
+            history: &[],
+            intent: None,
+        });
+
+        assert!(prompt.joined().contains("generatePassword"));
+        assert!(prompt.joined().contains("abc123"));
+    }
+
+    #[test]
+    fn test_build_feedback_prompt_includes_tests_when_present() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let tests = "Deno.test('prints hello', async () => {});";
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "hello",
+            original_script: "console.log('Hello');",
+            stderr: None,
+            exit_status: None,
+            user_feedback: "add a name parameter",
+            test_script: Some(tests),
+            history: &[],
+            intent: None,
+        });
+
+        assert!(prompt.joined().contains("EXISTING TESTS"));
+        assert!(prompt.joined().contains(tests));
+        assert!(prompt.joined().contains("MUST keep passing the existing tests"));
+    }
+
+    #[test]
+    fn test_build_feedback_prompt_omits_tests_when_absent() {
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "hello",
+            original_script: "console.log('Hello');",
+            user_feedback: "add a name parameter",
+            ..Default::default()
+        });
 
-        assert!(prompt.contains("generatePassword"));
-        assert!(prompt.contains("abc123"));
+        assert!(!prompt.joined().contains("EXISTING TESTS"));
+        assert!(!prompt.joined().contains("MUST keep passing"));
     }
 
     #[test]
     fn test_build_feedback_prompt_with_multiline_stderr() {
-        let generator = LlmGenerator::new();
+        let generator = LlmGenerator::with_http_client(MockHttpClient::new());
         let stderr = "error: Uncaught Error: something failed\n    at generatePassword (file:///tmp/script.ts:5:11)\n    at file:///tmp/script.ts:8:13";
-        let prompt = generator.build_feedback_prompt(
-            "password",
-            "console.log('test');",
-            Some(stderr),
-            "fix the error",
-        );
+        let prompt = generator.build_feedback_prompt(&FeedbackRequest {
+            command_name: "password",
+            original_script: "console.log('test');",
+            stderr: Some(stderr),
+            exit_status: None,
+            user_feedback: "fix the error",
+            test_script: None,
+            history: &[],
+            intent: None,
+        });
 
-        assert!(prompt.contains("Uncaught Error"));
-        assert!(prompt.contains("at generatePassword"));
+        assert!(prompt.joined().contains("Uncaught Error"));
+        assert!(prompt.joined().contains("at generatePassword"));
     }
 
     // =========================================================================
@@ -0,0 +1,174 @@
+//! Logging setup for the ergo CLI.
+//!
+//! Tracing output goes to `~/.abiogenesis/ergo.log`, unless it can't be
+//! written (a read-only home) or nothing should be (`--ephemeral`). With higher
+//! verbosity levels (`-vv`, `-vvv`) the same events are also rendered to
+//! stderr, so generation and parsing issues can be debugged without tailing
+//! the log file in another terminal.
+//!
+//! # Verbosity Levels
+//!
+//! | Flag    | Log file | stderr                         |
+//! |---------|----------|--------------------------------|
+//! | (none)  | info     | -                              |
+//! | `-v`    | debug    | -                              |
+//! | `-vv`   | debug    | ergo at debug, others at warn  |
+//! | `-vvv`  | trace    | ergo at trace, others at info  |
+
+use anyhow::Result;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Layer};
+
+/// Verbosity level selected on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Verbosity(u8);
+
+impl Verbosity {
+    /// Creates a verbosity level from the number of `-v` flags given.
+    pub fn from_count(count: u8) -> Self {
+        Self(count.min(3))
+    }
+
+    /// Returns the raw level (0-3).
+    pub fn level(&self) -> u8 {
+        self.0
+    }
+
+    /// Whether user-facing verbose output (progress messages) is enabled.
+    pub fn is_verbose(&self) -> bool {
+        self.0 >= 1
+    }
+
+    /// Filter directives for the log file.
+    pub fn file_directives(&self) -> &'static str {
+        match self.0 {
+            0 => "info",
+            1 | 2 => "debug",
+            _ => "trace",
+        }
+    }
+
+    /// Filter directives for stderr forwarding, if enabled at this level.
+    ///
+    /// Our own crates are shown in detail while dependencies (hyper, reqwest,
+    /// ...) are kept quiet unless the maximum level is requested.
+    pub fn stderr_directives(&self) -> Option<&'static str> {
+        match self.0 {
+            0 | 1 => None,
+            2 => Some("warn,abiogenesis=debug,ergo=debug"),
+            _ => Some("info,abiogenesis=trace,ergo=trace"),
+        }
+    }
+}
+
+/// Builds an [`EnvFilter`] from `RUST_LOG` (if set) plus the given directives.
+fn build_filter(directives: &str) -> Result<EnvFilter> {
+    let mut filter = EnvFilter::from_default_env();
+    for directive in directives.split(',') {
+        filter = filter.add_directive(directive.parse()?);
+    }
+    Ok(filter)
+}
+
+/// Initializes global tracing output for the given verbosity.
+///
+/// Events are appended to `log_file`, if given; when the verbosity level
+/// enables it, they are also written to stderr with ANSI colors. A log file
+/// that can't be opened is skipped with a warning.
+///
+/// # Errors
+///
+/// Returns an error if a filter directive is invalid, or a global subscriber
+/// has already been installed.
+pub fn init(verbosity: Verbosity, log_file: Option<&Path>) -> Result<()> {
+    let file = match log_file.map(open_log) {
+        Some(Ok(file)) => Some(file),
+        Some(Err(e)) => {
+            crate::ephemeral::warn_unsaved("the log", &e);
+            None
+        }
+        None => None,
+    };
+
+    let file_layer = match file {
+        Some(file) => Some(
+            fmt::layer()
+                .with_writer(file)
+                .with_ansi(false) // No colors in log file
+                .with_filter(build_filter(verbosity.file_directives())?),
+        ),
+        None => None,
+    };
+
+    let stderr_layer = match verbosity.stderr_directives() {
+        Some(directives) => Some(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_target(true)
+                .with_filter(build_filter(directives)?),
+        ),
+        None => None,
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(file_layer)
+        .with(stderr_layer);
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    Ok(())
+}
+
+/// Opens `log_file` for appending, creating it and its directory.
+fn open_log(log_file: &Path) -> std::io::Result<File> {
+    if let Some(parent) = log_file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(log_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_count_clamps_to_max_level() {
+        assert_eq!(Verbosity::from_count(0).level(), 0);
+        assert_eq!(Verbosity::from_count(2).level(), 2);
+        assert_eq!(Verbosity::from_count(9).level(), 3);
+    }
+
+    #[test]
+    fn test_is_verbose_from_first_level() {
+        assert!(!Verbosity::from_count(0).is_verbose());
+        assert!(Verbosity::from_count(1).is_verbose());
+        assert!(Verbosity::from_count(3).is_verbose());
+    }
+
+    #[test]
+    fn test_stderr_forwarding_starts_at_level_two() {
+        assert!(Verbosity::from_count(0).stderr_directives().is_none());
+        assert!(Verbosity::from_count(1).stderr_directives().is_none());
+        assert!(Verbosity::from_count(2).stderr_directives().is_some());
+        assert!(Verbosity::from_count(3).stderr_directives().is_some());
+    }
+
+    #[test]
+    fn test_file_directives_increase_with_level() {
+        assert_eq!(Verbosity::from_count(0).file_directives(), "info");
+        assert_eq!(Verbosity::from_count(1).file_directives(), "debug");
+        assert_eq!(Verbosity::from_count(3).file_directives(), "trace");
+    }
+
+    #[test]
+    fn test_all_directives_parse() {
+        for count in 0..=3 {
+            let verbosity = Verbosity::from_count(count);
+            assert!(build_filter(verbosity.file_directives()).is_ok());
+            if let Some(directives) = verbosity.stderr_directives() {
+                assert!(build_filter(directives).is_ok());
+            }
+        }
+    }
+}
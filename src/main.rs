@@ -1,52 +1,1833 @@
-use abiogenesis::command_cache::{CommandCache, PermissionConsent};
-use abiogenesis::command_router::CommandRouter;
-use abiogenesis::config::Config;
-use clap::{Arg, Command};
-use std::fs::OpenOptions;
+use abiogenesis::bedrock::BEDROCK_BACKEND;
+use abiogenesis::cancellation::{Cancellation, INTERRUPTED_EXIT_CODE};
+use abiogenesis::chain::{Chain, ChainStep, StepCondition};
+use abiogenesis::changelog::Changelog;
+use abiogenesis::ci_export::{self, ScriptSource};
+use abiogenesis::command_cache::{CachePathResolver, CommandCache, HierarchyPathResolver, PermissionConsent};
+use abiogenesis::command_router::{BatchOutcome, ChainOutcome, CommandRouter, DEFAULT_PARALLELISM, IntentOutcome, PrepareOutcome};
+use abiogenesis::config::{Config, ConfigLoader};
+use abiogenesis::deno_deps;
+use abiogenesis::dep_audit::{self, CommandInventory};
+use abiogenesis::diagnostics::{self, BugReport};
+use abiogenesis::docgen;
+use abiogenesis::doctor::{self, Status};
+use abiogenesis::environment;
+use abiogenesis::ephemeral;
+use abiogenesis::error::ErgoError;
+use abiogenesis::execution_context::ExecutionContext;
+use abiogenesis::executor::{Executor, ProcessRunner, SystemProcessRunner};
+use abiogenesis::explain;
+use abiogenesis::git_hooks::{self, HookInstall};
+use abiogenesis::gemini::GEMINI_BACKEND;
+use abiogenesis::http_client::ReqwestHttpClient;
+use abiogenesis::import::{self, ImportedCommand, Verification};
+use abiogenesis::intent_queue::{self, QueuedIntent};
+use abiogenesis::json_filter::JsonFilter;
+use abiogenesis::latency::{self, LatencyReport, LatencySample, SampleKind};
+use abiogenesis::llm_debug;
+use abiogenesis::llm_generator::{GeneratedCommand, GenerationResult, LlmGenerator};
+use abiogenesis::logging::{self, Verbosity};
+use abiogenesis::notify::{self, Notification};
+use abiogenesis::output_processing::{self, OutputFormat};
+use abiogenesis::permission_sim::{self, PermissionReport};
+use abiogenesis::permission_summary::PermissionSummary;
+use abiogenesis::permission_ui::PermissionUI;
+use abiogenesis::plugins;
+use abiogenesis::preload;
+use abiogenesis::project_scope;
+use abiogenesis::raycast_export;
+use abiogenesis::regenerate_filter::{self, RegenerateFilter};
+use abiogenesis::retention;
+use abiogenesis::providers::{SystemTimeProvider, TimeProvider};
+use abiogenesis::script_diff;
+use abiogenesis::secrets::SecretDelivery;
+use abiogenesis::security_review::{self, SecurityReport};
+use abiogenesis::snapshot;
+use abiogenesis::starter;
+use abiogenesis::suggest::{self, ProjectContext};
+use abiogenesis::telemetry::{self, StatsPayload, UsageEvent, UsageStats};
+use abiogenesis::trust::TrustLevel;
+use abiogenesis::usage_ledger::{self, SpendByCommand, UsageSummary};
+use abiogenesis::validation::{self, ValidationPipeline};
+use abiogenesis::vscode_tasks;
+use abiogenesis::workflow::Workflow;
+use clap::error::ErrorKind;
+use clap::{Arg, ArgMatches, Command};
+use std::ffi::OsString;
+use std::io::IsTerminal;
 use tracing::info;
-use tracing_subscriber::{fmt, EnvFilter};
 
-fn setup_logging(verbose: bool) -> anyhow::Result<()> {
+fn setup_logging(verbosity: Verbosity) -> anyhow::Result<()> {
+    if ephemeral::is_enabled() {
+        return logging::init(verbosity, None);
+    }
+
     // Get log directory from config
     let config_dir = Config::get_config_dir().unwrap_or_else(|_| {
         dirs::home_dir().unwrap_or_default().join(".abiogenesis")
     });
-    
-    // Create log directory if it doesn't exist
-    std::fs::create_dir_all(&config_dir)?;
-    
-    let log_file = config_dir.join("ergo.log");
-    
-    // Create or open log file
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_file)?;
-    
-    // Set log level based on verbosity
-    let log_level = if verbose { "debug" } else { "info" };
-    
-    // Configure tracing to write to file
-    let subscriber = fmt::Subscriber::builder()
-        .with_env_filter(EnvFilter::from_default_env().add_directive(log_level.parse()?))
-        .with_writer(file)
-        .with_ansi(false) // No colors in log file
-        .finish();
-    
-    tracing::subscriber::set_global_default(subscriber)?;
-    
+
+    logging::init(verbosity, Some(&config_dir.join("ergo.log")))
+}
+
+fn handle_config_subcommand(matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    let loader = ConfigLoader::new();
+
+    match matches.subcommand() {
+        Some(("get", sub)) => {
+            let key = sub.get_one::<String>("key").expect("key is required");
+            match loader.get_value(key)? {
+                Some(toml::Value::String(value)) => println!("{}", value),
+                Some(value) => println!("{}", value),
+                None => {
+                    eprintln!("❌ Configuration key '{}' is not set", key);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("set", sub)) => {
+            let key = sub.get_one::<String>("key").expect("key is required");
+            let value = sub.get_one::<String>("value").expect("value is required");
+            loader.set_value(key, value)?;
+            println!("✅ Set '{}'", key);
+        }
+        Some(("unset", sub)) => {
+            let key = sub.get_one::<String>("key").expect("key is required");
+            if loader.unset_value(key)? {
+                println!("✅ Unset '{}'", key);
+            } else {
+                println!("❌ Configuration key '{}' is not set", key);
+            }
+        }
+        _ => loader.show_config_info()?,
+    }
+
+    Ok(())
+}
+
+/// Runs the environment checks of `ergo doctor`, exiting with 1 if any fails.
+async fn handle_doctor() -> anyhow::Result<()> {
+    println!("🩺 Checking your ergo setup...\n");
+    let cache_dirs = HierarchyPathResolver::new().get_cache_dirs()?;
+    let checks = doctor::diagnose(
+        &SystemProcessRunner::default(),
+        &ReqwestHttpClient::new(),
+        &ConfigLoader::new(),
+        &cache_dirs,
+        SystemTimeProvider.now(),
+    )
+    .await;
+    for check in &checks {
+        println!("{}", check);
+    }
+
+    let failures = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failures > 0 {
+        println!("\n❌ {} check(s) failed", failures);
+        std::process::exit(1);
+    }
+    println!("\n✅ Everything looks good");
+    Ok(())
+}
+
+/// Creates a router that winds down cleanly on Ctrl-C.
+async fn interruptible_router(verbose: bool) -> anyhow::Result<CommandRouter> {
+    let cancellation = Cancellation::new();
+    cancellation.cancel_on_ctrl_c();
+    Ok(CommandRouter::new(verbose).await?.with_cancellation(cancellation))
+}
+
+/// Applies the flags that shape how the router generates and runs commands.
+fn configure_router(router: &mut CommandRouter, matches: &ArgMatches) -> anyhow::Result<()> {
+    if matches.get_flag("stage") {
+        router.set_staged_writes(true);
+    }
+    if matches.get_flag("workspace") {
+        router.set_workspace(true);
+    }
+    router.set_secret_delivery(secret_delivery(matches));
+    router.set_safety_override(matches.get_flag("i-know-what-im-doing"));
+    router.set_generation_retries(matches.get_one::<u32>("retry").copied().unwrap_or(0));
+    if let Some(model) = matches.get_one::<String>("model") {
+        router.set_model(model)?;
+    }
+    router.set_plan_first(matches.get_flag("plan"));
+    router.set_json_filter(matches.get_one::<JsonFilter>("jq").cloned());
+    Ok(())
+}
+
+/// Exits with the conventional status for SIGINT if the user interrupted.
+fn exit_if_interrupted<T>(result: Result<T, ErgoError>) -> Result<T, ErgoError> {
+    match result {
+        Err(ErgoError::Interrupted) => std::process::exit(INTERRUPTED_EXIT_CODE),
+        result => result,
+    }
+}
+
+/// Writes the starter commands to the home bioma on the first run, unless
+/// opted out (see [`starter`]).
+///
+/// The starter commands are a convenience: failures are only logged.
+async fn install_starter_commands() {
+    if ephemeral::is_enabled() || starter::opted_out(|name| std::env::var(name).ok()) {
+        return;
+    }
+    let Ok(config) = Config::load() else {
+        return;
+    };
+    if !config.starter.install {
+        return;
+    }
+    let (Ok(config_dir), Some(home)) = (Config::get_config_dir(), dirs::home_dir()) else {
+        return;
+    };
+    match starter::install_once(&config_dir, &config.cache.cache_dir(&home)).await {
+        Ok(installed) if !installed.is_empty() => eprintln!(
+            "🌱 Installed the starter commands {}. Try `ergo uuid`",
+            installed.join(", ")
+        ),
+        Ok(_) => {}
+        Err(e) => tracing::warn!("Failed to install the starter commands: {}", e),
+    }
+}
+
+/// Counts usage events and sends the daily stats ping if telemetry is on.
+///
+/// Telemetry never gets in the way of a command: failures are only logged.
+async fn record_usage(events: Vec<UsageEvent>) {
+    if ephemeral::is_enabled() {
+        return;
+    }
+    let Ok(config) = Config::load() else {
+        return;
+    };
+    if !config.telemetry.enabled {
+        return;
+    }
+    let Ok(path) = UsageStats::default_path() else {
+        return;
+    };
+    let now = SystemTimeProvider.now();
+    if let Err(e) = telemetry::record_events(&config.telemetry, &path, &events, now) {
+        tracing::warn!("Failed to record usage stats: {}", e);
+        return;
+    }
+    let client = ReqwestHttpClient::new();
+    let send = telemetry::send_if_due(&config.telemetry, &path, &client, now);
+    match tokio::time::timeout(std::time::Duration::from_secs(3), send).await {
+        Ok(Err(e)) => tracing::warn!("Failed to send usage stats: {}", e),
+        Err(_) => tracing::warn!("Timed out sending usage stats"),
+        Ok(Ok(_)) => {}
+    }
+}
+
+/// Records how long the commands that ran took (see [`latency`]) and posts
+/// their results to the `notify.webhook`.
+///
+/// Like telemetry, neither ever gets in the way: failures are only logged.
+async fn record_results<'a>(outcomes: impl IntoIterator<Item = &'a IntentOutcome>) {
+    let notifications: Vec<Notification> = outcomes.into_iter().filter_map(Notification::from_outcome).collect();
+    let now = SystemTimeProvider.now();
+    for notification in &notifications {
+        let sample = LatencySample {
+            timestamp: now,
+            kind: SampleKind::Execution,
+            name: notification.command.clone(),
+            duration_ms: notification.duration_ms as u64,
+            request_bytes: 0,
+            response_bytes: 0,
+            output_tokens: 0,
+        };
+        if !ephemeral::is_enabled()
+            && let Err(e) = latency::default_path().and_then(|path| {
+                latency::append(&path, &sample)?;
+                retention::prune(&path);
+                Ok(())
+            })
+        {
+            tracing::warn!("Failed to record execution latency: {}", e);
+        }
+    }
+
+    let Ok(config) = Config::load() else {
+        return;
+    };
+    if config.notify.webhook.is_none() {
+        return;
+    }
+    let client = ReqwestHttpClient::new();
+    for notification in &notifications {
+        let send = notify::send(&config.notify, &client, notification);
+        match tokio::time::timeout(std::time::Duration::from_secs(5), send).await {
+            Ok(Err(e)) => tracing::warn!("Failed to notify webhook: {}", e),
+            Err(_) => tracing::warn!("Timed out notifying webhook"),
+            Ok(Ok(_)) => {}
+        }
+    }
+}
+
+/// Returns the usage events for the steps of a chain or workflow.
+fn chain_events(outcomes: &[ChainOutcome]) -> Vec<UsageEvent> {
+    outcomes
+        .iter()
+        .filter_map(|step| step.outcome.as_ref())
+        .flat_map(UsageEvent::from_result)
+        .collect()
+}
+
+/// Returns the outcomes of the steps of a chain or workflow that ran.
+fn chain_results(outcomes: &[ChainOutcome]) -> impl Iterator<Item = &IntentOutcome> {
+    outcomes.iter().filter_map(|step| step.outcome.as_ref()?.as_ref().ok())
+}
+
+/// Lists the generator backends, marking the one in use.
+fn list_generators() -> anyhow::Result<()> {
+    let active = Config::load()?.generator.backend;
+    let dir = plugins::plugins_dir()?;
+    let marker = |name: &str| if name == active { "▶" } else { " " };
+
+    println!("🧩 Generator backends:");
+    println!(" {} {} (built in)", marker(plugins::BUILTIN_BACKEND), plugins::BUILTIN_BACKEND);
+    println!(" {} {} (built in, AWS)", marker(BEDROCK_BACKEND), BEDROCK_BACKEND);
+    println!(" {} {} (built in, Google)", marker(GEMINI_BACKEND), GEMINI_BACKEND);
+    for plugin in plugins::discover(&dir)? {
+        println!(" {} {} ({})", marker(&plugin.name), plugin.name, plugin.path.display());
+    }
+    println!("💡 Select one with: ergo config set generator.backend NAME");
+    Ok(())
+}
+
+/// Lists the models of the configured backend, marking the one in use.
+async fn list_models() -> anyhow::Result<()> {
+    let backend = Config::load()?.generator.backend;
+    if ![plugins::BUILTIN_BACKEND, BEDROCK_BACKEND, GEMINI_BACKEND].contains(&backend.as_str()) {
+        println!("ℹ️  The '{}' plugin backend doesn't list models; see its documentation", backend);
+        return Ok(());
+    }
+    println!("{}", LlmGenerator::new().list_models().await?);
+    Ok(())
+}
+
+/// Handles `--trust-bioma` and `--distrust-bioma`: lists `dir` (the current
+/// directory by default) at `level` in the `[trust]` settings.
+fn set_bioma_trust(dir: Option<&String>, level: TrustLevel) -> anyhow::Result<()> {
+    let dir = match dir {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => std::env::current_dir()?,
+    };
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| anyhow::anyhow!("Can't find {}: {}", dir.display(), e))?;
+    let mut trust = Config::load()?.trust;
+    if !trust.set_level(&dir, level) {
+        println!("ℹ️  {} is already {}", dir.display(), if level == TrustLevel::Trusted { "trusted" } else { "untrusted" });
+        return Ok(());
+    }
+    let loader = ConfigLoader::new();
+    loader.set_value("trust.trusted", &trust.trusted.join(","))?;
+    loader.set_value("trust.untrusted", &trust.untrusted.join(","))?;
+    match level {
+        TrustLevel::Trusted => println!("✅ Trusting the bioma at {}", dir.display()),
+        TrustLevel::Untrusted => println!(
+            "🚧 The bioma at {} is untrusted: its commands will show their script and ask for consent on every run",
+            dir.display()
+        ),
+    }
+    Ok(())
+}
+
+/// Handles `--telemetry status|on|off`.
+fn handle_telemetry(action: &str) -> anyhow::Result<()> {
+    let loader = ConfigLoader::new();
+    let path = UsageStats::default_path()?;
+    match action {
+        "on" => {
+            loader.set_value("telemetry.enabled", "true")?;
+            println!("✅ Anonymous usage statistics enabled");
+            if Config::load()?.telemetry.endpoint.is_none() {
+                println!("ℹ️  Counts are kept locally until 'telemetry.endpoint' is set");
+            }
+        }
+        "off" => {
+            loader.set_value("telemetry.enabled", "false")?;
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+            println!("✅ Anonymous usage statistics disabled and local counts deleted");
+        }
+        _ => {
+            let config = Config::load()?.telemetry;
+            println!("📊 Usage statistics: {}", if config.enabled { "on" } else { "off" });
+            println!(
+                "🌐 Endpoint: {}",
+                config.endpoint.as_deref().unwrap_or("(not set, nothing is sent)")
+            );
+            if config.enabled {
+                let now = SystemTimeProvider.now();
+                let payload = StatsPayload::from_stats(&UsageStats::load(&path, now), now);
+                println!("📦 Next payload:");
+                println!("{}", serde_json::to_string_pretty(&payload)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads how sensitive output should be delivered from `--show` and `--clipboard`.
+fn secret_delivery(matches: &clap::ArgMatches) -> SecretDelivery {
+    if matches.get_flag("show") {
+        SecretDelivery::Show
+    } else if matches.get_flag("clipboard") {
+        SecretDelivery::Clipboard
+    } else {
+        SecretDelivery::Mask
+    }
+}
+
+fn report_outcome(outcome: &IntentOutcome, verbose: bool) {
+    match outcome {
+        IntentOutcome::Generated { command, .. } if verbose => {
+            println!("🎯 Generated command: {}", command.name);
+            println!("📝 Description: {}", command.description);
+        }
+        IntentOutcome::PermissionDenied { command } => {
+            PermissionUI::new(verbose).show_permission_denied(&command.name);
+        }
+        _ => {}
+    }
+
+    if let Some(error) = outcome.execution().and_then(|result| result.error.as_deref()) {
+        eprintln!("❌ {}", error);
+    }
+
+    if outcome.execution().is_some_and(|result| result.interrupted) {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+/// Prints one summary line per command of a `--parallel` run.
+///
+/// Returns true if every command succeeded.
+fn report_batch(outcomes: &[BatchOutcome]) -> bool {
+    println!();
+    let mut all_ok = true;
+    for batch in outcomes {
+        match &batch.outcome {
+            Ok(outcome) => match outcome.execution() {
+                Some(result) if result.success => {
+                    println!("✅ {} ({:.1}s)", batch.command_name, result.duration.as_secs_f64());
+                }
+                Some(result) => {
+                    all_ok = false;
+                    match result.exit_code {
+                        Some(code) => println!(
+                            "❌ {} (exit code {}, {:.1}s)",
+                            batch.command_name,
+                            code,
+                            result.duration.as_secs_f64()
+                        ),
+                        None => println!("❌ {} (did not finish)", batch.command_name),
+                    }
+                }
+                None => {
+                    all_ok = false;
+                    println!("🚫 {} (permission denied)", batch.command_name);
+                }
+            },
+            Err(e) => {
+                all_ok = false;
+                println!("❌ {}: {}", batch.command_name, e);
+            }
+        }
+    }
+    all_ok
+}
+
+/// Reads the specification of `--intent-file FILE`, or stdin for
+/// `--intent-file -` and the intent `-`.
+fn read_intent_spec(matches: &clap::ArgMatches, intent_args: &[String]) -> anyhow::Result<Option<String>> {
+    let path = match matches.get_one::<String>("intent-file") {
+        Some(path) => path.as_str(),
+        None if intent_args == ["-"] => "-",
+        None => return Ok(None),
+    };
+    let spec = if path == "-" {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read intent file {}: {}", path, e))?
+    };
+    if spec.trim().is_empty() {
+        return Err(anyhow::anyhow!("The specification in {} is empty", if path == "-" { "stdin" } else { path }));
+    }
+    Ok(Some(spec.trim().to_string()))
+}
+
+/// Builds a chain from `--chain FILE`, or from the intent followed by the
+/// `--then`, `--on-failure` and `--finally` steps in command-line order.
+fn chain_from_args(matches: &clap::ArgMatches, intent_args: &[String]) -> anyhow::Result<Option<Chain>> {
+    if let Some(path) = matches.get_one::<String>("chain") {
+        if !intent_args.is_empty() {
+            return Err(anyhow::anyhow!("--chain can't be combined with an intent"));
+        }
+        return Chain::load(std::path::Path::new(path)).map(Some);
+    }
+
+    let mut steps = Vec::new();
+    for (flag, when) in [
+        ("then", StepCondition::Success),
+        ("on-failure", StepCondition::Failure),
+        ("finally", StepCondition::Always),
+    ] {
+        if let (Some(indices), Some(values)) = (matches.indices_of(flag), matches.get_many::<String>(flag)) {
+            for (index, value) in indices.zip(values) {
+                steps.push((index, ChainStep::parse(value, when)?));
+            }
+        }
+    }
+    if steps.is_empty() {
+        return Ok(None);
+    }
+    steps.sort_by_key(|(index, _)| *index);
+
+    let mut chain = Chain::default();
+    if !intent_args.is_empty() {
+        chain.steps.push(ChainStep {
+            intent: intent_args.to_vec(),
+            when: StepCondition::Success,
+        });
+    }
+    chain.steps.extend(steps.into_iter().map(|(_, step)| step));
+    Ok(Some(chain))
+}
+
+/// Prints one summary line per step of a chain.
+///
+/// Returns true if the last step that ran succeeded.
+fn report_chain(outcomes: &[ChainOutcome], verbose: bool) -> bool {
+    println!();
+    let mut last_ok = true;
+    for step in outcomes {
+        match &step.outcome {
+            None => println!("⏭️  {} (skipped)", step.command_line),
+            Some(Err(e)) => println!("❌ {}: {}", step.command_line, e),
+            Some(Ok(outcome)) => {
+                report_outcome(outcome, verbose);
+                let icon = if outcome.succeeded() { "✅" } else { "❌" };
+                match outcome.execution() {
+                    Some(result) => println!("{} {} ({:.1}s)", icon, step.command_line, result.duration.as_secs_f64()),
+                    None => println!("{} {}", icon, step.command_line),
+                }
+            }
+        }
+        if step.outcome.is_some() {
+            last_ok = step.succeeded();
+        }
+    }
+    last_ok
+}
+
+async fn report_bug(output: &str) -> anyhow::Result<()> {
+    let config_dir = Config::get_config_dir()?;
+
+    // Include the cache entry of the command that ran last, if any
+    let cache_entry = match ExecutionContext::load().ok().flatten() {
+        Some(context) => {
+            let cache = CommandCache::new().await?;
+            let script = match cache.get_command(&context.command_name).await? {
+                Some(command) => cache.get_script_content(&command.for_platform(std::env::consts::OS)).ok(),
+                None => None,
+            };
+            cache
+                .get_entry_json(&context.command_name)
+                .map(|entry| (entry, script))
+        }
+        None => None,
+    };
+
+    let deno_version = std::process::Command::new("deno")
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string());
+
+    let report = BugReport::collect(&config_dir, cache_entry, deno_version)?;
+    let path = if output.is_empty() {
+        diagnostics::default_report_path(SystemTimeProvider.now())
+    } else {
+        output.into()
+    };
+    report.write_tarball(&path)?;
+
+    println!("📦 Bug report written to {}", path.display());
+    println!("   Contents: {}", report.file_names().join(", "));
+    println!("   Secrets have been redacted, but please review it before sharing.");
+    Ok(())
+}
+
+async fn show_revision_diff(command_name: &str, revisions: &[&String]) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let Some(current) = cache.current_revision(command_name) else {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+
+    let parse = |rev: &String| -> anyhow::Result<u32> {
+        rev.trim_start_matches('r')
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid revision '{}'", rev))
+    };
+    let (rev_a, rev_b) = match revisions {
+        [] => (current.saturating_sub(1).max(1), current),
+        [a] => (parse(a)?, current),
+        [a, b, ..] => (parse(a)?, parse(b)?),
+    };
+
+    if rev_a == rev_b {
+        if current == 1 {
+            println!("ℹ️  '{}' has a single revision; nothing to compare", command_name);
+        } else if rev_a == current {
+            println!("ℹ️  Revision {} of '{}' is the current script; nothing to compare", rev_a, command_name);
+        } else {
+            println!("ℹ️  Both sides are revision {} of '{}'; nothing to compare", rev_a, command_name);
+        }
+        return Ok(());
+    }
+
+    let old = cache.get_revision(command_name, rev_a)?;
+    let new = cache.get_revision(command_name, rev_b)?;
+    script_diff::write_revision_diff(
+        &mut std::io::stdout(),
+        (&old.script, &old.permissions),
+        (&new.script, &new.permissions),
+        &format!("{}@{}", command_name, rev_a),
+        &format!("{}@{}", command_name, rev_b),
+    )?;
+    Ok(())
+}
+
+/// Prints the changelog of a cached command, summarized by the model if
+/// `summarize` is set.
+async fn show_changelog(command_name: &str, summarize: bool) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let Some(changelog) = Changelog::build(&cache, command_name)? else {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+    println!("{}", changelog);
+
+    if summarize && changelog.current > 1 {
+        println!();
+        println!("🤖 Asking Claude to summarize the changes...");
+        let summary = LlmGenerator::new()
+            .summarize_changelog(command_name, &changelog.to_string())
+            .await?;
+        println!("🧾 Summary:");
+        for line in summary.lines() {
+            println!("   {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Regenerates a cached command from the prompt recorded in its provenance,
+/// with the recorded model and settings if `same_model` is set, or with
+/// `model` if given. A model chosen with `--model` when the command was
+/// generated is kept otherwise.
+///
+/// Generated for another platform, the result is stored as this platform's
+/// variant; otherwise it becomes a new revision.
+async fn regenerate_command(command_name: &str, same_model: bool, model: Option<&str>) -> anyhow::Result<()> {
+    let mut cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(command_name).await? else {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+    if let Some(revision) = cache.pinned_revision(command_name) {
+        println!(
+            "📌 '{}' is pinned at revision {}. Run `ergo --unpin {}` to regenerate it.",
+            command_name, revision, command_name
+        );
+        std::process::exit(1);
+    }
+    let Some(provenance) = &command.provenance else {
+        anyhow::bail!(
+            "'{}' has no recorded provenance: it was generated by a plugin or before ergo recorded how commands were generated",
+            command_name
+        );
+    };
+
+    let os = std::env::consts::OS;
+    let as_variant = provenance.os != os;
+    if same_model {
+        println!(
+            "🔁 Regenerating '{}' with {} model {} as it was generated...",
+            command_name, provenance.backend, provenance.model
+        );
+    } else {
+        println!("🔁 Regenerating '{}' from its original prompt...", command_name);
+    }
+    if as_variant {
+        println!("   It was generated for {}; the new script becomes the {} variant.", provenance.os, os);
+    }
+    let backend = Config::load()?.generator.backend;
+    let mut generator = LlmGenerator::new();
+    if !same_model && let Some(model) = model.or_else(|| provenance.chosen_model(&backend)) {
+        println!("   Using model {}", model);
+        generator = generator.with_model(model);
+    }
+    let generation = generator
+        .regenerate_from_provenance(command_name, provenance, same_model)
+        .await?;
+    let generation = vet_regeneration(command_name, generation, &Config::load()?)?;
+    write_regeneration_diff(&cache, command_name, &command, &generation, as_variant)?;
+    store_regeneration(&mut cache, command_name, &generation, as_variant).await
+}
+
+/// Pins the std imports of a regenerated script and runs the validators on it.
+fn vet_regeneration(
+    command_name: &str,
+    mut generation: GenerationResult,
+    config: &Config,
+) -> anyhow::Result<GenerationResult> {
+    if let Ok((script, count)) = deno_deps::pin_std_imports(&generation.script_content, &config.deps.std_version)
+        && count > 0
+    {
+        generation.script_content = script;
+    }
+    // A replay reproduces the original prompt, so failures aren't repaired
+    let issues = ValidationPipeline::from_config(&config.validation, &config.safety).validate(&generation, &[]);
+    if !issues.is_empty() {
+        return Err(anyhow::anyhow!(
+            "🛑 Not storing the regenerated '{}', it fails validation:\n{}",
+            command_name,
+            validation::format_issues(&issues)
+        ));
+    }
+    Ok(generation)
+}
+
+/// Prints the diff between the script in use and a regenerated one.
+fn write_regeneration_diff(
+    cache: &CommandCache,
+    command_name: &str,
+    command: &GeneratedCommand,
+    generation: &GenerationResult,
+    as_variant: bool,
+) -> anyhow::Result<()> {
+    let os = std::env::consts::OS;
+    let current = command.for_platform(os);
+    let old_script = cache.get_script_content(&current).unwrap_or_default();
+    let (old_label, new_label) = if as_variant {
+        (format!("{}.{}", command_name, os), format!("{}.{} (regenerated)", command_name, os))
+    } else {
+        let revision = cache.current_revision(command_name).unwrap_or(1);
+        (format!("{}@{}", command_name, revision), format!("{}@{}", command_name, revision + 1))
+    };
+    script_diff::write_revision_diff(
+        &mut std::io::stdout(),
+        (&old_script, &current.permissions),
+        (&generation.script_content, &generation.command.permissions),
+        &old_label,
+        &new_label,
+    )?;
+    Ok(())
+}
+
+/// Stores a regenerated script as a new revision, or as the host platform's
+/// variant of a command generated for another platform.
+async fn store_regeneration(
+    cache: &mut CommandCache,
+    command_name: &str,
+    generation: &GenerationResult,
+    as_variant: bool,
+) -> anyhow::Result<()> {
+    let os = std::env::consts::OS;
+    let regenerated = &generation.command;
+    if as_variant {
+        cache
+            .store_variant(command_name, os, &regenerated.permissions, &generation.script_content)
+            .await?;
+        println!("✅ Stored the regenerated script as the {} variant of '{}'", os, command_name);
+    } else {
+        cache.store_command(command_name, regenerated, &generation.script_content).await?;
+        println!("✅ Regenerated '{}'", command_name);
+    }
+    Ok(())
+}
+
+/// Regenerates the cached commands matching `filters` with the configured
+/// model and the current prompt, showing each diff. Every new version is
+/// confirmed; with `auto`, it's stored if it passes the command's tests.
+async fn regenerate_all(filters: &[RegenerateFilter], auto: bool, model: Option<&str>) -> anyhow::Result<()> {
+    let mut cache = CommandCache::new().await?;
+    let mut names = cache.list_cached_commands().await;
+    names.sort();
+
+    let mut selected = Vec::new();
+    let mut unregenerable = 0;
+    for name in names {
+        let Some(command) = cache.get_command(&name).await? else {
+            continue;
+        };
+        if !regenerate_filter::matches_all(filters, &command) {
+            continue;
+        }
+        if let Some(revision) = cache.pinned_revision(&name) {
+            println!("📌 Skipping '{}', pinned at revision {}", name, revision);
+        } else if command.provenance.is_none() {
+            unregenerable += 1;
+        } else {
+            selected.push((name, command));
+        }
+    }
+    if unregenerable > 0 {
+        println!(
+            "ℹ️  Skipping {} command(s) without recorded provenance (generated by a plugin or an older ergo)",
+            unregenerable
+        );
+    }
+    if selected.is_empty() {
+        println!("❌ No cached command to regenerate");
+        return Ok(());
+    }
+
+    let config = Config::load()?;
+    let mut generator = LlmGenerator::new();
+    if let Some(model) = model {
+        generator = generator.with_model(model);
+    }
+    let executor = Executor::new(false);
+    let os = std::env::consts::OS;
+    let total = selected.len();
+    let (mut replaced, mut kept, mut failed) = (0, 0, 0);
+    for (index, (name, command)) in selected.into_iter().enumerate() {
+        let Some(provenance) = &command.provenance else {
+            continue;
+        };
+        println!("\n🔁 [{}/{}] Regenerating '{}' (generated with {})...", index + 1, total, name, provenance.model);
+        let generation = match generator.regenerate_with_current_prompt(&name, provenance).await {
+            Ok(generation) => vet_regeneration(&name, generation, &config),
+            Err(e) => Err(e),
+        };
+        let generation = match generation {
+            Ok(generation) => generation,
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                failed += 1;
+                continue;
+            }
+        };
+        let as_variant = provenance.os != os;
+        let current = command.for_platform(os);
+        if cache.get_script_content(&current).ok().as_deref() == Some(generation.script_content.as_str())
+            && current.permissions == generation.command.permissions
+        {
+            println!("📄 Unchanged");
+            kept += 1;
+            continue;
+        }
+        write_regeneration_diff(&cache, &name, &command, &generation, as_variant)?;
+
+        let accepted = if auto {
+            match cache.get_test_script(&name)? {
+                Some(tests) => {
+                    let outcome = executor.run_command_tests(&tests, &generation.script_content)?;
+                    if !outcome.passed {
+                        eprintln!("🛑 The regenerated '{}' fails its tests; keeping the current version", name);
+                    }
+                    outcome.passed
+                }
+                None => {
+                    println!("⏭️  '{}' has no tests; keeping the current version (run without --auto to review it)", name);
+                    false
+                }
+            }
+        } else {
+            confirm(&format!("Replace '{}' with the regenerated version?", name))?
+        };
+        if accepted {
+            store_regeneration(&mut cache, &name, &generation, as_variant).await?;
+            replaced += 1;
+        } else {
+            kept += 1;
+        }
+    }
+    println!("\n📊 {} regenerated, {} kept, {} failed", replaced, kept, failed);
+    Ok(())
+}
+
+/// Imports the commands published at `url` (a bioma tarball or a single
+/// command file) into the cache. Each shows its script and asks for consent
+/// on every run until its permissions are allowed forever.
+async fn import_url(url: &str, sha256: Option<&str>) -> anyhow::Result<()> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        anyhow::bail!("'{}' isn't an http(s) URL", url);
+    }
+    println!("📥 Downloading {}...", url);
+    let (bytes, verification) = import::download(&ReqwestHttpClient::new(), url, sha256).await?;
+    match verification {
+        Verification::Given => println!("🔒 Checksum verified"),
+        Verification::Published => println!("🔒 Checksum verified against {}.sha256", url),
+        Verification::Unverified(digest) => println!(
+            "⚠️  No checksum is published next to it. SHA-256 of the download: {}\n   Pass --sha256 with a checksum you trust to check it",
+            digest
+        ),
+    }
+    let bundle = import::parse_bundle(&bytes)?;
+    for skipped in &bundle.skipped {
+        println!("⏭️  Skipping {}", skipped);
+    }
+
+    let mut cache = CommandCache::new().await?;
+    let mut imported = 0;
+    for ImportedCommand {
+        mut command,
+        script,
+        variant_scripts,
+    } in bundle.commands
+    {
+        let name = command.name.clone();
+        if cache.get_command(&name).await?.is_some() {
+            println!("⏭️  Skipping '{}': a command with that name is already cached", name);
+            continue;
+        }
+        let variants = std::mem::take(&mut command.variants);
+        cache.store_command(&name, &command, &script).await?;
+        for (os, variant) in variants {
+            cache
+                .store_variant(&name, &os, &variant.permissions, &variant_scripts[&os])
+                .await?;
+        }
+        cache.set_imported_from(&name, url).await?;
+        println!("📦 Imported '{}': {}", name, command.description);
+        imported += 1;
+    }
+    if imported > 0 {
+        println!(
+            "✅ Imported {} command(s). Each shows its script and asks for consent when run, until you allow it forever",
+            imported
+        );
+    } else {
+        println!("❌ Nothing was imported");
+    }
+    Ok(())
+}
+
+/// Moves the std imports of a command and its platform variants to the
+/// configured std version, showing what changed.
+async fn bump_deps(command_name: &str) -> anyhow::Result<()> {
+    let version = Config::load()?.deps.std_version;
+    let mut cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(command_name).await? else {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+
+    let mut bumped_any = false;
+    let script = cache.get_script_content(&command)?;
+    let (bumped, count) = deno_deps::bump_std_imports(&script, &version)?;
+    if count > 0 {
+        let revision = cache.current_revision(command_name).unwrap_or(1);
+        script_diff::write_revision_diff(
+            &mut std::io::stdout(),
+            (&script, &command.permissions),
+            (&bumped, &command.permissions),
+            &format!("{}@{}", command_name, revision),
+            &format!("{}@{}", command_name, revision + 1),
+        )?;
+        cache.store_command(command_name, &command, &bumped).await?;
+        println!("⬆️  Moved {} std import(s) of '{}' to std@{}", count, command_name, version);
+        bumped_any = true;
+    }
+
+    for (os, variant) in &command.variants {
+        let script = cache.get_script_content(&command.for_platform(os))?;
+        let (bumped, count) = deno_deps::bump_std_imports(&script, &version)?;
+        if count == 0 {
+            continue;
+        }
+        let label = format!("{}.{}", command_name, os);
+        script_diff::write_revision_diff(
+            &mut std::io::stdout(),
+            (&script, &variant.permissions),
+            (&bumped, &variant.permissions),
+            &label,
+            &format!("{} (bumped)", label),
+        )?;
+        cache.store_variant(command_name, os, &variant.permissions, &bumped).await?;
+        println!("⬆️  Moved {} std import(s) of the {} variant to std@{}", count, os, version);
+        bumped_any = true;
+    }
+
+    if !bumped_any {
+        println!("✅ '{}' already imports std@{}", command_name, version);
+    }
+    Ok(())
+}
+
+/// Lists the remote imports of one cached command, or of all of them, and
+/// flags outdated, unpinned and vulnerable dependencies.
+async fn show_deps(command_name: Option<&str>) -> anyhow::Result<()> {
+    let deps = Config::load()?.deps;
+    let cache = CommandCache::new().await?;
+    let names = match command_name {
+        Some(name) => vec![name.to_string()],
+        None => {
+            let mut names = cache.list_cached_commands().await;
+            names.sort();
+            names
+        }
+    };
+    if names.is_empty() {
+        println!("📭 No cached commands");
+        return Ok(());
+    }
+
+    let mut inventories = Vec::new();
+    for name in &names {
+        let Some(command) = cache.get_command(name).await? else {
+            println!("❌ Command '{}' not found in cache", name);
+            return Ok(());
+        };
+        let mut scripts = vec![cache.get_script_content(&command)?];
+        for os in command.variants.keys() {
+            scripts.push(cache.get_script_content(&command.for_platform(os))?);
+        }
+        inventories.push(CommandInventory::audit(name, &scripts, &deps.std_version));
+    }
+
+    let client = ReqwestHttpClient::new();
+    if let Err(e) = dep_audit::check_advisories(&client, &deps.advisory_url, &mut inventories).await {
+        println!("⚠️  Couldn't check advisories at {}: {}", deps.advisory_url, e);
+    }
+
+    for inventory in inventories.iter().filter(|i| command_name.is_some() || !i.imports.is_empty()) {
+        println!("{}", inventory);
+    }
+    let flagged = inventories.iter().filter(|i| i.has_issues()).count();
+    if flagged == 0 {
+        println!("✅ No outdated, unpinned or vulnerable dependencies");
+    } else {
+        println!("⚠️  {} command(s) with dependencies worth a look", flagged);
+    }
+    Ok(())
+}
+
+/// Summarizes which cached commands hold which permissions.
+async fn show_permissions_report() -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let mut commands = Vec::new();
+    for name in cache.list_cached_commands().await {
+        if let Some(command) = cache.get_command(&name).await? {
+            commands.push(command);
+        }
+    }
+    if commands.is_empty() {
+        println!("📭 No cached commands");
+        return Ok(());
+    }
+    println!("{}", PermissionSummary::build(&commands));
+    Ok(())
+}
+
+/// Runs a cached command with every permission denied in a scratch
+/// directory and compares what it attempted with what it declares.
+async fn simulate_command(command_name: &str, args: &[String]) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(command_name).await? else {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+    let command = command.for_platform(std::env::consts::OS);
+    let runner = SystemProcessRunner::default();
+    if !runner.program_exists("deno") {
+        println!("❌ Deno is not installed; it is needed to simulate '{}'", command_name);
+        return Ok(());
+    }
+
+    let temp_dir = tempfile::Builder::new().prefix("ergo-simulate-").tempdir()?;
+    let dir = temp_dir.path();
+    let script_path = dir.join(format!("{}.ts", command_name));
+    std::fs::write(&script_path, cache.get_script_content(&command)?)?;
+    println!(
+        "🧪 Simulating '{}' with every permission denied, in {}",
+        command_name,
+        dir.display()
+    );
+    let simulation = permission_sim::simulate(&runner, &script_path, args, dir);
+    drop(temp_dir);
+
+    println!("{}", PermissionReport::compare(command_name, &command.permissions, simulation?));
+    Ok(())
+}
+
+async fn set_output_processors(command_name: &str, list: &str) -> anyhow::Result<()> {
+    let processors = match list {
+        "default" => None,
+        list => Some(output_processing::parse_processors(list)?),
+    };
+
+    let mut cache = CommandCache::new().await?;
+    if !cache.set_output_processors(command_name, processors.clone()).await? {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    }
+
+    match processors {
+        None => println!("✅ '{}' now uses the global output processors", command_name),
+        Some(p) if p.is_empty() => println!("✅ '{}' output will not be processed", command_name),
+        Some(p) => {
+            let names: Vec<String> = p.iter().map(|p| p.to_string()).collect();
+            println!("✅ '{}' output processors: {}", command_name, names.join(", "));
+        }
+    }
+    Ok(())
+}
+
+async fn set_note(command_name: &str, note: &str) -> anyhow::Result<()> {
+    let mut cache = CommandCache::new().await?;
+    if !cache.set_note(command_name, Some(note.to_string())).await? {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    }
+
+    match cache.get_note(command_name) {
+        Some(note) => println!("🗒️  Noted on '{}': {}", command_name, note),
+        None => println!("🗑️  Removed the note of '{}'", command_name),
+    }
+    Ok(())
+}
+
+async fn save_preset(command_name: &str, preset: &str, args: &[String]) -> anyhow::Result<()> {
+    let preset = preset.strip_prefix('@').unwrap_or(preset);
+    if preset.is_empty() || preset.starts_with('@') {
+        println!("❌ Invalid preset name '{}'", preset);
+        return Ok(());
+    }
+
+    let mut cache = CommandCache::new().await?;
+    if !cache.save_preset(command_name, preset, args.to_vec()).await? {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    }
+
+    if args.is_empty() {
+        println!("🗑️  Removed preset '@{}' of '{}'", preset, command_name);
+    } else {
+        println!("✅ Saved preset: ergo {} @{} → {}", command_name, preset, args.join(" "));
+    }
+    Ok(())
+}
+
+/// Rates the current revision of the last command run as working.
+async fn rate_last_command(comment: &str, pin: bool) -> anyhow::Result<()> {
+    let Some(context) = ExecutionContext::load()? else {
+        eprintln!("No previous command execution found. Run a command first, then use --yep.");
+        return Ok(());
+    };
+
+    let mut cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(&context.command_name).await? else {
+        println!("❌ Command '{}' not found in cache", context.command_name);
+        return Ok(());
+    };
+    if cache.get_script_content(&command.for_platform(std::env::consts::OS))? != context.script_content {
+        println!(
+            "❌ '{}' changed since it last ran. Run it again before rating it.",
+            context.command_name
+        );
+        return Ok(());
+    }
+    if !context.success {
+        println!("⚠️  The last run of '{}' failed; rating it anyway", context.command_name);
+    }
+
+    let comment = (!comment.is_empty()).then(|| comment.to_string());
+    let Some(rating) = cache
+        .rate_command(&context.command_name, comment, pin, context.environment.clone())
+        .await? else {
+        return Ok(());
+    };
+    println!("👍 Rated '{}' revision {} as working", context.command_name, rating.revision);
+    if rating.pinned {
+        println!("📌 Pinned: --nope won't replace it until you run `ergo --unpin {}`", context.command_name);
+    }
+    Ok(())
+}
+
+/// Queues an intent to be generated later with `--flush-queue`.
+fn queue_intent(intent: Vec<String>) -> anyhow::Result<()> {
+    let path = intent_queue::default_path()?;
+    let queued = QueuedIntent {
+        intent,
+        cwd: std::env::current_dir()?,
+        queued_at: SystemTimeProvider.now(),
+    };
+    intent_queue::push(&path, &queued)?;
+    let waiting = intent_queue::read(&path)?.len();
+    println!("📥 Queued '{}' ({} waiting)", queued.display(), waiting);
+    println!("   Run `ergo --flush-queue` once you're back online.");
+    Ok(())
+}
+
+/// Generates every queued intent from the directory it was queued in, and
+/// keeps the ones that failed queued.
+async fn flush_queue(retries: u32, verbose: bool) -> anyhow::Result<()> {
+    let path = intent_queue::default_path()?;
+    let queued = intent_queue::read(&path)?;
+    if queued.is_empty() {
+        println!("📭 The queue is empty");
+        return Ok(());
+    }
+    println!("📤 Generating {} queued intent(s)...", queued.len());
+
+    let cancellation = Cancellation::new();
+    cancellation.cancel_on_ctrl_c();
+    let mut remaining = Vec::new();
+    let mut interrupted = false;
+    for item in queued {
+        if interrupted {
+            remaining.push(item);
+            continue;
+        }
+        match prepare_queued(&item, &cancellation, retries, verbose).await {
+            Ok(PrepareOutcome::Generated { command }) => {
+                println!("✅ {} → '{}': {}", item.display(), command.name, command.description);
+            }
+            Ok(PrepareOutcome::AlreadyCached { command }) => {
+                println!("📦 {} → '{}' was already generated", item.display(), command.name);
+            }
+            Err(e) => {
+                interrupted = matches!(e, ErgoError::Interrupted);
+                if !interrupted {
+                    println!("❌ {}: {}", item.display(), e);
+                }
+                remaining.push(item);
+            }
+        }
+    }
+
+    intent_queue::replace(&path, &remaining)?;
+    if interrupted {
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    if !remaining.is_empty() {
+        println!("⚠️  {} intent(s) stay queued; run `ergo --flush-queue` again to retry them", remaining.len());
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Generates a queued intent from the directory it was queued in.
+async fn prepare_queued(
+    item: &QueuedIntent,
+    cancellation: &Cancellation,
+    retries: u32,
+    verbose: bool,
+) -> Result<PrepareOutcome, ErgoError> {
+    std::env::set_current_dir(&item.cwd)
+        .map_err(|e| anyhow::anyhow!("can't enter {}: {}", item.cwd.display(), e))?;
+    let mut router = CommandRouter::new(verbose).await?.with_cancellation(cancellation.clone());
+    router.set_generation_retries(retries);
+    router.prepare_intent(&item.intent).await
+}
+
+/// Suggests commands for the project in the current directory and generates
+/// the ones the user picks.
+async fn suggest_commands(retries: u32, verbose: bool) -> anyhow::Result<()> {
+    let context = ProjectContext::detect(&std::env::current_dir()?);
+    if context.is_empty() {
+        println!("🤷 No project detected here (no package.json, Cargo.toml, Makefile, ...)");
+        println!("   Run `ergo suggest` from a project's root directory.");
+        return Ok(());
+    }
+    let mut detected = context.languages.clone();
+    detected.extend(context.frameworks.iter().cloned());
+    println!("🔍 Detected {} with {} script(s)", detected.join(", "), context.scripts.len());
+
+    let existing = CommandCache::new().await?.list_cached_commands().await;
+    println!("🤖 Asking Claude for command ideas...");
+    let suggestions = LlmGenerator::new().suggest_commands(&context.summary(), &existing).await?;
+    if suggestions.is_empty() {
+        println!("🤷 Claude had no suggestions for this project");
+        return Ok(());
+    }
+    println!();
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        println!("{:>3}. {}", i + 1, suggestion.intent);
+        if !suggestion.reason.is_empty() {
+            println!("     {}", suggestion.reason);
+        }
+    }
+    println!();
+
+    if !std::io::stdin().is_terminal() {
+        println!("💡 Run `ergo suggest` in a terminal to pick commands, or generate one with `ergo \"<intent>\"`.");
+        return Ok(());
+    }
+    let selected = loop {
+        print!("Generate which? (e.g. 1,3 or 2-4, all; Enter for none): ");
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            break Vec::new();
+        }
+        match suggest::parse_selection(&line, suggestions.len()) {
+            Ok(selected) => break selected,
+            Err(e) => println!("⚠️  {}", e),
+        }
+    };
+    if selected.is_empty() {
+        println!("👋 Nothing generated");
+        return Ok(());
+    }
+
+    println!("🧬 Generating {} command(s)...", selected.len());
+    let mut router = interruptible_router(verbose).await?;
+    router.set_generation_retries(retries);
+    let mut failures = 0;
+    for i in selected {
+        let intent = &suggestions[i].intent;
+        match exit_if_interrupted(router.prepare_intent(std::slice::from_ref(intent)).await) {
+            Ok(PrepareOutcome::Generated { command }) => {
+                println!("✅ '{}': {}", command.name, command.description);
+            }
+            Ok(PrepareOutcome::AlreadyCached { command }) => {
+                println!("📦 '{}' was already generated", command.name);
+            }
+            Err(e) => {
+                failures += 1;
+                println!("❌ {}: {}", intent, e);
+            }
+        }
+    }
+    if failures > 0 {
+        println!("⚠️  {} command(s) failed to generate", failures);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Prints the most recent model API exchange captured with `--debug-llm`.
+fn show_last_llm() -> anyhow::Result<()> {
+    let Some((path, exchange)) = llm_debug::latest(&llm_debug::default_dir()?)? else {
+        println!("📭 No model API exchanges captured yet; run ergo with --debug-llm");
+        return Ok(());
+    };
+    println!("🔍 {}", path.display());
+    println!("📤 POST {}", exchange.url);
+    for (name, value) in &exchange.headers {
+        println!("   {}: {}", name, value);
+    }
+    println!("{}", serde_json::to_string_pretty(&exchange.request)?);
+    match (&exchange.response, &exchange.error) {
+        (_, Some(error)) => println!("\n❌ {}", error),
+        (Some(response), None) => println!("\n📥 Response:\n{}", serde_json::to_string_pretty(response)?),
+        (None, None) => println!("\n📥 No response recorded"),
+    }
+    Ok(())
+}
+
+/// Asks a yes/no question on the terminal; anything but yes means no.
+///
+/// When stdin is piped (`make 2>&1 | ergo --why make`), the answer is read
+/// from the controlling terminal instead.
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    let mut terminal: Box<dyn std::io::BufRead> = if std::io::stdin().is_terminal() {
+        Box::new(std::io::stdin().lock())
+    } else {
+        match std::fs::File::open("/dev/tty") {
+            Ok(tty) => Box::new(std::io::BufReader::new(tty)),
+            Err(_) => {
+                println!("{} Rerun ergo without piping its input to answer.", question);
+                return Ok(false);
+            }
+        }
+    };
+    print!("{} [y/N]: ", question);
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut line = String::new();
+    terminal.read_line(&mut line)?;
+    Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+async fn why_failed(command_line: &str, error_output: Option<&String>, verbose: bool) -> anyhow::Result<()> {
+    // Without error output on the command line, read it from a pipe:
+    // `make 2>&1 | ergo --why make`
+    let error_output = match error_output {
+        Some(output) => output.clone(),
+        None if !std::io::stdin().is_terminal() => std::io::read_to_string(std::io::stdin())?,
+        None => String::new(),
+    };
+
+    println!("🤖 Asking Claude why '{}' failed...", command_line);
+    let diagnosis = LlmGenerator::new().diagnose_failure(command_line, &error_output).await?;
+    println!();
+    println!("🩺 {}", diagnosis.explanation);
+
+    if let Some(fix) = &diagnosis.fixed_command {
+        println!();
+        println!("💡 Suggested fix:");
+        println!("   {}", fix);
+        if confirm("\nRun it?")? {
+            let status = SystemProcessRunner::default().run_interactive(None, "sh", &["-c", fix])?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(1));
+            }
+        }
+    } else if let Some(intent) = &diagnosis.ergo_intent {
+        println!();
+        println!("💡 ergo can do this instead: {}", intent);
+        if confirm("\nGenerate and run it?")? {
+            let mut router = interruptible_router(verbose).await?;
+            let outcome = exit_if_interrupted(router.process_intent(vec![intent.clone()]).await)?;
+            report_outcome(&outcome, verbose);
+        }
+    }
+    Ok(())
+}
+
+/// Prints GitHub Actions steps running a cached command, with hints on
+/// stderr so the steps can be redirected into a workflow file.
+async fn export_ci_steps(command_name: &str, args: &[String], inline: bool) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(command_name).await? else {
+        eprintln!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+
+    // GitHub-hosted runners are Linux unless the job says otherwise
+    let command = command.for_platform("linux");
+    let script_path = cache.command_dir(command_name).map(|dir| dir.join(&command.script_file));
+    let repository_path = script_path.as_deref().and_then(|path| {
+        let root = ci_export::repository_root(&std::env::current_dir().ok()?)?;
+        ci_export::repository_path(&root, path)
+    });
+    let source = match repository_path {
+        Some(path) if !inline => ScriptSource::Repository(path),
+        _ => ScriptSource::Inline(cache.get_script_content(&command)?),
+    };
+
+    let deno_version = match cache.get_rating(command_name).and_then(|r| r.environment.as_ref()) {
+        Some(environment) => environment.deno_version.clone(),
+        None => {
+            let local = environment::deno_version(&SystemProcessRunner::default());
+            match &local {
+                Some(version) => eprintln!(
+                    "💡 '{}' hasn't been marked as working with --yep; pinning your local Deno {}",
+                    command_name, version
+                ),
+                None => eprintln!(
+                    "💡 '{}' hasn't been marked as working with --yep and Deno isn't installed; using the latest {}",
+                    command_name,
+                    ci_export::FALLBACK_DENO_VERSION
+                ),
+            }
+            local
+        }
+    };
+
+    print!("{}", ci_export::render_steps(&command, &source, deno_version.as_deref(), args));
+    match &source {
+        ScriptSource::Repository(path) => {
+            eprintln!("💡 The steps run {} from the repository; make sure it's committed", path.display())
+        }
+        ScriptSource::Inline(_) if !inline => {
+            eprintln!("💡 '{}' isn't in a bioma of this repository, so its script is inlined", command_name)
+        }
+        ScriptSource::Inline(_) => {}
+    }
+    Ok(())
+}
+
+/// Saves the write bioma as the snapshot `name` (see [`snapshot`]).
+fn snapshot_bioma(name: &str) -> anyhow::Result<()> {
+    let bioma = HierarchyPathResolver::new().get_write_dir()?;
+    let path = snapshot::snapshot_path(&snapshot::default_dir()?, name)?;
+    let info = snapshot::create(&bioma, &path, SystemTimeProvider.now(), false)?;
+    println!("📸 Saved {} command(s) of {} as '{}'", info.commands, bioma.display(), name);
+    println!("   Roll back to it with: ergo bioma restore {}", name);
+    Ok(())
+}
+
+/// Replaces the write bioma with the snapshot `name`, saving it first as
+/// [`snapshot::BEFORE_RESTORE`].
+fn restore_bioma(name: &str) -> anyhow::Result<()> {
+    if ephemeral::is_enabled() {
+        anyhow::bail!("Restoring a snapshot rewrites the bioma on disk, which --ephemeral doesn't allow");
+    }
+    let dir = snapshot::default_dir()?;
+    let path = snapshot::snapshot_path(&dir, name)?;
+    if !path.exists() {
+        let available = snapshot::list(&dir)?;
+        if available.is_empty() {
+            anyhow::bail!("There's no snapshot '{}'; take one with: ergo bioma snapshot <name>", name);
+        }
+        anyhow::bail!("There's no snapshot '{}'; the snapshots are: {}", name, available.join(", "));
+    }
+
+    let bioma = HierarchyPathResolver::new().get_write_dir()?;
+    let info = snapshot::read_info(&path)?;
+    if info.bioma != bioma {
+        println!(
+            "⚠️  '{}' was taken of {}, but commands are written to {} here",
+            name,
+            info.bioma.display(),
+            bioma.display()
+        );
+        if !confirm("Restore it here anyway?")? {
+            println!("❌ Nothing was restored");
+            return Ok(());
+        }
+    }
+    // Restoring the safety snapshot itself mustn't overwrite it first
+    let saved = name != snapshot::BEFORE_RESTORE && bioma.is_dir();
+    if saved {
+        let before = snapshot::snapshot_path(&dir, snapshot::BEFORE_RESTORE)?;
+        snapshot::create(&bioma, &before, SystemTimeProvider.now(), true)?;
+    }
+    snapshot::restore(&path, &bioma)?;
+    println!("⏪ Restored {} command(s) from '{}' into {}", info.commands, name, bioma.display());
+    if saved {
+        println!("   The bioma as it was is saved as '{}'", snapshot::BEFORE_RESTORE);
+    }
+    Ok(())
+}
+
+/// Caches the imports of the most-used commands (see [`preload`]).
+async fn warm_bioma() -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let cache = CommandCache::new().await?;
+    let report = preload::preload(&cache, &SystemProcessRunner::default(), &config.preload).await?;
+    if report.preloaded.is_empty() && report.failed.is_empty() {
+        println!("🔥 No command has been run yet, so there's nothing to preload");
+        return Ok(());
+    }
+    println!("🔥 Preloaded {} command(s): {}", report.preloaded.len(), report.preloaded.join(", "));
+    for (name, reason) in &report.failed {
+        println!("⚠️  Couldn't preload '{}': {}", name, reason);
+    }
+    Ok(())
+}
+
+/// Removes the latency log, the usage ledger and the `--debug-llm` captures,
+/// or their entries from before `before` (see [`retention`]).
+fn clear_history(before: Option<&str>) -> anyhow::Result<()> {
+    let cutoff = before.map(retention::parse_date).transpose()?;
+    let mut removed = 0;
+    for log in [latency::default_path()?, usage_ledger::default_path()?] {
+        removed += retention::clear_log(&log, cutoff)?;
+    }
+    removed += retention::clear_dir(&llm_debug::default_dir()?, cutoff)?;
+    match before {
+        Some(date) => println!("🧹 Removed {} history entries from before {}", removed, date),
+        None => println!("🧹 Removed {} history entries", removed),
+    }
+    Ok(())
+}
+
+/// Installs a git hook (or pre-commit framework hooks) running cached
+/// commands that may run without asking for permission.
+async fn install_git_hook(hook: &str, commands: &[String], framework: bool) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let mut unapproved = Vec::new();
+    for name in commands {
+        if cache.get_command(name).await?.is_none() {
+            println!("❌ Command '{}' not found in cache", name);
+            std::process::exit(1);
+        }
+        if cache.needs_permission_consent(name) {
+            unapproved.push(name.as_str());
+        }
+    }
+    if !unapproved.is_empty() {
+        println!("🔐 Hooks can't ask for permission. Run these once and always allow them first:");
+        for name in unapproved {
+            println!("   ergo {}", name);
+        }
+        std::process::exit(1);
+    }
+
+    let runner = SystemProcessRunner::default();
+    if framework {
+        let path = git_hooks::repository_root(&runner)?.join(git_hooks::FRAMEWORK_CONFIG_FILE);
+        if path.exists() {
+            println!("📋 {} already exists; add this to its repos: list", path.display());
+            print!("{}", git_hooks::render_framework_repo(commands));
+        } else {
+            std::fs::write(&path, git_hooks::render_framework_config(commands))?;
+            println!("✅ Wrote {}", path.display());
+            println!("   Run `pre-commit install` to activate it");
+        }
+        return Ok(());
+    }
+
+    match git_hooks::install_hook(&git_hooks::hook_path(&runner, hook)?, hook, commands)? {
+        HookInstall::Written(path) => {
+            println!("✅ Installed {} hook at {}", hook, path.display());
+            println!("   Runs: {}", commands.join(", "));
+        }
+        HookInstall::Foreign { path, lines } => {
+            println!("⚠️  {} wasn't written by ergo, so it was left alone. Add these lines to it:", path.display());
+            print!("{}", lines);
+        }
+    }
+    Ok(())
+}
+
+/// Writes `.vscode/tasks.json` in the current project with a task for each
+/// command of its bioma, keeping the tasks ergo didn't write.
+async fn export_vscode_tasks() -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let mut commands = cache.list_commands().await;
+    commands.sort_by(|a, b| a.0.cmp(&b.0));
+    let Some((first, _, _)) = commands.first() else {
+        println!("📭 No commands cached in this project yet");
+        return Ok(());
+    };
+
+    let home = dirs::home_dir();
+    let layout = Config::load()?.cache;
+    let Some(root) = cache
+        .command_dir(first)
+        .and_then(|dir| project_scope::project_root(&dir, home.as_deref(), &layout))
+    else {
+        println!("❌ No project bioma found here; only commands in a project's .abiogenesis/biomas are exported");
+        return Ok(());
+    };
+
+    let mut export = vscode_tasks::Export::default();
+    for (name, command, _) in &commands {
+        export.add(command, &cache.preset_names(name));
+    }
+    let path = root.join(".vscode").join("tasks.json");
+    let existing = std::fs::read_to_string(&path).ok();
+    let tasks = vscode_tasks::merge_tasks(existing.as_deref(), &export)?;
+    std::fs::create_dir_all(root.join(".vscode"))?;
+    std::fs::write(&path, tasks)?;
+    println!("✅ Wrote {} task(s) to {}", export.tasks.len(), path.display());
+    if existing.is_some_and(|text| text.contains("//") || text.contains("/*")) {
+        println!("⚠️  Comments in the previous file were not kept");
+    }
+    println!("   Run them with Terminal > Run Task...");
+    Ok(())
+}
+
+/// Prints (or writes to `dir`) a Raycast script command running a cached
+/// command from its project, or from the home directory.
+async fn export_raycast_script(command_name: &str, dir: Option<&str>) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(command_name).await? else {
+        eprintln!("❌ Command '{}' not found in cache", command_name);
+        std::process::exit(1);
+    };
+
+    let home = dirs::home_dir();
+    let layout = Config::load()?.cache;
+    let working_dir = cache
+        .command_dir(command_name)
+        .and_then(|bioma| project_scope::project_root(&bioma, home.as_deref(), &layout))
+        .or(home)
+        .ok_or_else(|| anyhow::anyhow!("Could not determine the home directory"))?;
+    let script = raycast_export::render_script(&command, &std::env::current_exe()?, &working_dir);
+
+    match dir {
+        Some(dir) => {
+            let path = std::path::Path::new(dir).join(raycast_export::script_file_name(command_name));
+            std::fs::create_dir_all(dir)?;
+            std::fs::write(&path, script)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+            }
+            eprintln!("✅ Wrote {}", path.display());
+        }
+        None => print!("{}", script),
+    }
+    if cache.needs_permission_consent(command_name) {
+        eprintln!(
+            "🔐 Raycast can't ask for permission. Run `ergo {}` once and always allow it first.",
+            command_name
+        );
+    }
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    
-    let matches = Command::new("ergo")
+async fn install_man_page(command_name: &str) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(command_name).await? else {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+
+    let presets: Vec<(&str, &[String])> = cache
+        .preset_names(command_name)
+        .into_iter()
+        .map(|preset| (preset, cache.get_preset(command_name, preset).unwrap_or_default()))
+        .collect();
+    let page = docgen::render_man_page(
+        &command,
+        &presets,
+        cache.get_owner(command_name),
+        cache.get_note(command_name),
+        SystemTimeProvider.now(),
+    );
+    let path = docgen::install_man_page(&docgen::user_man_dir()?, command_name, &page)?;
+    println!("✅ Installed man page at {}", path.display());
+    println!("   Try: man {}", command_name);
+    Ok(())
+}
+
+async fn describe_command(command_name: &str) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(command_name).await? else {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+
+    println!("🔧 {}", command.name);
+    println!("📝 {}", command.description);
+    if let Some(owner) = cache.get_owner(command_name) {
+        println!("👤 Generated by {}", owner);
+    }
+    if let Some(note) = cache.get_note(command_name) {
+        println!("🗒️  {}", note);
+    }
+    if command.spec.is_none()
+        && let Some(intent) = cache.get_intent(command_name)
+    {
+        println!("💬 Requested as: {}", intent);
+    }
+    if !command.examples.is_empty() {
+        println!();
+        println!("💡 Examples:");
+        for example in &command.examples {
+            println!("   {}", example);
+        }
+    }
+    if !command.permissions.is_empty() {
+        println!();
+        println!("🔑 Permissions:");
+        for perm in &command.permissions {
+            println!("   🛡️  {} - {}", perm.permission, perm.reason);
+        }
+    }
+    if command.output_format == OutputFormat::Json {
+        println!("📤 Output: JSON (filter with --jq)");
+    }
+    if !command.platforms.is_empty() {
+        println!("🖥️  Platforms: {}", command.platforms.join(", "));
+    }
+    for (os, variant) in &command.variants {
+        println!("🖥️  {} variant: {}", os, variant.script_file);
+    }
+    if let Some(spec) = &command.spec {
+        println!();
+        println!("📄 Generated from this specification:");
+        for line in spec.lines() {
+            println!("   {}", line);
+        }
+    }
+    if !command.assumptions.is_empty() {
+        println!();
+        match command.confidence {
+            Some(confidence) => println!("🤔 Assumptions (confidence: {}):", confidence),
+            None => println!("🤔 Assumptions:"),
+        }
+        for assumption in &command.assumptions {
+            println!("   • {}", assumption);
+        }
+    }
+    if let Some(rating) = cache.get_rating(command_name) {
+        println!();
+        let pinned = if rating.pinned { " 📌 pinned" } else { "" };
+        match &rating.comment {
+            Some(comment) => println!("👍 Revision {} works: {}{}", rating.revision, comment, pinned),
+            None => println!("👍 Revision {} works{}", rating.revision, pinned),
+        }
+        if let Some(env) = &rating.environment {
+            println!(
+                "   Validated with Deno {} and ergo {} on {}",
+                env.deno_version.as_deref().unwrap_or("(unknown)"),
+                env.ergo_version,
+                env.platform
+            );
+        }
+    }
+    let presets = cache.preset_names(command_name);
+    if !presets.is_empty() {
+        println!();
+        println!("📌 Presets:");
+        for preset in presets {
+            let args = cache.get_preset(command_name, preset).unwrap_or_default();
+            println!("   @{} → {}", preset, args.join(" "));
+        }
+    }
+    Ok(())
+}
+
+async fn explain_command(command_name: &str) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(command_name).await? else {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+    let command = command.for_platform(std::env::consts::OS);
+    let script = cache.get_script_content(&command)?;
+
+    println!("🤖 Asking Claude to explain '{}'...", command_name);
+    let explanation = LlmGenerator::new().explain_script(command_name, &script).await?;
+    println!();
+
+    explain::write_explanation(&mut std::io::stdout(), command_name, &script, &explanation)?;
+    Ok(())
+}
+
+async fn review_command(command_name: &str) -> anyhow::Result<()> {
+    let cache = CommandCache::new().await?;
+    let Some(command) = cache.get_command(command_name).await? else {
+        println!("❌ Command '{}' not found in cache", command_name);
+        return Ok(());
+    };
+    let command = command.for_platform(std::env::consts::OS);
+    let script = cache.get_script_content(&command)?;
+
+    let mut static_findings = security_review::static_findings(&script, &command.permissions);
+    static_findings.extend(security_review::blocked_patterns(&script, &command.permissions, &Config::load()?.safety));
+
+    println!("🤖 Asking Claude to audit '{}'...", command_name);
+    let model_review = LlmGenerator::new()
+        .review_script(command_name, &script, &command.permissions)
+        .await?;
+    println!();
+
+    let report = SecurityReport::new(command_name, SystemTimeProvider.now(), static_findings, model_review);
+    security_review::write_report(&mut std::io::stdout(), &report)?;
+
+    let log_path = Config::get_config_dir()?.join(security_review::AUDIT_LOG_FILE);
+    security_review::append_to_audit_log(&log_path, &report)?;
+    println!();
+    println!("📝 Report saved to {}", log_path.display());
+    Ok(())
+}
+
+/// The `ergo` command line; without `subcommands`, every word is part of the
+/// intent.
+fn cli(subcommands: bool) -> Command {
+    let cli = Command::new("ergo")
         .about("AI-powered command interceptor - cogito, ergo sum")
         .long_about("ergo bridges intent (cogito) to execution (sum) by generating commands on the fly when they don't exist")
         .arg(Arg::new("intent")
             .help("The command or intent to execute")
             .num_args(1..))
+        .arg(Arg::new("intent-file")
+            .long("intent-file")
+            .help("Generate a command from the specification in FILE (- reads it from stdin)")
+            .value_name("FILE")
+            .conflicts_with_all(["intent", "parallel", "chain"])
+            .num_args(1))
         .arg(Arg::new("set-api-key")
             .long("set-api-key")
             .help("Set the Anthropic API key")
@@ -76,8 +1857,8 @@ async fn main() -> anyhow::Result<()> {
         .arg(Arg::new("verbose")
             .short('v')
             .long("verbose")
-            .help("Enable verbose output")
-            .action(clap::ArgAction::SetTrue))
+            .help("Enable verbose output (-vv also logs to stderr, -vvv traces everything)")
+            .action(clap::ArgAction::Count))
         .arg(Arg::new("nope")
             .short('n')
             .long("nope")
@@ -85,13 +1866,521 @@ async fn main() -> anyhow::Result<()> {
             .value_name("FEEDBACK")
             .num_args(0..=1)
             .default_missing_value(""))
-        .get_matches();
+        .arg(Arg::new("queue")
+            .long("queue")
+            .help("Save INTENT to generate later with --flush-queue, e.g. while offline")
+            .value_name("INTENT")
+            .num_args(1..))
+        .arg(Arg::new("flush-queue")
+            .long("flush-queue")
+            .help("Generate every queued intent (without running them), reporting each result")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("with-last")
+            .long("with-last")
+            .help("Generate a command for INTENT that works on the previous command's output, e.g. \"now chart these numbers\"")
+            .value_name("INTENT")
+            .num_args(1))
+        .arg(Arg::new("yep")
+            .short('y')
+            .long("yep")
+            .help("Mark the last command run as working, with an optional comment")
+            .value_name("COMMENT")
+            .num_args(0..=1)
+            .default_missing_value("")
+            .conflicts_with("nope"))
+        .arg(Arg::new("pin")
+            .long("pin")
+            .help("With --yep, pin the current revision so --nope won't replace it")
+            .requires("yep")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("regenerate")
+            .long("regenerate")
+            .help("Regenerate a cached command from its original prompt, without running it")
+            .value_name("COMMAND_NAME")
+            .num_args(1))
+        .arg(Arg::new("same-model")
+            .long("same-model")
+            .help("With --regenerate, use the model and settings the command was generated with")
+            .requires("regenerate")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("regenerate-all")
+            .long("regenerate-all")
+            .help("Regenerate every cached command (or those matching --filter) with the current model and prompt, confirming each")
+            .conflicts_with("regenerate")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("filter")
+            .long("filter")
+            .help("With --regenerate-all, only commands matching KEY=VALUE (name, model, backend, os or tag); repeatable")
+            .value_name("KEY=VALUE")
+            .requires("regenerate-all")
+            .action(clap::ArgAction::Append))
+        .arg(Arg::new("auto")
+            .long("auto")
+            .help("With --regenerate-all, store new versions that pass the command's tests without asking")
+            .requires("regenerate-all")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("import-url")
+            .long("import-url")
+            .help("Import the commands of a bioma tarball (.tar.gz) or an exported command file at URL")
+            .value_name("URL")
+            .num_args(1))
+        .arg(Arg::new("sha256")
+            .long("sha256")
+            .help("With --import-url, the SHA-256 checksum the download must have")
+            .value_name("HEX")
+            .requires("import-url")
+            .num_args(1))
+        .arg(Arg::new("unpin")
+            .long("unpin")
+            .help("Unpin a command so it can be regenerated again")
+            .value_name("COMMAND_NAME")
+            .num_args(1))
+        .arg(Arg::new("retry")
+            .long("retry")
+            .help("Retry generation requests that time out (generator.timeout), up to N times")
+            .value_name("N")
+            .value_parser(clap::value_parser!(u32))
+            .num_args(0..=1)
+            .default_missing_value("2"))
+        .arg(Arg::new("model")
+            .long("model")
+            .help("Generate or regenerate with this model instead of the configured one (see --models)")
+            .value_name("MODEL")
+            .num_args(1)
+            .conflicts_with("same-model"))
+        .arg(Arg::new("plan")
+            .long("plan")
+            .help("Plan a new command first (steps, permissions, edge cases) and generate it once you approve the plan")
+            .conflicts_with("parallel")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("i-know-what-im-doing")
+            .long("i-know-what-im-doing")
+            .help("Run commands even if their scripts match catastrophic patterns (recursive deletes of / or $HOME, rm -rf, disk formatting)")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("stage")
+            .long("stage")
+            .help("Run the command on a copy of the current directory and review file changes before applying them")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("workspace")
+            .long("workspace")
+            .help("Run the command in a temporary directory holding only the files named in its arguments, and review its outputs before copying them back")
+            .conflicts_with("stage")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("show")
+            .long("show")
+            .help("Print output of commands that generate secrets instead of hiding it")
+            .conflicts_with("clipboard")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("clipboard")
+            .long("clipboard")
+            .help("Copy output of commands that generate secrets to the clipboard, clearing it after 30 seconds")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("jq")
+            .long("jq")
+            .help("Filter the JSON output of the command with a jq-style path, e.g. --jq '.items[].name'")
+            .value_name("EXPR")
+            .value_parser(|s: &str| s.parse::<JsonFilter>().map_err(|e| e.to_string())))
+        .arg(Arg::new("then")
+            .long("then")
+            .visible_alias("on-success")
+            .help("Run another command after the previous one succeeds, e.g. ergo build --then \"deploy @prod\" (repeatable)")
+            .value_name("COMMAND")
+            .action(clap::ArgAction::Append))
+        .arg(Arg::new("on-failure")
+            .long("on-failure")
+            .help("Run a command if the previous one failed (repeatable)")
+            .value_name("COMMAND")
+            .action(clap::ArgAction::Append))
+        .arg(Arg::new("finally")
+            .long("finally")
+            .help("Run a command whether or not the previous one succeeded (repeatable)")
+            .value_name("COMMAND")
+            .action(clap::ArgAction::Append))
+        .arg(Arg::new("chain")
+            .long("chain")
+            .help("Run the steps of a TOML pipeline file")
+            .value_name("FILE")
+            .conflicts_with_all(["then", "on-failure", "finally", "parallel"]))
+        .arg(Arg::new("parallel")
+            .long("parallel")
+            .help("Run several generated commands at once; quote each one with its arguments, e.g. ergo --parallel \"ping-api prod\" disk-usage")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("jobs")
+            .long("jobs")
+            .short('j')
+            .help("Maximum number of commands --parallel runs at once (default: 4)")
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize)))
+        .arg(Arg::new("report-bug")
+            .long("report-bug")
+            .help("Collect a sanitized diagnostics bundle (.tar.gz) to attach to an issue")
+            .value_name("OUTPUT")
+            .num_args(0..=1)
+            .default_missing_value(""))
+        .arg(Arg::new("diff")
+            .long("diff")
+            .help("Show changes between revisions of a cached command (defaults to previous vs current)")
+            .value_names(["COMMAND_NAME", "REV_A", "REV_B"])
+            .num_args(1..=3))
+        .arg(Arg::new("changelog")
+            .long("changelog")
+            .help("Show the revisions of a cached command with the feedback and changes behind each one")
+            .value_name("COMMAND_NAME")
+            .num_args(1))
+        .arg(Arg::new("summarize")
+            .long("summarize")
+            .help("With --changelog, have the model summarize what changed")
+            .requires("changelog")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("bump-deps")
+            .long("bump-deps")
+            .help("Move a cached command's Deno std imports to the configured deps.std_version")
+            .value_name("COMMAND_NAME")
+            .num_args(1))
+        .arg(Arg::new("simulate")
+            .long("simulate")
+            .help("Run a cached command with every permission denied and compare what it attempts with what it declares")
+            .value_names(["COMMAND_NAME", "ARGS"])
+            .num_args(1..)
+            .allow_hyphen_values(true))
+        .arg(Arg::new("deps")
+            .long("deps")
+            .help("List the remote imports of cached commands and check them for outdated or vulnerable versions")
+            .value_name("COMMAND_NAME")
+            .num_args(0..=1)
+            .default_missing_value(""))
+        .arg(Arg::new("permissions-report")
+            .long("permissions-report")
+            .help("Summarize which cached commands hold which permissions, riskiest first")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("set-output")
+            .long("set-output")
+            .help("Set a cached command's output processors (comma-separated json, color, pager; 'default' uses the global setting)")
+            .value_names(["COMMAND_NAME", "PROCESSORS"])
+            .num_args(2))
+        .arg(Arg::new("note")
+            .long("note")
+            .help("Attach a note to a cached command, e.g. why it exists (an empty note removes it)")
+            .value_names(["COMMAND_NAME", "TEXT"])
+            .num_args(2))
+        .arg(Arg::new("save-preset")
+            .long("save-preset")
+            .help("Save arguments for a cached command, used later as 'ergo COMMAND_NAME @PRESET' (no arguments removes the preset)")
+            .value_names(["COMMAND_NAME", "PRESET", "ARGS"])
+            .num_args(2..)
+            .allow_hyphen_values(true))
+        .arg(Arg::new("install-man")
+            .long("install-man")
+            .help("Generate a man page for a cached command and install it so 'man COMMAND_NAME' works")
+            .value_name("COMMAND_NAME")
+            .num_args(1))
+        .arg(Arg::new("describe")
+            .long("describe")
+            .help("Show a cached command's description, usage examples, permissions and presets")
+            .value_name("COMMAND_NAME")
+            .num_args(1))
+        .arg(Arg::new("why")
+            .long("why")
+            .help("Explain why a command line failed and suggest a fix or an ergo command (error output is read from stdin if omitted)")
+            .value_names(["COMMAND_LINE", "ERROR_OUTPUT"])
+            .num_args(1..=2)
+            .allow_hyphen_values(true))
+        .arg(Arg::new("list-generators")
+            .long("list-generators")
+            .help("List the built-in generator backends and the plugins in ~/.abiogenesis/plugins")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("models")
+            .long("models")
+            .help("List the models the configured generator backend offers, marking the one in use")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("ephemeral")
+            .long("ephemeral")
+            .help("Write nothing to disk: keep generated commands, consent and logs in memory until ergo exits (also ERGO_EPHEMERAL=1)")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("debug-llm")
+            .long("debug-llm")
+            .help("Write each model API request and raw response to ~/.abiogenesis/llm_debug, secrets redacted")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("last-llm")
+            .long("last-llm")
+            .help("Show the most recent model API exchange captured with --debug-llm")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("usage")
+            .long("usage")
+            .help("Show tokens used by model calls, the savings from prompt caching and the most expensive commands")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("latency")
+            .long("latency")
+            .help("With --usage, show latency histograms of model calls and local command runs instead")
+            .requires("usage")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("telemetry")
+            .long("telemetry")
+            .help("Show, enable or disable opt-in anonymous usage statistics (counts only, never intents or scripts)")
+            .value_name("ACTION")
+            .value_parser(["status", "on", "off"])
+            .num_args(0..=1)
+            .default_missing_value("status"))
+        .arg(Arg::new("trust-bioma")
+            .long("trust-bioma")
+            .help("Trust the bioma of a directory (the current one by default): stored permission decisions apply")
+            .value_name("DIR")
+            .num_args(0..=1))
+        .arg(Arg::new("distrust-bioma")
+            .long("distrust-bioma")
+            .help("Mark the bioma of a directory (the current one by default) untrusted: its commands show their script and ask for consent on every run")
+            .value_name("DIR")
+            .num_args(0..=1)
+            .conflicts_with("trust-bioma"))
+        .arg(Arg::new("explain")
+            .long("explain")
+            .help("Explain what a cached command does, line by line, and list its external effects")
+            .value_name("COMMAND_NAME")
+            .num_args(1))
+        .arg(Arg::new("review")
+            .long("review")
+            .help("Audit a cached command for risky patterns and save the report to the audit log")
+            .value_name("COMMAND_NAME")
+            .num_args(1));
+    if !subcommands {
+        return cli;
+    }
+    cli
+        .subcommand(Command::new("config")
+            .about("Read or modify configuration values")
+            .subcommand(Command::new("get")
+                .about("Print the value of a configuration key")
+                .arg(Arg::new("key").required(true)))
+            .subcommand(Command::new("set")
+                .about("Set a configuration key")
+                .arg(Arg::new("key").required(true))
+                .arg(Arg::new("value").required(true)))
+            .subcommand(Command::new("unset")
+                .about("Remove a configuration key")
+                .arg(Arg::new("key").required(true))))
+        .subcommand(Command::new("doctor")
+            .about("Check Deno, the API key, config, caches and clock, with hints to fix problems"))
+        .subcommand(Command::new("suggest")
+            .about("Suggest commands for the current project and generate the ones you pick"))
+        .subcommand(Command::new("ci")
+            .about("Run cached commands in CI pipelines")
+            .subcommand_required(true)
+            .subcommand(Command::new("export")
+                .about("Print GitHub Actions steps that install Deno and run a cached command")
+                .arg(Arg::new("command").required(true).value_name("COMMAND_NAME"))
+                .arg(Arg::new("args")
+                    .value_name("ARGS")
+                    .num_args(0..)
+                    .trailing_var_arg(true)
+                    .allow_hyphen_values(true)
+                    .help("Arguments the step passes to the command"))
+                .arg(Arg::new("inline")
+                    .long("inline")
+                    .help("Embed the script in the step instead of running it from the repository")
+                    .action(clap::ArgAction::SetTrue))))
+        .subcommand(Command::new("export")
+            .about("Export cached commands to other tools")
+            .subcommand_required(true)
+            .subcommand(Command::new("vscode-tasks")
+                .about("Write .vscode/tasks.json with a task for each command of the project's bioma"))
+            .subcommand(Command::new("raycast")
+                .about("Print a Raycast script command that runs a cached command")
+                .arg(Arg::new("command").required(true).value_name("COMMAND_NAME"))
+                .arg(Arg::new("dir")
+                    .long("dir")
+                    .value_name("DIR")
+                    .help("Write the script into this Raycast script commands directory instead"))))
+        .subcommand(Command::new("hook")
+            .about("Run cached commands from git hooks")
+            .subcommand_required(true)
+            .subcommand(Command::new("install")
+                .about("Install a git hook that runs cached commands and fails when one of them fails")
+                .arg(Arg::new("hook").required(true).value_parser(["pre-commit"]))
+                .arg(Arg::new("commands")
+                    .required(true)
+                    .num_args(1..)
+                    .value_name("COMMAND_NAME")
+                    .help("Cached commands to run, in order"))
+                .arg(Arg::new("framework")
+                    .long("framework")
+                    .help("Add the commands to .pre-commit-config.yaml for the pre-commit framework instead")
+                    .action(clap::ArgAction::SetTrue))))
+        .subcommand(Command::new("bioma")
+            .about("Snapshot the bioma commands are written to, and roll it back")
+            .subcommand_required(true)
+            .subcommand(Command::new("snapshot")
+                .about("Save the whole bioma (commands, scripts and revisions) under a name")
+                .arg(Arg::new("name").required(true).value_name("NAME")))
+            .subcommand(Command::new("restore")
+                .about("Replace the bioma with a snapshot; the current one is saved as 'before-restore'")
+                .arg(Arg::new("name").required(true).value_name("NAME")))
+            .subcommand(Command::new("warm")
+                .about("Cache the imports of the most-used commands so they start instantly")))
+        .subcommand(Command::new("history")
+            .about("Manage the latency log, the usage ledger and --debug-llm captures")
+            .subcommand_required(true)
+            .subcommand(Command::new("clear")
+                .about("Remove the history, or the part of it older than a date")
+                .arg(Arg::new("before")
+                    .long("before")
+                    .value_name("DATE")
+                    .help("Only remove entries from before this date (YYYY-MM-DD, UTC)"))))
+        .subcommand(Command::new("workflow")
+            .about("Run multi-step workflow files")
+            .subcommand_required(true)
+            .subcommand(Command::new("run")
+                .about("Run a workflow, generating missing commands and reviewing all permissions up front")
+                .arg(Arg::new("file").required(true).value_name("FILE"))))
+}
+
+/// Parses the command line.
+///
+/// Subcommand names are also words of free-text intents, and may name cached
+/// commands. So when a subcommand can't use the words after it (`ergo history
+/// of my git commits`), or lacks the subcommand it needs and a cached command
+/// has its name (`ergo history`), the words are an intent instead.
+async fn parse_args(args: Vec<OsString>) -> ArgMatches {
+    let error = match cli(true).try_get_matches_from(&args) {
+        Ok(matches) => return matches,
+        Err(error) => error,
+    };
+    if let Ok(matches) = cli(false).try_get_matches_from(&args) {
+        let intent: Vec<&String> = matches.get_many::<String>("intent").map(Iterator::collect).unwrap_or_default();
+        let starts_with_subcommand = intent.first().is_some_and(|word| cli(true).find_subcommand(word).is_some());
+        let is_intent = starts_with_subcommand
+            && match error.kind() {
+                ErrorKind::InvalidSubcommand | ErrorKind::UnknownArgument => true,
+                ErrorKind::MissingSubcommand | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+                    intent.len() == 1 && is_cached(intent[0]).await
+                }
+                _ => false,
+            };
+        if is_intent {
+            return matches;
+        }
+    }
+    error.exit()
+}
+
+async fn is_cached(command_name: &str) -> bool {
+    match CommandCache::new().await {
+        Ok(cache) => matches!(cache.get_command(command_name).await, Ok(Some(_))),
+        Err(_) => false,
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
     
+    let matches = parse_args(std::env::args_os().collect()).await;
+    
+    if matches.get_flag("ephemeral") || ephemeral::requested(|name| std::env::var(name).ok()) {
+        ephemeral::enable();
+    }
+
     // Setup logging early, but after parsing verbose flag
-    let verbose = matches.get_flag("verbose");
-    setup_logging(verbose)?;
+    let verbosity = Verbosity::from_count(matches.get_count("verbose"));
+    let verbose = verbosity.is_verbose();
+    setup_logging(verbosity)?;
+    if let Ok(config) = Config::load() {
+        retention::configure(config.history);
+    }
+    if matches.get_flag("debug-llm") {
+        llm_debug::enable(llm_debug::default_dir()?);
+    }
     
     // Handle configuration commands
+    if let Some(("config", config_matches)) = matches.subcommand() {
+        return handle_config_subcommand(config_matches);
+    }
+
+    install_starter_commands().await;
+
+    if let Some(("doctor", _)) = matches.subcommand() {
+        return handle_doctor().await;
+    }
+
+    if let Some(("suggest", _)) = matches.subcommand() {
+        return suggest_commands(matches.get_one::<u32>("retry").copied().unwrap_or(0), verbose).await;
+    }
+
+    if let Some(("ci", ci_matches)) = matches.subcommand()
+        && let Some(("export", export_matches)) = ci_matches.subcommand()
+    {
+        let command_name = export_matches.get_one::<String>("command").expect("command is required");
+        let args: Vec<String> = export_matches
+            .get_many::<String>("args")
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default();
+        return export_ci_steps(command_name, &args, export_matches.get_flag("inline")).await;
+    }
+
+    if let Some(("export", export_matches)) = matches.subcommand() {
+        match export_matches.subcommand() {
+            Some(("vscode-tasks", _)) => return export_vscode_tasks().await,
+            Some(("raycast", raycast_matches)) => {
+                let command_name = raycast_matches.get_one::<String>("command").expect("command is required");
+                let dir = raycast_matches.get_one::<String>("dir").map(String::as_str);
+                return export_raycast_script(command_name, dir).await;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(("hook", hook_matches)) = matches.subcommand()
+        && let Some(("install", install_matches)) = hook_matches.subcommand()
+    {
+        let hook = install_matches.get_one::<String>("hook").expect("hook is required");
+        let commands: Vec<String> = install_matches
+            .get_many::<String>("commands")
+            .expect("commands are required")
+            .cloned()
+            .collect();
+        return install_git_hook(hook, &commands, install_matches.get_flag("framework")).await;
+    }
+
+    if let Some(("bioma", bioma_matches)) = matches.subcommand() {
+        match bioma_matches.subcommand() {
+            Some(("snapshot", snapshot_matches)) => {
+                return snapshot_bioma(snapshot_matches.get_one::<String>("name").expect("name is required"));
+            }
+            Some(("restore", restore_matches)) => {
+                return restore_bioma(restore_matches.get_one::<String>("name").expect("name is required"));
+            }
+            Some(("warm", _)) => return warm_bioma().await,
+            _ => {}
+        }
+    }
+
+    if let Some(("history", history_matches)) = matches.subcommand()
+        && let Some(("clear", clear_matches)) = history_matches.subcommand()
+    {
+        return clear_history(clear_matches.get_one::<String>("before").map(String::as_str));
+    }
+
+    if let Some(("workflow", workflow_matches)) = matches.subcommand()
+        && let Some(("run", run_matches)) = workflow_matches.subcommand()
+    {
+        let file = run_matches.get_one::<String>("file").expect("file is required");
+        let workflow = Workflow::load(std::path::Path::new(file))?;
+        let mut router = interruptible_router(verbose).await?;
+        configure_router(&mut router, &matches)?;
+
+        let result = router.process_workflow(&workflow).await;
+        if let Ok(Some(outcomes)) = &result {
+            record_usage(chain_events(outcomes)).await;
+            record_results(chain_results(outcomes)).await;
+        }
+        let Some(outcomes) = exit_if_interrupted(result)? else {
+            PermissionUI::new(verbose).show_permission_denied(&workflow.name);
+            std::process::exit(1);
+        };
+        if !report_chain(&outcomes, verbose) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     if let Some(api_key) = matches.get_one::<String>("set-api-key") {
         let mut config = Config::load()?;
         config.set_api_key(api_key.clone())?;
@@ -99,6 +2388,57 @@ async fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    if let Some(output) = matches.get_one::<String>("report-bug") {
+        return report_bug(output).await;
+    }
+
+    if matches.get_flag("list-generators") {
+        return list_generators();
+    }
+
+    if matches.get_flag("models") {
+        return list_models().await;
+    }
+
+    if matches.get_flag("last-llm") {
+        return show_last_llm();
+    }
+
+    if matches.get_flag("usage") && matches.get_flag("latency") {
+        let samples = latency::read(&latency::default_path()?)?;
+        if samples.is_empty() {
+            println!("📭 No latency recorded yet");
+        } else {
+            println!("{}", LatencyReport::from_samples(&samples));
+        }
+        return Ok(());
+    }
+
+    if matches.get_flag("usage") {
+        let entries = usage_ledger::read(&usage_ledger::default_path()?)?;
+        if entries.is_empty() {
+            println!("📭 No model calls recorded yet");
+        } else {
+            println!("{}", UsageSummary::from_entries(&entries));
+            let spend = SpendByCommand::from_entries(&entries);
+            if !spend.commands.is_empty() {
+                println!("\n{}", spend);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(action) = matches.get_one::<String>("telemetry") {
+        return handle_telemetry(action);
+    }
+
+    if matches.contains_id("trust-bioma") {
+        return set_bioma_trust(matches.get_one::<String>("trust-bioma"), TrustLevel::Trusted);
+    }
+    if matches.contains_id("distrust-bioma") {
+        return set_bioma_trust(matches.get_one::<String>("distrust-bioma"), TrustLevel::Untrusted);
+    }
+
     if matches.get_flag("config") {
         Config::show_config_info()?;
         return Ok(());
@@ -123,6 +2463,9 @@ async fn main() -> anyhow::Result<()> {
             for (name, command, decision) in commands {
                 println!("🔧 {}", name);
                 println!("   📝 {}", command.description);
+                if let Some(example) = command.examples.first() {
+                    println!("   💡 {}", example);
+                }
                 if !command.permissions.is_empty() {
                     println!("   🔑 Permissions:");
                     for perm in &command.permissions {
@@ -134,6 +2477,7 @@ async fn main() -> anyhow::Result<()> {
                         PermissionConsent::AcceptOnce => "Accept Once",
                         PermissionConsent::AcceptForever => "Accept Forever",
                         PermissionConsent::Denied => "Denied",
+                        PermissionConsent::AcceptForSession => "Accept for Session",
                     };
                     println!("   ✅ User Decision: {}", consent_str);
                 }
@@ -147,12 +2491,79 @@ async fn main() -> anyhow::Result<()> {
         let mut cache = CommandCache::new().await?;
         if cache.remove_command(command_name).await? {
             println!("✅ Removed command '{}' from cache", command_name);
+            if let Ok(dir) = docgen::user_man_dir()
+                && docgen::remove_man_page(&dir, command_name)?
+            {
+                println!("🗑️  Removed its man page");
+            }
         } else {
             println!("❌ Command '{}' not found in cache", command_name);
         }
         return Ok(());
     }
 
+    if let Some(values) = matches.get_many::<String>("diff") {
+        let values: Vec<&String> = values.collect();
+        return show_revision_diff(values[0], &values[1..]).await;
+    }
+
+    if let Some(command_name) = matches.get_one::<String>("changelog") {
+        return show_changelog(command_name, matches.get_flag("summarize")).await;
+    }
+
+    if let Some(command_name) = matches.get_one::<String>("bump-deps") {
+        return bump_deps(command_name).await;
+    }
+
+    if let Some(values) = matches.get_many::<String>("simulate") {
+        let values: Vec<String> = values.cloned().collect();
+        return simulate_command(&values[0], &values[1..]).await;
+    }
+
+    if let Some(command_name) = matches.get_one::<String>("deps") {
+        return show_deps(Some(command_name.as_str()).filter(|n| !n.is_empty())).await;
+    }
+
+    if matches.get_flag("permissions-report") {
+        return show_permissions_report().await;
+    }
+
+    if let Some(values) = matches.get_many::<String>("set-output") {
+        let values: Vec<&String> = values.collect();
+        return set_output_processors(values[0], values[1]).await;
+    }
+
+    if let Some(values) = matches.get_many::<String>("note") {
+        let values: Vec<&String> = values.collect();
+        return set_note(values[0], values[1]).await;
+    }
+
+    if let Some(values) = matches.get_many::<String>("save-preset") {
+        let values: Vec<String> = values.cloned().collect();
+        return save_preset(&values[0], &values[1], &values[2..]).await;
+    }
+
+    if let Some(command_name) = matches.get_one::<String>("install-man") {
+        return install_man_page(command_name).await;
+    }
+
+    if let Some(command_name) = matches.get_one::<String>("describe") {
+        return describe_command(command_name).await;
+    }
+
+    if let Some(values) = matches.get_many::<String>("why") {
+        let values: Vec<&String> = values.collect();
+        return why_failed(values[0], values.get(1).copied(), verbose).await;
+    }
+
+    if let Some(command_name) = matches.get_one::<String>("explain") {
+        return explain_command(command_name).await;
+    }
+
+    if let Some(command_name) = matches.get_one::<String>("review") {
+        return review_command(command_name).await;
+    }
+
     if matches.get_flag("cache-stats") {
         let cache = CommandCache::new().await?;
         let stats = cache.get_stats().await?;
@@ -160,28 +2571,192 @@ async fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    if let Some(comment) = matches.get_one::<String>("yep") {
+        return rate_last_command(comment, matches.get_flag("pin")).await;
+    }
+
+    if let Some(command_name) = matches.get_one::<String>("regenerate") {
+        let model = matches.get_one::<String>("model").map(String::as_str);
+        return regenerate_command(command_name, matches.get_flag("same-model"), model).await;
+    }
+
+    if let Some(url) = matches.get_one::<String>("import-url") {
+        return import_url(url, matches.get_one::<String>("sha256").map(String::as_str)).await;
+    }
+
+    if matches.get_flag("regenerate-all") {
+        let filters = matches
+            .get_many::<String>("filter")
+            .unwrap_or_default()
+            .map(|filter| RegenerateFilter::parse(filter))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let model = matches.get_one::<String>("model").map(String::as_str);
+        return regenerate_all(&filters, matches.get_flag("auto"), model).await;
+    }
+
+    if let Some(command_name) = matches.get_one::<String>("unpin") {
+        let mut cache = CommandCache::new().await?;
+        if cache.unpin(command_name).await? {
+            println!("✅ '{}' is no longer pinned", command_name);
+        } else {
+            println!("❌ Command '{}' is not pinned", command_name);
+        }
+        return Ok(());
+    }
+
     // Handle --nope feedback loop
     if let Some(feedback) = matches.get_one::<String>("nope") {
-        let mut router = CommandRouter::new(verbose).await?;
-        return router.process_corrective_feedback(feedback).await;
+        let mut router = interruptible_router(verbose).await?;
+        configure_router(&mut router, &matches)?;
+        let result = router.process_corrective_feedback(feedback).await;
+        match &result {
+            Ok(outcome) => {
+                record_usage(UsageEvent::from_outcome(outcome)).await;
+                record_results([outcome]).await;
+            }
+            Err(e) => record_usage(vec![UsageEvent::from_error(e)]).await,
+        }
+        report_outcome(&exit_if_interrupted(result)?, verbose);
+        return Ok(());
+    }
+
+    if let Some(intent) = matches.get_many::<String>("queue") {
+        return queue_intent(intent.cloned().collect());
+    }
+
+    if matches.get_flag("flush-queue") {
+        return flush_queue(matches.get_one::<u32>("retry").copied().unwrap_or(0), verbose).await;
+    }
+
+    // Handle --with-last follow-ups
+
+    if let Some(intent) = matches.get_one::<String>("with-last") {
+        let mut router = interruptible_router(verbose).await?;
+        configure_router(&mut router, &matches)?;
+        let result = router.process_with_last(intent).await;
+        match &result {
+            Ok(outcome) => {
+                record_usage(UsageEvent::from_outcome(outcome)).await;
+                record_results([outcome]).await;
+            }
+            Err(e) => record_usage(vec![UsageEvent::from_error(e)]).await,
+        }
+        report_outcome(&exit_if_interrupted(result)?, verbose);
+        return Ok(());
     }
 
     // Handle normal command execution
-    let intent_args: Vec<String> = matches
+    let mut intent_args: Vec<String> = matches
         .get_many::<String>("intent")
         .unwrap_or_default()
         .map(|s| s.to_string())
         .collect();
+    let spec = read_intent_spec(&matches, &intent_args)?;
+    if spec.is_some() {
+        intent_args.clear();
+    }
 
-    if intent_args.is_empty() {
+    let chain = chain_from_args(&matches, &intent_args)?;
+    if spec.is_some() && chain.is_some() {
+        return Err(anyhow::anyhow!("A specification can't be combined with --then, --on-failure or --finally"));
+    }
+    if intent_args.is_empty() && chain.is_none() && spec.is_none() {
         eprintln!("No intent provided. Use 'ergo --help' for usage information.");
         return Ok(());
     }
 
     info!("Processing intent: {:?}", intent_args);
 
-    let mut router = CommandRouter::new(verbose).await?;
-    router.process_intent(intent_args).await?;
+    let mut router = interruptible_router(verbose).await?;
+    configure_router(&mut router, &matches)?;
+
+    if matches.get_flag("parallel") {
+        let intents = intent_args
+            .iter()
+            .map(|intent| intent.split_whitespace().map(str::to_string).collect())
+            .collect();
+        let max_parallel = matches.get_one::<usize>("jobs").copied().unwrap_or(DEFAULT_PARALLELISM);
+        let outcomes = router.process_many(intents, max_parallel).await?;
+        record_usage(outcomes.iter().flat_map(|b| UsageEvent::from_result(&b.outcome)).collect()).await;
+        record_results(outcomes.iter().filter_map(|b| b.outcome.as_ref().ok())).await;
+        let interrupted = outcomes
+            .iter()
+            .any(|b| matches!(&b.outcome, Ok(o) if o.execution().is_some_and(|r| r.interrupted)));
+        let all_ok = report_batch(&outcomes);
+        if interrupted {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        if !all_ok {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some(chain) = chain {
+        let result = router.process_chain(&chain).await;
+        match &result {
+            Ok(outcomes) => {
+                record_usage(chain_events(outcomes)).await;
+                record_results(chain_results(outcomes)).await;
+            }
+            Err(e) => record_usage(vec![UsageEvent::from_error(e)]).await,
+        }
+        let outcomes = exit_if_interrupted(result)?;
+        if !report_chain(&outcomes, verbose) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let result = match &spec {
+        Some(spec) => router.process_spec(spec).await,
+        None => router.process_intent(intent_args).await,
+    };
+    record_usage(UsageEvent::from_result(&result)).await;
+    if let Ok(outcome) = &result {
+        record_results([outcome]).await;
+    }
+    let outcome = exit_if_interrupted(result)?;
+    report_outcome(&outcome, verbose);
 
+    // Fail like the command did, so scripts and git hooks can rely on the status
+    if !outcome.succeeded() {
+        std::process::exit(outcome.execution().and_then(|r| r.exit_code).filter(|&code| code != 0).unwrap_or(1));
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserved_names_cover_the_cli_subcommands() {
+        let mut cli = cli(true);
+        cli.build();
+        let mut names: Vec<&str> = cli.get_subcommands().map(Command::get_name).collect();
+        // Flag words people type as if they were commands
+        names.extend(["nope", "version", "yep"]);
+        names.sort();
+
+        let mut reserved = abiogenesis::reserved::ERGO_NAMES.to_vec();
+        reserved.sort();
+        assert_eq!(reserved, names);
+    }
+
+    #[tokio::test]
+    async fn test_subcommand_words_start_intents_the_subcommand_cannot_parse() {
+        let args = |line: &str| line.split(' ').map(OsString::from).collect::<Vec<_>>();
+
+        let matches = parse_args(args("ergo history of my git commits")).await;
+        assert!(matches.subcommand().is_none());
+        let intent: Vec<&String> = matches.get_many::<String>("intent").unwrap().collect();
+        assert_eq!(intent, ["history", "of", "my", "git", "commits"]);
+
+        let matches = parse_args(args("ergo doctor my setup")).await;
+        assert_eq!(matches.get_many::<String>("intent").unwrap().count(), 3);
+
+        let matches = parse_args(args("ergo history clear --before 2026-01-01")).await;
+        assert!(matches!(matches.subcommand(), Some(("history", _))));
+    }
+}
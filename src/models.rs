@@ -0,0 +1,125 @@
+//! Models a generator backend offers, for `ergo --models`.
+//!
+//! Each API lists models its own way: the Anthropic API at `/v1/models`,
+//! Bedrock with `ListFoundationModels`, and Gemini on AI Studio at
+//! `/v1beta/models`. [`ModelList`] gathers the IDs, marks the one in use,
+//! and says which setting selects another, so IDs don't have to be copied
+//! from documentation by hand.
+
+use serde_json::Value;
+use std::fmt;
+
+/// The models of a backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelList {
+    /// The backend, e.g. `claude` or `bedrock`.
+    pub backend: String,
+    /// The model in use.
+    pub selected: String,
+    /// IDs of the models the credentials can use.
+    pub models: Vec<String>,
+    /// The setting that selects the model, if it can be changed.
+    pub setting: Option<&'static str>,
+}
+
+impl fmt::Display for ModelList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "🤖 Models available with the {} backend:", self.backend)?;
+        if self.models.is_empty() {
+            write!(f, "\n   (none)")?;
+        }
+        for model in &self.models {
+            write!(f, "\n {} {}", if *model == self.selected { "▶" } else { " " }, model)?;
+        }
+        if !self.models.is_empty() && !self.models.contains(&self.selected) {
+            write!(f, "\n⚠️  The model in use, '{}', isn't among them", self.selected)?;
+        }
+        match self.setting {
+            Some(setting) => write!(f, "\n💡 Select one with: ergo config set {} ID", setting),
+            None => write!(f, "\n💡 The {} backend always uses {}", self.backend, self.selected),
+        }
+    }
+}
+
+/// Model IDs of an Anthropic `/v1/models` answer.
+pub fn parse_anthropic(response: &Value) -> Vec<String> {
+    ids(response, "data", "id")
+}
+
+/// Model IDs of a Bedrock `ListFoundationModels` answer.
+pub fn parse_bedrock(response: &Value) -> Vec<String> {
+    ids(response, "modelSummaries", "modelId")
+}
+
+/// Names of the models of a Gemini `models.list` answer that can generate
+/// content, without their `models/` prefix.
+pub fn parse_gemini(response: &Value) -> Vec<String> {
+    let Some(models) = response.get("models").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    models
+        .iter()
+        .filter(|model| {
+            model
+                .get("supportedGenerationMethods")
+                .and_then(Value::as_array)
+                .is_some_and(|methods| methods.iter().any(|m| m == "generateContent"))
+        })
+        .filter_map(|model| model.get("name")?.as_str())
+        .map(|name| name.strip_prefix("models/").unwrap_or(name).to_string())
+        .collect()
+}
+
+/// The `field` of every item of the `list` array.
+fn ids(response: &Value, list: &str, field: &str) -> Vec<String> {
+    response
+        .get(list)
+        .and_then(Value::as_array)
+        .map(|items| items.iter().filter_map(|item| item.get(field)?.as_str().map(str::to_string)).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_each_api() {
+        assert_eq!(
+            parse_anthropic(&json!({"data": [{"id": "claude-a", "type": "model"}, {"id": "claude-b"}]})),
+            vec!["claude-a", "claude-b"]
+        );
+        assert_eq!(
+            parse_bedrock(&json!({"modelSummaries": [{"modelId": "anthropic.claude-3-haiku-20240307-v1:0"}]})),
+            vec!["anthropic.claude-3-haiku-20240307-v1:0"]
+        );
+        assert_eq!(
+            parse_gemini(&json!({"models": [
+                {"name": "models/gemini-1.5-flash", "supportedGenerationMethods": ["generateContent", "countTokens"]},
+                {"name": "models/text-embedding-004", "supportedGenerationMethods": ["embedContent"]},
+            ]})),
+            vec!["gemini-1.5-flash"]
+        );
+        assert!(parse_anthropic(&json!({"error": {}})).is_empty());
+    }
+
+    #[test]
+    fn test_display_marks_selected_model() {
+        let mut list = ModelList {
+            backend: "gemini".to_string(),
+            selected: "gemini-1.5-flash".to_string(),
+            models: vec!["gemini-1.5-flash".to_string(), "gemini-1.5-pro".to_string()],
+            setting: Some("generator.gemini.model"),
+        };
+
+        assert_eq!(
+            list.to_string(),
+            "🤖 Models available with the gemini backend:\n ▶ gemini-1.5-flash\n   gemini-1.5-pro\n\
+            💡 Select one with: ergo config set generator.gemini.model ID"
+        );
+
+        list.selected = "gemini-0".to_string();
+        assert!(list.to_string().contains("⚠️  The model in use, 'gemini-0', isn't among them"));
+    }
+}
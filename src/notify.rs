@@ -0,0 +1,340 @@
+//! Webhook notifications of command results.
+//!
+//! With `notify.webhook` set, ergo posts the command's name, status,
+//! duration and the tail of its output to the webhook after every generated
+//! command it runs, which helps when commands run from cron, CI or other
+//! unattended setups:
+//!
+//! ```toml
+//! [notify]
+//! webhook = "https://hooks.slack.com/services/..."
+//! on = "failure"        # or "always" (the default)
+//! max_output = 500      # characters of output included
+//! ```
+//!
+//! Slack and Discord webhooks are recognized by their URL and get a message
+//! in their format; any other URL gets a plain JSON object (see
+//! [`Notification::payload`]). Sensitive output (see [`crate::secrets`]) is
+//! never sent.
+
+use crate::command_router::IntentOutcome;
+use crate::http_client::HttpClient;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::info;
+
+/// Notification settings (the `[notify]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    /// Where results are posted; nothing is sent while it is unset.
+    pub webhook: Option<String>,
+    /// The webhook's message format.
+    pub format: WebhookFormat,
+    /// Which results are posted.
+    pub on: NotifyOn,
+    /// Characters of output included, from the end.
+    pub max_output: usize,
+}
+
+fn default_max_output() -> usize {
+    500
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            webhook: None,
+            format: WebhookFormat::default(),
+            on: NotifyOn::default(),
+            max_output: default_max_output(),
+        }
+    }
+}
+
+/// Message format of a webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// Slack or Discord, going by the URL; generic otherwise.
+    #[default]
+    Auto,
+    /// A Slack incoming webhook (`{"text": ...}`).
+    Slack,
+    /// A Discord webhook (`{"content": ...}`).
+    Discord,
+    /// A JSON object with one field per detail.
+    Generic,
+}
+
+impl WebhookFormat {
+    /// Resolves [`WebhookFormat::Auto`] for `url`.
+    pub fn resolve(self, url: &str) -> WebhookFormat {
+        match self {
+            WebhookFormat::Auto if url.contains("hooks.slack.com") => WebhookFormat::Slack,
+            WebhookFormat::Auto if url.contains("discord.com/api/webhooks") || url.contains("discordapp.com/api/webhooks") => {
+                WebhookFormat::Discord
+            }
+            WebhookFormat::Auto => WebhookFormat::Generic,
+            format => format,
+        }
+    }
+}
+
+/// Which results are posted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    /// Every result.
+    #[default]
+    Always,
+    /// Only failures and interruptions.
+    Failure,
+}
+
+/// How a command ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Exited with status 0.
+    Succeeded,
+    /// Exited with another status, or didn't run to the end.
+    Failed,
+    /// Stopped with Ctrl-C.
+    Interrupted,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Succeeded => "succeeded",
+            Status::Failed => "failed",
+            Status::Interrupted => "interrupted",
+        }
+    }
+
+    fn emoji(self) -> &'static str {
+        match self {
+            Status::Succeeded => "✅",
+            Status::Failed => "❌",
+            Status::Interrupted => "⏹️",
+        }
+    }
+}
+
+/// The result of one command run, as notified.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    /// The command's name.
+    pub command: String,
+    /// How it ended.
+    pub status: Status,
+    /// Its exit code, if it exited normally.
+    pub exit_code: Option<i32>,
+    /// Wall-clock time it ran for.
+    pub duration_ms: u128,
+    /// Its output: stdout, or stderr when it failed; `None` when sensitive.
+    pub output: Option<String>,
+}
+
+impl Notification {
+    /// The notification for an intent's outcome, if a generated command ran.
+    pub fn from_outcome(outcome: &IntentOutcome) -> Option<Self> {
+        let (IntentOutcome::CacheHit { command, result }
+        | IntentOutcome::Generated { command, result }
+        | IntentOutcome::Regenerated { command, result }) = outcome
+        else {
+            return None;
+        };
+
+        let status = if result.interrupted {
+            Status::Interrupted
+        } else if result.success {
+            Status::Succeeded
+        } else {
+            Status::Failed
+        };
+        let stdout = result.stdout.as_deref().unwrap_or_default();
+        let stderr = result.stderr.as_deref().unwrap_or_default();
+        let output = if status != Status::Succeeded && !stderr.trim().is_empty() {
+            stderr
+        } else {
+            stdout
+        };
+        Some(Self {
+            command: command.name.clone(),
+            status,
+            exit_code: result.exit_code,
+            duration_ms: result.duration.as_millis(),
+            output: (!result.sensitive).then(|| output.to_string()),
+        })
+    }
+
+    /// Whether the notification should be sent under `on`.
+    pub fn wanted(&self, on: NotifyOn) -> bool {
+        on == NotifyOn::Always || self.status != Status::Succeeded
+    }
+
+    /// The body posted to a webhook in `format`, with at most `max_output`
+    /// characters of output.
+    ///
+    /// The generic body is
+    /// `{"command", "status", "exit_code", "duration_ms", "output"}`, with
+    /// `status` one of `succeeded`, `failed` or `interrupted`.
+    pub fn payload(&self, format: WebhookFormat, max_output: usize) -> Value {
+        let output = self.output.as_deref().map(|output| tail(output.trim_end(), max_output));
+        if matches!(format, WebhookFormat::Generic | WebhookFormat::Auto) {
+            return json!({
+                "command": self.command,
+                "status": self.status.as_str(),
+                "exit_code": self.exit_code,
+                "duration_ms": self.duration_ms,
+                "output": output,
+            });
+        }
+
+        let mut text = format!(
+            "{} `{}` {} in {:.1}s",
+            self.status.emoji(),
+            self.command,
+            self.status.as_str(),
+            self.duration_ms as f64 / 1000.0
+        );
+        if let Some(code) = self.exit_code.filter(|&code| code != 0) {
+            text.push_str(&format!(" (exit code {})", code));
+        }
+        match output {
+            Some(output) if !output.is_empty() => text.push_str(&format!("\n```\n{}\n```", output.replace("```", "'''"))),
+            Some(_) => {}
+            None => text.push_str("\n_(output is sensitive and was not sent)_"),
+        }
+        match format {
+            WebhookFormat::Discord => json!({ "content": text }),
+            _ => json!({ "text": text }),
+        }
+    }
+}
+
+/// The last `max_chars` characters of `text`, marked with `…` when cut.
+fn tail(text: &str, max_chars: usize) -> String {
+    let count = text.chars().count();
+    if count <= max_chars {
+        return text.to_string();
+    }
+    let start = text.char_indices().nth(count - max_chars).map_or(0, |(index, _)| index);
+    format!("…{}", &text[start..])
+}
+
+/// Posts `notification` to the configured webhook, if there is one and the
+/// notification is wanted.
+///
+/// Returns whether anything was sent.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub async fn send<H: HttpClient + ?Sized>(config: &NotifyConfig, client: &H, notification: &Notification) -> Result<bool> {
+    let Some(url) = config.webhook.as_deref().filter(|url| !url.is_empty()) else {
+        return Ok(false);
+    };
+    if !notification.wanted(config.on) {
+        return Ok(false);
+    }
+    let payload = notification.payload(config.format.resolve(url), config.max_output);
+    client.post_json(url, &[("Content-Type", "application/json")], &payload).await?;
+    info!("Notified webhook of '{}' ({})", notification.command, notification.status.as_str());
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockHttpClient, command};
+
+    fn notification(status: Status, output: Option<&str>) -> Notification {
+        Notification {
+            command: "backup".to_string(),
+            status,
+            exit_code: Some(if status == Status::Succeeded { 0 } else { 2 }),
+            duration_ms: 1300,
+            output: output.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_payload_formats() {
+        let failed = notification(Status::Failed, Some("line 1\ndisk full\n"));
+
+        assert_eq!(
+            failed.payload(WebhookFormat::Slack, 9),
+            json!({ "text": "❌ `backup` failed in 1.3s (exit code 2)\n```\n…disk full\n```" })
+        );
+        assert_eq!(
+            notification(Status::Succeeded, None).payload(WebhookFormat::Discord, 100),
+            json!({ "content": "✅ `backup` succeeded in 1.3s\n_(output is sensitive and was not sent)_" })
+        );
+        assert_eq!(
+            failed.payload(WebhookFormat::Generic, 100),
+            json!({
+                "command": "backup",
+                "status": "failed",
+                "exit_code": 2,
+                "duration_ms": 1300,
+                "output": "line 1\ndisk full",
+            })
+        );
+        assert_eq!(WebhookFormat::Auto.resolve("https://hooks.slack.com/services/T/B/X"), WebhookFormat::Slack);
+        assert_eq!(WebhookFormat::Auto.resolve("https://example.com/hook"), WebhookFormat::Generic);
+    }
+
+    #[tokio::test]
+    async fn test_send_respects_config() {
+        let client = MockHttpClient::new().with_response("ok");
+        let mut config = NotifyConfig::default();
+        let succeeded = notification(Status::Succeeded, Some("done"));
+
+        assert!(!send(&config, &client, &succeeded).await.unwrap());
+
+        config.webhook = Some("https://hooks.slack.com/services/T/B/X".to_string());
+        config.on = NotifyOn::Failure;
+        assert!(!send(&config, &client, &succeeded).await.unwrap());
+
+        config.on = NotifyOn::Always;
+        assert!(send(&config, &client, &succeeded).await.unwrap());
+        let requests = client.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].1, json!({ "text": "✅ `backup` succeeded in 1.3s\n```\ndone\n```" }));
+    }
+
+    #[test]
+    fn test_from_outcome_skips_sensitive_output() {
+        let result = crate::executor::ExecutionResult {
+            success: false,
+            exit_code: Some(1),
+            signal: None,
+            duration: std::time::Duration::from_millis(40),
+            stdout: Some("partial".to_string()),
+            stderr: Some("boom".to_string()),
+            args: Vec::new(),
+            cwd: std::path::PathBuf::new(),
+            script_hash: String::new(),
+            interrupted: false,
+            sensitive: false,
+            error: None,
+        };
+        let outcome = IntentOutcome::CacheHit {
+            command: command("backup", &[]),
+            result: result.clone(),
+        };
+        let secret = IntentOutcome::CacheHit {
+            command: command("token", &[]),
+            result: crate::executor::ExecutionResult { sensitive: true, ..result },
+        };
+
+        let notification = Notification::from_outcome(&outcome).unwrap();
+        assert_eq!(notification.status, Status::Failed);
+        assert_eq!(notification.output.as_deref(), Some("boom"));
+        assert_eq!(Notification::from_outcome(&secret).unwrap().output, None);
+        assert!(Notification::from_outcome(&IntentOutcome::SystemExecuted { command_name: "ls".to_string() }).is_none());
+    }
+}
@@ -0,0 +1,449 @@
+//! Post-processing of generated commands' output.
+//!
+//! Output processors reformat what a command prints before it reaches the
+//! terminal: pretty-printing JSON, colorizing it, or opening a pager when the
+//! output is longer than the screen. They are set globally in the `[output]`
+//! table of `config.toml` and can be overridden per command in the cache.
+//!
+//! Commands that declare `output_format = "json"` get JSON handling of their
+//! own: the output must parse, it is pretty-printed on a terminal and passed
+//! through as is when piped, and `--jq` filters it (see [`crate::json_filter`]).
+//!
+//! Processing needs the whole output, so it is buffered up to
+//! [`MAX_PROCESSED_BYTES`]; beyond that the output is passed through as is.
+
+use crate::executor::ProcessRunner;
+use crate::json_filter::JsonFilter;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use tracing::{info, warn};
+
+/// Output larger than this is passed through unprocessed (16 MiB).
+pub const MAX_PROCESSED_BYTES: usize = 16 * 1024 * 1024;
+
+/// A transformation applied to a command's standard output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputProcessor {
+    /// Pretty-print output that is valid JSON.
+    Json,
+    /// Colorize JSON output when writing to a terminal.
+    Color,
+    /// Open a pager when the output doesn't fit on the screen.
+    Pager,
+}
+
+impl OutputProcessor {
+    /// All processors, in the order they are applied.
+    pub const ALL: [OutputProcessor; 3] = [OutputProcessor::Json, OutputProcessor::Color, OutputProcessor::Pager];
+}
+
+impl fmt::Display for OutputProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OutputProcessor::Json => "json",
+            OutputProcessor::Color => "color",
+            OutputProcessor::Pager => "pager",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for OutputProcessor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        OutputProcessor::ALL
+            .into_iter()
+            .find(|p| p.to_string() == s.trim())
+            .ok_or_else(|| anyhow!("Unknown output processor '{}' (expected json, color, or pager)", s.trim()))
+    }
+}
+
+/// What a command prints on standard output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Free-form text.
+    #[default]
+    Text,
+    /// A JSON document.
+    Json,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Renders JSON output: pretty-printed for a terminal, untouched when piped.
+///
+/// With a `filter`, each result is printed on its own, compact when piped
+/// (like `jq -c`).
+///
+/// # Errors
+///
+/// Returns an error if `output` isn't valid JSON or the filter doesn't apply.
+pub fn render_json(output: &str, filter: Option<&JsonFilter>, is_terminal: bool) -> Result<String> {
+    let value: serde_json::Value =
+        serde_json::from_str(output).map_err(|e| anyhow!("Output is not valid JSON ({})", e))?;
+    let results = match filter {
+        Some(filter) => filter.apply(&value)?,
+        None if !is_terminal => return Ok(output.to_string()),
+        None => vec![value],
+    };
+
+    let mut text = String::new();
+    for result in results {
+        let rendered = if is_terminal {
+            serde_json::to_string_pretty(&result)?
+        } else {
+            result.to_string()
+        };
+        text.push_str(&rendered);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Parses a comma-separated list of processors, e.g. `json,pager`.
+pub fn parse_processors(list: &str) -> Result<Vec<OutputProcessor>> {
+    list.split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Output settings (the `[output]` table in `config.toml`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutputConfig {
+    /// Processors applied to every generated command without its own list.
+    #[serde(default)]
+    pub processors: Vec<OutputProcessor>,
+}
+
+/// Where processed output ends up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Terminal {
+    /// Whether stdout is a terminal.
+    pub is_terminal: bool,
+    /// Number of rows, if known.
+    pub height: Option<usize>,
+}
+
+impl Terminal {
+    /// Describes the current process's stdout.
+    pub fn detect() -> Self {
+        use std::io::IsTerminal;
+        let is_terminal = std::io::stdout().is_terminal();
+        Self {
+            is_terminal,
+            height: is_terminal.then(terminal_height).flatten(),
+        }
+    }
+}
+
+/// Returns the number of rows of the terminal attached to stdout.
+#[cfg(unix)]
+fn terminal_height() -> Option<usize> {
+    // SAFETY: TIOCGWINSZ only writes into the winsize struct we pass.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_row > 0 {
+        return Some(size.ws_row as usize);
+    }
+    std::env::var("LINES").ok()?.parse().ok()
+}
+
+#[cfg(not(unix))]
+fn terminal_height() -> Option<usize> {
+    std::env::var("LINES").ok()?.parse().ok()
+}
+
+/// Applies the text transformations (`json`, `color`) to `output`.
+///
+/// Output that isn't JSON is returned unchanged. Colors are only added when
+/// `color` is true.
+pub fn transform(processors: &[OutputProcessor], output: &str, color: bool) -> String {
+    let mut text = output.to_string();
+
+    if processors.contains(&OutputProcessor::Json)
+        && let Ok(value) = serde_json::from_str::<serde_json::Value>(output)
+        && let Ok(pretty) = serde_json::to_string_pretty(&value)
+    {
+        text = pretty + "\n";
+    }
+
+    if color && processors.contains(&OutputProcessor::Color) && serde_json::from_str::<serde_json::Value>(&text).is_ok()
+    {
+        text = colorize_json(&text);
+    }
+
+    text
+}
+
+const KEY: &str = "\x1b[34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const LITERAL: &str = "\x1b[35m";
+const RESET: &str = "\x1b[0m";
+
+/// Adds ANSI colors to JSON text, keeping its layout.
+fn colorize_json(json: &str) -> String {
+    let chars: Vec<char> = json.chars().collect();
+    let mut out = String::with_capacity(json.len() * 2);
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            let token: String = chars[start..i].iter().collect();
+            let is_key = chars[i..].iter().find(|c| !c.is_whitespace()) == Some(&':');
+            out.push_str(if is_key { KEY } else { STRING });
+            out.push_str(&token);
+            out.push_str(RESET);
+        } else if c == '-' || c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || "+-.".contains(chars[i])) {
+                i += 1;
+            }
+            out.push_str(NUMBER);
+            out.extend(&chars[start..i]);
+            out.push_str(RESET);
+        } else if c.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+            out.push_str(LITERAL);
+            out.extend(&chars[start..i]);
+            out.push_str(RESET);
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+
+    out
+}
+
+/// Buffers a command's stdout and writes it processed once it has finished.
+///
+/// If the output grows beyond the buffer limit, what was buffered is written
+/// as is and the rest is passed straight through.
+pub struct ProcessingWriter<W: Write> {
+    processors: Vec<OutputProcessor>,
+    format: OutputFormat,
+    filter: Option<JsonFilter>,
+    inner: W,
+    buffer: Vec<u8>,
+    limit: usize,
+    overflowed: bool,
+}
+
+impl<W: Write> ProcessingWriter<W> {
+    /// Creates a writer applying `processors` to everything written to it.
+    pub fn new(processors: Vec<OutputProcessor>, inner: W) -> Self {
+        Self::with_limit(processors, inner, MAX_PROCESSED_BYTES)
+    }
+
+    /// Creates a writer that stops processing after `limit` bytes.
+    pub fn with_limit(processors: Vec<OutputProcessor>, inner: W, limit: usize) -> Self {
+        Self {
+            processors,
+            format: OutputFormat::Text,
+            filter: None,
+            inner,
+            buffer: Vec::new(),
+            limit,
+            overflowed: false,
+        }
+    }
+
+    /// Handles the output as `format`, filtering it with `filter` if any.
+    ///
+    /// A filter implies JSON output.
+    pub fn with_format(mut self, format: OutputFormat, filter: Option<JsonFilter>) -> Self {
+        self.format = format;
+        self.filter = filter;
+        self
+    }
+
+    /// Processes the buffered output and writes it, through a pager if
+    /// requested and the output doesn't fit on the terminal.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if JSON output doesn't parse or can't be filtered;
+    /// the output is then written as is.
+    pub fn finish<P: ProcessRunner>(mut self, terminal: Terminal, runner: &P) -> Result<()> {
+        if self.overflowed {
+            return Ok(());
+        }
+
+        let raw = String::from_utf8_lossy(&self.buffer);
+        let color = terminal.is_terminal && std::env::var_os("NO_COLOR").is_none();
+        let mut invalid = None;
+        let text = if self.format == OutputFormat::Json || self.filter.is_some() {
+            match render_json(&raw, self.filter.as_ref(), terminal.is_terminal) {
+                // Already pretty-printed (or left raw on purpose): only colors remain
+                Ok(json) => transform(&[OutputProcessor::Color], &json, color && self.processors.contains(&OutputProcessor::Color)),
+                Err(e) => {
+                    invalid = Some(e);
+                    raw.to_string()
+                }
+            }
+        } else {
+            transform(&self.processors, &raw, color)
+        };
+
+        let too_long = terminal.height.is_some_and(|rows| text.lines().count() >= rows);
+        if self.processors.contains(&OutputProcessor::Pager) && terminal.is_terminal && too_long {
+            let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+            let mut words = pager.split_whitespace();
+            if let Some(program) = words.next() {
+                let args: Vec<&str> = words.collect();
+                info!("Paging output through {}", pager);
+                match runner.run_with_input(program, &args, text.as_bytes()) {
+                    Ok(_) => return Ok(()),
+                    Err(e) => warn!("Pager '{}' failed, printing instead: {}", pager, e),
+                }
+            }
+        }
+
+        self.inner.write_all(text.as_bytes())?;
+        self.inner.flush()?;
+        invalid.map_or(Ok(()), Err)
+    }
+}
+
+impl<W: Write> Write for ProcessingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if self.overflowed {
+            return self.inner.write(data);
+        }
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() > self.limit {
+            info!("Output exceeds {} bytes, passing it through unprocessed", self.limit);
+            self.overflowed = true;
+            self.inner.write_all(&self.buffer)?;
+            self.buffer = Vec::new();
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.overflowed { self.inner.flush() } else { Ok(()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::SystemProcessRunner;
+
+    const NO_TERMINAL: Terminal = Terminal {
+        is_terminal: false,
+        height: None,
+    };
+
+    #[test]
+    fn test_parse_processors() {
+        assert_eq!(
+            parse_processors("json, pager").unwrap(),
+            vec![OutputProcessor::Json, OutputProcessor::Pager]
+        );
+        assert!(parse_processors("").unwrap().is_empty());
+        assert!(parse_processors("json,xml").is_err());
+    }
+
+    #[test]
+    fn test_transform_pretty_prints_json() {
+        let text = transform(&[OutputProcessor::Json], r#"{"a":[1,2]}"#, false);
+
+        assert_eq!(text, "{\n  \"a\": [\n    1,\n    2\n  ]\n}\n");
+    }
+
+    #[test]
+    fn test_transform_leaves_other_output_alone() {
+        let text = transform(&[OutputProcessor::Json, OutputProcessor::Color], "hello\n", true);
+
+        assert_eq!(text, "hello\n");
+    }
+
+    #[test]
+    fn test_colorize_json_marks_keys_and_values() {
+        let text = transform(&[OutputProcessor::Color], r#"{"n": -1.5, "ok": true, "s": "x:y"}"#, true);
+
+        assert!(text.contains(&format!("{KEY}\"n\"{RESET}: {NUMBER}-1.5{RESET}")));
+        assert!(text.contains(&format!("{LITERAL}true{RESET}")));
+        assert!(text.contains(&format!("{STRING}\"x:y\"{RESET}")));
+    }
+
+    #[test]
+    fn test_color_is_skipped_off_terminal() {
+        let text = transform(&[OutputProcessor::Color], r#"{"a":1}"#, false);
+
+        assert_eq!(text, r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn test_processing_writer_buffers_until_finish() {
+        let mut out = Vec::new();
+        let mut writer = ProcessingWriter::new(vec![OutputProcessor::Json], &mut out);
+        writer.write_all(br#"{"a":"#).unwrap();
+        writer.write_all(b"1}").unwrap();
+
+        writer.finish(NO_TERMINAL, &SystemProcessRunner::default()).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "{\n  \"a\": 1\n}\n");
+    }
+
+    #[test]
+    fn test_render_json_pretty_prints_for_terminals_only() {
+        let raw = r#"{"items":[{"name":"a"},{"name":"b"}]}"#;
+        let filter: JsonFilter = ".items[].name".parse().unwrap();
+
+        assert_eq!(render_json(raw, None, false).unwrap(), raw);
+        assert!(render_json(raw, None, true).unwrap().starts_with("{\n  \"items\": ["));
+        assert_eq!(render_json(raw, Some(&filter), false).unwrap(), "\"a\"\n\"b\"\n");
+        assert!(render_json("not json", None, true).unwrap_err().to_string().starts_with("Output is not valid JSON"));
+    }
+
+    #[test]
+    fn test_processing_writer_reports_invalid_json_output() {
+        let mut out = Vec::new();
+        let mut writer = ProcessingWriter::new(Vec::new(), &mut out).with_format(OutputFormat::Json, None);
+        writer.write_all(b"oops\n").unwrap();
+
+        let err = writer.finish(NO_TERMINAL, &SystemProcessRunner::default()).unwrap_err();
+
+        assert!(err.to_string().contains("not valid JSON"));
+        assert_eq!(String::from_utf8(out).unwrap(), "oops\n");
+    }
+
+    #[test]
+    fn test_processing_writer_passes_through_after_limit() {
+        let mut out = Vec::new();
+        let mut writer = ProcessingWriter::with_limit(vec![OutputProcessor::Json], &mut out, 4);
+        writer.write_all(b"{\"a\"").unwrap();
+        writer.write_all(b": 1}").unwrap();
+
+        writer.finish(NO_TERMINAL, &SystemProcessRunner::default()).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"a\": 1}");
+    }
+}
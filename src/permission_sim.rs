@@ -0,0 +1,399 @@
+//! Dry-run permission simulation for `ergo --simulate <command>`.
+//!
+//! The cached script runs in a scratch directory with every permission
+//! denied (`deno run --no-prompt`). Deno reports each denied access on
+//! stderr ("Requires read access to "/etc/hosts", run again with the
+//! --allow-read flag"), which tells what the script actually attempts.
+//!
+//! A script usually stops at its first denial, so the simulation runs it
+//! again, granting exactly the accesses seen so far, until it attempts
+//! nothing new. Only accesses that merely inspect (read, env, sys) are
+//! granted; the first write, network or run attempt ends the simulation,
+//! since exploring past it would mean performing it.
+//!
+//! The attempts are then compared with the declared permissions to spot
+//! missing and unneeded claims. Denials a script catches and hides are not
+//! seen, so "not attempted" is a hint, not a proof.
+
+use crate::executor::ProcessRunner;
+use crate::llm_generator::PermissionRequest;
+use crate::permissions::{Permission, PermissionKind};
+use anyhow::Result;
+use std::fmt;
+use std::path::Path;
+use tracing::info;
+
+/// Most runs of one simulation.
+pub const MAX_ROUNDS: usize = 8;
+
+/// An access the script attempted and Deno denied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    /// What kind of access.
+    pub kind: PermissionKind,
+    /// The path, host, variable, program or API, if Deno named one.
+    pub target: Option<String>,
+}
+
+impl Attempt {
+    /// Whether granting it lets the script change something outside itself.
+    fn has_effects(&self) -> bool {
+        matches!(self.kind, PermissionKind::Write | PermissionKind::Net | PermissionKind::Run)
+    }
+
+    /// A permission granting exactly this access.
+    fn grant(&self) -> Permission {
+        match &self.target {
+            Some(target) => Permission::scoped(self.kind, vec![target.clone()]),
+            None => Permission::unrestricted(self.kind),
+        }
+    }
+}
+
+impl fmt::Display for Attempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.target {
+            Some(target) => write!(f, "{} {}", self.kind.flag(), target),
+            None => write!(f, "{}", self.kind.flag()),
+        }
+    }
+}
+
+/// Finds the denied accesses Deno reported in `stderr`, in order, without
+/// duplicates. Kinds ergo doesn't know (e.g. `import`) are skipped.
+pub fn parse_denials(stderr: &str) -> Vec<Attempt> {
+    let mut attempts: Vec<Attempt> = Vec::new();
+    for rest in stderr.split("Requires ").skip(1) {
+        let Some((word, rest)) = rest.split_once(" access") else {
+            continue;
+        };
+        let flag = format!("--allow-{}", word);
+        let Some(kind) = PermissionKind::ALL.into_iter().find(|kind| kind.flag() == flag) else {
+            continue;
+        };
+        let target = rest
+            .strip_prefix(" to \"")
+            .and_then(|quoted| quoted.split_once('"'))
+            .map(|(target, _)| target.to_string());
+        let attempt = Attempt { kind, target };
+        if !attempts.contains(&attempt) {
+            attempts.push(attempt);
+        }
+    }
+    attempts
+}
+
+/// What a simulation found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulation {
+    /// Every access attempted, in the order they were first seen.
+    pub attempts: Vec<Attempt>,
+    /// The access with effects that ended the simulation, if any.
+    pub stopped_at: Option<Attempt>,
+    /// Whether the last run exited successfully.
+    pub completed: bool,
+    /// How many times the script ran.
+    pub rounds: usize,
+}
+
+/// Runs the script at `script_path` in `dir` with every permission denied,
+/// granting inspecting accesses round after round (see the module docs).
+///
+/// # Errors
+///
+/// Returns an error if Deno can't be started.
+pub fn simulate<P: ProcessRunner>(runner: &P, script_path: &Path, args: &[String], dir: &Path) -> Result<Simulation> {
+    let mut simulation = Simulation {
+        attempts: Vec::new(),
+        stopped_at: None,
+        completed: false,
+        rounds: 0,
+    };
+
+    while simulation.rounds < MAX_ROUNDS {
+        let grants: Vec<String> = simulation.attempts.iter().map(|a| a.grant().to_flag()).collect();
+        let mut deno_args = vec!["run".to_string(), "--no-prompt".to_string()];
+        deno_args.extend(grants);
+        deno_args.push(script_path.to_string_lossy().to_string());
+        deno_args.extend(args.iter().cloned());
+        let deno_args: Vec<&str> = deno_args.iter().map(String::as_str).collect();
+
+        let mut stderr = Vec::new();
+        let status = runner.run_streaming(Some(dir), "deno", &deno_args, &mut std::io::sink(), &mut stderr)?;
+        simulation.rounds += 1;
+        simulation.completed = status.success();
+
+        let new: Vec<Attempt> = parse_denials(&String::from_utf8_lossy(&stderr))
+            .into_iter()
+            .filter(|a| !simulation.attempts.contains(a))
+            .collect();
+        info!("Simulation round {}: {} new denied access(es)", simulation.rounds, new.len());
+        if new.is_empty() {
+            break;
+        }
+        simulation.stopped_at = new.iter().find(|a| a.has_effects()).cloned();
+        simulation.attempts.extend(new);
+        if simulation.stopped_at.is_some() {
+            break;
+        }
+    }
+    Ok(simulation)
+}
+
+/// Whether a declared `permission` allows `attempt`, as far as can be told
+/// without resolving relative paths.
+fn covers(permission: &Permission, attempt: &Attempt) -> bool {
+    if permission.kind != attempt.kind {
+        return false;
+    }
+    let (Some(scopes), Some(target)) = (&permission.scopes, &attempt.target) else {
+        return true;
+    };
+    scopes.iter().any(|scope| match attempt.kind {
+        PermissionKind::Read | PermissionKind::Write => {
+            !scope.starts_with('/') || target == scope || target.starts_with(&format!("{}/", scope.trim_end_matches('/')))
+        }
+        PermissionKind::Net => target == scope || target.split(':').next() == Some(scope.as_str()),
+        _ => target == scope,
+    })
+}
+
+/// How the declared permissions compare with a simulation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionReport {
+    /// The command simulated.
+    pub command: String,
+    /// Declared permissions the script attempted.
+    pub used: Vec<String>,
+    /// Attempts no declared permission allows.
+    pub missing: Vec<Attempt>,
+    /// Declared permissions the script never attempted.
+    pub unused: Vec<String>,
+    /// The simulation compared against.
+    pub simulation: Simulation,
+}
+
+impl PermissionReport {
+    /// Compares `declared` with what `simulation` saw.
+    pub fn compare(command: &str, declared: &[PermissionRequest], simulation: Simulation) -> Self {
+        let declared: Vec<Permission> = declared.iter().filter_map(|p| p.parsed().ok()).collect();
+        let mut used = Vec::new();
+        let mut unused = Vec::new();
+        for permission in &declared {
+            if simulation.attempts.iter().any(|a| covers(permission, a)) {
+                used.push(permission.to_flag());
+            } else {
+                unused.push(permission.to_flag());
+            }
+        }
+        let missing = simulation
+            .attempts
+            .iter()
+            .filter(|a| !declared.iter().any(|p| covers(p, a)))
+            .cloned()
+            .collect();
+        Self {
+            command: command.to_string(),
+            used,
+            missing,
+            unused,
+            simulation,
+        }
+    }
+}
+
+impl fmt::Display for PermissionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let simulation = &self.simulation;
+        writeln!(
+            f,
+            "🧪 '{}' ran {} time(s) with permissions denied",
+            self.command, simulation.rounds
+        )?;
+        if simulation.attempts.is_empty() {
+            writeln!(f, "   It attempted nothing that needs a permission")?;
+        } else {
+            writeln!(f, "   Attempted:")?;
+            for attempt in &simulation.attempts {
+                writeln!(f, "     • {}", attempt)?;
+            }
+        }
+        for flag in &self.used {
+            writeln!(f, "✅ Declared and used: {}", flag)?;
+        }
+        for attempt in &self.missing {
+            writeln!(f, "⚠️  Attempted but not declared: {}", attempt)?;
+        }
+        for flag in &self.unused {
+            writeln!(f, "💡 Declared but not attempted (maybe removable): {}", flag)?;
+        }
+        if let Some(attempt) = &simulation.stopped_at {
+            write!(
+                f,
+                "⏹️  Stopped at {}: exploring further would perform it, so later accesses weren't seen",
+                attempt
+            )?;
+        } else if simulation.completed {
+            write!(f, "🏁 The script ran to completion")?;
+        } else {
+            write!(f, "❗ The script failed for another reason; later accesses may not have been reached")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{ExitStatus, Output};
+    use std::sync::Mutex;
+
+    const READ_DENIED: &str = "error: Uncaught (in promise) NotCapable: Requires read access to \"/etc/hosts\", run again with the --allow-read flag\n";
+    const ENV_DENIED: &str = "PermissionDenied: Requires env access to \"HOME\", run again with the --allow-env flag\nPermissionDenied: Requires env access, run again with the --allow-env flag\n";
+    const NET_DENIED: &str = "NotCapable: Requires net access to \"api.example.com:443\", run again with the --allow-net flag\n";
+
+    /// Answers each run with the next stderr, then succeeds silently.
+    struct ScriptedRunner {
+        stderrs: Mutex<Vec<&'static str>>,
+        calls: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl ScriptedRunner {
+        fn new(stderrs: &[&'static str]) -> Self {
+            Self {
+                stderrs: Mutex::new(stderrs.iter().rev().copied().collect()),
+                calls: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl ProcessRunner for ScriptedRunner {
+        fn run(&self, _program: &str, _args: &[&str]) -> Result<Output> {
+            unimplemented!()
+        }
+
+        fn run_streaming(
+            &self,
+            _dir: Option<&Path>,
+            program: &str,
+            args: &[&str],
+            _stdout: &mut (dyn Write + Send),
+            stderr: &mut (dyn Write + Send),
+        ) -> Result<ExitStatus> {
+            let mut call = vec![program.to_string()];
+            call.extend(args.iter().map(|a| a.to_string()));
+            self.calls.lock().unwrap().push(call);
+            match self.stderrs.lock().unwrap().pop() {
+                Some(output) => {
+                    stderr.write_all(output.as_bytes())?;
+                    Ok(ExitStatus::from_raw(1 << 8))
+                }
+                None => Ok(ExitStatus::from_raw(0)),
+            }
+        }
+
+        fn run_interactive(&self, _dir: Option<&Path>, _program: &str, _args: &[&str]) -> Result<ExitStatus> {
+            unimplemented!()
+        }
+
+        fn run_with_input(&self, _program: &str, _args: &[&str], _input: &[u8]) -> Result<ExitStatus> {
+            unimplemented!()
+        }
+
+        fn program_exists(&self, _program: &str) -> bool {
+            true
+        }
+    }
+
+    fn declared(flags: &[&str]) -> Vec<PermissionRequest> {
+        flags
+            .iter()
+            .map(|flag| PermissionRequest {
+                permission: flag.to_string(),
+                reason: "test".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_denials_reads_deno_messages() {
+        let attempts = parse_denials(&format!(
+            "{}{}{}Requires import access to \"x.dev:443\", run again with the --allow-import flag",
+            READ_DENIED, ENV_DENIED, READ_DENIED
+        ));
+
+        assert_eq!(
+            attempts,
+            vec![
+                Attempt {
+                    kind: PermissionKind::Read,
+                    target: Some("/etc/hosts".to_string())
+                },
+                Attempt {
+                    kind: PermissionKind::Env,
+                    target: Some("HOME".to_string())
+                },
+                Attempt {
+                    kind: PermissionKind::Env,
+                    target: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_simulate_grants_inspecting_accesses_until_nothing_new() {
+        let runner = ScriptedRunner::new(&[READ_DENIED, ENV_DENIED]);
+
+        let simulation = simulate(&runner, Path::new("/tmp/s/hello.ts"), &["x".to_string()], Path::new("/tmp/s")).unwrap();
+
+        assert_eq!(simulation.rounds, 3);
+        assert!(simulation.completed);
+        assert_eq!(simulation.stopped_at, None);
+        assert_eq!(simulation.attempts.len(), 3);
+        let calls = runner.calls.lock().unwrap();
+        assert_eq!(calls[0], vec!["deno", "run", "--no-prompt", "/tmp/s/hello.ts", "x"]);
+        assert_eq!(
+            calls[2][..6],
+            ["deno", "run", "--no-prompt", "--allow-read=/etc/hosts", "--allow-env=HOME", "--allow-env"]
+        );
+    }
+
+    #[test]
+    fn test_simulate_stops_at_accesses_with_effects() {
+        let runner = ScriptedRunner::new(&[READ_DENIED, NET_DENIED, ENV_DENIED]);
+
+        let simulation = simulate(&runner, Path::new("s.ts"), &[], Path::new(".")).unwrap();
+
+        assert_eq!(simulation.rounds, 2);
+        assert!(!simulation.completed);
+        assert_eq!(simulation.stopped_at.unwrap().kind, PermissionKind::Net);
+    }
+
+    #[test]
+    fn test_report_compares_declared_permissions() {
+        let simulation = Simulation {
+            attempts: parse_denials(&format!("{}{}", READ_DENIED, NET_DENIED)),
+            stopped_at: None,
+            completed: true,
+            rounds: 3,
+        };
+
+        let report = PermissionReport::compare(
+            "weather",
+            &declared(&["--allow-net=api.example.com", "--allow-read=/var/data", "--allow-env"]),
+            simulation,
+        );
+
+        assert_eq!(report.used, vec!["--allow-net=api.example.com"]);
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].to_string(), "--allow-read /etc/hosts");
+        assert_eq!(report.unused, vec!["--allow-read=/var/data", "--allow-env"]);
+        let text = report.to_string();
+        assert!(text.contains("⚠️  Attempted but not declared: --allow-read /etc/hosts"));
+        assert!(text.contains("💡 Declared but not attempted (maybe removable): --allow-env"));
+        assert!(text.ends_with("🏁 The script ran to completion"));
+    }
+}
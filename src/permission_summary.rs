@@ -0,0 +1,231 @@
+//! Summary of the permissions held across the whole cache, for
+//! `ergo --permissions-report`.
+//!
+//! Every grant a cached command holds (on any platform) is classified by kind
+//! and by whether it's limited to scopes, and commands are grouped by grant.
+//! Groups are sorted by risk tier — the [`Severity`] of what the grant allows —
+//! so the broadest access comes first:
+//!
+//! - high: running programs, writing any file, unrecognized permissions
+//! - medium: writing some paths, reading any file, reaching any host, reading
+//!   any environment variable
+//! - low: everything else
+
+use crate::llm_generator::{GeneratedCommand, PermissionRequest};
+use crate::permissions::PermissionKind;
+use crate::security_review::Severity;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A class of permission a command can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Grant {
+    /// A known Deno permission, unrestricted or limited to scopes.
+    Known {
+        /// What kind of access is granted.
+        kind: PermissionKind,
+        /// Whether it isn't limited to specific scopes.
+        unrestricted: bool,
+    },
+    /// A flag ergo doesn't recognize as a Deno permission.
+    Unrecognized,
+}
+
+impl Grant {
+    /// Classifies the permission a command requests.
+    pub fn of(request: &PermissionRequest) -> Self {
+        match request.parsed() {
+            Ok(permission) => Grant::Known {
+                kind: permission.kind,
+                unrestricted: permission.is_unrestricted(),
+            },
+            Err(_) => Grant::Unrecognized,
+        }
+    }
+
+    /// How much the grant exposes.
+    pub fn risk(&self) -> Severity {
+        match *self {
+            Grant::Unrecognized => Severity::High,
+            Grant::Known { kind, unrestricted } => match kind {
+                PermissionKind::Run => Severity::High,
+                PermissionKind::Write if unrestricted => Severity::High,
+                PermissionKind::Write => Severity::Medium,
+                PermissionKind::Read | PermissionKind::Net | PermissionKind::Env if unrestricted => Severity::Medium,
+                _ => Severity::Low,
+            },
+        }
+    }
+
+    /// What holding the grant lets a command do, e.g. `write any file`.
+    pub fn describe(&self) -> &'static str {
+        let Grant::Known { kind, unrestricted } = *self else {
+            return "request an unrecognized permission";
+        };
+        match (kind, unrestricted) {
+            (PermissionKind::Read, true) => "read any file",
+            (PermissionKind::Read, false) => "read specific paths",
+            (PermissionKind::Write, true) => "write any file",
+            (PermissionKind::Write, false) => "write specific paths",
+            (PermissionKind::Net, true) => "reach any host",
+            (PermissionKind::Net, false) => "reach specific hosts",
+            (PermissionKind::Env, true) => "read any environment variable",
+            (PermissionKind::Env, false) => "read specific environment variables",
+            (PermissionKind::Run, true) => "run any program",
+            (PermissionKind::Run, false) => "run specific programs",
+            (PermissionKind::Sys, true) => "query any system information",
+            (PermissionKind::Sys, false) => "query specific system information",
+        }
+    }
+}
+
+/// The commands holding one grant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrantGroup {
+    /// The grant.
+    pub grant: Grant,
+    /// The commands holding it, sorted.
+    pub commands: Vec<String>,
+}
+
+/// Which commands hold which grants.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PermissionSummary {
+    /// How many commands were looked at.
+    pub total: usize,
+    /// The grants held, riskiest first.
+    pub groups: Vec<GrantGroup>,
+    /// Commands needing no permission at all, sorted.
+    pub unprivileged: Vec<String>,
+}
+
+impl PermissionSummary {
+    /// Groups `commands` by the grants they hold on any platform.
+    pub fn build<'a>(commands: impl IntoIterator<Item = &'a GeneratedCommand>) -> Self {
+        let mut holders: BTreeMap<Grant, BTreeSet<String>> = BTreeMap::new();
+        let mut unprivileged = Vec::new();
+        let mut total = 0;
+        for command in commands {
+            total += 1;
+            let requests = command
+                .permissions
+                .iter()
+                .chain(command.variants.values().flat_map(|variant| &variant.permissions));
+            let mut any = false;
+            for request in requests {
+                holders.entry(Grant::of(request)).or_default().insert(command.name.clone());
+                any = true;
+            }
+            if !any {
+                unprivileged.push(command.name.clone());
+            }
+        }
+        unprivileged.sort();
+
+        let mut groups: Vec<GrantGroup> = holders
+            .into_iter()
+            .map(|(grant, commands)| GrantGroup {
+                grant,
+                commands: commands.into_iter().collect(),
+            })
+            .collect();
+        // Riskiest first, then the most widely held
+        groups.sort_by(|a, b| {
+            b.grant
+                .risk()
+                .cmp(&a.grant.risk())
+                .then(b.commands.len().cmp(&a.commands.len()))
+                .then(a.grant.cmp(&b.grant))
+        });
+        Self {
+            total,
+            groups,
+            unprivileged,
+        }
+    }
+}
+
+fn commands_can(count: usize) -> String {
+    match count {
+        1 => "1 command can".to_string(),
+        n => format!("{} commands can", n),
+    }
+}
+
+impl fmt::Display for PermissionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "🔐 Permissions of {} cached command(s)", self.total)?;
+        let mut tier = None;
+        for group in &self.groups {
+            let risk = group.grant.risk();
+            if tier != Some(risk) {
+                let heading = match risk {
+                    Severity::High => "🔴 High risk",
+                    Severity::Medium => "🟠 Medium risk",
+                    Severity::Low => "🟢 Low risk",
+                };
+                write!(f, "\n\n{}", heading)?;
+                tier = Some(risk);
+            }
+            write!(
+                f,
+                "\n   {} {}: {}",
+                commands_can(group.commands.len()),
+                group.grant.describe(),
+                group.commands.join(", ")
+            )?;
+        }
+        if !self.unprivileged.is_empty() {
+            write!(f, "\n\n⚪ No permissions: {}", self.unprivileged.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_generator::PlatformVariant;
+    use crate::testing::command;
+
+    #[test]
+    fn test_summary_groups_commands_by_grant_riskiest_first() {
+        let mut deploy = command("deploy", &["--allow-run=git", "--allow-net"]);
+        deploy.variants.insert(
+            "windows".to_string(),
+            PlatformVariant {
+                script_file: "deploy.windows.ts".to_string(),
+                permissions: command("", &["--allow-write"]).permissions,
+            },
+        );
+        let commands = [
+            deploy,
+            command("weather", &["--allow-net"]),
+            command("notes", &["--allow-read=./notes", "--allow-write=./notes"]),
+            command("greet", &[]),
+        ];
+
+        let summary = PermissionSummary::build(&commands);
+
+        let groups: Vec<(&str, Vec<&str>)> = summary
+            .groups
+            .iter()
+            .map(|g| (g.grant.describe(), g.commands.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ("write any file", vec!["deploy"]),
+                ("run specific programs", vec!["deploy"]),
+                ("reach any host", vec!["deploy", "weather"]),
+                ("write specific paths", vec!["notes"]),
+                ("read specific paths", vec!["notes"]),
+            ]
+        );
+        assert_eq!(summary.unprivileged, vec!["greet"]);
+        let text = summary.to_string();
+        assert!(text.starts_with("🔐 Permissions of 4 cached command(s)\n\n🔴 High risk\n   1 command can write any file: deploy\n"));
+        assert!(text.contains("🟠 Medium risk\n   2 commands can reach any host: deploy, weather\n"));
+        assert!(text.ends_with("🟢 Low risk\n   1 command can read specific paths: notes\n\n⚪ No permissions: greet"));
+    }
+}
@@ -3,13 +3,129 @@
 //! This module provides the interactive UI for requesting user consent when
 //! commands require special permissions (file access, network, etc.).
 
-use crate::command_cache::{PermissionConsent, PermissionDecision};
-use crate::llm_generator::PermissionRequest;
+use crate::command_cache::{PermissionConsent, PermissionDecision, SESSION_CONSENT_SECS};
+use crate::llm_generator::{Confidence, GeneratedCommand, GenerationPlan, PermissionRequest, UsageExample};
+use crate::permissions::PermissionKind;
+use crate::project_scope::Escape;
 use crate::providers::{SystemTimeProvider, TimeProvider};
+use crate::semantic_search::SimilarCommand;
 use anyhow::Result;
-use std::io::{self, BufRead, Write};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
 use tracing::info;
 
+/// Decides whether a command may run with the permissions just shown.
+///
+/// The default, [`StdinConsent`], asks the user. Tests and apps embedding the
+/// router can answer without a terminal.
+pub trait ConsentProvider: Send + Sync {
+    /// Returns the decision for `subject` (e.g. "command 'weather'"), whose
+    /// permissions have been shown on `output`.
+    fn choose(&self, subject: &str, output: &mut dyn Write) -> Result<PermissionConsent>;
+
+    /// Answers a yes/no `question`, to be shown on `output`.
+    ///
+    /// By default the answer is yes unless [`Self::choose`] denies.
+    fn confirm(&self, question: &str, output: &mut dyn Write) -> Result<bool> {
+        Ok(self.choose(question, output)? != PermissionConsent::Denied)
+    }
+
+    /// Asks an open `question`, to be shown on `output`.
+    ///
+    /// By default the answer is empty.
+    fn ask(&self, _question: &str, _output: &mut dyn Write) -> Result<String> {
+        Ok(String::new())
+    }
+}
+
+/// Environment variable answering every consent prompt (`once`, `forever`
+/// or `deny`), for automation that has no terminal to ask on.
+pub const ASSUME_CONSENT_ENV: &str = "ERGO_ASSUME_CONSENT";
+
+/// Asks on stdin for a 1/2/3 choice until it is valid.
+///
+/// Fails instead of asking when stdin isn't a terminal (a script, a pipe or
+/// a closed stdin), since nobody is there to answer.
+pub struct StdinConsent;
+
+impl StdinConsent {
+    fn stdin(prompt: &str) -> Result<io::StdinLock<'static>> {
+        let stdin = io::stdin();
+        if !stdin.is_terminal() {
+            anyhow::bail!(
+                "Can't ask about {}: stdin isn't a terminal.\n💡 Run it once in a terminal and choose \
+                'Accept Forever', or set {}=once (or session, forever, deny) to answer prompts in automation",
+                prompt,
+                ASSUME_CONSENT_ENV
+            );
+        }
+        Ok(stdin.lock())
+    }
+}
+
+impl ConsentProvider for StdinConsent {
+    fn choose(&self, subject: &str, mut output: &mut dyn Write) -> Result<PermissionConsent> {
+        PermissionUI::read_choice_with_io(subject, &mut Self::stdin(subject)?, &mut output)
+    }
+
+    fn confirm(&self, question: &str, mut output: &mut dyn Write) -> Result<bool> {
+        PermissionUI::read_yes_no_with_io(question, &mut Self::stdin(question)?, &mut output)
+    }
+
+    fn ask(&self, question: &str, mut output: &mut dyn Write) -> Result<String> {
+        PermissionUI::read_answer_with_io(question, &mut Self::stdin(question)?, &mut output)
+    }
+}
+
+/// Gives the same answer to every consent prompt, as set by
+/// [`ASSUME_CONSENT_ENV`]. Confirmations are yes unless it denies.
+pub struct AssumedConsent(pub PermissionConsent);
+
+impl AssumedConsent {
+    /// Parses a value of [`ASSUME_CONSENT_ENV`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `value` isn't `once`, `session`, `forever` or
+    /// `deny`.
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "once" => Ok(Self(PermissionConsent::AcceptOnce)),
+            "forever" => Ok(Self(PermissionConsent::AcceptForever)),
+            "deny" => Ok(Self(PermissionConsent::Denied)),
+            "session" => Ok(Self(PermissionConsent::AcceptForSession)),
+            other => anyhow::bail!("Invalid {} '{}': expected once, session, forever or deny", ASSUME_CONSENT_ENV, other),
+        }
+    }
+}
+
+impl ConsentProvider for AssumedConsent {
+    fn choose(&self, subject: &str, output: &mut dyn Write) -> Result<PermissionConsent> {
+        let answer = match self.0 {
+            PermissionConsent::AcceptOnce => "accept once",
+            PermissionConsent::AcceptForever => "accept forever",
+            PermissionConsent::Denied => "deny",
+            PermissionConsent::AcceptForSession => "accept for this session",
+        };
+        writeln!(output, "\n🤖 Consent for {}: {} ({} is set)", subject, answer, ASSUME_CONSENT_ENV)?;
+        info!("Assumed {:?} for {}", self.0, subject);
+        Ok(self.0.clone())
+    }
+}
+
+/// The consent provider selected by the environment: [`AssumedConsent`]
+/// when [`ASSUME_CONSENT_ENV`] is set, [`StdinConsent`] otherwise.
+///
+/// # Errors
+///
+/// Returns an error if [`ASSUME_CONSENT_ENV`] has an invalid value.
+pub fn select_consent_provider(env: impl Fn(&str) -> Option<String>) -> Result<Box<dyn ConsentProvider>> {
+    match env(ASSUME_CONSENT_ENV).filter(|value| !value.is_empty()) {
+        Some(value) => Ok(Box::new(AssumedConsent::parse(&value)?)),
+        None => Ok(Box::new(StdinConsent)),
+    }
+}
+
 /// Handles user interaction for permission consent dialogs.
 ///
 /// `PermissionUI` displays permission requests to users and collects their
@@ -17,6 +133,7 @@ use tracing::info;
 /// - Accept Once: Run the command this time, ask again next time
 /// - Accept Forever: Always run with these permissions
 /// - Deny: Don't run the command
+/// - Accept for Session: Run without asking again from this shell session
 ///
 /// # Example
 ///
@@ -32,12 +149,13 @@ use tracing::info;
 ///     },
 /// ];
 ///
-/// let consent = ui.prompt_for_consent("my-command", "Does something", &permissions)?;
+/// let consent = ui.prompt_for_consent("my-command", "Does something", &permissions, &[])?;
 /// # Ok::<(), anyhow::Error>(())
 /// ```
 pub struct PermissionUI {
     verbose: bool,
     time_provider: Box<dyn TimeProvider>,
+    consent: Box<dyn ConsentProvider>,
 }
 
 impl PermissionUI {
@@ -55,9 +173,16 @@ impl PermissionUI {
         Self {
             verbose,
             time_provider,
+            consent: Box::new(StdinConsent),
         }
     }
 
+    /// Uses `consent` instead of stdin to decide on permission requests.
+    pub fn with_consent_provider(mut self, consent: Box<dyn ConsentProvider>) -> Self {
+        self.consent = consent;
+        self
+    }
+
     // =========================================================================
     // Core methods with I/O injection (testable)
     // =========================================================================
@@ -71,6 +196,7 @@ impl PermissionUI {
     /// * `command_name` - Name of the command requesting permissions
     /// * `command_description` - Human-readable description of the command
     /// * `permissions` - List of permissions the command requires
+    /// * `examples` - Usage examples shown with the request
     /// * `input` - Reader to get user input from (e.g., stdin or mock)
     /// * `output` - Writer for displaying the prompt (e.g., stdout or mock)
     ///
@@ -86,50 +212,248 @@ impl PermissionUI {
         command_name: &str,
         command_description: &str,
         permissions: &[PermissionRequest],
+        examples: &[UsageExample],
         input: &mut R,
         output: &mut W,
+    ) -> Result<PermissionConsent> {
+        self.consent_dialog(command_name, command_description, permissions, examples, output, |subject, output| {
+            Self::read_choice_with_io(subject, input, output)
+        })
+    }
+
+    /// Shows a command's permission request and lets `choose` decide.
+    fn consent_dialog<W: Write>(
+        &self,
+        command_name: &str,
+        command_description: &str,
+        permissions: &[PermissionRequest],
+        examples: &[UsageExample],
+        output: &mut W,
+        choose: impl FnOnce(&str, &mut W) -> Result<PermissionConsent>,
     ) -> Result<PermissionConsent> {
         if permissions.is_empty() {
             // No permissions needed, auto-accept
             return Ok(PermissionConsent::AcceptForever);
         }
 
-        self.display_permission_request_with_io(command_name, command_description, permissions, output)?;
+        self.display_permission_request_with_io(command_name, command_description, permissions, examples, output)?;
+        choose(&format!("command '{}'", command_name), output)
+    }
 
+    /// Prompts for consent to run a command of an untrusted bioma (see
+    /// [`crate::trust`]) using custom I/O streams.
+    ///
+    /// The script is shown before the permission request, and consent is
+    /// asked even if the command needs no permissions.
+    pub fn prompt_for_untrusted_consent_with_io<R: BufRead, W: Write>(
+        &self,
+        command: &GeneratedCommand,
+        bioma: &Path,
+        script: &str,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<PermissionConsent> {
+        let notice = Self::untrusted_notice(&command.name, bioma);
+        self.review_consent_dialog(command, &notice, script, output, |subject, output| {
+            Self::read_choice_with_io(subject, input, output)
+        })
+    }
+
+    /// Prompts for consent to run a command whose script changed on disk
+    /// since it was generated, using custom I/O streams.
+    ///
+    /// The diff is shown before the permission request, and consent is
+    /// asked even if the command needs no permissions.
+    pub fn prompt_for_modified_script_consent_with_io<R: BufRead, W: Write>(
+        &self,
+        command: &GeneratedCommand,
+        diff: &str,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<PermissionConsent> {
+        let notice = Self::modified_script_notice(&command.name);
+        self.review_consent_dialog(command, &notice, diff, output, |subject, output| {
+            Self::read_choice_with_io(subject, input, output)
+        })
+    }
+
+    fn untrusted_notice(command_name: &str, bioma: &Path) -> String {
+        format!(
+            "🚧 '{}' comes from an untrusted bioma: {}\n   Review its script; it is shown on every run, whatever was allowed before:",
+            command_name,
+            bioma.display()
+        )
+    }
+
+    fn imported_notice(command_name: &str, source: &str) -> String {
+        format!(
+            "📦 '{}' was imported from {}\n   Review its script; it is shown on every run until you allow it forever:",
+            command_name, source
+        )
+    }
+
+    fn modified_script_notice(command_name: &str) -> String {
+        format!(
+            "⚠️  The script of '{}' was changed on disk since ergo wrote it.\n   Review the changes; it won't run with them unless you allow it again:",
+            command_name
+        )
+    }
+
+    /// Shows `notice` and `listing` (a script or a diff), then the command's
+    /// permission request, and lets `choose` decide.
+    fn review_consent_dialog<W: Write>(
+        &self,
+        command: &GeneratedCommand,
+        notice: &str,
+        listing: &str,
+        output: &mut W,
+        choose: impl FnOnce(&str, &mut W) -> Result<PermissionConsent>,
+    ) -> Result<PermissionConsent> {
+        writeln!(output, "\n{}", notice)?;
+        for line in listing.lines() {
+            writeln!(output, "   │ {}", line)?;
+        }
+        self.display_permission_request_with_io(
+            &command.name,
+            &command.description,
+            &command.permissions,
+            &command.examples,
+            output,
+        )?;
+        choose(&format!("command '{}'", command.name), output)
+    }
+
+    /// Prompts once for the permissions of every command in a workflow,
+    /// using custom I/O streams.
+    ///
+    /// # Arguments
+    ///
+    /// * `workflow_name` - Name of the workflow
+    /// * `commands` - Name, description and permissions of each command that
+    ///   still needs consent
+    /// * `input` - Reader to get user input from (e.g., stdin or mock)
+    /// * `output` - Writer for displaying the prompt (e.g., stdout or mock)
+    ///
+    /// # Returns
+    ///
+    /// One decision applying to all the commands. Auto-accepts if none of
+    /// them needs permissions.
+    pub fn prompt_for_workflow_consent_with_io<R: BufRead, W: Write>(
+        &self,
+        workflow_name: &str,
+        commands: &[(&str, &str, &[PermissionRequest])],
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<PermissionConsent> {
+        Self::workflow_consent_dialog(workflow_name, commands, output, |subject, output| {
+            Self::read_choice_with_io(subject, input, output)
+        })
+    }
+
+    /// Shows the permission requests of a workflow's commands and lets
+    /// `choose` decide for all of them.
+    fn workflow_consent_dialog<W: Write>(
+        workflow_name: &str,
+        commands: &[(&str, &str, &[PermissionRequest])],
+        output: &mut W,
+        choose: impl FnOnce(&str, &mut W) -> Result<PermissionConsent>,
+    ) -> Result<PermissionConsent> {
+        if commands.iter().all(|(_, _, permissions)| permissions.is_empty()) {
+            return Ok(PermissionConsent::AcceptForever);
+        }
+
+        writeln!(output, "\n{}", "=".repeat(60))?;
+        writeln!(output, "🔐 WORKFLOW PERMISSION REVIEW")?;
+        writeln!(output, "{}", "=".repeat(60))?;
+        writeln!(output)?;
+        writeln!(output, "🧭 Workflow: {}", workflow_name)?;
+
+        for (command_name, description, permissions) in commands.iter().filter(|(_, _, p)| !p.is_empty()) {
+            writeln!(output)?;
+            writeln!(output, "📋 Command: {}", command_name)?;
+            writeln!(output, "📝 Description: {}", description)?;
+            writeln!(output)?;
+            Self::write_permissions_with_io(permissions, output)?;
+        }
+
+        writeln!(output, "{}", "-".repeat(60))?;
+        writeln!(output, "What would you like to do?")?;
+        writeln!(output)?;
+        writeln!(output, "  1️⃣  Accept Once    - Run the workflow this time only")?;
+        writeln!(output, "  2️⃣  Accept Forever - Always run these commands with their permissions")?;
+        writeln!(output, "  3️⃣  Deny          - Don't run the workflow")?;
+        writeln!(output, "  4️⃣  Accept for Session - Run these commands without asking again in this shell session")?;
+        writeln!(output)?;
+        writeln!(output, "{}", "=".repeat(60))?;
+
+        choose(&format!("workflow '{}'", workflow_name), output)
+    }
+
+    /// Reads a 1/2/3 consent choice, asking again until it is valid.
+    fn read_choice_with_io<R: BufRead, W: Write>(subject: &str, input: &mut R, output: &mut W) -> Result<PermissionConsent> {
         loop {
-            write!(output, "\nChoose an option (1/2/3): ")?;
+            write!(output, "\nChoose an option (1/2/3/4): ")?;
             output.flush()?;
 
             let mut line = String::new();
-            input.read_line(&mut line)?;
+            if input.read_line(&mut line)? == 0 {
+                anyhow::bail!("No answer about {}: the input ended", subject);
+            }
             let choice = line.trim();
 
             match choice {
                 "1" => {
-                    info!("User chose 'Accept Once' for command '{}'", command_name);
+                    info!("User chose 'Accept Once' for {}", subject);
                     return Ok(PermissionConsent::AcceptOnce);
                 }
                 "2" => {
-                    info!("User chose 'Accept Forever' for command '{}'", command_name);
+                    info!("User chose 'Accept Forever' for {}", subject);
                     return Ok(PermissionConsent::AcceptForever);
                 }
                 "3" => {
-                    info!("User chose 'Deny' for command '{}'", command_name);
+                    info!("User chose 'Deny' for {}", subject);
                     return Ok(PermissionConsent::Denied);
                 }
+                "4" => {
+                    info!("User chose 'Accept for Session' for {}", subject);
+                    return Ok(PermissionConsent::AcceptForSession);
+                }
                 _ => {
-                    writeln!(output, "Invalid choice. Please enter 1, 2, or 3.")?;
+                    writeln!(output, "Invalid choice. Please enter 1, 2, 3, or 4.")?;
                 }
             }
         }
     }
 
+    /// Asks a yes/no `question`; an empty answer means yes, end of input no.
+    fn read_yes_no_with_io<R: BufRead, W: Write>(question: &str, input: &mut R, output: &mut W) -> Result<bool> {
+        write!(output, "{} [Y/n]: ", question)?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(false);
+        }
+        Ok(matches!(line.trim().to_lowercase().as_str(), "" | "y" | "yes"))
+    }
+
+    /// Asks an open `question`; end of input is an empty answer.
+    fn read_answer_with_io<R: BufRead, W: Write>(question: &str, input: &mut R, output: &mut W) -> Result<String> {
+        write!(output, "{}: ", question)?;
+        output.flush()?;
+
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        Ok(line.trim().to_string())
+    }
+
     /// Displays the permission request dialog to the provided output.
     fn display_permission_request_with_io<W: Write>(
         &self,
         command_name: &str,
         command_description: &str,
         permissions: &[PermissionRequest],
+        examples: &[UsageExample],
         output: &mut W,
     ) -> Result<()> {
         writeln!(output, "\n{}", "=".repeat(60))?;
@@ -139,32 +463,64 @@ impl PermissionUI {
         writeln!(output, "📋 Command: {}", command_name)?;
         writeln!(output, "📝 Description: {}", command_description)?;
         writeln!(output)?;
-
-        if permissions.is_empty() {
-            writeln!(output, "✅ This command requires no special permissions.")?;
-        } else {
-            writeln!(output, "🔑 This command requires the following permissions:")?;
-            writeln!(output)?;
-
-            for (i, perm) in permissions.iter().enumerate() {
-                writeln!(output, "   {}. 🛡️ {}", i + 1, perm.permission)?;
-                writeln!(output, "      💡 Why: {}", perm.reason)?;
-                writeln!(output)?;
+        if !examples.is_empty() {
+            writeln!(output, "💡 Examples:")?;
+            for example in examples {
+                writeln!(output, "   {}", example)?;
             }
+            writeln!(output)?;
         }
 
+        Self::write_permissions_with_io(permissions, output)?;
+
         writeln!(output, "{}", "-".repeat(60))?;
         writeln!(output, "What would you like to do?")?;
         writeln!(output)?;
         writeln!(output, "  1️⃣  Accept Once    - Run this time only, ask again next time")?;
         writeln!(output, "  2️⃣  Accept Forever - Always run with these permissions")?;
         writeln!(output, "  3️⃣  Deny          - Don't run this command")?;
+        writeln!(
+            output,
+            "  4️⃣  Accept for Session - Don't ask again in this shell session (for {} hours)",
+            SESSION_CONSENT_SECS / 3600
+        )?;
         writeln!(output)?;
         writeln!(output, "{}", "=".repeat(60))?;
 
         Ok(())
     }
 
+    /// Lists a command's permissions with their scopes and reasons.
+    fn write_permissions_with_io<W: Write>(permissions: &[PermissionRequest], output: &mut W) -> Result<()> {
+        if permissions.is_empty() {
+            writeln!(output, "✅ This command requires no special permissions.")?;
+            return Ok(());
+        }
+
+        writeln!(output, "🔑 This command requires the following permissions:")?;
+        writeln!(output)?;
+
+        for (i, perm) in permissions.iter().enumerate() {
+            match perm.parsed() {
+                Ok(parsed) => {
+                    writeln!(output, "   {}. 🛡️ {} ({})", i + 1, parsed.kind.description(), perm.permission)?;
+                    if parsed.is_unrestricted() {
+                        writeln!(output, "      ⚠️  Scope: {} (unrestricted)", parsed.describe_scope())?;
+                    } else {
+                        writeln!(output, "      🎯 Scope: {}", parsed.describe_scope())?;
+                    }
+                }
+                Err(_) => {
+                    writeln!(output, "   {}. 🛡️ {}", i + 1, perm.permission)?;
+                    writeln!(output, "      ⚠️  Unrecognized permission")?;
+                }
+            }
+            writeln!(output, "      💡 Why: {}", perm.reason)?;
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+
     /// Shows permission denied message to the provided output.
     ///
     /// # Arguments
@@ -213,19 +569,149 @@ impl PermissionUI {
         Ok(())
     }
 
+    /// Shows the assumptions the model made while generating a command and
+    /// how confident it is, so wrong assumptions can be corrected before the
+    /// command runs.
+    ///
+    /// Prints nothing when there are no assumptions and confidence isn't low.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_name` - Name of the generated command
+    /// * `assumptions` - What the model assumed
+    /// * `confidence` - The model's confidence, if it gave one
+    /// * `output` - Writer for the message
+    pub fn show_assumptions_with_io<W: Write>(
+        &self,
+        command_name: &str,
+        assumptions: &[String],
+        confidence: Option<Confidence>,
+        output: &mut W,
+    ) -> Result<()> {
+        if assumptions.is_empty() && confidence != Some(Confidence::Low) {
+            return Ok(());
+        }
+
+        match confidence {
+            Some(confidence) => writeln!(
+                output,
+                "🤔 Generated '{}' (confidence: {}){}",
+                command_name,
+                confidence,
+                if assumptions.is_empty() { "" } else { ", assuming:" }
+            )?,
+            None => writeln!(output, "🤔 Generated '{}', assuming:", command_name)?,
+        }
+        for assumption in assumptions {
+            writeln!(output, "   • {}", assumption)?;
+        }
+        writeln!(
+            output,
+            "   Wrong? Correct it with: ergo --nope \"<what to change>\""
+        )?;
+        Ok(())
+    }
+
+    /// Warns that a project command reaches outside its project, before
+    /// asking for consent (see [`crate::project_scope`]).
+    ///
+    /// Prints nothing when there are no escapes.
+    ///
+    /// # Arguments
+    ///
+    /// * `command_name` - Name of the project command
+    /// * `root` - The project root
+    /// * `escapes` - Where the command reaches outside the root
+    /// * `output` - Writer for the warning
+    pub fn show_outside_project_with_io<W: Write>(
+        &self,
+        command_name: &str,
+        root: &Path,
+        escapes: &[Escape],
+        output: &mut W,
+    ) -> Result<()> {
+        if escapes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(
+            output,
+            "⚠️  '{}' belongs to the project at {} but reaches outside it:",
+            command_name,
+            root.display()
+        )?;
+        for escape in escapes {
+            let access = if escape.kind == PermissionKind::Write { "writes" } else { "reads" };
+            match escape.line {
+                Some(line) => writeln!(output, "   • {} {} (script line {})", access, escape.path, line)?,
+                None => writeln!(output, "   • {} {} ({} scope)", access, escape.path, escape.kind.flag())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Offers to run a cached command similar to a natural language intent
+    /// instead of generating a new one.
+    ///
+    /// # Returns
+    ///
+    /// Whether the user wants to run the cached command.
+    pub fn offer_similar_command_with_io<W: Write>(&self, similar: &SimilarCommand, output: &mut W) -> Result<bool> {
+        writeln!(
+            output,
+            "🔎 A cached command may already do this: '{}' ({:.0}% match)",
+            similar.name,
+            similar.similarity * 100.0
+        )?;
+        writeln!(output, "   {}", similar.description)?;
+        let question = format!("Run existing '{}' instead of generating a new command?", similar.name);
+        let accepted = self.consent.confirm(&question, output)?;
+        info!("User {} running similar command '{}'", if accepted { "chose" } else { "declined" }, similar.name);
+        Ok(accepted)
+    }
+
+    /// Shows the plan for a command before it is generated and lets the
+    /// user approve it or ask for changes, which are added to the plan
+    /// until it is approved.
+    ///
+    /// # Returns
+    ///
+    /// Whether the plan was approved; an empty answer to "what should
+    /// change" cancels the generation.
+    pub fn review_plan_with_io<W: Write>(&self, request: &str, plan: &mut GenerationPlan, output: &mut W) -> Result<bool> {
+        loop {
+            writeln!(output, "📋 Plan for '{}':", request)?;
+            for line in plan.to_string().lines() {
+                writeln!(output, "   {}", line)?;
+            }
+            if self.consent.confirm("Generate the command following this plan?", output)? {
+                info!("User approved the plan for '{}'", request);
+                return Ok(true);
+            }
+            let change = self
+                .consent
+                .ask("What should change? (leave empty to cancel)", output)?;
+            if change.is_empty() {
+                info!("User cancelled the plan for '{}'", request);
+                return Ok(false);
+            }
+            plan.changes.push(change);
+        }
+    }
+
     // =========================================================================
     // Convenience methods using standard I/O
     // =========================================================================
 
-    /// Prompts the user for permission consent using stdin/stdout.
-    ///
-    /// This is a convenience wrapper around [`Self::prompt_for_consent_with_io`].
+    /// Prompts for permission consent on stdout, deciding with the consent
+    /// provider (stdin by default).
     ///
     /// # Arguments
     ///
     /// * `command_name` - Name of the command requesting permissions
     /// * `command_description` - Human-readable description of the command
     /// * `permissions` - List of permissions the command requires
+    /// * `examples` - Usage examples shown with the request
     ///
     /// # Returns
     ///
@@ -236,11 +722,52 @@ impl PermissionUI {
         command_name: &str,
         command_description: &str,
         permissions: &[PermissionRequest],
+        examples: &[UsageExample],
     ) -> Result<PermissionConsent> {
-        let stdin = io::stdin();
-        let mut input = stdin.lock();
-        let mut output = io::stdout();
-        self.prompt_for_consent_with_io(command_name, command_description, permissions, &mut input, &mut output)
+        self.consent_dialog(command_name, command_description, permissions, examples, &mut io::stdout(), |subject, output| {
+            self.consent.choose(subject, output)
+        })
+    }
+
+    /// Prompts on stdout for consent to run a command of an untrusted
+    /// bioma, deciding with the consent provider (stdin by default).
+    pub fn prompt_for_untrusted_consent(&self, command: &GeneratedCommand, bioma: &Path, script: &str) -> Result<PermissionConsent> {
+        let notice = Self::untrusted_notice(&command.name, bioma);
+        self.review_consent_dialog(command, &notice, script, &mut io::stdout(), |subject, output| {
+            self.consent.choose(subject, output)
+        })
+    }
+
+    /// Prompts on stdout for consent to run a command imported from
+    /// `source` (see `ergo --import-url`), deciding with the consent provider
+    /// (stdin by default).
+    pub fn prompt_for_imported_consent(&self, command: &GeneratedCommand, source: &str, script: &str) -> Result<PermissionConsent> {
+        let notice = Self::imported_notice(&command.name, source);
+        self.review_consent_dialog(command, &notice, script, &mut io::stdout(), |subject, output| {
+            self.consent.choose(subject, output)
+        })
+    }
+
+    /// Prompts on stdout for consent to run a command whose script changed
+    /// on disk, showing `diff`, deciding with the consent provider (stdin by
+    /// default).
+    pub fn prompt_for_modified_script_consent(&self, command: &GeneratedCommand, diff: &str) -> Result<PermissionConsent> {
+        let notice = Self::modified_script_notice(&command.name);
+        self.review_consent_dialog(command, &notice, diff, &mut io::stdout(), |subject, output| {
+            self.consent.choose(subject, output)
+        })
+    }
+
+    /// Prompts once for the permissions of every command in a workflow,
+    /// deciding with the consent provider (stdin by default).
+    pub fn prompt_for_workflow_consent(
+        &self,
+        workflow_name: &str,
+        commands: &[(&str, &str, &[PermissionRequest])],
+    ) -> Result<PermissionConsent> {
+        Self::workflow_consent_dialog(workflow_name, commands, &mut io::stdout(), |subject, output| {
+            self.consent.choose(subject, output)
+        })
     }
 
     /// Creates a permission decision record.
@@ -288,6 +815,8 @@ impl PermissionUI {
             permissions,
             consent,
             decided_at: timestamp,
+            script_hash: None,
+            session: None,
         }
     }
 
@@ -309,6 +838,38 @@ impl PermissionUI {
         let mut output = io::stdout();
         let _ = self.show_running_with_permissions_with_io(command_name, permissions, &mut output);
     }
+
+    /// Shows a generated command's assumptions and confidence on stdout.
+    ///
+    /// This is a convenience wrapper around [`Self::show_assumptions_with_io`].
+    pub fn show_assumptions(&self, command_name: &str, assumptions: &[String], confidence: Option<Confidence>) {
+        let mut output = io::stdout();
+        let _ = self.show_assumptions_with_io(command_name, assumptions, confidence, &mut output);
+    }
+
+    /// Offers on stdout to run a cached command similar to an intent.
+    ///
+    /// This is a convenience wrapper around [`Self::offer_similar_command_with_io`].
+    pub fn offer_similar_command(&self, similar: &SimilarCommand) -> Result<bool> {
+        let mut output = io::stdout();
+        self.offer_similar_command_with_io(similar, &mut output)
+    }
+
+    /// Reviews the plan for a command on stdout.
+    ///
+    /// This is a convenience wrapper around [`Self::review_plan_with_io`].
+    pub fn review_plan(&self, request: &str, plan: &mut GenerationPlan) -> Result<bool> {
+        let mut output = io::stdout();
+        self.review_plan_with_io(request, plan, &mut output)
+    }
+
+    /// Warns on stdout that a project command reaches outside its project.
+    ///
+    /// This is a convenience wrapper around [`Self::show_outside_project_with_io`].
+    pub fn show_outside_project(&self, command_name: &str, root: &Path, escapes: &[Escape]) {
+        let mut output = io::stdout();
+        let _ = self.show_outside_project_with_io(command_name, root, escapes, &mut output);
+    }
 }
 
 #[cfg(test)]
@@ -353,7 +914,7 @@ mod tests {
         let mut output = Vec::new();
 
         let result = ui
-            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &mut input, &mut output)
+            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &[], &mut input, &mut output)
             .unwrap();
 
         assert!(matches!(result, PermissionConsent::AcceptForever));
@@ -370,12 +931,39 @@ mod tests {
         let mut output = Vec::new();
 
         let result = ui
-            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &mut input, &mut output)
+            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &[], &mut input, &mut output)
             .unwrap();
 
         assert!(matches!(result, PermissionConsent::AcceptOnce));
     }
 
+    #[test]
+    fn test_workflow_prompt_lists_every_command_needing_permissions() {
+        let ui = PermissionUI::new(false);
+        let read = vec![test_permission("--allow-read", "Read the changelog")];
+        let net = vec![test_permission("--allow-net", "Post to the chat")];
+        let commands: Vec<(&str, &str, &[PermissionRequest])> = vec![
+            ("changelog", "Builds the changelog", &read),
+            ("last-tag", "Prints the last tag", &[]),
+            ("announce", "Announces the release", &net),
+        ];
+
+        let mut input = Cursor::new(b"x\n3\n");
+        let mut output = Vec::new();
+
+        let result = ui
+            .prompt_for_workflow_consent_with_io("release", &commands, &mut input, &mut output)
+            .unwrap();
+
+        assert!(matches!(result, PermissionConsent::Denied));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("🧭 Workflow: release"));
+        assert!(output.contains("📋 Command: changelog"));
+        assert!(output.contains("📋 Command: announce"));
+        assert!(!output.contains("last-tag"));
+        assert!(output.contains("Invalid choice"));
+    }
+
     #[test]
     fn test_prompt_returns_accept_forever_for_input_2() {
         let ui = PermissionUI::new(false);
@@ -385,7 +973,7 @@ mod tests {
         let mut output = Vec::new();
 
         let result = ui
-            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &mut input, &mut output)
+            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &[], &mut input, &mut output)
             .unwrap();
 
         assert!(matches!(result, PermissionConsent::AcceptForever));
@@ -400,12 +988,28 @@ mod tests {
         let mut output = Vec::new();
 
         let result = ui
-            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &mut input, &mut output)
+            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &[], &mut input, &mut output)
             .unwrap();
 
         assert!(matches!(result, PermissionConsent::Denied));
     }
 
+    #[test]
+    fn test_prompt_returns_accept_for_session_for_input_4() {
+        let ui = PermissionUI::new(false);
+        let permissions = vec![test_permission("--allow-net", "Fetch data")];
+
+        let mut input = Cursor::new(b"4\n");
+        let mut output = Vec::new();
+
+        let result = ui
+            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &[], &mut input, &mut output)
+            .unwrap();
+
+        assert!(matches!(result, PermissionConsent::AcceptForSession));
+        assert!(String::from_utf8(output).unwrap().contains("4️⃣  Accept for Session - Don't ask again in this shell session (for 8 hours)"));
+    }
+
     #[test]
     fn test_prompt_retries_on_invalid_input() {
         let ui = PermissionUI::new(false);
@@ -416,7 +1020,7 @@ mod tests {
         let mut output = Vec::new();
 
         let result = ui
-            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &mut input, &mut output)
+            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &[], &mut input, &mut output)
             .unwrap();
 
         assert!(matches!(result, PermissionConsent::AcceptForever));
@@ -425,6 +1029,35 @@ mod tests {
         assert!(output_str.contains("Invalid choice"));
     }
 
+    #[test]
+    fn test_prompt_fails_when_input_ends() {
+        let ui = PermissionUI::new(false);
+        let permissions = vec![test_permission("--allow-read", "Read files")];
+
+        let mut input = Cursor::new(b"");
+        let mut output = Vec::new();
+
+        let error = ui
+            .prompt_for_consent_with_io("test-cmd", "Test command", &permissions, &[], &mut input, &mut output)
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "No answer about command 'test-cmd': the input ended");
+    }
+
+    #[test]
+    fn test_assumed_consent_from_environment() {
+        let env = |value: &'static str| move |name: &str| (name == ASSUME_CONSENT_ENV).then(|| value.to_string());
+        let mut output = Vec::new();
+
+        let forever = select_consent_provider(env("Forever")).unwrap();
+        assert_eq!(forever.choose("command 'x'", &mut output).unwrap(), PermissionConsent::AcceptForever);
+        assert_eq!(select_consent_provider(env("deny")).unwrap().choose("command 'x'", &mut output).unwrap(), PermissionConsent::Denied);
+        assert!(!select_consent_provider(env("deny")).unwrap().confirm("Generate?", &mut output).unwrap());
+        assert!(select_consent_provider(env("sure")).is_err());
+        assert!(select_consent_provider(|_: &str| None).is_ok());
+        assert!(String::from_utf8(output).unwrap().starts_with("\n🤖 Consent for command 'x': accept forever (ERGO_ASSUME_CONSENT is set)\n"));
+    }
+
     #[test]
     fn test_prompt_displays_permission_info() {
         let ui = PermissionUI::new(false);
@@ -436,7 +1069,7 @@ mod tests {
         let mut input = Cursor::new(b"1\n");
         let mut output = Vec::new();
 
-        ui.prompt_for_consent_with_io("my-command", "Does important stuff", &permissions, &mut input, &mut output)
+        ui.prompt_for_consent_with_io("my-command", "Does important stuff", &permissions, &[], &mut input, &mut output)
             .unwrap();
 
         let output_str = String::from_utf8(output).unwrap();
@@ -457,6 +1090,48 @@ mod tests {
         assert!(output_str.contains("Deny"));
     }
 
+    #[test]
+    fn test_prompt_displays_usage_examples() {
+        let ui = PermissionUI::new(false);
+        let permissions = vec![test_permission("--allow-net", "Fetch the forecast")];
+        let examples = vec![UsageExample {
+            usage: "ergo weather London".to_string(),
+            explanation: "Today's weather in London".to_string(),
+        }];
+
+        let mut input = Cursor::new(b"3\n");
+        let mut output = Vec::new();
+
+        ui.prompt_for_consent_with_io("weather", "Shows the weather", &permissions, &examples, &mut input, &mut output)
+            .unwrap();
+
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("💡 Examples:"));
+        assert!(output_str.contains("ergo weather London  # Today's weather in London"));
+    }
+
+    #[test]
+    fn test_prompt_displays_permission_scopes() {
+        let ui = PermissionUI::new(false);
+        let permissions = vec![
+            test_permission("--allow-net=api.github.com", "Call the GitHub API"),
+            test_permission("--allow-read", "Read files"),
+            test_permission("--allow-everything", "Bogus"),
+        ];
+
+        let mut input = Cursor::new(b"3\n");
+        let mut output = Vec::new();
+
+        ui.prompt_for_consent_with_io("cmd", "desc", &permissions, &[], &mut input, &mut output)
+            .unwrap();
+
+        let output_str = String::from_utf8(output).unwrap();
+        assert!(output_str.contains("Network access (--allow-net=api.github.com)"));
+        assert!(output_str.contains("Scope: hosts: api.github.com"));
+        assert!(output_str.contains("Scope: any paths (unrestricted)"));
+        assert!(output_str.contains("Unrecognized permission"));
+    }
+
     #[test]
     fn test_prompt_trims_whitespace_from_input() {
         let ui = PermissionUI::new(false);
@@ -467,7 +1142,7 @@ mod tests {
         let mut output = Vec::new();
 
         let result = ui
-            .prompt_for_consent_with_io("test-cmd", "Test", &permissions, &mut input, &mut output)
+            .prompt_for_consent_with_io("test-cmd", "Test", &permissions, &[], &mut input, &mut output)
             .unwrap();
 
         assert!(matches!(result, PermissionConsent::AcceptForever));
@@ -585,4 +1260,104 @@ mod tests {
         // Should be silent when non-verbose and no permissions
         assert!(output.is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_show_assumptions_lists_assumptions_and_confidence() {
+        let ui = PermissionUI::new(false);
+        let mut output = Vec::new();
+        let assumptions = vec!["files are UTF-8".to_string(), "git is installed".to_string()];
+
+        ui.show_assumptions_with_io("changelog", &assumptions, Some(Confidence::Medium), &mut output)
+            .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("🤔 Generated 'changelog' (confidence: medium), assuming:"));
+        assert!(output.contains("   • files are UTF-8\n   • git is installed\n"));
+        assert!(output.contains("ergo --nope"));
+    }
+
+    #[test]
+    fn test_show_assumptions_is_silent_when_nothing_to_report() {
+        let ui = PermissionUI::new(true);
+        let mut output = Vec::new();
+
+        ui.show_assumptions_with_io("hello", &[], Some(Confidence::High), &mut output).unwrap();
+        ui.show_assumptions_with_io("hello", &[], None, &mut output).unwrap();
+        assert!(output.is_empty());
+
+        ui.show_assumptions_with_io("hello", &[], Some(Confidence::Low), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap().lines().next(), Some("🤔 Generated 'hello' (confidence: low)"));
+    }
+
+    #[test]
+    fn test_show_outside_project_lists_escapes() {
+        let ui = PermissionUI::new(false);
+        let mut output = Vec::new();
+        let escapes = [
+            Escape { kind: PermissionKind::Read, path: "~/.ssh".to_string(), line: None },
+            Escape { kind: PermissionKind::Write, path: "/etc/hosts".to_string(), line: Some(7) },
+        ];
+
+        ui.show_outside_project_with_io("sync", Path::new("/work/app"), &[], &mut output).unwrap();
+        assert!(output.is_empty());
+        ui.show_outside_project_with_io("sync", Path::new("/work/app"), &escapes, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "⚠️  'sync' belongs to the project at /work/app but reaches outside it:\n\
+             \u{20}  • reads ~/.ssh (--allow-read scope)\n\
+             \u{20}  • writes /etc/hosts (script line 7)\n"
+        );
+    }
+
+    /// Answers yes/no questions and open questions in order.
+    struct ScriptedConsent(std::sync::Mutex<Vec<&'static str>>);
+
+    impl ScriptedConsent {
+        fn next(&self) -> String {
+            self.0.lock().unwrap().remove(0).to_string()
+        }
+    }
+
+    impl ConsentProvider for ScriptedConsent {
+        fn choose(&self, _subject: &str, _output: &mut dyn Write) -> Result<PermissionConsent> {
+            unreachable!()
+        }
+
+        fn confirm(&self, _question: &str, _output: &mut dyn Write) -> Result<bool> {
+            Ok(self.next() == "y")
+        }
+
+        fn ask(&self, _question: &str, _output: &mut dyn Write) -> Result<String> {
+            Ok(self.next())
+        }
+    }
+
+    #[test]
+    fn test_review_plan_collects_changes_until_approved() {
+        let consent = ScriptedConsent(std::sync::Mutex::new(vec!["n", "Print dates as ISO 8601", "y"]));
+        let ui = PermissionUI::new(false).with_consent_provider(Box::new(consent));
+        let mut plan = GenerationPlan {
+            summary: "Show the last commits".to_string(),
+            steps: vec!["Run git log".to_string()],
+            permissions: vec![test_permission("--allow-run=git", "Run git")],
+            edge_cases: vec!["Not a git repository".to_string()],
+            changes: Vec::new(),
+        };
+        let mut output = Vec::new();
+
+        assert!(ui.review_plan_with_io("recent-commits", &mut plan, &mut output).unwrap());
+
+        assert_eq!(plan.changes, vec!["Print dates as ISO 8601"]);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(
+            "📋 Plan for 'recent-commits':\n   Summary: Show the last commits\n   Steps:\n     1. Run git log\n   \
+             Permissions:\n     - --allow-run=git: Run git\n   Edge cases:\n     - Not a git repository\n"
+        ));
+        assert!(output.ends_with("   Changes requested by the user:\n     - Print dates as ISO 8601\n"));
+
+        let cancel = ScriptedConsent(std::sync::Mutex::new(vec!["n", ""]));
+        let ui = PermissionUI::new(false).with_consent_provider(Box::new(cancel));
+        assert!(!ui.review_plan_with_io("recent-commits", &mut plan, &mut Vec::new()).unwrap());
+    }
+}
@@ -0,0 +1,236 @@
+//! Typed Deno permissions.
+//!
+//! Generated commands declare their permissions as Deno flag strings such as
+//! `--allow-read` or `--allow-net=api.example.com`. This module parses those
+//! strings into a [`Permission`] (kind plus optional scope list) so the rest
+//! of ergo can validate them, show their scopes in the consent dialog, and
+//! reason about how broad a grant is.
+//!
+//! # Example
+//!
+//! ```
+//! use abiogenesis::permissions::{Permission, PermissionKind};
+//!
+//! let perm: Permission = "--allow-read=./data,./config".parse().unwrap();
+//! assert_eq!(perm.kind, PermissionKind::Read);
+//! assert_eq!(perm.scopes, Some(vec!["./data".to_string(), "./config".to_string()]));
+//! assert_eq!(perm.to_flag(), "--allow-read=./data,./config");
+//! ```
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// The kinds of Deno permission a generated command may request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PermissionKind {
+    /// `--allow-read`: read files and directories.
+    Read,
+    /// `--allow-write`: create, modify or delete files.
+    Write,
+    /// `--allow-net`: make network connections.
+    Net,
+    /// `--allow-env`: read environment variables.
+    Env,
+    /// `--allow-run`: spawn subprocesses.
+    Run,
+    /// `--allow-sys`: query system information.
+    Sys,
+}
+
+impl PermissionKind {
+    /// All known kinds, in the order they are usually listed.
+    pub const ALL: [PermissionKind; 6] = [
+        PermissionKind::Read,
+        PermissionKind::Write,
+        PermissionKind::Net,
+        PermissionKind::Env,
+        PermissionKind::Run,
+        PermissionKind::Sys,
+    ];
+
+    /// The Deno flag for this kind, without a scope.
+    pub fn flag(&self) -> &'static str {
+        match self {
+            PermissionKind::Read => "--allow-read",
+            PermissionKind::Write => "--allow-write",
+            PermissionKind::Net => "--allow-net",
+            PermissionKind::Env => "--allow-env",
+            PermissionKind::Run => "--allow-run",
+            PermissionKind::Sys => "--allow-sys",
+        }
+    }
+
+    /// Short human-readable description of what the kind grants.
+    pub fn description(&self) -> &'static str {
+        match self {
+            PermissionKind::Read => "Read files",
+            PermissionKind::Write => "Write files",
+            PermissionKind::Net => "Network access",
+            PermissionKind::Env => "Environment variables",
+            PermissionKind::Run => "Run programs",
+            PermissionKind::Sys => "System information",
+        }
+    }
+
+    /// What the scope values of this kind refer to (paths, hosts, ...).
+    pub fn scope_noun(&self) -> &'static str {
+        match self {
+            PermissionKind::Read | PermissionKind::Write => "paths",
+            PermissionKind::Net => "hosts",
+            PermissionKind::Env => "variables",
+            PermissionKind::Run => "programs",
+            PermissionKind::Sys => "APIs",
+        }
+    }
+
+    fn from_flag(flag: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.flag() == flag)
+    }
+}
+
+/// A parsed Deno permission: a kind plus an optional list of scopes.
+///
+/// `scopes` is `None` for an unrestricted grant (e.g. `--allow-read`) and
+/// `Some` with at least one entry for a scoped grant (e.g.
+/// `--allow-read=./data`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Permission {
+    /// What kind of access is granted.
+    pub kind: PermissionKind,
+    /// The paths, hosts, variables or programs the grant is limited to.
+    pub scopes: Option<Vec<String>>,
+}
+
+impl Permission {
+    /// Creates an unrestricted permission of the given kind.
+    pub fn unrestricted(kind: PermissionKind) -> Self {
+        Self { kind, scopes: None }
+    }
+
+    /// Creates a permission limited to the given scopes.
+    pub fn scoped(kind: PermissionKind, scopes: Vec<String>) -> Self {
+        Self {
+            kind,
+            scopes: Some(scopes),
+        }
+    }
+
+    /// Returns true if the permission is not limited to specific scopes.
+    pub fn is_unrestricted(&self) -> bool {
+        self.scopes.is_none()
+    }
+
+    /// Renders the permission as a Deno command-line flag.
+    pub fn to_flag(&self) -> String {
+        match &self.scopes {
+            Some(scopes) => format!("{}={}", self.kind.flag(), scopes.join(",")),
+            None => self.kind.flag().to_string(),
+        }
+    }
+
+    /// Human-readable description of the scope (e.g. `paths: ./data, ./config`).
+    pub fn describe_scope(&self) -> String {
+        match &self.scopes {
+            Some(scopes) => format!("{}: {}", self.kind.scope_noun(), scopes.join(", ")),
+            None => format!("any {}", self.kind.scope_noun()),
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = anyhow::Error;
+
+    /// Parses a Deno permission flag such as `--allow-net=example.com`.
+    ///
+    /// Only the kinds in [`PermissionKind::ALL`] are accepted. Scope values
+    /// must be non-empty and may not contain whitespace or start with `-`.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (flag, value) = match s.split_once('=') {
+            Some((flag, value)) => (flag, Some(value)),
+            None => (s, None),
+        };
+
+        let kind = PermissionKind::from_flag(flag).ok_or_else(|| {
+            let known: Vec<&str> = PermissionKind::ALL.iter().map(|k| k.flag()).collect();
+            anyhow!("Unknown permission '{}'. Expected one of: {}", s, known.join(", "))
+        })?;
+
+        let Some(value) = value else {
+            return Ok(Self::unrestricted(kind));
+        };
+
+        let scopes: Vec<String> = value.split(',').map(|v| v.trim().to_string()).collect();
+        for scope in &scopes {
+            if scope.is_empty() {
+                return Err(anyhow!("Permission '{}' has an empty scope", s));
+            }
+            if scope.starts_with('-') || scope.chars().any(char::is_whitespace) {
+                return Err(anyhow!("Permission '{}' has an invalid scope '{}'", s, scope));
+            }
+        }
+
+        Ok(Self::scoped(kind, scopes))
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_flag())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_unrestricted() {
+        let perm: Permission = "--allow-net".parse().unwrap();
+
+        assert_eq!(perm, Permission::unrestricted(PermissionKind::Net));
+        assert!(perm.is_unrestricted());
+    }
+
+    #[test]
+    fn test_parse_scoped_trims_values() {
+        let perm: Permission = "--allow-env=HOME, USER".parse().unwrap();
+
+        assert_eq!(perm.kind, PermissionKind::Env);
+        assert_eq!(perm.scopes, Some(vec!["HOME".to_string(), "USER".to_string()]));
+    }
+
+    #[test]
+    fn test_to_flag_round_trips() {
+        for flag in ["--allow-read", "--allow-write=./out", "--allow-net=a.com,b.com:443"] {
+            let perm: Permission = flag.parse().unwrap();
+            assert_eq!(perm.to_flag(), flag);
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_kinds() {
+        for flag in ["--allow-all", "-A", "--allow-ffi", "--unstable", "allow-read", ""] {
+            let err = flag.parse::<Permission>().unwrap_err();
+            assert!(err.to_string().contains("Unknown permission"), "{}", flag);
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_scopes() {
+        assert!("--allow-read=".parse::<Permission>().is_err());
+        assert!("--allow-read=a,,b".parse::<Permission>().is_err());
+        assert!("--allow-read=./x --allow-all".parse::<Permission>().is_err());
+        assert!("--allow-read=--allow-all".parse::<Permission>().is_err());
+    }
+
+    #[test]
+    fn test_describe_scope() {
+        let scoped: Permission = "--allow-net=api.github.com".parse().unwrap();
+        let open: Permission = "--allow-read".parse().unwrap();
+
+        assert_eq!(scoped.describe_scope(), "hosts: api.github.com");
+        assert_eq!(open.describe_scope(), "any paths");
+    }
+}
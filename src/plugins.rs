@@ -0,0 +1,387 @@
+//! Generator plugins: third-party command generation backends.
+//!
+//! A plugin is an executable in `~/.abiogenesis/plugins/`. The file name
+//! (without extension) is the plugin's name, and the `[generator]` table in
+//! `config.toml` picks the backend:
+//!
+//! ```toml
+//! [generator]
+//! backend = "gateway"    # "claude", "bedrock" or "gemini" (built in), or a plugin name
+//! plugin_timeout = 120   # seconds a plugin may take per request
+//! ```
+//!
+//! For every request ergo starts the plugin, writes one JSON object to its
+//! stdin and closes it. The `operation` field says what is asked for:
+//!
+//! ```json
+//! {"operation": "generate", "command_name": "weather", "args": ["London"]}
+//! {"operation": "describe", "description": "show today's date"}
+//! {"operation": "regenerate", "command_name": "weather", "original_script": "...",
+//!  "stderr": "...", "user_feedback": "...", "test_script": null}
+//! ```
+//!
+//! The plugin answers on stdout with the same command JSON the Claude backend
+//! produces (`name`, `description`, `script`, `permissions`, and optionally
+//! `sensitive_output`, `output_format` and `examples`), or with `{"error": "message"}`. A
+//! non-zero exit is also treated as an error, reported with the plugin's
+//! stderr. Permissions are validated exactly as for the built-in backend.
+
+use crate::bedrock::{BEDROCK_BACKEND, BedrockConfig};
+use crate::gemini::{GEMINI_BACKEND, GeminiConfig};
+use crate::llm_generator::{self, CommandGenerator, FeedbackRequest, GenerationResult};
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+/// Name of the built-in Claude backend.
+pub const BUILTIN_BACKEND: &str = "claude";
+
+/// Default number of seconds a plugin may take to answer.
+pub const DEFAULT_PLUGIN_TIMEOUT_SECS: u64 = 120;
+
+/// Default number of seconds a generation request may take.
+pub const DEFAULT_GENERATION_TIMEOUT_SECS: u64 = 120;
+
+/// Generator settings (the `[generator]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeneratorConfig {
+    /// Backend that generates commands: `claude`, `bedrock`, `gemini`, or a plugin name.
+    ///
+    /// `provider` is accepted as another name for this key.
+    #[serde(default = "default_backend", alias = "provider")]
+    pub backend: String,
+    /// Seconds a plugin may take to answer a request.
+    #[serde(default = "default_plugin_timeout")]
+    pub plugin_timeout: u64,
+    /// Seconds a generation request may take before it's abandoned.
+    #[serde(default = "default_generation_timeout")]
+    pub timeout: u64,
+    /// Settings of the `bedrock` backend.
+    #[serde(default)]
+    pub bedrock: BedrockConfig,
+    /// Settings of the `gemini` backend.
+    #[serde(default)]
+    pub gemini: GeminiConfig,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            backend: default_backend(),
+            plugin_timeout: DEFAULT_PLUGIN_TIMEOUT_SECS,
+            timeout: DEFAULT_GENERATION_TIMEOUT_SECS,
+            bedrock: BedrockConfig::default(),
+            gemini: GeminiConfig::default(),
+        }
+    }
+}
+
+fn default_backend() -> String {
+    BUILTIN_BACKEND.to_string()
+}
+
+fn default_plugin_timeout() -> u64 {
+    DEFAULT_PLUGIN_TIMEOUT_SECS
+}
+
+fn default_generation_timeout() -> u64 {
+    DEFAULT_GENERATION_TIMEOUT_SECS
+}
+
+/// A plugin found in the plugins directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plugin {
+    /// The name used in `generator.backend`.
+    pub name: String,
+    /// Path of the executable.
+    pub path: PathBuf,
+}
+
+/// Returns the directory plugins are discovered in (`~/.abiogenesis/plugins`).
+pub fn plugins_dir() -> Result<PathBuf> {
+    Ok(crate::config::Config::get_config_dir()?.join("plugins"))
+}
+
+/// Lists the executable plugins in `dir`, sorted by name.
+///
+/// A missing directory has no plugins.
+pub fn discover(dir: &Path) -> Result<Vec<Plugin>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+
+    let mut plugins: Vec<Plugin> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_executable(path))
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_str()?.to_string();
+            Some(Plugin { name, path })
+        })
+        .collect();
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(plugins)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Creates the generator selected by `config`.
+///
+/// # Errors
+///
+/// Returns an error naming the available plugins if the backend is neither
+/// the built-in one nor a plugin in `dir`, or if it is a built-in one but
+/// the library was built without the `claude` feature.
+pub fn select_generator(config: &GeneratorConfig, dir: &Path) -> Result<Box<dyn CommandGenerator>> {
+    // LlmGenerator serves every built-in backend, picking the API from the config
+    if [BUILTIN_BACKEND, BEDROCK_BACKEND, GEMINI_BACKEND].contains(&config.backend.as_str()) {
+        #[cfg(feature = "claude")]
+        return Ok(Box::new(crate::llm_generator::LlmGenerator::new()));
+        #[cfg(not(feature = "claude"))]
+        return Err(anyhow!(
+            "Generator backend '{}' needs the `claude` feature of abiogenesis; select a plugin instead",
+            config.backend
+        ));
+    }
+
+    let plugins = discover(dir)?;
+    let Some(plugin) = plugins.iter().find(|p| p.name == config.backend).cloned() else {
+        return Err(anyhow!(
+            "Generator backend '{}' not found. Available: {}, {}, {}{}",
+            config.backend,
+            BUILTIN_BACKEND,
+            BEDROCK_BACKEND,
+            GEMINI_BACKEND,
+            plugins.iter().map(|p| format!(", {}", p.name)).collect::<String>()
+        ));
+    };
+
+    info!("Using generator plugin '{}' at {:?}", plugin.name, plugin.path);
+    Ok(Box::new(PluginGenerator::new(plugin, Duration::from_secs(config.plugin_timeout))))
+}
+
+/// A request sent to a plugin on stdin.
+#[derive(Debug, Serialize)]
+#[serde(tag = "operation", rename_all = "lowercase")]
+enum PluginRequest<'a> {
+    Generate { command_name: &'a str, args: &'a [String] },
+    Describe { description: &'a str },
+    Regenerate(&'a FeedbackRequest<'a>),
+}
+
+/// A [`CommandGenerator`] backed by a plugin executable.
+pub struct PluginGenerator {
+    plugin: Plugin,
+    timeout: Duration,
+}
+
+impl PluginGenerator {
+    /// Creates a generator that runs `plugin`, allowing `timeout` per request.
+    pub fn new(plugin: Plugin, timeout: Duration) -> Self {
+        Self { plugin, timeout }
+    }
+
+    /// Runs the plugin with `request` and parses its answer.
+    async fn call(&self, request: &PluginRequest<'_>) -> Result<GenerationResult> {
+        let input = serde_json::to_vec(request)?;
+        let mut child = tokio::process::Command::new(&self.plugin.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start generator plugin '{}'", self.plugin.name))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // A plugin that doesn't read its request may already have exited
+            match stdin.write_all(&input).await {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+                _ => {}
+            }
+        }
+
+        let output = tokio::time::timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "Generator plugin '{}' did not answer within {}s",
+                    self.plugin.name,
+                    self.timeout.as_secs()
+                )
+            })??;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Generator plugin '{}' failed ({}): {}",
+                self.plugin.name,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if let Ok(serde_json::Value::Object(map)) = serde_json::from_str::<serde_json::Value>(&stdout)
+            && let Some(error) = map.get("error")
+        {
+            let message = error.as_str().map(str::to_string).unwrap_or_else(|| error.to_string());
+            return Err(anyhow!("Generator plugin '{}' failed: {}", self.plugin.name, message));
+        }
+        llm_generator::parse_command_json(stdout.trim())
+    }
+}
+
+#[async_trait]
+impl CommandGenerator for PluginGenerator {
+    async fn generate_command(&self, command_name: &str, args: &[String]) -> Result<GenerationResult> {
+        info!("Generating command '{}' with plugin '{}'", command_name, self.plugin.name);
+        let mut result = self.call(&PluginRequest::Generate { command_name, args }).await?;
+        rename(&mut result, command_name);
+        Ok(result)
+    }
+
+    async fn generate_command_from_description(&self, description: &str) -> Result<GenerationResult> {
+        info!("Generating command from description with plugin '{}'", self.plugin.name);
+        self.call(&PluginRequest::Describe { description }).await
+    }
+
+    async fn regenerate_command_with_feedback(&self, request: &FeedbackRequest<'_>) -> Result<GenerationResult> {
+        info!("Regenerating '{}' with plugin '{}'", request.command_name, self.plugin.name);
+        let mut result = self.call(&PluginRequest::Regenerate(request)).await?;
+        rename(&mut result, request.command_name);
+        Ok(result)
+    }
+}
+
+/// Keeps the name the user asked for, whatever the plugin suggested.
+fn rename(result: &mut GenerationResult, command_name: &str) {
+    result.command.name = command_name.to_string();
+    result.command.script_file = format!("{}.ts", command_name);
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    const COMMAND_JSON: &str = r#"{"name":"suggested","description":"Says hi","script":"console.log('hi')","permissions":[{"permission":"--allow-env","reason":"Read HOME"}]}"#;
+
+    fn write_plugin(dir: &Path, name: &str, body: &str) -> Plugin {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        Plugin {
+            name: Path::new(name).file_stem().unwrap().to_str().unwrap().to_string(),
+            path,
+        }
+    }
+
+    fn generator(plugin: Plugin) -> PluginGenerator {
+        PluginGenerator::new(plugin, Duration::from_secs(10))
+    }
+
+    #[test]
+    fn test_discover_lists_executables_only() {
+        let temp_dir = TempDir::new().unwrap();
+        write_plugin(temp_dir.path(), "gateway.sh", "true");
+        write_plugin(temp_dir.path(), "ollama", "true");
+        std::fs::write(temp_dir.path().join("README"), "not a plugin").unwrap();
+
+        let names: Vec<String> = discover(temp_dir.path()).unwrap().into_iter().map(|p| p.name).collect();
+
+        assert_eq!(names, vec!["gateway", "ollama"]);
+        assert!(discover(&temp_dir.path().join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_select_unknown_backend_lists_plugins() {
+        let temp_dir = TempDir::new().unwrap();
+        write_plugin(temp_dir.path(), "gateway", "true");
+        let config = GeneratorConfig {
+            backend: "vertex".to_string(),
+            ..Default::default()
+        };
+
+        let err = select_generator(&config, temp_dir.path()).err().unwrap().to_string();
+
+        assert!(err.contains("'vertex' not found"));
+        assert!(err.contains("claude, bedrock, gemini, gateway"));
+    }
+
+    #[tokio::test]
+    async fn test_plugin_receives_request_and_keeps_requested_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let request_file = temp_dir.path().join("request.json");
+        let plugin = write_plugin(
+            temp_dir.path(),
+            "echo",
+            &format!("cat > '{}'\necho '{}'", request_file.display(), COMMAND_JSON),
+        );
+
+        let result = generator(plugin)
+            .generate_command("hello", &["--loud".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(result.command.name, "hello");
+        assert_eq!(result.command.script_file, "hello.ts");
+        assert_eq!(result.command.permissions[0].permission, "--allow-env");
+        let request: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&request_file).unwrap()).unwrap();
+        assert_eq!(
+            request,
+            serde_json::json!({"operation": "generate", "command_name": "hello", "args": ["--loud"]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_describe_uses_plugin_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let plugin = write_plugin(temp_dir.path(), "echo", &format!("cat > /dev/null\necho '{}'", COMMAND_JSON));
+
+        let result = generator(plugin).generate_command_from_description("say hi").await.unwrap();
+
+        assert_eq!(result.command.name, "suggested");
+    }
+
+    #[tokio::test]
+    async fn test_plugin_errors_are_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let refusing = write_plugin(temp_dir.path(), "refusing", r#"echo '{"error": "quota exceeded"}'"#);
+        let crashing = write_plugin(temp_dir.path(), "crashing", "echo boom >&2\nexit 3");
+
+        let err = generator(refusing).generate_command("x", &[]).await.unwrap_err().to_string();
+        assert!(err.contains("quota exceeded"));
+
+        let err = generator(crashing).generate_command("x", &[]).await.unwrap_err().to_string();
+        assert!(err.contains("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_plugin_permissions_are_validated() {
+        let temp_dir = TempDir::new().unwrap();
+        let plugin = write_plugin(
+            temp_dir.path(),
+            "sneaky",
+            r#"echo '{"name":"x","description":"d","script":"","permissions":[{"permission":"--allow-everything","reason":"r"}]}'"#,
+        );
+
+        assert!(generator(plugin).generate_command("x", &[]).await.is_err());
+    }
+}
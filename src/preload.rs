@@ -0,0 +1,146 @@
+//! Preloading of the most-used commands.
+//!
+//! The first run of a script downloads and compiles its remote imports, which
+//! can take seconds. [`preload`] runs `deno cache` on the scripts of the
+//! commands run most often (by usage count), so their imports are already in
+//! Deno's cache when they're next run.
+//!
+//! `ergo bioma warm` preloads once from the shell. A process embedding the
+//! router that stays up should call [`preload`] at startup when
+//! `preload.enabled` is set; `preload.max_commands` caps the work either way.
+
+use crate::command_cache::CommandCache;
+use crate::executor::ProcessRunner;
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use tracing::{info, warn};
+
+/// Preloading settings (the `[preload]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreloadConfig {
+    /// Whether long-running processes preload at startup.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Most commands preloaded.
+    #[serde(default = "default_max_commands")]
+    pub max_commands: usize,
+}
+
+impl Default for PreloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            max_commands: default_max_commands(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_commands() -> usize {
+    10
+}
+
+/// What [`preload`] did.
+#[derive(Debug, Default, PartialEq)]
+pub struct PreloadReport {
+    /// Commands whose imports are now cached, most used first.
+    pub preloaded: Vec<String>,
+    /// Commands that couldn't be preloaded, with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+/// The `max` commands of `cache` run most often, most used first.
+///
+/// Commands never run are left out; ties go to the most recently used.
+pub async fn most_used(cache: &CommandCache, max: usize) -> Vec<String> {
+    let mut used: Vec<_> = cache
+        .list_cached_commands()
+        .await
+        .into_iter()
+        .filter_map(|name| cache.usage(&name).filter(|usage| usage.count > 0).map(|usage| (name, usage)))
+        .collect();
+    used.sort_by(|(a, usage_a), (b, usage_b)| {
+        (usage_b.count, usage_b.last_used)
+            .cmp(&(usage_a.count, usage_a.last_used))
+            .then_with(|| a.cmp(b))
+    });
+    used.into_iter().take(max).map(|(name, _)| name).collect()
+}
+
+/// Caches the imports of the `config.max_commands` most-used commands with
+/// `deno cache`.
+///
+/// A command that fails is reported and the others are still preloaded.
+///
+/// # Errors
+///
+/// Returns an error if Deno isn't installed.
+pub async fn preload<P: ProcessRunner>(cache: &CommandCache, runner: &P, config: &PreloadConfig) -> Result<PreloadReport> {
+    if !runner.program_exists("deno") {
+        bail!("Deno is not installed. Please install Deno to preload commands.");
+    }
+    let mut report = PreloadReport::default();
+    for name in most_used(cache, config.max_commands).await {
+        match preload_command(cache, runner, &name).await {
+            Ok(()) => report.preloaded.push(name),
+            Err(e) => {
+                warn!("Failed to preload '{}': {}", name, e);
+                report.failed.push((name, e.to_string()));
+            }
+        }
+    }
+    info!("Preloaded {} command(s)", report.preloaded.len());
+    Ok(report)
+}
+
+async fn preload_command<P: ProcessRunner>(cache: &CommandCache, runner: &P, name: &str) -> Result<()> {
+    let Some(command) = cache.get_command(name).await? else {
+        bail!("it's no longer cached");
+    };
+    let script = cache.get_script_content(&command)?;
+    let mut script_file = tempfile::Builder::new().prefix("ergo_preload_").suffix(".ts").tempfile()?;
+    script_file.write_all(script.as_bytes())?;
+    script_file.flush()?;
+
+    let path = script_file.path().to_string_lossy().to_string();
+    let output = runner.run("deno", &["cache", &path])?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FixedTime, MockPathResolver, MockProcessRunner, command};
+
+    #[tokio::test]
+    async fn test_preload_caches_the_most_used_commands() {
+        let mut cache = CommandCache::with_providers(Box::new(MockPathResolver::new().unwrap()), Box::new(FixedTime(0)))
+            .await
+            .unwrap();
+        for (name, runs) in [("rare", 1), ("never", 0), ("daily", 3), ("weekly", 2)] {
+            cache.store_command(name, &command(name, &[]), "console.log('hi')").await.unwrap();
+            for _ in 0..runs {
+                cache.update_usage(name).await.unwrap();
+            }
+        }
+        assert_eq!(most_used(&cache, 10).await, vec!["daily", "weekly", "rare"]);
+
+        let runner = MockProcessRunner::new();
+        let config = PreloadConfig {
+            max_commands: 2,
+            ..PreloadConfig::default()
+        };
+        let report = preload(&cache, &runner, &config).await.unwrap();
+        assert_eq!(report.preloaded, vec!["daily", "weekly"]);
+        assert!(report.failed.is_empty());
+        assert_eq!(runner.calls().len(), 2);
+        assert!(runner.calls().iter().all(|call| call[..2] == ["deno", "cache"]));
+    }
+}
@@ -0,0 +1,372 @@
+//! Progress events of the command router.
+//!
+//! [`CommandRouter`](crate::command_router::CommandRouter) reports what it is
+//! doing as [`RouterEvent`]s sent to a [`ProgressSink`], so every frontend can
+//! render progress its own way. The default, [`ConsoleProgress`], prints the
+//! `ergo` messages: notices and warnings always, the rest in verbose mode.
+//! Any `Fn(&RouterEvent)` is a sink, which makes forwarding events to a
+//! channel a one-liner:
+//!
+//! ```ignore
+//! let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+//! let router = CommandRouter::new(false)
+//!     .await?
+//!     .with_progress(move |event: &RouterEvent| {
+//!         let _ = tx.send(event.clone());
+//!     });
+//! ```
+
+use crate::executor::ExecutionResult;
+
+/// Something the router started or finished.
+#[derive(Debug, Clone)]
+pub enum RouterEvent {
+    /// A command is being generated.
+    GenerationStarted {
+        /// The name asked for, or `None` when generating from a description.
+        command_name: Option<String>,
+        /// The command name or the description it is generated from.
+        request: String,
+    },
+    /// Unversioned std imports of a generated script were pinned.
+    ImportsPinned {
+        /// How many imports were pinned.
+        count: usize,
+        /// The std version they were pinned to.
+        std_version: String,
+    },
+    /// A command is being regenerated from `--nope` feedback.
+    RegenerationStarted {
+        /// The command being regenerated.
+        command_name: String,
+        /// The OS whose variant alone is replaced, if any.
+        variant: Option<String>,
+        /// The user's feedback, empty if none was given.
+        feedback: String,
+        /// Whether the stderr of the last run is sent as context.
+        with_stderr: bool,
+    },
+    /// A regenerated command was cached.
+    Regenerated {
+        /// The regenerated command.
+        command_name: String,
+        /// Its new description.
+        description: String,
+    },
+    /// A regenerated command passes its tests.
+    TestsPassed {
+        /// The tested command.
+        command_name: String,
+    },
+    /// A generated command was found in the cache.
+    CacheHit {
+        /// The cached command.
+        command_name: String,
+    },
+    /// The user is asked to consent to a command's permissions.
+    ConsentRequested {
+        /// The command needing consent.
+        command_name: String,
+    },
+    /// A command starts running.
+    Executing {
+        /// The command.
+        command_name: String,
+        /// Its arguments.
+        args: Vec<String>,
+        /// Whether it is a program from PATH rather than a generated command.
+        system: bool,
+    },
+    /// A generated command finished running.
+    Finished {
+        /// The command.
+        command_name: String,
+        /// How it went.
+        result: ExecutionResult,
+    },
+    /// A step of a chain or workflow starts.
+    StepStarted {
+        /// The step's command line.
+        command_line: String,
+    },
+    /// A command a workflow step needs is generated from the step's
+    /// description.
+    WorkflowGeneration {
+        /// The command.
+        command_name: String,
+        /// What the step says it does.
+        description: String,
+    },
+    /// A command is being planned before it's generated.
+    PlanningStarted {
+        /// The command name or the description it is planned from.
+        request: String,
+    },
+    /// A generation timed out and is tried again.
+    GenerationRetried {
+        /// The timeout it ran into, in seconds.
+        timeout_secs: u64,
+        /// Which retry this is, from 1.
+        attempt: u32,
+        /// How many retries are allowed.
+        retries: u32,
+    },
+    /// A generated command fails validation and the model is asked to repair
+    /// it.
+    RepairStarted {
+        /// The command.
+        command_name: String,
+        /// Which repair this is, from 1.
+        attempt: u32,
+        /// How many repairs are allowed.
+        max_attempts: u32,
+        /// The issues found, one per line.
+        issues: String,
+    },
+    /// A repaired command passes validation.
+    Repaired {
+        /// The command.
+        command_name: String,
+    },
+    /// The model chose a name a command can't have (see [`crate::reserved`])
+    /// and is asked for another one.
+    NameRefused {
+        /// The name it chose.
+        name: String,
+        /// Why it can't be used, e.g. `a shell builtin`.
+        reason: String,
+    },
+    /// A command is generated to work on the output of the last run.
+    UsingLastOutput {
+        /// The command that ran last.
+        command_name: String,
+    },
+    /// A command generated for other operating systems is about to run.
+    OtherPlatform {
+        /// The command.
+        command_name: String,
+        /// The platforms it was generated for.
+        platforms: Vec<String>,
+        /// The platform it runs on.
+        os: String,
+    },
+    /// A command validated with `--yep` runs in a different environment.
+    EnvironmentChanged {
+        /// The command.
+        command_name: String,
+        /// What changed since it was validated.
+        differences: Vec<String>,
+    },
+    /// A command runs despite catastrophic patterns, as the user overrode the
+    /// safety rails.
+    SafetyOverridden {
+        /// The command.
+        command_name: String,
+        /// The patterns matched, e.g. `line 3: Recursively deletes ...`.
+        patterns: Vec<String>,
+    },
+}
+
+impl RouterEvent {
+    /// Whether the event warns about something that may go wrong.
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            RouterEvent::OtherPlatform { .. } | RouterEvent::EnvironmentChanged { .. } | RouterEvent::SafetyOverridden { .. }
+        )
+    }
+}
+
+/// Receives the router's progress events.
+pub trait ProgressSink: Send + Sync {
+    /// Handles `event`. Called on the router's task, so it should be quick.
+    fn emit(&self, event: &RouterEvent);
+}
+
+impl<F: Fn(&RouterEvent) + Send + Sync> ProgressSink for F {
+    fn emit(&self, event: &RouterEvent) {
+        self(event)
+    }
+}
+
+/// Prints progress as `ergo` shows it: warnings on stderr, everything else on
+/// stdout, and the verbose-only messages with `-v`.
+#[derive(Debug, Clone, Copy)]
+pub struct ConsoleProgress {
+    verbose: bool,
+}
+
+impl ConsoleProgress {
+    /// Creates a sink printing the verbose-only messages when `verbose`.
+    pub fn new(verbose: bool) -> Self {
+        Self { verbose }
+    }
+
+    /// The lines printed for `event`.
+    pub fn render(&self, event: &RouterEvent) -> Vec<String> {
+        match event {
+            RouterEvent::StepStarted { command_line } => vec![format!("🔗 {}", command_line)],
+            RouterEvent::WorkflowGeneration {
+                command_name,
+                description,
+            } => vec![format!("⚡ Generating '{}': {}", command_name, description)],
+            RouterEvent::PlanningStarted { request } => vec![format!("🗺️  Planning '{}'...", request)],
+            RouterEvent::GenerationRetried {
+                timeout_secs,
+                attempt,
+                retries,
+            } => vec![format!(
+                "⏳ Generation timed out after {}s, retrying ({}/{})",
+                timeout_secs, attempt, retries
+            )],
+            RouterEvent::RepairStarted {
+                command_name,
+                attempt,
+                max_attempts,
+                issues,
+            } => vec![format!(
+                "🔧 '{}' fails validation, asking for a fix ({}/{}):\n{}",
+                command_name, attempt, max_attempts, issues
+            )],
+            RouterEvent::Repaired { command_name } => {
+                vec![format!("✅ The repaired '{}' passes validation", command_name)]
+            }
+            RouterEvent::NameRefused { name, reason } => {
+                vec![format!("🏷️  '{}' is {}, asking for another name", name, reason)]
+            }
+            RouterEvent::UsingLastOutput { command_name } => {
+                vec![format!("📎 Using the output of '{}'", command_name)]
+            }
+            RouterEvent::OtherPlatform {
+                command_name,
+                platforms,
+                os,
+            } => vec![format!(
+                "⚠️  '{}' was generated for {} and may not work on {}. If it fails, regenerate it with: ergo --nope \"make it work on {}\"",
+                command_name,
+                platforms.join(", "),
+                os,
+                os
+            )],
+            RouterEvent::EnvironmentChanged {
+                command_name,
+                differences,
+            } => {
+                let mut lines = vec![format!(
+                    "⚠️  '{}' was validated in a different environment; now running with:",
+                    command_name
+                )];
+                lines.extend(differences.iter().map(|difference| format!("   • {}", difference)));
+                lines.push("   If it misbehaves, tell ergo with: ergo --nope \"<what went wrong>\"".to_string());
+                lines
+            }
+            RouterEvent::SafetyOverridden { command_name, patterns } => {
+                let mut lines = vec![format!("⚠️  Running '{}' despite catastrophic patterns:", command_name)];
+                lines.extend(patterns.iter().map(|pattern| format!("   • {}", pattern)));
+                lines
+            }
+            // The rest is only shown in verbose mode
+            _ if !self.verbose => Vec::new(),
+            RouterEvent::GenerationStarted {
+                command_name: Some(name),
+                ..
+            } => vec![format!("⚡ Command '{}' not found, generating with AI...", name)],
+            RouterEvent::GenerationStarted { request, .. } => {
+                vec![format!("💭 Understanding your request: {}", request)]
+            }
+            RouterEvent::ImportsPinned { count, std_version } => {
+                vec![format!("📌 Pinned {} std import(s) to std@{}", count, std_version)]
+            }
+            RouterEvent::RegenerationStarted {
+                command_name,
+                variant,
+                feedback,
+                with_stderr,
+            } => {
+                let mut lines = vec![format!("🔄 Regenerating command '{}'...", command_name)];
+                if let Some(os) = variant {
+                    lines.push(format!("🖥️  Only its {} variant will be replaced", os));
+                }
+                if !feedback.is_empty() {
+                    lines.push(format!("💭 Feedback: {}", feedback));
+                } else if *with_stderr {
+                    lines.push("💭 Using stderr from last execution as context".to_string());
+                }
+                lines
+            }
+            RouterEvent::Regenerated { description, .. } => vec![
+                "✨ Command regenerated successfully!".to_string(),
+                format!("📝 New description: {}", description),
+            ],
+            RouterEvent::TestsPassed { .. } => vec!["✅ Regenerated command passes its tests".to_string()],
+            // The consent prompt and the command's own output already show these
+            RouterEvent::CacheHit { .. }
+            | RouterEvent::ConsentRequested { .. }
+            | RouterEvent::Executing { .. }
+            | RouterEvent::Finished { .. } => Vec::new(),
+        }
+    }
+}
+
+impl ProgressSink for ConsoleProgress {
+    fn emit(&self, event: &RouterEvent) {
+        for line in self.render(event) {
+            if event.is_warning() {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_progress_prints_only_when_verbose() {
+        let event = RouterEvent::RegenerationStarted {
+            command_name: "weather".to_string(),
+            variant: Some("windows".to_string()),
+            feedback: "use Celsius".to_string(),
+            with_stderr: true,
+        };
+        assert_eq!(
+            ConsoleProgress::new(true).render(&event),
+            vec![
+                "🔄 Regenerating command 'weather'...",
+                "🖥️  Only its windows variant will be replaced",
+                "💭 Feedback: use Celsius"
+            ]
+        );
+        assert!(ConsoleProgress::new(false).render(&event).is_empty());
+
+        let cache_hit = RouterEvent::CacheHit {
+            command_name: "weather".to_string(),
+        };
+        assert!(ConsoleProgress::new(true).render(&cache_hit).is_empty());
+    }
+
+    #[test]
+    fn test_console_progress_always_prints_notices_and_warnings() {
+        let step = RouterEvent::StepStarted {
+            command_line: "weather Paris".to_string(),
+        };
+        assert_eq!(ConsoleProgress::new(false).render(&step), vec!["🔗 weather Paris"]);
+        assert!(!step.is_warning());
+
+        let overridden = RouterEvent::SafetyOverridden {
+            command_name: "tidy".to_string(),
+            patterns: vec!["line 2: Recursively deletes the home directory".to_string()],
+        };
+        assert_eq!(
+            ConsoleProgress::new(false).render(&overridden),
+            vec![
+                "⚠️  Running 'tidy' despite catastrophic patterns:",
+                "   • line 2: Recursively deletes the home directory"
+            ]
+        );
+        assert!(overridden.is_warning());
+    }
+}
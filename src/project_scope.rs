@@ -0,0 +1,231 @@
+//! Detection of project commands that reach outside their project.
+//!
+//! A command cached in a project's bioma (`<project>/.abiogenesis/biomas`,
+//! or wherever [`CacheConfig`] puts it) is expected to work on that project. Before asking for consent, its file
+//! permissions are checked against the project root:
+//!
+//! - scoped `--allow-read`/`--allow-write` paths that resolve outside the
+//!   root (`~/.ssh`, `/etc`, `../other-repo`)
+//! - for unrestricted grants, the paths the script itself names outside the
+//!   root, e.g. `"/etc/hosts"` or `Deno.env.get("HOME")`
+//!
+//! The script check is a heuristic: it looks at string literals naming the
+//! home directory or well-known system directories, not at computed paths.
+
+use crate::command_cache::CacheConfig;
+use crate::llm_generator::PermissionRequest;
+use crate::permissions::PermissionKind;
+use std::path::{Component, Path, PathBuf};
+
+/// Top-level directories whose absolute paths in a script are reported.
+///
+/// Other absolute strings are too often URL paths (`"/api/v1"`) to flag.
+const SYSTEM_ROOTS: &[&str] = &[
+    "bin", "boot", "dev", "etc", "home", "Library", "media", "mnt", "opt", "private", "proc", "root", "sbin", "srv",
+    "sys", "System", "tmp", "Users", "usr", "var", "Volumes",
+];
+
+/// A way a command reaches outside its project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escape {
+    /// Whether the path is read or written.
+    pub kind: PermissionKind,
+    /// The path as the command names it.
+    pub path: String,
+    /// The script line naming the path, or `None` for a permission scope.
+    pub line: Option<usize>,
+}
+
+/// Returns the project root of a bioma directory, or `None` for the home
+/// bioma (or any directory that doesn't follow the cache `layout`).
+pub fn project_root(bioma_dir: &Path, home: Option<&Path>, layout: &CacheConfig) -> Option<PathBuf> {
+    let root = layout.base_dir(bioma_dir)?;
+    (Some(root.as_path()) != home).then_some(root)
+}
+
+/// Finds where a project command's file access reaches outside `root`.
+///
+/// # Arguments
+///
+/// * `root` - The project root
+/// * `cwd` - The directory the command runs in, which relative paths resolve against
+/// * `home` - The home directory, which `~` expands to
+/// * `permissions` - The command's permissions
+/// * `script` - The command's script
+pub fn find_escapes(
+    root: &Path,
+    cwd: &Path,
+    home: Option<&Path>,
+    permissions: &[PermissionRequest],
+    script: &str,
+) -> Vec<Escape> {
+    let mut escapes = Vec::new();
+    for permission in permissions.iter().filter_map(|p| p.parsed().ok()) {
+        if !matches!(permission.kind, PermissionKind::Read | PermissionKind::Write) {
+            continue;
+        }
+        match &permission.scopes {
+            Some(scopes) => escapes.extend(
+                scopes
+                    .iter()
+                    .filter(|scope| is_outside(root, cwd, home, scope))
+                    .map(|scope| Escape {
+                        kind: permission.kind,
+                        path: scope.clone(),
+                        line: None,
+                    }),
+            ),
+            None => escapes.extend(script_paths(script).into_iter().filter_map(|(line, path)| {
+                is_outside(root, cwd, home, &path).then_some(Escape {
+                    kind: permission.kind,
+                    path,
+                    line: Some(line),
+                })
+            })),
+        }
+    }
+    escapes
+}
+
+/// Whether `path` resolves outside `root`.
+fn is_outside(root: &Path, cwd: &Path, home: Option<&Path>, path: &str) -> bool {
+    let expanded = match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    };
+    !normalize(&cwd.join(expanded)).starts_with(normalize(root))
+}
+
+/// Resolves `.` and `..` components without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Paths outside any project that a script names, with their line numbers:
+/// the home directory and absolute paths under [`SYSTEM_ROOTS`].
+fn script_paths(script: &str) -> Vec<(usize, String)> {
+    let mut paths = Vec::new();
+    for (index, line) in script.lines().enumerate() {
+        if line.contains("Deno.env.get(\"HOME\")") || line.contains("Deno.env.get('HOME')") {
+            paths.push((index + 1, "~".to_string()));
+        }
+        for literal in string_literals(line) {
+            let names_system_dir = literal
+                .strip_prefix('/')
+                .and_then(|rest| rest.split('/').next())
+                .is_some_and(|first| SYSTEM_ROOTS.contains(&first));
+            if names_system_dir || literal == "~" || literal.starts_with("~/") {
+                paths.push((index + 1, literal.to_string()));
+            }
+        }
+    }
+    paths
+}
+
+/// The contents of the string literals on a line (quotes not unescaped).
+fn string_literals(line: &str) -> Vec<&str> {
+    let mut literals = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find(['"', '\'', '`']) {
+        let quote = rest[start..].chars().next().unwrap_or('"');
+        let after = &rest[start + 1..];
+        let Some(end) = after.find(quote) else { break };
+        literals.push(&after[..end]);
+        rest = &after[end + 1..];
+    }
+    literals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perm(flag: &str) -> PermissionRequest {
+        PermissionRequest {
+            permission: flag.to_string(),
+            reason: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_project_root_skips_home_bioma() {
+        let home = Path::new("/home/ada");
+
+        let layout = CacheConfig::default();
+
+        assert_eq!(
+            project_root(Path::new("/work/app/.abiogenesis/biomas"), Some(home), &layout),
+            Some(PathBuf::from("/work/app"))
+        );
+        assert_eq!(project_root(Path::new("/home/ada/.abiogenesis/biomas"), Some(home), &layout), None);
+        assert_eq!(project_root(Path::new("/tmp/biomas"), Some(home), &layout), None);
+    }
+
+    #[test]
+    fn test_project_root_follows_the_layout() {
+        let layout = CacheConfig {
+            dir_name: ".acme".to_string(),
+            bioma_dir: "ergo/commands".to_string(),
+            ..CacheConfig::default()
+        };
+
+        assert_eq!(
+            project_root(Path::new("/work/app/.acme/ergo/commands"), None, &layout),
+            Some(PathBuf::from("/work/app"))
+        );
+        assert_eq!(project_root(Path::new("/work/app/.abiogenesis/biomas"), None, &layout), None);
+    }
+
+    #[test]
+    fn test_scopes_outside_the_root_escape() {
+        let root = Path::new("/work/app");
+        let permissions = [
+            perm("--allow-read=./src,~/.ssh,../../other"),
+            perm("--allow-write=/etc/hosts,/work/app/out"),
+            perm("--allow-net=example.com"),
+        ];
+
+        let escapes = find_escapes(root, &root.join("src"), Some(Path::new("/home/ada")), &permissions, "");
+
+        let paths: Vec<(PermissionKind, &str)> = escapes.iter().map(|e| (e.kind, e.path.as_str())).collect();
+        assert_eq!(
+            paths,
+            vec![
+                (PermissionKind::Read, "~/.ssh"),
+                (PermissionKind::Read, "../../other"),
+                (PermissionKind::Write, "/etc/hosts")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unrestricted_grants_check_script_paths() {
+        let root = Path::new("/work/app");
+        let script = "const key = await Deno.readTextFile(`${Deno.env.get(\"HOME\")}/.ssh/id_rsa`);\n\
+                      const hosts = Deno.readTextFileSync('/etc/hosts');\n\
+                      await fetch(\"https://api.example.com\" + \"/api/v1\");\n\
+                      Deno.readTextFileSync(\"./README.md\");";
+
+        let escapes = find_escapes(root, root, Some(Path::new("/home/ada")), &[perm("--allow-read")], script);
+
+        assert_eq!(
+            escapes,
+            vec![
+                Escape { kind: PermissionKind::Read, path: "~".to_string(), line: Some(1) },
+                Escape { kind: PermissionKind::Read, path: "/etc/hosts".to_string(), line: Some(2) },
+            ]
+        );
+    }
+}
@@ -38,4 +38,34 @@ impl TimeProvider for SystemTimeProvider {
             .map(|d| d.as_secs())
             .unwrap_or(0)
     }
-}
\ No newline at end of file
+}
+
+/// Converts a Unix timestamp to a UTC calendar date (year, month, day).
+///
+/// Uses the civil-from-days algorithm (proleptic Gregorian calendar), so no
+/// date library is needed for the few places that format timestamps.
+pub fn utc_date(timestamp: u64) -> (i64, i64, i64) {
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a UTC calendar date to the Unix timestamp of its midnight, the
+/// inverse of [`utc_date`]. Returns `None` for dates before 1970.
+pub fn utc_midnight(year: i64, month: i64, day: i64) -> Option<u64> {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    u64::try_from(days).ok().map(|days| days * 86_400)
+}
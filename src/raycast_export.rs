@@ -0,0 +1,174 @@
+//! Raycast script commands for cached commands, for `ergo export raycast`.
+//!
+//! A [script command](https://github.com/raycast/script-commands) is a shell
+//! script whose `@raycast.*` comment headers tell the launcher its title,
+//! description and arguments. The exported script runs the command through
+//! ergo from the directory it was exported in, so project commands are found:
+//!
+//! ```bash
+//! #!/bin/bash
+//!
+//! # @raycast.schemaVersion 1
+//! # @raycast.title Weather
+//! # @raycast.mode fullOutput
+//! # @raycast.argument1 { "type": "text", "placeholder": "London", "optional": true }
+//! ...
+//! exec /usr/local/bin/ergo weather "${args[@]}" </dev/null
+//! ```
+//!
+//! Raycast has no terminal to answer permission prompts in, so commands need
+//! permissions the user already granted forever.
+
+use crate::executor::shell_quote;
+use crate::llm_generator::GeneratedCommand;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// The most arguments Raycast passes to a script command.
+pub const MAX_ARGUMENTS: usize = 3;
+
+/// Name of the script file for `command`.
+pub fn script_file_name(command: &str) -> String {
+    format!("ergo-{}.sh", command)
+}
+
+/// Renders the script command running `command`.
+///
+/// # Arguments
+///
+/// * `command` - The cached command
+/// * `ergo` - Path of the ergo binary, since launchers don't load the shell's `PATH`
+/// * `dir` - Directory the command runs in
+pub fn render_script(command: &GeneratedCommand, ergo: &Path, dir: &Path) -> String {
+    let mut script = String::new();
+    let _ = writeln!(script, "#!/bin/bash");
+    let _ = writeln!(script);
+    let _ = writeln!(script, "# Required parameters:");
+    let _ = writeln!(script, "# @raycast.schemaVersion 1");
+    let _ = writeln!(script, "# @raycast.title {}", title(&command.name));
+    let _ = writeln!(script, "# @raycast.mode fullOutput");
+    let _ = writeln!(script);
+    let _ = writeln!(script, "# Optional parameters:");
+    let _ = writeln!(script, "# @raycast.packageName ergo");
+    let _ = writeln!(script, "# @raycast.currentDirectoryPath {}", dir.display());
+    for (index, placeholder) in placeholders(command).iter().enumerate() {
+        let _ = writeln!(
+            script,
+            "# @raycast.argument{} {{ \"type\": \"text\", \"placeholder\": {}, \"optional\": true }}",
+            index + 1,
+            serde_json::Value::from(placeholder.as_str())
+        );
+    }
+    let _ = writeln!(script);
+    let _ = writeln!(script, "# Documentation:");
+    let _ = writeln!(
+        script,
+        "# @raycast.description {}",
+        command.description.lines().next().unwrap_or_default()
+    );
+    let _ = writeln!(script);
+    let _ = writeln!(script, "# Generated by `ergo export raycast {}`.", command.name);
+    let _ = writeln!(script, "args=()");
+    let _ = writeln!(script, "for arg in \"$@\"; do");
+    let _ = writeln!(script, "  [ -n \"$arg\" ] && args+=(\"$arg\")");
+    let _ = writeln!(script, "done");
+    let _ = writeln!(
+        script,
+        "exec {} {} \"${{args[@]}}\" </dev/null",
+        shell_quote(&ergo.to_string_lossy()),
+        shell_quote(&command.name)
+    );
+    script
+}
+
+/// The launcher title for a command name, e.g. `Check Todos` for `check-todos`.
+pub fn title(name: &str) -> String {
+    name.split(['-', '_'])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Argument placeholders, taken from the arguments of the command's first
+/// usage example, at most [`MAX_ARGUMENTS`].
+pub fn placeholders(command: &GeneratedCommand) -> Vec<String> {
+    let Some(example) = command.examples.first() else {
+        return Vec::new();
+    };
+    let invocation = format!("ergo {}", command.name);
+    let Some(args) = example.usage.trim().strip_prefix(&invocation) else {
+        return Vec::new();
+    };
+    if !args.is_empty() && !args.starts_with(char::is_whitespace) {
+        return Vec::new();
+    }
+    split_words(args).into_iter().take(MAX_ARGUMENTS).collect()
+}
+
+/// Splits a command line into words, honoring single and double quotes.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_default().push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_generator::UsageExample;
+    use crate::testing::command;
+
+    #[test]
+    fn test_render_script() {
+        let mut weather = command("weather-now", &["--allow-net"]);
+        weather.description = "Show the weather for a city".to_string();
+        weather.examples = vec![UsageExample {
+            usage: "ergo weather-now \"New York\" --metric".to_string(),
+            explanation: "Weather in New York".to_string(),
+        }];
+
+        let script = render_script(&weather, Path::new("/opt/ergo bin/ergo"), Path::new("/home/ada/site"));
+
+        assert!(script.starts_with("#!/bin/bash\n\n# Required parameters:\n# @raycast.schemaVersion 1\n# @raycast.title Weather Now\n"));
+        assert!(script.contains("# @raycast.currentDirectoryPath /home/ada/site\n"));
+        assert!(script.contains(
+            "# @raycast.argument1 { \"type\": \"text\", \"placeholder\": \"New York\", \"optional\": true }\n"
+        ));
+        assert!(script.contains("# @raycast.argument2 { \"type\": \"text\", \"placeholder\": \"--metric\", \"optional\": true }\n"));
+        assert!(script.contains("# @raycast.description Show the weather for a city\n"));
+        assert!(script.ends_with("exec '/opt/ergo bin/ergo' weather-now \"${args[@]}\" </dev/null\n"));
+    }
+
+    #[test]
+    fn test_placeholders_need_a_matching_example() {
+        let mut hello = command("hello", &[]);
+        assert!(placeholders(&hello).is_empty());
+
+        hello.examples = vec![UsageExample {
+            usage: "ergo hello-world a b".to_string(),
+            explanation: String::new(),
+        }];
+        assert!(placeholders(&hello).is_empty());
+    }
+}
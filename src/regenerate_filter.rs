@@ -0,0 +1,144 @@
+//! Selection of cached commands for `ergo --regenerate-all`.
+//!
+//! After switching to a better model, or after ergo's prompt improved, every
+//! cached command can be generated again. `--filter KEY=VALUE` narrows that
+//! down; repeated filters must all match:
+//!
+//! - `name=deploy-*`: the command's name, where `*` matches any text
+//! - `model=claude-3-5-haiku-20241022`: the model that generated it
+//! - `backend=bedrock`: the backend that generated it
+//! - `os=macos`: the platform it was generated for
+//! - `tag=infra`: a tag in the command's `tags` list in `commands.json`
+//!
+//! Only commands with a recorded provenance can be selected: the others
+//! don't say what was asked for.
+
+use crate::llm_generator::GeneratedCommand;
+use anyhow::{Result, bail};
+
+/// Keys a filter can select commands by.
+const KEYS: &[&str] = &["name", "model", "backend", "os", "tag"];
+
+/// One `KEY=VALUE` condition on cached commands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegenerateFilter {
+    /// What the condition looks at, one of [`KEYS`].
+    pub key: String,
+    /// The value it must have.
+    pub value: String,
+}
+
+impl RegenerateFilter {
+    /// Parses a `KEY=VALUE` filter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there's no `=`, the value is empty or the key is unknown.
+    pub fn parse(filter: &str) -> Result<Self> {
+        let Some((key, value)) = filter.split_once('=') else {
+            bail!("Invalid filter '{}': expected KEY=VALUE, e.g. model=claude-3-5-haiku-20241022", filter);
+        };
+        let (key, value) = (key.trim().to_lowercase(), value.trim());
+        if !KEYS.contains(&key.as_str()) {
+            bail!("Unknown filter key '{}': use one of {}", key, KEYS.join(", "));
+        }
+        if value.is_empty() {
+            bail!("Filter '{}' has no value", filter);
+        }
+        Ok(Self {
+            key,
+            value: value.to_string(),
+        })
+    }
+
+    /// Whether `command` meets the condition.
+    pub fn matches(&self, command: &GeneratedCommand) -> bool {
+        let provenance = command.provenance.as_ref();
+        match self.key.as_str() {
+            "name" => matches_name(&command.name, &self.value),
+            "model" => provenance.is_some_and(|p| p.model == self.value),
+            "backend" => provenance.is_some_and(|p| p.backend.eq_ignore_ascii_case(&self.value)),
+            "os" => provenance.is_some_and(|p| p.os == self.value),
+            "tag" => command
+                .extra
+                .get("tags")
+                .and_then(|tags| tags.as_array())
+                .is_some_and(|tags| tags.iter().any(|tag| tag.as_str() == Some(&self.value))),
+            _ => false,
+        }
+    }
+}
+
+/// Whether `command` meets every filter.
+pub fn matches_all(filters: &[RegenerateFilter], command: &GeneratedCommand) -> bool {
+    filters.iter().all(|filter| filter.matches(command))
+}
+
+/// Whether `name` is `pattern`, where `*` in the pattern matches any text.
+fn matches_name(name: &str, pattern: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if parts.len() == 1 {
+        return name == pattern;
+    }
+    if name.len() < first.len() + last.len() || !name.starts_with(first) || !name.ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::command;
+
+    fn filters(specs: &[&str]) -> Vec<RegenerateFilter> {
+        specs.iter().map(|spec| RegenerateFilter::parse(spec).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_filters() {
+        assert_eq!(
+            RegenerateFilter::parse(" Model = claude-x ").unwrap(),
+            RegenerateFilter {
+                key: "model".to_string(),
+                value: "claude-x".to_string()
+            }
+        );
+        assert!(RegenerateFilter::parse("claude-x").unwrap_err().to_string().contains("expected KEY=VALUE"));
+        assert!(RegenerateFilter::parse("owner=ada").unwrap_err().to_string().contains("Unknown filter key 'owner'"));
+        assert!(RegenerateFilter::parse("tag=").is_err());
+    }
+
+    #[test]
+    fn test_matches_name_tags_and_provenance() {
+        let mut deploy = command("deploy-preview", &[]);
+        deploy.extra.insert("tags".to_string(), serde_json::json!(["infra", "ci"]));
+        let plain = command("greet", &[]);
+
+        assert!(matches_all(&filters(&["name=deploy-*", "tag=infra"]), &deploy));
+        assert!(matches_all(&filters(&["name=*-preview"]), &deploy));
+        assert!(!matches_all(&filters(&["name=deploy-*", "tag=web"]), &deploy));
+        assert!(!matches_all(&filters(&["tag=infra"]), &plain));
+        assert!(matches_all(&[], &plain));
+        // Without provenance, nothing says which model generated it
+        assert!(!matches_all(&filters(&["model=claude-x"]), &plain));
+    }
+
+    #[test]
+    fn test_name_patterns() {
+        assert!(matches_name("greet", "greet"));
+        assert!(!matches_name("greeter", "greet"));
+        assert!(matches_name("greeter", "gr*er"));
+        assert!(matches_name("a-b-c", "a*b*c"));
+        assert!(!matches_name("ab", "ab*b"));
+        assert!(matches_name("anything", "*"));
+    }
+}
@@ -0,0 +1,65 @@
+//! Names generated commands can't take.
+//!
+//! A command named after an ergo subcommand (`ergo config`) or flag word
+//! (`nope`) could never be run, since the CLI handles those first. One named
+//! after a shell builtin (`cd`, `export`) is confusing at best, and shadows
+//! the builtin for anyone aliasing ergo's commands into their shell.
+//! [`CommandRouter`](crate::command_router::CommandRouter) refuses such names
+//! for named intents, and asks the model for another one when it suggests
+//! them.
+
+/// Subcommands and flag words of the `ergo` CLI (checked against the CLI by
+/// a test in `main.rs`).
+pub const ERGO_NAMES: &[&str] = &[
+    "bioma", "ci", "config", "doctor", "export", "help", "history", "hook", "nope", "suggest", "version", "workflow",
+    "yep",
+];
+
+/// Builtins of POSIX shells and bash.
+pub const SHELL_BUILTINS: &[&str] = &[
+    "alias", "bg", "bind", "break", "builtin", "cd", "command", "continue", "declare", "dirs", "disown", "enable",
+    "eval", "exec", "exit", "export", "fc", "fg", "getopts", "hash", "jobs", "let", "local", "logout", "popd",
+    "pushd", "read", "readonly", "return", "set", "shift", "shopt", "source", "times", "trap", "type", "typeset",
+    "ulimit", "umask", "unalias", "unset", "wait",
+];
+
+/// Why `name` can't be a command's name, or `None` if it can.
+pub fn reason(name: &str) -> Option<&'static str> {
+    let name = name.to_ascii_lowercase();
+    if ERGO_NAMES.contains(&name.as_str()) {
+        Some("an ergo subcommand or flag")
+    } else if SHELL_BUILTINS.contains(&name.as_str()) {
+        Some("a shell builtin")
+    } else {
+        None
+    }
+}
+
+/// `description` with a request to name the command otherwise than the
+/// `refused` names, each with why it was refused.
+pub fn rename_request(description: &str, refused: &[(String, &str)]) -> String {
+    let names: Vec<String> = refused.iter().map(|(name, reason)| format!("'{}' ({})", name, reason)).collect();
+    format!(
+        "{}\n\nThe command can't be named {}: choose a different name.",
+        description,
+        names.join(" or ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reason_flags_ergo_names_and_shell_builtins() {
+        assert_eq!(reason("config"), Some("an ergo subcommand or flag"));
+        assert_eq!(reason("Nope"), Some("an ergo subcommand or flag"));
+        assert_eq!(reason("cd"), Some("a shell builtin"));
+        assert_eq!(reason("weather"), None);
+        assert_eq!(reason("config-backup"), None);
+        assert_eq!(
+            rename_request("go home", &[("cd".to_string(), "a shell builtin")]),
+            "go home\n\nThe command can't be named 'cd' (a shell builtin): choose a different name."
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Bounded growth of ergo's history.
+//!
+//! The latency log, the token usage ledger and the `--debug-llm` capture
+//! directory only ever grow, so they're pruned after each write to the limits
+//! of the `[history]` table: entries older than `max_age_days` go first, then
+//! the oldest ones until at most `max_entries` and `max_bytes` remain. A limit
+//! of 0 turns it off, and the newest entry is always kept.
+//!
+//! `ergo history clear [--before DATE]` removes everything, or everything
+//! older than a date, from all of them.
+
+use crate::providers::{SystemTimeProvider, TimeProvider, utc_midnight};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+use tracing::{info, warn};
+
+/// Limits configured for this process, if any.
+static LIMITS: OnceLock<HistoryConfig> = OnceLock::new();
+
+/// History retention settings (the `[history]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryConfig {
+    /// Most entries kept in each log or directory (0 for no limit).
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    /// Days an entry is kept (0 for no limit).
+    #[serde(default = "default_max_age_days")]
+    pub max_age_days: u64,
+    /// Most bytes kept in each log or directory (0 for no limit).
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: default_max_entries(),
+            max_age_days: default_max_age_days(),
+            max_bytes: default_max_bytes(),
+        }
+    }
+}
+
+fn default_max_entries() -> usize {
+    10_000
+}
+
+fn default_max_age_days() -> u64 {
+    365
+}
+
+fn default_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+impl HistoryConfig {
+    /// How many of `entries`, oldest first as `(timestamp, bytes)`, to drop
+    /// at `now` so the rest is within the limits.
+    pub fn excess(&self, entries: &[(u64, u64)], now: u64) -> usize {
+        let mut drop = 0;
+        if self.max_age_days > 0 {
+            let cutoff = now.saturating_sub(self.max_age_days * 86_400);
+            drop = entries.iter().take_while(|(timestamp, _)| *timestamp < cutoff).count();
+        }
+        if self.max_entries > 0 {
+            drop = drop.max(entries.len().saturating_sub(self.max_entries));
+        }
+        if self.max_bytes > 0 {
+            let mut kept: u64 = entries[drop..].iter().map(|(_, bytes)| bytes).sum();
+            while kept > self.max_bytes && drop < entries.len() {
+                kept -= entries[drop].1;
+                drop += 1;
+            }
+        }
+        drop.min(entries.len().saturating_sub(1))
+    }
+}
+
+/// Uses `config` for the pruning of this process.
+pub fn configure(config: HistoryConfig) {
+    let _ = LIMITS.set(config);
+}
+
+/// The limits in use: the configured ones, or the defaults.
+pub fn limits() -> HistoryConfig {
+    LIMITS.get().cloned().unwrap_or_default()
+}
+
+/// Prunes the log or capture directory at `path` to [`limits`] after a
+/// write. Failures are only logged, since the write itself succeeded.
+pub fn prune(path: &Path) {
+    let now = SystemTimeProvider.now();
+    let result = if path.is_dir() {
+        prune_dir(path, &limits(), now)
+    } else {
+        prune_log(path, &limits(), now)
+    };
+    match result {
+        Ok(0) => {}
+        Ok(dropped) => info!("Pruned {} old entries of {:?}", dropped, path),
+        Err(e) => warn!("Couldn't prune {:?}: {}", path, e),
+    }
+}
+
+/// Drops the oldest lines of the JSON Lines log at `path` beyond `limits`.
+///
+/// Returns how many were dropped.
+///
+/// # Errors
+///
+/// Returns an error if the log can't be read or rewritten.
+pub fn prune_log(path: &Path, limits: &HistoryConfig, now: u64) -> Result<usize> {
+    drop_from_log(path, |entries| limits.excess(entries, now))
+}
+
+/// Removes the oldest files of the capture directory `dir` beyond `limits`.
+///
+/// Returns how many were removed.
+///
+/// # Errors
+///
+/// Returns an error if the directory can't be listed or a file removed.
+pub fn prune_dir(dir: &Path, limits: &HistoryConfig, now: u64) -> Result<usize> {
+    drop_from_dir(dir, |entries| limits.excess(entries, now))
+}
+
+/// Drops every line of the log at `path`, or those older than `before`.
+///
+/// # Errors
+///
+/// Returns an error if the log can't be read or rewritten.
+pub fn clear_log(path: &Path, before: Option<u64>) -> Result<usize> {
+    drop_from_log(path, |entries| older_than(entries, before))
+}
+
+/// Removes every file of the capture directory `dir`, or those older than `before`.
+///
+/// # Errors
+///
+/// Returns an error if the directory can't be listed or a file removed.
+pub fn clear_dir(dir: &Path, before: Option<u64>) -> Result<usize> {
+    drop_from_dir(dir, |entries| older_than(entries, before))
+}
+
+/// Parses a `YYYY-MM-DD` date into the Unix timestamp of its midnight (UTC).
+///
+/// # Errors
+///
+/// Returns an error if `text` isn't a valid date.
+pub fn parse_date(text: &str) -> Result<u64> {
+    let invalid = || anyhow!("'{}' isn't a date; use YYYY-MM-DD", text);
+    let parts: Vec<i64> = text
+        .trim()
+        .split('-')
+        .map(|part| part.parse().map_err(|_| invalid()))
+        .collect::<Result<_>>()?;
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let timestamp = utc_midnight(year, month, day).ok_or_else(invalid)?;
+    // Rejects days past the end of the month, which roll over
+    if crate::providers::utc_date(timestamp) != (year, month, day) {
+        return Err(invalid());
+    }
+    Ok(timestamp)
+}
+
+/// How many of `entries` are older than `before`, or all of them.
+fn older_than(entries: &[(u64, u64)], before: Option<u64>) -> usize {
+    match before {
+        Some(before) => entries.iter().take_while(|(timestamp, _)| *timestamp < before).count(),
+        None => entries.len(),
+    }
+}
+
+/// Drops the first `count(entries)` lines of the log at `path`.
+fn drop_from_log(path: &Path, count: impl FnOnce(&[(u64, u64)]) -> usize) -> Result<usize> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    // Torn or malformed lines have no timestamp, so they count as the oldest
+    let entries: Vec<(u64, u64)> = lines
+        .iter()
+        .map(|line| {
+            let timestamp = serde_json::from_str::<Value>(line)
+                .ok()
+                .and_then(|entry| entry.get("timestamp")?.as_u64())
+                .unwrap_or(0);
+            (timestamp, line.len() as u64 + 1)
+        })
+        .collect();
+    let dropped = count(&entries);
+    if dropped == 0 {
+        return Ok(0);
+    }
+
+    let kept: String = lines[dropped..].iter().map(|line| format!("{}\n", line)).collect();
+    let temp_path = path.with_extension("jsonl.tmp");
+    fs::write(&temp_path, kept)?;
+    fs::rename(&temp_path, path)?;
+    Ok(dropped)
+}
+
+/// Removes the oldest `count(entries)` JSON files of `dir`.
+fn drop_from_dir(dir: &Path, count: impl FnOnce(&[(u64, u64)]) -> usize) -> Result<usize> {
+    let listing = match fs::read_dir(dir) {
+        Ok(listing) => listing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut files: Vec<(u64, u64, PathBuf)> = Vec::new();
+    for entry in listing {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let metadata = fs::metadata(&path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+        files.push((captured_at(&path).unwrap_or(modified), metadata.len(), path));
+    }
+    files.sort();
+
+    let entries: Vec<(u64, u64)> = files.iter().map(|(timestamp, bytes, _)| (*timestamp, *bytes)).collect();
+    let dropped = count(&entries);
+    for (_, _, path) in &files[..dropped] {
+        fs::remove_file(path)?;
+    }
+    Ok(dropped)
+}
+
+/// When a capture was made, from its name (see [`crate::llm_debug::record`]).
+fn captured_at(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let millis: u64 = stem.split('-').next()?.parse().ok()?;
+    Some(millis / 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const DAY: u64 = 86_400;
+
+    #[test]
+    fn test_excess_applies_every_limit_and_keeps_the_newest() {
+        let entries = [(DAY, 100), (10 * DAY, 100), (20 * DAY, 100), (30 * DAY, 300)];
+        let limits = |max_entries, max_age_days, max_bytes| HistoryConfig {
+            max_entries,
+            max_age_days,
+            max_bytes,
+        };
+
+        assert_eq!(limits(0, 0, 0).excess(&entries, 30 * DAY), 0);
+        assert_eq!(limits(0, 15, 0).excess(&entries, 30 * DAY), 2);
+        assert_eq!(limits(3, 0, 0).excess(&entries, 30 * DAY), 1);
+        assert_eq!(limits(0, 0, 450).excess(&entries, 30 * DAY), 2);
+        assert_eq!(limits(0, 1, 0).excess(&entries, 400 * DAY), 3);
+        assert_eq!(limits(0, 0, 0).excess(&[], 0), 0);
+    }
+
+    #[test]
+    fn test_prune_and_clear_log_drop_the_oldest_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("latency.jsonl");
+        let lines: String = (1..=5).map(|day| format!("{{\"timestamp\":{}}}\n", day * DAY)).collect();
+        fs::write(&path, format!("torn\n{}", lines)).unwrap();
+        let limits = HistoryConfig {
+            max_entries: 4,
+            max_age_days: 0,
+            max_bytes: 0,
+        };
+
+        assert_eq!(prune_log(&path, &limits, 5 * DAY).unwrap(), 2);
+        assert!(fs::read_to_string(&path).unwrap().starts_with(&format!("{{\"timestamp\":{}}}\n", 2 * DAY)));
+        assert_eq!(clear_log(&path, Some(4 * DAY)).unwrap(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        assert_eq!(clear_log(&path, None).unwrap(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        assert_eq!(clear_log(&temp_dir.path().join("missing.jsonl"), None).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_prune_dir_removes_the_oldest_captures() {
+        let temp_dir = TempDir::new().unwrap();
+        for day in 1..=3 {
+            let name = format!("{:015}-0000.json", day * DAY * 1000);
+            fs::write(temp_dir.path().join(name), "{}").unwrap();
+        }
+        fs::write(temp_dir.path().join("notes.txt"), "kept").unwrap();
+        let limits = HistoryConfig {
+            max_entries: 1,
+            ..HistoryConfig::default()
+        };
+
+        assert_eq!(prune_dir(temp_dir.path(), &limits, 3 * DAY).unwrap(), 2);
+        let mut left: Vec<_> = fs::read_dir(temp_dir.path()).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        left.sort();
+        assert_eq!(left, vec![format!("{:015}-0000.json", 3 * DAY * 1000), "notes.txt".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-02").unwrap(), DAY);
+        assert_eq!(parse_date("2024-03-01").unwrap(), 1_709_251_200);
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("yesterday").is_err());
+        assert!(parse_date("2024-13-01").is_err());
+    }
+}
@@ -0,0 +1,377 @@
+//! OS-level sandbox wrappers around the Deno process.
+//!
+//! Deno's permission flags are the primary security boundary for generated
+//! commands. For defense in depth, the Deno process itself can be started
+//! inside an OS sandbox that restricts the filesystem and network even if
+//! Deno's own checks were bypassed:
+//!
+//! - **bubblewrap** (`bwrap`) or **firejail** on Linux
+//! - **sandbox-exec** on macOS
+//!
+//! The wrapper is configured in `config.toml`:
+//!
+//! ```toml
+//! [sandbox]
+//! mode = "auto"      # off | auto | bubblewrap | firejail | sandbox-exec
+//! required = false   # refuse to run if the wrapper is missing
+//! run_as = "ergo-sandbox"  # optional: run as a less privileged user (via sudo)
+//! stage_writes = false     # review file changes before applying them
+//! workspace = false        # run in a temporary directory holding only the inputs
+//! ```
+//!
+//! Under the sandbox the whole filesystem is read-only except the Deno cache
+//! directory and the paths the command may write to: the scopes of its
+//! `--allow-write` permission, or the current directory for an unrestricted
+//! write grant. Network access is cut off unless the command requests
+//! `--allow-net`.
+
+use crate::permissions::{Permission, PermissionKind};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Which sandbox wrapper to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SandboxMode {
+    /// Run Deno directly (Deno's permission sandbox only).
+    #[default]
+    Off,
+    /// Use the first wrapper available on this platform.
+    Auto,
+    /// Use bubblewrap (Linux).
+    Bubblewrap,
+    /// Use firejail (Linux).
+    Firejail,
+    /// Use sandbox-exec (macOS).
+    SandboxExec,
+}
+
+/// Sandbox settings (the `[sandbox]` table in `config.toml`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SandboxConfig {
+    /// Which wrapper to use.
+    #[serde(default)]
+    pub mode: SandboxMode,
+    /// If true, refuse to run commands when the wrapper is not installed
+    /// instead of falling back to running Deno directly.
+    #[serde(default)]
+    pub required: bool,
+    /// Run generated commands as this (less privileged) user via
+    /// `sudo -n -u <user>`. A numeric uid can be given as `#1001`.
+    #[serde(default)]
+    pub run_as: Option<String>,
+    /// Run commands that may write files in a staging copy of the working
+    /// directory and ask before applying their changes (see
+    /// [`crate::staging`]).
+    #[serde(default)]
+    pub stage_writes: bool,
+    /// Run commands in a fresh temporary directory holding copies of their
+    /// input files, and ask before copying what they produce back (see
+    /// [`crate::workspace`]).
+    #[serde(default)]
+    pub workspace: bool,
+}
+
+impl SandboxConfig {
+    /// Picks the wrapper to use on the given platform.
+    ///
+    /// Returns `Ok(None)` when the sandbox is off, or when the wanted wrapper
+    /// is missing and the sandbox is not required.
+    ///
+    /// # Arguments
+    ///
+    /// * `os` - The platform (as in [`std::env::consts::OS`])
+    /// * `program_exists` - Checks whether a program is installed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sandbox is required but no wrapper is available.
+    pub fn resolve(&self, os: &str, program_exists: impl Fn(&str) -> bool) -> Result<Option<SandboxWrapper>> {
+        let candidates: Vec<SandboxWrapper> = match self.mode {
+            SandboxMode::Off => return Ok(None),
+            SandboxMode::Auto => match os {
+                "linux" => vec![SandboxWrapper::Bubblewrap, SandboxWrapper::Firejail],
+                "macos" => vec![SandboxWrapper::SandboxExec],
+                _ => Vec::new(),
+            },
+            SandboxMode::Bubblewrap => vec![SandboxWrapper::Bubblewrap],
+            SandboxMode::Firejail => vec![SandboxWrapper::Firejail],
+            SandboxMode::SandboxExec => vec![SandboxWrapper::SandboxExec],
+        };
+
+        if let Some(wrapper) = candidates.into_iter().find(|w| program_exists(w.program())) {
+            return Ok(Some(wrapper));
+        }
+
+        if self.required {
+            Err(anyhow!(
+                "A sandbox wrapper is required (sandbox.mode = {:?}) but none is installed. \
+                 Install bubblewrap/firejail (Linux) or use macOS sandbox-exec, \
+                 or set `ergo config set sandbox.required false`.",
+                self.mode
+            ))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// What the sandboxed Deno process may access.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxPolicy {
+    /// Whether network access is allowed.
+    pub allow_net: bool,
+    /// Paths that stay writable. Everything else is read-only.
+    pub writable_paths: Vec<PathBuf>,
+    /// Paths that must stay readable even where the wrapper hides the host
+    /// filesystem (e.g. the script file under a private `/tmp`).
+    pub readable_paths: Vec<PathBuf>,
+}
+
+impl SandboxPolicy {
+    /// Derives the sandbox policy from a command's permissions.
+    ///
+    /// # Arguments
+    ///
+    /// * `permissions` - The command's validated permissions
+    /// * `cwd` - The directory the command runs in; relative write scopes are
+    ///   resolved against it and it is the write area for unrestricted grants
+    /// * `deno_dir` - Deno's cache directory, which must stay writable
+    pub fn from_permissions(permissions: &[Permission], cwd: &Path, deno_dir: Option<&Path>) -> Self {
+        let allow_net = permissions.iter().any(|p| p.kind == PermissionKind::Net);
+
+        let mut writable_paths: Vec<PathBuf> = deno_dir.map(Path::to_path_buf).into_iter().collect();
+        for perm in permissions.iter().filter(|p| p.kind == PermissionKind::Write) {
+            match &perm.scopes {
+                Some(scopes) => writable_paths.extend(scopes.iter().map(|s| cwd.join(s))),
+                None => writable_paths.push(cwd.to_path_buf()),
+            }
+        }
+        writable_paths.dedup();
+
+        Self {
+            allow_net,
+            writable_paths,
+            readable_paths: Vec::new(),
+        }
+    }
+
+    /// Keeps `path` readable inside the sandbox.
+    pub fn with_readable(mut self, path: &Path) -> Self {
+        self.readable_paths.push(path.to_path_buf());
+        self
+    }
+}
+
+/// An installed sandbox wrapper program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxWrapper {
+    Bubblewrap,
+    Firejail,
+    SandboxExec,
+}
+
+impl SandboxWrapper {
+    /// The wrapper's executable name.
+    pub fn program(&self) -> &'static str {
+        match self {
+            SandboxWrapper::Bubblewrap => "bwrap",
+            SandboxWrapper::Firejail => "firejail",
+            SandboxWrapper::SandboxExec => "sandbox-exec",
+        }
+    }
+
+    /// Builds the wrapper's arguments for running `command` under `policy`.
+    ///
+    /// `command` is the full command to sandbox, starting with the program
+    /// (e.g. `["deno", "run", "--allow-read", "script.ts"]`).
+    pub fn wrap_args(&self, command: &[String], policy: &SandboxPolicy) -> Vec<String> {
+        let mut args: Vec<String> = Vec::new();
+        // Paths that don't exist can't be bound; they couldn't be written anyway
+        let writable = policy.writable_paths.iter().filter(|p| p.exists());
+
+        match self {
+            SandboxWrapper::Bubblewrap => {
+                args.extend(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"].map(String::from));
+                args.extend(["--tmpfs", "/tmp"].map(String::from));
+                for path in policy.readable_paths.iter().filter(|p| p.exists()) {
+                    let path = path.to_string_lossy().to_string();
+                    args.extend(["--ro-bind".to_string(), path.clone(), path]);
+                }
+                for path in writable {
+                    let path = path.to_string_lossy().to_string();
+                    args.extend(["--bind".to_string(), path.clone(), path]);
+                }
+                if !policy.allow_net {
+                    args.push("--unshare-net".to_string());
+                }
+                args.extend(["--die-with-parent", "--"].map(String::from));
+            }
+            SandboxWrapper::Firejail => {
+                args.extend(["--quiet", "--noprofile", "--read-only=/"].map(String::from));
+                for path in writable {
+                    args.push(format!("--read-write={}", path.to_string_lossy()));
+                }
+                if !policy.allow_net {
+                    args.push("--net=none".to_string());
+                }
+                args.push("--".to_string());
+            }
+            SandboxWrapper::SandboxExec => {
+                let mut profile = String::from("(version 1)(allow default)(deny file-write*)");
+                profile.push_str("(allow file-write* (subpath \"/private/tmp\") (subpath \"/private/var/folders\")");
+                for path in writable {
+                    profile.push_str(&format!(" (subpath \"{}\")", path.to_string_lossy().replace('"', "\\\"")));
+                }
+                profile.push(')');
+                if !policy.allow_net {
+                    profile.push_str("(deny network*)");
+                }
+                args.extend(["-p".to_string(), profile]);
+            }
+        }
+
+        args.extend(command.iter().cloned());
+        args
+    }
+}
+
+/// Returns Deno's cache directory (`$DENO_DIR` or the platform default).
+pub fn deno_dir() -> Option<PathBuf> {
+    std::env::var_os("DENO_DIR")
+        .map(PathBuf::from)
+        .or_else(|| dirs::cache_dir().map(|dir| dir.join("deno")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config(mode: SandboxMode, required: bool) -> SandboxConfig {
+        SandboxConfig {
+            mode,
+            required,
+            run_as: None,
+            stage_writes: false,
+            workspace: false,
+        }
+    }
+
+    fn command() -> Vec<String> {
+        ["deno", "run", "script.ts"].map(String::from).to_vec()
+    }
+
+    #[test]
+    fn test_resolve_off_never_wraps() {
+        let result = config(SandboxMode::Off, true).resolve("linux", |_| true).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_resolve_auto_prefers_bubblewrap_then_firejail_on_linux() {
+        let auto = config(SandboxMode::Auto, false);
+
+        assert_eq!(auto.resolve("linux", |_| true).unwrap(), Some(SandboxWrapper::Bubblewrap));
+        assert_eq!(
+            auto.resolve("linux", |p| p == "firejail").unwrap(),
+            Some(SandboxWrapper::Firejail)
+        );
+        assert_eq!(auto.resolve("macos", |_| true).unwrap(), Some(SandboxWrapper::SandboxExec));
+    }
+
+    #[test]
+    fn test_resolve_falls_back_when_not_required() {
+        let result = config(SandboxMode::Bubblewrap, false).resolve("linux", |_| false).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_resolve_errors_when_required_and_missing() {
+        let result = config(SandboxMode::Auto, true).resolve("windows", |_| true);
+        assert!(result.unwrap_err().to_string().contains("required"));
+    }
+
+    #[test]
+    fn test_policy_from_permissions() {
+        let cwd = Path::new("/work");
+        let permissions: Vec<Permission> = ["--allow-write=out,/tmp/x", "--allow-read"]
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+
+        let policy = SandboxPolicy::from_permissions(&permissions, cwd, Some(Path::new("/deno")));
+
+        assert!(!policy.allow_net);
+        assert_eq!(
+            policy.writable_paths,
+            vec![PathBuf::from("/deno"), PathBuf::from("/work/out"), PathBuf::from("/tmp/x")]
+        );
+    }
+
+    #[test]
+    fn test_policy_unrestricted_write_is_limited_to_cwd() {
+        let permissions = vec![
+            Permission::unrestricted(PermissionKind::Write),
+            Permission::unrestricted(PermissionKind::Net),
+        ];
+
+        let policy = SandboxPolicy::from_permissions(&permissions, Path::new("/work"), None);
+
+        assert!(policy.allow_net);
+        assert_eq!(policy.writable_paths, vec![PathBuf::from("/work")]);
+    }
+
+    #[test]
+    fn test_bubblewrap_args() {
+        let temp_dir = TempDir::new().unwrap();
+        let writable = temp_dir.path().to_path_buf();
+        let policy = SandboxPolicy {
+            allow_net: false,
+            writable_paths: vec![writable.clone(), PathBuf::from("/does/not/exist")],
+            readable_paths: vec![writable.join("..")],
+        };
+
+        let args = SandboxWrapper::Bubblewrap.wrap_args(&command(), &policy);
+        let joined = args.join(" ");
+
+        assert!(joined.starts_with("--ro-bind / /"));
+        assert!(joined.contains(&format!("--bind {0} {0}", writable.display())));
+        assert!(joined.contains(&format!("--tmpfs /tmp --ro-bind {0}/.. {0}/..", writable.display())));
+        assert!(!joined.contains("/does/not/exist"));
+        assert!(joined.contains("--unshare-net"));
+        assert!(joined.ends_with("-- deno run script.ts"));
+    }
+
+    #[test]
+    fn test_firejail_args_keep_network_when_allowed() {
+        let policy = SandboxPolicy {
+            allow_net: true,
+            writable_paths: Vec::new(),
+            readable_paths: Vec::new(),
+        };
+
+        let args = SandboxWrapper::Firejail.wrap_args(&command(), &policy);
+
+        assert!(args.contains(&"--read-only=/".to_string()));
+        assert!(!args.contains(&"--net=none".to_string()));
+        assert_eq!(&args[args.len() - 4..], &["--", "deno", "run", "script.ts"]);
+    }
+
+    #[test]
+    fn test_sandbox_exec_profile_denies_network() {
+        let policy = SandboxPolicy {
+            allow_net: false,
+            writable_paths: Vec::new(),
+            readable_paths: Vec::new(),
+        };
+
+        let args = SandboxWrapper::SandboxExec.wrap_args(&command(), &policy);
+
+        assert_eq!(args[0], "-p");
+        assert!(args[1].contains("(deny file-write*)"));
+        assert!(args[1].contains("(deny network*)"));
+        assert_eq!(args[2], "deno");
+    }
+}
@@ -0,0 +1,188 @@
+//! Diffs between command revisions.
+//!
+//! When a command is regenerated (via `--nope`) or when inspecting its history
+//! (via `--diff`), users need to see exactly what the model changed. This
+//! module renders unified diffs of the script source and a summary of
+//! permission changes.
+
+use crate::llm_generator::PermissionRequest;
+use similar::TextDiff;
+
+/// Number of unchanged context lines shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// Renders a unified diff between two scripts.
+///
+/// Returns an empty string when the scripts are identical.
+///
+/// # Arguments
+///
+/// * `old` - The previous script
+/// * `new` - The new script
+/// * `old_label` - Label for the old side (e.g., `hello@1`)
+/// * `new_label` - Label for the new side (e.g., `hello@2`)
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(old_label, new_label)
+        .missing_newline_hint(false)
+        .to_string()
+}
+
+/// A change in the declared permissions of a command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PermissionChange {
+    /// A permission that is newly requested.
+    Added(PermissionRequest),
+    /// A permission that is no longer requested.
+    Removed(PermissionRequest),
+}
+
+/// Computes the permission changes between two revisions.
+///
+/// Permissions are compared by their flag; changes to the reason alone are
+/// not reported.
+pub fn permission_diff(old: &[PermissionRequest], new: &[PermissionRequest]) -> Vec<PermissionChange> {
+    let mut changes = Vec::new();
+
+    for perm in old {
+        if !new.iter().any(|p| p.permission == perm.permission) {
+            changes.push(PermissionChange::Removed(perm.clone()));
+        }
+    }
+    for perm in new {
+        if !old.iter().any(|p| p.permission == perm.permission) {
+            changes.push(PermissionChange::Added(perm.clone()));
+        }
+    }
+
+    changes
+}
+
+/// Formats permission changes as `+`/`-` lines.
+pub fn format_permission_diff(changes: &[PermissionChange]) -> String {
+    changes
+        .iter()
+        .map(|change| match change {
+            PermissionChange::Added(p) => format!("+ {} ({})\n", p.permission, p.reason),
+            PermissionChange::Removed(p) => format!("- {} ({})\n", p.permission, p.reason),
+        })
+        .collect()
+}
+
+/// Writes a full script and permission diff report.
+///
+/// # Arguments
+///
+/// * `output` - Writer for the report
+/// * `old` - Script and permissions of the old revision
+/// * `new` - Script and permissions of the new revision
+/// * `old_label` / `new_label` - Labels for the two sides
+pub fn write_revision_diff<W: std::io::Write>(
+    output: &mut W,
+    old: (&str, &[PermissionRequest]),
+    new: (&str, &[PermissionRequest]),
+    old_label: &str,
+    new_label: &str,
+) -> std::io::Result<()> {
+    let script_diff = unified_diff(old.0, new.0, old_label, new_label);
+    if script_diff.is_empty() {
+        writeln!(output, "📄 Script unchanged")?;
+    } else {
+        writeln!(output, "📄 Script changes:")?;
+        write!(output, "{}", script_diff)?;
+    }
+
+    let permission_changes = permission_diff(old.1, new.1);
+    if permission_changes.is_empty() {
+        writeln!(output, "🔑 Permissions unchanged")?;
+    } else {
+        writeln!(output, "🔑 Permission changes:")?;
+        write!(output, "{}", format_permission_diff(&permission_changes))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perm(flag: &str) -> PermissionRequest {
+        PermissionRequest {
+            permission: flag.to_string(),
+            reason: format!("needs {}", flag),
+        }
+    }
+
+    #[test]
+    fn test_unified_diff_identical_is_empty() {
+        assert_eq!(unified_diff("a\n", "a\n", "old", "new"), "");
+    }
+
+    #[test]
+    fn test_unified_diff_shows_changed_lines() {
+        let diff = unified_diff("a\nb\nc\n", "a\nB\nc\n", "cmd@1", "cmd@2");
+
+        assert!(diff.contains("--- cmd@1"));
+        assert!(diff.contains("+++ cmd@2"));
+        assert!(diff.contains("-b"));
+        assert!(diff.contains("+B"));
+        assert!(diff.contains(" a"));
+    }
+
+    #[test]
+    fn test_permission_diff_detects_added_and_removed() {
+        let old = vec![perm("--allow-read"), perm("--allow-net")];
+        let new = vec![perm("--allow-read"), perm("--allow-write")];
+
+        let changes = permission_diff(&old, &new);
+
+        assert_eq!(
+            changes,
+            vec![
+                PermissionChange::Removed(perm("--allow-net")),
+                PermissionChange::Added(perm("--allow-write")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_permission_diff_ignores_reason_changes() {
+        let old = vec![perm("--allow-read")];
+        let new = vec![PermissionRequest {
+            permission: "--allow-read".to_string(),
+            reason: "different reason".to_string(),
+        }];
+
+        assert!(permission_diff(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn test_format_permission_diff() {
+        let changes = vec![
+            PermissionChange::Added(perm("--allow-net")),
+            PermissionChange::Removed(perm("--allow-env")),
+        ];
+
+        let formatted = format_permission_diff(&changes);
+
+        assert!(formatted.contains("+ --allow-net"));
+        assert!(formatted.contains("- --allow-env"));
+    }
+
+    #[test]
+    fn test_write_revision_diff_reports_unchanged() {
+        let mut output = Vec::new();
+        write_revision_diff(&mut output, ("x\n", &[]), ("x\n", &[]), "a", "b").unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Script unchanged"));
+        assert!(output.contains("Permissions unchanged"));
+    }
+}
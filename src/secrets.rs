@@ -0,0 +1,251 @@
+//! Handling of sensitive command output.
+//!
+//! Commands that print secrets (password and token generators, for example)
+//! are flagged by the model when they are generated, or recognized by their
+//! name and description. Their output is never saved in the execution
+//! context, and on a terminal it is hidden unless `--show` is given. Instead,
+//! ergo offers to copy it to the clipboard and clears the clipboard again
+//! after [`CLIPBOARD_CLEAR_SECONDS`].
+
+use crate::cancellation::Cancellation;
+use crate::executor::ProcessRunner;
+use crate::llm_generator::GeneratedCommand;
+use anyhow::{Result, anyhow};
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// How long a copied secret stays on the clipboard.
+pub const CLIPBOARD_CLEAR_SECONDS: u64 = 30;
+
+/// Words in a command's name or description that suggest it prints secrets.
+const SENSITIVE_WORDS: &[&str] = &[
+    "password",
+    "passphrase",
+    "passwd",
+    "secret",
+    "token",
+    "api-key",
+    "apikey",
+    "private-key",
+    "credential",
+    "otp",
+    "totp",
+];
+
+/// How sensitive output reaches the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SecretDelivery {
+    /// Hide the output on a terminal and offer to copy it to the clipboard.
+    #[default]
+    Mask,
+    /// Print the output as usual (`--show`).
+    Show,
+    /// Copy the output to the clipboard without printing it (`--clipboard`).
+    Clipboard,
+}
+
+/// Returns true if a command's output should be treated as a secret.
+///
+/// Uses the model's `sensitive_output` flag, falling back to a keyword check
+/// on the command's name and description.
+pub fn is_sensitive(command: &GeneratedCommand) -> bool {
+    if command.sensitive_output {
+        return true;
+    }
+    let text = format!("{} {}", command.name, command.description)
+        .to_lowercase()
+        .replace([' ', '_'], "-");
+    text.split(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .flat_map(|word| {
+            let parts: Vec<&str> = word.split('-').collect();
+            // Single words and adjacent pairs, so "api key" matches "api-key"
+            let pairs: Vec<String> = parts.windows(2).map(|w| w.join("-")).collect();
+            parts.into_iter().map(str::to_string).chain(pairs).collect::<Vec<_>>()
+        })
+        .any(|word| SENSITIVE_WORDS.iter().any(|s| word == *s || word == format!("{}s", s)))
+}
+
+/// Message shown in place of hidden output.
+pub fn hidden_notice(output: &str) -> String {
+    let lines = output.lines().count();
+    format!(
+        "🔒 Sensitive output hidden ({} line{}). Use --show to display it or --clipboard to copy it.",
+        lines,
+        if lines == 1 { "" } else { "s" }
+    )
+}
+
+/// Asks whether hidden output should be copied to the clipboard.
+pub fn offer_clipboard_with_io<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> Result<bool> {
+    write!(output, "📋 Copy it to the clipboard? [Y/n] ")?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "" | "y" | "yes"))
+}
+
+/// A program invocation: the program and its arguments.
+type Invocation = (&'static str, &'static [&'static str]);
+
+/// The system clipboard, accessed through the platform's command-line tools.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clipboard {
+    copy: Invocation,
+    paste: Invocation,
+}
+
+impl Clipboard {
+    /// Finds a clipboard tool for `os`.
+    ///
+    /// # Arguments
+    ///
+    /// * `os` - The target OS (as in `std::env::consts::OS`)
+    /// * `wayland` - Whether a Wayland session is running
+    /// * `program_exists` - Checks if a program is installed
+    pub fn detect(os: &str, wayland: bool, program_exists: impl Fn(&str) -> bool) -> Option<Self> {
+        let candidates: &[(Invocation, Invocation)] = match os {
+            "macos" => &[(("pbcopy", &[]), ("pbpaste", &[]))],
+            _ if wayland => &[(("wl-copy", &[]), ("wl-paste", &["-n"]))],
+            _ => &[
+                (("xclip", &["-selection", "clipboard"]), ("xclip", &["-selection", "clipboard", "-o"])),
+                (("xsel", &["--clipboard", "--input"]), ("xsel", &["--clipboard", "--output"])),
+            ],
+        };
+        candidates
+            .iter()
+            .find(|(copy, _)| program_exists(copy.0))
+            .map(|&(copy, paste)| Self { copy, paste })
+    }
+
+    /// Finds a clipboard tool on this system.
+    pub fn detect_system<P: ProcessRunner>(runner: &P) -> Option<Self> {
+        Self::detect(
+            std::env::consts::OS,
+            std::env::var_os("WAYLAND_DISPLAY").is_some(),
+            |program| runner.program_exists(program),
+        )
+    }
+
+    /// Puts `text` on the clipboard.
+    pub fn copy<P: ProcessRunner>(&self, runner: &P, text: &str) -> Result<()> {
+        let status = runner.run_with_input(self.copy.0, self.copy.1, text.as_bytes())?;
+        if !status.success() {
+            return Err(anyhow!("{} failed with {}", self.copy.0, status));
+        }
+        Ok(())
+    }
+
+    /// Empties the clipboard if it still holds `text`.
+    ///
+    /// Something the user copied in the meantime is left alone.
+    pub fn clear_if_unchanged<P: ProcessRunner>(&self, runner: &P, text: &str) -> Result<bool> {
+        let current = runner.run(self.paste.0, self.paste.1)?;
+        if current.status.success() && current.stdout != text.as_bytes() {
+            info!("Clipboard changed since copying, leaving it alone");
+            return Ok(false);
+        }
+        self.copy(runner, "")?;
+        Ok(true)
+    }
+
+    /// Copies `text`, waits [`CLIPBOARD_CLEAR_SECONDS`] (or until cancelled),
+    /// then clears the clipboard.
+    pub fn copy_temporarily<P: ProcessRunner, W: Write + ?Sized>(
+        &self,
+        runner: &P,
+        text: &str,
+        cancellation: &Cancellation,
+        output: &mut W,
+    ) -> Result<()> {
+        self.copy(runner, text)?;
+        writeln!(
+            output,
+            "📋 Copied to the clipboard. Clearing it in {}s (Ctrl-C to clear now)...",
+            CLIPBOARD_CLEAR_SECONDS
+        )?;
+
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(CLIPBOARD_CLEAR_SECONDS) && !cancellation.is_cancelled() {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        if self.clear_if_unchanged(runner, text)? {
+            writeln!(output, "🧹 Clipboard cleared")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn command(name: &str, description: &str) -> GeneratedCommand {
+        GeneratedCommand {
+            name: name.to_string(),
+            description: description.to_string(),
+            script_file: format!("{}.ts", name),
+            permissions: vec![],
+            sensitive_output: false,
+            output_format: crate::output_processing::OutputFormat::Text,
+            examples: vec![],
+            assumptions: vec![],
+            confidence: None,
+            platforms: vec![],
+            variants: Default::default(),
+            provenance: None,
+            spec: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_is_sensitive_uses_model_flag() {
+        let mut cmd = command("roll", "Rolls a die");
+        assert!(!is_sensitive(&cmd));
+
+        cmd.sensitive_output = true;
+        assert!(is_sensitive(&cmd));
+    }
+
+    #[test]
+    fn test_is_sensitive_recognizes_keywords() {
+        assert!(is_sensitive(&command("password", "Generates a password")));
+        assert!(is_sensitive(&command("gen", "Creates a random API key")));
+        assert!(is_sensitive(&command("new_token", "Prints tokens")));
+        assert!(!is_sensitive(&command("keyboard-layout", "Shows the keyboard layout")));
+        assert!(!is_sensitive(&command("hotpot", "Suggests hotpot recipes")));
+    }
+
+    #[test]
+    fn test_hidden_notice_counts_lines() {
+        assert!(hidden_notice("s3cret\n").contains("(1 line)"));
+        assert!(hidden_notice("a\nb\n").contains("(2 lines)"));
+        assert!(!hidden_notice("s3cret\n").contains("s3cret"));
+    }
+
+    #[test]
+    fn test_offer_clipboard_defaults_to_yes() {
+        let mut output = Vec::new();
+
+        assert!(offer_clipboard_with_io(&mut Cursor::new("\n"), &mut output).unwrap());
+        assert!(offer_clipboard_with_io(&mut Cursor::new("y\n"), &mut output).unwrap());
+        assert!(!offer_clipboard_with_io(&mut Cursor::new("n\n"), &mut output).unwrap());
+    }
+
+    #[test]
+    fn test_detect_clipboard_per_platform() {
+        let mac = Clipboard::detect("macos", false, |_| true).unwrap();
+        assert_eq!(mac.copy.0, "pbcopy");
+
+        let wayland = Clipboard::detect("linux", true, |_| true).unwrap();
+        assert_eq!(wayland.copy.0, "wl-copy");
+
+        let x11 = Clipboard::detect("linux", false, |p| p == "xsel").unwrap();
+        assert_eq!(x11.paste, ("xsel", &["--clipboard", "--output"][..]));
+
+        assert!(Clipboard::detect("linux", false, |_| false).is_none());
+    }
+}
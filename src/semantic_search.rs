@@ -0,0 +1,206 @@
+//! Semantic lookup of cached commands for conversational intents.
+//!
+//! Every cached command stores an embedding of its name and description.
+//! Before generating a command for a natural language intent, the intent is
+//! embedded the same way and compared with the cached commands; when one is
+//! similar enough, ergo offers to run it instead of generating a new one.
+//!
+//! Embeddings are computed locally, without any API call: words (lightly
+//! stemmed, minus stop words) and their character trigrams are hashed into a
+//! fixed-size vector, so "pretty print json" lands close to "Formats JSON
+//! input" and typos still overlap. Each embedding records the model that
+//! produced it, so changing the model invalidates stored vectors instead of
+//! comparing incompatible ones.
+
+use crate::llm_generator::GeneratedCommand;
+use serde::{Deserialize, Serialize};
+
+/// Name of the embedding model, stored with every embedding.
+pub const EMBEDDING_MODEL: &str = "hashed-trigrams-v1";
+
+/// Number of dimensions of an embedding.
+const DIMENSIONS: usize = 1024;
+
+/// Weight of a whole word relative to each of its trigrams.
+const WORD_WEIGHT: f32 = 2.0;
+
+/// Words too common to tell commands apart.
+const STOP_WORDS: &[&str] = &[
+    "a", "all", "an", "and", "as", "at", "by", "do", "for", "from", "give", "i", "in", "into", "is", "it", "its", "me",
+    "my", "of", "on", "or", "please", "show", "that", "the", "this", "to", "what", "with",
+];
+
+/// Semantic search settings (the `[semantic_search]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SemanticSearchConfig {
+    /// Whether conversational intents look for a similar cached command first.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Cosine similarity (0 to 1) a cached command needs to be offered.
+    #[serde(default = "default_min_similarity")]
+    pub min_similarity: f32,
+}
+
+impl Default for SemanticSearchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            min_similarity: default_min_similarity(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_min_similarity() -> f32 {
+    0.3
+}
+
+/// A text's position in embedding space.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Embedding {
+    /// The model that computed the vector, e.g. [`EMBEDDING_MODEL`].
+    pub model: String,
+    /// Unit-length vector.
+    pub vector: Vec<f32>,
+}
+
+impl Embedding {
+    /// Embeds `text` with [`EMBEDDING_MODEL`].
+    pub fn of(text: &str) -> Self {
+        let mut vector = vec![0.0; DIMENSIONS];
+        for word in words(text) {
+            add_feature(&mut vector, &word, WORD_WEIGHT);
+            let padded: Vec<char> = format!("^{}$", word).chars().collect();
+            for trigram in padded.windows(3) {
+                add_feature(&mut vector, &trigram.iter().collect::<String>(), 1.0);
+            }
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        Self {
+            model: EMBEDDING_MODEL.to_string(),
+            vector,
+        }
+    }
+
+    /// Embeds what a command does: its name and description.
+    pub fn of_command(command: &GeneratedCommand) -> Self {
+        Self::of(&format!("{} {}", command.name, command.description))
+    }
+
+    /// Whether this embedding was computed by the current model.
+    pub fn is_current(&self) -> bool {
+        self.model == EMBEDDING_MODEL && self.vector.len() == DIMENSIONS
+    }
+
+    /// Cosine similarity with `other`, or 0 if they come from different models.
+    pub fn similarity(&self, other: &Embedding) -> f32 {
+        if self.model != other.model || self.vector.len() != other.vector.len() {
+            return 0.0;
+        }
+        self.vector.iter().zip(&other.vector).map(|(a, b)| a * b).sum()
+    }
+}
+
+/// A cached command similar to an intent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimilarCommand {
+    /// The command's name.
+    pub name: String,
+    /// The command's description.
+    pub description: String,
+    /// Cosine similarity with the intent, from 0 to 1.
+    pub similarity: f32,
+}
+
+/// Lowercased, lightly stemmed words of `text`, without stop words.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| !word.is_empty() && !STOP_WORDS.contains(&word.as_str()))
+        .map(|word| stem(&word))
+        .collect()
+}
+
+/// Strips common English suffixes, so "formats" and "formatting" match "format".
+fn stem(word: &str) -> String {
+    for suffix in ["ing", "ed", "es", "s"] {
+        if let Some(stem) = word.strip_suffix(suffix)
+            && stem.chars().count() >= 3
+            && !stem.ends_with('s')
+        {
+            return stem.to_string();
+        }
+    }
+    word.to_string()
+}
+
+/// Adds `weight` to the dimension `feature` hashes to, with a hashed sign so
+/// collisions cancel out on average.
+fn add_feature(vector: &mut [f32], feature: &str, weight: f32) {
+    // FNV-1a, stable across platforms and releases
+    let hash = feature
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3));
+    let index = (hash % DIMENSIONS as u64) as usize;
+    let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+    vector[index] += sign * weight;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn similarity(a: &str, b: &str) -> f32 {
+        Embedding::of(a).similarity(&Embedding::of(b))
+    }
+
+    #[test]
+    fn test_related_texts_are_similar() {
+        let format_json = "format-json Formats JSON input with indentation";
+
+        let weather = "weather Shows the current weather for a city";
+
+        assert!(similarity("pretty print json", format_json) > 0.3);
+        assert!(similarity("indent json", format_json) > 0.3);
+        assert!(similarity("what's the weather like", weather) > 0.3);
+        assert!(similarity("what's the weather like", format_json) < 0.15);
+        assert!(similarity("list open ports", weather) < 0.15);
+        assert!((similarity(format_json, format_json) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_embeddings_are_unit_length() {
+        let embedding = Embedding::of("count lines of code");
+
+        let norm: f32 = embedding.vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+        assert!(embedding.is_current());
+        assert!(Embedding::of("the a of").vector.iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn test_other_models_are_not_compared() {
+        let stale = Embedding {
+            model: "some-api-model".to_string(),
+            ..Embedding::of("format json")
+        };
+
+        assert!(!stale.is_current());
+        assert_eq!(stale.similarity(&Embedding::of("format json")), 0.0);
+    }
+
+    #[test]
+    fn test_stem() {
+        assert_eq!(stem("formats"), "format");
+        assert_eq!(stem("formatting"), "formatt");
+        assert_eq!(stem("listed"), "list");
+        assert_eq!(stem("process"), "process");
+        assert_eq!(stem("bus"), "bus");
+    }
+}
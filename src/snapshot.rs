@@ -0,0 +1,251 @@
+//! Snapshots of a whole bioma, for `ergo bioma snapshot` and `ergo bioma restore`.
+//!
+//! A snapshot is a gzipped tarball of the write bioma — `commands.json`, the
+//! scripts, their revisions and everything else in it — kept as
+//! `~/.abiogenesis/snapshots/<name>.tar.gz`, with a `snapshot.json` recording
+//! which bioma it was taken of and when. Taking one before a regeneration
+//! spree lets the whole library be rolled back if things get worse.
+//!
+//! Restoring replaces the bioma's contents with the snapshot's. The bioma as
+//! it was is first saved as the [`BEFORE_RESTORE`] snapshot, so a restore can
+//! be undone too.
+
+use anyhow::{Context, Result, anyhow, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use tracing::info;
+
+/// Snapshot taken automatically before a restore.
+pub const BEFORE_RESTORE: &str = "before-restore";
+
+/// Directory of the bioma's files inside a snapshot.
+const BIOMA_PREFIX: &str = "bioma";
+
+/// Name of the metadata file inside a snapshot.
+const METADATA_FILE: &str = "snapshot.json";
+
+/// What a snapshot holds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    /// The bioma it was taken of.
+    pub bioma: PathBuf,
+    /// When it was taken (Unix timestamp).
+    pub created_at: u64,
+    /// How many commands the bioma had.
+    pub commands: usize,
+}
+
+/// Returns the directory snapshots are kept in.
+pub fn default_dir() -> Result<PathBuf> {
+    Ok(crate::config::Config::get_config_dir()?.join("snapshots"))
+}
+
+/// Path of the snapshot `name` in `snapshots_dir`.
+///
+/// # Errors
+///
+/// Returns an error if `name` isn't a valid snapshot name: letters, digits,
+/// `-`, `_` and `.`, not starting with a dot.
+pub fn snapshot_path(snapshots_dir: &Path, name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        bail!("'{}' isn't a valid snapshot name; use letters, digits, '-', '_' and '.'", name);
+    }
+    Ok(snapshots_dir.join(format!("{}.tar.gz", name)))
+}
+
+/// Names of the snapshots in `snapshots_dir`, sorted.
+pub fn list(snapshots_dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(snapshots_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|file| file.strip_suffix(".tar.gz").map(str::to_string))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Saves the contents of `bioma` as the snapshot at `path`, replacing it if
+/// `overwrite`.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot exists and `overwrite` is false, or if
+/// the bioma can't be read or the snapshot written.
+pub fn create(bioma: &Path, path: &Path, created_at: u64, overwrite: bool) -> Result<SnapshotInfo> {
+    if path.exists() && !overwrite {
+        bail!("The snapshot {} already exists; pick another name", path.display());
+    }
+    if !bioma.is_dir() {
+        bail!("There's no bioma at {} to snapshot", bioma.display());
+    }
+    let info = SnapshotInfo {
+        bioma: bioma.to_path_buf(),
+        created_at,
+        commands: count_commands(bioma),
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = path.with_extension("gz.tmp");
+    let mut builder = tar::Builder::new(GzEncoder::new(fs::File::create(&temp_path)?, Compression::default()));
+    builder.follow_symlinks(false);
+    let metadata = serde_json::to_vec_pretty(&info)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(metadata.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(created_at);
+    header.set_cksum();
+    builder.append_data(&mut header, METADATA_FILE, metadata.as_slice())?;
+    builder
+        .append_dir_all(BIOMA_PREFIX, bioma)
+        .with_context(|| format!("Failed to archive {}", bioma.display()))?;
+    builder.into_inner()?.finish()?;
+    fs::rename(&temp_path, path)?;
+    info!("Saved a snapshot of {:?} with {} command(s) to {:?}", bioma, info.commands, path);
+    Ok(info)
+}
+
+/// Reads what the snapshot at `path` holds.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot is missing or isn't one.
+pub fn read_info(path: &Path) -> Result<SnapshotInfo> {
+    let mut archive = open(path)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_ref() == Path::new(METADATA_FILE) {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            return serde_json::from_str(&content).with_context(|| format!("{} is corrupted", path.display()));
+        }
+    }
+    Err(anyhow!("{} isn't a bioma snapshot", path.display()))
+}
+
+/// Replaces the contents of `bioma` with those of the snapshot at `path`.
+///
+/// The snapshot is unpacked next to the bioma first, and swapped in once
+/// complete, so a broken snapshot leaves the bioma untouched.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot can't be read or holds paths outside the
+/// bioma, or if the bioma can't be replaced.
+pub fn restore(path: &Path, bioma: &Path) -> Result<SnapshotInfo> {
+    let info = read_info(path)?;
+    let parent = bioma
+        .parent()
+        .ok_or_else(|| anyhow!("Can't restore into {}", bioma.display()))?;
+    fs::create_dir_all(parent)?;
+    let staging = tempfile::Builder::new().prefix(".ergo-restore-").tempdir_in(parent)?;
+
+    let mut archive = open(path)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        let Ok(relative) = entry_path.strip_prefix(BIOMA_PREFIX) else {
+            continue;
+        };
+        if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+            bail!("{} holds a path outside the bioma: {}", path.display(), entry_path.display());
+        }
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let kind = entry.header().entry_type();
+        let target = staging.path().join(relative);
+        if kind.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if kind.is_file() {
+            if let Some(dir) = target.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            entry.unpack(&target)?;
+        }
+    }
+
+    // Swap the restored bioma in, then drop the old one
+    let staged = staging.keep();
+    let replaced = parent.join(format!(".ergo-replaced-{}", std::process::id()));
+    if bioma.exists() {
+        fs::rename(bioma, &replaced)?;
+    }
+    if let Err(e) = fs::rename(&staged, bioma) {
+        if replaced.exists() {
+            fs::rename(&replaced, bioma)?;
+        }
+        return Err(e.into());
+    }
+    if replaced.exists() {
+        fs::remove_dir_all(&replaced)?;
+    }
+    info!("Restored {:?} from {:?}", bioma, path);
+    Ok(info)
+}
+
+fn open(path: &Path) -> Result<tar::Archive<GzDecoder<fs::File>>> {
+    let file = fs::File::open(path).with_context(|| format!("No snapshot at {}", path.display()))?;
+    Ok(tar::Archive::new(GzDecoder::new(file)))
+}
+
+/// How many commands the bioma's `commands.json` lists.
+fn count_commands(bioma: &Path) -> usize {
+    fs::read_to_string(bioma.join("commands.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&content).ok())
+        .map_or(0, |commands| commands.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_restore_rolls_the_bioma_back() {
+        let home = TempDir::new().unwrap();
+        let bioma = home.path().join("biomas");
+        fs::create_dir_all(bioma.join("revisions")).unwrap();
+        fs::write(bioma.join("commands.json"), r#"{"greet": {}}"#).unwrap();
+        fs::write(bioma.join("greet.ts"), "console.log('hi')").unwrap();
+        fs::write(bioma.join("revisions/greet.1.ts"), "console.log('hello')").unwrap();
+        let path = snapshot_path(&home.path().join("snapshots"), "v1").unwrap();
+
+        let info = create(&bioma, &path, 1_700_000_000, false).unwrap();
+        assert_eq!((info.commands, info.created_at), (1, 1_700_000_000));
+        assert!(create(&bioma, &path, 1_700_000_001, false).is_err());
+        assert_eq!(list(&home.path().join("snapshots")).unwrap(), vec!["v1"]);
+
+        fs::write(bioma.join("greet.ts"), "console.log('worse')").unwrap();
+        fs::write(bioma.join("extra.ts"), "console.log('new')").unwrap();
+        assert_eq!(restore(&path, &bioma).unwrap(), info);
+
+        assert_eq!(fs::read_to_string(bioma.join("greet.ts")).unwrap(), "console.log('hi')");
+        assert_eq!(fs::read_to_string(bioma.join("revisions/greet.1.ts")).unwrap(), "console.log('hello')");
+        assert!(!bioma.join("extra.ts").exists());
+        assert_eq!(fs::read_dir(home.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_snapshot_names_stay_in_the_snapshots_dir() {
+        let dir = Path::new("/snapshots");
+        assert_eq!(snapshot_path(dir, "before-upgrade").unwrap(), dir.join("before-upgrade.tar.gz"));
+        assert!(snapshot_path(dir, "../escape").is_err());
+        assert!(snapshot_path(dir, ".hidden").is_err());
+        assert!(read_info(&dir.join("missing.tar.gz")).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+//! Staged filesystem writes for generated commands.
+//!
+//! In staging mode (`ergo --stage ...` or `sandbox.stage_writes = true`), a
+//! command that may write files runs inside a copy of the current directory
+//! instead of the real one. Afterwards ergo compares the copy with the real
+//! directory, shows a manifest of created, modified and deleted files, and
+//! applies the changes only if the user agrees.
+//!
+//! This is a safety net for commands like "clean up my downloads folder":
+//! a bad generation only damages the staging copy.
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Component, Path, PathBuf};
+use tempfile::TempDir;
+use tracing::info;
+
+/// Largest directory (in bytes) that will be copied into a staging area.
+pub const MAX_STAGE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Files changed by a staged command, relative to the working directory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeManifest {
+    /// Files that did not exist before.
+    pub created: Vec<PathBuf>,
+    /// Files whose content changed.
+    pub modified: Vec<PathBuf>,
+    /// Files that were removed.
+    pub deleted: Vec<PathBuf>,
+}
+
+impl ChangeManifest {
+    /// Returns true if the command changed nothing.
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// A staging copy of a working directory.
+///
+/// The copy is removed when the area is dropped.
+#[derive(Debug)]
+pub struct StagingArea {
+    _root: TempDir,
+    staged_dir: PathBuf,
+    real_dir: PathBuf,
+}
+
+impl StagingArea {
+    /// Copies `real_dir` into a new staging directory under `base`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory is larger than [`MAX_STAGE_BYTES`]
+    /// or cannot be copied.
+    pub fn create(real_dir: &Path, base: &Path) -> Result<Self> {
+        let size = dir_size(real_dir)?;
+        if size > MAX_STAGE_BYTES {
+            return Err(anyhow!(
+                "'{}' is too large to stage ({} MiB, limit {} MiB). Run the command from a smaller directory or without staging.",
+                real_dir.display(),
+                size / (1024 * 1024),
+                MAX_STAGE_BYTES / (1024 * 1024)
+            ));
+        }
+
+        // Created fresh, only we can use it and no one can guess its name
+        let root = tempfile::Builder::new().prefix("ergo_stage_").tempdir_in(base)?;
+        let staged_dir = root.path().join("work");
+        copy_dir(real_dir, &staged_dir)?;
+        info!("Staged '{}' into '{}'", real_dir.display(), staged_dir.display());
+
+        Ok(Self {
+            _root: root,
+            staged_dir,
+            real_dir: real_dir.to_path_buf(),
+        })
+    }
+
+    /// The directory the command should run in.
+    pub fn staged_dir(&self) -> &Path {
+        &self.staged_dir
+    }
+
+    /// Maps an absolute path inside the real directory to the staging copy.
+    ///
+    /// Returns `None` for paths outside the real directory, which cannot be
+    /// staged.
+    pub fn map_path(&self, path: &Path) -> Option<PathBuf> {
+        path.strip_prefix(&self.real_dir)
+            .ok()
+            .map(|relative| self.staged_dir.join(relative))
+    }
+
+    /// Compares the staging copy with the real directory.
+    pub fn manifest(&self) -> Result<ChangeManifest> {
+        let real = list_files(&self.real_dir)?;
+        let staged = list_files(&self.staged_dir)?;
+
+        let mut manifest = ChangeManifest::default();
+        for path in staged.difference(&real) {
+            manifest.created.push(path.clone());
+        }
+        for path in real.intersection(&staged) {
+            if fs::read(self.real_dir.join(path))? != fs::read(self.staged_dir.join(path))? {
+                manifest.modified.push(path.clone());
+            }
+        }
+        for path in real.difference(&staged) {
+            manifest.deleted.push(path.clone());
+        }
+
+        Ok(manifest)
+    }
+
+    /// Applies the changes in `manifest` to the real directory.
+    pub fn apply(&self, manifest: &ChangeManifest) -> Result<()> {
+        for path in manifest.created.iter().chain(&manifest.modified) {
+            let target = self.real_dir.join(path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(self.staged_dir.join(path), &target)?;
+        }
+        for path in &manifest.deleted {
+            fs::remove_file(self.real_dir.join(path))?;
+            // Remove directories the command emptied, up to the working directory
+            let mut dir = path.parent();
+            while let Some(relative) = dir.filter(|d| !d.as_os_str().is_empty()) {
+                if self.staged_dir.join(relative).exists() || fs::remove_dir(self.real_dir.join(relative)).is_err() {
+                    break;
+                }
+                dir = relative.parent();
+            }
+        }
+        info!("Applied staged changes to '{}'", self.real_dir.display());
+        Ok(())
+    }
+}
+
+/// Writes a human-readable change manifest.
+pub fn write_manifest<W: Write + ?Sized>(output: &mut W, manifest: &ChangeManifest) -> std::io::Result<()> {
+    if manifest.is_empty() {
+        writeln!(output, "📂 The command made no file changes")?;
+        return Ok(());
+    }
+
+    writeln!(output, "📂 Staged file changes:")?;
+    for path in &manifest.created {
+        writeln!(output, "   + {}", path.display())?;
+    }
+    for path in &manifest.modified {
+        writeln!(output, "   ~ {}", path.display())?;
+    }
+    for path in &manifest.deleted {
+        writeln!(output, "   - {}", path.display())?;
+    }
+    Ok(())
+}
+
+/// Decides whether staged changes should be applied.
+pub trait ChangeApprover: Send + Sync {
+    /// Shows the manifest and returns true if the changes should be applied.
+    fn approve(&self, manifest: &ChangeManifest, output: &mut dyn Write) -> Result<bool>;
+}
+
+/// Asks on stdin whether to apply the changes (default: no).
+pub struct StdinApprover;
+
+impl StdinApprover {
+    /// Prompts on `output` and reads the answer from `input`.
+    pub fn approve_with_io<R: BufRead>(
+        manifest: &ChangeManifest,
+        input: &mut R,
+        output: &mut dyn Write,
+    ) -> Result<bool> {
+        write_manifest(output, manifest)?;
+        if manifest.is_empty() {
+            return Ok(false);
+        }
+
+        write!(output, "\nApply these changes? [y/N]: ")?;
+        output.flush()?;
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+}
+
+impl ChangeApprover for StdinApprover {
+    fn approve(&self, manifest: &ChangeManifest, output: &mut dyn Write) -> Result<bool> {
+        Self::approve_with_io(manifest, &mut std::io::stdin().lock(), output)
+    }
+}
+
+/// Lists the files under `root`, relative to it.
+pub fn list_files(root: &Path) -> Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else {
+                files.insert(entry.path().strip_prefix(root)?.to_path_buf());
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn dir_size(root: &Path) -> Result<u64> {
+    list_files(root)?
+        .iter()
+        .map(|p| Ok(fs::symlink_metadata(root.join(p))?.len()))
+        .sum()
+}
+
+/// Copies the directory `from` to `to`.
+///
+/// Relative symlinks to something inside `from` stay symlinks. Any other
+/// link would let a command write through it to the real file, past the
+/// review of its changes: one to a file is copied as that file, anything
+/// else is refused.
+///
+/// # Errors
+///
+/// Returns an error if a link leads outside `from` to something other than a
+/// file, or if copying fails.
+pub fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    copy_tree(from, Path::new(""), to)
+}
+
+fn copy_tree(root: &Path, relative: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_tree(root, &relative.join(entry.file_name()), &target)?;
+        } else if file_type.is_symlink() {
+            let link = fs::read_link(entry.path())?;
+            if stays_inside(relative, &link) {
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&link, &target)?;
+            } else if entry.path().is_file() {
+                fs::copy(entry.path(), &target)?;
+            } else {
+                return Err(anyhow!(
+                    "'{}' links to '{}', outside '{}'; it can't be copied safely",
+                    entry.path().display(),
+                    link.display(),
+                    root.display()
+                ));
+            }
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the link `link`, in the directory `dir` (relative to the copied
+/// tree), is relative and points inside the tree.
+fn stays_inside(dir: &Path, link: &Path) -> bool {
+    let mut depth = dir.components().count();
+    for component in link.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, TempDir) {
+        let real = TempDir::new().unwrap();
+        fs::write(real.path().join("keep.txt"), "keep").unwrap();
+        fs::write(real.path().join("edit.txt"), "before").unwrap();
+        fs::create_dir(real.path().join("old")).unwrap();
+        fs::write(real.path().join("old/gone.txt"), "gone").unwrap();
+        (real, TempDir::new().unwrap())
+    }
+
+    fn simulate_command(area: &StagingArea) {
+        let dir = area.staged_dir();
+        fs::write(dir.join("edit.txt"), "after").unwrap();
+        fs::write(dir.join("new.txt"), "new").unwrap();
+        fs::remove_dir_all(dir.join("old")).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_lists_created_modified_and_deleted() {
+        let (real, base) = setup();
+        let area = StagingArea::create(real.path(), base.path()).unwrap();
+        simulate_command(&area);
+
+        let manifest = area.manifest().unwrap();
+
+        assert_eq!(manifest.created, vec![PathBuf::from("new.txt")]);
+        assert_eq!(manifest.modified, vec![PathBuf::from("edit.txt")]);
+        assert_eq!(manifest.deleted, vec![PathBuf::from("old/gone.txt")]);
+    }
+
+    #[test]
+    fn test_real_directory_untouched_until_applied() {
+        let (real, base) = setup();
+        let area = StagingArea::create(real.path(), base.path()).unwrap();
+        simulate_command(&area);
+
+        assert_eq!(fs::read_to_string(real.path().join("edit.txt")).unwrap(), "before");
+        assert!(!real.path().join("new.txt").exists());
+
+        area.apply(&area.manifest().unwrap()).unwrap();
+
+        assert_eq!(fs::read_to_string(real.path().join("edit.txt")).unwrap(), "after");
+        assert_eq!(fs::read_to_string(real.path().join("new.txt")).unwrap(), "new");
+        assert!(!real.path().join("old").exists());
+        assert!(real.path().join("keep.txt").exists());
+    }
+
+    #[test]
+    fn test_staging_copy_removed_on_drop() {
+        let (real, base) = setup();
+        let staged = {
+            let area = StagingArea::create(real.path(), base.path()).unwrap();
+            area.staged_dir().to_path_buf()
+        };
+
+        assert!(!staged.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_leaving_the_directory_are_not_staged_as_links() {
+        let outside = TempDir::new().unwrap();
+        let real_dir = outside.path().join("real");
+        fs::create_dir(&real_dir).unwrap();
+        fs::write(outside.path().join("outside.txt"), "secret").unwrap();
+        fs::write(real_dir.join("inside.txt"), "inside").unwrap();
+        std::os::unix::fs::symlink("../outside.txt", real_dir.join("escape")).unwrap();
+        std::os::unix::fs::symlink("inside.txt", real_dir.join("alias")).unwrap();
+        let base = TempDir::new().unwrap();
+
+        let area = StagingArea::create(&real_dir, base.path()).unwrap();
+        let staged = area.staged_dir();
+        assert!(staged.join("alias").is_symlink());
+        assert!(!staged.join("escape").is_symlink());
+        fs::write(staged.join("escape"), "changed").unwrap();
+        assert_eq!(fs::read_to_string(outside.path().join("outside.txt")).unwrap(), "secret");
+        assert_eq!(area.manifest().unwrap().modified, vec![PathBuf::from("escape")]);
+
+        std::os::unix::fs::symlink("..", real_dir.join("parent")).unwrap();
+        assert!(StagingArea::create(&real_dir, base.path()).is_err());
+    }
+
+    #[test]
+    fn test_map_path() {
+        let (real, base) = setup();
+        let area = StagingArea::create(real.path(), base.path()).unwrap();
+
+        assert_eq!(
+            area.map_path(&real.path().join("sub/file")),
+            Some(area.staged_dir().join("sub/file"))
+        );
+        assert_eq!(area.map_path(Path::new("/elsewhere")), None);
+    }
+
+    #[test]
+    fn test_stdin_approver_defaults_to_no() {
+        let manifest = ChangeManifest {
+            created: vec![PathBuf::from("a")],
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+
+        assert!(!StdinApprover::approve_with_io(&manifest, &mut Cursor::new(b"\n"), &mut output).unwrap());
+        assert!(StdinApprover::approve_with_io(&manifest, &mut Cursor::new(b"y\n"), &mut output).unwrap());
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("+ a"));
+        assert!(output.contains("Apply these changes?"));
+    }
+
+    #[test]
+    fn test_stdin_approver_skips_prompt_without_changes() {
+        let mut output = Vec::new();
+
+        let approved =
+            StdinApprover::approve_with_io(&ChangeManifest::default(), &mut Cursor::new(b"y\n"), &mut output).unwrap();
+
+        assert!(!approved);
+        assert!(String::from_utf8(output).unwrap().contains("no file changes"));
+    }
+}
@@ -0,0 +1,226 @@
+//! Starter commands shipped with ergo.
+//!
+//! A few vetted commands are embedded in the binary and written to the home
+//! bioma the first time ergo runs, so it is useful (and demos work offline)
+//! before any API key is set:
+//!
+//! - `uuid [COUNT]` prints random UUIDs
+//! - `timestamp [TIME]` prints the current time, or converts one, in the usual formats
+//! - `json-format [--compact]` pretty-prints the JSON read from stdin
+//!
+//! They are installed once: a removed starter command stays removed. Set
+//! `starter.install = false` (or `ERGO_NO_STARTER=1`, e.g. in a package's
+//! post-install) before the first run to start with an empty cache.
+
+use crate::command_cache::{CachePathResolver, CommandCache};
+use crate::llm_generator::{GeneratedCommand, PermissionRequest, UsageExample};
+use crate::output_processing::OutputFormat;
+use crate::providers::SystemTimeProvider;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Environment variable that keeps the starter commands from being installed.
+pub const NO_STARTER_ENV: &str = "ERGO_NO_STARTER";
+
+/// File in the config directory recording that the starter commands were installed.
+pub const MARKER_FILE: &str = "starter-installed";
+
+/// Starter command settings (the `[starter]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StarterConfig {
+    /// Whether the starter commands are written to the home bioma on the first run.
+    #[serde(default = "default_install")]
+    pub install: bool,
+}
+
+impl Default for StarterConfig {
+    fn default() -> Self {
+        Self {
+            install: default_install(),
+        }
+    }
+}
+
+fn default_install() -> bool {
+    true
+}
+
+/// A command embedded in the binary.
+#[derive(Debug, Clone, Copy)]
+pub struct StarterCommand {
+    /// The command name.
+    pub name: &'static str,
+    /// What the command does.
+    pub description: &'static str,
+    /// The script.
+    pub script: &'static str,
+    /// What it prints on stdout.
+    pub output_format: OutputFormat,
+    /// Invocations and what they do.
+    pub examples: &'static [(&'static str, &'static str)],
+}
+
+/// The starter commands. None of them needs a Deno permission.
+pub const STARTER_COMMANDS: &[StarterCommand] = &[
+    StarterCommand {
+        name: "uuid",
+        description: "Print random (v4) UUIDs",
+        script: include_str!("starter/uuid.ts"),
+        output_format: OutputFormat::Text,
+        examples: &[("ergo uuid", "Print a UUID"), ("ergo uuid 5", "Print five UUIDs")],
+    },
+    StarterCommand {
+        name: "timestamp",
+        description: "Print the current time, or convert a Unix or ISO 8601 time, in the usual formats",
+        script: include_str!("starter/timestamp.ts"),
+        output_format: OutputFormat::Text,
+        examples: &[
+            ("ergo timestamp", "Show the current time"),
+            ("ergo timestamp 1700000000", "Convert a Unix timestamp"),
+        ],
+    },
+    StarterCommand {
+        name: "json-format",
+        description: "Pretty-print the JSON read from stdin",
+        script: include_str!("starter/json-format.ts"),
+        output_format: OutputFormat::Json,
+        examples: &[
+            ("cat data.json | ergo json-format", "Pretty-print a file"),
+            ("cat data.json | ergo json-format --compact", "Print it on one line"),
+        ],
+    },
+];
+
+impl StarterCommand {
+    /// The command as it is cached.
+    pub fn command(&self) -> GeneratedCommand {
+        GeneratedCommand {
+            name: self.name.to_string(),
+            description: self.description.to_string(),
+            script_file: format!("{}.ts", self.name),
+            permissions: Vec::<PermissionRequest>::new(),
+            sensitive_output: false,
+            output_format: self.output_format,
+            examples: self
+                .examples
+                .iter()
+                .map(|(usage, explanation)| UsageExample {
+                    usage: usage.to_string(),
+                    explanation: explanation.to_string(),
+                })
+                .collect(),
+            assumptions: Vec::new(),
+            confidence: None,
+            platforms: Vec::new(),
+            variants: BTreeMap::new(),
+            provenance: None,
+            spec: None,
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+/// Whether `env` keeps the starter commands from being installed:
+/// [`NO_STARTER_ENV`] set to anything but empty, `0` or `false`.
+pub fn opted_out(env: impl Fn(&str) -> Option<String>) -> bool {
+    env(NO_STARTER_ENV).is_some_and(|value| !matches!(value.trim(), "" | "0" | "false"))
+}
+
+/// Stores the starter commands `cache` doesn't have yet.
+///
+/// Returns the names of the commands stored.
+///
+/// # Errors
+///
+/// Returns an error if the cache can't be read or written.
+pub async fn install(cache: &mut CommandCache) -> Result<Vec<&'static str>> {
+    let mut installed = Vec::new();
+    for starter in STARTER_COMMANDS {
+        if cache.get_command(starter.name).await?.is_some() {
+            continue;
+        }
+        cache.store_command(starter.name, &starter.command(), starter.script).await?;
+        installed.push(starter.name);
+    }
+    Ok(installed)
+}
+
+/// Installs the starter commands into the bioma at `bioma_dir`, unless
+/// `config_dir` records that they were installed before.
+///
+/// Returns the names of the commands stored.
+///
+/// # Errors
+///
+/// Returns an error if the bioma or the marker file can't be written.
+pub async fn install_once(config_dir: &Path, bioma_dir: &Path) -> Result<Vec<&'static str>> {
+    let marker = config_dir.join(MARKER_FILE);
+    if marker.exists() {
+        return Ok(Vec::new());
+    }
+    let resolver = BiomaResolver(bioma_dir.to_path_buf());
+    let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(SystemTimeProvider)).await?;
+    let installed = install(&mut cache).await?;
+    fs::create_dir_all(config_dir)?;
+    fs::write(&marker, format!("{}\n", env!("CARGO_PKG_VERSION")))?;
+    info!("Installed starter commands {:?} into {:?}", installed, bioma_dir);
+    Ok(installed)
+}
+
+/// Resolves to a single bioma, whatever the current directory.
+struct BiomaResolver(PathBuf);
+
+impl CachePathResolver for BiomaResolver {
+    fn get_write_dir(&self) -> Result<PathBuf> {
+        Ok(self.0.clone())
+    }
+
+    fn find_command(&self, _name: &str) -> Result<Option<GeneratedCommand>> {
+        Ok(None)
+    }
+
+    fn find_script(&self, _script_file: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_install_once_keeps_removed_commands_removed() {
+        let home = tempfile::tempdir().unwrap();
+        let (config_dir, bioma) = (home.path().join(".abiogenesis"), home.path().join(".abiogenesis/biomas"));
+
+        let installed = install_once(&config_dir, &bioma).await.unwrap();
+        assert_eq!(installed, vec!["uuid", "timestamp", "json-format"]);
+        assert!(bioma.join("uuid.ts").exists());
+        assert!(config_dir.join(MARKER_FILE).exists());
+
+        fs::remove_file(bioma.join("uuid.ts")).unwrap();
+        assert!(install_once(&config_dir, &bioma).await.unwrap().is_empty());
+        assert!(!bioma.join("uuid.ts").exists());
+    }
+
+    #[tokio::test]
+    async fn test_install_leaves_existing_commands_alone() {
+        let home = tempfile::tempdir().unwrap();
+        let resolver = BiomaResolver(home.path().to_path_buf());
+        let mut cache = CommandCache::with_providers(Box::new(resolver), Box::new(SystemTimeProvider))
+            .await
+            .unwrap();
+        let mut mine = STARTER_COMMANDS[0].command();
+        mine.description = "My own uuid".to_string();
+        cache.store_command("uuid", &mine, "console.log('mine')").await.unwrap();
+
+        assert_eq!(install(&mut cache).await.unwrap(), vec!["timestamp", "json-format"]);
+        assert_eq!(cache.get_command("uuid").await.unwrap().unwrap().description, "My own uuid");
+        assert!(!opted_out(|_: &str| None));
+        assert!(opted_out(|_: &str| Some("1".to_string())));
+    }
+}
@@ -0,0 +1,11 @@
+// Pretty-prints the JSON read from stdin: `cat data.json | json-format`,
+// or `json-format --compact` to print it on one line.
+const compact = Deno.args.includes("--compact");
+const input = await new Response(Deno.stdin.readable).text();
+try {
+  const value = JSON.parse(input);
+  console.log(compact ? JSON.stringify(value) : JSON.stringify(value, null, 2));
+} catch (error) {
+  console.error(`Invalid JSON: ${(error as Error).message}`);
+  Deno.exit(1);
+}
@@ -0,0 +1,21 @@
+// Prints the current time, or converts the time given, in the usual formats:
+// `timestamp`, `timestamp 1700000000`, `timestamp 2024-05-01T12:00:00Z`.
+const input = Deno.args[0];
+let date: Date;
+if (input === undefined) {
+  date = new Date();
+} else if (/^-?\d+(\.\d+)?$/.test(input)) {
+  const value = Number(input);
+  // Ten digits or fewer are seconds, more are milliseconds
+  date = new Date(Math.abs(value) < 1e11 ? value * 1000 : value);
+} else {
+  date = new Date(input);
+}
+if (isNaN(date.getTime())) {
+  console.error(`Not a time: ${input}`);
+  Deno.exit(1);
+}
+console.log(`ISO 8601:   ${date.toISOString()}`);
+console.log(`Local:      ${date.toString()}`);
+console.log(`Unix:       ${Math.floor(date.getTime() / 1000)}`);
+console.log(`Unix (ms):  ${date.getTime()}`);
@@ -0,0 +1,9 @@
+// Prints random (v4) UUIDs: `uuid` prints one, `uuid 5` prints five.
+const count = Number(Deno.args[0] ?? "1");
+if (!Number.isInteger(count) || count < 1) {
+  console.error("Usage: uuid [COUNT]");
+  Deno.exit(1);
+}
+for (let i = 0; i < count; i++) {
+  console.log(crypto.randomUUID());
+}
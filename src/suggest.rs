@@ -0,0 +1,386 @@
+//! Project-aware command suggestions for `ergo suggest`.
+//!
+//! [`ProjectContext::detect`] looks at the files in the current directory to
+//! work out the project's languages, frameworks and existing scripts
+//! (`package.json` scripts, `Makefile` targets, `deno.json` tasks). The model
+//! turns that summary into a handful of command ideas, the user picks some
+//! from a numbered list (see [`parse_selection`]) and ergo generates them all.
+
+use anyhow::{Result, anyhow, bail};
+use std::fs;
+use std::path::Path;
+
+/// Most scripts included in a project summary, to keep prompts small.
+const MAX_SCRIPTS: usize = 30;
+
+/// Longest script command included in a project summary.
+const MAX_SCRIPT_COMMAND_CHARS: usize = 120;
+
+/// Files whose presence reveals a language.
+const LANGUAGE_MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "Rust"),
+    ("package.json", "JavaScript"),
+    ("tsconfig.json", "TypeScript"),
+    ("deno.json", "TypeScript (Deno)"),
+    ("pyproject.toml", "Python"),
+    ("requirements.txt", "Python"),
+    ("go.mod", "Go"),
+    ("Gemfile", "Ruby"),
+    ("pom.xml", "Java"),
+    ("build.gradle", "Java"),
+    ("composer.json", "PHP"),
+];
+
+/// Files whose presence reveals a tool worth building commands around.
+const TOOL_MARKERS: &[(&str, &str)] = &[
+    ("Dockerfile", "Docker"),
+    ("docker-compose.yml", "Docker Compose"),
+    ("compose.yaml", "Docker Compose"),
+    (".github/workflows", "GitHub Actions"),
+];
+
+/// Dependencies whose presence reveals a framework, by manifest.
+const NPM_FRAMEWORKS: &[(&str, &str)] = &[
+    ("react", "React"),
+    ("next", "Next.js"),
+    ("vue", "Vue"),
+    ("svelte", "Svelte"),
+    ("@angular/core", "Angular"),
+    ("express", "Express"),
+    ("jest", "Jest"),
+    ("vitest", "Vitest"),
+];
+const CARGO_FRAMEWORKS: &[(&str, &str)] = &[
+    ("tokio", "Tokio"),
+    ("axum", "Axum"),
+    ("actix-web", "Actix Web"),
+    ("rocket", "Rocket"),
+    ("clap", "clap"),
+    ("bevy", "Bevy"),
+];
+const PYTHON_FRAMEWORKS: &[(&str, &str)] = &[
+    ("django", "Django"),
+    ("flask", "Flask"),
+    ("fastapi", "FastAPI"),
+    ("pytest", "pytest"),
+];
+
+/// A script the project already defines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectScript {
+    /// How the script is invoked, e.g. `npm run build` or `make test`.
+    pub invocation: String,
+    /// What it runs, if known.
+    pub command: Option<String>,
+}
+
+/// What ergo knows about the project in a directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectContext {
+    /// Languages, e.g. `Rust`, in detection order.
+    pub languages: Vec<String>,
+    /// Frameworks and tools, e.g. `React` or `Docker`.
+    pub frameworks: Vec<String>,
+    /// Scripts the project already defines.
+    pub scripts: Vec<ProjectScript>,
+}
+
+impl ProjectContext {
+    /// Inspects the project rooted at `dir`.
+    ///
+    /// Unreadable or malformed manifests are skipped rather than reported, so
+    /// a broken `package.json` still leaves the rest of the context.
+    pub fn detect(dir: &Path) -> Self {
+        let mut context = Self::default();
+        for (file, language) in LANGUAGE_MARKERS {
+            if dir.join(file).exists() {
+                push_unique(&mut context.languages, language);
+            }
+        }
+        for (file, tool) in TOOL_MARKERS {
+            if dir.join(file).exists() {
+                push_unique(&mut context.frameworks, tool);
+            }
+        }
+
+        if let Some(package) = read_json(&dir.join("package.json")) {
+            for section in ["dependencies", "devDependencies"] {
+                if let Some(deps) = package.get(section).and_then(|d| d.as_object()) {
+                    if deps.contains_key("typescript") {
+                        push_unique(&mut context.languages, "TypeScript");
+                    }
+                    detect_frameworks(&mut context.frameworks, NPM_FRAMEWORKS, |name| deps.contains_key(name));
+                }
+            }
+            context.scripts.extend(json_scripts(&package, "scripts", "npm run"));
+        }
+        if let Some(deno) = read_json(&dir.join("deno.json")) {
+            context.scripts.extend(json_scripts(&deno, "tasks", "deno task"));
+        }
+        if let Some(cargo) = fs::read_to_string(dir.join("Cargo.toml")).ok().and_then(|c| c.parse::<toml::Table>().ok()) {
+            let has_dependency = |name: &str| {
+                ["dependencies", "dev-dependencies"]
+                    .iter()
+                    .any(|section| cargo.get(*section).and_then(|d| d.as_table()).is_some_and(|d| d.contains_key(name)))
+            };
+            detect_frameworks(&mut context.frameworks, CARGO_FRAMEWORKS, has_dependency);
+        }
+        let python_requirements: String = ["requirements.txt", "pyproject.toml"]
+            .iter()
+            .filter_map(|file| fs::read_to_string(dir.join(file)).ok())
+            .collect::<Vec<_>>()
+            .join("\n")
+            .to_lowercase();
+        let python_words: Vec<&str> = python_requirements
+            .split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+            .collect();
+        detect_frameworks(&mut context.frameworks, PYTHON_FRAMEWORKS, |name| python_words.contains(&name));
+        if let Ok(makefile) = fs::read_to_string(dir.join("Makefile")) {
+            context.scripts.extend(makefile_targets(&makefile));
+        }
+
+        context.scripts.truncate(MAX_SCRIPTS);
+        context
+    }
+
+    /// Returns true if nothing was detected.
+    pub fn is_empty(&self) -> bool {
+        self.languages.is_empty() && self.frameworks.is_empty() && self.scripts.is_empty()
+    }
+
+    /// Describes the project for the suggestion prompt.
+    pub fn summary(&self) -> String {
+        let list = |items: &[String]| {
+            if items.is_empty() {
+                "(none detected)".to_string()
+            } else {
+                items.join(", ")
+            }
+        };
+        let mut summary = format!("Languages: {}\nFrameworks and tools: {}\nExisting scripts:", list(&self.languages), list(&self.frameworks));
+        if self.scripts.is_empty() {
+            summary.push_str(" (none)");
+        }
+        for script in &self.scripts {
+            match &script.command {
+                Some(command) => summary.push_str(&format!("\n- {}: {}", script.invocation, truncate(command))),
+                None => summary.push_str(&format!("\n- {}", script.invocation)),
+            }
+        }
+        summary
+    }
+}
+
+/// Parses a pick-list answer into 0-based indices of `count` items.
+///
+/// Accepts item numbers and ranges separated by commas or spaces
+/// (`1,3 5-7`), or `all`. An empty answer picks nothing.
+pub fn parse_selection(input: &str, count: usize) -> Result<Vec<usize>> {
+    let input = input.trim().to_lowercase();
+    if input == "all" || input == "a" {
+        return Ok((0..count).collect());
+    }
+    let number = |text: &str| -> Result<usize> {
+        let n: usize = text.trim().parse().map_err(|_| anyhow!("'{}' is not a number", text))?;
+        if n == 0 || n > count {
+            bail!("{} is not between 1 and {}", n, count);
+        }
+        Ok(n - 1)
+    };
+
+    let mut selected = Vec::new();
+    for part in input.split([',', ' ']).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (number(start)?, number(end)?);
+                if start > end {
+                    bail!("'{}' is not a valid range", part);
+                }
+                selected.extend(start..=end);
+            }
+            None => selected.push(number(part)?),
+        }
+    }
+    selected.sort_unstable();
+    selected.dedup();
+    Ok(selected)
+}
+
+fn push_unique(items: &mut Vec<String>, item: &str) {
+    if !items.iter().any(|existing| existing == item) {
+        items.push(item.to_string());
+    }
+}
+
+fn detect_frameworks(frameworks: &mut Vec<String>, known: &[(&str, &str)], has_dependency: impl Fn(&str) -> bool) {
+    for (dependency, framework) in known {
+        if has_dependency(dependency) {
+            push_unique(frameworks, framework);
+        }
+    }
+}
+
+fn read_json(path: &Path) -> Option<serde_json::Value> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// Scripts in the `key` object of a JSON manifest, e.g. `package.json` scripts.
+fn json_scripts(manifest: &serde_json::Value, key: &str, runner: &str) -> Vec<ProjectScript> {
+    manifest
+        .get(key)
+        .and_then(|scripts| scripts.as_object())
+        .map(|scripts| {
+            scripts
+                .iter()
+                .map(|(name, command)| ProjectScript {
+                    invocation: format!("{} {}", runner, name),
+                    command: command.as_str().map(str::to_string),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Explicit targets of a Makefile, with the first line of their recipe.
+///
+/// Special (`.PHONY`), pattern (`%.o`) and variable-named targets are skipped.
+fn makefile_targets(makefile: &str) -> Vec<ProjectScript> {
+    let lines: Vec<&str> = makefile.lines().collect();
+    let mut targets = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.starts_with(['\t', ' ', '#', '.']) {
+            continue;
+        }
+        let Some((names, rest)) = line.split_once(':') else {
+            continue;
+        };
+        if rest.starts_with('=') || names.contains(['=', '%', '$']) {
+            continue;
+        }
+        let recipe = lines
+            .get(i + 1)
+            .and_then(|next| next.strip_prefix('\t'))
+            .map(|command| command.trim().trim_start_matches('@').to_string());
+        for name in names.split_whitespace() {
+            targets.push(ProjectScript {
+                invocation: format!("make {}", name),
+                command: recipe.clone(),
+            });
+        }
+    }
+    targets
+}
+
+fn truncate(command: &str) -> String {
+    if command.chars().count() <= MAX_SCRIPT_COMMAND_CHARS {
+        return command.to_string();
+    }
+    format!("{}…", command.chars().take(MAX_SCRIPT_COMMAND_CHARS).collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detect_node_project() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("package.json"),
+            r#"{
+                "scripts": { "build": "tsc -p .", "test": "vitest run" },
+                "dependencies": { "react": "^18.0.0" },
+                "devDependencies": { "typescript": "^5.0.0", "vitest": "^1.0.0" }
+            }"#,
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("Dockerfile"), "FROM node:20\n").unwrap();
+
+        let context = ProjectContext::detect(temp_dir.path());
+
+        assert_eq!(context.languages, vec!["JavaScript", "TypeScript"]);
+        assert_eq!(context.frameworks, vec!["Docker", "React", "Vitest"]);
+        assert_eq!(
+            context.scripts,
+            vec![
+                ProjectScript {
+                    invocation: "npm run build".to_string(),
+                    command: Some("tsc -p .".to_string()),
+                },
+                ProjectScript {
+                    invocation: "npm run test".to_string(),
+                    command: Some("vitest run".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_detect_rust_and_python_frameworks() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join("Cargo.toml"),
+            "[package]\nname = \"app\"\n\n[dependencies]\naxum = \"0.7\"\ntokio = { version = \"1\" }\n",
+        )
+        .unwrap();
+        fs::write(temp_dir.path().join("requirements.txt"), "Flask==3.0\npytest-cov\n").unwrap();
+
+        let context = ProjectContext::detect(temp_dir.path());
+
+        assert_eq!(context.languages, vec!["Rust", "Python"]);
+        assert_eq!(context.frameworks, vec!["Tokio", "Axum", "Flask"]);
+    }
+
+    #[test]
+    fn test_detect_empty_directory_and_malformed_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(ProjectContext::detect(temp_dir.path()).is_empty());
+
+        fs::write(temp_dir.path().join("package.json"), "{ not json").unwrap();
+        let context = ProjectContext::detect(temp_dir.path());
+        assert_eq!(context.languages, vec!["JavaScript"]);
+        assert!(context.scripts.is_empty());
+    }
+
+    #[test]
+    fn test_makefile_targets() {
+        let makefile = "CC := gcc\n.PHONY: build test\n\nbuild:\n\t@cargo build --release\n\ntest lint: build\n\tcargo test\n%.o: %.c\n\t$(CC) -c $<\n";
+
+        let targets = makefile_targets(makefile);
+
+        let invocations: Vec<&str> = targets.iter().map(|t| t.invocation.as_str()).collect();
+        assert_eq!(invocations, vec!["make build", "make test", "make lint"]);
+        assert_eq!(targets[0].command.as_deref(), Some("cargo build --release"));
+        assert_eq!(targets[2].command.as_deref(), Some("cargo test"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let context = ProjectContext {
+            languages: vec!["Rust".to_string()],
+            frameworks: Vec::new(),
+            scripts: vec![ProjectScript {
+                invocation: "make test".to_string(),
+                command: Some("x".repeat(200)),
+            }],
+        };
+
+        let summary = context.summary();
+
+        assert!(summary.starts_with("Languages: Rust\nFrameworks and tools: (none detected)\nExisting scripts:\n- make test: xxx"));
+        assert!(summary.ends_with("x…"));
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(parse_selection("1,3", 5).unwrap(), vec![0, 2]);
+        assert_eq!(parse_selection(" 4-5 2 2 ", 5).unwrap(), vec![1, 3, 4]);
+        assert_eq!(parse_selection("all", 3).unwrap(), vec![0, 1, 2]);
+        assert!(parse_selection("", 3).unwrap().is_empty());
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("3-1", 3).is_err());
+        assert!(parse_selection("two", 3).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+//! Opt-in, anonymous usage statistics.
+//!
+//! Nothing is collected unless the user runs `ergo --telemetry on` (or sets
+//! `telemetry.enabled = true`). When enabled, ergo keeps a few counters in
+//! `~/.abiogenesis/usage_stats.json` and, at most once a day, posts them to
+//! `telemetry.endpoint` before starting over. The exact payload is built by
+//! [`StatsPayload::from_stats`] and shown by `ergo --telemetry status`.
+//!
+//! Intents, command names, arguments, scripts, output, paths, and API keys
+//! are never recorded or sent, and there is no user or machine identifier.
+
+use crate::command_router::IntentOutcome;
+use crate::error::ErgoError;
+use crate::http_client::HttpClient;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Minimum time between two stats pings (one day).
+pub const SEND_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Version of the payload format, bumped whenever a field is added.
+pub const PAYLOAD_SCHEMA: u32 = 1;
+
+/// Telemetry settings (the `[telemetry]` table in `config.toml`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    /// Whether usage statistics are collected and sent. Off by default.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Where stats are posted; nothing is sent while it is unset.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+/// Coarse reason a command didn't succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCategory {
+    /// A generated command exited with a non-zero code.
+    NonzeroExit,
+    /// The user pressed Ctrl-C.
+    Interrupted,
+    /// The user declined a command's permissions.
+    PermissionDenied,
+    /// Anything else: generation, network, or system command errors.
+    Error,
+}
+
+/// Something worth counting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageEvent {
+    /// A new command was generated.
+    Generated,
+    /// A cached command was run.
+    CacheHit,
+    /// A command was regenerated from `--nope` feedback.
+    Regenerated,
+    /// A command from PATH was run.
+    SystemCommand,
+    /// A command didn't succeed.
+    Failure(FailureCategory),
+}
+
+impl UsageEvent {
+    /// Returns the events describing the result of processing an intent.
+    pub fn from_result(result: &Result<IntentOutcome, ErgoError>) -> Vec<UsageEvent> {
+        match result {
+            Ok(outcome) => Self::from_outcome(outcome),
+            Err(e) => vec![Self::from_error(e)],
+        }
+    }
+
+    /// Returns the failure event for an error that stopped an intent.
+    pub fn from_error(error: &ErgoError) -> UsageEvent {
+        if matches!(error, ErgoError::Interrupted) {
+            UsageEvent::Failure(FailureCategory::Interrupted)
+        } else {
+            UsageEvent::Failure(FailureCategory::Error)
+        }
+    }
+
+    /// Returns the events describing a processed intent.
+    pub fn from_outcome(outcome: &IntentOutcome) -> Vec<UsageEvent> {
+        let mut events = vec![match outcome {
+            IntentOutcome::SystemExecuted { .. } => UsageEvent::SystemCommand,
+            IntentOutcome::CacheHit { .. } => UsageEvent::CacheHit,
+            IntentOutcome::Generated { .. } => UsageEvent::Generated,
+            IntentOutcome::Regenerated { .. } => UsageEvent::Regenerated,
+            IntentOutcome::PermissionDenied { .. } => UsageEvent::Failure(FailureCategory::PermissionDenied),
+        }];
+        if let Some(result) = outcome.execution() {
+            if result.interrupted {
+                events.push(UsageEvent::Failure(FailureCategory::Interrupted));
+            } else if !result.success {
+                events.push(UsageEvent::Failure(match result.exit_code {
+                    Some(_) => FailureCategory::NonzeroExit,
+                    None => FailureCategory::Error,
+                }));
+            }
+        }
+        events
+    }
+}
+
+/// Counters kept between pings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageStats {
+    /// When counting started (Unix timestamp).
+    #[serde(default)]
+    pub period_start: u64,
+    #[serde(default)]
+    pub generations: u32,
+    #[serde(default)]
+    pub cache_hits: u32,
+    #[serde(default)]
+    pub regenerations: u32,
+    #[serde(default)]
+    pub system_commands: u32,
+    #[serde(default)]
+    pub failures: BTreeMap<FailureCategory, u32>,
+}
+
+impl UsageStats {
+    /// Returns the path of the stats file.
+    pub fn default_path() -> Result<PathBuf> {
+        Ok(crate::config::Config::get_config_dir()?.join("usage_stats.json"))
+    }
+
+    /// Loads stats from `path`, starting fresh if the file is missing or
+    /// unreadable.
+    pub fn load(path: &Path, now: u64) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or(Self {
+                period_start: now,
+                ..Default::default()
+            })
+    }
+
+    /// Saves stats to `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Counts an event.
+    pub fn record(&mut self, event: UsageEvent) {
+        match event {
+            UsageEvent::Generated => self.generations += 1,
+            UsageEvent::CacheHit => self.cache_hits += 1,
+            UsageEvent::Regenerated => self.regenerations += 1,
+            UsageEvent::SystemCommand => self.system_commands += 1,
+            UsageEvent::Failure(category) => *self.failures.entry(category).or_default() += 1,
+        }
+    }
+
+    /// Returns true if nothing has been counted.
+    pub fn is_empty(&self) -> bool {
+        self.generations == 0
+            && self.cache_hits == 0
+            && self.regenerations == 0
+            && self.system_commands == 0
+            && self.failures.is_empty()
+    }
+}
+
+/// The complete stats ping. Every field sent is listed here.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatsPayload {
+    /// Payload format version ([`PAYLOAD_SCHEMA`]).
+    pub schema: u32,
+    /// ergo's version, e.g. `0.1.0`.
+    pub version: &'static str,
+    /// Operating system family, e.g. `linux` or `macos`.
+    pub os: &'static str,
+    /// CPU architecture, e.g. `x86_64`.
+    pub arch: &'static str,
+    /// Length of the counting period in whole days.
+    pub period_days: u64,
+    /// Number of commands generated.
+    pub generations: u32,
+    /// Number of cached commands run.
+    pub cache_hits: u32,
+    /// Number of commands regenerated with `--nope`.
+    pub regenerations: u32,
+    /// Number of PATH commands run through ergo.
+    pub system_commands: u32,
+    /// Number of failures per [`FailureCategory`].
+    pub failures: BTreeMap<FailureCategory, u32>,
+}
+
+impl StatsPayload {
+    /// Builds the payload for the counters in `stats`.
+    pub fn from_stats(stats: &UsageStats, now: u64) -> Self {
+        Self {
+            schema: PAYLOAD_SCHEMA,
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            period_days: now.saturating_sub(stats.period_start) / (24 * 60 * 60),
+            generations: stats.generations,
+            cache_hits: stats.cache_hits,
+            regenerations: stats.regenerations,
+            system_commands: stats.system_commands,
+            failures: stats.failures.clone(),
+        }
+    }
+}
+
+/// Counts `events` if telemetry is enabled; does nothing otherwise.
+pub fn record_events(config: &TelemetryConfig, path: &Path, events: &[UsageEvent], now: u64) -> Result<()> {
+    if !config.enabled || events.is_empty() {
+        return Ok(());
+    }
+    let mut stats = UsageStats::load(path, now);
+    for event in events {
+        stats.record(*event);
+    }
+    stats.save(path)
+}
+
+/// Posts the stats if telemetry is enabled, an endpoint is set, a day has
+/// passed since the last ping, and something was counted. Counting then
+/// starts over.
+///
+/// # Returns
+///
+/// `true` if a ping was sent.
+pub async fn send_if_due<H: HttpClient>(config: &TelemetryConfig, path: &Path, client: &H, now: u64) -> Result<bool> {
+    let Some(endpoint) = config.endpoint.as_deref().filter(|_| config.enabled) else {
+        return Ok(false);
+    };
+    let stats = UsageStats::load(path, now);
+    if stats.is_empty() || now.saturating_sub(stats.period_start) < SEND_INTERVAL_SECS {
+        return Ok(false);
+    }
+
+    let payload = serde_json::to_value(StatsPayload::from_stats(&stats, now))?;
+    if let Err(e) = client
+        .post_json(endpoint, &[("content-type", "application/json")], &payload)
+        .await
+    {
+        warn!("Failed to send usage stats: {}", e);
+        return Ok(false);
+    }
+
+    info!("Sent usage stats to {}", endpoint);
+    UsageStats {
+        period_start: now,
+        ..Default::default()
+    }
+    .save(path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use crate::llm_generator::GeneratedCommand;
+    use crate::testing::MockHttpClient;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    const DAY: u64 = SEND_INTERVAL_SECS;
+
+    fn enabled() -> TelemetryConfig {
+        TelemetryConfig {
+            enabled: true,
+            endpoint: Some("https://stats.invalid/ping".to_string()),
+        }
+    }
+
+    fn cache_hit(success: bool, exit_code: Option<i32>) -> Result<IntentOutcome, ErgoError> {
+        Ok(IntentOutcome::CacheHit {
+            command: GeneratedCommand {
+                name: "secret-project".to_string(),
+                description: "d".to_string(),
+                script_file: "s.ts".to_string(),
+                permissions: vec![],
+                sensitive_output: false,
+                output_format: crate::output_processing::OutputFormat::Text,
+                examples: vec![],
+                assumptions: vec![],
+                confidence: None,
+                platforms: vec![],
+                variants: Default::default(),
+                provenance: None,
+                spec: None,
+                extra: Default::default(),
+            },
+            result: ExecutionResult {
+                success,
+                exit_code,
+                signal: None,
+                duration: Duration::ZERO,
+                stdout: None,
+                stderr: None,
+                args: vec![],
+                cwd: PathBuf::new(),
+                script_hash: String::new(),
+                interrupted: false,
+                sensitive: false,
+                error: None,
+            },
+        })
+    }
+
+    #[test]
+    fn test_events_from_outcomes() {
+        assert_eq!(UsageEvent::from_result(&cache_hit(true, Some(0))), vec![UsageEvent::CacheHit]);
+        assert_eq!(
+            UsageEvent::from_result(&cache_hit(false, Some(2))),
+            vec![UsageEvent::CacheHit, UsageEvent::Failure(FailureCategory::NonzeroExit)]
+        );
+        assert_eq!(
+            UsageEvent::from_result(&Err(ErgoError::Interrupted)),
+            vec![UsageEvent::Failure(FailureCategory::Interrupted)]
+        );
+    }
+
+    #[test]
+    fn test_nothing_is_recorded_when_disabled() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("usage_stats.json");
+
+        record_events(&TelemetryConfig::default(), &path, &[UsageEvent::Generated], 0).unwrap();
+
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_payload_contains_only_counts() {
+        let mut stats = UsageStats::default();
+        stats.record(UsageEvent::Generated);
+        stats.record(UsageEvent::Failure(FailureCategory::PermissionDenied));
+
+        let payload = serde_json::to_value(StatsPayload::from_stats(&stats, 3 * DAY)).unwrap();
+
+        let fields: Vec<&str> = payload.as_object().unwrap().keys().map(String::as_str).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "arch",
+                "cache_hits",
+                "failures",
+                "generations",
+                "os",
+                "period_days",
+                "regenerations",
+                "schema",
+                "system_commands",
+                "version"
+            ]
+        );
+        assert_eq!(payload["period_days"], 3);
+        assert_eq!(payload["failures"]["permission_denied"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_if_due_sends_daily_and_resets() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("usage_stats.json");
+        let client = MockHttpClient::new().otherwise("");
+        record_events(&enabled(), &path, &UsageEvent::from_result(&cache_hit(true, Some(0))), 0).unwrap();
+
+        assert!(!send_if_due(&enabled(), &path, &client, DAY - 1).await.unwrap());
+        assert!(send_if_due(&enabled(), &path, &client, DAY).await.unwrap());
+
+        let requests = client.sent_to("stats.invalid");
+        assert_eq!(requests.len(), 1);
+        let body = requests[0].body.as_ref().unwrap();
+        assert_eq!(body["cache_hits"], 1);
+        assert!(!body.to_string().contains("secret-project"));
+        let stats = UsageStats::load(&path, 0);
+        assert!(stats.is_empty());
+        assert_eq!(stats.period_start, DAY);
+    }
+
+    #[tokio::test]
+    async fn test_send_if_due_needs_an_endpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("usage_stats.json");
+        let client = MockHttpClient::new().otherwise("");
+        let config = TelemetryConfig {
+            endpoint: None,
+            ..enabled()
+        };
+        record_events(&config, &path, &[UsageEvent::Generated], 0).unwrap();
+
+        assert!(!send_if_due(&config, &path, &client, 10 * DAY).await.unwrap());
+        assert!(client.sent().is_empty());
+    }
+}
@@ -0,0 +1,1326 @@
+//! Ready-made mocks for testing apps that embed abiogenesis.
+//!
+//! Available with the `testing` feature:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! abiogenesis = { version = "0.1", features = ["testing"] }
+//! ```
+//!
+//! [`TestRouter`] builds a [`CommandRouter`] wired to mocks: generated
+//! commands come from a [`MockGenerator`], processes are "run" by a
+//! [`MockProcessRunner`], consent is answered by a [`MockConsent`], and the
+//! cache lives in a scratch directory removed with the router. Nothing reads
+//! the user's configuration, calls a model, or spawns a process.
+//!
+//! ```no_run
+//! use abiogenesis::command_router::IntentOutcome;
+//! use abiogenesis::testing::{MockGenerator, MockProcessRunner, TestRouter};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let runner = MockProcessRunner::new().with_output("deno", "Hello!\n", 0);
+//! let mut router = TestRouter::new()
+//!     .generator(MockGenerator::new().with_command("hello", "console.log('Hello!')", &[]))
+//!     .runner(runner.clone())
+//!     .build()
+//!     .await?;
+//!
+//! let outcome = router.process_intent(vec!["hello".to_string()]).await?;
+//! assert!(matches!(outcome, IntentOutcome::Generated { .. }));
+//! assert_eq!(runner.calls()[0][0], "deno");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! To test code that talks to a model or webhook with the real request
+//! building and response parsing, give it a [`MockHttpClient`] with scripted
+//! responses, errors and per-URL routes, and check the requests it recorded.
+//!
+//! Every mock is cheap to clone and clones share their state, so a test can
+//! keep a clone to inspect what the router did with the one it was given.
+
+use crate::command_cache::{CachePathResolver, CommandCache, PermissionConsent};
+use crate::command_router::CommandRouter;
+use crate::executor::{Executor, ProcessRunner};
+use crate::http_client::{HttpClient, HttpRequest, HttpResponse};
+use crate::llm_generator::{
+    CommandGenerator, FeedbackRequest, GeneratedCommand, GenerationPlan, GenerationResult, PermissionRequest, PlanRequest,
+};
+use crate::permission_ui::{ConsentProvider, PermissionUI};
+use crate::providers::TimeProvider;
+use crate::validation::ValidationPipeline;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output};
+use std::sync::{Arc, Mutex};
+
+/// Timestamp the mocks report as "now" unless told otherwise.
+pub const TEST_TIME: u64 = 1_700_000_000;
+
+/// Builds a command with the given Deno permission flags, each with a
+/// placeholder reason.
+pub fn command(name: &str, permissions: &[&str]) -> GeneratedCommand {
+    GeneratedCommand {
+        name: name.to_string(),
+        description: format!("Test command '{}'", name),
+        script_file: format!("{}.ts", name),
+        permissions: permissions
+            .iter()
+            .map(|permission| PermissionRequest {
+                permission: permission.to_string(),
+                reason: "Needed by the test".to_string(),
+            })
+            .collect(),
+        sensitive_output: false,
+        output_format: crate::output_processing::OutputFormat::Text,
+        examples: Vec::new(),
+        assumptions: Vec::new(),
+        confidence: None,
+        platforms: Vec::new(),
+        variants: BTreeMap::new(),
+        provenance: None,
+        spec: None,
+        extra: BTreeMap::new(),
+    }
+}
+
+// =============================================================================
+// Generator
+// =============================================================================
+
+/// A [`CommandGenerator`] answering from commands registered up front.
+///
+/// Unknown requests fail, naming what was asked for.
+#[derive(Clone, Default)]
+pub struct MockGenerator {
+    commands: Arc<Mutex<HashMap<String, (GeneratedCommand, String)>>>,
+    descriptions: Arc<Mutex<HashMap<String, String>>>,
+    regenerated: Arc<Mutex<HashMap<String, (GeneratedCommand, String)>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockGenerator {
+    /// Creates a generator that knows no commands.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers requests for `name` with `script` and `permissions`.
+    pub fn with_command(self, name: &str, script: &str, permissions: &[&str]) -> Self {
+        self.commands
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), (command(name, permissions), script.to_string()));
+        self
+    }
+
+    /// Answers the natural language `description` with the command `name`,
+    /// which must also be registered with [`Self::with_command`].
+    pub fn with_description(self, description: &str, name: &str) -> Self {
+        self.descriptions
+            .lock()
+            .unwrap()
+            .insert(description.to_string(), name.to_string());
+        self
+    }
+
+    /// Answers `--nope` feedback on `name` with `script` and `permissions`.
+    pub fn with_regenerated(self, name: &str, script: &str, permissions: &[&str]) -> Self {
+        self.regenerated
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), (command(name, permissions), script.to_string()));
+        self
+    }
+
+    /// The requests received so far, e.g. `generate hello`,
+    /// `describe show today's date`, `regenerate hello: louder` or
+    /// `model claude-big` (from [`CommandGenerator::set_model`]), or
+    /// `plan hello` and `implement hello` (with `--plan`).
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The name of the command registered for `description`.
+    fn described(&self, description: &str) -> Result<String> {
+        self.descriptions
+            .lock()
+            .unwrap()
+            .get(description)
+            .cloned()
+            .ok_or_else(|| anyhow!("MockGenerator has no command for '{}'", description))
+    }
+
+    fn answer(&self, known: &Mutex<HashMap<String, (GeneratedCommand, String)>>, name: &str) -> Result<GenerationResult> {
+        let known = known.lock().unwrap();
+        let (command, script_content) = known
+            .get(name)
+            .ok_or_else(|| anyhow!("MockGenerator has no command '{}'", name))?;
+        Ok(GenerationResult {
+            command: command.clone(),
+            script_content: script_content.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl CommandGenerator for MockGenerator {
+    async fn generate_command(&self, command_name: &str, _args: &[String]) -> Result<GenerationResult> {
+        self.requests.lock().unwrap().push(format!("generate {}", command_name));
+        self.answer(&self.commands, command_name)
+    }
+
+    async fn generate_command_from_description(&self, description: &str) -> Result<GenerationResult> {
+        self.requests.lock().unwrap().push(format!("describe {}", description));
+        self.answer(&self.commands, &self.described(description)?)
+    }
+
+    async fn regenerate_command_with_feedback(&self, request: &FeedbackRequest<'_>) -> Result<GenerationResult> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push(format!("regenerate {}: {}", request.command_name, request.user_feedback));
+        self.answer(&self.regenerated, request.command_name)
+    }
+
+    async fn plan_command(&self, request: PlanRequest<'_>) -> Result<GenerationPlan> {
+        self.requests.lock().unwrap().push(format!("plan {}", request));
+        Ok(GenerationPlan {
+            summary: format!("Do {}", request),
+            steps: vec![format!("Implement {}", request)],
+            ..GenerationPlan::default()
+        })
+    }
+
+    async fn generate_from_plan(&self, request: PlanRequest<'_>, plan: &GenerationPlan) -> Result<GenerationResult> {
+        let mut line = format!("implement {}", request);
+        for change in &plan.changes {
+            line.push_str(&format!(" (change: {})", change));
+        }
+        self.requests.lock().unwrap().push(line);
+        match request {
+            PlanRequest::Named { name, .. } => self.answer(&self.commands, name),
+            PlanRequest::Described(description) => self.answer(&self.commands, &self.described(description)?),
+        }
+    }
+
+    fn set_model(&mut self, model: &str) -> Result<()> {
+        self.requests.lock().unwrap().push(format!("model {}", model));
+        Ok(())
+    }
+}
+
+// =============================================================================
+// Cache paths
+// =============================================================================
+
+/// A [`CachePathResolver`] writing to a scratch directory (removed when the
+/// resolver is dropped) and finding commands registered up front, as if they
+/// were cached in a parent directory.
+pub struct MockPathResolver {
+    dir: tempfile::TempDir,
+    commands: HashMap<String, GeneratedCommand>,
+    scripts: HashMap<String, String>,
+}
+
+impl MockPathResolver {
+    /// Creates a resolver with a fresh scratch directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the scratch directory can't be created.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            dir: tempfile::Builder::new().prefix("abiogenesis-test-").tempdir()?,
+            commands: HashMap::new(),
+            scripts: HashMap::new(),
+        })
+    }
+
+    /// Makes `command` findable, with `script` as its script.
+    pub fn with_command(mut self, command: GeneratedCommand, script: &str) -> Self {
+        self.scripts.insert(command.script_file.clone(), script.to_string());
+        self.commands.insert(command.name.clone(), command);
+        self
+    }
+
+    /// The scratch directory new commands are written to.
+    pub fn write_dir(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl CachePathResolver for MockPathResolver {
+    fn get_write_dir(&self) -> Result<PathBuf> {
+        Ok(self.dir.path().to_path_buf())
+    }
+
+    fn find_command(&self, name: &str) -> Result<Option<GeneratedCommand>> {
+        Ok(self.commands.get(name).cloned())
+    }
+
+    fn find_script(&self, script_file: &str) -> Result<Option<String>> {
+        Ok(self.scripts.get(script_file).cloned())
+    }
+}
+
+// =============================================================================
+// Processes
+// =============================================================================
+
+/// Exit status with `code`.
+fn exit_status(code: i32) -> ExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        ExitStatus::from_raw(code << 8)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::ExitStatusExt;
+        ExitStatus::from_raw(code as u32)
+    }
+}
+
+/// A [`ProcessRunner`] that records invocations instead of spawning processes.
+///
+/// Only registered programs exist; `deno` is registered from the start and
+/// prints nothing. Every run of a program prints its registered output and
+/// exits with its registered code.
+#[derive(Clone)]
+pub struct MockProcessRunner {
+    programs: Arc<Mutex<HashMap<String, (String, i32)>>>,
+    calls: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl Default for MockProcessRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockProcessRunner {
+    /// Creates a runner where only `deno` exists.
+    pub fn new() -> Self {
+        let runner = Self {
+            programs: Arc::default(),
+            calls: Arc::default(),
+        };
+        runner.with_output("deno", "", 0)
+    }
+
+    /// Makes `program` exist, printing `stdout` and exiting with `code`.
+    pub fn with_output(self, program: &str, stdout: &str, code: i32) -> Self {
+        self.programs
+            .lock()
+            .unwrap()
+            .insert(program.to_string(), (stdout.to_string(), code));
+        self
+    }
+
+    /// Makes `program` missing.
+    pub fn without(self, program: &str) -> Self {
+        self.programs.lock().unwrap().remove(program);
+        self
+    }
+
+    /// Every invocation so far, as program followed by arguments.
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl ProcessRunner for MockProcessRunner {
+    fn run(&self, program: &str, args: &[&str]) -> Result<Output> {
+        let mut call = vec![program.to_string()];
+        call.extend(args.iter().map(|a| a.to_string()));
+        self.calls.lock().unwrap().push(call);
+        let programs = self.programs.lock().unwrap();
+        let (stdout, code) = programs
+            .get(program)
+            .ok_or_else(|| anyhow!("MockProcessRunner has no program '{}'", program))?;
+        Ok(Output {
+            status: exit_status(*code),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        })
+    }
+
+    fn run_streaming(
+        &self,
+        _dir: Option<&Path>,
+        program: &str,
+        args: &[&str],
+        stdout: &mut (dyn Write + Send),
+        stderr: &mut (dyn Write + Send),
+    ) -> Result<ExitStatus> {
+        let output = self.run(program, args)?;
+        stdout.write_all(&output.stdout)?;
+        stderr.write_all(&output.stderr)?;
+        Ok(output.status)
+    }
+
+    fn run_interactive(&self, _dir: Option<&Path>, program: &str, args: &[&str]) -> Result<ExitStatus> {
+        self.run(program, args).map(|output| output.status)
+    }
+
+    fn run_with_input(&self, program: &str, args: &[&str], _input: &[u8]) -> Result<ExitStatus> {
+        self.run(program, args).map(|output| output.status)
+    }
+
+    fn program_exists(&self, program: &str) -> bool {
+        self.programs.lock().unwrap().contains_key(program)
+    }
+}
+
+// =============================================================================
+// HTTP
+// =============================================================================
+
+/// A scripted answer of [`MockHttpClient`]: a response or an error message.
+type Answer = Result<HttpResponse, String>;
+
+/// A URL fragment and the answers queued for the URLs containing it.
+type Route = (String, VecDeque<Answer>);
+
+/// An [`HttpClient`] answering with scripted responses, recording every
+/// request it gets.
+///
+/// Each request is answered by, in order of precedence:
+/// 1. the next response queued for a URL it contains ([`MockHttpClient::on`]),
+/// 2. the next response queued for any URL ([`MockHttpClient::with_response`]),
+/// 3. the fallback response ([`MockHttpClient::otherwise`]),
+///
+/// and fails if there is none. Queued responses answer once, so a failure
+/// followed by a success scripts a retry:
+///
+/// ```no_run
+/// use abiogenesis::testing::MockHttpClient;
+///
+/// let client = MockHttpClient::new()
+///     .on_error("api.anthropic.com", "connection reset")
+///     .on("api.anthropic.com", r#"{"content": []}"#)
+///     .otherwise_status(404, "not found");
+/// ```
+///
+/// Useful to test the real prompt building and parsing of
+/// [`crate::llm_generator::LlmGenerator`] without a network.
+#[derive(Clone, Default)]
+pub struct MockHttpClient {
+    routes: Arc<Mutex<Vec<Route>>>,
+    responses: Arc<Mutex<VecDeque<Answer>>>,
+    fallback: Arc<Mutex<Option<Answer>>>,
+    requests: Arc<Mutex<Vec<HttpRequest>>>,
+}
+
+impl MockHttpClient {
+    /// Creates a client with no responses queued.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a successful (200) response body.
+    pub fn with_response(self, body: &str) -> Self {
+        self.with_http_response(HttpResponse::new(200, body))
+    }
+
+    /// Queues a response with `status` and `body`.
+    pub fn with_status(self, status: u16, body: &str) -> Self {
+        self.with_http_response(HttpResponse::new(status, body))
+    }
+
+    /// Queues a complete response, e.g. one with headers.
+    pub fn with_http_response(self, response: HttpResponse) -> Self {
+        self.responses.lock().unwrap().push_back(Ok(response));
+        self
+    }
+
+    /// Queues a failed request.
+    pub fn with_error(self, message: &str) -> Self {
+        self.responses.lock().unwrap().push_back(Err(message.to_string()));
+        self
+    }
+
+    /// Queues a successful (200) response for requests to URLs containing `url`.
+    pub fn on(self, url: &str, body: &str) -> Self {
+        self.on_response(url, HttpResponse::new(200, body))
+    }
+
+    /// Queues a complete response for requests to URLs containing `url`.
+    pub fn on_response(self, url: &str, response: HttpResponse) -> Self {
+        self.route(url, Ok(response))
+    }
+
+    /// Queues a failed request for URLs containing `url`.
+    pub fn on_error(self, url: &str, message: &str) -> Self {
+        self.route(url, Err(message.to_string()))
+    }
+
+    fn route(self, url: &str, answer: Answer) -> Self {
+        {
+            let mut routes = self.routes.lock().unwrap();
+            match routes.iter_mut().find(|(pattern, _)| pattern == url) {
+                Some((_, queue)) => queue.push_back(answer),
+                None => routes.push((url.to_string(), VecDeque::from([answer]))),
+            }
+        }
+        self
+    }
+
+    /// Answers every request nothing else is queued for with a successful
+    /// (200) `body`.
+    pub fn otherwise(self, body: &str) -> Self {
+        self.otherwise_status(200, body)
+    }
+
+    /// Answers every request nothing else is queued for with `status` and `body`.
+    pub fn otherwise_status(self, status: u16, body: &str) -> Self {
+        *self.fallback.lock().unwrap() = Some(Ok(HttpResponse::new(status, body)));
+        self
+    }
+
+    /// The URL and JSON body (`null` if none) of every request so far.
+    pub fn requests(&self) -> Vec<(String, Value)> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| (request.url.clone(), request.body.clone().unwrap_or(Value::Null)))
+            .collect()
+    }
+
+    /// Every request so far, with its method, headers and timeout.
+    pub fn sent(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The requests so far to URLs containing `url`.
+    pub fn sent_to(&self, url: &str) -> Vec<HttpRequest> {
+        self.sent().into_iter().filter(|request| request.url.contains(url)).collect()
+    }
+
+    /// How many queued responses haven't been used, to check a test
+    /// exercised every request it scripted.
+    pub fn pending(&self) -> usize {
+        let routed: usize = self.routes.lock().unwrap().iter().map(|(_, queue)| queue.len()).sum();
+        routed + self.responses.lock().unwrap().len()
+    }
+
+    fn answer(&self, request: HttpRequest) -> Result<HttpResponse> {
+        let url = request.url.clone();
+        self.requests.lock().unwrap().push(request);
+        let routed = self
+            .routes
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|(pattern, queue)| url.contains(pattern.as_str()) && !queue.is_empty())
+            .and_then(|(_, queue)| queue.pop_front());
+        let answer = routed
+            .or_else(|| self.responses.lock().unwrap().pop_front())
+            .or_else(|| self.fallback.lock().unwrap().clone());
+        match answer {
+            Some(Ok(response)) => Ok(response),
+            Some(Err(message)) => Err(anyhow!(message)),
+            None => Err(anyhow!("MockHttpClient has no response queued for {}", url)),
+        }
+    }
+}
+
+#[async_trait]
+impl HttpClient for MockHttpClient {
+    async fn post_json(&self, url: &str, headers: &[(&str, &str)], body: &Value) -> Result<String> {
+        let request = HttpRequest::post_json(url, body.clone()).headers(headers);
+        Ok(self.answer(request)?.body)
+    }
+
+    async fn send(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        self.answer(request.clone())
+    }
+}
+
+// =============================================================================
+// Consent and time
+// =============================================================================
+
+/// A [`ConsentProvider`] giving the same answer every time.
+#[derive(Clone)]
+pub struct MockConsent {
+    consent: PermissionConsent,
+    subjects: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockConsent {
+    /// Answers every request with `consent`.
+    pub fn always(consent: PermissionConsent) -> Self {
+        Self {
+            consent,
+            subjects: Arc::default(),
+        }
+    }
+
+    /// What consent was asked for so far, e.g. "command 'weather'".
+    pub fn subjects(&self) -> Vec<String> {
+        self.subjects.lock().unwrap().clone()
+    }
+}
+
+impl ConsentProvider for MockConsent {
+    fn choose(&self, subject: &str, _output: &mut dyn Write) -> Result<PermissionConsent> {
+        self.subjects.lock().unwrap().push(subject.to_string());
+        Ok(self.consent.clone())
+    }
+}
+
+/// A [`TimeProvider`] stuck at one timestamp.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTime(pub u64);
+
+impl TimeProvider for FixedTime {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
+
+// =============================================================================
+// Router
+// =============================================================================
+
+/// Builds a [`CommandRouter`] wired to mocks.
+///
+/// Unless replaced, the generator knows no commands, only `deno` exists, and
+/// every permission request is accepted once. Generated commands aren't
+/// validated (see [`TestRouter::validation`]). The last execution is not
+/// saved, so `--nope` (which reads it from the user's config directory) is
+/// out of reach of test routers.
+pub struct TestRouter {
+    generator: Box<dyn CommandGenerator>,
+    runner: Arc<dyn ProcessRunner>,
+    consent: Box<dyn ConsentProvider>,
+    resolver: Option<MockPathResolver>,
+    time: u64,
+    verbose: bool,
+    validation: Option<ValidationPipeline>,
+}
+
+impl Default for TestRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestRouter {
+    /// Starts a builder with the defaults above.
+    pub fn new() -> Self {
+        Self {
+            generator: Box::new(MockGenerator::new()),
+            runner: Arc::new(MockProcessRunner::new()),
+            consent: Box::new(MockConsent::always(PermissionConsent::AcceptOnce)),
+            resolver: None,
+            time: TEST_TIME,
+            verbose: false,
+            validation: None,
+        }
+    }
+
+    /// Generates commands with `generator`.
+    pub fn generator(mut self, generator: impl CommandGenerator + 'static) -> Self {
+        self.generator = Box::new(generator);
+        self
+    }
+
+    /// Runs processes with `runner`.
+    pub fn runner(mut self, runner: impl ProcessRunner + 'static) -> Self {
+        self.runner = Arc::new(runner);
+        self
+    }
+
+    /// Decides on permission requests with `consent`.
+    pub fn consent(mut self, consent: impl ConsentProvider + 'static) -> Self {
+        self.consent = Box::new(consent);
+        self
+    }
+
+    /// Resolves cached commands with `resolver`, e.g. to start with some.
+    pub fn resolver(mut self, resolver: MockPathResolver) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Reports `time` as the current timestamp.
+    pub fn time(mut self, time: u64) -> Self {
+        self.time = time;
+        self
+    }
+
+    /// Enables verbose output.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Validates generated commands with `validation` before caching them.
+    pub fn validation(mut self, validation: ValidationPipeline) -> Self {
+        self.validation = Some(validation);
+        self
+    }
+
+    /// Creates the router.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the scratch cache directory can't be created.
+    pub async fn build(self) -> Result<CommandRouter> {
+        let resolver = match self.resolver {
+            Some(resolver) => resolver,
+            None => MockPathResolver::new()?,
+        };
+        let working_dir = resolver.write_dir().to_path_buf();
+        let cache = CommandCache::with_providers(Box::new(resolver), Box::new(FixedTime(self.time))).await?;
+        let executor = Executor::new(self.verbose)
+            .with_process_runner(self.runner)
+            .with_working_dir(working_dir)
+            .with_context_saving(false);
+        let permission_ui =
+            PermissionUI::with_time_provider(self.verbose, Box::new(FixedTime(self.time))).with_consent_provider(self.consent);
+        let mut router = CommandRouter::from_parts(cache, self.generator, executor, permission_ui, self.verbose);
+        if let Some(validation) = self.validation {
+            router.set_validation(validation);
+        }
+        Ok(router)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_router::{IntentOutcome, PrepareOutcome};
+    use crate::error::ErgoError;
+    use crate::progress::RouterEvent;
+    use crate::security_review::SafetyConfig;
+    use crate::validation::{FailureAction, ValidationConfig};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_router_generates_and_runs_with_mocks() {
+        let runner = MockProcessRunner::new().with_output("deno", "Hello!\n", 0);
+        let consent = MockConsent::always(PermissionConsent::AcceptOnce);
+        let generator = MockGenerator::new().with_command("hello", "console.log('Hello!')", &["--allow-env"]);
+        let mut router = TestRouter::new()
+            .generator(generator.clone())
+            .runner(runner.clone())
+            .consent(consent.clone())
+            .build()
+            .await
+            .unwrap();
+
+        let outcome = router.process_intent(vec!["hello".to_string(), "world".to_string()]).await.unwrap();
+
+        let IntentOutcome::Generated { command, result } = outcome else {
+            panic!("expected a generated command, got {:?}", outcome);
+        };
+        assert_eq!(command.name, "hello");
+        assert!(result.success);
+        assert_eq!(generator.requests(), vec!["generate hello"]);
+        assert_eq!(consent.subjects(), vec!["command 'hello'"]);
+        let deno = runner.calls().into_iter().find(|call| call[0] == "deno").unwrap();
+        assert!(deno.contains(&"--allow-env".to_string()));
+        assert_eq!(deno.last().unwrap(), "world");
+    }
+
+    #[tokio::test]
+    async fn test_chosen_model_reaches_the_generator() {
+        let generator = MockGenerator::new().with_command("hello", "console.log('Hello!')", &[]);
+        let mut router = TestRouter::new()
+            .generator(generator.clone())
+            .consent(MockConsent::always(PermissionConsent::AcceptOnce))
+            .build()
+            .await
+            .unwrap();
+
+        router.set_model("claude-big").unwrap();
+        router.process_intent(vec!["hello".to_string()]).await.unwrap();
+
+        assert_eq!(generator.requests(), vec!["model claude-big", "generate hello"]);
+    }
+
+    #[tokio::test]
+    async fn test_plan_is_approved_before_generating() {
+        let generator = MockGenerator::new().with_command("hello", "console.log('Hello!')", &[]);
+        let mut router = TestRouter::new()
+            .generator(generator.clone())
+            .consent(MockConsent::always(PermissionConsent::AcceptOnce))
+            .build()
+            .await
+            .unwrap();
+        router.set_plan_first(true);
+
+        let outcome = router.process_intent(vec!["hello".to_string(), "world".to_string()]).await.unwrap();
+
+        assert!(matches!(outcome, IntentOutcome::Generated { .. }));
+        assert_eq!(generator.requests(), vec!["plan hello world", "implement hello world"]);
+
+        let declined = MockGenerator::new().with_command("bye", "console.log('Bye!')", &[]);
+        let mut router = TestRouter::new()
+            .generator(declined.clone())
+            .consent(MockConsent::always(PermissionConsent::Denied))
+            .build()
+            .await
+            .unwrap();
+        router.set_plan_first(true);
+
+        let error = router.process_intent(vec!["bye".to_string()]).await.unwrap_err();
+
+        assert!(error.to_string().starts_with("Cancelled: the plan for 'bye' wasn't approved"));
+        assert_eq!(declined.requests(), vec!["plan bye"]);
+    }
+
+    #[tokio::test]
+    async fn test_router_runs_mock_system_programs_and_cached_commands() {
+        let runner = MockProcessRunner::new().with_output("ls", "a.txt\n", 0);
+        let resolver = MockPathResolver::new().unwrap().with_command(command("greet", &[]), "console.log('hi')");
+        let generator = MockGenerator::new();
+        let mut router = TestRouter::new()
+            .generator(generator.clone())
+            .runner(runner.clone())
+            .resolver(resolver)
+            .build()
+            .await
+            .unwrap();
+
+        let listed = router.process_intent(vec!["ls".to_string(), "-la".to_string()]).await.unwrap();
+        let greeted = router.process_intent(vec!["greet".to_string()]).await.unwrap();
+
+        assert!(matches!(listed, IntentOutcome::SystemExecuted { .. }));
+        assert!(matches!(greeted, IntentOutcome::CacheHit { .. }));
+        assert_eq!(runner.calls()[0], vec!["ls", "-la"]);
+        assert!(generator.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_command_that_cannot_start_reports_why() {
+        let resolver = MockPathResolver::new().unwrap().with_command(command("greet", &[]), "console.log('hi')");
+        let mut router = TestRouter::new()
+            .runner(MockProcessRunner::new().without("deno"))
+            .resolver(resolver)
+            .build()
+            .await
+            .unwrap();
+
+        let outcome = router.process_intent(vec!["greet".to_string()]).await.unwrap();
+
+        let result = outcome.execution().unwrap();
+        assert!(!result.success);
+        assert!(result.error.as_deref().unwrap().contains("Deno is not installed"));
+    }
+
+    #[tokio::test]
+    async fn test_router_reports_progress_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let mut router = TestRouter::new()
+            .generator(MockGenerator::new().with_command("hello", "console.log('Hello!')", &[]))
+            .build()
+            .await
+            .unwrap()
+            .with_progress(move |event: &RouterEvent| {
+                let name = match event {
+                    RouterEvent::GenerationStarted { .. } => "generating",
+                    RouterEvent::CacheHit { .. } => "cached",
+                    RouterEvent::ConsentRequested { .. } => "consent",
+                    RouterEvent::Executing { .. } => "executing",
+                    RouterEvent::Finished { result, .. } if result.success => "succeeded",
+                    _ => "other",
+                };
+                recorded.lock().unwrap().push(name);
+            });
+
+        router.process_intent(vec!["hello".to_string()]).await.unwrap();
+        router.process_intent(vec!["hello".to_string()]).await.unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["generating", "consent", "executing", "succeeded", "cached", "executing", "succeeded"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_workflow_reports_generation_and_steps_as_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let generator = MockGenerator::new()
+            .with_command("greet", "console.log('hi')", &[])
+            .with_description("Create a command named 'greet' that does this: say hi", "greet");
+        let mut router = TestRouter::new()
+            .generator(generator)
+            .build()
+            .await
+            .unwrap()
+            .with_progress(move |event: &RouterEvent| match event {
+                RouterEvent::WorkflowGeneration { command_name, .. } => {
+                    recorded.lock().unwrap().push(format!("generating {}", command_name))
+                }
+                RouterEvent::StepStarted { command_line } => recorded.lock().unwrap().push(command_line.clone()),
+                _ => {}
+            });
+        let workflow =
+            crate::workflow::Workflow::parse("steps:\n  - command: greet\n    description: say hi\n    args: [Ada]\n")
+                .unwrap();
+
+        router.process_workflow(&workflow).await.unwrap();
+
+        assert_eq!(*events.lock().unwrap(), vec!["generating greet", "greet Ada"]);
+    }
+
+    #[tokio::test]
+    async fn test_denied_consent_does_not_run_the_command() {
+        let runner = MockProcessRunner::new();
+        let mut router = TestRouter::new()
+            .generator(MockGenerator::new().with_command("wipe", "Deno.remove('/')", &["--allow-write"]))
+            .runner(runner.clone())
+            .consent(MockConsent::always(PermissionConsent::Denied))
+            .build()
+            .await
+            .unwrap();
+
+        let outcome = router.process_intent(vec!["wipe".to_string()]).await.unwrap();
+
+        assert!(matches!(outcome, IntentOutcome::PermissionDenied { .. }));
+        assert!(runner.calls().iter().all(|call| call[0] != "deno"));
+    }
+
+    #[tokio::test]
+    async fn test_safety_rails_block_catastrophic_scripts_despite_consent() {
+        let runner = MockProcessRunner::new();
+        let consent = MockConsent::always(PermissionConsent::AcceptForever);
+        let script = "await Deno.remove(Deno.env.get('HOME'), { recursive: true });";
+        let mut router = TestRouter::new()
+            .generator(MockGenerator::new().with_command("tidy", script, &["--allow-write", "--allow-env"]))
+            .runner(runner.clone())
+            .consent(consent.clone())
+            .build()
+            .await
+            .unwrap();
+
+        let err = router.process_intent(vec!["tidy".to_string()]).await.unwrap_err();
+
+        assert!(matches!(err, ErgoError::PermissionDenied(_)));
+        assert!(err.to_string().contains("Recursively deletes the home directory"));
+        assert!(consent.subjects().is_empty());
+        assert!(runner.calls().iter().all(|call| call[0] != "deno"));
+
+        router.set_safety_override(true);
+        let outcome = router.process_intent(vec!["tidy".to_string()]).await.unwrap();
+        assert!(matches!(outcome, IntentOutcome::CacheHit { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_bioma_always_asks_for_consent() {
+        let consent = MockConsent::always(PermissionConsent::AcceptForever);
+        let resolver = MockPathResolver::new().unwrap();
+        let bioma = resolver.write_dir().to_path_buf();
+        let mut router = TestRouter::new()
+            .generator(MockGenerator::new().with_command("greet", "console.log('hi')", &[]))
+            .consent(consent.clone())
+            .resolver(resolver)
+            .build()
+            .await
+            .unwrap();
+        router.set_trust(crate::trust::TrustConfig {
+            untrusted: vec![bioma.display().to_string()],
+            ..Default::default()
+        });
+
+        router.process_intent(vec!["greet".to_string()]).await.unwrap();
+        let outcome = router.process_intent(vec!["greet".to_string()]).await.unwrap();
+
+        assert!(matches!(outcome, IntentOutcome::CacheHit { .. }));
+        assert_eq!(consent.subjects(), vec!["command 'greet'", "command 'greet'"]);
+
+        router.set_trust(crate::trust::TrustConfig::default());
+        router.process_intent(vec!["greet".to_string()]).await.unwrap();
+        assert_eq!(consent.subjects().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_workflow_from_untrusted_bioma_always_asks_for_consent() {
+        let consent = MockConsent::always(PermissionConsent::AcceptForever);
+        let resolver = MockPathResolver::new().unwrap();
+        let bioma = resolver.write_dir().to_path_buf();
+        let mut router = TestRouter::new()
+            .generator(MockGenerator::new().with_command("greet", "console.log('hi')", &[]))
+            .consent(consent.clone())
+            .resolver(resolver)
+            .build()
+            .await
+            .unwrap();
+        router.process_intent(vec!["greet".to_string()]).await.unwrap();
+        router.set_trust(crate::trust::TrustConfig {
+            untrusted: vec![bioma.display().to_string()],
+            ..Default::default()
+        });
+        let workflow = crate::workflow::Workflow::parse("steps:\n  - command: greet\n").unwrap();
+
+        for runs in 1..=2 {
+            let outcomes = router.process_workflow(&workflow).await.unwrap();
+            assert_eq!(outcomes.map(|outcomes| outcomes.len()), Some(1));
+            assert_eq!(consent.subjects(), vec!["command 'greet'"; runs]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_imported_command_asks_until_allowed_forever() {
+        let consent = MockConsent::always(PermissionConsent::AcceptForever);
+        let resolver = MockPathResolver::new().unwrap();
+        let entry = serde_json::json!({
+            "greet": {"command": command("greet", &[]), "imported_from": "https://example.com/bioma.tar.gz"}
+        });
+        std::fs::write(resolver.write_dir().join("commands.json"), entry.to_string()).unwrap();
+        std::fs::write(resolver.write_dir().join("greet.ts"), "console.log('hi')").unwrap();
+        let mut router = TestRouter::new().consent(consent.clone()).resolver(resolver).build().await.unwrap();
+
+        // Asked even though the command needs no permissions
+        let outcome = router.process_intent(vec!["greet".to_string()]).await.unwrap();
+        assert!(matches!(outcome, IntentOutcome::CacheHit { .. }));
+        assert_eq!(consent.subjects(), vec!["command 'greet'"]);
+
+        router.process_intent(vec!["greet".to_string()]).await.unwrap();
+        assert_eq!(consent.subjects().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_script_changed_on_disk_asks_for_consent_again() {
+        let consent = MockConsent::always(PermissionConsent::AcceptForever);
+        let resolver = MockPathResolver::new().unwrap();
+        let script_path = resolver.write_dir().join("greet.ts");
+        let mut router = TestRouter::new()
+            .generator(MockGenerator::new().with_command("greet", "console.log('hi')", &[]))
+            .consent(consent.clone())
+            .resolver(resolver)
+            .build()
+            .await
+            .unwrap();
+
+        router.process_intent(vec!["greet".to_string()]).await.unwrap();
+        assert!(consent.subjects().is_empty());
+
+        // Asked even though the command needs no permissions
+        std::fs::write(&script_path, "fetch('https://example.com')").unwrap();
+        router.process_intent(vec!["greet".to_string()]).await.unwrap();
+        assert_eq!(consent.subjects(), vec!["command 'greet'"]);
+
+        // Allowing the changes takes the script as it is now
+        router.process_intent(vec!["greet".to_string()]).await.unwrap();
+        assert_eq!(consent.subjects().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_workflow_shows_scripts_changed_on_disk() {
+        let consent = MockConsent::always(PermissionConsent::AcceptForever);
+        let resolver = MockPathResolver::new().unwrap();
+        let script_path = resolver.write_dir().join("greet.ts");
+        let mut router = TestRouter::new()
+            .generator(MockGenerator::new().with_command("greet", "console.log('hi')", &[]))
+            .consent(consent.clone())
+            .resolver(resolver)
+            .build()
+            .await
+            .unwrap();
+        router.process_intent(vec!["greet".to_string()]).await.unwrap();
+        std::fs::write(&script_path, "fetch('https://example.com')").unwrap();
+
+        let workflow = crate::workflow::Workflow::parse("steps:\n  - command: greet\n").unwrap();
+        let outcomes = router.process_workflow(&workflow).await.unwrap();
+
+        assert_eq!(outcomes.map(|outcomes| outcomes.len()), Some(1));
+        assert_eq!(consent.subjects(), vec!["command 'greet'"]);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_intent_generates_without_running() {
+        let runner = MockProcessRunner::new();
+        let generator = MockGenerator::new()
+            .with_command("disk-usage", "console.log('42%')", &[])
+            .with_description("show disk usage", "disk-usage");
+        let mut router = TestRouter::new()
+            .generator(generator.clone())
+            .runner(runner.clone())
+            .build()
+            .await
+            .unwrap();
+
+        let outcome = router.prepare_intent(&["show disk usage".to_string()]).await.unwrap();
+        assert!(matches!(outcome, PrepareOutcome::Generated { command } if command.name == "disk-usage"));
+
+        let outcome = router.prepare_intent(&["disk-usage".to_string()]).await.unwrap();
+        assert!(matches!(outcome, PrepareOutcome::AlreadyCached { .. }));
+        assert_eq!(generator.requests(), vec!["describe show disk usage"]);
+        assert!(runner.calls().iter().all(|call| call[0] != "deno"));
+
+        let err = router.prepare_intent(&["deno".to_string()]).await.unwrap_err();
+        assert!(err.to_string().contains("system command"));
+    }
+
+    #[tokio::test]
+    async fn test_spec_is_kept_with_the_command() {
+        let spec = "# Weekly report\n\nSummarize the commits of the last 7 days.\n\n- Group them by author";
+        let generator = MockGenerator::new()
+            .with_command("weekly-report", "console.log('report')", &[])
+            .with_description(spec, "weekly-report");
+        let mut router = TestRouter::new().generator(generator.clone()).build().await.unwrap();
+
+        let outcome = router.process_spec(spec).await.unwrap();
+
+        assert!(matches!(outcome, IntentOutcome::Generated { .. }));
+        assert_eq!(generator.requests(), vec![format!("describe {}", spec)]);
+        let PrepareOutcome::AlreadyCached { command } = router.prepare_intent(&["weekly-report".to_string()]).await.unwrap()
+        else {
+            panic!("weekly-report wasn't cached");
+        };
+        assert_eq!(command.spec.as_deref(), Some(spec));
+    }
+
+    #[tokio::test]
+    async fn test_reserved_names_are_never_generated() {
+        let description = "go to my projects folder";
+        let renamed = crate::reserved::rename_request(description, &[("cd".to_string(), "a shell builtin")]);
+        let generator = MockGenerator::new()
+            .with_command("cd", "console.log('~/src')", &[])
+            .with_command("projects-dir", "console.log('~/src')", &[])
+            .with_description(description, "cd")
+            .with_description(&renamed, "projects-dir");
+        let refused = Arc::new(Mutex::new(Vec::new()));
+        let recorded = refused.clone();
+        let mut router = TestRouter::new()
+            .generator(generator.clone())
+            .build()
+            .await
+            .unwrap()
+            .with_progress(move |event: &RouterEvent| {
+                if let RouterEvent::NameRefused { name, .. } = event {
+                    recorded.lock().unwrap().push(name.clone());
+                }
+            });
+
+        let outcome = router.process_intent(vec![description.to_string()]).await.unwrap();
+        assert!(matches!(outcome, IntentOutcome::Generated { command, .. } if command.name == "projects-dir"));
+        assert_eq!(*refused.lock().unwrap(), vec!["cd"]);
+        assert_eq!(generator.requests(), vec![format!("describe {}", description), format!("describe {}", renamed)]);
+
+        let err = router.process_intent(vec!["config".to_string()]).await.unwrap_err();
+        assert!(matches!(err, ErgoError::Other(_)));
+        assert!(err.to_string().contains("'config' is an ergo subcommand or flag"));
+        assert_eq!(generator.requests().len(), 2);
+
+        let workflow = crate::workflow::Workflow::parse("steps:\n  - command: history\n    description: list my commits\n").unwrap();
+        let err = router.process_workflow(&workflow).await.unwrap_err();
+        assert!(err.to_string().contains("'history' is an ergo subcommand or flag"));
+        assert_eq!(generator.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_suggested_names_are_normalized_before_caching() {
+        let generator = MockGenerator::new()
+            .with_command("Disk Usage/Report", "console.log('42%')", &[])
+            .with_description("report disk usage", "Disk Usage/Report");
+        let mut router = TestRouter::new().generator(generator.clone()).build().await.unwrap();
+
+        let outcome = router.process_intent(vec!["report disk usage".to_string()]).await.unwrap();
+
+        let IntentOutcome::Generated { command, .. } = outcome else {
+            panic!("expected a generated command, got {:?}", outcome);
+        };
+        assert_eq!((command.name.as_str(), command.script_file.as_str()), ("disk-usage-report", "disk-usage-report.ts"));
+        assert!(matches!(
+            router.prepare_intent(&["disk-usage-report".to_string()]).await.unwrap(),
+            PrepareOutcome::AlreadyCached { .. }
+        ));
+        assert!(matches!(router.prepare_intent(&["../escape".to_string()]).await, Err(ErgoError::Other(_))));
+    }
+
+    #[tokio::test]
+    async fn test_similar_cached_command_is_offered_before_generating() {
+        let generator = MockGenerator::new()
+            .with_command("disk-usage", "console.log('42%')", &[])
+            .with_description("show disk usage", "disk-usage")
+            .with_description("check disk usage", "disk-usage");
+        let consent = MockConsent::always(PermissionConsent::AcceptOnce);
+        let mut router = TestRouter::new()
+            .generator(generator.clone())
+            .consent(consent.clone())
+            .build()
+            .await
+            .unwrap();
+
+        router.process_intent(vec!["show disk usage".to_string()]).await.unwrap();
+        let outcome = router.process_intent(vec!["check disk usage".to_string()]).await.unwrap();
+
+        assert!(matches!(outcome, IntentOutcome::CacheHit { command, .. } if command.name == "disk-usage"));
+        assert_eq!(generator.requests(), vec!["describe show disk usage"]);
+        assert!(consent.subjects().iter().any(|subject| subject.contains("Run existing 'disk-usage'")));
+    }
+
+    /// A generator whose requests never complete, like a stalled connection.
+    #[derive(Clone, Default)]
+    struct StalledGenerator {
+        attempts: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl CommandGenerator for StalledGenerator {
+        async fn generate_command(&self, _command_name: &str, _args: &[String]) -> Result<GenerationResult> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            std::future::pending().await
+        }
+
+        async fn generate_command_from_description(&self, _description: &str) -> Result<GenerationResult> {
+            std::future::pending().await
+        }
+
+        async fn regenerate_command_with_feedback(&self, _request: &FeedbackRequest<'_>) -> Result<GenerationResult> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stalled_generation_times_out_and_retries() {
+        let generator = StalledGenerator::default();
+        let mut router = TestRouter::new().generator(generator.clone()).build().await.unwrap();
+        router.set_generation_timeout(std::time::Duration::from_millis(10));
+
+        let err = router.process_intent(vec!["hello".to_string()]).await.unwrap_err();
+        assert!(matches!(&err, ErgoError::Generation(e) if e.is::<crate::cancellation::GenerationTimedOut>()));
+        assert_eq!(err.to_string(), "Generation timed out after 0s; retry with --retry");
+        assert_eq!(generator.attempts.load(Ordering::SeqCst), 1);
+
+        router.set_generation_retries(2);
+        let err = router.process_intent(vec!["hello".to_string()]).await.unwrap_err();
+        assert!(err.to_string().contains("even after retrying"));
+        assert_eq!(generator.attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_failing_generation_is_repaired_before_caching() {
+        let truncated = "const res = await fetch(url);\nif (res.ok) {\n";
+        let fixed = "const res = await fetch(url);\nif (res.ok) {\n  console.log(await res.text());\n}\n";
+        let generator = MockGenerator::new()
+            .with_command("status", truncated, &["--allow-net"])
+            .with_regenerated("status", fixed, &["--allow-net"]);
+        let runner = MockProcessRunner::new().with_output("deno", "up\n", 0);
+        let config = ValidationConfig::default();
+        let mut router = TestRouter::new()
+            .generator(generator.clone())
+            .runner(runner)
+            .validation(ValidationPipeline::from_config(&config, &SafetyConfig::default()))
+            .build()
+            .await
+            .unwrap();
+
+        let outcome = router.process_intent(vec!["status".to_string()]).await.unwrap();
+
+        let IntentOutcome::Generated { command, .. } = outcome else {
+            panic!("expected a generated command, got {:?}", outcome);
+        };
+        assert_eq!(command.name, "status");
+        let requests = generator.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].starts_with("regenerate status: The script failed automatic validation"));
+        assert!(requests[1].contains("[syntax] line 2: '{' is never closed"));
+    }
+
+    #[tokio::test]
+    async fn test_failing_generation_is_blocked_from_the_cache() {
+        let generator = MockGenerator::new().with_command("show-home", "console.log(Deno.env.get(\"HOME\"));\n", &[]);
+        let runner = MockProcessRunner::new().with_output("deno", "", 0);
+        let config = ValidationConfig {
+            on_failure: FailureAction::Block,
+            ..ValidationConfig::default()
+        };
+        let mut router = TestRouter::new()
+            .generator(generator.clone())
+            .runner(runner.clone())
+            .validation(ValidationPipeline::from_config(&config, &SafetyConfig::default()))
+            .build()
+            .await
+            .unwrap();
+
+        let err = router.process_intent(vec!["show-home".to_string()]).await.unwrap_err();
+
+        assert!(matches!(err, ErgoError::Generation(_)));
+        assert!(err.to_string().contains("Not caching 'show-home'"));
+        assert!(err.to_string().contains("Calls Deno.env but doesn't request --allow-env"));
+        assert_eq!(generator.requests(), vec!["generate show-home"]);
+        assert!(runner.calls().iter().all(|call| call[0] != "deno"));
+        assert!(matches!(
+            router.prepare_intent(&["show-home".to_string()]).await,
+            Err(e) if e.to_string().contains("fails validation")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_mock_http_client_answers_in_order() {
+        let client = MockHttpClient::new().with_response(r#"{"ok": true}"#).with_error("offline");
+        let body = serde_json::json!({"prompt": "hi"});
+
+        let first = client.post_json("https://example.com/v1", &[], &body).await.unwrap();
+        let second = client.post_json("https://example.com/v1", &[], &body).await.unwrap_err();
+        let third = client.post_json("https://example.com/v1", &[], &body).await.unwrap_err();
+
+        assert_eq!(first, r#"{"ok": true}"#);
+        assert_eq!(second.to_string(), "offline");
+        assert!(third.to_string().contains("no response queued"));
+        assert_eq!(client.requests().len(), 3);
+        assert_eq!(client.requests()[0], ("https://example.com/v1".to_string(), body));
+    }
+
+    #[tokio::test]
+    async fn test_mock_http_client_scripts_mixed_requests() {
+        let client = MockHttpClient::new()
+            .with_http_response(HttpResponse::new(200, r#"{"tag_name": "v1.2.0"}"#).with_header("ETag", "\"abc\""))
+            .with_status(404, "not found");
+
+        let release = client.get("https://example.com/latest", &[("accept", "application/json")]).await.unwrap();
+        let missing = client
+            .send(&HttpRequest::get("https://example.com/missing").timeout(std::time::Duration::from_secs(2)))
+            .await
+            .unwrap();
+
+        assert_eq!(release.header("etag"), Some("\"abc\""));
+        assert_eq!(release.json::<Value>().unwrap()["tag_name"], "v1.2.0");
+        assert!(!missing.is_success());
+        assert_eq!(missing.error_for_status().unwrap_err().to_string(), "HTTP 404: not found");
+        let sent = client.sent();
+        assert_eq!(sent[0].method, crate::http_client::Method::Get);
+        assert_eq!(sent[0].headers, vec![("accept".to_string(), "application/json".to_string())]);
+        assert_eq!(sent[1].timeout, Some(std::time::Duration::from_secs(2)));
+        assert_eq!(client.requests()[1].1, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_mock_http_client_routes_by_url() {
+        let client = MockHttpClient::new()
+            .on_error("api.anthropic.com", "connection reset")
+            .on("api.anthropic.com", "second try")
+            .with_response("queued")
+            .otherwise_status(404, "not found");
+        let body = serde_json::json!({});
+
+        let first = client.post_json("https://api.anthropic.com/v1/messages", &[("x-api-key", "k")], &body).await;
+        let second = client.post_json("https://api.anthropic.com/v1/messages", &[], &body).await.unwrap();
+        let third = client.post_json("https://api.anthropic.com/v1/messages", &[], &body).await.unwrap();
+        let other = client.get("https://example.com", &[]).await.unwrap();
+
+        assert_eq!(first.unwrap_err().to_string(), "connection reset");
+        assert_eq!(second, "second try");
+        assert_eq!(third, "queued");
+        assert_eq!(other.status, 404);
+        assert_eq!(client.pending(), 0);
+        let anthropic = client.sent_to("api.anthropic.com");
+        assert_eq!(anthropic.len(), 3);
+        assert_eq!(anthropic[0].header_value("X-Api-Key"), Some("k"));
+    }
+}
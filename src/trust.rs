@@ -0,0 +1,142 @@
+//! Trust levels of biomas.
+//!
+//! A bioma written by someone else (a project's shared `.abiogenesis`, one
+//! copied from a colleague) can change under your feet: a `git pull` may
+//! replace a script you once allowed forever. Marking such a bioma
+//! untrusted makes every run of its commands show the script and ask for
+//! consent, whatever was decided before:
+//!
+//! ```toml
+//! [trust]
+//! untrusted = ["~/src/shared-tools"]   # project directories or biomas
+//! trusted = ["~"]                      # the home bioma
+//! default = "trusted"                  # biomas in neither list
+//! ```
+//!
+//! `ergo --distrust-bioma [DIR]` and `ergo --trust-bioma [DIR]` edit the
+//! lists. An entry matches a bioma or the directory holding it, never its
+//! subdirectories; a bioma in both lists is untrusted.
+//!
+//! Permissions allowed forever can also lapse: with `grant_expiry_days` set,
+//! a command not run for that long asks for consent again
+//! ([`ExpiredGrants::AskOnce`]) or loses its decision altogether
+//! ([`ExpiredGrants::Revoke`]).
+
+use crate::command_cache::CacheConfig;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// How much a bioma's commands are trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustLevel {
+    /// Stored permission decisions apply.
+    #[default]
+    Trusted,
+    /// Every run shows the script and asks for consent.
+    Untrusted,
+}
+
+/// What happens to a command's AcceptForever decision once it expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpiredGrants {
+    /// The decision stays but no longer applies: consent is asked again, as
+    /// after AcceptOnce.
+    #[default]
+    AskOnce,
+    /// The decision is dropped, as if none had been made.
+    Revoke,
+}
+
+/// Trust settings (the `[trust]` table in `config.toml`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrustConfig {
+    /// Trust level of biomas in neither list.
+    pub default: TrustLevel,
+    /// Biomas, or the directories holding them, that are trusted.
+    pub trusted: Vec<String>,
+    /// Biomas, or the directories holding them, that are untrusted.
+    pub untrusted: Vec<String>,
+    /// Days a command may go unrun before its AcceptForever decision
+    /// expires (0 for never).
+    pub grant_expiry_days: u64,
+    /// What an expired AcceptForever decision becomes.
+    pub expired_grants: ExpiredGrants,
+}
+
+impl TrustConfig {
+    /// The trust level of the bioma at `bioma`.
+    pub fn level(&self, bioma: &Path, layout: &CacheConfig, home: Option<&Path>) -> TrustLevel {
+        let base = layout.base_dir(bioma);
+        let listed = |entries: &[String]| {
+            entries.iter().map(|entry| expand(entry, home)).any(|entry| entry == bioma || Some(&entry) == base.as_ref())
+        };
+        if listed(&self.untrusted) {
+            TrustLevel::Untrusted
+        } else if listed(&self.trusted) {
+            TrustLevel::Trusted
+        } else {
+            self.default
+        }
+    }
+
+    /// Lists `dir` at `level`, removing it from the other list.
+    ///
+    /// Returns `false` if it was already listed there.
+    pub fn set_level(&mut self, dir: &Path, level: TrustLevel) -> bool {
+        let entry = dir.display().to_string();
+        let (list, other) = match level {
+            TrustLevel::Trusted => (&mut self.trusted, &mut self.untrusted),
+            TrustLevel::Untrusted => (&mut self.untrusted, &mut self.trusted),
+        };
+        other.retain(|listed| *listed != entry);
+        if list.contains(&entry) {
+            return false;
+        }
+        list.push(entry);
+        true
+    }
+}
+
+/// `entry` with a leading `~` replaced by `home`.
+fn expand(entry: &str, home: Option<&Path>) -> PathBuf {
+    match (entry.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => home.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(entry),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_matches_biomas_and_their_directories() {
+        let layout = CacheConfig::default();
+        let home = Path::new("/home/ada");
+        let config = TrustConfig {
+            default: TrustLevel::Untrusted,
+            trusted: vec!["~".to_string(), "/src/shared".to_string()],
+            untrusted: vec!["/src/shared/.abiogenesis/biomas".to_string()],
+            ..TrustConfig::default()
+        };
+
+        assert_eq!(config.level(&layout.cache_dir(home), &layout, Some(home)), TrustLevel::Trusted);
+        assert_eq!(config.level(&layout.cache_dir(Path::new("/src/shared")), &layout, Some(home)), TrustLevel::Untrusted);
+        assert_eq!(config.level(&layout.cache_dir(&home.join("project")), &layout, Some(home)), TrustLevel::Untrusted);
+        assert_eq!(TrustConfig::default().level(&layout.cache_dir(home), &layout, Some(home)), TrustLevel::Trusted);
+    }
+
+    #[test]
+    fn test_set_level_moves_the_entry() {
+        let mut config = TrustConfig::default();
+
+        assert!(config.set_level(Path::new("/src/shared"), TrustLevel::Untrusted));
+        assert!(!config.set_level(Path::new("/src/shared"), TrustLevel::Untrusted));
+        assert!(config.set_level(Path::new("/src/shared"), TrustLevel::Trusted));
+        assert_eq!(config.trusted, vec!["/src/shared"]);
+        assert!(config.untrusted.is_empty());
+    }
+}
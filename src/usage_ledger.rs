@@ -0,0 +1,401 @@
+//! Token usage ledger for model API calls.
+//!
+//! Every call to a model appends one line to
+//! `~/.abiogenesis/usage_ledger.jsonl` with the tokens it used, including the
+//! tokens written to and read from Anthropic's prompt cache. Calls that
+//! generate or regenerate a command are attributed to it. `ergo --usage`
+//! summarizes the ledger, shows how much the cache saved and which commands
+//! cost the most.
+//!
+//! Cache reads are billed at a tenth of the normal input price and cache
+//! writes at 1.25 times, so savings are reported in equivalent input tokens:
+//! `0.9 × read - 0.25 × written`. Spend per command uses the same unit, with
+//! output tokens at five times the input price.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Share of the input price saved on each cached token read.
+const CACHE_READ_DISCOUNT: f64 = 0.9;
+
+/// Surcharge on the input price for each token written to the cache.
+const CACHE_WRITE_SURCHARGE: f64 = 0.25;
+
+/// Price of an output token relative to an input token.
+const OUTPUT_PRICE_RATIO: f64 = 5.0;
+
+/// Most commands listed in the spend view.
+const MAX_LISTED_COMMANDS: usize = 10;
+
+/// Tokens used by one model call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// Input tokens billed at the normal price.
+    #[serde(default)]
+    pub input_tokens: u64,
+    /// Output tokens.
+    #[serde(default)]
+    pub output_tokens: u64,
+    /// Input tokens written to the prompt cache.
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    /// Input tokens read from the prompt cache.
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Reads the `usage` object of an Anthropic Messages API response
+    /// (also returned by Bedrock).
+    pub fn from_anthropic(response: &Value) -> Option<Self> {
+        serde_json::from_value(response.get("usage")?.clone()).ok()
+    }
+
+    /// Reads the `usageMetadata` object of a Gemini response.
+    pub fn from_gemini(response: &Value) -> Option<Self> {
+        let usage = response.get("usageMetadata")?;
+        let count = |field: &str| usage.get(field).and_then(Value::as_u64).unwrap_or(0);
+        let cached = count("cachedContentTokenCount");
+        Some(Self {
+            // Gemini counts cached tokens as part of the prompt
+            input_tokens: count("promptTokenCount").saturating_sub(cached),
+            output_tokens: count("candidatesTokenCount"),
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: cached,
+        })
+    }
+
+    /// Adds `other` to these totals.
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+
+    /// What the tokens cost, in equivalent uncached input tokens.
+    pub fn cost(&self) -> u64 {
+        (self.input_tokens as f64
+            + self.cache_creation_input_tokens as f64 * (1.0 + CACHE_WRITE_SURCHARGE)
+            + self.cache_read_input_tokens as f64 * (1.0 - CACHE_READ_DISCOUNT)
+            + self.output_tokens as f64 * OUTPUT_PRICE_RATIO)
+            .round() as u64
+    }
+}
+
+/// One line of the ledger.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// When the call was made (Unix timestamp).
+    pub timestamp: u64,
+    /// The model that answered, e.g. `claude-3-haiku-20240307`.
+    pub model: String,
+    /// The command the call generated or regenerated, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Whether the call regenerated an existing command (`--nope`, `--regenerate`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub regeneration: bool,
+    /// Tokens used.
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+}
+
+/// Returns the path of the ledger file.
+pub fn default_path() -> Result<PathBuf> {
+    Ok(crate::config::Config::get_config_dir()?.join("usage_ledger.jsonl"))
+}
+
+/// Appends `entry` to the ledger at `path`.
+pub fn append(path: &Path, entry: &LedgerEntry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Reads every entry of the ledger at `path`, skipping malformed lines.
+///
+/// A missing ledger has no entries.
+pub fn read(path: &Path) -> Result<Vec<LedgerEntry>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+/// Totals over a set of ledger entries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageSummary {
+    /// Number of model calls.
+    pub calls: usize,
+    /// Summed token usage.
+    pub totals: TokenUsage,
+}
+
+impl UsageSummary {
+    /// Sums `entries`.
+    pub fn from_entries(entries: &[LedgerEntry]) -> Self {
+        let mut summary = Self {
+            calls: entries.len(),
+            ..Default::default()
+        };
+        for entry in entries {
+            summary.totals.add(&entry.usage);
+        }
+        summary
+    }
+
+    /// All input tokens, cached or not.
+    pub fn total_input_tokens(&self) -> u64 {
+        self.totals.input_tokens + self.totals.cache_creation_input_tokens + self.totals.cache_read_input_tokens
+    }
+
+    /// Input tokens saved by the prompt cache, in equivalent uncached tokens.
+    /// Negative while cache writes haven't paid off yet.
+    pub fn cache_savings(&self) -> i64 {
+        (self.totals.cache_read_input_tokens as f64 * CACHE_READ_DISCOUNT
+            - self.totals.cache_creation_input_tokens as f64 * CACHE_WRITE_SURCHARGE)
+            .round() as i64
+    }
+}
+
+impl fmt::Display for UsageSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "📒 Model usage ({} call{}):", self.calls, if self.calls == 1 { "" } else { "s" })?;
+        writeln!(f, "   📥 Input tokens: {}", self.total_input_tokens())?;
+        writeln!(f, "      uncached: {}", self.totals.input_tokens)?;
+        writeln!(f, "      written to cache: {}", self.totals.cache_creation_input_tokens)?;
+        writeln!(f, "      read from cache: {}", self.totals.cache_read_input_tokens)?;
+        writeln!(f, "   📤 Output tokens: {}", self.totals.output_tokens)?;
+        let total = self.total_input_tokens();
+        let percent = if total == 0 {
+            0.0
+        } else {
+            self.cache_savings() as f64 * 100.0 / total as f64
+        };
+        write!(
+            f,
+            "   💰 Prompt cache savings: {} input tokens ({:.1}% of input)",
+            self.cache_savings(),
+            percent
+        )
+    }
+}
+
+/// What generating and regenerating one command has cost.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandSpend {
+    /// The command's name.
+    pub command: String,
+    /// Calls that generated it.
+    pub generations: usize,
+    /// Calls that regenerated it.
+    pub regenerations: usize,
+    /// Summed token usage.
+    pub usage: TokenUsage,
+}
+
+/// The commands that cost the most, most expensive first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpendByCommand {
+    /// At most [`MAX_LISTED_COMMANDS`] commands.
+    pub commands: Vec<CommandSpend>,
+}
+
+impl SpendByCommand {
+    /// Attributes `entries` to their commands; calls for no command are left out.
+    pub fn from_entries(entries: &[LedgerEntry]) -> Self {
+        let mut by_command: Vec<CommandSpend> = Vec::new();
+        for entry in entries {
+            let Some(command) = &entry.command else {
+                continue;
+            };
+            let index = match by_command.iter().position(|spend| &spend.command == command) {
+                Some(index) => index,
+                None => {
+                    by_command.push(CommandSpend {
+                        command: command.clone(),
+                        ..Default::default()
+                    });
+                    by_command.len() - 1
+                }
+            };
+            let spend = &mut by_command[index];
+            if entry.regeneration {
+                spend.regenerations += 1;
+            } else {
+                spend.generations += 1;
+            }
+            spend.usage.add(&entry.usage);
+        }
+        by_command.sort_by(|a, b| b.usage.cost().cmp(&a.usage.cost()).then_with(|| a.command.cmp(&b.command)));
+        by_command.truncate(MAX_LISTED_COMMANDS);
+        Self { commands: by_command }
+    }
+}
+
+impl fmt::Display for SpendByCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "💸 Most expensive commands (in equivalent input tokens):")?;
+        let width = self.commands.iter().map(|spend| spend.command.len()).max().unwrap_or(0);
+        for spend in &self.commands {
+            let plural = |n: usize| if n == 1 { "" } else { "s" };
+            write!(
+                f,
+                "\n   {:<width$}  {:>9}  ({} generation{}, {} regeneration{})",
+                spend.command,
+                spend.usage.cost(),
+                spend.generations,
+                plural(spend.generations),
+                spend.regenerations,
+                plural(spend.regenerations),
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn entry(usage: TokenUsage) -> LedgerEntry {
+        LedgerEntry {
+            timestamp: 0,
+            model: "claude-3-haiku-20240307".to_string(),
+            command: None,
+            regeneration: false,
+            usage,
+        }
+    }
+
+    #[test]
+    fn test_token_usage_from_responses() {
+        let anthropic = json!({"usage": {"input_tokens": 20, "output_tokens": 300, "cache_read_input_tokens": 2100}});
+        assert_eq!(
+            TokenUsage::from_anthropic(&anthropic),
+            Some(TokenUsage {
+                input_tokens: 20,
+                output_tokens: 300,
+                cache_creation_input_tokens: 0,
+                cache_read_input_tokens: 2100,
+            })
+        );
+
+        let gemini = json!({"usageMetadata": {"promptTokenCount": 500, "candidatesTokenCount": 80, "cachedContentTokenCount": 400}});
+        let usage = TokenUsage::from_gemini(&gemini).unwrap();
+        assert_eq!((usage.input_tokens, usage.cache_read_input_tokens, usage.output_tokens), (100, 400, 80));
+
+        assert_eq!(TokenUsage::from_anthropic(&json!({})), None);
+    }
+
+    #[test]
+    fn test_append_and_read_ledger() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("usage_ledger.jsonl");
+        let first = entry(TokenUsage {
+            input_tokens: 10,
+            ..Default::default()
+        });
+
+        append(&path, &first).unwrap();
+        append(&path, &first).unwrap();
+        fs::write(&path, format!("{}not json\n", fs::read_to_string(&path).unwrap())).unwrap();
+
+        assert_eq!(read(&path).unwrap(), vec![first.clone(), first]);
+        assert!(read(&temp_dir.path().join("missing.jsonl")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_summary_reports_cache_savings() {
+        let write = entry(TokenUsage {
+            input_tokens: 50,
+            output_tokens: 400,
+            cache_creation_input_tokens: 2000,
+            cache_read_input_tokens: 0,
+        });
+        let read = entry(TokenUsage {
+            input_tokens: 50,
+            output_tokens: 400,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: 2000,
+        });
+
+        let summary = UsageSummary::from_entries(&[write, read]);
+
+        assert_eq!(summary.calls, 2);
+        assert_eq!(summary.total_input_tokens(), 4100);
+        // 0.9 × 2000 - 0.25 × 2000
+        assert_eq!(summary.cache_savings(), 1300);
+        assert!(summary.to_string().contains("Prompt cache savings: 1300 input tokens (31.7% of input)"));
+    }
+
+    #[test]
+    fn test_entries_without_command_read_as_unattributed() {
+        let line = r#"{"timestamp": 1, "model": "m", "input_tokens": 5}"#;
+
+        let entry: LedgerEntry = serde_json::from_str(line).unwrap();
+
+        assert_eq!(entry.command, None);
+        assert!(!entry.regeneration);
+        assert!(!serde_json::to_string(&entry).unwrap().contains("regeneration"));
+    }
+
+    #[test]
+    fn test_spend_by_command_ranks_by_cost() {
+        let call = |command: Option<&str>, regeneration: bool, input_tokens: u64, output_tokens: u64| LedgerEntry {
+            command: command.map(str::to_string),
+            regeneration,
+            ..entry(TokenUsage {
+                input_tokens,
+                output_tokens,
+                ..Default::default()
+            })
+        };
+        let entries = [
+            call(Some("weather"), false, 100, 100),
+            call(Some("deploy"), false, 100, 100),
+            call(Some("deploy"), true, 200, 400),
+            call(Some("deploy"), true, 200, 400),
+            call(None, false, 10_000, 10_000),
+        ];
+
+        let spend = SpendByCommand::from_entries(&entries);
+
+        assert_eq!(spend.commands.len(), 2);
+        assert_eq!(spend.commands[0].command, "deploy");
+        assert_eq!((spend.commands[0].generations, spend.commands[0].regenerations), (1, 2));
+        // 500 input + 5 × 900 output
+        assert_eq!(spend.commands[0].usage.cost(), 5000);
+        assert_eq!(spend.commands[1].usage.cost(), 600);
+        let shown = spend.to_string();
+        assert!(shown.contains("deploy        5000  (1 generation, 2 regenerations)"));
+        assert!(shown.contains("weather        600  (1 generation, 0 regenerations)"));
+    }
+
+    #[test]
+    fn test_cost_weights_cache_and_output() {
+        let usage = TokenUsage {
+            input_tokens: 100,
+            output_tokens: 10,
+            cache_creation_input_tokens: 1000,
+            cache_read_input_tokens: 1000,
+        };
+
+        // 100 + 1.25 × 1000 + 0.1 × 1000 + 5 × 10
+        assert_eq!(usage.cost(), 1500);
+    }
+}
@@ -0,0 +1,707 @@
+//! Checks run over freshly generated commands before they're cached.
+//!
+//! Every generation passes through a [`ValidationPipeline`] of
+//! [`GenerationValidator`]s between the generator and
+//! [`CommandCache::store_command`](crate::command_cache::CommandCache::store_command).
+//! The built-in validators are:
+//!
+//! - **`syntax`**: brackets, strings, template literals and comments are
+//!   balanced, which catches scripts cut off mid-generation
+//! - **`permissions`**: the script requests every permission the Deno APIs
+//!   it calls need, and only permissions Deno knows
+//! - **`safety`**: the script matches no catastrophic pattern (see
+//!   [`security_review::blocked_patterns`])
+//! - **`rules`**: the organization's own rules from the `[validation]` table
+//!
+//! When a generation fails, the router either asks the model to repair it,
+//! passing the issues as feedback, or refuses to cache it (see
+//! [`FailureAction`]).
+//!
+//! ```toml
+//! [validation]
+//! on_failure = "repair"
+//! max_repairs = 2
+//!
+//! [[validation.rules]]
+//! pattern = "console.debug("
+//! message = "Don't leave debug logging in commands"
+//!
+//! [[validation.rules]]
+//! pattern = "// Owner:"
+//! message = "Every command names its owner"
+//! required = true
+//! ```
+
+use crate::llm_generator::GenerationResult;
+use crate::permissions::PermissionKind;
+use crate::security_review::{self, SafetyConfig};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Validation settings (the `[validation]` table in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationConfig {
+    /// Whether generated commands are validated at all.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// What happens to a generation that fails validation.
+    #[serde(default)]
+    pub on_failure: FailureAction,
+    /// How many times the model is asked to repair a failing generation.
+    #[serde(default = "default_max_repairs")]
+    pub max_repairs: u32,
+    /// Built-in validators to leave out, by name (e.g. `permissions`).
+    #[serde(default)]
+    pub skip: Vec<String>,
+    /// The organization's own rules.
+    #[serde(default)]
+    pub rules: Vec<OrgRule>,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            on_failure: FailureAction::default(),
+            max_repairs: default_max_repairs(),
+            skip: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_repairs() -> u32 {
+    2
+}
+
+/// What happens to a generation that fails validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureAction {
+    /// Ask the model to fix the issues, up to `max_repairs` times, then
+    /// refuse to cache it.
+    #[default]
+    Repair,
+    /// Refuse to cache it straight away.
+    Block,
+}
+
+/// An organization rule: a pattern scripts must not contain, or must contain
+/// when `required` is set.
+///
+/// `*` in the pattern matches any text on the line; whitespace is ignored,
+/// as in the `[safety]` deny patterns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrgRule {
+    /// The pattern to look for.
+    pub pattern: String,
+    /// What's wrong, as shown to the user and the model.
+    pub message: String,
+    /// Whether scripts must contain the pattern instead of avoiding it.
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// A problem a validator found in a generated command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    /// The name of the validator that found it.
+    pub validator: String,
+    /// 1-based line number in the script, if the issue is tied to one.
+    pub line: Option<usize>,
+    /// What's wrong.
+    pub message: String,
+}
+
+impl ValidationIssue {
+    /// Creates an issue found by the validator named `validator`.
+    pub fn new(validator: &str, line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            validator: validator.to_string(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "[{}] line {}: {}", self.validator, line, self.message),
+            None => write!(f, "[{}] {}", self.validator, self.message),
+        }
+    }
+}
+
+/// A check run over every generated command before it's cached.
+pub trait GenerationValidator: Send + Sync {
+    /// Short name identifying the validator in issues and `validation.skip`.
+    fn name(&self) -> &str;
+
+    /// Checks a generated script and the permissions it requests.
+    fn validate(&self, generation: &GenerationResult) -> Vec<ValidationIssue>;
+}
+
+/// The validators a generation goes through, and what happens when it fails.
+pub struct ValidationPipeline {
+    validators: Vec<Box<dyn GenerationValidator>>,
+    on_failure: FailureAction,
+    max_repairs: u32,
+}
+
+impl ValidationPipeline {
+    /// Creates a pipeline without validators, with the failure policy from
+    /// `config`.
+    pub fn new(config: &ValidationConfig) -> Self {
+        Self {
+            validators: Vec::new(),
+            on_failure: config.on_failure,
+            max_repairs: config.max_repairs,
+        }
+    }
+
+    /// Creates the pipeline `config` describes: the built-in validators it
+    /// doesn't skip, then its organization rules.
+    pub fn from_config(config: &ValidationConfig, safety: &SafetyConfig) -> Self {
+        let pipeline = Self::new(config);
+        if !config.enabled {
+            return pipeline;
+        }
+        let builtin: [Box<dyn GenerationValidator>; 3] = [
+            Box::new(SyntaxValidator),
+            Box::new(PermissionValidator),
+            Box::new(SafetyValidator::new(safety.clone())),
+        ];
+        let mut validators: Vec<Box<dyn GenerationValidator>> = builtin
+            .into_iter()
+            .filter(|validator| !config.skip.iter().any(|name| name == validator.name()))
+            .collect();
+        if !config.rules.is_empty() {
+            validators.push(Box::new(OrgRulesValidator::new(config.rules.clone())));
+        }
+        Self { validators, ..pipeline }
+    }
+
+    /// Adds a validator to the end of the pipeline.
+    pub fn with_validator(mut self, validator: impl GenerationValidator + 'static) -> Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    /// What happens to a generation that fails validation.
+    pub fn on_failure(&self) -> FailureAction {
+        self.on_failure
+    }
+
+    /// How many times the model is asked to repair a failing generation.
+    pub fn max_repairs(&self) -> u32 {
+        self.max_repairs
+    }
+
+    /// Runs every validator except those named in `skip` and collects their
+    /// issues.
+    pub fn validate(&self, generation: &GenerationResult, skip: &[&str]) -> Vec<ValidationIssue> {
+        self.validators
+            .iter()
+            .filter(|validator| !skip.contains(&validator.name()))
+            .flat_map(|validator| validator.validate(generation))
+            .collect()
+    }
+}
+
+/// Turns issues into feedback asking the model to repair a script.
+pub fn repair_feedback(issues: &[ValidationIssue]) -> String {
+    let listing: Vec<String> = issues.iter().map(|issue| format!("- {}", issue)).collect();
+    format!(
+        "The script failed automatic validation. Fix these problems and keep everything else the same:\n{}",
+        listing.join("\n")
+    )
+}
+
+/// Formats issues as an indented list for the terminal.
+pub fn format_issues(issues: &[ValidationIssue]) -> String {
+    issues
+        .iter()
+        .map(|issue| format!("   • {}", issue))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// =============================================================================
+// Syntax
+// =============================================================================
+
+/// Checks that brackets, strings, template literals and comments are
+/// balanced, without running Deno.
+pub struct SyntaxValidator;
+
+impl SyntaxValidator {
+    /// The validator's name.
+    pub const NAME: &'static str = "syntax";
+}
+
+impl GenerationValidator for SyntaxValidator {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn validate(&self, generation: &GenerationResult) -> Vec<ValidationIssue> {
+        balance_error(&generation.script_content)
+            .map(|(line, message)| ValidationIssue::new(Self::NAME, Some(line), message))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Something opened in a script that must be closed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frame {
+    Paren,
+    Bracket,
+    Brace,
+    Template,
+    Interpolation,
+}
+
+impl Frame {
+    fn opener(self) -> &'static str {
+        match self {
+            Frame::Paren => "(",
+            Frame::Bracket => "[",
+            Frame::Brace => "{",
+            Frame::Template => "`",
+            Frame::Interpolation => "${",
+        }
+    }
+
+    fn closer(self) -> char {
+        match self {
+            Frame::Paren => ')',
+            Frame::Bracket => ']',
+            Frame::Brace | Frame::Interpolation => '}',
+            Frame::Template => '`',
+        }
+    }
+}
+
+/// Characters after which `/` starts a regular expression rather than a division.
+const REGEX_PRECEDERS: &str = "(,=:[!&|?{};+-*%<>~^";
+
+/// Keywords after which `/` starts a regular expression.
+const REGEX_KEYWORDS: &[&str] = &["return", "typeof", "case", "do", "else", "in", "of", "new", "delete", "void", "throw", "yield", "await"];
+
+/// Finds the first unbalanced construct in a script, with its line.
+fn balance_error(script: &str) -> Option<(usize, String)> {
+    let chars: Vec<char> = script.chars().collect();
+    let mut stack: Vec<(Frame, usize)> = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    // A shebang isn't JavaScript
+    if script.starts_with("#!") {
+        while i < chars.len() && chars[i] != '\n' {
+            i += 1;
+        }
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+        }
+
+        if matches!(stack.last(), Some((Frame::Template, _))) {
+            match c {
+                '\\' => {
+                    if chars.get(i + 1) == Some(&'\n') {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                '`' => {
+                    stack.pop();
+                }
+                '$' if chars.get(i + 1) == Some(&'{') => {
+                    stack.push((Frame::Interpolation, line));
+                    i += 1;
+                }
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let start = line;
+                i += 2;
+                loop {
+                    match chars.get(i) {
+                        None => return Some((start, "Comment is never closed".to_string())),
+                        Some('*') if chars.get(i + 1) == Some(&'/') => break,
+                        Some('\n') => line += 1,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            '/' if starts_regex(&chars[..i]) => {
+                let mut in_class = false;
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None | Some('\n') => return Some((line, "Regular expression is never closed".to_string())),
+                        Some('\\') => i += 1,
+                        Some('[') => in_class = true,
+                        Some(']') => in_class = false,
+                        Some('/') if !in_class => break,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+            }
+            '"' | '\'' => {
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None | Some('\n') => return Some((line, "String is never closed".to_string())),
+                        Some('\\') => {
+                            if chars.get(i + 1) == Some(&'\n') {
+                                line += 1;
+                            }
+                            i += 1;
+                        }
+                        Some(&quote) if quote == c => break,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+            }
+            '`' => stack.push((Frame::Template, line)),
+            '(' => stack.push((Frame::Paren, line)),
+            '[' => stack.push((Frame::Bracket, line)),
+            '{' => stack.push((Frame::Brace, line)),
+            ')' | ']' | '}' => match stack.pop() {
+                Some((frame, _)) if frame.closer() == c => {}
+                Some((frame, opened)) => {
+                    return Some((
+                        line,
+                        format!("Unexpected '{}', '{}' opened on line {} is still open", c, frame.opener(), opened),
+                    ));
+                }
+                None => return Some((line, format!("Unexpected '{}' with nothing to close", c))),
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+
+    stack.last().map(|(frame, opened)| {
+        (
+            *opened,
+            format!("'{}' is never closed; the script may have been cut off", frame.opener()),
+        )
+    })
+}
+
+/// Whether a `/` after `before` starts a regular expression.
+fn starts_regex(before: &[char]) -> bool {
+    let code: Vec<char> = before.iter().rev().skip_while(|c| c.is_whitespace()).copied().collect();
+    let Some(&previous) = code.first() else {
+        return true;
+    };
+    if REGEX_PRECEDERS.contains(previous) {
+        return true;
+    }
+    let word: String = code
+        .iter()
+        .take_while(|c| c.is_alphanumeric() || **c == '_' || **c == '$')
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    REGEX_KEYWORDS.contains(&word.as_str())
+}
+
+// =============================================================================
+// Permissions
+// =============================================================================
+
+/// Checks that a script requests the permissions the Deno APIs it calls
+/// need, and only permissions Deno knows.
+pub struct PermissionValidator;
+
+impl PermissionValidator {
+    /// The validator's name.
+    pub const NAME: &'static str = "permissions";
+}
+
+/// Deno APIs and the permission they need.
+const PERMISSION_USES: &[(&str, PermissionKind)] = &[
+    ("fetch(", PermissionKind::Net),
+    ("Deno.connect(", PermissionKind::Net),
+    ("Deno.listen(", PermissionKind::Net),
+    ("new WebSocket(", PermissionKind::Net),
+    ("Deno.readTextFile", PermissionKind::Read),
+    ("Deno.readFile", PermissionKind::Read),
+    ("Deno.readDir", PermissionKind::Read),
+    ("Deno.stat", PermissionKind::Read),
+    ("Deno.lstat", PermissionKind::Read),
+    ("Deno.realPath", PermissionKind::Read),
+    ("Deno.writeTextFile", PermissionKind::Write),
+    ("Deno.writeFile", PermissionKind::Write),
+    ("Deno.mkdir", PermissionKind::Write),
+    ("Deno.remove", PermissionKind::Write),
+    ("Deno.rename", PermissionKind::Write),
+    ("Deno.copyFile", PermissionKind::Write),
+    ("Deno.create(", PermissionKind::Write),
+    ("Deno.env.", PermissionKind::Env),
+    ("Deno.Command(", PermissionKind::Run),
+    ("Deno.run(", PermissionKind::Run),
+    ("Deno.hostname(", PermissionKind::Sys),
+    ("Deno.osRelease(", PermissionKind::Sys),
+    ("Deno.loadavg(", PermissionKind::Sys),
+    ("Deno.systemMemoryInfo(", PermissionKind::Sys),
+    ("Deno.networkInterfaces(", PermissionKind::Sys),
+];
+
+impl GenerationValidator for PermissionValidator {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn validate(&self, generation: &GenerationResult) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let mut granted = Vec::new();
+        for request in &generation.command.permissions {
+            match request.parsed() {
+                Ok(permission) => granted.push(permission.kind),
+                Err(e) => issues.push(ValidationIssue::new(Self::NAME, None, e.to_string())),
+            }
+        }
+
+        let mut reported = Vec::new();
+        for (index, line) in generation.script_content.lines().enumerate() {
+            let code = line.trim_start();
+            if code.starts_with("//") || code.starts_with('*') {
+                continue;
+            }
+            for (api, kind) in PERMISSION_USES.iter().filter(|(api, _)| line.contains(api)) {
+                if granted.contains(kind) || reported.contains(kind) {
+                    continue;
+                }
+                reported.push(*kind);
+                issues.push(ValidationIssue::new(
+                    Self::NAME,
+                    Some(index + 1),
+                    format!("Calls {} but doesn't request {}", api.trim_end_matches(['(', '.']), kind.flag()),
+                ));
+            }
+        }
+        issues
+    }
+}
+
+// =============================================================================
+// Safety
+// =============================================================================
+
+/// Checks that a script matches no catastrophic pattern, as the safety rails
+/// would refuse to run it anyway.
+pub struct SafetyValidator {
+    config: SafetyConfig,
+}
+
+impl SafetyValidator {
+    /// The validator's name.
+    pub const NAME: &'static str = "safety";
+
+    /// Creates a validator enforcing the `[safety]` settings in `config`.
+    pub fn new(config: SafetyConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl GenerationValidator for SafetyValidator {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn validate(&self, generation: &GenerationResult) -> Vec<ValidationIssue> {
+        security_review::blocked_patterns(&generation.script_content, &generation.command.permissions, &self.config)
+            .into_iter()
+            .map(|finding| ValidationIssue::new(Self::NAME, finding.line, finding.message))
+            .collect()
+    }
+}
+
+// =============================================================================
+// Organization Rules
+// =============================================================================
+
+/// Checks a script against the organization's own rules.
+pub struct OrgRulesValidator {
+    rules: Vec<OrgRule>,
+}
+
+impl OrgRulesValidator {
+    /// The validator's name.
+    pub const NAME: &'static str = "rules";
+
+    /// Creates a validator enforcing `rules`.
+    pub fn new(rules: Vec<OrgRule>) -> Self {
+        Self { rules }
+    }
+}
+
+impl GenerationValidator for OrgRulesValidator {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn validate(&self, generation: &GenerationResult) -> Vec<ValidationIssue> {
+        let script = &generation.script_content;
+        let mut issues = Vec::new();
+        for rule in &self.rules {
+            let mut matches = script
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| security_review::matches_pattern(line, &rule.pattern))
+                .map(|(index, _)| index + 1)
+                .peekable();
+            if rule.required {
+                if matches.peek().is_none() {
+                    issues.push(ValidationIssue::new(Self::NAME, None, rule.message.clone()));
+                }
+            } else {
+                issues.extend(matches.map(|line| ValidationIssue::new(Self::NAME, Some(line), rule.message.clone())));
+            }
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::command;
+
+    fn generation(script: &str, permissions: &[&str]) -> GenerationResult {
+        GenerationResult {
+            command: command("test", permissions),
+            script_content: script.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_syntax_accepts_balanced_scripts() {
+        let script = r#"#!/usr/bin/env -S deno run
+// Counts words (in "quotes" too)
+const text = await Deno.readTextFile(Deno.args[0]);
+const words = text.split(/[\s(]+/).filter((w) => w !== "}");
+/* a { comment */
+console.log(`Words: ${words.length} in ${Deno.args.map((a) => `"${a}"`).join(", ")}`);
+const ratio = words.length / 2 / 3;
+"#;
+
+        assert_eq!(balance_error(script), None);
+    }
+
+    #[test]
+    fn test_syntax_reports_truncated_and_mismatched_scripts() {
+        let truncated = "function main() {\n  for (const x of xs) {\n    console.log(x);\n";
+        let mismatched = "const xs = [1, 2);\n";
+        let unterminated = "const greeting = 'hello;\nconsole.log(greeting);\n";
+
+        let (line, message) = balance_error(truncated).unwrap();
+        assert_eq!(line, 2);
+        assert!(message.contains("cut off"));
+        let (line, message) = balance_error(mismatched).unwrap();
+        assert_eq!(line, 1);
+        assert!(message.contains("'[' opened on line 1"));
+        assert_eq!(balance_error(unterminated).unwrap().0, 1);
+    }
+
+    #[test]
+    fn test_permission_validator_reports_missing_and_unknown_permissions() {
+        let script = "// fetch( in a comment is fine\nconst res = await fetch(url);\nawait Deno.writeTextFile(\"out.json\", await res.text());\nconsole.log(Deno.env.get(\"HOME\"));\n";
+        let generated = generation(script, &["--allow-net=api.example.com", "--allow-network"]);
+
+        let issues = PermissionValidator.validate(&generated);
+
+        let messages: Vec<String> = issues.iter().map(ToString::to_string).collect();
+        assert_eq!(issues.len(), 3, "{:?}", messages);
+        assert!(messages[0].contains("--allow-network"));
+        assert_eq!(messages[1], "[permissions] line 3: Calls Deno.writeTextFile but doesn't request --allow-write");
+        assert_eq!(messages[2], "[permissions] line 4: Calls Deno.env but doesn't request --allow-env");
+    }
+
+    #[test]
+    fn test_org_rules_forbid_and_require_patterns() {
+        let rules = vec![
+            OrgRule {
+                pattern: "console.debug(".to_string(),
+                message: "No debug logging".to_string(),
+                required: false,
+            },
+            OrgRule {
+                pattern: "// Owner: *".to_string(),
+                message: "Every command names its owner".to_string(),
+                required: true,
+            },
+        ];
+        let validator = OrgRulesValidator::new(rules);
+
+        let issues = validator.validate(&generation("console.log(1);\nconsole.debug (2);\n", &[]));
+        let owned = validator.validate(&generation("// Owner: platform-team\nconsole.log(1);\n", &[]));
+
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue::new("rules", Some(2), "No debug logging"),
+                ValidationIssue::new("rules", None, "Every command names its owner"),
+            ]
+        );
+        assert!(owned.is_empty());
+    }
+
+    #[test]
+    fn test_pipeline_from_config_skips_validators_and_parses_toml() {
+        let config: ValidationConfig = toml::from_str(
+            r#"
+on_failure = "block"
+skip = ["permissions"]
+
+[[rules]]
+pattern = "TODO"
+message = "Finish the script"
+"#,
+        )
+        .unwrap();
+        let pipeline = ValidationPipeline::from_config(&config, &SafetyConfig::default());
+        let generated = generation("await Deno.remove(\"/\", { recursive: true }); // TODO\n", &["--allow-write"]);
+
+        let issues = pipeline.validate(&generated, &[]);
+        let without_safety = pipeline.validate(&generated, &[SafetyValidator::NAME]);
+
+        assert_eq!(pipeline.on_failure(), FailureAction::Block);
+        assert_eq!(pipeline.max_repairs(), 2);
+        let validators: Vec<&str> = issues.iter().map(|issue| issue.validator.as_str()).collect();
+        assert_eq!(validators, vec!["safety", "rules"]);
+        assert_eq!(without_safety.len(), 1);
+        assert!(ValidationPipeline::from_config(&ValidationConfig { enabled: false, ..config }, &SafetyConfig::default())
+            .validate(&generated, &[])
+            .is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+//! VS Code tasks for project commands, for `ergo export vscode-tasks`.
+//!
+//! Every command of a project bioma becomes a shell task labelled
+//! `ergo: <name>` in `.vscode/tasks.json`, plus one task per argument preset.
+//! Commands with usage examples prompt for their arguments when run. Tasks
+//! get a problem matcher when the command evidently wraps a tool VS Code
+//! knows how to parse, e.g. `eslint` or `tsc`.
+//!
+//! Exporting again replaces the `ergo: ` tasks and keeps every other task in
+//! the file. Comments in an existing file are not kept.
+
+use crate::executor::shell_quote;
+use crate::llm_generator::GeneratedCommand;
+use crate::permissions::PermissionKind;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Prefix of the labels of tasks ergo writes, which it may replace.
+pub const LABEL_PREFIX: &str = "ergo: ";
+
+/// Prefix of the ids of inputs ergo writes, which it may replace.
+pub const INPUT_PREFIX: &str = "ergo-";
+
+/// Problem matchers built into VS Code, by the tool they parse the output of.
+const PROBLEM_MATCHERS: &[(&str, &str)] = &[
+    ("eslint", "$eslint-stylish"),
+    ("tsc", "$tsc"),
+    ("typescript", "$tsc"),
+    ("gcc", "$gcc"),
+    ("clang", "$gcc"),
+    ("jshint", "$jshint"),
+    ("lessc", "$lessc"),
+    ("msbuild", "$msCompile"),
+];
+
+/// A task running a command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    /// `ergo: <name>`, or `ergo: <name> @<preset>`.
+    pub label: String,
+    /// The command's description.
+    pub detail: String,
+    /// Always `shell`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The shell command line.
+    pub command: String,
+    /// Matchers turning the command's output into problems.
+    pub problem_matcher: Vec<String>,
+}
+
+/// A prompt for a command's arguments.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Input {
+    /// `ergo-<name>-args`.
+    pub id: String,
+    /// Always `promptString`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The prompt, with an example.
+    pub description: String,
+    /// Always empty.
+    pub default: String,
+}
+
+/// The tasks and inputs for a project's commands.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Export {
+    /// One task per command and per preset.
+    pub tasks: Vec<Task>,
+    /// One argument prompt per command with usage examples.
+    pub inputs: Vec<Input>,
+}
+
+impl Export {
+    /// Adds the tasks running `command` and its `presets`.
+    pub fn add(&mut self, command: &GeneratedCommand, presets: &[&str]) {
+        let detail = command.description.lines().next().unwrap_or_default().to_string();
+        let problem_matcher = problem_matchers(command);
+        let invocation = format!("ergo {}", shell_quote(&command.name));
+
+        let mut line = invocation.clone();
+        if let Some(example) = command.examples.first() {
+            let id = format!("{}{}-args", INPUT_PREFIX, command.name);
+            let example_args = example
+                .usage
+                .trim()
+                .strip_prefix(&format!("{} ", invocation))
+                .map(str::trim)
+                .unwrap_or(example.usage.trim());
+            line = format!("{} ${{input:{}}}", invocation, id);
+            self.inputs.push(Input {
+                id,
+                kind: "promptString".to_string(),
+                description: format!("Arguments for {}, e.g. {}", invocation, example_args),
+                default: String::new(),
+            });
+        }
+        self.tasks.push(Task {
+            label: format!("{}{}", LABEL_PREFIX, command.name),
+            detail: detail.clone(),
+            kind: "shell".to_string(),
+            command: line,
+            problem_matcher: problem_matcher.clone(),
+        });
+
+        for preset in presets {
+            self.tasks.push(Task {
+                label: format!("{}{} @{}", LABEL_PREFIX, command.name, preset),
+                detail: detail.clone(),
+                kind: "shell".to_string(),
+                command: format!("{} {}", invocation, shell_quote(&format!("@{}", preset))),
+                problem_matcher: problem_matcher.clone(),
+            });
+        }
+    }
+}
+
+/// The problem matchers for the tools `command` wraps, found in the
+/// programs it may run and the words of its name and description.
+pub fn problem_matchers(command: &GeneratedCommand) -> Vec<String> {
+    let programs = command
+        .permissions
+        .iter()
+        .filter_map(|p| p.parsed().ok())
+        .filter(|p| p.kind == PermissionKind::Run)
+        .flat_map(|p| p.scopes.unwrap_or_default());
+    let text = format!("{} {}", command.name, command.description).to_lowercase();
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .map(str::to_string)
+        .chain(programs)
+        .collect();
+
+    let mut matchers: Vec<String> = Vec::new();
+    for (tool, matcher) in PROBLEM_MATCHERS {
+        if words.iter().any(|word| word == tool) && !matchers.iter().any(|m| m == matcher) {
+            matchers.push(matcher.to_string());
+        }
+    }
+    matchers
+}
+
+/// Renders `tasks.json` with `export`, replacing the tasks and inputs ergo
+/// wrote to `existing` before and keeping everything else.
+///
+/// # Errors
+///
+/// Returns an error if `existing` isn't valid JSON (with comments).
+pub fn merge_tasks(existing: Option<&str>, export: &Export) -> Result<String> {
+    let mut file = match existing {
+        Some(text) if !text.trim().is_empty() => match serde_json::from_str(&strip_jsonc(text))
+            .context("Existing tasks.json is not valid JSON")?
+        {
+            Value::Object(map) => map,
+            _ => anyhow::bail!("Existing tasks.json is not a JSON object"),
+        },
+        _ => Map::new(),
+    };
+
+    let kept = |key: &str, field: &str, prefix: &str, file: &mut Map<String, Value>| -> Vec<Value> {
+        match file.remove(key) {
+            Some(Value::Array(items)) => items
+                .into_iter()
+                .filter(|item| !item.get(field).and_then(Value::as_str).is_some_and(|v| v.starts_with(prefix)))
+                .collect(),
+            _ => Vec::new(),
+        }
+    };
+    let tasks = kept("tasks", "label", LABEL_PREFIX, &mut file);
+    let inputs = kept("inputs", "id", INPUT_PREFIX, &mut file);
+    let version = file.remove("version").unwrap_or_else(|| Value::from("2.0.0"));
+
+    let tasks_file = TasksFile {
+        version,
+        tasks: tasks.into_iter().map(Entry::Kept).chain(export.tasks.iter().map(Entry::Ours)).collect(),
+        inputs: inputs.into_iter().map(Entry::Kept).chain(export.inputs.iter().map(Entry::Ours)).collect(),
+        rest: file,
+    };
+    Ok(format!("{}
+", serde_json::to_string_pretty(&tasks_file)?))
+}
+
+/// `tasks.json`, written with `version` and `tasks` first.
+#[derive(Serialize)]
+struct TasksFile<'a> {
+    version: Value,
+    tasks: Vec<Entry<'a, Task>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    inputs: Vec<Entry<'a, Input>>,
+    #[serde(flatten)]
+    rest: Map<String, Value>,
+}
+
+/// A task or input someone else wrote, or one of ergo's.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Entry<'a, T> {
+    Kept(Value),
+    Ours(&'a T),
+}
+
+/// Strips the comments and trailing commas VS Code allows in its JSON files.
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => out.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            ']' | '}' => {
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_generator::UsageExample;
+    use crate::testing::command;
+
+    fn lint() -> GeneratedCommand {
+        let mut lint = command("lint", &["--allow-run=eslint", "--allow-read"]);
+        lint.description = "Lint the sources\nand report problems".to_string();
+        lint.examples = vec![UsageExample {
+            usage: "ergo lint src/".to_string(),
+            explanation: "Lint the src directory".to_string(),
+        }];
+        lint
+    }
+
+    #[test]
+    fn test_export_adds_tasks_inputs_and_problem_matchers() {
+        let mut export = Export::default();
+        export.add(&lint(), &["ci"]);
+        export.add(&command("build-types", &[]), &[]);
+
+        assert_eq!(
+            export.tasks.iter().map(|t| (t.label.as_str(), t.command.as_str())).collect::<Vec<_>>(),
+            vec![
+                ("ergo: lint", "ergo lint ${input:ergo-lint-args}"),
+                ("ergo: lint @ci", "ergo lint @ci"),
+                ("ergo: build-types", "ergo build-types"),
+            ]
+        );
+        assert_eq!(export.tasks[0].detail, "Lint the sources");
+        assert_eq!(export.tasks[1].problem_matcher, vec!["$eslint-stylish"]);
+        assert!(export.tasks[2].problem_matcher.is_empty());
+        assert_eq!(export.inputs.len(), 1);
+        assert_eq!(export.inputs[0].description, "Arguments for ergo lint, e.g. src/");
+    }
+
+    #[test]
+    fn test_merge_tasks_replaces_only_ergo_entries() {
+        let existing = r#"{
+            // Build tasks
+            "version": "2.0.0",
+            "tasks": [
+                { "label": "npm: build", "type": "npm", "script": "build", },
+                { "label": "ergo: stale", "type": "shell", "command": "ergo stale" },
+            ],
+            /* prompts */
+            "inputs": [{ "id": "ergo-stale-args", "type": "promptString" }],
+        }"#;
+        let mut export = Export::default();
+        export.add(&lint(), &[]);
+
+        let merged: Value = serde_json::from_str(&merge_tasks(Some(existing), &export).unwrap()).unwrap();
+
+        let labels: Vec<&str> = merged["tasks"].as_array().unwrap().iter().map(|t| t["label"].as_str().unwrap()).collect();
+        assert_eq!(labels, vec!["npm: build", "ergo: lint"]);
+        assert_eq!(merged["tasks"][1]["problemMatcher"], serde_json::json!(["$eslint-stylish"]));
+        assert_eq!(merged["inputs"].as_array().unwrap().len(), 1);
+        assert_eq!(merged["inputs"][0]["id"], "ergo-lint-args");
+    }
+
+    #[test]
+    fn test_strip_jsonc_keeps_strings() {
+        let text = "{\"url\": \"http://x/*y*/\", // note\n\"a\": [1, 2,],}";
+
+        assert_eq!(strip_jsonc(text), "{\"url\": \"http://x/*y*/\", \n\"a\": [1, 2]}");
+    }
+}
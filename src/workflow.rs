@@ -0,0 +1,214 @@
+//! Workflow files: declarative multi-step recipes over generated commands.
+//!
+//! A workflow is a YAML file listing steps. Each step names a command, with a
+//! description used to generate it if it isn't cached yet, and arguments that
+//! can include the output of earlier steps:
+//!
+//! ```yaml
+//! name: release-notes
+//! steps:
+//!   - id: version
+//!     command: last-tag
+//!     description: Print the most recent git tag
+//!   - id: notes
+//!     command: changelog
+//!     description: Print a markdown changelog of the commits since a git ref
+//!     args: ["${steps.version.output}"]
+//!   - command: notify-failure
+//!     description: Print a desktop notification saying the release failed
+//!     when: failure
+//! ```
+//!
+//! `when` works as in [`crate::chain`]. Before anything runs, the permissions
+//! of every step are reviewed together (see
+//! [`CommandRouter::process_workflow`](crate::command_router::CommandRouter::process_workflow)).
+
+use crate::chain::StepCondition;
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// A parsed and validated workflow file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Workflow {
+    /// Name shown in the permission review; defaults to the file name.
+    #[serde(default)]
+    pub name: String,
+    /// The steps, in order.
+    pub steps: Vec<WorkflowStep>,
+}
+
+/// One step of a workflow.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct WorkflowStep {
+    /// Identifier other steps use to refer to this step's output; defaults to
+    /// the command name.
+    #[serde(default)]
+    pub id: String,
+    /// Name of the cached command to run.
+    pub command: String,
+    /// What the command does, used to generate it if it isn't cached.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Arguments, which may contain `${steps.<id>.output}` references.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// When the step runs.
+    #[serde(default)]
+    pub when: StepCondition,
+}
+
+impl Workflow {
+    /// Parses and validates a workflow from YAML.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the YAML is invalid, there are no steps, step ids
+    /// repeat, or an argument refers to a step that doesn't come earlier.
+    pub fn parse(yaml: &str) -> Result<Self> {
+        let mut workflow: Workflow = serde_yaml::from_str(yaml).context("Invalid workflow file")?;
+        if workflow.steps.is_empty() {
+            return Err(anyhow!("Workflow has no steps"));
+        }
+
+        let mut seen = HashSet::new();
+        for step in &mut workflow.steps {
+            if step.command.trim().is_empty() {
+                return Err(anyhow!("Workflow steps need a command"));
+            }
+            if step.id.is_empty() {
+                step.id = step.command.clone();
+            }
+            for arg in &step.args {
+                for reference in references(arg)? {
+                    if !seen.contains(reference) {
+                        return Err(anyhow!(
+                            "Step '{}' uses the output of '{}', which is not an earlier step",
+                            step.id,
+                            reference
+                        ));
+                    }
+                }
+            }
+            if !seen.insert(step.id.as_str()) {
+                return Err(anyhow!("Duplicate step id '{}'", step.id));
+            }
+        }
+
+        Ok(workflow)
+    }
+
+    /// Loads a workflow file, naming it after the file if it has no name.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read workflow file {}", path.display()))?;
+        let mut workflow = Self::parse(&content)?;
+        if workflow.name.is_empty() {
+            workflow.name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+        Ok(workflow)
+    }
+}
+
+/// Returns the step ids referenced by `${steps.<id>.output}` in `arg`.
+fn references(arg: &str) -> Result<Vec<&str>> {
+    let mut ids = Vec::new();
+    let mut rest = arg;
+    while let Some(start) = rest.find("${") {
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed '${{' in argument '{}'", arg))?;
+        let id = after[..end]
+            .trim()
+            .strip_prefix("steps.")
+            .and_then(|r| r.strip_suffix(".output"))
+            .ok_or_else(|| anyhow!("Unknown reference '${{{}}}' (expected ${{steps.<id>.output}})", &after[..end]))?;
+        ids.push(id);
+        rest = &after[end + 1..];
+    }
+    Ok(ids)
+}
+
+/// Replaces `${steps.<id>.output}` references in `arg` with the outputs of
+/// earlier steps, trimmed of surrounding whitespace.
+///
+/// # Errors
+///
+/// Returns an error if a referenced step has no output (it was skipped,
+/// failed to run, or printed nothing).
+pub fn interpolate(arg: &str, outputs: &HashMap<String, String>) -> Result<String> {
+    let mut result = String::with_capacity(arg.len());
+    let mut rest = arg;
+    for id in references(arg)? {
+        let start = rest.find("${").expect("reference was found above");
+        let end = start + rest[start..].find('}').expect("reference was found above");
+        let output = outputs
+            .get(id)
+            .ok_or_else(|| anyhow!("Step '{}' produced no output", id))?;
+        result.push_str(&rest[..start]);
+        result.push_str(output.trim());
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELEASE: &str = r#"
+name: release
+steps:
+  - id: version
+    command: last-tag
+    description: Print the most recent git tag
+  - command: changelog
+    args: ["--since", "${steps.version.output}"]
+  - command: notify
+    when: failure
+"#;
+
+    #[test]
+    fn test_parse_workflow() {
+        let workflow = Workflow::parse(RELEASE).unwrap();
+
+        assert_eq!(workflow.name, "release");
+        assert_eq!(workflow.steps.len(), 3);
+        assert_eq!(workflow.steps[0].description.as_deref(), Some("Print the most recent git tag"));
+        assert_eq!(workflow.steps[1].id, "changelog");
+        assert_eq!(workflow.steps[2].when, StepCondition::Failure);
+    }
+
+    #[test]
+    fn test_parse_rejects_forward_and_unknown_references() {
+        let forward = "steps:\n  - command: a\n    args: ['${steps.b.output}']\n  - command: b\n";
+        assert!(Workflow::parse(forward).is_err());
+
+        let unknown = "steps:\n  - command: a\n    args: ['${env.HOME}']\n";
+        assert!(Workflow::parse(unknown).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_duplicate_ids_and_empty_workflows() {
+        assert!(Workflow::parse("steps:\n  - command: a\n  - command: a\n").is_err());
+        assert!(Workflow::parse("steps: []\n").is_err());
+    }
+
+    #[test]
+    fn test_interpolate_outputs() {
+        let outputs = HashMap::from([("version".to_string(), "v1.2.0\n".to_string())]);
+
+        assert_eq!(
+            interpolate("from ${steps.version.output} to HEAD", &outputs).unwrap(),
+            "from v1.2.0 to HEAD"
+        );
+        assert_eq!(interpolate("plain", &outputs).unwrap(), "plain");
+        assert!(interpolate("${steps.missing.output}", &outputs).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! Temporary workspaces for generated commands.
+//!
+//! In workspace mode (`ergo --workspace ...` or `sandbox.workspace = true`),
+//! a command runs in a fresh temporary directory instead of the current one.
+//! Arguments naming files or directories under the current directory (by
+//! relative path) are copied in at the same relative path, so the command
+//! finds its inputs, but nothing it writes lands in the current directory.
+//!
+//! Afterwards ergo lists the files the command produced or changed and
+//! copies them back only if the user agrees (see
+//! [`ChangeApprover`](crate::staging::ChangeApprover)). Unlike staging, the
+//! command only sees its inputs, not a copy of the whole directory.
+
+use crate::staging::{self, ChangeManifest, MAX_STAGE_BYTES};
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tempfile::TempDir;
+use tracing::info;
+
+/// A temporary directory a command runs in, holding copies of its inputs.
+///
+/// The directory is removed when the workspace is dropped.
+#[derive(Debug)]
+pub struct Workspace {
+    _root: TempDir,
+    dir: PathBuf,
+    real_dir: PathBuf,
+    inputs: Vec<PathBuf>,
+}
+
+impl Workspace {
+    /// Creates a workspace under `base` for a command run in `real_dir` with
+    /// `args`, copying in the inputs they name (see [`input_paths`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the inputs are larger than [`MAX_STAGE_BYTES`] or
+    /// cannot be copied.
+    pub fn create(real_dir: &Path, base: &Path, args: &[String]) -> Result<Self> {
+        let inputs = input_paths(real_dir, args);
+        let mut size = 0;
+        for input in &inputs {
+            let path = real_dir.join(input);
+            size += if path.is_dir() {
+                staging::list_files(&path)?
+                    .iter()
+                    .map(|p| Ok(fs::symlink_metadata(path.join(p))?.len()))
+                    .sum::<Result<u64>>()?
+            } else {
+                fs::metadata(&path)?.len()
+            };
+        }
+        if size > MAX_STAGE_BYTES {
+            return Err(anyhow!(
+                "The command's inputs are too large to copy into a workspace ({} MiB, limit {} MiB). Run it without --workspace.",
+                size / (1024 * 1024),
+                MAX_STAGE_BYTES / (1024 * 1024)
+            ));
+        }
+
+        let root = tempfile::Builder::new().prefix("ergo_workspace_").tempdir_in(base)?;
+        let dir = root.path().join("work");
+        fs::create_dir_all(&dir)?;
+        let workspace = Self {
+            _root: root,
+            dir,
+            real_dir: real_dir.to_path_buf(),
+            inputs,
+        };
+
+        for input in &workspace.inputs {
+            let (from, to) = (real_dir.join(input), workspace.dir.join(input));
+            if from.is_dir() {
+                staging::copy_dir(&from, &to)?;
+            } else {
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(&from, &to)?;
+            }
+        }
+        info!(
+            "Created workspace '{}' with {} input(s) from '{}'",
+            workspace.dir.display(),
+            workspace.inputs.len(),
+            real_dir.display()
+        );
+        Ok(workspace)
+    }
+
+    /// The directory the command should run in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The inputs copied in, relative to the working directory.
+    pub fn inputs(&self) -> &[PathBuf] {
+        &self.inputs
+    }
+
+    /// Maps an absolute path inside the real directory to the workspace.
+    ///
+    /// Returns `None` for paths outside the real directory.
+    pub fn map_path(&self, path: &Path) -> Option<PathBuf> {
+        path.strip_prefix(&self.real_dir)
+            .ok()
+            .map(|relative| self.dir.join(relative))
+    }
+
+    /// Lists the files the command produced: new files, and files that
+    /// differ from their counterpart in the real directory. Files missing
+    /// from the workspace are never reported as deleted.
+    pub fn outputs(&self) -> Result<ChangeManifest> {
+        let mut manifest = ChangeManifest::default();
+        for path in staging::list_files(&self.dir)? {
+            let real = self.real_dir.join(&path);
+            if !real.exists() {
+                manifest.created.push(path);
+            } else if fs::read(&real)? != fs::read(self.dir.join(&path))? {
+                manifest.modified.push(path);
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Copies the files in `outputs` back to the real directory.
+    pub fn copy_back(&self, outputs: &ChangeManifest) -> Result<()> {
+        for path in outputs.created.iter().chain(&outputs.modified) {
+            let target = self.real_dir.join(path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(self.dir.join(path), &target)?;
+        }
+        info!("Copied workspace outputs to '{}'", self.real_dir.display());
+        Ok(())
+    }
+}
+
+/// Finds the arguments naming existing files or directories under
+/// `real_dir`, as paths relative to it.
+///
+/// Only relative paths count, including the value of `--flag=path`
+/// arguments; paths leaving the directory with `..` are ignored.
+pub fn input_paths(real_dir: &Path, args: &[String]) -> Vec<PathBuf> {
+    let mut inputs: Vec<PathBuf> = Vec::new();
+    for arg in args {
+        let value = match arg.strip_prefix("--").and_then(|flag| flag.split_once('=')) {
+            Some((_, value)) => value,
+            None if arg.starts_with('-') => continue,
+            None => arg,
+        };
+        let path = Path::new(value);
+        let relative: PathBuf = path
+            .components()
+            .filter(|component| *component != Component::CurDir)
+            .collect();
+        if value.is_empty()
+            || path.is_absolute()
+            || path.components().any(|component| component == Component::ParentDir)
+            || !real_dir.join(&relative).exists()
+            || inputs.contains(&relative)
+        {
+            continue;
+        }
+        inputs.push(relative);
+    }
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, TempDir) {
+        let real = TempDir::new().unwrap();
+        fs::write(real.path().join("input.csv"), "a,b").unwrap();
+        fs::write(real.path().join("unrelated.txt"), "keep").unwrap();
+        fs::create_dir(real.path().join("photos")).unwrap();
+        fs::write(real.path().join("photos/cat.jpg"), "meow").unwrap();
+        (real, TempDir::new().unwrap())
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_input_paths_picks_existing_relative_paths() {
+        let (real, _base) = setup();
+        let args = strings(&["./input.csv", "--out=photos", "-v", "missing.txt", "../input.csv", "/etc/hosts", "input.csv"]);
+
+        let inputs = input_paths(real.path(), &args);
+
+        assert_eq!(inputs, vec![PathBuf::from("input.csv"), PathBuf::from("photos")]);
+    }
+
+    #[test]
+    fn test_workspace_holds_inputs_and_reports_outputs() {
+        let (real, base) = setup();
+        let workspace = Workspace::create(real.path(), base.path(), &strings(&["input.csv", "photos"])).unwrap();
+        let dir = workspace.dir().to_path_buf();
+        assert!(dir.join("photos/cat.jpg").exists());
+        assert!(!dir.join("unrelated.txt").exists());
+
+        fs::write(dir.join("input.csv"), "a,b,c").unwrap();
+        fs::write(dir.join("report.txt"), "done").unwrap();
+        fs::remove_file(dir.join("photos/cat.jpg")).unwrap();
+        let outputs = workspace.outputs().unwrap();
+
+        assert_eq!(outputs.created, vec![PathBuf::from("report.txt")]);
+        assert_eq!(outputs.modified, vec![PathBuf::from("input.csv")]);
+        assert!(outputs.deleted.is_empty());
+        assert!(!real.path().join("report.txt").exists());
+
+        workspace.copy_back(&outputs).unwrap();
+        drop(workspace);
+
+        assert_eq!(fs::read_to_string(real.path().join("report.txt")).unwrap(), "done");
+        assert_eq!(fs::read_to_string(real.path().join("input.csv")).unwrap(), "a,b,c");
+        assert!(real.path().join("photos/cat.jpg").exists());
+        assert!(!dir.exists());
+    }
+}